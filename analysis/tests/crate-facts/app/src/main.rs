@@ -0,0 +1,12 @@
+// `p` is only passed to `get` from the library crate, so it can be rewritten only if the
+// library's facts for `get` are available.
+unsafe fn read_it(p: *const i32) -> i32 {
+    crate_facts_lib::get(p)
+}
+
+fn main() {
+    let x = 1;
+    unsafe {
+        read_it(&x);
+    }
+}
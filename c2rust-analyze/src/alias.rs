@@ -0,0 +1,435 @@
+//! May-alias pre-pass, selected with `--alias-analysis`.
+//!
+//! The borrow check decides which pointers can be `UNIQUE` by tracking loans through each
+//! function, but a pointer that is stored to memory and loaded back looks like an unrelated
+//! pointer to it.  This pass computes flow-insensitive points-to sets for the locals of each
+//! function and finds pointers that may alias through memory:
+//!
+//! ```ignore
+//! *pp = p;
+//! let q = *pp;    // `q` and `p` may point to the same object
+//! ```
+//!
+//! Each such pointer loses `UNIQUE` through a dataflow constraint, before the borrow check runs.
+//! Two borrows of the same local are left to the borrow check, which knows whether their uses
+//! overlap.
+//!
+//! Two algorithms are available.  Andersen's analysis computes a points-to set for each location
+//! from inclusion constraints, and is the more precise of the two.  Steensgaard's analysis unifies
+//! everything that one location may point to into a single class, which takes nearly linear time
+//! but may merge unrelated objects.  Both are intraprocedural and field-insensitive: each local,
+//! each pointer argument's target, and each pointer returned by a call is one abstract object.
+
+use crate::util::{ty_callee, Callee};
+use rustc_middle::mir::{
+    Body, Local, Location, Operand, Place, ProjectionElem, Rvalue, StatementKind, TerminatorKind,
+};
+use rustc_middle::ty::TyCtxt;
+use std::collections::HashSet;
+use std::env;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AliasAnalysis {
+    Andersen,
+    Steensgaard,
+}
+
+/// Get the pre-pass selected with `--alias-analysis`, or `None` if only the borrow check should
+/// be used.
+pub fn alias_analysis() -> Option<AliasAnalysis> {
+    let val = env::var("C2RUST_ANALYZE_ALIAS_ANALYSIS").ok()?;
+    match val.as_str() {
+        "borrowck" => None,
+        "andersen" => Some(AliasAnalysis::Andersen),
+        "steensgaard" => Some(AliasAnalysis::Steensgaard),
+        _ => panic!("bad value {:?} for C2RUST_ANALYZE_ALIAS_ANALYSIS", val),
+    }
+}
+
+/// An abstract memory location.  The first `mir.local_decls.len()` locations are the locals of
+/// the function; the rest are objects created by the pass.
+type Loc = usize;
+
+#[derive(Clone, Copy, Debug)]
+enum Constraint {
+    /// `.0 = &.1`
+    AddrOf(Loc, Loc),
+    /// `.0 = .1`
+    Copy(Loc, Loc),
+    /// `.0 = *.1`
+    Load(Loc, Loc),
+    /// `*.0 = .1`
+    Store(Loc, Loc),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RootKind {
+    Arg,
+    Borrow,
+    Load,
+}
+
+/// A local that receives a pointer that isn't a copy of another local.
+#[derive(Clone, Copy, Debug)]
+struct Root {
+    local: Local,
+    kind: RootKind,
+    loc: Location,
+}
+
+/// How a place is read or written, ignoring field and index projections.
+enum PlaceAccess {
+    /// The local itself.
+    Local(Local),
+    /// The target of the pointer in a local.
+    Deref(Local),
+    /// Something behind more than one pointer, which isn't tracked.
+    Unknown,
+}
+
+fn place_access(pl: Place) -> PlaceAccess {
+    let derefs = pl
+        .projection
+        .iter()
+        .filter(|elem| matches!(elem, ProjectionElem::Deref))
+        .count();
+    match derefs {
+        0 => PlaceAccess::Local(pl.local),
+        1 if matches!(pl.projection[0], ProjectionElem::Deref) => PlaceAccess::Deref(pl.local),
+        _ => PlaceAccess::Unknown,
+    }
+}
+
+struct ConstraintBuilder {
+    constraints: Vec<Constraint>,
+    roots: Vec<Root>,
+    num_locs: usize,
+}
+
+impl ConstraintBuilder {
+    fn fresh(&mut self) -> Loc {
+        self.num_locs += 1;
+        self.num_locs - 1
+    }
+
+    /// Add constraints for assigning a value to `dest`.  `f` adds the constraints for the value,
+    /// given the location that receives it.
+    fn assign(&mut self, dest: Place, f: impl FnOnce(&mut Self, Loc)) {
+        match place_access(dest) {
+            PlaceAccess::Local(local) => f(self, local.as_usize()),
+            PlaceAccess::Deref(ptr) => {
+                let tmp = self.fresh();
+                f(self, tmp);
+                self.constraints
+                    .push(Constraint::Store(ptr.as_usize(), tmp));
+            }
+            PlaceAccess::Unknown => {}
+        }
+    }
+
+    fn operand(&mut self, dest: Loc, op: &Operand, loc: Location, root_dest: Option<Local>) {
+        let pl = match op.place() {
+            Some(pl) => pl,
+            None => return,
+        };
+        match place_access(pl) {
+            PlaceAccess::Local(src) => self
+                .constraints
+                .push(Constraint::Copy(dest, src.as_usize())),
+            PlaceAccess::Deref(ptr) => {
+                self.constraints
+                    .push(Constraint::Load(dest, ptr.as_usize()));
+                if let Some(local) = root_dest {
+                    self.roots.push(Root {
+                        local,
+                        kind: RootKind::Load,
+                        loc,
+                    });
+                }
+            }
+            PlaceAccess::Unknown => {
+                let obj = self.fresh();
+                self.constraints.push(Constraint::AddrOf(dest, obj));
+            }
+        }
+    }
+}
+
+fn build_constraints<'tcx>(tcx: TyCtxt<'tcx>, mir: &Body<'tcx>) -> ConstraintBuilder {
+    let mut b = ConstraintBuilder {
+        constraints: Vec::new(),
+        roots: Vec::new(),
+        num_locs: mir.local_decls.len(),
+    };
+
+    for local in mir.args_iter() {
+        if !mir.local_decls[local].ty.is_any_ptr() {
+            continue;
+        }
+        let obj = b.fresh();
+        b.constraints
+            .push(Constraint::AddrOf(local.as_usize(), obj));
+        b.roots.push(Root {
+            local,
+            kind: RootKind::Arg,
+            loc: Location::START,
+        });
+    }
+
+    for (bb, bb_data) in mir.basic_blocks().iter_enumerated() {
+        for (i, stmt) in bb_data.statements.iter().enumerate() {
+            let loc = Location {
+                block: bb,
+                statement_index: i,
+            };
+            let (dest, rv) = match stmt.kind {
+                StatementKind::Assign(ref x) => (x.0, &x.1),
+                _ => continue,
+            };
+            // Only plain locals are tracked as roots, since they have their own `PointerId`s.
+            let root_dest = if dest.projection.is_empty() {
+                Some(dest.local)
+            } else {
+                None
+            };
+            match *rv {
+                Rvalue::Use(ref op) | Rvalue::Cast(_, ref op, _) => {
+                    b.assign(dest, |b, d| b.operand(d, op, loc, root_dest));
+                }
+                Rvalue::Aggregate(_, ref ops) => {
+                    b.assign(dest, |b, d| {
+                        for op in ops {
+                            b.operand(d, op, loc, None);
+                        }
+                    });
+                }
+                Rvalue::Ref(_, _, pl) | Rvalue::AddressOf(_, pl) => match place_access(pl) {
+                    PlaceAccess::Local(local) => {
+                        b.assign(dest, |b, d| {
+                            b.constraints.push(Constraint::AddrOf(d, local.as_usize()))
+                        });
+                        if let Some(local) = root_dest {
+                            b.roots.push(Root {
+                                local,
+                                kind: RootKind::Borrow,
+                                loc,
+                            });
+                        }
+                    }
+                    // `&(*p).f` points into the same object as `p`.
+                    PlaceAccess::Deref(ptr) => {
+                        b.assign(dest, |b, d| {
+                            b.constraints.push(Constraint::Copy(d, ptr.as_usize()))
+                        });
+                    }
+                    PlaceAccess::Unknown => {
+                        b.assign(dest, |b, d| {
+                            let obj = b.fresh();
+                            b.constraints.push(Constraint::AddrOf(d, obj));
+                        });
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        if let TerminatorKind::Call {
+            ref func,
+            ref args,
+            destination,
+            ..
+        } = bb_data.terminator().kind
+        {
+            if !destination.ty(mir, tcx).ty.is_any_ptr() {
+                continue;
+            }
+            let loc = mir.terminator_loc(bb);
+            match ty_callee(tcx, func.ty(mir, tcx)) {
+                Callee::PtrOffset { .. } => {
+                    b.assign(destination, |b, d| b.operand(d, &args[0], loc, None));
+                }
+                _ => {
+                    b.assign(destination, |b, d| {
+                        let obj = b.fresh();
+                        b.constraints.push(Constraint::AddrOf(d, obj));
+                    });
+                }
+            }
+        }
+    }
+
+    b
+}
+
+/// Points-to sets computed by Andersen's analysis.
+fn andersen(constraints: &[Constraint], num_locs: usize) -> Vec<HashSet<Loc>> {
+    let mut pts = vec![HashSet::new(); num_locs];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &c in constraints {
+            let (dest, new) = match c {
+                Constraint::AddrOf(dest, obj) => (vec![dest], HashSet::from([obj])),
+                Constraint::Copy(dest, src) => (vec![dest], pts[src].clone()),
+                Constraint::Load(dest, ptr) => {
+                    let new = pts[ptr].iter().flat_map(|&o| pts[o].clone()).collect();
+                    (vec![dest], new)
+                }
+                Constraint::Store(ptr, src) => {
+                    (pts[ptr].iter().copied().collect(), pts[src].clone())
+                }
+            };
+            for d in dest {
+                for &o in &new {
+                    changed |= pts[d].insert(o);
+                }
+            }
+        }
+    }
+    pts
+}
+
+/// Union-find over abstract locations, where each class points to at most one other class.
+struct Steensgaard {
+    parent: Vec<Loc>,
+    pointee: Vec<Option<Loc>>,
+    /// Whether each class contains an object.  Classes without one are only placeholders for
+    /// memory the function never stored a pointer into, and don't make anything alias.
+    has_obj: Vec<bool>,
+}
+
+impl Steensgaard {
+    fn new(num_locs: usize) -> Steensgaard {
+        Steensgaard {
+            parent: (0..num_locs).collect(),
+            pointee: vec![None; num_locs],
+            has_obj: vec![false; num_locs],
+        }
+    }
+
+    fn find(&mut self, x: Loc) -> Loc {
+        let parent = self.parent[x];
+        if parent == x {
+            return x;
+        }
+        let rep = self.find(parent);
+        self.parent[x] = rep;
+        rep
+    }
+
+    /// Get the class that `x` points to, creating an empty one if needed.
+    fn pointee(&mut self, x: Loc) -> Loc {
+        let x = self.find(x);
+        match self.pointee[x] {
+            Some(p) => self.find(p),
+            None => {
+                let p = self.parent.len();
+                self.parent.push(p);
+                self.pointee.push(None);
+                self.has_obj.push(false);
+                self.pointee[x] = Some(p);
+                p
+            }
+        }
+    }
+
+    fn join(&mut self, a: Loc, b: Loc) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        self.parent[b] = a;
+        self.has_obj[a] |= self.has_obj[b];
+        match (self.pointee[a], self.pointee[b]) {
+            (Some(pa), Some(pb)) => self.join(pa, pb),
+            (None, Some(pb)) => self.pointee[a] = Some(pb),
+            _ => {}
+        }
+    }
+
+    fn solve(&mut self, constraints: &[Constraint]) {
+        for &c in constraints {
+            match c {
+                Constraint::AddrOf(dest, obj) => {
+                    let p = self.pointee(dest);
+                    self.join(p, obj);
+                    let rep = self.find(p);
+                    self.has_obj[rep] = true;
+                }
+                Constraint::Copy(dest, src) => {
+                    let (pd, ps) = (self.pointee(dest), self.pointee(src));
+                    self.join(pd, ps);
+                }
+                Constraint::Load(dest, ptr) => {
+                    let pd = self.pointee(dest);
+                    let pp = self.pointee(ptr);
+                    let ppp = self.pointee(pp);
+                    self.join(pd, ppp);
+                }
+                Constraint::Store(ptr, src) => {
+                    let pp = self.pointee(ptr);
+                    let ppp = self.pointee(pp);
+                    let ps = self.pointee(src);
+                    self.join(ppp, ps);
+                }
+            }
+        }
+    }
+}
+
+/// Find the locals in `mir` that may alias another pointer through memory, along with the
+/// location where each one gets its value.  Two borrows of the same local aren't reported.
+pub fn aliased_locals<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &Body<'tcx>,
+    kind: AliasAnalysis,
+) -> Vec<(Local, Location)> {
+    let b = build_constraints(tcx, mir);
+    let may_alias: Box<dyn Fn(Local, Local) -> bool> = match kind {
+        AliasAnalysis::Andersen => {
+            let pts = andersen(&b.constraints, b.num_locs);
+            Box::new(move |x, y| !pts[x.as_usize()].is_disjoint(&pts[y.as_usize()]))
+        }
+        AliasAnalysis::Steensgaard => {
+            let mut s = Steensgaard::new(b.num_locs);
+            s.solve(&b.constraints);
+            let class = |s: &mut Steensgaard, x: Local| {
+                let x = s.find(x.as_usize());
+                let p = s.find(s.pointee[x]?);
+                if s.has_obj[p] {
+                    Some(p)
+                } else {
+                    None
+                }
+            };
+            let classes = mir
+                .local_decls
+                .indices()
+                .map(|l| class(&mut s, l))
+                .collect::<Vec<_>>();
+            Box::new(move |x, y| {
+                let cx = classes[x.as_usize()];
+                cx.is_some() && cx == classes[y.as_usize()]
+            })
+        }
+    };
+
+    let roots = b
+        .roots
+        .iter()
+        .filter(|r| mir.local_decls[r.local].ty.is_any_ptr())
+        .collect::<Vec<_>>();
+    let mut aliased = Vec::new();
+    for (i, r1) in roots.iter().enumerate() {
+        let conflict = roots.iter().enumerate().any(|(j, r2)| {
+            i != j
+                && r1.local != r2.local
+                && !(r1.kind == RootKind::Borrow && r2.kind == RootKind::Borrow)
+                && may_alias(r1.local, r2.local)
+        });
+        if conflict {
+            aliased.push((r1.local, r1.loc));
+        }
+    }
+    aliased
+}
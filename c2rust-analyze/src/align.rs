@@ -0,0 +1,292 @@
+//! Recognition of pointer/integer round-trips that only compute alignment.
+//!
+//! C code checks and adjusts the alignment of pointers by casting them to integers:
+//!
+//! ```ignore
+//! // Is `p` aligned to 8 bytes?
+//! (p as usize & 7) == 0
+//! p as usize % 8 == 0
+//!
+//! // Round `p` up to the next multiple of 8.
+//! ((p as usize).wrapping_add(7) & !7) as *mut T
+//! ```
+//!
+//! Casting an integer back to a pointer would normally make the result opaque to the analysis.
+//! Here, the result is just `p` offset by a few elements, so the analysis treats the round-up
+//! like a call to `offset` on `p`, and the rewriter replaces both idioms with `align_offset` or
+//! `is_aligned`.
+//!
+//! The same address tracking is used by `crate::provenance` to find pointers rebuilt from other
+//! pointers' addresses.
+
+use crate::util::{ty_callee, Callee};
+use rustc_middle::mir::{
+    BinOp, Body, CastKind, LocalKind, Operand, Place, PlaceElem, Rvalue, StatementKind,
+    TerminatorKind, UnOp,
+};
+use rustc_middle::ty::{ParamEnv, Ty, TyCtxt, TyKind};
+
+/// An integer operation that defines a MIR temporary, with any overflow checking removed.
+#[derive(Clone, Copy, Debug)]
+enum IntOp<'a, 'tcx> {
+    /// `p as usize`, where `p` is a pointer.
+    Expose(&'a Operand<'tcx>),
+    /// A copy, or a cast between integer types.
+    Use(&'a Operand<'tcx>),
+    Not(&'a Operand<'tcx>),
+    /// A binary operation.  `x.wrapping_add(y)` and `x.wrapping_sub(y)` are treated as `Add` and
+    /// `Sub`.
+    Binary(BinOp, &'a Operand<'tcx>, &'a Operand<'tcx>),
+}
+
+/// Find the single assignment to the temporary `pl` in `mir`, and return the integer operation
+/// it performs.
+fn int_def<'a, 'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &'a Body<'tcx>,
+    pl: Place<'tcx>,
+) -> Option<IntOp<'a, 'tcx>> {
+    if mir.local_kind(pl.local) != LocalKind::Temp {
+        return None;
+    }
+    // With overflow checks enabled, `a + b` becomes `tmp = CheckedAdd(a, b); x = move tmp.0`.
+    let checked = match *pl.projection {
+        [] => false,
+        [PlaceElem::Field(f, _)] if f.index() == 0 => true,
+        _ => return None,
+    };
+
+    let mut def = None;
+    for bb_data in mir.basic_blocks().iter() {
+        for stmt in &bb_data.statements {
+            if let StatementKind::Assign(ref x) = stmt.kind {
+                if x.0.local == pl.local {
+                    if def.is_some() || !x.0.projection.is_empty() {
+                        return None;
+                    }
+                    def = Some(Ok(&x.1));
+                }
+            }
+        }
+        if let TerminatorKind::Call {
+            ref func,
+            ref args,
+            destination,
+            ..
+        } = bb_data.terminator().kind
+        {
+            if destination.local == pl.local {
+                if def.is_some() || !destination.projection.is_empty() {
+                    return None;
+                }
+                def = Some(Err((func, args)));
+            }
+        }
+    }
+
+    match (def?, checked) {
+        (Ok(&Rvalue::Use(ref op)), false) => Some(IntOp::Use(op)),
+        (Ok(&Rvalue::Cast(CastKind::PointerExposeAddress, ref op, _)), false) => {
+            Some(IntOp::Expose(op))
+        }
+        (Ok(&Rvalue::Cast(CastKind::Misc, ref op, ty)), false) => {
+            if ty.is_integral() && op.ty(mir, tcx).is_integral() {
+                Some(IntOp::Use(op))
+            } else {
+                None
+            }
+        }
+        (Ok(&Rvalue::UnaryOp(UnOp::Not, ref op)), false) => Some(IntOp::Not(op)),
+        (Ok(&Rvalue::BinaryOp(bop, ref ops)), false)
+        | (Ok(&Rvalue::CheckedBinaryOp(bop, ref ops)), true) => {
+            Some(IntOp::Binary(bop, &ops.0, &ops.1))
+        }
+        (Err((func, args)), false) => {
+            let func_ty = func.ty(mir, tcx);
+            if !matches!(ty_callee(tcx, func_ty), Callee::Trivial) {
+                return None;
+            }
+            let bop = match *func_ty.kind() {
+                TyKind::FnDef(did, _) => match tcx.item_name(did).as_str() {
+                    "wrapping_add" => BinOp::Add,
+                    "wrapping_sub" => BinOp::Sub,
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            match **args {
+                [ref a, ref b] if a.ty(mir, tcx).is_integral() => Some(IntOp::Binary(bop, a, b)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn operand_int_def<'a, 'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &'a Body<'tcx>,
+    op: &Operand<'tcx>,
+) -> Option<IntOp<'a, 'tcx>> {
+    int_def(tcx, mir, op.place()?)
+}
+
+/// The width in bits of the integer type `ty`, and whether it's signed.
+fn int_width<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Option<(u64, bool)> {
+    let ptr_bits = tcx.data_layout.pointer_size.bits();
+    match *ty.kind() {
+        TyKind::Int(int_ty) => Some((int_ty.bit_width().unwrap_or(ptr_bits), true)),
+        TyKind::Uint(uint_ty) => Some((uint_ty.bit_width().unwrap_or(ptr_bits), false)),
+        _ => None,
+    }
+}
+
+fn truncate(x: u128, bits: u64) -> u128 {
+    if bits >= 128 {
+        x
+    } else {
+        x & ((1 << bits) - 1)
+    }
+}
+
+/// Evaluate `op`, which must be an integer built from constants, as in `!(8 - 1) as usize`.  The
+/// result is truncated to the width of `op`'s type.
+fn const_value<'tcx>(tcx: TyCtxt<'tcx>, mir: &Body<'tcx>, op: &Operand<'tcx>) -> Option<u128> {
+    let ty = op.ty(mir, tcx);
+    let (bits, _) = int_width(tcx, ty)?;
+    let value = match *op {
+        Operand::Constant(ref c) => c.literal.try_eval_bits(tcx, ParamEnv::reveal_all(), ty)?,
+        Operand::Copy(pl) | Operand::Move(pl) => match int_def(tcx, mir, pl)? {
+            IntOp::Use(inner) => {
+                let x = const_value(tcx, mir, inner)?;
+                let (inner_bits, signed) = int_width(tcx, inner.ty(mir, tcx))?;
+                if signed && inner_bits < 128 && (x >> (inner_bits - 1)) & 1 == 1 {
+                    // Sign-extend.
+                    x | !((1 << inner_bits) - 1)
+                } else {
+                    x
+                }
+            }
+            IntOp::Not(inner) => !const_value(tcx, mir, inner)?,
+            IntOp::Binary(BinOp::Add, a, b) => {
+                const_value(tcx, mir, a)?.wrapping_add(const_value(tcx, mir, b)?)
+            }
+            IntOp::Binary(BinOp::Sub, a, b) => {
+                const_value(tcx, mir, a)?.wrapping_sub(const_value(tcx, mir, b)?)
+            }
+            _ => return None,
+        },
+    };
+    Some(truncate(value, bits))
+}
+
+/// If `op` holds the address of a pointer, as in `p as usize`, possibly followed by integer
+/// casts, return the pointer.
+fn addr_source<'a, 'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &'a Body<'tcx>,
+    op: &Operand<'tcx>,
+) -> Option<&'a Operand<'tcx>> {
+    match operand_int_def(tcx, mir, op)? {
+        IntOp::Expose(ptr) => Some(ptr),
+        IntOp::Use(inner) => addr_source(tcx, mir, inner),
+        _ => None,
+    }
+}
+
+/// If the address of some pointer contributes to the integer `op`, as in `p as usize + 8`, return
+/// the first such pointer.
+pub fn any_addr_source<'a, 'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &'a Body<'tcx>,
+    op: &Operand<'tcx>,
+) -> Option<&'a Operand<'tcx>> {
+    match operand_int_def(tcx, mir, op)? {
+        IntOp::Expose(ptr) => Some(ptr),
+        IntOp::Use(inner) | IntOp::Not(inner) => any_addr_source(tcx, mir, inner),
+        IntOp::Binary(_, a, b) => {
+            any_addr_source(tcx, mir, a).or_else(|| any_addr_source(tcx, mir, b))
+        }
+    }
+}
+
+/// If `op` computes the distance between two pointers, as in `q as usize - p as usize`, possibly
+/// followed by integer casts, return `q` and `p`.
+pub fn addr_difference<'a, 'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &'a Body<'tcx>,
+    op: &Operand<'tcx>,
+) -> Option<(&'a Operand<'tcx>, &'a Operand<'tcx>)> {
+    match operand_int_def(tcx, mir, op)? {
+        IntOp::Use(inner) => addr_difference(tcx, mir, inner),
+        IntOp::Binary(BinOp::Sub, a, b) => {
+            Some((addr_source(tcx, mir, a)?, addr_source(tcx, mir, b)?))
+        }
+        _ => None,
+    }
+}
+
+/// If `x` is a power of two, return it as a `u64`.
+fn power_of_two(x: u128) -> Option<u64> {
+    if x.is_power_of_two() {
+        u64::try_from(x).ok()
+    } else {
+        None
+    }
+}
+
+/// If `op` computes `(p as usize + (n - 1)) & !(n - 1)`, and `p` has type `ptr_ty`, return `p`
+/// and the alignment `n`.  This is the usual idiom for rounding `p` up to a multiple of `n`, and
+/// `ptr_ty` is the type that the result is cast back to.
+pub fn align_up_source<'a, 'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &'a Body<'tcx>,
+    op: &Operand<'tcx>,
+    ptr_ty: Ty<'tcx>,
+) -> Option<(&'a Operand<'tcx>, u64)> {
+    let (bits, _) = int_width(tcx, op.ty(mir, tcx))?;
+    let (sum, mask) = match operand_int_def(tcx, mir, op)? {
+        IntOp::Binary(BinOp::BitAnd, a, b) => match const_value(tcx, mir, b) {
+            Some(mask) => (a, mask),
+            None => (b, const_value(tcx, mir, a)?),
+        },
+        _ => return None,
+    };
+    let (addr, add) = match operand_int_def(tcx, mir, sum)? {
+        IntOp::Binary(BinOp::Add, a, b) => match const_value(tcx, mir, b) {
+            Some(add) => (a, add),
+            None => (b, const_value(tcx, mir, a)?),
+        },
+        _ => return None,
+    };
+    let align = power_of_two(add.checked_add(1)?)?;
+    if mask != truncate(!add, bits) {
+        return None;
+    }
+    let ptr = addr_source(tcx, mir, addr)?;
+    if ptr.ty(mir, tcx) != ptr_ty {
+        return None;
+    }
+    Some((ptr, align))
+}
+
+/// If `bop(ops.0, ops.1)` checks the alignment of a pointer, as in `(p as usize & (n - 1)) == 0`
+/// or `p as usize % n == 0`, return `p` and the alignment `n`.
+pub fn aligned_check_source<'a, 'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &'a Body<'tcx>,
+    bop: BinOp,
+    ops: &(Operand<'tcx>, Operand<'tcx>),
+) -> Option<(&'a Operand<'tcx>, u64)> {
+    if !matches!(bop, BinOp::Eq | BinOp::Ne) || const_value(tcx, mir, &ops.1)? != 0 {
+        return None;
+    }
+    let (addr, align) = match operand_int_def(tcx, mir, &ops.0)? {
+        IntOp::Binary(BinOp::BitAnd, a, b) => {
+            (a, power_of_two(const_value(tcx, mir, b)?.checked_add(1)?)?)
+        }
+        IntOp::Binary(BinOp::Rem, a, b) => (a, power_of_two(const_value(tcx, mir, b)?)?),
+        _ => return None,
+    };
+    Some((addr_source(tcx, mir, addr)?, align))
+}
@@ -1,31 +1,64 @@
+use crate::alias;
 use crate::annotate::AnnotationBuffer;
 use crate::borrowck;
+use crate::closure_callbacks;
 use crate::context::{
-    self, AnalysisCtxt, AnalysisCtxtData, DontRewriteFieldReason, DontRewriteFnReason,
+    self, AnalysisCtxt, AnalysisCtxtData, Assignment, DontRewriteFieldReason, DontRewriteFnReason,
     DontRewriteStaticReason, FlagSet, GlobalAnalysisCtxt, GlobalAssignment, LFnSig, LTy, LTyCtxt,
     LocalAssignment, PermissionSet, PointerId, PointerInfo,
 };
+use crate::crate_facts::{self, CrateFacts};
 use crate::dataflow;
-use crate::dataflow::DataflowConstraints;
+use crate::dataflow::{DataflowConstraints, PermRemovals};
 use crate::equiv::GlobalEquivSet;
 use crate::equiv::LocalEquivSet;
+use crate::errno_result;
+use crate::ffi_escape;
+use crate::file_io;
+use crate::flex_array;
+use crate::fn_cache;
+use crate::fn_ptr_fields;
+use crate::fn_tables;
+use crate::html_report;
+use crate::interactive;
+use crate::known_fn_db::load_known_fn_db;
 use crate::labeled_ty::LabeledTyCtxt;
+use crate::len_fields;
+use crate::linked_lists::{self, ListShape};
+use crate::migration_stats::{self, MigrationStats};
+use crate::out_params;
+use crate::output_facts::{self, FactKind, OutputFacts};
 use crate::panic_detail;
 use crate::panic_detail::PanicDetail;
+use crate::pdg_facts;
 use crate::pointee_type;
-use crate::pointee_type::PointeeTypes;
+use crate::pointee_type::{ConflictResolution, PointeeTypes};
 use crate::pointer_id::GlobalPointerTable;
 use crate::pointer_id::LocalPointerTable;
 use crate::pointer_id::PointerTable;
+use crate::provenance;
 use crate::recent_writes::RecentWrites;
+use crate::refcount;
 use crate::rewrite;
+use crate::rewrite_manifest;
+use crate::sarif::{self, SarifLog};
+use crate::self_ref;
+use crate::sentinel;
+use crate::stable_facts;
+use crate::static_bufs;
+use crate::static_inits::{self, InitOwner, InitValue};
+use crate::static_kinds::{self, StaticKind};
+use crate::thread_shared;
 use crate::type_desc;
 use crate::type_desc::Ownership;
+use crate::unions;
+use crate::user_data;
 use crate::util;
-use crate::util::Callee;
-use crate::util::TestAttr;
+use crate::util::{describe_rvalue, AtomicOp, Callee, RvalueDesc};
+use crate::util::{TestAttr, UserAttr};
+use crate::vec_structs;
 use ::log::warn;
-use c2rust_pdg::graph::Graphs;
+use regex::RegexSet;
 use rustc_hir::def::DefKind;
 use rustc_hir::def_id::CrateNum;
 use rustc_hir::def_id::DefId;
@@ -35,10 +68,13 @@ use rustc_hir::definitions::DefPathData;
 use rustc_index::vec::IndexVec;
 use rustc_middle::mir::visit::{PlaceContext, Visitor};
 use rustc_middle::mir::{
-    AggregateKind, BindingForm, Body, Constant, Local, LocalDecl, LocalInfo, LocalKind, Location,
-    Operand, Place, PlaceElem, PlaceRef, Rvalue, StatementKind,
+    AggregateKind, BindingForm, Body, BorrowKind, CastKind, Constant, InlineAsmOperand, Local,
+    LocalDecl, LocalInfo, LocalKind, Location, Mutability, Operand, Place, PlaceElem, PlaceRef,
+    Rvalue, StatementKind, TerminatorKind, VarDebugInfoContents,
 };
+use rustc_middle::ty::adjustment::PointerCast;
 use rustc_middle::ty::GenericArgKind;
+use rustc_middle::ty::GenericParamDefKind;
 use rustc_middle::ty::Ty;
 use rustc_middle::ty::TyCtxt;
 use rustc_middle::ty::TyKind;
@@ -55,11 +91,13 @@ use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::iter;
+use std::mem;
 use std::ops::Deref;
 use std::ops::DerefMut;
 use std::ops::Index;
 use std::panic::AssertUnwindSafe;
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 /// A wrapper around `T` that dynamically tracks whether it's initialized or not.
 /// [`RefCell`][std::cell::RefCell] dynamically tracks borrowing and panics if the rules are
@@ -126,7 +164,7 @@ impl<T> DerefMut for MaybeUnset<T> {
 /// using pretty much the same way the [`Display`] `impl` does it.
 ///
 /// [`Display`]: std::fmt::Display
-fn is_string_literal(c: &Constant) -> bool {
+pub fn is_string_literal(c: &Constant) -> bool {
     let s = c.to_string();
     s.ends_with('"') && {
         let s = match s.strip_prefix("const ") {
@@ -201,6 +239,10 @@ fn label_rvalue_tys<'tcx>(acx: &mut AnalysisCtxt<'_, 'tcx>, mir: &Body<'tcx>) {
                         let tuple_ty = rv.ty(acx, acx.tcx());
                         acx.assign_pointer_ids(tuple_ty)
                     }
+                    AggregateKind::Closure(..) => {
+                        let closure_ty = rv.ty(acx, acx.tcx());
+                        acx.assign_pointer_ids(closure_ty)
+                    }
                     _ => continue,
                 },
                 Rvalue::Cast(_, _, ty) => {
@@ -218,6 +260,32 @@ fn label_rvalue_tys<'tcx>(acx: &mut AnalysisCtxt<'_, 'tcx>, mir: &Body<'tcx>) {
     }
 }
 
+/// Assign `PointerId`s in `acx.addr_of_local_fields` for each field of a local whose address is
+/// taken in `mir`, as in `&x.a.b`.  Every prefix of the field path gets its own `PointerId`, so
+/// the constraints in `dataflow` can relate each field to its parent.
+fn label_addr_of_local_fields<'tcx>(acx: &mut AnalysisCtxt<'_, 'tcx>, mir: &Body<'tcx>) {
+    for bb_data in mir.basic_blocks().iter() {
+        for stmt in &bb_data.statements {
+            let rv = match stmt.kind {
+                StatementKind::Assign(ref x) => &x.1,
+                _ => continue,
+            };
+            let (local, proj) = match describe_rvalue(rv) {
+                Some(RvalueDesc::AddrOfLocal { local, proj }) => (local, proj),
+                _ => continue,
+            };
+            let path = util::field_path_prefix(proj);
+            for len in 1..=path.len() {
+                let key = (local, &path[..len]);
+                if !acx.addr_of_local_fields.contains_key(&key) {
+                    let ptr = acx.new_pointer(PointerInfo::empty());
+                    acx.addr_of_local_fields.insert(key, ptr);
+                }
+            }
+        }
+    }
+}
+
 /// Set flags in `acx.ptr_info` based on analysis of the `mir`.  This is used for `PointerInfo`
 /// flags that represent non-local properties or other properties that can't be set easily when the
 /// `PointerId` is first allocated.
@@ -233,6 +301,9 @@ fn update_pointer_info<'tcx>(acx: &mut AnalysisCtxt<'_, 'tcx>, mir: &Body<'tcx>)
     // make these checks more precise at some point.
     let mut write_count = HashMap::with_capacity(mir.local_decls.len());
     let mut rhs_is_ref = HashSet::new();
+    // Locals captured by a closure, and the local each reference temporary borrows from.
+    let mut captures = HashSet::new();
+    let mut ref_targets = HashMap::new();
 
     for (bb, bb_data) in mir.basic_blocks().iter_enumerated() {
         for (i, stmt) in bb_data.statements.iter().enumerate() {
@@ -263,10 +334,70 @@ fn update_pointer_info<'tcx>(acx: &mut AnalysisCtxt<'_, 'tcx>, mir: &Body<'tcx>)
                 if !ref_pl.is_indirect() {
                     eprintln!("  record write to ref target {:?}", ref_pl);
                     *write_count.entry(ref_pl.local).or_insert(0) += 1;
+                    ref_targets.insert(pl.local, ref_pl.local);
                 }
 
                 rhs_is_ref.insert(pl.local);
             }
+
+            if let Rvalue::Aggregate(ref kind, ref ops) = *rv {
+                if let AggregateKind::Closure(..) = **kind {
+                    captures.extend(ops.iter().filter_map(|op| op.place()).map(|pl| pl.local));
+                }
+            }
+        }
+
+        // Inline assembly can do anything with the pointers passed to it, so every local used as
+        // an operand gets `OPAQUE_USE`, including any pointers nested inside its type.
+        if let TerminatorKind::InlineAsm { ref operands, .. } = bb_data.terminator().kind {
+            for op in operands {
+                let (in_value, out_place) = match *op {
+                    InlineAsmOperand::In { ref value, .. } => (Some(value), None),
+                    InlineAsmOperand::Out { place, .. } => (None, place),
+                    InlineAsmOperand::InOut {
+                        ref in_value,
+                        out_place,
+                        ..
+                    } => (Some(in_value), out_place),
+                    _ => continue,
+                };
+                if let Some(pl) = out_place {
+                    if !pl.is_indirect() {
+                        *write_count.entry(pl.local).or_insert(0) += 1;
+                    }
+                }
+                let locals = in_value
+                    .and_then(|op| op.place())
+                    .into_iter()
+                    .chain(out_place)
+                    .map(|pl| pl.local);
+                for local in locals {
+                    eprintln!("update_pointer_info: {:?} is an inline asm operand", local);
+                    let lty = acx.local_tys[local];
+                    for ptr in lty.iter().map(|lty| lty.label) {
+                        if !ptr.is_none() {
+                            acx.ptr_info_mut()[ptr].insert(PointerInfo::OPAQUE_USE);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // The closure's body sees its captured variables only through its environment, whose type is
+    // fixed along with the closure's signature.  Like inline assembly operands, the captured
+    // locals get `OPAQUE_USE`, including the locals that by-reference captures borrow from.
+    let borrowed = captures
+        .iter()
+        .filter_map(|local| ref_targets.get(local).copied())
+        .collect::<Vec<_>>();
+    for local in captures.into_iter().chain(borrowed) {
+        eprintln!("update_pointer_info: {:?} is captured by a closure", local);
+        let lty = acx.local_tys[local];
+        for ptr in lty.iter().map(|lty| lty.label) {
+            if !ptr.is_none() {
+                acx.ptr_info_mut()[ptr].insert(PointerInfo::OPAQUE_USE);
+            }
         }
     }
 
@@ -340,79 +471,1219 @@ pub(super) fn gather_foreign_sigs<'tcx>(gacx: &mut GlobalAnalysisCtxt<'tcx>, tcx
             .map(|&ty| gacx.assign_pointer_ids_with_info(ty, PointerInfo::ANNOTATED))
             .collect::<Vec<_>>();
 
-        let inputs = gacx.lcx.mk_slice(&inputs);
-        let output = gacx.assign_pointer_ids_with_info(sig.output(), PointerInfo::ANNOTATED);
-        let c_variadic = sig.c_variadic;
-        let lsig = LFnSig {
-            inputs,
-            output,
-            c_variadic,
-        };
-        gacx.fn_sigs.insert(did, lsig);
+        let inputs = gacx.lcx.mk_slice(&inputs);
+        let output = gacx.assign_pointer_ids_with_info(sig.output(), PointerInfo::ANNOTATED);
+        let c_variadic = sig.c_variadic;
+        let lsig = LFnSig {
+            inputs,
+            output,
+            c_variadic,
+        };
+        gacx.fn_sigs.insert(did, lsig);
+    }
+}
+
+/// Assign `PointerId`s for the signatures of functions from other crates that are mentioned in
+/// this crate and have an entry in `facts`, and record them in `gacx.upstream_fn_facts`.
+fn gather_upstream_sigs(
+    gacx: &mut GlobalAnalysisCtxt,
+    facts: &CrateFacts,
+    fn_ldids: &[LocalDefId],
+) {
+    let tcx = gacx.tcx;
+
+    struct UpstreamFnVisitor<'a> {
+        dids: &'a mut Vec<DefId>,
+    }
+
+    impl<'tcx> Visitor<'tcx> for UpstreamFnVisitor<'_> {
+        fn visit_constant(&mut self, c: &Constant<'tcx>, _location: Location) {
+            if let TyKind::FnDef(did, _) = *c.ty().kind() {
+                if !did.is_local() {
+                    self.dids.push(did);
+                }
+            }
+        }
+    }
+
+    let mut dids = Vec::new();
+    for &ldid in fn_ldids {
+        let ldid_const = WithOptConstParam::unknown(ldid);
+        let mir = tcx.mir_built(ldid_const);
+        let mir = mir.borrow();
+        UpstreamFnVisitor { dids: &mut dids }.visit_body(&mir);
+    }
+
+    for did in dids {
+        if gacx.upstream_fn_facts.contains_key(&did) {
+            continue;
+        }
+        let fn_facts = match facts.fns.get(&crate_facts::def_path_hash(tcx, did)) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let sig = tcx.erase_late_bound_regions(tcx.fn_sig(did));
+        let inputs = sig
+            .inputs()
+            .iter()
+            .map(|&ty| gacx.assign_pointer_ids_with_info(ty, PointerInfo::ANNOTATED))
+            .collect::<Vec<_>>();
+        let inputs = gacx.lcx.mk_slice(&inputs);
+        let output = gacx.assign_pointer_ids_with_info(sig.output(), PointerInfo::ANNOTATED);
+        let lsig = LFnSig {
+            inputs,
+            output,
+            c_variadic: sig.c_variadic,
+        };
+
+        if crate_facts::sig_pointers(&lsig).len() != fn_facts.ptrs.len() {
+            warn!("facts for {did:?} don't match its signature {lsig:?}");
+            continue;
+        }
+        gacx.fn_sigs.insert(did, lsig);
+        gacx.upstream_fn_facts.insert(did, fn_facts.clone());
+    }
+}
+
+/// Apply the facts recorded for functions from other crates to the pointers in their signatures,
+/// and forbid updates to them, since their crates have already been rewritten.
+fn apply_upstream_facts(
+    gacx: &GlobalAnalysisCtxt,
+    gasn: &mut GlobalAssignment,
+    g_updates_forbidden: &mut GlobalPointerTable<PermissionSet>,
+) {
+    for (did, fn_facts) in &gacx.upstream_fn_facts {
+        let lsig = &gacx.fn_sigs[did];
+        for (i, ptr) in crate_facts::sig_pointers(lsig).into_iter().enumerate() {
+            if ptr.is_none() {
+                continue;
+            }
+            gasn.perms[ptr] = fn_facts.perms(i);
+            gasn.flags[ptr].insert(fn_facts.flags(i));
+            g_updates_forbidden[ptr] = PermissionSet::all();
+        }
+    }
+}
+
+fn mark_foreign_fixed<'tcx>(
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    gasn: &mut GlobalAssignment,
+    tcx: TyCtxt<'tcx>,
+) {
+    // FIX the inputs and outputs of function declarations in extern blocks
+    for (did, lsig) in gacx.fn_sigs.iter() {
+        if tcx.is_foreign_item(did) {
+            make_sig_fixed(gasn, lsig);
+        }
+    }
+
+    // FIX the types of static declarations in extern blocks
+    for (did, lty) in gacx.static_tys.iter() {
+        if tcx.is_foreign_item(did) {
+            make_ty_fixed(gasn, lty);
+
+            // Also fix the `addr_of_static` permissions.
+            let ptr = gacx.addr_of_static[did];
+            gasn.flags[ptr].insert(FlagSet::FIXED);
+        }
+    }
+
+    // FIX the fields of structs mentioned in extern blocks
+    for adt_did in &gacx.adt_metadata.struct_dids {
+        if gacx.foreign_mentioned_tys.contains(adt_did) {
+            let adt_def = tcx.adt_def(adt_did);
+            let fields = adt_def.all_fields();
+            for field in fields {
+                let field_lty = gacx.field_ltys[&field.did];
+                eprintln!(
+                    "adding FIXED permission for {adt_did:?} field {:?}",
+                    field.did
+                );
+                make_ty_fixed(gasn, field_lty);
+            }
+        }
+    }
+}
+
+fn mark_all_statics_fixed<'tcx>(gacx: &mut GlobalAnalysisCtxt<'tcx>, gasn: &mut GlobalAssignment) {
+    for (did, lty) in gacx.static_tys.iter() {
+        make_ty_fixed(gasn, lty);
+
+        // Also fix the `addr_of_static` permissions.
+        let ptr = gacx.addr_of_static[did];
+        gasn.flags[ptr].insert(FlagSet::FIXED);
+    }
+}
+
+fn mark_all_structs_fixed<'tcx>(
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    gasn: &mut GlobalAssignment,
+    tcx: TyCtxt<'tcx>,
+) {
+    for adt_did in &gacx.adt_metadata.struct_dids {
+        let adt_def = tcx.adt_def(adt_did);
+        let fields = adt_def.all_fields();
+        for field in fields {
+            let field_lty = gacx.field_ltys[&field.did];
+            make_ty_fixed(gasn, field_lty);
+        }
+    }
+}
+
+/// Find `void*` struct fields that are used with several different pointee types, and record them
+/// in `AdtMetadata::generic_void_fields`.  The containing struct will be rewritten with a generic
+/// type parameter in place of `c_void`.  The fields themselves are marked `FIXED`, since they
+/// remain raw pointers.
+fn mark_generic_void_fields<'tcx>(
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    gasn: &mut GlobalAssignment,
+    pointee_types: &GlobalPointerTable<PointeeTypes<'tcx>>,
+) {
+    let tcx = gacx.tcx;
+    for adt_did in gacx.adt_metadata.struct_dids.clone() {
+        if gacx.foreign_mentioned_tys.contains(&adt_did) {
+            continue;
+        }
+        // The new parameter is always named `T` and given a default, so it must be the only type
+        // parameter.
+        let has_ty_params = tcx
+            .generics_of(adt_did)
+            .params
+            .iter()
+            .any(|p| !matches!(p.kind, GenericParamDefKind::Lifetime));
+        if has_ty_params {
+            continue;
+        }
+
+        let adt_def = tcx.adt_def(adt_did);
+        for field in adt_def.all_fields() {
+            let lty = gacx.field_ltys[&field.did];
+            let is_void_ptr = matches!(*lty.ty.kind(), TyKind::RawPtr(tm)
+                if util::is_c_void(tcx, tm.ty));
+            if !is_void_ptr || lty.label.is_none() {
+                continue;
+            }
+            // The field is rewritten in place from `*mut c_void` to `*mut T`, which requires the
+            // pointer type to be written out rather than hidden behind an alias.
+            let is_hir_ptr = match tcx.hir().get_if_local(field.did) {
+                Some(rustc_hir::Node::Field(fd)) => matches!(fd.ty.kind, rustc_hir::TyKind::Ptr(_)),
+                _ => false,
+            };
+            if !is_hir_ptr {
+                continue;
+            }
+            if gasn.flags[lty.label].contains(FlagSet::FIXED) {
+                continue;
+            }
+            if !pointee_types[lty.label].is_polymorphic() {
+                continue;
+            }
+            eprintln!(
+                "{adt_did:?} field {:?} is used with pointee types {:?}",
+                field.did, pointee_types[lty.label].ltys
+            );
+            gasn.flags[lty.label].insert(FlagSet::FIXED);
+            gacx.adt_metadata
+                .table
+                .get_mut(&adt_did)
+                .unwrap()
+                .generic_void_fields
+                .insert(field.did);
+        }
+    }
+}
+
+/// Find the user-data registration fns whose callback and user-data parameters can be replaced
+/// by a closure, and record them in `gacx.closure_callbacks`.  Only functions listed in
+/// `C2RUST_ANALYZE_CLOSURE_CALLBACK_FNS_LIST` or marked `#[c2rust_analyze_test::closure_callback]`
+/// are considered.  Their signatures and callers are rewritten by a separate pass (see
+/// `rewrite::gen_closure_callback_rewrites`), so these functions are excluded from the normal
+/// rewrites and from the user-data rewrites.
+fn mark_closure_callback_fns(
+    gacx: &mut GlobalAnalysisCtxt,
+    gasn: &mut GlobalAssignment,
+    all_fn_ldids: &[LocalDefId],
+) {
+    let tcx = gacx.tcx;
+    let mut candidates = HashSet::new();
+    if let Ok(path) = env::var("C2RUST_ANALYZE_CLOSURE_CALLBACK_FNS_LIST") {
+        read_defs_list(&mut candidates, &path).unwrap();
+    }
+    for &ldid in all_fn_ldids {
+        if util::has_test_attr(tcx, ldid, TestAttr::ClosureCallback) {
+            candidates.insert(ldid.to_def_id());
+        }
+    }
+    if candidates.is_empty() {
+        return;
+    }
+    let closure_callbacks =
+        closure_callbacks::find_closure_callback_fns(gacx, all_fn_ldids, &candidates);
+    for &did in closure_callbacks.fns.keys() {
+        make_sig_fixed(gasn, &gacx.fn_sigs[&did]);
+        gacx.dont_rewrite_fns
+            .add(did, DontRewriteFnReason::CLOSURE_CALLBACK);
+    }
+    gacx.closure_callbacks = closure_callbacks;
+}
+
+/// Find functions that pass a `void*` user-data argument through to a callback, along with the
+/// callbacks passed to them, and record them in `gacx.user_data`.  The signatures of these
+/// functions are rewritten separately (see `rewrite::user_data`), so all pointers
+/// in them are marked `FIXED`.
+fn mark_user_data_callbacks<'tcx>(
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    gasn: &mut GlobalAssignment,
+    all_fn_ldids: &[LocalDefId],
+    pointee_types: &GlobalPointerTable<PointeeTypes<'tcx>>,
+) {
+    let user_data = user_data::find_user_data_callbacks(gacx, all_fn_ldids, pointee_types);
+    for did in user_data
+        .registration_fns
+        .keys()
+        .chain(user_data.callbacks.keys())
+    {
+        make_sig_fixed(gasn, &gacx.fn_sigs[did]);
+    }
+    gacx.user_data = user_data;
+}
+
+/// Find `void*` struct fields that are only used to hold function pointers, and record them in
+/// `gacx.fn_ptr_fields`.  These fields are rewritten to `Option<fn(..)>` by a separate pass over
+/// all function bodies (see `rewrite::gen_fn_ptr_field_rewrites`), so they're marked `FIXED` here
+/// to keep the normal pointer rewrites from touching them.
+fn mark_fn_ptr_fields(gacx: &mut GlobalAnalysisCtxt, gasn: &mut GlobalAssignment) {
+    let fn_ptr_fields = fn_ptr_fields::find_fn_ptr_fields(gacx);
+    for did in fn_ptr_fields.keys() {
+        make_ty_fixed(gasn, gacx.field_ltys[did]);
+    }
+    gacx.fn_ptr_fields = fn_ptr_fields;
+}
+
+/// If `C2RUST_ANALYZE_USE_RC=1` is set, find reference-counted structs and record them in
+/// `gacx.refcount`.  Pointers to these structs are rewritten to `Rc<RefCell<T>>` by a separate
+/// pass (see `rewrite::gen_refcount_rewrites`), so the functions that use them are excluded from
+/// the normal rewrites.
+fn mark_refcounted_structs(
+    gacx: &mut GlobalAnalysisCtxt,
+    gasn: &mut GlobalAssignment,
+    all_fn_ldids: &[LocalDefId],
+) {
+    let use_rc = env::var("C2RUST_ANALYZE_USE_RC").map_or(false, |val| val == "1");
+    if !use_rc {
+        return;
+    }
+    let refcount = refcount::find_refcounted_structs(gacx, all_fn_ldids);
+    for &did in &refcount.fns {
+        make_sig_fixed(gasn, &gacx.fn_sigs[&did]);
+        gacx.dont_rewrite_fns
+            .add(did, DontRewriteFnReason::REFCOUNTED);
+    }
+    gacx.refcount = refcount;
+}
+
+/// Find structs that are shared between threads through `pthread_create`, and record them in
+/// `gacx.thread_shared`.  Pointers to these structs are rewritten to `Arc<Mutex<T>>` by a separate
+/// pass (see `rewrite::gen_thread_shared_rewrites`), so the functions that use them are excluded
+/// from the normal rewrites.
+fn mark_thread_shared_structs(
+    gacx: &mut GlobalAnalysisCtxt,
+    gasn: &mut GlobalAssignment,
+    all_fn_ldids: &[LocalDefId],
+) {
+    let thread_shared = thread_shared::find_thread_shared_structs(gacx, all_fn_ldids);
+    for &did in &thread_shared.fns {
+        make_sig_fixed(gasn, &gacx.fn_sigs[&did]);
+        gacx.dont_rewrite_fns
+            .add(did, DontRewriteFnReason::THREAD_SHARED);
+    }
+    gacx.thread_shared = thread_shared;
+}
+
+/// If `C2RUST_ANALYZE_LINKED_LISTS=1` is set, find linked-list node structs and record them in
+/// `gacx.linked_lists`.  The `next` link of a singly-linked list owns the following node, so it's
+/// given the `FREE` permission even if the program never frees the list.  Pointers to
+/// doubly-linked nodes are rewritten to arena indices by a separate pass (see
+/// `rewrite::gen_linked_list_rewrites`), so the functions that use them are excluded from the
+/// normal rewrites.
+fn mark_linked_lists(
+    gacx: &mut GlobalAnalysisCtxt,
+    gasn: &mut GlobalAssignment,
+    all_fn_ldids: &[LocalDefId],
+) {
+    let enabled = env::var("C2RUST_ANALYZE_LINKED_LISTS").map_or(false, |val| val == "1");
+    if !enabled {
+        return;
+    }
+    let linked_lists = linked_lists::find_linked_lists(gacx, all_fn_ldids);
+    for shape in linked_lists.structs.values() {
+        if let ListShape::Singly { next } = *shape {
+            let ptr = gacx.field_ltys[&next].label;
+            if !ptr.is_none() && !gasn.flags[ptr].contains(FlagSet::FIXED) {
+                gasn.perms[ptr].insert(PermissionSet::FREE);
+            }
+        }
+    }
+    for did in &linked_lists.arena_fields {
+        make_ty_fixed(gasn, gacx.field_ltys[did]);
+    }
+    for &did in &linked_lists.fns {
+        make_sig_fixed(gasn, &gacx.fn_sigs[&did]);
+        gacx.dont_rewrite_fns
+            .add(did, DontRewriteFnReason::LINKED_LIST);
+    }
+    gacx.linked_lists = linked_lists;
+}
+
+/// Find the self-pointer fields of the self-referential structs listed in
+/// `C2RUST_ANALYZE_SELF_REF_INDEX_STRUCTS_LIST` or `C2RUST_ANALYZE_SELF_REF_PIN_STRUCTS_LIST`, or
+/// marked with the corresponding `#[c2rust_analyze_test]` attributes, and record them in
+/// `gacx.self_ref`.  The self-pointers can't become references, so they're marked `FIXED`.  With
+/// the index strategy, they're rewritten to indices by a separate pass (see
+/// `rewrite::gen_self_ref_rewrites`), so the functions that use them are excluded from the normal
+/// rewrites.
+fn mark_self_ref_structs(gacx: &mut GlobalAnalysisCtxt, gasn: &mut GlobalAssignment) {
+    let tcx = gacx.tcx;
+    let mut index_candidates = HashSet::new();
+    let mut pin_candidates = HashSet::new();
+    if let Ok(path) = env::var("C2RUST_ANALYZE_SELF_REF_INDEX_STRUCTS_LIST") {
+        read_defs_list(&mut index_candidates, &path).unwrap();
+    }
+    if let Ok(path) = env::var("C2RUST_ANALYZE_SELF_REF_PIN_STRUCTS_LIST") {
+        read_defs_list(&mut pin_candidates, &path).unwrap();
+    }
+    for &did in &gacx.adt_metadata.struct_dids {
+        let ldid = match did.as_local() {
+            Some(x) => x,
+            None => continue,
+        };
+        if util::has_test_attr(tcx, ldid, TestAttr::SelfRefIndex) {
+            index_candidates.insert(did);
+        }
+        if util::has_test_attr(tcx, ldid, TestAttr::SelfRefPin) {
+            pin_candidates.insert(did);
+        }
+    }
+    if index_candidates.is_empty() && pin_candidates.is_empty() {
+        return;
+    }
+    let self_ref = self_ref::find_self_ref_structs(gacx, &index_candidates, &pin_candidates);
+    for s in self_ref.structs.values() {
+        for did in s.ptrs.keys() {
+            make_ty_fixed(gasn, gacx.field_ltys[did]);
+        }
+    }
+    for &did in &self_ref.fns {
+        make_sig_fixed(gasn, &gacx.fn_sigs[&did]);
+        gacx.dont_rewrite_fns
+            .add(did, DontRewriteFnReason::SELF_REF);
+    }
+    gacx.self_ref = self_ref;
+}
+
+/// Find growable-buffer structs whose `(ptr, len, cap)` fields can be replaced by a `Vec<T>`, and
+/// record them in `gacx.vec_structs`.  The struct definitions and the uses of these fields are
+/// rewritten by a separate pass (see `rewrite::gen_vec_struct_rewrites`), so the pointer fields
+/// and the functions that use them are excluded from the normal rewrites.
+fn mark_vec_structs(
+    gacx: &mut GlobalAnalysisCtxt,
+    gasn: &mut GlobalAssignment,
+    all_fn_ldids: &[LocalDefId],
+) {
+    let vec_structs = vec_structs::find_vec_structs(gacx, all_fn_ldids);
+    for fields in vec_structs.structs.values() {
+        make_ty_fixed(gasn, gacx.field_ltys[&fields.ptr]);
+    }
+    for &did in &vec_structs.fns {
+        make_sig_fixed(gasn, &gacx.fn_sigs[&did]);
+        gacx.dont_rewrite_fns
+            .add(did, DontRewriteFnReason::VEC_STRUCT);
+    }
+    gacx.vec_structs = vec_structs;
+}
+
+/// Find structs ending in a flexible array member whose tail can be rewritten to a `Vec<T>`, and
+/// record them in `gacx.flex_arrays`.  The tail's type is rewritten by `gen_adt_ty_rewrites`, and
+/// its uses by a separate pass (see `rewrite::gen_flex_array_rewrites`), so the functions that
+/// use it are excluded from the normal rewrites.
+fn mark_flex_array_structs(
+    gacx: &mut GlobalAnalysisCtxt,
+    gasn: &mut GlobalAssignment,
+    all_fn_ldids: &[LocalDefId],
+) {
+    let flex_arrays = flex_array::find_flex_array_structs(gacx, all_fn_ldids);
+    for &did in &flex_arrays.fns {
+        make_sig_fixed(gasn, &gacx.fn_sigs[&did]);
+        gacx.dont_rewrite_fns
+            .add(did, DontRewriteFnReason::FLEX_ARRAY);
+    }
+    gacx.flex_arrays = flex_arrays;
+}
+
+/// Find unions whose fields are only read where the field is known to be active, and record them
+/// in `gacx.unions`.  These unions are rewritten to enums by a separate pass (see
+/// `rewrite::gen_union_rewrites`), so the functions that access their fields are excluded from the
+/// normal rewrites.
+fn mark_union_enums(
+    gacx: &mut GlobalAnalysisCtxt,
+    gasn: &mut GlobalAssignment,
+    all_fn_ldids: &[LocalDefId],
+) {
+    let unions = unions::find_union_enums(gacx, all_fn_ldids);
+    for &did in &unions.fns {
+        make_sig_fixed(gasn, &gacx.fn_sigs[&did]);
+        gacx.dont_rewrite_fns
+            .add(did, DontRewriteFnReason::UNION_ENUM);
+    }
+    gacx.unions = unions;
+}
+
+/// Find the functions whose errno-style return codes can be rewritten to `Result`, and record them
+/// in `gacx.errno_results`.  Only functions listed in `C2RUST_ANALYZE_ERRNO_RESULT_FNS_LIST` or
+/// marked `#[c2rust_analyze_test::errno_result]` are considered.  These functions return integers,
+/// so the pointer analysis is unaffected; their signatures and uses are rewritten by a separate
+/// pass (see `rewrite::gen_errno_result_rewrites`).
+fn mark_errno_result_fns(gacx: &mut GlobalAnalysisCtxt, all_fn_ldids: &[LocalDefId]) {
+    let tcx = gacx.tcx;
+    let mut candidates = HashSet::new();
+    if let Ok(path) = env::var("C2RUST_ANALYZE_ERRNO_RESULT_FNS_LIST") {
+        read_defs_list(&mut candidates, &path).unwrap();
+    }
+    for &ldid in all_fn_ldids {
+        if util::has_test_attr(tcx, ldid, TestAttr::ErrnoResult) {
+            candidates.insert(ldid.to_def_id());
+        }
+    }
+    if candidates.is_empty() {
+        return;
+    }
+    gacx.errno_results = errno_result::find_errno_result_fns(tcx, all_fn_ldids, &candidates);
+}
+
+/// Find the provenance violations in each function, and record them in
+/// `gacx.provenance_violations`.  Functions that create pointers from integers are not rewritten;
+/// cross-allocation arithmetic is only reported.
+fn mark_provenance_violations(gacx: &mut GlobalAnalysisCtxt, all_fn_ldids: &[LocalDefId]) {
+    let tcx = gacx.tcx;
+    for &ldid in all_fn_ldids {
+        let did = ldid.to_def_id();
+        if gacx.fn_analysis_invalid(did) {
+            continue;
+        }
+        let mir = tcx.mir_built(WithOptConstParam::unknown(ldid));
+        let violations = provenance::find_violations(tcx, &mir.borrow());
+        if violations.is_empty() {
+            continue;
+        }
+        if violations
+            .iter()
+            .any(|v| v.kind == provenance::ProvenanceViolationKind::IntToPtr)
+        {
+            gacx.dont_rewrite_fns
+                .add(did, DontRewriteFnReason::INT_TO_PTR_CAST);
+        }
+        gacx.provenance_violations.insert(did, violations);
+    }
+}
+
+/// Find the functions whose out-parameters can be rewritten to return values, and record them in
+/// `gacx.out_params`.  Only functions listed in `C2RUST_ANALYZE_OUT_PARAM_FNS_LIST` or marked
+/// `#[c2rust_analyze_test::out_param]` are considered.  The out-parameter is removed from the
+/// signature by a separate pass (see `rewrite::gen_out_param_rewrites`), so these functions are
+/// excluded from the normal rewrites.
+fn mark_out_param_fns(
+    gacx: &mut GlobalAnalysisCtxt,
+    gasn: &mut GlobalAssignment,
+    all_fn_ldids: &[LocalDefId],
+) {
+    let tcx = gacx.tcx;
+    let mut candidates = HashSet::new();
+    if let Ok(path) = env::var("C2RUST_ANALYZE_OUT_PARAM_FNS_LIST") {
+        read_defs_list(&mut candidates, &path).unwrap();
+    }
+    for &ldid in all_fn_ldids {
+        if util::has_test_attr(tcx, ldid, TestAttr::OutParam) {
+            candidates.insert(ldid.to_def_id());
+        }
+    }
+    if candidates.is_empty() {
+        return;
+    }
+    let out_params = out_params::find_out_param_fns(tcx, all_fn_ldids, &candidates);
+    for &did in out_params.fns.keys() {
+        make_sig_fixed(gasn, &gacx.fn_sigs[&did]);
+        gacx.dont_rewrite_fns
+            .add(did, DontRewriteFnReason::OUT_PARAM);
+    }
+    gacx.out_params = out_params;
+}
+
+/// Find the functions that return pointers into static buffers, and record them in
+/// `gacx.static_bufs`.  Only functions listed in `C2RUST_ANALYZE_STATIC_BUF_FNS_LIST` or marked
+/// `#[c2rust_analyze_test::static_buf]` are considered.  Their return types are rewritten by a
+/// separate pass (see `rewrite::gen_static_buf_rewrites`), so these functions are excluded from the
+/// normal rewrites.
+fn mark_static_buf_fns(
+    gacx: &mut GlobalAnalysisCtxt,
+    gasn: &mut GlobalAssignment,
+    all_fn_ldids: &[LocalDefId],
+) {
+    let tcx = gacx.tcx;
+    let mut candidates = HashSet::new();
+    if let Ok(path) = env::var("C2RUST_ANALYZE_STATIC_BUF_FNS_LIST") {
+        read_defs_list(&mut candidates, &path).unwrap();
+    }
+    for &ldid in all_fn_ldids {
+        if util::has_test_attr(tcx, ldid, TestAttr::StaticBuf) {
+            candidates.insert(ldid.to_def_id());
+        }
+    }
+    if candidates.is_empty() {
+        return;
+    }
+    let static_bufs = static_bufs::find_static_buf_fns(tcx, all_fn_ldids, &candidates);
+    for &did in static_bufs.fns.keys() {
+        make_sig_fixed(gasn, &gacx.fn_sigs[&did]);
+        gacx.dont_rewrite_fns
+            .add(did, DontRewriteFnReason::STATIC_BUF);
+    }
+    gacx.static_bufs = static_bufs;
+}
+
+/// Find the functions with `FILE*` locals that have a single owner, and record them in
+/// `gacx.file_io`.  These locals are rewritten to `BufReader` or `BufWriter` by a separate pass
+/// (see `rewrite::gen_file_io_rewrites`), so the functions are excluded from the normal rewrites.
+fn mark_file_io_fns(
+    gacx: &mut GlobalAnalysisCtxt,
+    gasn: &mut GlobalAssignment,
+    all_fn_ldids: &[LocalDefId],
+) {
+    let file_io = file_io::find_file_io_fns(gacx.tcx, all_fn_ldids);
+    for &did in &file_io.fns {
+        make_sig_fixed(gasn, &gacx.fn_sigs[&did]);
+        gacx.dont_rewrite_fns.add(did, DontRewriteFnReason::FILE_IO);
+    }
+    gacx.file_io = file_io;
+}
+
+/// Permissions for the pointers returned by `extern` functions marked
+/// `#[c2rust::foreign_memory]`.  The memory can be read, written, and indexed in either direction,
+/// but it can't be freed, and it may be null.
+const FOREIGN_MEMORY_PERMS: PermissionSet = PermissionSet::union_all([
+    PermissionSet::READ,
+    PermissionSet::WRITE,
+    PermissionSet::OFFSET_ADD,
+    PermissionSet::OFFSET_SUB,
+]);
+
+/// Mark the pointers to foreign memory, which is managed outside of Rust, such as a region
+/// returned by `mmap`.  These are the pointers returned by known fns that return foreign memory
+/// (see `KnownFn::returns_foreign_memory`) or by `extern` functions marked
+/// `#[c2rust::foreign_memory]`, along with every pointer derived from them.
+///
+/// The returned pointers of marked functions that aren't known fns get `FOREIGN_MEMORY_PERMS`,
+/// so that the uses of derived pointers don't conflict with the fixed signature.  `FREE` is added
+/// to the `updates_forbidden` mask of all foreign memory pointers, so none of them are boxed.
+fn mark_foreign_memory(
+    gacx: &GlobalAnalysisCtxt,
+    all_fn_ldids: &[LocalDefId],
+    func_info: &mut HashMap<LocalDefId, FuncInfo>,
+    gasn: &mut GlobalAssignment,
+    g_updates_forbidden: &mut GlobalPointerTable<PermissionSet>,
+) {
+    let tcx = gacx.tcx;
+    let mut g_marks = GlobalPointerTable::<bool>::new(gacx.num_pointers());
+    for (&did, lsig) in &gacx.fn_sigs {
+        if !tcx.is_foreign_item(did) {
+            continue;
+        }
+        let known_fn = gacx.known_fn(did);
+        let marked = known_fn.map_or(false, |known_fn| known_fn.returns_foreign_memory())
+            || did.as_local().map_or(false, |ldid| {
+                util::has_user_attr(tcx, ldid, UserAttr::ForeignMemory)
+            });
+        if !marked {
+            continue;
+        }
+        for lty in lsig.output.iter() {
+            let ptr = lty.label;
+            if ptr.is_none() {
+                continue;
+            }
+            g_marks[ptr] = true;
+            // Known fns already have permissions for their return values.
+            if known_fn.is_none() {
+                gasn.perms[ptr] = FOREIGN_MEMORY_PERMS;
+            }
+        }
+    }
+
+    // Follow dataflow edges forward from the returned pointers.  Edges can pass through global
+    // pointers, such as struct fields, into other functions, so repeat until nothing changes.
+    let mut l_marks = HashMap::new();
+    loop {
+        let mut changed = false;
+        for &ldid in all_fn_ldids {
+            if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+                continue;
+            }
+            let info = &func_info[&ldid];
+            let marks = l_marks
+                .entry(ldid)
+                .or_insert_with(|| LocalPointerTable::new(info.acx_data.num_pointers()));
+            changed |= info.dataflow.propagate_forward(&mut g_marks.and_mut(marks));
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    for (ptr, &marked) in g_marks.iter() {
+        if marked {
+            g_updates_forbidden[ptr].insert(PermissionSet::FREE);
+        }
+    }
+    for (ldid, l_marks) in l_marks {
+        let info = func_info.get_mut(&ldid).unwrap();
+        for (ptr, &marked) in l_marks.iter() {
+            if marked {
+                info.l_updates_forbidden[ptr].insert(PermissionSet::FREE);
+            }
+        }
+    }
+}
+
+/// Mark the pointers used for volatile accesses `FIXED`, along with every pointer they may be
+/// derived from.  Volatile accesses usually go to memory-mapped registers, which shouldn't be
+/// accessed through references, so these pointers stay raw.  Other pointers in the same functions
+/// are rewritten as usual.
+fn mark_volatile_ptrs(
+    gacx: &GlobalAnalysisCtxt,
+    all_fn_ldids: &[LocalDefId],
+    func_info: &mut HashMap<LocalDefId, FuncInfo>,
+    gasn: &mut GlobalAssignment,
+) {
+    let tcx = gacx.tcx;
+    let mut g_marks = GlobalPointerTable::<bool>::new(gacx.num_pointers());
+    let mut l_marks = HashMap::new();
+    for &ldid in all_fn_ldids {
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+            continue;
+        }
+        let info = func_info.get_mut(&ldid).unwrap();
+        let ldid_const = WithOptConstParam::unknown(ldid);
+        let mir = tcx.mir_built(ldid_const);
+        let mir = mir.borrow();
+        let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+        let mut marks = LocalPointerTable::<bool>::new(acx.num_pointers());
+        for bb_data in mir.basic_blocks().iter() {
+            let (func, args) = match bb_data.terminator().kind {
+                TerminatorKind::Call {
+                    ref func, ref args, ..
+                } => (func, args),
+                _ => continue,
+            };
+            if matches!(
+                util::ty_callee(tcx, func.ty(&*mir, tcx)),
+                Callee::ReadVolatile | Callee::WriteVolatile
+            ) {
+                let ptr = acx.type_of(&args[0]).label;
+                if !ptr.is_none() {
+                    g_marks.and_mut(&mut marks)[ptr] = true;
+                }
+            }
+        }
+        info.acx_data.set(acx.into_data());
+        l_marks.insert(ldid, marks);
+    }
+
+    // Follow dataflow edges backward from the accessed pointers.  Edges can pass through global
+    // pointers, such as struct fields, into other functions, so repeat until nothing changes.
+    loop {
+        let mut changed = false;
+        for (ldid, marks) in &mut l_marks {
+            changed |= func_info[ldid]
+                .dataflow
+                .propagate_backward(&mut g_marks.and_mut(marks));
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    for (ptr, &marked) in g_marks.iter() {
+        if marked {
+            gasn.flags[ptr].insert(FlagSet::FIXED);
+        }
+    }
+    for (ldid, marks) in l_marks {
+        let info = func_info.get_mut(&ldid).unwrap();
+        for (ptr, &marked) in marks.iter() {
+            if marked {
+                info.lasn.flags[ptr].insert(FlagSet::FIXED);
+            }
+        }
+    }
+}
+
+/// Mark `FIXED` the pointers read from the variadic arguments of a C-variadic function with
+/// `VaListImpl::arg`.  The caller chooses the type of each variadic argument, so the value read
+/// is always a raw pointer.  The rest of the function's body is analyzed as usual, and the result
+/// is cast to a safe reference where it's used.
+fn mark_va_arg_ptrs(
+    gacx: &GlobalAnalysisCtxt,
+    all_fn_ldids: &[LocalDefId],
+    func_info: &mut HashMap<LocalDefId, FuncInfo>,
+    gasn: &mut GlobalAssignment,
+) {
+    let tcx = gacx.tcx;
+    for &ldid in all_fn_ldids {
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+            continue;
+        }
+        if !gacx.fn_sigs[&ldid.to_def_id()].c_variadic {
+            continue;
+        }
+        let info = func_info.get_mut(&ldid).unwrap();
+        let ldid_const = WithOptConstParam::unknown(ldid);
+        let mir = tcx.mir_built(ldid_const);
+        let mir = mir.borrow();
+        let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+        let mut asn = gasn.and(&mut info.lasn);
+        for bb_data in mir.basic_blocks().iter() {
+            let (func, destination) = match bb_data.terminator().kind {
+                TerminatorKind::Call {
+                    ref func,
+                    destination,
+                    ..
+                } => (func, destination),
+                _ => continue,
+            };
+            if !matches!(util::ty_callee(tcx, func.ty(&*mir, tcx)), Callee::VaArg) {
+                continue;
+            }
+            for lty in acx.type_of(destination).iter() {
+                if !lty.label.is_none() {
+                    asn.flags_mut()[lty.label].insert(FlagSet::FIXED);
+                }
+            }
+        }
+        info.acx_data.set(acx.into_data());
+    }
+}
+
+/// Check whether the atomic operation `op` can be performed on a value of type `ty` using the
+/// methods of the corresponding atomic type.  `AtomicPtr` supports only loads, stores, and
+/// exchanges, and the signed and unsigned comparisons must match the signedness of the integer.
+fn atomic_op_supported(ty: Ty, op: AtomicOp) -> bool {
+    if type_desc::atomic_ty_path(ty).is_none() {
+        return false;
+    }
+    match (ty.kind(), op) {
+        (TyKind::RawPtr(_), AtomicOp::Rmw { .. }) => false,
+        (TyKind::Int(_), AtomicOp::Rmw { signed, .. }) => signed != Some(false),
+        (TyKind::Uint(_), AtomicOp::Rmw { signed, .. }) => signed != Some(true),
+        _ => true,
+    }
+}
+
+/// Set `ATOMIC` on the pointers passed to atomic intrinsics, along with every pointer that may
+/// alias them, so they're rewritten to `&AtomicI32`, `&AtomicPtr<T>`, and so on.  The pointers
+/// stored in an `AtomicPtr` stay raw, so the pointee pointer, along with the values stored and
+/// loaded, are marked `FIXED`.  Calls that have no equivalent atomic method are handled like
+/// volatile accesses: their pointers, and every pointer they may be derived from, are `FIXED`.
+fn mark_atomic_ptrs(
+    gacx: &GlobalAnalysisCtxt,
+    all_fn_ldids: &[LocalDefId],
+    func_info: &mut HashMap<LocalDefId, FuncInfo>,
+    gasn: &mut GlobalAssignment,
+) {
+    let tcx = gacx.tcx;
+    let mut g_atomic = GlobalPointerTable::<bool>::new(gacx.num_pointers());
+    let mut g_fixed = GlobalPointerTable::<bool>::new(gacx.num_pointers());
+    let mut l_marks = HashMap::new();
+    for &ldid in all_fn_ldids {
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+            continue;
+        }
+        let info = func_info.get_mut(&ldid).unwrap();
+        let ldid_const = WithOptConstParam::unknown(ldid);
+        let mir = tcx.mir_built(ldid_const);
+        let mir = mir.borrow();
+        let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+        let mut atomic = LocalPointerTable::<bool>::new(acx.num_pointers());
+        let mut fixed = LocalPointerTable::<bool>::new(acx.num_pointers());
+        for bb_data in mir.basic_blocks().iter() {
+            let (func, args, destination) = match bb_data.terminator().kind {
+                TerminatorKind::Call {
+                    ref func,
+                    ref args,
+                    destination,
+                    ..
+                } => (func, args, destination),
+                _ => continue,
+            };
+            let op = match util::ty_callee(tcx, func.ty(&*mir, tcx)) {
+                Callee::Atomic { op, .. } => op,
+                _ => continue,
+            };
+            let ptr_lty = acx.type_of(&args[0]);
+            if ptr_lty.label.is_none() {
+                continue;
+            }
+            let pointee_lty = ptr_lty.args[0];
+            if !atomic_op_supported(pointee_lty.ty, op) {
+                g_fixed.and_mut(&mut fixed)[ptr_lty.label] = true;
+                continue;
+            }
+            g_atomic.and_mut(&mut atomic)[ptr_lty.label] = true;
+            if pointee_lty.ty.is_unsafe_ptr() {
+                let dest_lty = acx.type_of(destination);
+                let value_ltys = args[1..].iter().map(|arg| acx.type_of(arg));
+                let ltys = iter::once(pointee_lty)
+                    .chain(dest_lty.iter())
+                    .chain(value_ltys);
+                for lty in ltys {
+                    if !lty.label.is_none() {
+                        g_fixed.and_mut(&mut fixed)[lty.label] = true;
+                    }
+                }
+            }
+        }
+        info.acx_data.set(acx.into_data());
+        l_marks.insert(ldid, (atomic, fixed));
+    }
+
+    // Atomic pointers must agree with every pointer they may alias, so `ATOMIC` spreads both
+    // forward and backward, like `CELL`.  `FIXED` spreads only backward, as for volatile accesses.
+    loop {
+        let mut changed = false;
+        for (ldid, (atomic, fixed)) in &mut l_marks {
+            let dataflow = &func_info[ldid].dataflow;
+            changed |= dataflow.propagate_forward(&mut g_atomic.and_mut(atomic));
+            changed |= dataflow.propagate_backward(&mut g_atomic.and_mut(atomic));
+            changed |= dataflow.propagate_backward(&mut g_fixed.and_mut(fixed));
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    for ((ptr, &atomic), (_, &fixed)) in g_atomic.iter().zip(g_fixed.iter()) {
+        if atomic {
+            gasn.flags[ptr].insert(FlagSet::ATOMIC);
+        }
+        if fixed {
+            gasn.flags[ptr].insert(FlagSet::FIXED);
+        }
+    }
+    for (ldid, (atomic, fixed)) in l_marks {
+        let info = func_info.get_mut(&ldid).unwrap();
+        for ((ptr, &atomic), (_, &fixed)) in atomic.iter().zip(fixed.iter()) {
+            if atomic {
+                info.lasn.flags[ptr].insert(FlagSet::ATOMIC);
+            }
+            if fixed {
+                info.lasn.flags[ptr].insert(FlagSet::FIXED);
+            }
+        }
+    }
+}
+
+/// Set `CSTR` on the string arguments of `strlen`, `strcmp`, `strcpy`, and `strcat`, along with
+/// every pointer that may alias them, so they're rewritten to `&CStr`.  `&CStr` can't be written
+/// through, offset, dereferenced, or freed, so if any pointer that may alias a string is used in
+/// one of those ways, none of them are marked, and they stay slices.  `FIXED` pointers, including
+/// the parameters of the string functions themselves, stay raw and are converted with `as_ptr`
+/// and `CStr::from_ptr`, so neither mark spreads through them.  This uses the final permissions,
+/// so it runs after they've reached a fixpoint.
+fn mark_cstr_ptrs(
+    gacx: &GlobalAnalysisCtxt,
+    all_fn_ldids: &[LocalDefId],
+    func_info: &mut HashMap<LocalDefId, FuncInfo>,
+    gasn: &mut GlobalAssignment,
+) {
+    struct DerefVisitor<'a, 'b, 'tcx> {
+        acx: &'a AnalysisCtxt<'b, 'tcx>,
+        ptrs: Vec<PointerId>,
+    }
+
+    impl<'tcx> Visitor<'tcx> for DerefVisitor<'_, '_, 'tcx> {
+        fn visit_place(&mut self, pl: &Place<'tcx>, _context: PlaceContext, _location: Location) {
+            for (base, elem) in pl.iter_projections() {
+                if elem == PlaceElem::Deref {
+                    self.ptrs.push(self.acx.type_of(base).label);
+                }
+            }
+        }
+    }
+
+    let tcx = gacx.tcx;
+    let unsupported_perms = PermissionSet::WRITE | PermissionSet::OFFSET_SUB | PermissionSet::FREE;
+    let mut g_cstr = GlobalPointerTable::<bool>::new(gacx.num_pointers());
+    let mut g_bad = GlobalPointerTable::<bool>::new(gacx.num_pointers());
+    let mut g_fixed = GlobalPointerTable::<bool>::new(gacx.num_pointers());
+    for (ptr, &perms) in gasn.perms.iter() {
+        g_fixed[ptr] = gasn.flags[ptr].contains(FlagSet::FIXED);
+        g_bad[ptr] = !g_fixed[ptr] && perms.intersects(unsupported_perms);
+    }
+    let mut l_marks = HashMap::new();
+    for &ldid in all_fn_ldids {
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+            continue;
+        }
+        let info = func_info.get_mut(&ldid).unwrap();
+        let ldid_const = WithOptConstParam::unknown(ldid);
+        let mir = tcx.mir_built(ldid_const);
+        let mir = mir.borrow();
+        let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+        let mut cstr = LocalPointerTable::<bool>::new(acx.num_pointers());
+        let mut bad = LocalPointerTable::<bool>::new(acx.num_pointers());
+        let mut fixed = LocalPointerTable::<bool>::new(acx.num_pointers());
+        for (ptr, &perms) in info.lasn.perms.iter() {
+            fixed[ptr] = info.lasn.flags[ptr].contains(FlagSet::FIXED);
+            bad[ptr] = !fixed[ptr] && perms.intersects(unsupported_perms);
+        }
+
+        let mut cstr_ptrs = Vec::new();
+        let mut v = DerefVisitor {
+            acx: &acx,
+            ptrs: Vec::new(),
+        };
+        v.visit_body(&mir);
+        let mut bad_ptrs = v.ptrs;
+        for bb_data in mir.basic_blocks().iter() {
+            let (func, args, destination) = match bb_data.terminator().kind {
+                TerminatorKind::Call {
+                    ref func,
+                    ref args,
+                    destination,
+                    ..
+                } => (func, args, destination),
+                _ => continue,
+            };
+            match util::ty_callee(tcx, func.ty(&*mir, tcx)) {
+                Callee::UnknownDef(util::UnknownDefCallee::Direct {
+                    def_id,
+                    is_foreign: true,
+                    ..
+                }) => {
+                    // The first argument of `strcpy` and `strcat` is written through.
+                    let skip = match tcx.item_name(def_id).as_str() {
+                        "strlen" | "strcmp" => 0,
+                        "strcpy" | "strcat" => 1,
+                        _ => continue,
+                    };
+                    cstr_ptrs.extend(args[skip..].iter().map(|arg| acx.type_of(arg).label));
+                }
+                Callee::PtrOffset { .. } | Callee::PtrOffsetFrom => {
+                    bad_ptrs.extend(args.iter().map(|arg| acx.type_of(arg).label));
+                    bad_ptrs.push(acx.type_of(destination).label);
+                }
+                _ => {}
+            }
+        }
+
+        let fixed_tbl = g_fixed.and(&fixed);
+        for ptr in cstr_ptrs {
+            if !ptr.is_none() && !fixed_tbl[ptr] {
+                g_cstr.and_mut(&mut cstr)[ptr] = true;
+            }
+        }
+        for ptr in bad_ptrs {
+            if !ptr.is_none() && !fixed_tbl[ptr] {
+                g_bad.and_mut(&mut bad)[ptr] = true;
+            }
+        }
+        info.acx_data.set(acx.into_data());
+        l_marks.insert(ldid, (cstr, bad, fixed));
+    }
+
+    loop {
+        let mut changed = false;
+        for (ldid, (cstr, bad, fixed)) in &mut l_marks {
+            let dataflow = &func_info[ldid].dataflow;
+            let barrier = g_fixed.and(fixed);
+            changed |= dataflow.propagate_aliases(&mut g_cstr.and_mut(cstr), &barrier);
+            changed |= dataflow.propagate_aliases(&mut g_bad.and_mut(bad), &barrier);
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    for ((ptr, &cstr), (_, &bad)) in g_cstr.iter().zip(g_bad.iter()) {
+        if cstr && !bad {
+            gasn.flags[ptr].insert(FlagSet::CSTR);
+        }
+    }
+    for (ldid, (cstr, bad, _)) in l_marks {
+        let info = func_info.get_mut(&ldid).unwrap();
+        for ((ptr, &cstr), (_, &bad)) in cstr.iter().zip(bad.iter()) {
+            if cstr && !bad {
+                info.lasn.flags[ptr].insert(FlagSet::CSTR);
+            }
+        }
+    }
+}
+
+/// Pair pointer fields with the integer fields that hold their lengths, and record them in
+/// `gacx.len_fields`.  Stores of raw pointers into paired fields that become slices are rewritten
+/// using the paired length (see `mir_op::CastBuilder`).
+fn mark_len_fields(gacx: &mut GlobalAnalysisCtxt, all_fn_ldids: &[LocalDefId]) {
+    gacx.len_fields = len_fields::find_len_fields(gacx, all_fn_ldids);
+}
+
+/// Find the function parameters that point to sentinel-terminated arrays, and record them in
+/// `gacx.sentinel_params`.  Raw pointers passed to these parameters when they become slices are
+/// rewritten by scanning for the sentinel (see `mir_op::CastBuilder`).
+fn mark_sentinel_params(gacx: &mut GlobalAnalysisCtxt, all_fn_ldids: &[LocalDefId]) {
+    gacx.sentinel_params = sentinel::find_sentinel_params(gacx.tcx, all_fn_ldids);
+}
+
+/// Struct fields that own their pointee are rewritten to `Option<Box<T>>` rather than `Box<T>`,
+/// even if they are never null.  Moving a `Box` out of a field requires leaving something in its
+/// place, so uses that take ownership of the field's value are rewritten to call `take()`, which
+/// leaves `None` behind.
+fn make_owned_fields_nullable(gacx: &GlobalAnalysisCtxt, gasn: &mut GlobalAssignment) {
+    for lty in gacx.field_ltys.values() {
+        let ptr = lty.label;
+        if ptr.is_none() || gasn.flags[ptr].contains(FlagSet::FIXED) {
+            continue;
+        }
+        if gasn.perms[ptr].contains(PermissionSet::UNIQUE | PermissionSet::FREE) {
+            gasn.perms[ptr].remove(PermissionSet::NON_NULL);
+        }
     }
 }
 
-fn mark_foreign_fixed<'tcx>(
-    gacx: &mut GlobalAnalysisCtxt<'tcx>,
-    gasn: &mut GlobalAssignment,
-    tcx: TyCtxt<'tcx>,
-) {
-    // FIX the inputs and outputs of function declarations in extern blocks
-    for (did, lsig) in gacx.fn_sigs.iter() {
-        if tcx.is_foreign_item(did) {
-            make_sig_fixed(gasn, lsig);
+/// Pointers that own their pointee and are stored behind another pointer, as in a `T**`
+/// out-parameter or an array of pointers, are rewritten to `Option<Box<T>>`, for the same reason
+/// as owned fields (see `make_owned_fields_nullable`).  This turns an out-parameter into
+/// `&mut Option<Box<T>>` and an array of owned pointers into `&mut [Option<Box<T>>]`, and moving
+/// an element out uses `take()`.
+fn make_owned_pointees_nullable(acx: &AnalysisCtxt, asn: &mut Assignment) {
+    let (mut perms, flags) = asn.all_mut();
+    for lty in acx.local_tys.iter() {
+        for outer in lty.iter() {
+            if outer.label.is_none() {
+                continue;
+            }
+            let ptr = match outer.args.first() {
+                Some(pointee) => pointee.label,
+                None => continue,
+            };
+            if ptr.is_none() || flags[ptr].contains(FlagSet::FIXED) {
+                continue;
+            }
+            if perms[ptr].contains(PermissionSet::UNIQUE | PermissionSet::FREE) {
+                perms[ptr].remove(PermissionSet::NON_NULL);
+            }
         }
     }
+}
 
-    // FIX the types of static declarations in extern blocks
-    for (did, lty) in gacx.static_tys.iter() {
-        if tcx.is_foreign_item(did) {
-            make_ty_fixed(gasn, lty);
+/// Set the origin flags on the pointers created in `acx`'s function: `STACK` for the address of a
+/// local, `HEAP` for the result of an allocation, and `STATIC` for a string literal.  An allocation
+/// that's never freed lives for the rest of the program, so it's `STATIC` rather than `HEAP`, and
+/// is rewritten to a leaked `Box`.  These are propagated to the pointers derived from them
+/// afterward.  See `FlagSet::STACK`.
+fn mark_pointer_origins<'tcx>(
+    acx: &AnalysisCtxt<'_, 'tcx>,
+    mir: &Body<'tcx>,
+    asn: &mut Assignment,
+) {
+    let tcx = acx.tcx();
+    let (perms, mut flags) = asn.all_mut();
+    let mut mark = |ptr: PointerId, origin: FlagSet| {
+        if !ptr.is_none() && !flags[ptr].contains(FlagSet::FIXED) {
+            flags[ptr].insert(origin);
+        }
+    };
 
-            // Also fix the `addr_of_static` permissions.
-            let ptr = gacx.addr_of_static[did];
-            gasn.flags[ptr].insert(FlagSet::FIXED);
+    for (bb, bb_data) in mir.basic_blocks().iter_enumerated() {
+        for (i, stmt) in bb_data.statements.iter().enumerate() {
+            let rv = match stmt.kind {
+                StatementKind::Assign(ref x) => &x.1,
+                _ => continue,
+            };
+            if let Rvalue::Ref(_, _, pl) | Rvalue::AddressOf(_, pl) = *rv {
+                if !pl.is_indirect() {
+                    let loc = Location {
+                        block: bb,
+                        statement_index: i,
+                    };
+                    mark(acx.type_of_rvalue(rv, loc).label, FlagSet::STACK);
+                }
+            }
         }
-    }
 
-    // FIX the fields of structs mentioned in extern blocks
-    for adt_did in &gacx.adt_metadata.struct_dids {
-        if gacx.foreign_mentioned_tys.contains(adt_did) {
-            let adt_def = tcx.adt_def(adt_did);
-            let fields = adt_def.all_fields();
-            for field in fields {
-                let field_lty = gacx.field_ltys[&field.did];
-                eprintln!(
-                    "adding FIXED permission for {adt_did:?} field {:?}",
-                    field.did
-                );
-                make_ty_fixed(gasn, field_lty);
+        if let TerminatorKind::Call {
+            ref func,
+            destination,
+            ..
+        } = bb_data.terminator().kind
+        {
+            match util::ty_callee(tcx, func.ty(mir, tcx)) {
+                Callee::Malloc | Callee::Calloc => {
+                    let ptr = acx.type_of(destination).label;
+                    if !ptr.is_none() && !perms[ptr].contains(PermissionSet::FREE) {
+                        mark(ptr, FlagSet::STATIC);
+                    } else {
+                        mark(ptr, FlagSet::HEAP);
+                    }
+                }
+                Callee::Realloc => mark(acx.type_of(destination).label, FlagSet::HEAP),
+                _ => {}
             }
         }
     }
+
+    for lty in acx.string_literal_tys() {
+        mark(lty.label, FlagSet::STATIC);
+    }
 }
 
-fn mark_all_statics_fixed<'tcx>(gacx: &mut GlobalAnalysisCtxt<'tcx>, gasn: &mut GlobalAssignment) {
-    for (did, lty) in gacx.static_tys.iter() {
-        make_ty_fixed(gasn, lty);
+/// Classify each `static mut` that doesn't hold pointers by how it's used, and record the results
+/// in `gacx.static_kinds`.  Statics that aren't `ReadOnly` are rewritten to a safe alternative by
+/// a separate pass (see `rewrite::gen_static_kind_rewrites`), so the normal static rewrites skip
+/// them.
+fn mark_static_kinds(gacx: &mut GlobalAnalysisCtxt, gasn: &GlobalAssignment) {
+    gacx.static_kinds = static_kinds::find_static_kinds(gacx, gasn);
+}
 
-        // Also fix the `addr_of_static` permissions.
-        let ptr = gacx.addr_of_static[did];
-        gasn.flags[ptr].insert(FlagSet::FIXED);
+/// Record the pointer values in the initializers of statics in `gacx.static_inits`.  Pointers
+/// initialized to null lose `NON_NULL`, and statics and fields with unsupported initializers are
+/// left unchanged.
+fn mark_static_inits(gacx: &mut GlobalAnalysisCtxt, gasn: &mut GlobalAssignment) {
+    let info = static_inits::find_static_inits(gacx);
+    for init in &info.inits {
+        if matches!(init.value, InitValue::Null) {
+            gasn.perms[init.ptr].remove(PermissionSet::NON_NULL);
+        }
+    }
+    for &owner in &info.unsupported {
+        add_unsupported_init(gacx, gasn, owner);
     }
+    gacx.static_inits = info;
 }
 
-fn mark_all_structs_fixed<'tcx>(
-    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+fn add_unsupported_init(
+    gacx: &mut GlobalAnalysisCtxt,
     gasn: &mut GlobalAssignment,
-    tcx: TyCtxt<'tcx>,
+    owner: InitOwner,
 ) {
-    for adt_did in &gacx.adt_metadata.struct_dids {
-        let adt_def = tcx.adt_def(adt_did);
-        let fields = adt_def.all_fields();
-        for field in fields {
-            let field_lty = gacx.field_ltys[&field.did];
-            make_ty_fixed(gasn, field_lty);
+    match owner {
+        InitOwner::Static(did) => {
+            make_ty_fixed(gasn, gacx.item_lty(did));
+            gacx.dont_rewrite_statics
+                .add(did, DontRewriteStaticReason::UNSUPPORTED_INIT);
+        }
+        InitOwner::Field(did) => {
+            make_ty_fixed(gasn, gacx.field_ltys[&did]);
+            gacx.dont_rewrite_fields
+                .add(did, DontRewriteFieldReason::UNSUPPORTED_INIT);
         }
     }
 }
@@ -454,7 +1725,9 @@ fn parse_def_id(s: &str) -> Result<DefId, String> {
     Ok(def_id)
 }
 
-fn read_fixed_defs_list(fixed_defs: &mut HashSet<DefId>, path: &str) -> io::Result<()> {
+/// Read a list of `DefId`s from the file at `path`, one per line, and add them to `defs`.  Blank
+/// lines and lines starting with `#` are ignored.
+fn read_defs_list(defs: &mut HashSet<DefId>, path: &str) -> io::Result<()> {
     let f = BufReader::new(File::open(path)?);
     for (i, line) in f.lines().enumerate() {
         let line = line?;
@@ -466,7 +1739,7 @@ fn read_fixed_defs_list(fixed_defs: &mut HashSet<DefId>, path: &str) -> io::Resu
         let def_id = parse_def_id(line).unwrap_or_else(|e| {
             panic!("failed to parse {} line {}: {}", path, i + 1, e);
         });
-        fixed_defs.insert(def_id);
+        defs.insert(def_id);
     }
     Ok(())
 }
@@ -528,14 +1801,63 @@ fn check_rewrite_path_prefixes(tcx: TyCtxt, fixed_defs: &mut HashSet<DefId>, pre
     }
 }
 
+/// Read a list of regexes, separated by newlines, from the environment variable `var`.
+fn fn_filter_from_env(var: &str) -> Option<RegexSet> {
+    let patterns = env::var(var).ok()?;
+    let set = RegexSet::new(patterns.lines()).unwrap_or_else(|e| {
+        panic!("invalid regex in {}: {}", var, e);
+    });
+    Some(set)
+}
+
+/// Add to `fixed_defs` every function whose path doesn't match a regex in `include`, if it's set,
+/// or matches a regex in `exclude`.  The regexes are matched against the path printed by
+/// `def_path_str`.
+fn check_fn_filters(
+    tcx: TyCtxt,
+    fixed_defs: &mut HashSet<DefId>,
+    include: Option<&RegexSet>,
+    exclude: Option<&RegexSet>,
+) {
+    for ldid in tcx.hir_crate_items(()).definitions() {
+        if !matches!(tcx.def_kind(ldid), DefKind::Fn | DefKind::AssocFn) {
+            continue;
+        }
+        let path = tcx.def_path_str(ldid.to_def_id());
+        let included = include.map_or(true, |set| set.is_match(&path));
+        let excluded = exclude.map_or(false, |set| set.is_match(&path));
+        if !included || excluded {
+            fixed_defs.insert(ldid.to_def_id());
+        }
+    }
+}
+
 fn get_fixed_defs(tcx: TyCtxt) -> io::Result<HashSet<DefId>> {
     let mut fixed_defs = HashSet::new();
     if let Ok(path) = env::var("C2RUST_ANALYZE_FIXED_DEFS_LIST") {
-        read_fixed_defs_list(&mut fixed_defs, &path)?;
+        read_defs_list(&mut fixed_defs, &path)?;
     }
     if let Ok(prefixes) = env::var("C2RUST_ANALYZE_REWRITE_PATHS") {
         check_rewrite_path_prefixes(tcx, &mut fixed_defs, &prefixes);
     }
+    let include_fn = fn_filter_from_env("C2RUST_ANALYZE_INCLUDE_FN");
+    let exclude_fn = fn_filter_from_env("C2RUST_ANALYZE_EXCLUDE_FN");
+    if include_fn.is_some() || exclude_fn.is_some() {
+        check_fn_filters(
+            tcx,
+            &mut fixed_defs,
+            include_fn.as_ref(),
+            exclude_fn.as_ref(),
+        );
+    }
+    // Attributes in the source override the lists above.
+    for ldid in tcx.hir_crate_items(()).definitions() {
+        if util::has_user_attr(tcx, ldid, UserAttr::ForceRewrite) {
+            fixed_defs.remove(&ldid.to_def_id());
+        } else if util::has_user_attr(tcx, ldid, UserAttr::SkipRewrite) {
+            fixed_defs.insert(ldid.to_def_id());
+        }
+    }
     Ok(fixed_defs)
 }
 
@@ -567,6 +1889,18 @@ struct FuncInfo<'tcx> {
     local_pointee_types: MaybeUnset<LocalPointerTable<PointeeTypes<'tcx>>>,
     /// Table for looking up the most recent write to a given local.
     recent_writes: MaybeUnset<RecentWrites>,
+    /// Key of this function's dataflow constraints in the cache, if caching is enabled.  See
+    /// `fn_cache`.
+    cache_key: Option<String>,
+    /// Borrowck errors that caused `UNIQUE` to be removed from pointers in this function.  These
+    /// aren't recorded when the permissions are loaded from the cache.
+    borrowck_conflicts: Vec<borrowck::BorrowConflict>,
+    /// The dataflow constraints that removed permissions from pointers in this function.  Like
+    /// `borrowck_conflicts`, these aren't recorded when loading from the cache.
+    perm_removals: PermRemovals,
+    /// Total time spent propagating permissions through this function, across all iterations of
+    /// the fixpoint loop.  Compared against `C2RUST_ANALYZE_FN_TIME_BUDGET`.
+    analysis_time: Duration,
 }
 
 fn run(tcx: TyCtxt) {
@@ -585,6 +1919,15 @@ fn run(tcx: TyCtxt) {
     let mut gacx = GlobalAnalysisCtxt::new(tcx);
     let mut func_info = HashMap::new();
 
+    if let Ok(path) = env::var("C2RUST_ANALYZE_KNOWN_FNS_DB") {
+        let user_fns = load_known_fn_db(tcx, &path)
+            .unwrap_or_else(|e| panic!("failed to read known fns db {path:?}: {e}"));
+        eprintln!("loaded {} known fns from {path:?}", user_fns.len());
+        for user_fn in user_fns {
+            gacx.add_known_fn(user_fn);
+        }
+    }
+
     // Follow a postorder traversal, so that callers are visited after their callees.  This means
     // callee signatures will usually be up to date when we visit the call site.
     let all_fn_ldids = fn_body_owners_postorder(tcx);
@@ -594,6 +1937,8 @@ fn run(tcx: TyCtxt) {
     }
 
     populate_field_users(&mut gacx, &all_fn_ldids);
+    populate_local_borrow_args(&mut gacx, &all_fn_ldids);
+    populate_address_taken_fns(&mut gacx, &all_fn_ldids);
 
     // ----------------------------------
     // Label all global types
@@ -601,8 +1946,7 @@ fn run(tcx: TyCtxt) {
 
     // Assign global `PointerId`s for all pointers that appear in function signatures.
     for &ldid in &all_fn_ldids {
-        let sig = tcx.fn_sig(ldid.to_def_id());
-        let sig = tcx.erase_late_bound_regions(sig);
+        let sig = util::body_fn_sig(tcx, ldid);
 
         // All function signatures are fully annotated.
         let inputs = sig
@@ -624,6 +1968,11 @@ fn run(tcx: TyCtxt) {
 
     gather_foreign_sigs(&mut gacx, tcx);
 
+    if let Some(dir) = crate_facts::facts_dir() {
+        let facts = crate_facts::load_crate_facts(&dir);
+        gather_upstream_sigs(&mut gacx, &facts, &all_fn_ldids);
+    }
+
     // Collect all `static` items.
     let all_static_dids = all_static_items(tcx);
     eprintln!("statics:");
@@ -638,6 +1987,11 @@ fn run(tcx: TyCtxt) {
         gacx.assign_pointer_to_static(did);
     }
 
+    // Assign global `PointerId`s for types of `const` items that hold pointers.
+    for did in all_const_items(tcx) {
+        gacx.assign_pointer_to_const(did);
+    }
+
     // Label the field types of each struct.
     for ldid in tcx.hir_crate_items(()).definitions() {
         let did = ldid.to_def_id();
@@ -652,6 +2006,10 @@ fn run(tcx: TyCtxt) {
     // all field types are labeled.
     gacx.construct_region_metadata();
 
+    // Find the functions stored into each function pointer field.  Pointee type inference and
+    // dataflow analysis use these to resolve indirect calls.
+    populate_fn_table_fields(&mut gacx);
+
     // ----------------------------------
     // Infer pointee types
     // ----------------------------------
@@ -699,9 +2057,11 @@ fn run(tcx: TyCtxt) {
             }
 
             label_rvalue_tys(&mut acx, &mir);
+            label_addr_of_local_fields(&mut acx, &mir);
             update_pointer_info(&mut acx, &mir);
 
-            pointee_type::generate_constraints(&acx, &mir)
+            let pointee_constraints = pointee_type::generate_constraints(&acx, &mir);
+            (pointee_constraints, RecentWrites::new(&mir))
         }));
 
         let mut info = FuncInfo::default();
@@ -709,8 +2069,9 @@ fn run(tcx: TyCtxt) {
         info.acx_data.set(acx.into_data());
 
         match r {
-            Ok(pointee_constraints) => {
+            Ok((pointee_constraints, recent_writes)) => {
                 info.pointee_constraints.set(pointee_constraints);
+                info.recent_writes.set(recent_writes);
             }
             Err(pd) => {
                 gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::POINTEE_INVALID, pd);
@@ -718,7 +2079,6 @@ fn run(tcx: TyCtxt) {
         }
 
         info.local_pointee_types.set(local_pointee_types);
-        info.recent_writes.set(RecentWrites::new(&mir));
         func_info.insert(ldid, info);
     }
 
@@ -750,7 +2110,12 @@ fn run(tcx: TyCtxt) {
 
             let pointee_constraints = info.pointee_constraints.get();
             let pointee_types = global_pointee_types.and_mut(info.local_pointee_types.get_mut());
-            pointee_type::solve_constraints(pointee_constraints, pointee_types);
+            let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+                pointee_type::solve_constraints(pointee_constraints, pointee_types);
+            }));
+            if let Err(pd) = r {
+                gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::POINTEE_INVALID, pd);
+            }
         }
 
         if global_pointee_types == old_global_pointee_types {
@@ -758,6 +2123,25 @@ fn run(tcx: TyCtxt) {
         }
     }
 
+    // Pick a type for each pointer that's used with several incompatible pointee types.
+    for (ptr, tys) in global_pointee_types.iter_mut() {
+        if let Some(resolution) = tys.resolve_conflict(tcx) {
+            print_pointee_conflict(ptr, "global", tys, resolution);
+        }
+    }
+    for &ldid in &all_fn_ldids {
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+            continue;
+        }
+        let name = util::fn_name(tcx, ldid.to_def_id());
+        let info = func_info.get_mut(&ldid).unwrap();
+        for (ptr, tys) in info.local_pointee_types.get_mut().iter_mut() {
+            if let Some(resolution) = tys.resolve_conflict(tcx) {
+                print_pointee_conflict(ptr, name, tys, resolution);
+            }
+        }
+    }
+
     // Print results for debugging
     for &ldid in &all_fn_ldids {
         if gacx.fn_analysis_invalid(ldid.to_def_id()) {
@@ -770,11 +2154,21 @@ fn run(tcx: TyCtxt) {
         let mir = mir.borrow();
 
         let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
-        let name = tcx.item_name(ldid.to_def_id());
+        let name = util::fn_name(tcx, ldid.to_def_id());
         let pointee_types = global_pointee_types.and(info.local_pointee_types.get());
-        print_function_pointee_types(&acx, name, &mir, pointee_types);
+        let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+            print_function_pointee_types(&acx, name, &mir, pointee_types);
+        }));
 
         info.acx_data.set(acx.into_data());
+
+        if let Err(pd) = r {
+            gacx.mark_fn_failed(
+                ldid.to_def_id(),
+                DontRewriteFnReason::MISC_ANALYSIS_INVALID,
+                pd,
+            );
+        }
     }
 
     // ----------------------------------
@@ -785,6 +2179,19 @@ fn run(tcx: TyCtxt) {
     // that two pointer types must be converted to the same reference type.  Some additional data
     // computed during this the process is kept around for use in later passes.
     let mut global_equiv = GlobalEquivSet::new(gacx.num_pointers());
+    {
+        // Function table fields must have the same signature as the functions stored in them.
+        let mut no_locals = LocalEquivSet::new(0);
+        let mut equiv = global_equiv.and_mut(&mut no_locals);
+        for (a, b) in fn_tables::table_sig_equivs(&gacx) {
+            equiv.unify(a, b);
+        }
+    }
+    let cache_dir = fn_cache::cache_dir();
+    let crate_fingerprint = cache_dir
+        .as_ref()
+        .map(|_| fn_cache::crate_fingerprint(&gacx, &global_pointee_types));
+    let alias_analysis = alias::alias_analysis();
     for &ldid in &all_fn_ldids {
         if gacx.fn_analysis_invalid(ldid.to_def_id()) {
             continue;
@@ -799,11 +2206,35 @@ fn run(tcx: TyCtxt) {
         let recent_writes = info.recent_writes.get();
         let pointee_types = global_pointee_types.and(info.local_pointee_types.get());
 
-        let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
-            dataflow::generate_constraints(&acx, &mir, recent_writes, pointee_types)
-        }));
+        info.cache_key = crate_fingerprint
+            .as_ref()
+            .map(|fp| fn_cache::constraints_key(fp, &acx, &mir, pointee_types));
+        let cached = match (&cache_dir, &info.cache_key) {
+            (Some(dir), Some(key)) => {
+                fn_cache::load::<fn_cache::CachedConstraints>(dir, "constraints", key)
+            }
+            _ => None,
+        };
+
+        let r = match cached {
+            Some(x) => {
+                eprintln!("loaded cached dataflow constraints for {ldid:?}");
+                Ok(x)
+            }
+            None => {
+                let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+                    dataflow::generate_constraints(&acx, &mir, recent_writes, pointee_types)
+                }));
+                if let (Ok(x), Some(dir), Some(key)) = (&r, &cache_dir, &info.cache_key) {
+                    if let Err(e) = fn_cache::save(dir, "constraints", key, x) {
+                        warn!("failed to cache dataflow constraints for {ldid:?}: {e}");
+                    }
+                }
+                r
+            }
+        };
 
-        let (dataflow, equiv_constraints) = match r {
+        let (mut dataflow, equiv_constraints) = match r {
             Ok(x) => x,
             Err(pd) => {
                 info.acx_data.set(acx.into_data());
@@ -812,6 +2243,18 @@ fn run(tcx: TyCtxt) {
             }
         };
 
+        // Pointers that may alias through memory can't be `UNIQUE`.  These constraints aren't
+        // cached, so the cache doesn't depend on `--alias-analysis`.
+        if let Some(kind) = alias_analysis {
+            let aliased = alias::aliased_locals(tcx, &mir, kind);
+            dataflow.add_alias_conflicts(
+                aliased
+                    .into_iter()
+                    .map(|(local, loc)| (acx.local_tys[local].label, loc))
+                    .filter(|&(ptr, _)| !ptr.is_none()),
+            );
+        }
+
         // Compute local equivalence classes and dataflow constraints.
         let mut local_equiv = LocalEquivSet::new(acx.num_pointers());
         let mut equiv = global_equiv.and_mut(&mut local_equiv);
@@ -877,10 +2320,12 @@ fn run(tcx: TyCtxt) {
         //    `NOT_TEMPORARY_REF`, but not `ANNOTATED`)
         // 3. Temporary refs (`REF` but not `ANNOTATED` or `NOT_TEMPORARY_REF`)
         //
-        // Currently, we apply the `FIXED` flag to categories 1 and 2.
-        info.contains(PointerInfo::REF)
+        // Currently, we apply the `FIXED` flag to categories 1 and 2.  Pointers passed to inline
+        // assembly are also `FIXED`, as we can't see how they're used.
+        (info.contains(PointerInfo::REF)
             && (info.contains(PointerInfo::ANNOTATED)
-                || info.contains(PointerInfo::NOT_TEMPORARY_REF))
+                || info.contains(PointerInfo::NOT_TEMPORARY_REF)))
+            || info.contains(PointerInfo::OPAQUE_USE)
     }
 
     // track all types mentioned in extern blocks, we
@@ -901,6 +2346,7 @@ fn run(tcx: TyCtxt) {
     }
 
     mark_foreign_fixed(&mut gacx, &mut gasn, tcx);
+    apply_upstream_facts(&gacx, &mut gasn, &mut g_updates_forbidden);
 
     if rewrite_pointwise {
         // In pointwise mode, we restrict rewriting to a single fn at a time.  All statics and
@@ -916,6 +2362,18 @@ fn run(tcx: TyCtxt) {
         *existing_perms = perms;
     }
 
+    for (ptr, perms) in ffi_escape::ffi_param_perms(&gacx) {
+        gasn.perms[ptr] = perms;
+    }
+
+    // Start from the results of an earlier run, if there are any.
+    if let Some(dir) = stable_facts::load_dir() {
+        if let Some(facts) = stable_facts::load_analysis_facts(tcx, &dir) {
+            let count = stable_facts::apply_analysis_facts(&gacx, &mut gasn, &facts);
+            eprintln!("loaded analysis facts for {count} pointers from {dir:?}");
+        }
+    }
+
     for info in func_info.values_mut() {
         let num_pointers = info.acx_data.num_pointers();
         let mut lasn = LocalAssignment::new(num_pointers, INITIAL_PERMS, INITIAL_FLAGS);
@@ -931,30 +2389,40 @@ fn run(tcx: TyCtxt) {
         info.l_updates_forbidden.set(l_updates_forbidden);
     }
 
-    // Load permission info from PDG
-    let mut func_def_path_hash_to_ldid = HashMap::new();
-    for &ldid in &all_fn_ldids {
-        let def_path_hash: (u64, u64) = tcx.def_path_hash(ldid.to_def_id()).0.as_value();
-        eprintln!("def_path_hash {:?} = {:?}", def_path_hash, ldid);
-        func_def_path_hash_to_ldid.insert(def_path_hash, ldid);
-    }
+    // Load permission info from PDG.  See `pdg_facts`.
+    if let Some(pdg_path) = pdg_facts::pdg_facts_path() {
+        let mut func_def_path_hash_to_ldid = HashMap::new();
+        for &ldid in &all_fn_ldids {
+            let def_path_hash: (u64, u64) = tcx.def_path_hash(ldid.to_def_id()).0.as_value();
+            eprintln!("def_path_hash {:?} = {:?}", def_path_hash, ldid);
+            func_def_path_hash_to_ldid.insert(def_path_hash, ldid);
+        }
 
-    if let Some(pdg_file_path) = std::env::var_os("PDG_FILE") {
-        let f = std::fs::File::open(pdg_file_path).unwrap();
-        let graphs: Graphs = bincode::deserialize_from(f).unwrap();
+        let graphs = pdg_facts::load_pdg_facts(&pdg_path);
+        eprintln!(
+            "loaded {} PDG graphs from {pdg_path:?}",
+            graphs.graphs.len()
+        );
 
         for g in &graphs.graphs {
-            for n in &g.nodes {
+            let observed = pdg_facts::observed_perms(g);
+            for (n, observed) in g.nodes.iter().zip(observed) {
                 let def_path_hash: (u64, u64) = n.function.id.0.into();
                 let ldid = match func_def_path_hash_to_ldid.get(&def_path_hash) {
                     Some(&x) => x,
                     None => {
-                        panic!(
+                        // The PDG may include functions from other crates, or come from a
+                        // slightly different version of this one.
+                        eprintln!(
                             "pdg: unknown DefPathHash {:?} for function {:?}",
                             n.function.id, n.function.name
                         );
+                        continue;
                     }
                 };
+                if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+                    continue;
+                }
                 let info = func_info.get_mut(&ldid).unwrap();
                 let ldid_const = WithOptConstParam::unknown(ldid);
                 let mir = tcx.mir_built(ldid_const);
@@ -988,7 +2456,7 @@ fn run(tcx: TyCtxt) {
                     }
                 };
 
-                let node_info = match n.info.as_ref() {
+                let observed = match observed {
                     Some(x) => x,
                     None => {
                         eprintln!(
@@ -1000,21 +2468,12 @@ fn run(tcx: TyCtxt) {
                     }
                 };
 
+                // Observed uses add permissions, but an observed non-unique use takes away
+                // `UNIQUE`.  Several nodes may map to the same `PointerId`, so this only ever
+                // moves away from the initial assignment.
                 let old_perms = asn.perms()[ptr];
-                let mut perms = old_perms;
-                if node_info.flows_to.load.is_some() {
-                    perms.insert(PermissionSet::READ);
-                }
-                if node_info.flows_to.store.is_some() {
-                    perms.insert(PermissionSet::WRITE);
-                }
-                if node_info.flows_to.pos_offset.is_some() {
-                    perms.insert(PermissionSet::OFFSET_ADD);
-                }
-                if node_info.flows_to.neg_offset.is_some() {
-                    perms.insert(PermissionSet::OFFSET_SUB);
-                }
-                if !node_info.unique {
+                let mut perms = old_perms | (observed & !PermissionSet::UNIQUE);
+                if !observed.contains(PermissionSet::UNIQUE) {
                     perms.remove(PermissionSet::UNIQUE);
                 }
                 // TODO: PermissionSet::NON_NULL
@@ -1057,6 +2516,14 @@ fn run(tcx: TyCtxt) {
                     .add(ldid.to_def_id(), DontRewriteFnReason::USER_REQUEST);
             }
 
+            // Closures are called through the `Fn*` traits, so their signatures can't change.
+            // Their bodies are still rewritten.
+            DefKind::Closure => {
+                if let Some(lsig) = gacx.fn_sigs.get(&ldid.to_def_id()) {
+                    make_sig_fixed(&mut gasn, lsig);
+                }
+            }
+
             DefKind::Struct | DefKind::Enum | DefKind::Union => {
                 let adt_def = tcx.adt_def(ldid);
                 for field in adt_def.all_fields() {
@@ -1098,10 +2565,39 @@ fn run(tcx: TyCtxt) {
                     .add(ldid.to_def_id(), DontRewriteStaticReason::USER_REQUEST);
             }
 
+            DefKind::Const if def_fixed => {
+                if let Some(&lty) = gacx.const_tys.get(&ldid.to_def_id()) {
+                    make_ty_fixed(&mut gasn, lty);
+                    gacx.dont_rewrite_statics
+                        .add(ldid.to_def_id(), DontRewriteStaticReason::USER_REQUEST);
+                }
+            }
+
             _ => {}
         }
     }
 
+    mark_generic_void_fields(&mut gacx, &mut gasn, &global_pointee_types);
+    mark_closure_callback_fns(&mut gacx, &mut gasn, &all_fn_ldids);
+    mark_user_data_callbacks(&mut gacx, &mut gasn, &all_fn_ldids, &global_pointee_types);
+    mark_fn_ptr_fields(&mut gacx, &mut gasn);
+    mark_refcounted_structs(&mut gacx, &mut gasn, &all_fn_ldids);
+    mark_thread_shared_structs(&mut gacx, &mut gasn, &all_fn_ldids);
+    mark_linked_lists(&mut gacx, &mut gasn, &all_fn_ldids);
+    mark_self_ref_structs(&mut gacx, &mut gasn);
+    mark_vec_structs(&mut gacx, &mut gasn, &all_fn_ldids);
+    mark_flex_array_structs(&mut gacx, &mut gasn, &all_fn_ldids);
+    mark_union_enums(&mut gacx, &mut gasn, &all_fn_ldids);
+    mark_len_fields(&mut gacx, &all_fn_ldids);
+    mark_sentinel_params(&mut gacx, &all_fn_ldids);
+    mark_errno_result_fns(&mut gacx, &all_fn_ldids);
+    mark_out_param_fns(&mut gacx, &mut gasn, &all_fn_ldids);
+    mark_static_buf_fns(&mut gacx, &mut gasn, &all_fn_ldids);
+    mark_file_io_fns(&mut gacx, &mut gasn, &all_fn_ldids);
+    mark_provenance_violations(&mut gacx, &all_fn_ldids);
+    mark_static_kinds(&mut gacx, &gasn);
+    mark_static_inits(&mut gacx, &mut gasn);
+
     // ----------------------------------
     // Run dataflow solver and borrowck analysis
     // ----------------------------------
@@ -1114,10 +2610,38 @@ fn run(tcx: TyCtxt) {
         &mut gasn,
         &mut g_updates_forbidden,
     );
+    mark_foreign_memory(
+        &gacx,
+        &all_fn_ldids,
+        &mut func_info,
+        &mut gasn,
+        &mut g_updates_forbidden,
+    );
+    mark_volatile_ptrs(&gacx, &all_fn_ldids, &mut func_info, &mut gasn);
+    mark_va_arg_ptrs(&gacx, &all_fn_ldids, &mut func_info, &mut gasn);
+    mark_atomic_ptrs(&gacx, &all_fn_ldids, &mut func_info, &mut gasn);
 
     eprintln!("=== ADT Metadata ===");
     eprintln!("{:?}", gacx.adt_metadata);
 
+    let call_graph_sccs = call_graph_sccs(tcx, &all_fn_ldids);
+
+    for &ldid in &all_fn_ldids {
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+            make_return_nullable(&mut gasn, &gacx.fn_sigs[&ldid.to_def_id()]);
+        }
+    }
+
+    // Functions whose permission propagation takes longer than this in total are left
+    // unrewritten, so that a few pathological functions don't hold up the rest of the crate.
+    // The budget is only checked between iterations, so a single slow iteration still runs to
+    // completion.
+    let fn_time_budget = env::var("C2RUST_ANALYZE_FN_TIME_BUDGET").ok().map(|val| {
+        let secs = f64::from_str(&val)
+            .unwrap_or_else(|e| panic!("bad C2RUST_ANALYZE_FN_TIME_BUDGET {val:?}: {e}"));
+        Duration::from_secs_f64(secs)
+    });
+
     let mut loop_count = 0;
     loop {
         // Loop until the global assignment reaches a fixpoint.  The inner loop also runs until a
@@ -1127,50 +2651,52 @@ fn run(tcx: TyCtxt) {
         loop_count += 1;
         let old_gasn = gasn.clone();
 
-        for &ldid in &all_fn_ldids {
-            if gacx.fn_analysis_invalid(ldid.to_def_id()) {
-                continue;
-            }
-
-            let info = func_info.get_mut(&ldid).unwrap();
-            let ldid_const = WithOptConstParam::unknown(ldid);
-            let name = tcx.item_name(ldid.to_def_id());
-            let mir = tcx.mir_built(ldid_const);
-            let mir = mir.borrow();
-
-            let field_ltys = gacx.field_ltys.clone();
-            let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
-            let mut asn = gasn.and(&mut info.lasn);
-            let updates_forbidden = g_updates_forbidden.and(&info.l_updates_forbidden);
-
-            let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
-                // `dataflow.propagate` and `borrowck_mir` both run until the assignment converges
-                // on a fixpoint, so there's no need to do multiple iterations here.
-                info.dataflow
-                    .propagate(&mut asn.perms_mut(), &updates_forbidden);
-
-                borrowck::borrowck_mir(
-                    &acx,
-                    &info.dataflow,
-                    &mut asn.perms_mut(),
-                    &updates_forbidden,
-                    name.as_str(),
-                    &mir,
-                    field_ltys,
-                );
-            }));
-
-            info.acx_data.set(acx.into_data());
-
-            match r {
-                Ok(()) => {}
-                Err(pd) => {
-                    gacx.mark_fn_failed(
-                        ldid.to_def_id(),
-                        DontRewriteFnReason::BORROWCK_INVALID,
-                        pd,
+        for scc in &call_graph_sccs {
+            // Iterate each cycle of recursive functions to a fixpoint before moving on to its
+            // callers.  Otherwise, the permissions its callers see would depend on which member
+            // of the cycle happened to be visited last.
+            let mut scc_loop_count = 0;
+            loop {
+                scc_loop_count += 1;
+                assert!(scc_loop_count <= 1000);
+                let old_scc_gasn = scc.is_recursive.then(|| gasn.clone());
+
+                for &ldid in &scc.fns {
+                    if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+                        continue;
+                    }
+                    let start = Instant::now();
+                    propagate_fn_perms(
+                        &mut gacx,
+                        &mut func_info,
+                        &mut gasn,
+                        &g_updates_forbidden,
+                        ldid,
                     );
-                    continue;
+                    let info = func_info.get_mut(&ldid).unwrap();
+                    info.analysis_time += start.elapsed();
+                    if let Some(budget) = fn_time_budget {
+                        if info.analysis_time > budget {
+                            let pd = PanicDetail::new(format!(
+                                "analysis took {:.3}s, exceeding the time budget of {:.3}s",
+                                info.analysis_time.as_secs_f64(),
+                                budget.as_secs_f64(),
+                            ));
+                            gacx.mark_fn_failed(
+                                ldid.to_def_id(),
+                                DontRewriteFnReason::TIME_BUDGET,
+                                pd,
+                            );
+                        }
+                    }
+                    if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+                        make_return_nullable(&mut gasn, &gacx.fn_sigs[&ldid.to_def_id()]);
+                    }
+                }
+
+                match old_scc_gasn {
+                    Some(old_scc_gasn) if gasn != old_scc_gasn => continue,
+                    _ => break,
                 }
             }
         }
@@ -1218,6 +2744,10 @@ fn run(tcx: TyCtxt) {
             info.dataflow.propagate_cell(&mut asn);
 
             acx.check_string_literal_perms(&asn);
+
+            make_owned_pointees_nullable(&acx, &mut asn);
+
+            mark_pointer_origins(&acx, &mir, &mut asn);
         }));
 
         info.acx_data.set(acx.into_data());
@@ -1235,6 +2765,32 @@ fn run(tcx: TyCtxt) {
         }
     }
 
+    make_owned_fields_nullable(&gacx, &mut gasn);
+
+    // Propagate pointer origins.  Origins flow between functions through signatures, fields, and
+    // statics, so this repeats until no function's pointers change.
+    for &ptr in gacx.addr_of_static.values() {
+        if !gasn.flags[ptr].contains(FlagSet::FIXED) {
+            gasn.flags[ptr].insert(FlagSet::STATIC);
+        }
+    }
+    loop {
+        let mut changed = false;
+        for &ldid in &all_fn_ldids {
+            if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+                continue;
+            }
+            let info = func_info.get_mut(&ldid).unwrap();
+            let mut asn = gasn.and(&mut info.lasn);
+            changed |= info.dataflow.propagate_origins(&mut asn);
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    mark_cstr_ptrs(&gacx, &all_fn_ldids, &mut func_info, &mut gasn);
+
     // Check that these perms haven't changed.
     let mut known_perm_error_ptrs = HashSet::new();
     for (ptr, perms) in gacx.known_fn_ptr_perms() {
@@ -1291,6 +2847,92 @@ fn run(tcx: TyCtxt) {
     }
 }
 
+/// Run the dataflow and borrowck analyses on `ldid` until its part of the assignment converges.
+fn propagate_fn_perms<'tcx>(
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    func_info: &mut HashMap<LocalDefId, FuncInfo<'tcx>>,
+    gasn: &mut GlobalAssignment,
+    g_updates_forbidden: &GlobalPointerTable<PermissionSet>,
+    ldid: LocalDefId,
+) {
+    let tcx = gacx.tcx;
+    let info = func_info.get_mut(&ldid).unwrap();
+    let ldid_const = WithOptConstParam::unknown(ldid);
+    let name = util::fn_name(tcx, ldid.to_def_id());
+    let mir = tcx.mir_built(ldid_const);
+    let mir = mir.borrow();
+
+    let field_ltys = gacx.field_ltys.clone();
+    let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+    let mut asn = gasn.and(&mut info.lasn);
+    let updates_forbidden = g_updates_forbidden.and(&info.l_updates_forbidden);
+
+    // Reuse the results of an earlier run if this function and the permissions it starts from
+    // are unchanged.  See `fn_cache`.
+    let cache = fn_cache::cache_dir()
+        .zip(info.cache_key.as_ref())
+        .map(|(dir, key)| {
+            let key = fn_cache::perms_key(key, &info.dataflow, asn.perms(), updates_forbidden);
+            (dir, key)
+        });
+    if let Some((ref dir, ref key)) = cache {
+        if let Some(changes) = fn_cache::load::<fn_cache::CachedPerms>(dir, "perms", key) {
+            eprintln!("loaded cached permissions for {ldid:?}");
+            let mut perms = asn.perms_mut();
+            for (ptr, p) in changes {
+                perms[ptr] = p;
+            }
+            info.acx_data.set(acx.into_data());
+            return;
+        }
+    }
+    let old_perms = asn.perms().iter().map(|(_, &p)| p).collect::<Vec<_>>();
+
+    let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+        // `dataflow.propagate` and `borrowck_mir` both run until the assignment converges on a
+        // fixpoint, so there's no need to do multiple iterations here.
+        info.dataflow.propagate(
+            &mut asn.perms_mut(),
+            &updates_forbidden,
+            &mut info.perm_removals,
+        );
+
+        borrowck::borrowck_mir(
+            &acx,
+            &info.dataflow,
+            &mut asn.perms_mut(),
+            &updates_forbidden,
+            name.as_str(),
+            &mir,
+            field_ltys,
+            &mut info.perm_removals,
+        )
+    }));
+
+    info.acx_data.set(acx.into_data());
+
+    match r {
+        Ok(conflicts) => {
+            info.borrowck_conflicts.extend(conflicts);
+            if let Some((ref dir, ref key)) = cache {
+                let changes: fn_cache::CachedPerms = asn
+                    .perms()
+                    .iter()
+                    .zip(old_perms)
+                    .filter(|&((_, &new), old)| new != old)
+                    .map(|((ptr, &new), _)| (ptr, new))
+                    .collect();
+                if let Err(e) = fn_cache::save(dir, "perms", key, &changes) {
+                    warn!("failed to cache permissions for {ldid:?}: {e}");
+                }
+            }
+        }
+        Err(pd) => {
+            gacx.mark_fn_failed(ldid.to_def_id(), DontRewriteFnReason::BORROWCK_INVALID, pd);
+        }
+    }
+}
+
 fn run2<'tcx>(
     pointwise_fn_ldid: Option<LocalDefId>,
     tcx: TyCtxt<'tcx>,
@@ -1320,7 +2962,7 @@ fn run2<'tcx>(
         let perms = gasn.perms[ptr];
         let desc = type_desc::perms_to_desc(lty.ty, perms, flags);
         match desc.own {
-            Ownership::Imm | Ownership::Cell | Ownership::Mut => true,
+            Ownership::Imm | Ownership::Cell | Ownership::RefCell | Ownership::Mut => true,
             Ownership::Raw | Ownership::RawMut | Ownership::Rc | Ownership::Box => false,
         }
     });
@@ -1357,6 +2999,23 @@ fn run2<'tcx>(
 
     // Generate rewrites for all functions.
     let mut all_rewrites = Vec::new();
+    // Where each expression rewrite came from, for `--dry-run` and the rewrite manifest.
+    let mut rewrite_origins = HashMap::new();
+
+    // With `--interactive`, the user reviews each function's rewrites as they're generated.
+    let mut review = if interactive::enabled() {
+        let owners = all_fn_ldids
+            .iter()
+            .map(|&ldid| {
+                let hir_id = tcx.hir().local_def_id_to_hir_id(ldid);
+                let span = tcx.hir().span_with_body(hir_id);
+                (span, tcx.def_path_str(ldid.to_def_id()))
+            })
+            .collect::<Vec<_>>();
+        Some(interactive::Review::new(tcx, owners).unwrap())
+    } else {
+        None
+    };
 
     let mut manual_shim_casts = rewrite::ManualShimCasts::No;
     if let Ok(val) = env::var("C2RUST_ANALYZE_USE_MANUAL_SHIMS") {
@@ -1366,13 +3025,42 @@ fn run2<'tcx>(
     }
     let manual_shim_casts = manual_shim_casts;
 
+    gacx.checked_offsets =
+        env::var("C2RUST_ANALYZE_CHECKED_OFFSETS").map_or(false, |val| val == "1");
+    gacx.dynamic_checks = env::var("C2RUST_ANALYZE_DYNAMIC_CHECKS").map_or(false, |val| val == "1");
+    gacx.clone_callees = env::var("C2RUST_ANALYZE_CLONE_CALLEES").map_or(false, |val| val == "1");
+
+    let safe_wrappers = env::var("C2RUST_ANALYZE_SAFE_WRAPPERS").map_or(false, |val| val == "1");
+    let minimize_unsafe =
+        env::var("C2RUST_ANALYZE_MINIMIZE_UNSAFE").map_or(false, |val| val == "1");
+
+    // The operations in each rewritten function that still require `unsafe` after rewriting, and
+    // the functions that can be made safe, as of the last iteration below.
+    let mut fn_unsafety = HashMap::new();
+    let mut safe_fns = HashSet::new();
+
     // It may take multiple tries to reach a state where all rewrites succeed.
     for i in 0.. {
         assert!(i < 100);
         func_reports.clear();
         all_rewrites.clear();
+        rewrite_origins.clear();
         eprintln!("\n--- start rewriting ---");
 
+        // Functions rewritten in this iteration.
+        let mut rewritten_fns = HashSet::new();
+        fn_unsafety.clear();
+
+        // Whether any casts couldn't be built in this iteration.  Resolving them changes the
+        // types of some pointers, so the functions using them need to be rewritten again.
+        let mut any_unresolved = false;
+
+        // Statics can only hold shared references, so check the pointers in their initializers
+        // against the final assignment.
+        for owner in static_inits::find_unrewritable_owners(&gacx, &gasn) {
+            add_unsupported_init(&mut gacx, &mut gasn, owner);
+        }
+
         // Update non-rewritten items first.  This has two purposes.  First, it clears the
         // `new_keys()` lists, which we check at the end of the loop to see whether we've reached a
         // fixpoint.  Second, doing this adds the `FIXED` flag to pointers that we shouldn't
@@ -1386,12 +3074,13 @@ fn run2<'tcx>(
 
             let info = func_info.get_mut(&ldid).unwrap();
             let ldid_const = WithOptConstParam::unknown(ldid);
-            let name = tcx.item_name(ldid.to_def_id());
+            let name = util::fn_name(tcx, ldid.to_def_id());
             let mir = tcx.mir_built(ldid_const);
             let mir = mir.borrow();
             let mut acx = gacx.function_context_with_data(&mir, info.acx_data.take());
-            let asn = gasn.and(&mut info.lasn);
+            let mut asn = gasn.and(&mut info.lasn);
             let pointee_types = global_pointee_types.and(info.local_pointee_types.get());
+            let mut unresolved = Vec::new();
 
             let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
                 if util::has_test_attr(tcx, ldid, TestAttr::SkipRewrite) {
@@ -1402,14 +3091,18 @@ fn run2<'tcx>(
                 }
 
                 let hir_body_id = tcx.hir().body_owned_by(ldid);
-                let expr_rewrites = rewrite::gen_expr_rewrites(
-                    &mut acx,
-                    &asn,
-                    pointee_types,
-                    ldid.to_def_id(),
-                    &mir,
-                    hir_body_id,
-                );
+                let (expr_rewrites, expr_unresolved, expr_origins, unsafe_locs) =
+                    rewrite::gen_expr_rewrites(
+                        &mut acx,
+                        &asn,
+                        pointee_types,
+                        ldid.to_def_id(),
+                        &mir,
+                        info.recent_writes.get(),
+                        hir_body_id,
+                    );
+                unresolved = expr_unresolved;
+                rewrite_origins.extend(expr_origins);
                 let ty_rewrites = rewrite::gen_ty_rewrites(&acx, &asn, pointee_types, &mir, ldid);
                 // Print rewrites
                 let report = func_reports.entry(ldid).or_default();
@@ -1427,8 +3120,30 @@ fn run2<'tcx>(
                 writeln!(report).unwrap();
                 all_rewrites.extend(expr_rewrites);
                 all_rewrites.extend(ty_rewrites);
+                rewritten_fns.insert(ldid);
+
+                // This is also needed for the migration statistics, so it's done even when
+                // neither `safe_wrappers` nor `minimize_unsafe` is set.
+                let unsafety = rewrite::body_unsafety(&acx, &asn, &mir, &unsafe_locs);
+                fn_unsafety.insert(ldid.to_def_id(), unsafety);
             }));
 
+            // Casts with conflicting permissions on either side are left out of the rewrites.
+            // Keep the pointers involved as raw pointers, note why, and try again.
+            if !unresolved.is_empty() {
+                let mut flags = asn.flags_mut();
+                for uc in unresolved {
+                    for &ptr in &uc.ptrs {
+                        flags[ptr].insert(FlagSet::FIXED);
+                    }
+                    ann.emit(
+                        uc.span,
+                        format_args!("c2rust::unresolved(reason = {:?})", uc.reason),
+                    );
+                }
+                any_unresolved = true;
+            }
+
             info.acx_data.set(acx.into_data());
 
             match r {
@@ -1440,10 +3155,85 @@ fn run2<'tcx>(
             }
         }
 
+        // Find the unsafe functions that can be made safe.  Exported functions that get safe
+        // wrappers keep their original signature under their original name, so this has to be
+        // done before generating shim calls.
+        safe_fns = rewrite::find_safe_fns(tcx, &fn_unsafety, safe_wrappers, minimize_unsafe);
+        gacx.safe_wrapper_fns = safe_fns
+            .iter()
+            .copied()
+            .filter(|&did| rewrite::is_exported_extern_fn(tcx, did))
+            .collect();
+
         // This call never panics, which is important because this is the fallback if the more
         // sophisticated analysis and rewriting above did panic.
-        let (shim_call_rewrites, shim_fn_def_ids) = rewrite::gen_shim_call_rewrites(&gacx, &gasn);
+        let (shim_call_rewrites, mut shim_fn_def_ids) =
+            rewrite::gen_shim_call_rewrites(&gacx, &gasn);
         all_rewrites.extend(shim_call_rewrites);
+        if crate_facts::facts_dir().is_some() {
+            shim_fn_def_ids.extend(rewrite::exported_shim_fns(&gacx, &gasn));
+        }
+        all_rewrites.extend(rewrite::gen_raw_clone_definition_rewrites(&gacx));
+
+        // Fix up user-data registration fns, callbacks, and calls in functions that aren't being
+        // rewritten, so they stay consistent with the rewritten ones.
+        all_rewrites.extend(rewrite::gen_user_data_call_rewrites(&gacx));
+
+        // Rewrite pointers to reference-counted structs, in the functions that were excluded
+        // from the normal rewrites above.
+        all_rewrites.extend(rewrite::gen_refcount_rewrites(&gacx));
+
+        // Likewise for pointers to structs shared between threads.
+        all_rewrites.extend(rewrite::gen_thread_shared_rewrites(&gacx));
+
+        // Likewise for pointers to doubly-linked list nodes, which become arena indices.
+        all_rewrites.extend(rewrite::gen_linked_list_rewrites(&gacx));
+
+        // Likewise for self-pointers, which become indices into their own struct.  This also
+        // adds the `PhantomPinned` marker to structs that keep their self-pointers.
+        all_rewrites.extend(rewrite::gen_self_ref_rewrites(&gacx));
+
+        // Likewise for the fields of growable-buffer structs, which become a single `Vec<T>`.
+        all_rewrites.extend(rewrite::gen_vec_struct_rewrites(&gacx));
+
+        // Likewise for allocations and tail accesses of structs with a flexible array member.
+        all_rewrites.extend(rewrite::gen_flex_array_rewrites(&gacx));
+
+        // Likewise for unions rewritten to enums, including their definitions.
+        all_rewrites.extend(rewrite::gen_union_rewrites(&gacx));
+
+        // Split exported functions with safe bodies into a safe function and an extern wrapper,
+        // and call the safe function directly from rewritten code.
+        all_rewrites.extend(rewrite::gen_safe_wrapper_call_rewrites(
+            &gacx,
+            &rewritten_fns,
+        ));
+        for def_id in gacx.safe_wrapper_fns.clone() {
+            let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+                all_rewrites.extend(rewrite::gen_safe_wrapper_rewrites(
+                    &gacx,
+                    &gasn,
+                    def_id,
+                    manual_shim_casts,
+                ));
+            }));
+            match r {
+                Ok(()) => {}
+                Err(pd) => {
+                    gacx.mark_fn_failed(def_id, DontRewriteFnReason::SHIM_GENERATION_FAILED, pd);
+                    continue;
+                }
+            }
+        }
+
+        // Remove `unsafe` blocks and qualifiers that are no longer needed.
+        if minimize_unsafe {
+            all_rewrites.extend(rewrite::gen_unsafety_rewrites(
+                &gacx,
+                &fn_unsafety,
+                &safe_fns,
+            ));
+        }
 
         // Generate shims for functions that need them.
         for def_id in shim_fn_def_ids {
@@ -1464,14 +3254,28 @@ fn run2<'tcx>(
             }
         }
 
+        // Let the user pick which functions' rewrites to apply.  Functions whose rewrites aren't
+        // accepted are left unchanged, and the next iteration adjusts their callers to match.
+        if let Some(ref mut review) = review {
+            let (selected, rejected) = review.review_fns(mem::take(&mut all_rewrites)).unwrap();
+            all_rewrites = selected;
+            for owner in rejected {
+                gacx.dont_rewrite_fns.add(
+                    all_fn_ldids[owner].to_def_id(),
+                    DontRewriteFnReason::USER_REQUEST,
+                );
+            }
+        }
+
         // Exit the loop upon reaching a fixpoint.
         let any_new_dont_rewrite_keys = !gacx.dont_rewrite_fns.new_keys().is_empty()
             || !gacx.dont_rewrite_statics.new_keys().is_empty()
             || !gacx.dont_rewrite_fields.new_keys().is_empty();
-        if !any_new_dont_rewrite_keys {
+        if !any_new_dont_rewrite_keys && !any_unresolved {
             break;
         }
     }
+    let num_reviewed_rewrites = all_rewrites.len();
 
     // Generate rewrites for statics
     let mut static_rewrites = Vec::new();
@@ -1479,8 +3283,17 @@ fn run2<'tcx>(
         if fixed_defs.contains(&def_id) {
             continue;
         }
+        if matches!(gacx.static_kinds.get(&def_id), Some(&kind) if kind != StaticKind::ReadOnly) {
+            continue;
+        }
         static_rewrites.extend(rewrite::gen_static_rewrites(tcx, &gasn, def_id, ptr));
     }
+    static_rewrites.extend(rewrite::gen_static_kind_rewrites(&gacx));
+    static_rewrites.extend(rewrite::gen_static_init_rewrites(
+        &gacx,
+        &gasn,
+        global_pointee_types,
+    ));
     let mut statics_report = String::new();
     writeln!(
         statics_report,
@@ -1499,6 +3312,33 @@ fn run2<'tcx>(
     }
     all_rewrites.extend(static_rewrites);
 
+    // Generate rewrites for function pointer fields and their uses
+    all_rewrites.extend(rewrite::gen_fn_ptr_field_rewrites(&gacx));
+
+    // Generate rewrites for functions stored into function table fields
+    all_rewrites.extend(rewrite::gen_fn_table_rewrites(&gacx, &gasn));
+
+    // Generate rewrites for `goto`-based cleanup code
+    all_rewrites.extend(rewrite::gen_goto_cleanup_rewrites(tcx, all_fn_ldids));
+
+    // Generate rewrites for `printf` calls with literal format strings
+    all_rewrites.extend(rewrite::gen_printf_rewrites(tcx, all_fn_ldids));
+
+    // Generate rewrites for functions returning errno-style codes and their callers
+    all_rewrites.extend(rewrite::gen_errno_result_rewrites(&gacx));
+
+    // Generate rewrites for functions with out-parameters and their callers
+    all_rewrites.extend(rewrite::gen_out_param_rewrites(&gacx));
+
+    // Generate rewrites for functions returning pointers into static buffers and their callers
+    all_rewrites.extend(rewrite::gen_static_buf_rewrites(&gacx));
+
+    // Generate rewrites for `FILE*` locals that become `BufReader`s and `BufWriter`s
+    all_rewrites.extend(rewrite::gen_file_io_rewrites(&gacx));
+
+    // Generate rewrites for callback-plus-context pairs that become closures
+    all_rewrites.extend(rewrite::gen_closure_callback_rewrites(&gacx));
+
     // Generate rewrites for ADTs
     let mut adt_reports = HashMap::<DefId, String>::new();
     for &def_id in gacx.adt_metadata.table.keys() {
@@ -1525,6 +3365,32 @@ fn run2<'tcx>(
         all_rewrites.extend(adt_rewrites);
     }
 
+    // Record the final signatures of this crate's exported functions for the crates that depend
+    // on it.
+    if pointwise_fn_ldid.is_none() {
+        if let Some(dir) = crate_facts::facts_dir() {
+            let facts = crate_facts::collect_crate_facts(&gacx, &gasn);
+            crate_facts::save_crate_facts(tcx, &dir, &facts).unwrap();
+        }
+
+        let save_dir = stable_facts::save_dir();
+        let load_dir = stable_facts::load_dir();
+        if save_dir.is_some() || load_dir.is_some() {
+            let facts = stable_facts::collect_analysis_facts(&gacx, &gasn);
+            if let Some(old_facts) =
+                load_dir.and_then(|dir| stable_facts::load_analysis_facts(tcx, &dir))
+            {
+                eprintln!("\nchanges since loaded analysis facts:");
+                for change in stable_facts::diff_analysis_facts(&old_facts, &facts) {
+                    eprintln!("  {change}");
+                }
+            }
+            if let Some(dir) = save_dir {
+                stable_facts::save_analysis_facts(tcx, &dir, &facts).unwrap();
+            }
+        }
+    }
+
     // ----------------------------------
     // Print reports for tests and debugging
     // ----------------------------------
@@ -1545,41 +3411,99 @@ fn run2<'tcx>(
         }
 
         let ldid_const = WithOptConstParam::unknown(ldid);
-        let name = tcx.item_name(ldid.to_def_id());
+        let name = util::fn_name(tcx, ldid.to_def_id());
         let mir = tcx.mir_built(ldid_const);
         let mir = mir.borrow();
         let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
         let asn = gasn.and(&mut info.lasn);
         let pointee_types = global_pointee_types.and(info.local_pointee_types.get());
 
-        // Print labeling and rewrites for the current function.
+        // Print labeling and rewrites for the current function.  This runs after all rewrites
+        // have been generated, so a panic here only loses the debug output for this function.
+        let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+            eprintln!("\nfinal labeling for {:?}:", name);
+            let lcx1 = crate::labeled_ty::LabeledTyCtxt::new(tcx);
+            let lcx2 = crate::labeled_ty::LabeledTyCtxt::new(tcx);
+            for (local, decl) in mir.local_decls.iter_enumerated() {
+                print_labeling_for_var(
+                    lcx1,
+                    lcx2,
+                    format_args!("{:?} ({})", local, describe_local(tcx, decl)),
+                    acx.addr_of_local[local],
+                    acx.local_tys[local],
+                    &asn.perms(),
+                    &asn.flags(),
+                );
+            }
+            let mut field_addrs = acx.addr_of_local_fields.iter().collect::<Vec<_>>();
+            field_addrs
+                .sort_by_key(|&(&(local, path), _)| (local, path.len(), format!("{path:?}")));
+            for (&(local, path), &ptr) in field_addrs {
+                let mut lty = acx.local_tys[local];
+                let mut path_str = String::new();
+                for proj in path {
+                    lty = acx.projection_lty(lty, proj);
+                    if let PlaceElem::Field(f, _) = *proj {
+                        path_str.push_str(&format!(".{}", f.index()));
+                    }
+                }
+                print_labeling_for_var(
+                    lcx1,
+                    lcx2,
+                    format_args!(
+                        "{:?} ({}){}",
+                        local,
+                        describe_local(tcx, &mir.local_decls[local]),
+                        path_str
+                    ),
+                    ptr,
+                    lty,
+                    &asn.perms(),
+                    &asn.flags(),
+                );
+            }
+
+            if !info.borrowck_conflicts.is_empty() {
+                eprintln!("\nborrowck conflicts for {:?}:", name);
+                for c in &info.borrowck_conflicts {
+                    eprintln!(
+                        "  {} lost UNIQUE: loan `{}` at {}",
+                        c.ptr,
+                        c.loan_desc,
+                        describe_span(tcx, c.loan_span)
+                    );
+                    eprintln!(
+                        "    conflicts with `{}` at {}",
+                        c.use_desc,
+                        describe_span(tcx, c.use_span)
+                    );
+                }
+            }
 
-        eprintln!("\nfinal labeling for {:?}:", name);
-        let lcx1 = crate::labeled_ty::LabeledTyCtxt::new(tcx);
-        let lcx2 = crate::labeled_ty::LabeledTyCtxt::new(tcx);
-        for (local, decl) in mir.local_decls.iter_enumerated() {
-            print_labeling_for_var(
-                lcx1,
-                lcx2,
-                format_args!("{:?} ({})", local, describe_local(tcx, decl)),
-                acx.addr_of_local[local],
-                acx.local_tys[local],
-                &asn.perms(),
-                &asn.flags(),
-            );
-        }
+            eprintln!("\ntype assignment for {:?}:", name);
+            rewrite::dump_rewritten_local_tys(&acx, &asn, pointee_types, &mir, describe_local);
 
-        eprintln!("\ntype assignment for {:?}:", name);
-        rewrite::dump_rewritten_local_tys(&acx, &asn, pointee_types, &mir, describe_local);
+            eprintln!();
+            if let Some(report) = func_reports.remove(&ldid) {
+                eprintln!("{}", report);
+            }
+        }));
 
-        eprintln!();
-        if let Some(report) = func_reports.remove(&ldid) {
-            eprintln!("{}", report);
+        if let Err(pd) = r {
+            eprintln!(
+                "failed to print results for {:?}: {}",
+                name,
+                pd.to_string_short()
+            );
         }
 
         info.acx_data.set(acx.into_data());
     }
 
+    if let Ok(queries) = env::var("C2RUST_ANALYZE_EXPLAIN") {
+        print_explanations(&queries, &mut gacx, &mut gasn, &mut func_info, all_fn_ldids);
+    }
+
     // Generate annotations for all functions.
     for ldid in tcx.hir().body_owners() {
         // Skip any body owners that aren't present in `func_info`, and also get the info itself.
@@ -1636,9 +3560,286 @@ fn run2<'tcx>(
             emit_lty_annotations(span, rv_lty, &format!("{:?}", stmt));
         }
 
+        for c in &info.borrowck_conflicts {
+            ann.emit(
+                c.loan_span,
+                format_args!("borrowck: {} lost UNIQUE: loan `{}`", c.ptr, c.loan_desc),
+            );
+            ann.emit(
+                c.use_span,
+                format_args!("borrowck: {} conflicting use `{}`", c.ptr, c.use_desc),
+            );
+        }
+
+        info.acx_data.set(acx.into_data());
+    }
+
+    // Collect the results for each pointer.  These are summarized in the migration statistics
+    // on every run, and exported in full if requested.
+    let mut facts = OutputFacts::new(tcx);
+
+    for ldid in tcx.hir().body_owners() {
+        let info = match func_info.get_mut(&ldid) {
+            Some(x) => x,
+            None => continue,
+        };
+        let did = ldid.to_def_id();
+        let owner = tcx.def_path_str(did);
+        let failure = output_facts::fn_failure(&gacx, did);
+
+        if let Some(lsig) = gacx.fn_sigs.get(&did) {
+            let hir_id = tcx.hir().local_def_id_to_hir_id(ldid);
+            let decl = tcx.hir().fn_decl_by_hir_id(hir_id);
+            for (i, &lty) in lsig.inputs.iter().enumerate() {
+                let span = decl
+                    .and_then(|decl| decl.inputs.get(i))
+                    .map_or_else(|| tcx.def_span(did), |hir_ty| hir_ty.span);
+                facts.add_lty(
+                    &gacx,
+                    &owner,
+                    FactKind::Sig,
+                    &format!("arg {}", i),
+                    span,
+                    lty,
+                    &gasn.perms,
+                    &gasn.flags,
+                    global_pointee_types,
+                    failure.as_deref(),
+                );
+            }
+            let span = decl.map_or_else(|| tcx.def_span(did), |decl| decl.output.span());
+            facts.add_lty(
+                &gacx,
+                &owner,
+                FactKind::Sig,
+                "return",
+                span,
+                lsig.output,
+                &gasn.perms,
+                &gasn.flags,
+                global_pointee_types,
+                failure.as_deref(),
+            );
+        }
+
+        if !info.acx_data.is_set() {
+            continue;
+        }
+
+        let ldid_const = WithOptConstParam::unknown(ldid);
+        let mir = tcx.mir_built(ldid_const);
+        let mir = mir.borrow();
+        let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+        let asn = gasn.and(&mut info.lasn);
+        let pointee_types = global_pointee_types.and(info.local_pointee_types.get());
+
+        let r = panic_detail::catch_unwind(AssertUnwindSafe(|| {
+            for (local, decl) in mir.local_decls.iter_enumerated() {
+                let span = local_span(decl);
+                let desc = format!("{:?} ({})", local, describe_local(tcx, decl));
+                let kind = if local.index() > mir.arg_count && decl.is_user_variable() {
+                    FactKind::Var
+                } else {
+                    FactKind::MirLocal
+                };
+                facts.add_lty(
+                    acx.gacx,
+                    &owner,
+                    kind,
+                    &desc,
+                    span,
+                    acx.local_tys[local],
+                    &asn.perms(),
+                    &asn.flags(),
+                    &pointee_types,
+                    failure.as_deref(),
+                );
+                facts.add_ptr(
+                    &owner,
+                    FactKind::AddrOf,
+                    &format!("&{}", desc),
+                    span,
+                    acx.addr_of_local[local],
+                    &asn.perms(),
+                    &asn.flags(),
+                    None,
+                    None,
+                    failure.as_deref(),
+                );
+            }
+
+            let mut rvalue_tys = acx.rvalue_tys.iter().collect::<Vec<_>>();
+            rvalue_tys.sort_by_key(|&(loc, _)| (loc.block, loc.statement_index));
+            for (&loc, &rv_lty) in rvalue_tys {
+                let stmt = mir.stmt_at(loc).either(|stmt| stmt, |_term| unreachable!());
+                facts.add_lty(
+                    acx.gacx,
+                    &owner,
+                    FactKind::Rvalue,
+                    &format!("{:?}", stmt),
+                    stmt.source_info.span,
+                    rv_lty,
+                    &asn.perms(),
+                    &asn.flags(),
+                    &pointee_types,
+                    failure.as_deref(),
+                );
+            }
+        }));
+
+        if let Err(pd) = r {
+            eprintln!(
+                "failed to export facts for {:?}: {}",
+                owner,
+                pd.to_string_short()
+            );
+        }
+
         info.acx_data.set(acx.into_data());
     }
 
+    let mut static_dids = gacx.static_tys.keys().cloned().collect::<Vec<_>>();
+    static_dids.sort();
+    for did in static_dids {
+        let owner = tcx.def_path_str(did);
+        let failure = output_facts::static_failure(&gacx, did);
+        let span = tcx.def_span(did);
+        facts.add_lty(
+            &gacx,
+            &owner,
+            FactKind::Static,
+            "static",
+            span,
+            gacx.static_tys[&did],
+            &gasn.perms,
+            &gasn.flags,
+            global_pointee_types,
+            failure.as_deref(),
+        );
+        facts.add_ptr(
+            &owner,
+            FactKind::Static,
+            "&static",
+            span,
+            gacx.addr_of_static[&did],
+            &gasn.perms,
+            &gasn.flags,
+            None,
+            None,
+            failure.as_deref(),
+        );
+    }
+
+    let mut field_dids = gacx.field_ltys.keys().cloned().collect::<Vec<_>>();
+    field_dids.sort();
+    for did in field_dids {
+        let owner = tcx.def_path_str(did);
+        let failure = output_facts::field_failure(&gacx, did);
+        facts.add_lty(
+            &gacx,
+            &owner,
+            FactKind::Field,
+            "field",
+            tcx.def_span(did),
+            gacx.field_ltys[&did],
+            &gasn.perms,
+            &gasn.flags,
+            global_pointee_types,
+            failure.as_deref(),
+        );
+    }
+
+    if let Some(path) = output_facts::output_path() {
+        facts.write(&path).unwrap();
+    }
+    if let Some(dir) = html_report::report_dir() {
+        html_report::write_report(tcx, &dir, facts.facts()).unwrap();
+    }
+
+    let unsafety_counts = rewrite::count_unsafety(&gacx, &fn_unsafety, &safe_fns, minimize_unsafe);
+    let stats = MigrationStats::new(tcx, facts.facts(), unsafety_counts);
+
+    // Write diagnostics in SARIF format, if requested.
+    if let Some(path) = sarif::output_path() {
+        let mut log = SarifLog::new(tcx);
+
+        for ldid in tcx.hir().body_owners() {
+            let did = ldid.to_def_id();
+            let name = tcx.def_path_str(did);
+            if let Some(detail) = gacx.fns_failed.get(&did) {
+                let span = if detail.span().is_dummy() {
+                    tcx.def_span(did)
+                } else {
+                    detail.span()
+                };
+                let msg = format!(
+                    "analysis of `{}` failed: {}",
+                    name,
+                    detail.to_string_short()
+                );
+                log.add_result(sarif::Rule::AnalysisFailed, span, &msg, &[]);
+            } else {
+                let reason = gacx.dont_rewrite_fns.get(did);
+                if !reason.is_empty() {
+                    let msg = format!("`{}` is not rewritten: {:?}", name, reason);
+                    log.add_result(sarif::Rule::FnNotRewritten, tcx.def_span(did), &msg, &[]);
+                }
+            }
+
+            let info = match func_info.get(&ldid) {
+                Some(x) => x,
+                None => continue,
+            };
+            for c in &info.borrowck_conflicts {
+                let msg = format!(
+                    "pointer {} in `{}` is not unique: loan `{}` conflicts with `{}`",
+                    c.ptr, name, c.loan_desc, c.use_desc
+                );
+                log.add_result(
+                    sarif::Rule::BorrowckConflict,
+                    c.loan_span,
+                    &msg,
+                    &[(c.use_span, "conflicting use")],
+                );
+            }
+        }
+
+        let mut static_dids = gacx.static_tys.keys().cloned().collect::<Vec<_>>();
+        static_dids.sort();
+        for did in static_dids {
+            let reason = gacx.dont_rewrite_statics.get(did);
+            if !reason.is_empty() {
+                let msg = format!(
+                    "static `{}` is not rewritten: {:?}",
+                    tcx.def_path_str(did),
+                    reason
+                );
+                log.add_result(
+                    sarif::Rule::StaticNotRewritten,
+                    tcx.def_span(did),
+                    &msg,
+                    &[],
+                );
+            }
+        }
+
+        let mut field_dids = gacx.field_ltys.keys().cloned().collect::<Vec<_>>();
+        field_dids.sort();
+        for did in field_dids {
+            let reason = gacx.dont_rewrite_fields.get(did);
+            if !reason.is_empty() {
+                let msg = format!(
+                    "field `{}` is not rewritten: {:?}",
+                    tcx.def_path_str(did),
+                    reason
+                );
+                log.add_result(sarif::Rule::FieldNotRewritten, tcx.def_span(did), &msg, &[]);
+            }
+        }
+
+        log.write(&path).unwrap();
+    }
+
     // Print results for `static` items.
     eprintln!("\nfinal labeling for static items:");
     let lcx1 = crate::labeled_ty::LabeledTyCtxt::new(tcx);
@@ -1700,21 +3901,103 @@ fn run2<'tcx>(
                 format_args!("  {} = {:?}, {:?}", ptr, gasn.perms[ptr], gasn.flags[ptr]),
             );
         }
-    }
+    }
+
+    let mut adt_dids = gacx.adt_metadata.table.keys().cloned().collect::<Vec<_>>();
+    adt_dids.sort();
+    for did in adt_dids {
+        if let Some(report) = adt_reports.remove(&did) {
+            eprintln!("\n{}", report);
+        }
+    }
+
+    // ----------------------------------
+    // Apply rewrites
+    // ----------------------------------
+
+    // In annotation-only mode, no code is changed.  Instead, each declaration of a raw pointer
+    // gets a comment giving its inferred permissions, so the results can be reviewed before
+    // enabling rewrites.
+    let annotate_only = env::var("C2RUST_ANALYZE_ANNOTATE_ONLY").map_or(false, |val| val == "1");
+    let annotations = if annotate_only {
+        all_rewrites.clear();
+        let mut perm_ann = AnnotationBuffer::new(tcx);
+
+        for ldid in tcx.hir().body_owners() {
+            let did = ldid.to_def_id();
+            if tcx.is_closure(did) {
+                continue;
+            }
+            let info = match func_info.get_mut(&ldid) {
+                Some(x) => x,
+                None => continue,
+            };
+
+            if let Some(lsig) = gacx.fn_sigs.get(&did) {
+                let body = tcx.hir().body(tcx.hir().body_owned_by(ldid));
+                for (param, &lty) in body.params.iter().zip(lsig.inputs.iter()) {
+                    let name = describe_span(tcx, param.pat.span);
+                    if let Some(msg) = perm_comment(&name, lty, &gasn.perms, &gasn.flags) {
+                        perm_ann.emit_plain(param.span, msg);
+                    }
+                }
+                let hir_id = tcx.hir().local_def_id_to_hir_id(ldid);
+                if let Some(decl) = tcx.hir().fn_decl_by_hir_id(hir_id) {
+                    let msg = perm_comment("return", lsig.output, &gasn.perms, &gasn.flags);
+                    if let Some(msg) = msg {
+                        perm_ann.emit_plain(decl.output.span(), msg);
+                    }
+                }
+            }
+
+            if !info.acx_data.is_set() {
+                continue;
+            }
+            let ldid_const = WithOptConstParam::unknown(ldid);
+            let mir = tcx.mir_built(ldid_const);
+            let mir = mir.borrow();
+            let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+            let asn = gasn.and(&mut info.lasn);
+            for (local, decl) in mir.local_decls.iter_enumerated() {
+                if local.index() <= mir.arg_count || !decl.is_user_variable() {
+                    continue;
+                }
+                let name = describe_local(tcx, decl);
+                let lty = acx.local_tys[local];
+                if let Some(msg) = perm_comment(&name, lty, &asn.perms(), &asn.flags()) {
+                    perm_ann.emit_plain(local_span(decl), msg);
+                }
+            }
+            info.acx_data.set(acx.into_data());
+        }
 
-    let mut adt_dids = gacx.adt_metadata.table.keys().cloned().collect::<Vec<_>>();
-    adt_dids.sort();
-    for did in adt_dids {
-        if let Some(report) = adt_reports.remove(&did) {
-            eprintln!("\n{}", report);
+        let mut static_dids = gacx.static_tys.keys().cloned().collect::<Vec<_>>();
+        static_dids.sort();
+        for did in static_dids {
+            let name = tcx.item_name(did).to_string();
+            let lty = gacx.static_tys[&did];
+            if let Some(msg) = perm_comment(&name, lty, &gasn.perms, &gasn.flags) {
+                perm_ann.emit_plain(tcx.def_span(did), msg);
+            }
         }
-    }
 
-    // ----------------------------------
-    // Apply rewrites
-    // ----------------------------------
+        let mut field_dids = gacx.field_ltys.keys().cloned().collect::<Vec<_>>();
+        field_dids.sort();
+        for did in field_dids {
+            if !did.is_local() {
+                continue;
+            }
+            let name = tcx.item_name(did).to_string();
+            let lty = gacx.field_ltys[&did];
+            if let Some(msg) = perm_comment(&name, lty, &gasn.perms, &gasn.flags) {
+                perm_ann.emit_plain(tcx.def_span(did), msg);
+            }
+        }
 
-    let annotations = ann.finish();
+        perm_ann.finish()
+    } else {
+        ann.finish()
+    };
 
     // Apply rewrite to all functions at once.
     let mut update_files = rewrite::UpdateFiles::No;
@@ -1727,6 +4010,9 @@ fn run2<'tcx>(
             "alongside" => {
                 update_files = rewrite::UpdateFiles::Alongside;
             }
+            "patch" => {
+                update_files = rewrite::UpdateFiles::Patch;
+            }
             "pointwise" => {
                 let pointwise_fn_ldid = pointwise_fn_ldid.expect(
                     "C2RUST_ANALYZE_REWRITE_MODE=pointwise, \
@@ -1738,7 +4024,24 @@ fn run2<'tcx>(
             _ => panic!("bad value {:?} for C2RUST_ANALYZE_REWRITE_MODE", val),
         }
     }
-    rewrite::apply_rewrites(tcx, all_rewrites, annotations, update_files);
+    // Let the user pick which of the rewrites generated after the rewrite loop to apply.
+    if let Some(mut review) = review {
+        let rest = all_rewrites.split_off(num_reviewed_rewrites.min(all_rewrites.len()));
+        all_rewrites.extend(review.review_rest(rest).unwrap());
+        review.finish().unwrap();
+    }
+
+    // Record the rewrites in the manifest, if requested.
+    if let Some(path) = rewrite_manifest::output_path() {
+        let entries = rewrite_manifest::build_entries(tcx, &all_rewrites, &rewrite_origins);
+        rewrite_manifest::write(&path, &entries).unwrap();
+    }
+
+    if env::var("C2RUST_ANALYZE_DRY_RUN").map_or(false, |val| val == "1") {
+        rewrite::print_dry_run(tcx, &all_rewrites, &rewrite_origins);
+    } else {
+        rewrite::apply_rewrites(tcx, all_rewrites, annotations, update_files);
+    }
 
     // ----------------------------------
     // Report caught panics
@@ -1782,6 +4085,15 @@ fn run2<'tcx>(
         eprintln!("analysis of {def_id:?} failed: {flags:?}");
     }
 
+    if !gacx.provenance_violations.is_empty() {
+        eprintln!("\nprovenance violations (must fix):");
+        for def_id in sorted_def_ids(gacx.provenance_violations.keys().copied()) {
+            for v in &gacx.provenance_violations[&def_id] {
+                eprintln!("{def_id:?}: {} at {:?}: {}", v.kind, v.span, v.desc);
+            }
+        }
+    }
+
     eprintln!(
         "\nsaw errors in {} / {} functions",
         gacx.fns_failed.len(),
@@ -1794,6 +4106,12 @@ fn run2<'tcx>(
             known_perm_error_fns.len()
         );
     }
+
+    eprintln!();
+    stats.print();
+    if let Some(path) = migration_stats::json_path() {
+        stats.write(&path).unwrap();
+    }
 }
 
 pub trait AssignPointerIds<'tcx> {
@@ -1853,6 +4171,17 @@ fn make_sig_fixed(gasn: &mut GlobalAssignment, lsig: &LFnSig) {
     }
 }
 
+/// Remove `NON_NULL` from the pointer returned by a function whose body wasn't analyzed.  Callers
+/// rely on the return type to tell whether their null checks on the result are needed (see
+/// `mark_null_checked` in `dataflow::type_check`), so it must not claim to be non-null without
+/// evidence from the body.
+fn make_return_nullable(gasn: &mut GlobalAssignment, lsig: &LFnSig) {
+    let ptr = lsig.output.label;
+    if !ptr.is_none() {
+        gasn.perms[ptr].remove(PermissionSet::NON_NULL);
+    }
+}
+
 /// For testing, putting #[c2rust_analyze_test::fail_before_analysis] on a function marks it as
 /// failed at this point.
 fn apply_test_attr_fail_before_analysis(
@@ -1904,6 +4233,33 @@ fn apply_test_attr_force_non_null_args(
     }
 }
 
+/// Build the comment for `--annotate-only` giving the permissions of the raw pointers in `lty`,
+/// outermost first, along with any flags they have.  Returns `None` if `lty` contains no raw
+/// pointers.
+fn perm_comment(
+    name: &str,
+    lty: LTy,
+    perms: &impl Index<PointerId, Output = PermissionSet>,
+    flags: &impl Index<PointerId, Output = FlagSet>,
+) -> Option<String> {
+    let parts = lty
+        .iter()
+        .filter(|lty| matches!(lty.ty.kind(), TyKind::RawPtr(_)) && !lty.label.is_none())
+        .map(|lty| {
+            let ptr = lty.label;
+            if flags[ptr].is_empty() {
+                format!("{:?}", perms[ptr])
+            } else {
+                format!("{:?} [{:?}]", perms[ptr], flags[ptr])
+            }
+        })
+        .collect::<Vec<_>>();
+    if parts.is_empty() {
+        return None;
+    }
+    Some(format!("c2rust: {}: {}", name, parts.join("; ")))
+}
+
 fn local_span(decl: &LocalDecl) -> Span {
     let mut span = decl.source_info.span;
     if let Some(ref info) = decl.local_info {
@@ -1917,6 +4273,173 @@ fn local_span(decl: &LocalDecl) -> Span {
     span
 }
 
+/// Print the reasons why the pointers in the variables named in `queries` lack `UNIQUE` or
+/// `NON_NULL`.  `queries` is a comma-separated list of entries like `foo::bar::p`, naming the
+/// variable `p` in the function `foo::bar`.
+///
+/// Each missing permission is traced back through the dataflow constraints that removed it, one
+/// copy at a time, to the statement or borrowck conflict that removed it first.
+fn print_explanations<'tcx>(
+    queries: &str,
+    gacx: &mut GlobalAnalysisCtxt<'tcx>,
+    gasn: &mut GlobalAssignment,
+    func_info: &mut HashMap<LocalDefId, FuncInfo<'tcx>>,
+    all_fn_ldids: &[LocalDefId],
+) {
+    let tcx = gacx.tcx;
+    for query in queries.split(',').filter(|q| !q.is_empty()) {
+        eprintln!("\nexplain {:?}:", query);
+        let (fn_path, var_name) = match query.rsplit_once("::") {
+            Some(x) => x,
+            None => {
+                eprintln!("  expected a query like `path::to::fn::var`");
+                continue;
+            }
+        };
+        let ldid = match all_fn_ldids
+            .iter()
+            .copied()
+            .find(|&ldid| tcx.def_path_str(ldid.to_def_id()) == fn_path)
+        {
+            Some(x) => x,
+            None => {
+                eprintln!("  no function named {:?}", fn_path);
+                continue;
+            }
+        };
+        if gacx.fn_analysis_invalid(ldid.to_def_id()) {
+            eprintln!("  analysis of {:?} failed", fn_path);
+            continue;
+        }
+
+        let mir = tcx.mir_built(WithOptConstParam::unknown(ldid));
+        let mir = mir.borrow();
+        let local = mir.var_debug_info.iter().find_map(|vdi| match vdi.value {
+            VarDebugInfoContents::Place(pl) if vdi.name.as_str() == var_name => pl.as_local(),
+            _ => None,
+        });
+        let local = match local {
+            Some(x) => x,
+            None => {
+                eprintln!("  no variable named {:?} in {:?}", var_name, fn_path);
+                continue;
+            }
+        };
+
+        let info = func_info.get_mut(&ldid).unwrap();
+        let acx = gacx.function_context_with_data(&mir, info.acx_data.take());
+        let lty = acx.local_tys[local];
+        info.acx_data.set(acx.into_data());
+
+        for ptr in lty.iter().map(|lty| lty.label).filter(|ptr| !ptr.is_none()) {
+            let (perms, flags) = {
+                let info = func_info.get_mut(&ldid).unwrap();
+                let asn = gasn.and(&mut info.lasn);
+                (asn.perms()[ptr], asn.flags()[ptr])
+            };
+            eprintln!("  {}: perms = {:?}, flags = {:?}", ptr, perms, flags);
+            for perm in [PermissionSet::UNIQUE, PermissionSet::NON_NULL] {
+                if !perms.contains(perm) {
+                    eprintln!("    missing {:?}:", perm);
+                    explain_removal(gacx, func_info, all_fn_ldids, ldid, ptr, perm);
+                }
+            }
+        }
+    }
+}
+
+/// Print the chain of constraints that removed `perm` from `ptr`, which appears in `ldid`.
+fn explain_removal(
+    gacx: &GlobalAnalysisCtxt,
+    func_info: &HashMap<LocalDefId, FuncInfo>,
+    all_fn_ldids: &[LocalDefId],
+    mut ldid: LocalDefId,
+    mut ptr: PointerId,
+    perm: PermissionSet,
+) {
+    let tcx = gacx.tcx;
+    let mut seen = HashSet::new();
+    'chain: while seen.insert((ldid, ptr)) {
+        // Local pointers can only lose permissions in their own function, but global ones can
+        // lose them in any function that uses them.
+        let candidates = iter::once(ldid).chain(
+            all_fn_ldids
+                .iter()
+                .copied()
+                .filter(|&f| ptr.is_global() && f != ldid),
+        );
+        for f in candidates {
+            if gacx.fn_analysis_invalid(f.to_def_id()) {
+                continue;
+            }
+            let info = &func_info[&f];
+            let name = util::fn_name(tcx, f.to_def_id());
+
+            if perm == PermissionSet::UNIQUE {
+                if let Some(c) = info.borrowck_conflicts.iter().find(|c| c.ptr == ptr) {
+                    eprintln!(
+                        "      {} lost {:?} in {:?}: borrowck: loan `{}` at {}",
+                        ptr,
+                        perm,
+                        name,
+                        c.loan_desc,
+                        describe_span(tcx, c.loan_span)
+                    );
+                    eprintln!(
+                        "        conflicts with `{}` at {}",
+                        c.use_desc,
+                        describe_span(tcx, c.use_span)
+                    );
+                    return;
+                }
+            }
+
+            let cause = match info.dataflow.removal_cause(&info.perm_removals, ptr, perm) {
+                Some(x) => x,
+                None => continue,
+            };
+            let at = match cause.origin {
+                Some(loc) => {
+                    let mir = tcx.mir_built(WithOptConstParam::unknown(f));
+                    let mir = mir.borrow();
+                    let (span, desc) = mir.stmt_at(loc).either(
+                        |stmt| (stmt.source_info.span, format!("{:?}", stmt)),
+                        |term| (term.source_info.span, format!("{:?}", term.kind)),
+                    );
+                    format!("`{}` at {}", desc, describe_span(tcx, span))
+                }
+                None => "function entry".to_owned(),
+            };
+            match cause.from {
+                Some(from) => {
+                    eprintln!(
+                        "      {} lost {:?} in {:?}: {} ({}), copied from {}",
+                        ptr, perm, name, at, cause.desc, from
+                    );
+                    ldid = f;
+                    ptr = from;
+                    continue 'chain;
+                }
+                None => {
+                    eprintln!(
+                        "      {} lost {:?} in {:?}: {} ({})",
+                        ptr, perm, name, at, cause.desc
+                    );
+                    return;
+                }
+            }
+        }
+
+        // The permission was removed by the initial assignment, or by a run whose results were
+        // loaded from the cache.
+        eprintln!(
+            "      {} lacked {:?} before dataflow propagation",
+            ptr, perm
+        );
+        return;
+    }
+}
+
 fn describe_local(tcx: TyCtxt, decl: &LocalDecl) -> String {
     let span = local_span(decl);
     describe_span(tcx, span)
@@ -2026,6 +4549,28 @@ fn print_function_pointee_types<'tcx>(
     }
 }
 
+/// Report a pointer that's used with several incompatible pointee types, listing each type along
+/// with the place where the pointer was first used at that type.
+fn print_pointee_conflict(
+    ptr: PointerId,
+    scope: impl Display,
+    tys: &PointeeTypes,
+    resolution: ConflictResolution,
+) {
+    let action = match resolution {
+        ConflictResolution::CommonPrefix(lty) => format!("using common prefix struct {:?}", lty.ty),
+        ConflictResolution::Bytes(lty) => format!("using byte type {:?}", lty.ty),
+        ConflictResolution::KeepRaw => "keeping raw pointer".to_owned(),
+    };
+    eprintln!("pointee type conflict for {ptr:?} ({scope}): {action}");
+    for ty in tys.distinct_tys() {
+        match tys.sources.get(&ty) {
+            Some(span) => eprintln!("  {ty:?} used at {span:?}"),
+            None => eprintln!("  {ty:?}"),
+        }
+    }
+}
+
 /// Return `LocalDefId`s for all `static`s.
 fn all_static_items(tcx: TyCtxt) -> Vec<DefId> {
     let mut order = Vec::new();
@@ -2041,6 +4586,28 @@ fn all_static_items(tcx: TyCtxt) -> Vec<DefId> {
     order
 }
 
+/// Return `DefId`s for all `const` items whose types contain pointers.
+fn all_const_items(tcx: TyCtxt) -> Vec<DefId> {
+    let mut order = Vec::new();
+
+    for root_ldid in tcx.hir_crate_items(()).definitions() {
+        match tcx.def_kind(root_ldid) {
+            DefKind::Const => {}
+            _ => continue,
+        }
+        let did = root_ldid.to_def_id();
+        let has_ptrs = tcx
+            .type_of(did)
+            .walk()
+            .any(|arg| matches!(arg.unpack(), GenericArgKind::Type(ty) if ty.is_any_ptr()));
+        if has_ptrs {
+            order.push(did);
+        }
+    }
+
+    order
+}
+
 fn is_impl_clone(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
     let clone_trait_def_id = match tcx.lang_items().clone_trait() {
         Some(def_id) => def_id,
@@ -2054,8 +4621,107 @@ fn is_impl_clone(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
     false
 }
 
-/// Return all `LocalDefId`s for all `fn`s that are `body_owners`, ordered according to a postorder
-/// traversal of the graph of references between bodies.  Also returns the callgraph itself, in the
+/// A strongly connected component of the callgraph: a single function, or a set of functions
+/// that all call each other, directly or indirectly.
+struct CallGraphScc {
+    fns: Vec<LocalDefId>,
+    /// Whether the functions call each other.  This is true for every component with more than
+    /// one function, and for a single function that calls itself.
+    is_recursive: bool,
+}
+
+/// Group `fn_ldids` into the strongly connected components of the callgraph.  The components are
+/// returned in postorder, so each one comes after all the components it calls into.  Within a
+/// component, functions keep their relative order from `fn_ldids`.
+fn call_graph_sccs(tcx: TyCtxt, fn_ldids: &[LocalDefId]) -> Vec<CallGraphScc> {
+    let index_of = fn_ldids
+        .iter()
+        .enumerate()
+        .map(|(i, &ldid)| (ldid, i))
+        .collect::<HashMap<_, _>>();
+    let callees = fn_ldids
+        .iter()
+        .map(|&ldid| {
+            let mut callees = Vec::new();
+            for_each_callee(tcx, ldid, |callee_ldid| {
+                if let Some(&j) = index_of.get(&callee_ldid) {
+                    callees.push(j);
+                }
+            });
+            callees.sort();
+            callees.dedup();
+            callees
+        })
+        .collect::<Vec<_>>();
+
+    // Tarjan's algorithm, using an explicit stack of `(node, next callee index)` frames in place
+    // of recursion.
+    const UNVISITED: usize = usize::MAX;
+    let n = fn_ldids.len();
+    let mut index = vec![UNVISITED; n];
+    let mut lowlink = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = Vec::new();
+    let mut next_index = 0;
+    let mut sccs = Vec::new();
+
+    for root in 0..n {
+        if index[root] != UNVISITED {
+            continue;
+        }
+        let mut frames = vec![(root, 0)];
+        index[root] = next_index;
+        lowlink[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while let Some(&(v, i)) = frames.last() {
+            if let Some(&w) = callees[v].get(i) {
+                frames.last_mut().unwrap().1 += 1;
+                if index[w] == UNVISITED {
+                    index[w] = next_index;
+                    lowlink[w] = next_index;
+                    next_index += 1;
+                    stack.push(w);
+                    on_stack[w] = true;
+                    frames.push((w, 0));
+                } else if on_stack[w] {
+                    lowlink[v] = lowlink[v].min(index[w]);
+                }
+                continue;
+            }
+
+            frames.pop();
+            if let Some(&(u, _)) = frames.last() {
+                lowlink[u] = lowlink[u].min(lowlink[v]);
+            }
+            if lowlink[v] == index[v] {
+                let mut members = Vec::new();
+                loop {
+                    let w = stack.pop().unwrap();
+                    on_stack[w] = false;
+                    members.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                members.sort();
+                let is_recursive = members.len() > 1 || callees[v].contains(&v);
+                sccs.push(CallGraphScc {
+                    fns: members.into_iter().map(|i| fn_ldids[i]).collect(),
+                    is_recursive,
+                });
+            }
+        }
+    }
+
+    sccs
+}
+
+/// Return all `LocalDefId`s for all `fn`s and closures that are `body_owners`, ordered according
+/// to a postorder traversal of the graph of references between bodies.  Each closure counts as a
+/// callee of the body that creates it.  Also returns the callgraph itself, in the
 /// form of a map from callee `LocalDefId` to a set of caller `LocalDefId`s.
 pub(super) fn fn_body_owners_postorder(tcx: TyCtxt) -> Vec<LocalDefId> {
     let mut seen = HashSet::new();
@@ -2077,6 +4743,7 @@ pub(super) fn fn_body_owners_postorder(tcx: TyCtxt) -> Vec<LocalDefId> {
                     continue;
                 }
             }
+            DefKind::Closure => {}
             DefKind::AnonConst | DefKind::Const | DefKind::Static(_) => continue,
             dk => panic!(
                 "unexpected def_kind {:?} for body_owner {:?}",
@@ -2136,6 +4803,17 @@ fn for_each_callee(tcx: TyCtxt, ldid: LocalDefId, f: impl FnMut(LocalDefId)) {
             }
             (self.f)(ldid);
         }
+
+        fn visit_rvalue(&mut self, rvalue: &Rvalue<'tcx>, location: Location) {
+            if let Rvalue::Aggregate(ref kind, _) = *rvalue {
+                if let AggregateKind::Closure(def_id, _) = **kind {
+                    if let Some(ldid) = def_id.as_local() {
+                        (self.f)(ldid);
+                    }
+                }
+            }
+            self.super_rvalue(rvalue, location);
+        }
     }
 
     CalleeVisitor { tcx, mir, f }.visit_body(mir);
@@ -2220,6 +4898,175 @@ fn populate_field_users(gacx: &mut GlobalAnalysisCtxt, fn_ldids: &[LocalDefId])
     }
 }
 
+/// Call `f(callee, i)` for each call within the body of `ldid` whose `i`th argument is a raw
+/// pointer made from a `&mut` borrow of one of the caller's locals, as in `callee(&mut x)` or
+/// `callee(&mut x as *mut T)`.
+fn for_each_local_borrow_arg(tcx: TyCtxt, ldid: LocalDefId, mut f: impl FnMut(LocalDefId, usize)) {
+    let ldid_const = WithOptConstParam::unknown(ldid);
+    let mir = tcx.mir_built(ldid_const);
+    let mir = mir.borrow();
+    let mir: &Body = &mir;
+
+    // The rvalue assigned to each local, for locals that are assigned exactly once.
+    let mut defs = HashMap::new();
+    let mut multi_defs = HashSet::new();
+    for bb_data in mir.basic_blocks().iter() {
+        for stmt in &bb_data.statements {
+            if let StatementKind::Assign(ref x) = stmt.kind {
+                let (pl, ref rv) = **x;
+                if defs.insert(pl.local, rv).is_some() || !pl.projection.is_empty() {
+                    multi_defs.insert(pl.local);
+                }
+            }
+        }
+    }
+    let def_of = |local: Local| {
+        if multi_defs.contains(&local) {
+            return None;
+        }
+        defs.get(&local).copied()
+    };
+
+    for bb_data in mir.basic_blocks().iter() {
+        let (func, args) = match bb_data.terminator().kind {
+            TerminatorKind::Call {
+                ref func, ref args, ..
+            } => (func, args),
+            _ => continue,
+        };
+        let callee = match util::ty_callee(tcx, func.ty(mir, tcx)) {
+            Callee::LocalDef { def_id, .. } => match def_id.as_local() {
+                Some(x) => x,
+                None => continue,
+            },
+            _ => continue,
+        };
+        for (i, arg) in args.iter().enumerate() {
+            // Rustc lowers both forms to `tmp = &mut x; arg = &raw mut *tmp`.
+            let tmp = match arg.place().and_then(|pl| pl.as_local()).and_then(def_of) {
+                Some(&Rvalue::AddressOf(Mutability::Mut, pl)) => match *pl.projection {
+                    [PlaceElem::Deref] => pl.local,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            let borrowed = match def_of(tmp) {
+                Some(&Rvalue::Ref(_, BorrowKind::Mut { .. }, pl)) => pl,
+                _ => continue,
+            };
+            if mir.local_kind(borrowed.local) == LocalKind::Var && !borrowed.is_indirect() {
+                f(callee, i);
+            }
+        }
+    }
+}
+
+/// Populate `gacx.fn_local_borrow_args`.
+fn populate_local_borrow_args(gacx: &mut GlobalAnalysisCtxt, fn_ldids: &[LocalDefId]) {
+    for &ldid in fn_ldids {
+        let mut args = Vec::new();
+        for_each_local_borrow_arg(gacx.tcx, ldid, |callee, i| args.push((callee, i)));
+        gacx.fn_local_borrow_args.insert(ldid, args);
+    }
+}
+
+/// Populate `gacx.address_taken_fns`.  Rustc turns every use of a function as a value into a
+/// `ReifyFnPointer` cast of the function item, so we look for those casts.
+fn populate_address_taken_fns(gacx: &mut GlobalAnalysisCtxt, fn_ldids: &[LocalDefId]) {
+    let tcx = gacx.tcx;
+    let mut seen = HashSet::new();
+    for &ldid in fn_ldids {
+        let ldid_const = WithOptConstParam::unknown(ldid);
+        let mir = tcx.mir_built(ldid_const);
+        let mir = mir.borrow();
+        for bb_data in mir.basic_blocks().iter() {
+            for stmt in &bb_data.statements {
+                let rv = match stmt.kind {
+                    StatementKind::Assign(ref x) => &x.1,
+                    _ => continue,
+                };
+                let op = match *rv {
+                    Rvalue::Cast(CastKind::Pointer(PointerCast::ReifyFnPointer), ref op, _) => op,
+                    _ => continue,
+                };
+                let did = match op.constant().map(|c| c.ty().kind()) {
+                    Some(&TyKind::FnDef(did, _)) => did,
+                    _ => continue,
+                };
+                if let Some(callee_ldid) = did.as_local() {
+                    if seen.insert(callee_ldid) {
+                        gacx.address_taken_fns.push(callee_ldid);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Populate `gacx.fn_table_fields`, and print the functions stored into each field.
+fn populate_fn_table_fields(gacx: &mut GlobalAnalysisCtxt) {
+    let tcx = gacx.tcx;
+    gacx.fn_table_fields = fn_tables::find_fn_table_fields(gacx);
+    if gacx.fn_table_fields.is_empty() {
+        return;
+    }
+
+    let mut fields = gacx.fn_table_fields.iter().collect::<Vec<_>>();
+    fields.sort_by_key(|&(&did, _)| did);
+    eprintln!("fn table fields:");
+    for (&did, field) in fields {
+        let targets = field
+            .targets
+            .iter()
+            .map(|&did| tcx.def_path_str(did))
+            .collect::<Vec<_>>();
+        eprintln!(
+            "  {}: [{}]{}",
+            tcx.def_path_str(did),
+            targets.join(", "),
+            if field.complete { "" } else { " (incomplete)" }
+        );
+    }
+}
+
+/// Check whether the body of `ldid` mentions no local functions, statics, or fields.  Rewriting
+/// the rest of the crate never changes the meaning of such a body, so a verbatim copy of the
+/// function's original definition remains valid.
+fn is_self_contained_fn(gacx: &GlobalAnalysisCtxt, ldid: LocalDefId) -> bool {
+    let tcx = gacx.tcx;
+    if !gacx.fn_fields_used.get(ldid).is_empty() {
+        return false;
+    }
+
+    let mut mentions_fn = false;
+    for_each_callee(tcx, ldid, |_| mentions_fn = true);
+    if mentions_fn {
+        return false;
+    }
+
+    struct StaticUseVisitor<'tcx> {
+        tcx: TyCtxt<'tcx>,
+        found: bool,
+    }
+
+    impl<'tcx> Visitor<'tcx> for StaticUseVisitor<'tcx> {
+        fn visit_constant(&mut self, c: &Constant<'tcx>, _location: Location) {
+            let static_did = context::const_alloc_id(c)
+                .and_then(|id| context::find_static_for_alloc(&self.tcx, id));
+            if static_did.map_or(false, |did| did.is_local()) {
+                self.found = true;
+            }
+        }
+    }
+
+    let ldid_const = WithOptConstParam::unknown(ldid);
+    let mir = tcx.mir_built(ldid_const);
+    let mir = mir.borrow();
+    let mut v = StaticUseVisitor { tcx, found: false };
+    v.visit_body(&mir);
+    !v.found
+}
+
 /// Call `take_new_keys()` on `gacx.dont_rewrite_{fns,statics,fields}` and process the results.
 /// This involves adding `FIXED` to some pointers and maybe propagating `DontRewrite` flags to
 /// other items.
@@ -2245,12 +5092,43 @@ fn process_new_dont_rewrite_items(gacx: &mut GlobalAnalysisCtxt, gasn: &mut Glob
                 );
             }
 
+            // A caller that isn't rewritten keeps passing raw pointers to its locals.  If the
+            // callee reads and writes through such a parameter, leave the callee unchanged as
+            // well, so that its signature, its body, and all of its call sites stay consistent.
+            // With `clone_callees`, a self-contained callee is instead copied for the
+            // non-rewritten callers, and stays rewritable for the others.  This splits the call
+            // sites into just two classes, rewritten and non-rewritten callers; it isn't general
+            // context sensitivity.
+            for &(callee_ldid, i) in gacx.fn_local_borrow_args.get(ldid) {
+                let callee = callee_ldid.to_def_id();
+                let ptr = match gacx.fn_sigs[&callee].inputs.get(i) {
+                    Some(lty) => lty.label,
+                    // Variadic argument
+                    None => continue,
+                };
+                if ptr.is_none() || gasn.flags[ptr].contains(FlagSet::FIXED) {
+                    continue;
+                }
+                if gasn.perms[ptr].contains(PermissionSet::READ | PermissionSet::WRITE) {
+                    let can_clone = gacx.clone_callees
+                        && gacx.tcx.def_kind(callee) == DefKind::Fn
+                        && is_self_contained_fn(gacx, callee_ldid)
+                        && rewrite::raw_clone_name(gacx.tcx, callee_ldid).is_some();
+                    if can_clone {
+                        gacx.raw_clone_calls.insert((ldid, callee_ldid));
+                    } else {
+                        gacx.dont_rewrite_fns
+                            .add(callee, DontRewriteFnReason::NON_REWRITTEN_CALLER);
+                    }
+                }
+            }
+
             // TODO: callers/callees
         }
 
         for did in gacx.dont_rewrite_statics.take_new_keys() {
             found_any = true;
-            let lty = gacx.static_tys[&did];
+            let lty = gacx.item_lty(did);
             make_ty_fixed(gasn, lty);
         }
 
@@ -23,6 +23,16 @@ impl<'tcx> AnnotationBuffer<'tcx> {
     }
 
     pub fn emit(&mut self, span: Span, msg: impl Display) {
+        self.emit_inner(span, msg, true);
+    }
+
+    /// Like `emit`, but the comment contains only `msg`, without the line number and source
+    /// snippet that `emit` adds in front of it.
+    pub fn emit_plain(&mut self, span: Span, msg: impl Display) {
+        self.emit_inner(span, msg, false);
+    }
+
+    fn emit_inner(&mut self, span: Span, msg: impl Display, with_src: bool) {
         if span.is_dummy() {
             // `DUMMY_SP` covers the range `BytePos(0) .. BytePos(0)`.  Whichever file happens to
             // be added to the `SourceMap` first will be assigned a range starting at `BytePos(0)`,
@@ -41,6 +51,14 @@ impl<'tcx> AnnotationBuffer<'tcx> {
         let sf = &sm.files()[file_idx];
         let line = sf.lookup_line(pos).unwrap_or(0);
 
+        if !with_src {
+            self.m
+                .entry(file_idx)
+                .or_insert_with(Vec::new)
+                .push((line, msg.to_string()));
+            return;
+        }
+
         let src = sm
             .span_to_snippet(span)
             .unwrap_or_else(|_| "<error>".into());
@@ -0,0 +1,196 @@
+//! Detection of pointers that always point to the start of a fixed-size array.
+//!
+//! A pointer that's offset is normally rewritten to a slice, which carries its length at run
+//! time.  When the pointer comes straight from an array, the length is already known:
+//!
+//! ```ignore
+//! let mut buf: [u8; 16] = [0; 16];
+//! let p: *mut u8 = buf.as_mut_ptr();
+//! *p.offset(3) = 1;
+//! ```
+//!
+//! Here `p` is rewritten to `&mut [u8; 16]` and initialized with `&mut buf`, instead of
+//! `&mut [u8]`.  Uses of `p` are rewritten the same way as for a slice, since indexing, range
+//! indexing, and coercion to `&[T]` all work on references to arrays.
+//!
+//! A local is handled if its only assignment is a call to `as_ptr` or `as_mut_ptr` on a
+//! reference to an array, its address is never taken, and it would otherwise become a
+//! non-optional `&[T]` or `&mut [T]`.
+
+use crate::context::{AnalysisCtxt, FlagSet, PermissionSet};
+use crate::pointee_type::PointeeTypes;
+use crate::pointer_id::PointerId;
+use crate::type_desc::{self, Ownership, PtrDesc, Quantity};
+use crate::util::{ty_callee, Callee};
+use rustc_middle::mir::visit::{MutatingUseContext, NonMutatingUseContext, PlaceContext, Visitor};
+use rustc_middle::mir::{
+    Body, CastKind, Local, LocalKind, Location, Operand, Place, Rvalue, StatementKind,
+    TerminatorKind,
+};
+use rustc_middle::ty::adjustment::PointerCast;
+use rustc_middle::ty::{ParamEnv, Ty, TyCtxt, TyKind};
+use std::collections::{HashMap, HashSet};
+use std::ops::Index;
+
+/// A local that points to the start of an array of `len` elements of type `elem_ty`.
+#[derive(Clone, Copy, Debug)]
+pub struct ArrayPtr<'tcx> {
+    pub elem_ty: Ty<'tcx>,
+    pub len: u64,
+    /// The location of the cast from a reference to the array to a slice, whose result is passed
+    /// to `as_ptr`.  This cast is removed so that the local receives the array reference itself.
+    pub unsize_loc: Location,
+}
+
+#[derive(Default)]
+struct DefsVisitor {
+    defs: HashMap<Local, Vec<Location>>,
+    addr_taken: HashSet<Local>,
+}
+
+impl<'tcx> Visitor<'tcx> for DefsVisitor {
+    fn visit_place(&mut self, pl: &Place<'tcx>, ctx: PlaceContext, loc: Location) {
+        if pl.projection.is_empty() {
+            if ctx.is_place_assignment() {
+                self.defs.entry(pl.local).or_default().push(loc);
+            } else if ctx.is_borrow()
+                || matches!(
+                    ctx,
+                    PlaceContext::MutatingUse(MutatingUseContext::AddressOf)
+                        | PlaceContext::NonMutatingUse(NonMutatingUseContext::AddressOf)
+                )
+            {
+                self.addr_taken.insert(pl.local);
+            }
+        }
+        self.super_place(pl, ctx, loc);
+    }
+}
+
+/// Check whether a pointer with the descriptor `desc` can be rewritten to a reference to an
+/// array.  Optional and raw pointers are left as they are, since `Option<&[T; N]>` and raw
+/// pointers don't coerce to their slice equivalents.
+pub fn desc_allows_array(desc: PtrDesc) -> bool {
+    matches!(desc.qty, Quantity::Slice | Quantity::OffsetPtr)
+        && matches!(desc.own, Ownership::Imm | Ownership::Mut)
+        && !desc.option
+}
+
+/// If `op` is a temporary holding a reference to an array cast to a slice, return the array's
+/// element type and length, and the location of the cast.
+fn unsized_array_arg<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &Body<'tcx>,
+    defs: &DefsVisitor,
+    op: &Operand<'tcx>,
+) -> Option<(Ty<'tcx>, u64, Location)> {
+    let pl = op.place()?;
+    if !pl.projection.is_empty() || mir.local_kind(pl.local) != LocalKind::Temp {
+        return None;
+    }
+    let loc = match defs.defs.get(&pl.local).map(|v| &v[..]) {
+        Some(&[loc]) => loc,
+        _ => return None,
+    };
+    let stmt = mir.basic_blocks()[loc.block]
+        .statements
+        .get(loc.statement_index)?;
+    let array_ref = match stmt.kind {
+        StatementKind::Assign(ref x) => match x.1 {
+            Rvalue::Cast(CastKind::Pointer(PointerCast::Unsize), ref op, _) => op,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let array_ty = match *array_ref.ty(mir, tcx).kind() {
+        TyKind::Ref(_, ty, _) => ty,
+        _ => return None,
+    };
+    match *array_ty.kind() {
+        TyKind::Array(elem_ty, len) => {
+            let len = len.try_eval_usize(tcx, ParamEnv::reveal_all())?;
+            Some((elem_ty, len, loc))
+        }
+        _ => None,
+    }
+}
+
+/// Find the locals of `mir` that point to the start of a fixed-size array and can be rewritten to
+/// references to that array, given the permissions and flags of the final assignment.
+pub fn find_array_ptrs<'tcx>(
+    acx: &AnalysisCtxt<'_, 'tcx>,
+    perms: &impl Index<PointerId, Output = PermissionSet>,
+    flags: &impl Index<PointerId, Output = FlagSet>,
+    pointee_types: &impl Index<PointerId, Output = PointeeTypes<'tcx>>,
+    mir: &Body<'tcx>,
+) -> HashMap<Local, ArrayPtr<'tcx>> {
+    let tcx = acx.tcx();
+    let mut defs = DefsVisitor::default();
+    defs.visit_body(mir);
+
+    let mut array_ptrs = HashMap::new();
+    for (local, decl) in mir.local_decls.iter_enumerated() {
+        if mir.local_kind(local) != LocalKind::Var
+            || !decl.ty.is_unsafe_ptr()
+            || defs.addr_taken.contains(&local)
+        {
+            continue;
+        }
+        let lty = acx.local_tys[local];
+        let ptr = lty.label;
+        if ptr.is_none() || flags[ptr].contains(FlagSet::FIXED) {
+            continue;
+        }
+        if !desc_allows_array(type_desc::perms_to_desc(lty.ty, perms[ptr], flags[ptr]).into()) {
+            continue;
+        }
+
+        let loc = match defs.defs.get(&local).map(|v| &v[..]) {
+            Some(&[loc]) => loc,
+            _ => continue,
+        };
+        let bb_data = &mir.basic_blocks()[loc.block];
+        if loc.statement_index != bb_data.statements.len() {
+            continue;
+        }
+        let arg = match bb_data.terminator().kind {
+            TerminatorKind::Call {
+                ref func,
+                ref args,
+                destination,
+                ..
+            } if destination.as_local() == Some(local) => {
+                match ty_callee(tcx, func.ty(mir, tcx)) {
+                    Callee::SliceAsPtr { .. } => {}
+                    _ => continue,
+                }
+                match args.first() {
+                    Some(arg) => arg,
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        let (elem_ty, len, unsize_loc) = match unsized_array_arg(tcx, mir, &defs, arg) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        // A pointer whose pointee type is rewritten, as with `void*`, keeps its slice type.
+        if let Some(pointee_lty) = pointee_types[ptr].get_sole_lty() {
+            if pointee_lty.ty != elem_ty {
+                continue;
+            }
+        }
+
+        array_ptrs.insert(
+            local,
+            ArrayPtr {
+                elem_ty,
+                len,
+                unsize_loc,
+            },
+        );
+    }
+    array_ptrs
+}
@@ -0,0 +1,34 @@
+//! `cargo c2rust-analyze`: run `c2rust-analyze` as a `cargo` subcommand.
+//!
+//! `cargo` runs `cargo-c2rust-analyze c2rust-analyze <args>...` for `cargo c2rust-analyze
+//! <args>...`.  This drops the subcommand name and runs the `c2rust-analyze` executable installed
+//! alongside this one, which then runs the inner `cargo` with itself as `$RUSTC_WRAPPER` and the
+//! sysroot of the toolchain it was built with.  `cargo` sets `$CARGO` for subcommands, so the
+//! inner `cargo` is the same one the user invoked, and it finds the workspace the usual way
+//! (from the current directory or `--manifest-path`).
+//!
+//! This is a separate, minimal executable so that it doesn't need to link `rustc_driver`.
+
+use anyhow::anyhow;
+use std::env;
+use std::process;
+use std::process::Command;
+
+fn main() -> anyhow::Result<()> {
+    let mut args = env::args_os().skip(1).peekable();
+    if args.peek().map_or(false, |arg| arg == "c2rust-analyze") {
+        args.next();
+    }
+
+    let own_exe = env::current_exe()?;
+    let analyze = own_exe.with_file_name(format!("c2rust-analyze{}", env::consts::EXE_SUFFIX));
+    if !analyze.is_file() {
+        return Err(anyhow!(
+            "`c2rust-analyze` not found next to `cargo-c2rust-analyze` (expected at {})",
+            analyze.display()
+        ));
+    }
+
+    let status = Command::new(&analyze).args(args).status()?;
+    process::exit(status.code().unwrap_or(1));
+}
@@ -2,23 +2,22 @@ use self::atoms::{AllFacts, AtomMaps, Origin, Output, SubPoint};
 use crate::context;
 use crate::context::AdtMetadataTable;
 use crate::context::{AnalysisCtxt, PermissionSet};
-use crate::dataflow::DataflowConstraints;
+use crate::dataflow::{DataflowConstraints, PermRemovals};
 use crate::labeled_ty::{LabeledTy, LabeledTyCtxt};
-use crate::pointer_id::{PointerTable, PointerTableMut};
-use crate::util::{describe_rvalue, RvalueDesc};
+use crate::pointer_id::{PointerId, PointerTable, PointerTableMut};
+use crate::util::{bytes_to_hex_string, describe_rvalue, sha256_hash, RvalueDesc};
 use indexmap::{IndexMap, IndexSet};
 use rustc_hir::def_id::DefId;
-use rustc_middle::mir::{Body, LocalKind, Place, StatementKind, START_BLOCK};
+use rustc_middle::mir::{Body, LocalKind, Location, Place, StatementKind, START_BLOCK};
 use rustc_middle::ty::{
     EarlyBoundRegion, GenericParamDefKind, List, OutlivesPredicate, PredicateKind, Region, Ty,
     TyKind,
 };
+use rustc_span::Span;
 use rustc_type_ir::RegionKind::ReEarlyBound;
-use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::fmt::{Debug, Formatter, Write as _};
+use std::fmt::{Debug, Formatter};
 use std::fs::{self, File};
-use std::hash::{Hash, Hasher};
 use std::io::{BufReader, BufWriter};
 
 mod atoms;
@@ -60,6 +59,9 @@ pub struct AdtMetadata<'tcx> {
     /// hypothetical lifetimes derived from pointer fields.
     pub lifetime_params: IndexSet<OriginParam>,
     pub field_info: IndexMap<DefId, FieldMetadata<'tcx>>,
+    /// `void*` fields that are used with several different pointee types.  If this is non-empty,
+    /// the structure gets a new type parameter `T`, and these fields are rewritten to `*mut T`.
+    pub generic_void_fields: IndexSet<DefId>,
 }
 
 /// An origin parameter of a type to resolve in a MIR body
@@ -117,6 +119,30 @@ impl std::fmt::Debug for OriginParam {
     }
 }
 
+/// A borrowck error that caused `UNIQUE` to be removed from a pointer: a loan of `ptr` was still
+/// live when another statement invalidated it.
+#[derive(Clone, Debug)]
+pub struct BorrowConflict {
+    /// The pointer that lost `UNIQUE`.
+    pub ptr: PointerId,
+    /// The statement that issued the loan.
+    pub loan_span: Span,
+    pub loan_desc: String,
+    /// The statement or terminator that invalidated the loan.
+    pub use_span: Span,
+    pub use_desc: String,
+}
+
+fn describe_location(mir: &Body, loc: Location) -> (Span, String) {
+    mir.stmt_at(loc).either(
+        |stmt| (stmt.source_info.span, format!("{:?}", stmt)),
+        |term| (term.source_info.span, format!("{:?}", term.kind)),
+    )
+}
+
+/// Remove `UNIQUE` from pointers until `mir` passes the borrow check.  Returns the conflicts that
+/// caused each removal.  Permissions removed by dataflow propagation along the way are recorded
+/// in `removals`.
 pub fn borrowck_mir<'tcx>(
     acx: &AnalysisCtxt<'_, 'tcx>,
     dataflow: &DataflowConstraints,
@@ -125,7 +151,9 @@ pub fn borrowck_mir<'tcx>(
     name: &str,
     mir: &Body<'tcx>,
     field_ltys: HashMap<DefId, context::LTy<'tcx>>,
-) {
+    removals: &mut PermRemovals,
+) -> Vec<BorrowConflict> {
+    let mut conflicts = Vec::new();
     let mut i = 0;
     loop {
         eprintln!("run polonius");
@@ -146,7 +174,7 @@ pub fn borrowck_mir<'tcx>(
         }
 
         let mut changed = false;
-        for loans in output.errors.values() {
+        for (&error_point, loans) in &output.errors {
             for &loan in loans {
                 let issued_point = facts
                     .loan_issued_at
@@ -161,13 +189,15 @@ pub fn borrowck_mir<'tcx>(
                         loan, issued_loc
                     );
                 });
-                let ptr = match stmt.kind {
+                let (ptr, loan_desc) = match stmt.kind {
                     StatementKind::Assign(ref x) => match describe_rvalue(&x.1) {
-                        Some(RvalueDesc::Project { base, proj: _ }) => acx
-                            .ptr_of(base)
-                            .unwrap_or_else(|| panic!("missing pointer ID for {:?}", base)),
-                        Some(RvalueDesc::AddrOfLocal { local, proj: _ }) => {
-                            acx.addr_of_local[local]
+                        Some(RvalueDesc::Project { base, proj: _ }) => (
+                            acx.ptr_of(base)
+                                .unwrap_or_else(|| panic!("missing pointer ID for {:?}", base)),
+                            format!("{:?}", x.1),
+                        ),
+                        Some(RvalueDesc::AddrOfLocal { local, proj }) => {
+                            (acx.addr_of_local_field(local, proj), format!("{:?}", x.1))
                         }
                         None => panic!("loan {:?} was issued by unknown rvalue {:?}?", loan, x.1),
                     },
@@ -178,12 +208,22 @@ pub fn borrowck_mir<'tcx>(
                 if hypothesis[ptr].contains(PermissionSet::UNIQUE) {
                     hypothesis[ptr].remove(PermissionSet::UNIQUE);
                     changed = true;
+
+                    let (use_span, use_desc) =
+                        describe_location(mir, maps.get_point_location(error_point));
+                    conflicts.push(BorrowConflict {
+                        ptr,
+                        loan_span: stmt.source_info.span,
+                        loan_desc,
+                        use_span,
+                        use_desc,
+                    });
                 }
             }
         }
 
         eprintln!("propagate");
-        changed |= dataflow.propagate(hypothesis, updates_forbidden);
+        changed |= dataflow.propagate(hypothesis, updates_forbidden, removals);
         eprintln!("done propagating");
 
         if !changed {
@@ -196,6 +236,10 @@ pub fn borrowck_mir<'tcx>(
             break;
         }
     }
+
+    // `output.errors` is unordered.
+    conflicts.sort_by_key(|c| (c.loan_span, c.use_span));
+    conflicts
 }
 
 fn run_polonius<'tcx>(
@@ -497,14 +541,6 @@ fn save_cached_output(facts_hash: &str, output: &Output) -> Result<(), bincode::
     bincode::serialize_into(f, &raw)
 }
 
-fn bytes_to_hex_string(b: &[u8]) -> String {
-    let mut s = String::with_capacity(b.len() * 2);
-    for &x in b {
-        write!(s, "{:02x}", x).unwrap();
-    }
-    s
-}
-
 fn hash_facts(facts: &AllFacts) -> [u8; 32] {
     let AllFacts {
         ref loan_issued_at,
@@ -554,23 +590,6 @@ fn hash_facts(facts: &AllFacts) -> [u8; 32] {
     ))
 }
 
-fn sha256_hash<T: Hash>(x: &T) -> [u8; 32] {
-    struct Sha256Hasher(Sha256);
-    impl Hasher for Sha256Hasher {
-        fn write(&mut self, bytes: &[u8]) {
-            self.0.update(bytes);
-        }
-        fn finish(&self) -> u64 {
-            panic!("Sha256Hasher doesn't support finish()");
-        }
-    }
-
-    let mut hasher = Sha256Hasher(Sha256::new());
-    x.hash(&mut hasher);
-    let digest = hasher.0.finalize();
-    digest.as_slice().try_into().unwrap()
-}
-
 fn construct_adt_origins<'tcx>(
     ltcx: &LTyCtxt<'tcx>,
     adt_metadata: &AdtMetadataTable,
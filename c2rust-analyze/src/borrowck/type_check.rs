@@ -1,3 +1,4 @@
+use crate::align;
 use crate::borrowck::atoms::{AllFacts, AtomMaps, Loan, Origin, Path, Point, SubPoint};
 use crate::borrowck::{assign_origins, construct_adt_origins, LTy, LTyCtxt, Label, OriginParam};
 use crate::context::{const_alloc_id, find_static_for_alloc};
@@ -29,6 +30,7 @@ struct TypeChecker<'tcx, 'a> {
     field_permissions: &'a HashMap<DefId, PermissionSet>,
     hypothesis: &'a PointerTableMut<'a, PermissionSet>,
     local_decls: &'a IndexVec<Local, LocalDecl<'tcx>>,
+    mir: &'a Body<'tcx>,
     current_location: Location,
     static_origin: Origin,
 }
@@ -362,19 +364,25 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                     Label::default()
                 })
             }
-            Rvalue::Cast(CastKind::PointerFromExposedAddress, ref op, _ty) => {
-                // We support only one case here, which is the case of null pointers
-                // constructed via casts such as `0 as *const T`
-                if let Some(true) = op.constant().cloned().map(util::is_null_const) {
-                    // Here we relabel `expect_ty` to utilize the permissions it carries
-                    // but substitute the rest of its `Label`s' parts with fresh origins
-                    // Otherwise, this is conceptually similar to labeling the cast target
-                    // `ty`. We would simply do that, but do not have the information necessary
-                    // to set its permissions.
-                    self.relabel_fresh_origins(expect_ty)
-                } else {
-                    panic!("Creating non-null pointers from exposed addresses not supported");
+            Rvalue::Cast(CastKind::PointerFromExposedAddress, ref op, ty) => {
+                if let Some((ptr, _)) = align::align_up_source(tcx, self.mir, op, ty) {
+                    // Rounding a pointer up to an aligned address produces a pointer into the
+                    // same object, so it's treated like a copy of the original pointer.
+                    let ptr_lty = self.visit_operand(ptr);
+                    let result_lty = self.relabel_fresh_origins(expect_ty);
+                    self.do_assign(result_lty, ptr_lty);
+                    return result_lty;
                 }
+                // Otherwise, the result is either null, as in `0 as *const T`, or a pointer with
+                // no provenance, which `provenance` reports and keeps from being rewritten.
+                // Neither one borrows from anything.
+                //
+                // Here we relabel `expect_ty` to utilize the permissions it carries
+                // but substitute the rest of its `Label`s' parts with fresh origins
+                // Otherwise, this is conceptually similar to labeling the cast target
+                // `ty`. We would simply do that, but do not have the information necessary
+                // to set its permissions.
+                self.relabel_fresh_origins(expect_ty)
             }
             Rvalue::Cast(CastKind::Pointer(PointerCast::MutToConstPointer), ref op, _ty) => {
                 let op_lty = self.visit_operand(op);
@@ -406,14 +414,16 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                     // relations between the regions of the array and the regions of its elements
                     self.ltcx.label(ty, &mut |_ty| Label::default())
                 }
-                AggregateKind::Tuple => {
+                // The operands of a closure are its captured variables, which are labeled like
+                // the elements of a tuple.
+                AggregateKind::Tuple | AggregateKind::Closure(..) => {
                     for (op_idx, op) in ops.iter().enumerate() {
                         let op_lty = self.visit_operand(op);
                         self.do_assign(expect_ty.args[op_idx], op_lty);
                     }
                     expect_ty
                 }
-                AggregateKind::Adt(adt_did, ..) => {
+                AggregateKind::Adt(adt_did, _, _, _, active_field) => {
                     /*
                         Generic types are not yet supported because of situations such as the
                         following:
@@ -444,7 +454,9 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                     assert_eq!(expect_ty.args.len(), 0, "Generic types not yet supported.");
 
                     let adt_def = tcx.adt_def(adt_did);
-                    for (fid, op) in ops.iter().enumerate() {
+                    for (i, op) in ops.iter().enumerate() {
+                        // A union literal initializes only its `active_field`.
+                        let fid = active_field.unwrap_or(i);
                         let field_lty = self.field_lty(expect_ty, adt_def, Field::from(fid));
                         let op_lty = self.visit_operand(op);
                         eprintln!("pseudo-assigning fields {field_lty:?} = {op_lty:?}");
@@ -583,7 +595,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                             self.visit_operand(p)
                         });
                     }
-                    Callee::Memcpy => {
+                    Callee::Memcpy | Callee::Memmove => {
                         let _pl_lty = self.visit_place(destination);
                         assert_matches!(&args[..], [dest, src, _] => {
                             self.visit_operand(dest);
@@ -597,6 +609,12 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                         });
                     }
                     Callee::SizeOf { .. } => {}
+                    Callee::PtrOffsetFrom => {
+                        let _rv_lty = assert_matches!(&args[..], [p1, p2] => {
+                            self.visit_operand(p1);
+                            self.visit_operand(p2)
+                        });
+                    }
                     Callee::IsNull => {
                         let _rv_lty = assert_matches!(&args[..], [p] => {
                             self.visit_operand(p)
@@ -607,6 +625,15 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                         // there's no need to call `do_assign` to set up subset relations.
                         let _pl_lty = self.visit_place(destination);
                     }
+                    Callee::ReadVolatile
+                    | Callee::WriteVolatile
+                    | Callee::Atomic { .. }
+                    | Callee::VaArg => {
+                        let _pl_lty = self.visit_place(destination);
+                        for arg in args {
+                            self.visit_operand(arg);
+                        }
+                    }
                 }
             }
             // TODO(spernsteiner): handle other `TerminatorKind`s
@@ -638,6 +665,7 @@ pub fn visit_body<'tcx>(
         field_permissions,
         hypothesis,
         local_decls: &mir.local_decls,
+        mir,
         current_location: Location::START,
         static_origin,
     };
@@ -0,0 +1,287 @@
+//! Detection of callback-plus-context parameter pairs that can be replaced by a closure.
+//!
+//! A user-data registration fn (see `crate::user_data`) takes a callback and a `void*` context
+//! pointer, and only ever passes the context back to the callback:
+//!
+//! ```ignore
+//! unsafe fn for_each(cb: unsafe extern "C" fn(*mut c_void, i32), data: *mut c_void) {
+//!     for i in 0..10 {
+//!         cb(data, i);
+//!     }
+//! }
+//!
+//! for_each(add_to, &mut total as *mut i32 as *mut c_void);
+//! ```
+//!
+//! When every caller passes a function defined in this crate as the callback, the pair can be
+//! replaced with a single closure that captures the context:
+//!
+//! ```ignore
+//! unsafe fn for_each(mut cb: impl FnMut(i32)) {
+//!     for i in 0..10 {
+//!         cb(i);
+//!     }
+//! }
+//!
+//! for_each(|arg0| add_to(&mut total as *mut i32 as *mut c_void, arg0));
+//! ```
+//!
+//! This is opt-in: a function is considered only if it's listed in the file named by
+//! `C2RUST_ANALYZE_CLOSURE_CALLBACK_FNS_LIST` or marked
+//! `#[c2rust_analyze_test::closure_callback]`.  The context expression is evaluated each time the
+//! closure is called, so it must be free of side effects.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::user_data::{self, RegistrationFn};
+use log::debug;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{BindingAnnotation, Expr, ExprKind, HirId, PatKind, QPath};
+use rustc_middle::ty::{TyCtxt, TypeckResults};
+use rustc_span::Span;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Debug, Default)]
+pub struct ClosureCallbackInfo {
+    /// Registration fns whose callback and user-data parameters are replaced by a closure.
+    pub fns: HashMap<DefId, RegistrationFn>,
+    /// Every function that calls one of `fns`.
+    pub callers: HashSet<DefId>,
+}
+
+/// A use of the callback or user-data parameter of a registration fn, or a call to one.
+#[derive(Clone, Debug)]
+pub enum ClosureCallbackUse {
+    /// A call to the callback within the registration fn, as in `cb(data, i)`.  `args` holds the
+    /// spans of all the arguments, including the user data.
+    CallCallback { args: Vec<Span> },
+    /// A call to a registration fn, as in `for_each(add_to, &mut total as *mut _)`.  `args`
+    /// holds the spans of all the arguments, and `cb_fn` the span of the path naming the
+    /// callback.
+    Call {
+        callee: DefId,
+        args: Vec<Span>,
+        cb_fn: Span,
+    },
+}
+
+fn strip_casts<'tcx>(mut ex: &'tcx Expr<'tcx>) -> &'tcx Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+/// Check whether evaluating `ex` has no side effects, so it can be repeated inside a closure.
+/// This accepts local variables, fields, and borrows of them, along with literals and casts.
+fn is_pure(ex: &Expr) -> bool {
+    match ex.kind {
+        ExprKind::Path(QPath::Resolved(None, path)) => {
+            matches!(path.res, Res::Local(_) | Res::Def(DefKind::Static(_), _))
+        }
+        ExprKind::Field(base, _) => is_pure(base),
+        ExprKind::AddrOf(_, _, inner) | ExprKind::Cast(inner, _) => is_pure(inner),
+        ExprKind::Lit(_) => true,
+        _ => false,
+    }
+}
+
+struct ClosureCallbackVisitor<'a, 'tcx> {
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    fns: &'a HashMap<DefId, RegistrationFn>,
+    /// If the body being visited is one of `fns`, its callback and user-data parameters.
+    params: Option<(HirId, HirId, usize)>,
+    /// Paths that have already been accounted for, such as the callee of a call.
+    handled: HashSet<HirId>,
+    uses: Vec<ClosureCallbackUse>,
+    rejected: HashSet<DefId>,
+    current_fn: DefId,
+}
+
+impl<'a, 'tcx> ClosureCallbackVisitor<'a, 'tcx> {
+    fn path_res(&self, ex: &Expr) -> Option<Res> {
+        match ex.kind {
+            ExprKind::Path(ref qpath) => Some(self.typeck_results.qpath_res(qpath, ex.hir_id)),
+            _ => None,
+        }
+    }
+
+    fn is_local(&self, ex: &Expr, id: HirId) -> bool {
+        self.path_res(ex) == Some(Res::Local(id))
+    }
+
+    /// Check a call to a registration fn, and record it if it can be rewritten.
+    fn visit_registration_call(
+        &mut self,
+        ex: &'tcx Expr<'tcx>,
+        callee: DefId,
+        args: &'tcx [Expr<'tcx>],
+    ) {
+        let reg = self.fns[&callee];
+        let cb = strip_casts(&args[reg.cb_arg]);
+        let cb_fn = match self.path_res(cb) {
+            Some(Res::Def(DefKind::Fn, did)) if did.is_local() => cb,
+            _ => {
+                debug!("{callee:?}: callback at {:?} is not a local fn", cb.span);
+                self.rejected.insert(callee);
+                return;
+            }
+        };
+        if !is_pure(&args[reg.data_arg]) {
+            debug!(
+                "{callee:?}: user data at {:?} may have side effects",
+                args[reg.data_arg].span
+            );
+            self.rejected.insert(callee);
+            return;
+        }
+        if ex.span.from_expansion() {
+            self.rejected.insert(callee);
+            return;
+        }
+        self.handled.insert(cb_fn.hir_id);
+        self.uses.push(ClosureCallbackUse::Call {
+            callee,
+            args: args.iter().map(|a| a.span).collect(),
+            cb_fn: cb_fn.span,
+        });
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for ClosureCallbackVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            ExprKind::Call(func, args) => {
+                if let Some((cb, data, cb_data_arg)) = self.params {
+                    if self.is_local(func, cb) {
+                        self.handled.insert(func.hir_id);
+                        if args
+                            .get(cb_data_arg)
+                            .map_or(false, |a| self.is_local(a, data))
+                        {
+                            self.handled.insert(args[cb_data_arg].hir_id);
+                            self.uses.push(ClosureCallbackUse::CallCallback {
+                                args: args.iter().map(|a| a.span).collect(),
+                            });
+                        }
+                    }
+                }
+                if let Some(Res::Def(DefKind::Fn, did)) = self.path_res(func) {
+                    if self.fns.contains_key(&did) {
+                        self.handled.insert(func.hir_id);
+                        self.visit_registration_call(ex, did, args);
+                    }
+                }
+            }
+            ExprKind::Path(_) if !self.handled.contains(&ex.hir_id) => {
+                if let Some((cb, data, _)) = self.params {
+                    if self.is_local(ex, cb) || self.is_local(ex, data) {
+                        debug!("{:?}: parameter used at {:?}", self.current_fn, ex.span);
+                        self.rejected.insert(self.current_fn);
+                    }
+                }
+                if let Some(Res::Def(DefKind::Fn, did)) = self.path_res(ex) {
+                    if self.fns.contains_key(&did) {
+                        debug!("{did:?}: used other than by a direct call at {:?}", ex.span);
+                        self.rejected.insert(did);
+                    }
+                }
+            }
+            _ => {}
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+fn collect_uses_and_rejects(
+    tcx: TyCtxt,
+    ldid: LocalDefId,
+    fns: &HashMap<DefId, RegistrationFn>,
+) -> (Vec<ClosureCallbackUse>, HashSet<DefId>) {
+    let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+        Some(x) => x,
+        None => return (Vec::new(), HashSet::new()),
+    };
+    let body = tcx.hir().body(hir_body_id);
+    let did = ldid.to_def_id();
+    let params = fns.get(&did).map(|reg| {
+        (
+            body.params[reg.cb_arg].pat.hir_id,
+            body.params[reg.data_arg].pat.hir_id,
+            reg.cb_data_arg,
+        )
+    });
+    let mut v = ClosureCallbackVisitor {
+        typeck_results: tcx.typeck_body(hir_body_id),
+        fns,
+        params,
+        handled: HashSet::new(),
+        uses: Vec::new(),
+        rejected: HashSet::new(),
+        current_fn: did,
+    };
+    v.visit_body(body);
+    (v.uses, v.rejected)
+}
+
+/// Collect the uses within the body of `ldid` that need rewriting.
+pub fn collect_closure_callback_uses(
+    tcx: TyCtxt,
+    ldid: LocalDefId,
+    info: &ClosureCallbackInfo,
+) -> Vec<ClosureCallbackUse> {
+    collect_uses_and_rejects(tcx, ldid, &info.fns).0
+}
+
+/// Among the `candidates` selected by the user, find the registration fns whose callback and
+/// user-data parameters can be replaced by a closure.
+pub fn find_closure_callback_fns(
+    gacx: &GlobalAnalysisCtxt,
+    all_fn_ldids: &[LocalDefId],
+    candidates: &HashSet<DefId>,
+) -> ClosureCallbackInfo {
+    let tcx = gacx.tcx;
+    let mut fns = HashMap::new();
+    for &ldid in all_fn_ldids {
+        if !candidates.contains(&ldid.to_def_id()) {
+            continue;
+        }
+        let reg = match user_data::registration_fn_info(gacx, ldid) {
+            Some(x) => x,
+            None => {
+                debug!("{ldid:?}: not a user-data registration fn");
+                continue;
+            }
+        };
+        // The callback parameter becomes `mut cb`, so it must be a simple binding.
+        let body = tcx.hir().body(tcx.hir().body_owned_by(ldid));
+        if !matches!(
+            body.params[reg.cb_arg].pat.kind,
+            PatKind::Binding(BindingAnnotation::Unannotated, _, _, None)
+        ) {
+            continue;
+        }
+        fns.insert(ldid.to_def_id(), reg);
+    }
+    if fns.is_empty() {
+        return ClosureCallbackInfo::default();
+    }
+
+    // Rejecting a function only removes the uses of that function, so no other function is
+    // affected.
+    let mut callers = HashSet::new();
+    let mut rejected = HashSet::new();
+    for &ldid in all_fn_ldids {
+        let (uses, r) = collect_uses_and_rejects(tcx, ldid, &fns);
+        if uses
+            .iter()
+            .any(|u| matches!(u, ClosureCallbackUse::Call { .. }))
+        {
+            callers.insert(ldid.to_def_id());
+        }
+        rejected.extend(r);
+    }
+    fns.retain(|did, _| !rejected.contains(did));
+    ClosureCallbackInfo { fns, callers }
+}
@@ -0,0 +1,109 @@
+//! Reading analysis options from a `c2rust-analyze.toml` file in the crate root.
+//!
+//! Each key in the file is the name of a command-line option, without the leading `--`, so a
+//! complex invocation can be checked in alongside the crate instead of being retyped:
+//!
+//! ```toml
+//! rewrite-paths = ["parser", "lexer::tokens"]
+//! rewrite-mode = "inplace"
+//! known-fns-db = "known_fns.toml"
+//! dynamic-checks = true
+//! ```
+//!
+//! A `true` value passes a flag, and an array passes the option once for each element.  Relative
+//! paths are resolved against the directory containing the file.  Options given on the command
+//! line take precedence over the ones in the file.
+
+use anyhow::{anyhow, Context};
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use toml_edit::{Document, Item, Value};
+
+/// The name of the configuration file, which is looked up in the directory of the crate's
+/// `Cargo.toml`.
+pub const CONFIG_FILE_NAME: &str = "c2rust-analyze.toml";
+
+/// Options whose values are file or directory paths.
+const PATH_OPTIONS: &[&str] = &[
+    "errno-result-fns-list",
+    "out-param-fns-list",
+    "static-buf-fns-list",
+    "closure-callback-fns-list",
+    "self-ref-index-structs-list",
+    "self-ref-pin-structs-list",
+    "fixed-defs-list",
+    "crate-facts-dir",
+    "known-fns-db",
+    "pdg-facts",
+    "save-analysis-facts",
+    "load-analysis-facts",
+    "output-facts",
+    "html-report",
+    "sarif",
+    "stats-json",
+    "rewrite-manifest",
+    "cache-dir",
+    "patch-file",
+    "decisions-file",
+];
+
+/// Convert a single config value to the argument string to pass for it.
+fn value_to_arg(dir: &Path, key: &str, value: &Value) -> anyhow::Result<OsString> {
+    Ok(match *value {
+        Value::String(ref s) if PATH_OPTIONS.contains(&key) => dir.join(s.value()).into(),
+        Value::String(ref s) => s.value().into(),
+        Value::Integer(ref i) => i.value().to_string().into(),
+        Value::Float(ref f) => f.value().to_string().into(),
+        _ => return Err(anyhow!("unsupported value for `{key}`: {value}")),
+    })
+}
+
+/// Read the configuration file in `dir`, if there is one, and convert it to command-line
+/// arguments.  `known_options` lists the long names of all options accepted on the command line.
+/// Returns `None` if `dir` contains no configuration file.
+pub fn load_config_args(
+    dir: &Path,
+    known_options: &[&str],
+) -> anyhow::Result<Option<Vec<OsString>>> {
+    let path = dir.join(CONFIG_FILE_NAME);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let text = fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+    let doc = text
+        .parse::<Document>()
+        .with_context(|| format!("parsing {}", path.display()))?;
+
+    let mut args = Vec::new();
+    for (key, item) in doc.iter() {
+        if !known_options.contains(&key) {
+            return Err(anyhow!("unknown option `{key}` in {}", path.display()));
+        }
+        let flag = OsString::from(format!("--{key}"));
+        let value = match *item {
+            Item::Value(ref value) => value,
+            _ => return Err(anyhow!("`{key}` in {} is not a value", path.display())),
+        };
+        match *value {
+            Value::Boolean(ref b) => {
+                if *b.value() {
+                    args.push(flag);
+                }
+            }
+            Value::Array(ref arr) => {
+                for elem in arr.iter() {
+                    args.push(flag.clone());
+                    args.push(value_to_arg(dir, key, elem)?);
+                }
+            }
+            _ => {
+                args.push(flag);
+                args.push(value_to_arg(dir, key, value)?);
+            }
+        }
+    }
+
+    eprintln!("using options from {}", path.display());
+    Ok(Some(args))
+}
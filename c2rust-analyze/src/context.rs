@@ -1,14 +1,34 @@
 use crate::analyze::fn_body_owners_postorder;
 use crate::analyze::AssignPointerIds;
 use crate::borrowck::{AdtMetadata, FieldMetadata, OriginArg, OriginParam};
+use crate::closure_callbacks::ClosureCallbackInfo;
+use crate::crate_facts::FnFacts;
+use crate::errno_result::ErrnoResultInfo;
+use crate::file_io::FileIoInfo;
+use crate::flex_array::FlexArrayInfo;
+use crate::fn_tables::FnTableField;
 use crate::known_fn::{all_known_fns, KnownFn};
+use crate::known_fn_db::UserKnownFn;
 use crate::labeled_ty::{LabeledTy, LabeledTyCtxt};
+use crate::len_fields::LenFieldInfo;
+use crate::linked_lists::LinkedListInfo;
+use crate::out_params::OutParamInfo;
 use crate::panic_detail::PanicDetail;
 use crate::pointer_id::{
     GlobalPointerTable, LocalPointerTable, NextGlobalPointerId, NextLocalPointerId, PointerTable,
     PointerTableMut,
 };
+use crate::provenance::ProvenanceViolation;
+use crate::refcount::RefcountInfo;
+use crate::self_ref::SelfRefInfo;
+use crate::static_bufs::StaticBufInfo;
+use crate::static_inits::StaticInitInfo;
+use crate::static_kinds::StaticKind;
+use crate::thread_shared::ThreadSharedInfo;
+use crate::unions::UnionInfo;
+use crate::user_data::UserDataCallbacks;
 use crate::util::{self, describe_rvalue, PhantomLifetime, RvalueDesc};
+use crate::vec_structs::VecStructInfo;
 use assert_matches::assert_matches;
 use bitflags::bitflags;
 use indexmap::IndexSet;
@@ -24,6 +44,7 @@ use rustc_middle::mir::{
 };
 use rustc_middle::ty::tls;
 use rustc_middle::ty::AdtDef;
+use rustc_middle::ty::ConstKind;
 use rustc_middle::ty::DefIdTree;
 use rustc_middle::ty::FieldDef;
 use rustc_middle::ty::GenericArgKind;
@@ -34,6 +55,7 @@ use rustc_middle::ty::Ty;
 use rustc_middle::ty::TyCtxt;
 use rustc_middle::ty::TyKind;
 use rustc_type_ir::RegionKind::{ReEarlyBound, ReStatic};
+use serde::{Deserialize, Serialize};
 use std::collections::hash_map::{Entry, HashMap};
 use std::collections::HashSet;
 use std::fmt::{Debug, Write as _};
@@ -55,7 +77,7 @@ bitflags! {
     /// We do not yet (here) consider unaligned or cast-from-integer pointers.
     ///
     /// [`UNIQUE`]: Self::UNIQUE
-    #[derive(Default)]
+    #[derive(Default, Serialize, Deserialize)]
     pub struct PermissionSet: u16 {
         /// The value(s) accessible through this pointer can be read.
         const READ = 0x0001;
@@ -99,8 +121,16 @@ bitflags! {
         /// * [`core::ptr::null_mut`]
         /// * [`core::ptr::from_exposed_addr`] with the constant `0`
         /// * `0 as * {const,mut} _`, a cast from the constant `0` to a pointer type
+        /// * pointers that the program checks for null, via [`_.is_null()`] or by comparing
+        ///   against one of the null pointers above, unless every value they may hold comes from
+        ///   this crate, as with the arguments of private functions and the results of local
+        ///   calls.  Those are nullable only if one of the values may be null.
         /// * anything else
         ///
+        /// A copy of a nullable pointer that's made where a null check guarantees it's non-null,
+        /// as in `if !p.is_null() { q = p; }`, can still be [`NON_NULL`].  See
+        /// `dataflow::non_null`.
+        ///
         /// Non-zero but invalid pointers, such as those produced by:
         /// * [`core::ptr::invalid`]
         /// * [`core::ptr::invalid_mut`]
@@ -118,7 +148,8 @@ bitflags! {
         /// Casts/transitions from non-[`NON_NULL`] to [`NON_NULL`] will become [`_.unwrap()`].
         ///
         /// [`_.is_null()`] on a [`NON_NULL`] pointer will become [`false`].\
-        /// [`_.is_null()`] on a non-[`NON_NULL`] pointer will become [`_.is_some()`].
+        /// [`_.is_null()`] on a non-[`NON_NULL`] pointer will become [`_.is_none()`], or
+        /// [`_.is_some()`] if it's negated.
         ///
         /// Constant null pointers, like those produced by:
         /// * [`core::ptr::null`]
@@ -136,6 +167,7 @@ bitflags! {
         /// [`Some(_)`]: Some
         /// [`_.unwrap()`]: Option::unwrap
         /// [`_.is_null()`]: core::ptr::is_null
+        /// [`_.is_none()`]: Option::is_none
         /// [`_.is_some()`]: Option::is_some
         const NON_NULL = 0x0080;
     }
@@ -173,7 +205,7 @@ impl PermissionSet {
 bitflags! {
     /// Additional flags describing a given pointer type.  These are mainly derived from
     /// `PermissionSet`, but don't follow the normal subtyping rules and propagation algorithm.
-    #[derive(Default)]
+    #[derive(Default, Serialize, Deserialize)]
     pub struct FlagSet: u16 {
         /// The pointee type is wrapped in `Cell`.  This is tracked separately from the
         /// `PermissionSet` since it depends on the past/future uses of the pointer in an unusual
@@ -186,13 +218,45 @@ bitflags! {
         /// cross an FFI boundary, and for arguments and return values of functions we can't
         /// rewrite.
         const FIXED = 0x0002;
+
+        /// The pointer may point into a local of some function, as with `&x` or `&mut x.f`.
+        ///
+        /// `STACK`, `HEAP`, and `STATIC` record where a pointer's value may come from.  They're
+        /// set on the pointers that create a value, and flow forward along dataflow edges, so a
+        /// pointer has the origins of every pointer it may be copied from.  A pointer with none of
+        /// them set has an unknown origin, such as an argument of an exported function.  Origins
+        /// are not tracked for `FIXED` pointers.
+        const STACK = 0x0004;
+
+        /// The pointer may point to memory allocated by `malloc`, `calloc`, or `realloc`.
+        const HEAP = 0x0008;
+
+        /// The pointer may point into a static, a string literal, or a heap allocation that's never
+        /// freed.
+        const STATIC = 0x0010;
+
+        /// The pointer is used with atomic intrinsics, so its pointee is replaced with the
+        /// corresponding atomic type, as in `&AtomicI32` or `&AtomicPtr<T>`.  Like `CELL`, this is
+        /// shared by every pointer that may alias the same memory.
+        const ATOMIC = 0x0020;
+
+        /// The pointer is only read, and only ever points to a NUL-terminated string, so it's
+        /// rewritten to `&CStr`.  Like `ATOMIC`, this is shared by every pointer that may alias the
+        /// same memory.
+        const CSTR = 0x0040;
     }
 }
 
+impl FlagSet {
+    /// Mask of the flags that describe where a pointer may point.  See [`FlagSet::STACK`].
+    pub const ORIGIN: Self =
+        Self::from_bits_truncate(Self::STACK.bits | Self::HEAP.bits | Self::STATIC.bits);
+}
+
 bitflags! {
     /// Flags indicating reasons why a function isn't being rewritten.
     #[derive(Default)]
-    pub struct DontRewriteFnReason: u16 {
+    pub struct DontRewriteFnReason: u32 {
         /// The user requested that this function be left unchanged.
         const USER_REQUEST = 1 << 0;
         /// The function contains an unsupported int-to-pointer cast.
@@ -211,27 +275,67 @@ bitflags! {
         /// Calling this function from non-rewritten code requires a shim, but shim generation
         /// failed.
         const SHIM_GENERATION_FAILED = 1 << 7;
+        /// The function uses pointers to a reference-counted struct, which are rewritten
+        /// separately to `Rc<RefCell<T>>`.
+        const REFCOUNTED = 1 << 8;
+        /// The function uses pointers to a struct shared between threads, which are rewritten
+        /// separately to `Arc<Mutex<T>>`.
+        const THREAD_SHARED = 1 << 9;
+        /// The function uses the fields of a growable-buffer struct, which is rewritten
+        /// separately to hold a `Vec<T>`.
+        const VEC_STRUCT = 1 << 10;
+        /// The function allocates, frees, or indexes into a struct with a flexible array member,
+        /// whose tail is rewritten separately to a trailing `Vec<T>`.
+        const FLEX_ARRAY = 1 << 11;
+        /// The function accesses the fields of a union, which is rewritten separately to an
+        /// `enum`.
+        const UNION_ENUM = 1 << 12;
+        /// The function has an out-parameter, which is rewritten separately to part of its
+        /// return value.
+        const OUT_PARAM = 1 << 13;
+        /// A non-rewritten function passes a borrow of one of its locals to a parameter of this
+        /// function that is both read and written through.
+        const NON_REWRITTEN_CALLER = 1 << 14;
+        /// The function returns a pointer into a static buffer, and its return type is rewritten
+        /// separately.
+        const STATIC_BUF = 1 << 15;
+        /// The function's callback and user-data parameters are replaced by a closure, which is
+        /// rewritten separately.
+        const CLOSURE_CALLBACK = 1 << 16;
+        /// The function uses pointers to a doubly-linked list node, which are rewritten separately
+        /// to arena indices.
+        const LINKED_LIST = 1 << 17;
+        /// The function uses the self-pointers of a self-referential struct, which are rewritten
+        /// separately to indices into the struct's own array field.
+        const SELF_REF = 1 << 18;
+        /// The function has a `FILE*` local with a single owner, which is rewritten separately to
+        /// a `BufReader` or `BufWriter`.
+        const FILE_IO = 1 << 19;
 
         /// Pointee analysis results for this function are invalid.
-        const POINTEE_INVALID = 1 << 10;
+        const POINTEE_INVALID = 1 << 20;
         /// Dataflow analysis results for this function are invalid.
-        const DATAFLOW_INVALID = 1 << 11;
+        const DATAFLOW_INVALID = 1 << 21;
         /// Borrowcheck/Polonius analysis results for this function are invalid.
-        const BORROWCK_INVALID = 1 << 12;
+        const BORROWCK_INVALID = 1 << 22;
         /// Results of some other analysis for this function are invalid.
-        const MISC_ANALYSIS_INVALID = 1 << 13;
+        const MISC_ANALYSIS_INVALID = 1 << 23;
         /// The set of rewrites generated for this function is invalid or incomplete.
-        const REWRITE_INVALID = 1 << 14;
+        const REWRITE_INVALID = 1 << 24;
         /// Analysis results for this function are valid, but were marked as invalid anyway in
         /// order to test error recovery.
-        const FAKE_INVALID_FOR_TESTING = 1 << 15;
+        const FAKE_INVALID_FOR_TESTING = 1 << 25;
+        /// Analysis of this function took longer than the per-function time budget, so its
+        /// results were discarded.
+        const TIME_BUDGET = 1 << 26;
 
         const ANALYSIS_INVALID_MASK = Self::POINTEE_INVALID.bits
             | Self::DATAFLOW_INVALID.bits
             | Self::BORROWCK_INVALID.bits
             | Self::MISC_ANALYSIS_INVALID.bits
             | Self::REWRITE_INVALID.bits
-            | Self::FAKE_INVALID_FOR_TESTING.bits;
+            | Self::FAKE_INVALID_FOR_TESTING.bits
+            | Self::TIME_BUDGET.bits;
     }
 }
 
@@ -243,6 +347,8 @@ bitflags! {
         const USER_REQUEST = 0x0001;
         /// The static is used in a function that isn't being rewritten.
         const NON_REWRITTEN_USE = 0x0002;
+        /// The static's initializer holds a pointer that can't be rewritten to a reference.
+        const UNSUPPORTED_INIT = 0x0004;
     }
 }
 
@@ -254,6 +360,9 @@ bitflags! {
         const USER_REQUEST = 0x0001;
         /// The field is used in a function that isn't being rewritten.
         const NON_REWRITTEN_USE = 0x0002;
+        /// The field is initialized in a static with a pointer that can't be rewritten to a
+        /// reference.
+        const UNSUPPORTED_INIT = 0x0004;
     }
 }
 
@@ -296,6 +405,10 @@ bitflags! {
         /// This `PointerId` has at least one local declaration that is not a temporary reference
         /// arising from an `&x` or `&mut x` expression in the source.
         const NOT_TEMPORARY_REF = 0x0004;
+
+        /// At least one declaration that produced this `PointerId` is used as an operand of
+        /// inline assembly or another construct that the analysis can't see into.
+        const OPAQUE_USE = 0x0008;
     }
 }
 
@@ -382,6 +495,27 @@ impl<'tcx> Debug for AdtMetadataTable<'tcx> {
     }
 }
 
+impl<'tcx> AdtMetadataTable<'tcx> {
+    /// Check whether the ADT `did` gets a generic type parameter for its polymorphic `void*`
+    /// fields.  See [`AdtMetadata::generic_void_fields`].
+    pub fn has_generic_void_fields(&self, did: DefId) -> bool {
+        self.table
+            .get(&did)
+            .map_or(false, |adt| !adt.generic_void_fields.is_empty())
+    }
+
+    /// Check whether `ty` mentions any ADT that has generic `void*` fields.
+    pub fn mentions_generic_adt(&self, ty: Ty<'tcx>) -> bool {
+        ty.walk().any(|arg| match arg.unpack() {
+            GenericArgKind::Type(ty) => match *ty.kind() {
+                TyKind::Adt(adt_def, _) => self.has_generic_void_fields(adt_def.did()),
+                _ => false,
+            },
+            _ => false,
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct GlobalAnalysisCtxt<'tcx> {
     pub tcx: TyCtxt<'tcx>,
@@ -391,6 +525,22 @@ pub struct GlobalAnalysisCtxt<'tcx> {
 
     pub fn_sigs: HashMap<DefId, LFnSig<'tcx>>,
     pub fn_fields_used: MultiMap<LocalDefId, LocalDefId>,
+    /// For each function, the calls that pass a `&mut` borrow of one of the function's locals as
+    /// a raw pointer argument, given as the callee and the argument index.
+    pub fn_local_borrow_args: MultiMap<LocalDefId, (LocalDefId, usize)>,
+    /// Local functions whose address is taken somewhere in the crate, as in `f as unsafe fn()`.
+    /// An indirect call through a function pointer may reach any of these that has a matching
+    /// signature.
+    pub address_taken_fns: Vec<LocalDefId>,
+    /// Calls from a non-rewritten caller to a callee that is still rewritten for its other
+    /// callers, given as `(caller, callee)`.  These calls go to `callee_raw`, a verbatim copy of
+    /// the callee's original definition.  Only populated when `C2RUST_ANALYZE_CLONE_CALLEES=1` is
+    /// set.
+    pub raw_clone_calls: HashSet<(LocalDefId, LocalDefId)>,
+    /// Functions from other crates of the workspace that this crate mentions, along with the
+    /// facts recorded when their crate was analyzed.  Each one also has an entry in `fn_sigs`.
+    /// Only populated when `C2RUST_ANALYZE_CRATE_FACTS_DIR` is set.  See `crate_facts`.
+    pub upstream_fn_facts: HashMap<DefId, FnFacts>,
 
     /// A map of all [`KnownFn`]s as determined by [`all_known_fns`].
     ///
@@ -399,6 +549,9 @@ pub struct GlobalAnalysisCtxt<'tcx> {
     ///
     /// [`name`]: KnownFn::name
     known_fns: HashMap<&'static str, &'static KnownFn>,
+    /// Pointee types declared for the arguments (`Some(i)`) and return values (`None`) of
+    /// user-supplied [`KnownFn`]s, keyed by name like `known_fns`.  See `known_fn_db`.
+    known_fn_pointees: HashMap<&'static str, Vec<(Option<usize>, Ty<'tcx>)>>,
 
     pub dont_rewrite_fns: FlagMap<DefId, DontRewriteFnReason>,
     pub dont_rewrite_statics: FlagMap<DefId, DontRewriteStaticReason>,
@@ -413,12 +566,118 @@ pub struct GlobalAnalysisCtxt<'tcx> {
 
     pub static_tys: HashMap<DefId, LTy<'tcx>>,
     pub addr_of_static: HashMap<DefId, PointerId>,
+    /// Labeled types of local `const` items that hold pointers.  Every use of the constant gets
+    /// this type, so the declared type can be rewritten along with the code that reads it.
+    pub const_tys: HashMap<DefId, LTy<'tcx>>,
 
     pub adt_metadata: AdtMetadataTable<'tcx>,
 
     pub fn_origins: FnOriginMap<'tcx>,
 
     pub foreign_mentioned_tys: HashSet<DefId>,
+
+    /// Functions that pass a `void*` user-data argument through to a callback, and the callbacks
+    /// passed to them.
+    pub user_data: UserDataCallbacks<'tcx>,
+
+    /// `void*` fields that only hold function pointers, along with the function pointer type each
+    /// one is rewritten to use.  See `crate::fn_ptr_fields`.
+    pub fn_ptr_fields: HashMap<DefId, Ty<'tcx>>,
+
+    /// Struct fields with function pointer types, along with the local functions stored into each
+    /// one.  See `crate::fn_tables`.
+    pub fn_table_fields: HashMap<DefId, FnTableField>,
+
+    /// Reference-counted structs whose pointers are rewritten to `Rc<RefCell<T>>`, and the
+    /// functions that use them.  Only populated when `C2RUST_ANALYZE_USE_RC=1` is set.  See
+    /// `crate::refcount`.
+    pub refcount: RefcountInfo,
+
+    /// Structs shared between threads through `pthread_create`, whose pointers are rewritten to
+    /// `Arc<Mutex<T>>`, and the functions that use them.  See `crate::thread_shared`.
+    pub thread_shared: ThreadSharedInfo,
+
+    /// `static mut` items that don't hold pointers, classified by how they're used.  See
+    /// `crate::static_kinds`.
+    pub static_kinds: HashMap<DefId, StaticKind>,
+
+    /// The pointer values in the initializers of statics that hold pointers.  See
+    /// `crate::static_inits`.
+    pub static_inits: StaticInitInfo<'tcx>,
+
+    /// Growable-buffer structs whose `(ptr, len, cap)` fields are replaced by a single `Vec<T>`,
+    /// and the functions that use those fields.  See `crate::vec_structs`.
+    pub vec_structs: VecStructInfo,
+
+    /// Structs ending in a flexible array member, whose tail is rewritten to a `Vec<T>`, and the
+    /// functions that allocate, free, or index into them.  See `crate::flex_array`.
+    pub flex_arrays: FlexArrayInfo,
+
+    /// Pointer fields paired with the integer fields that hold their lengths, and the lengths
+    /// stored alongside each store to a paired field.  See `crate::len_fields`.
+    pub len_fields: LenFieldInfo,
+
+    /// Function parameters that point to arrays terminated by a null or zero element, given as
+    /// a function and a parameter index.  See `crate::sentinel`.
+    pub sentinel_params: HashSet<(DefId, usize)>,
+
+    /// Unions whose active field can be tracked, which are rewritten to enums, and the functions
+    /// that access their fields.  See `crate::unions`.
+    pub unions: UnionInfo,
+
+    /// Functions whose errno-style integer return codes are rewritten to `Result`, and the
+    /// functions that call them.  See `crate::errno_result`.
+    pub errno_results: ErrnoResultInfo,
+
+    /// Int-to-pointer casts and cross-allocation pointer arithmetic found in each function.  These
+    /// are reported separately from permission conflicts.  See `crate::provenance`.
+    pub provenance_violations: HashMap<DefId, Vec<ProvenanceViolation>>,
+
+    /// Functions whose out-parameters are rewritten to part of their return values, and the
+    /// functions that call them.  See `crate::out_params`.
+    pub out_params: OutParamInfo<'tcx>,
+
+    /// Functions that return pointers into static buffers, which are rewritten to return owned or
+    /// `&'static` values, and the functions that call them.  See `crate::static_bufs`.
+    pub static_bufs: StaticBufInfo<'tcx>,
+
+    /// Functions with `FILE*` locals that are rewritten to `BufReader` or `BufWriter`.  See
+    /// `crate::file_io`.
+    pub file_io: FileIoInfo,
+
+    /// Registration fns whose callback and user-data parameters are replaced by a closure, and
+    /// the functions that call them.  See `crate::closure_callbacks`.
+    pub closure_callbacks: ClosureCallbackInfo,
+
+    /// Linked-list node structs, the doubly-linked ones whose pointers are rewritten to arena
+    /// indices, and the functions that use them.  Only populated when
+    /// `C2RUST_ANALYZE_LINKED_LISTS=1` is set.  See `crate::linked_lists`.
+    pub linked_lists: LinkedListInfo,
+
+    /// Self-referential structs selected for rewriting, along with their self-pointer fields, and
+    /// the functions that use the pointers of those rewritten to indices.  See `crate::self_ref`.
+    pub self_ref: SelfRefInfo,
+
+    /// Exported `extern "C"` functions whose rewritten bodies are fully safe, which are renamed to
+    /// `foo_safe` and get an extern wrapper under their original name.  Only populated when
+    /// `C2RUST_ANALYZE_SAFE_WRAPPERS=1` is set.  See `rewrite::safe_wrappers`.
+    pub safe_wrapper_fns: HashSet<DefId>,
+
+    /// Whether offsets that can't be proven in bounds are rewritten to slice operations that
+    /// panic at run time if they go out of bounds.  Without this, a function that offsets a
+    /// pointer to a single element isn't rewritten.  Set by `C2RUST_ANALYZE_CHECKED_OFFSETS=1`.
+    pub checked_offsets: bool,
+
+    /// Whether offsets of raw pointers with a known length are rewritten to offsets of slices
+    /// built with `slice::from_raw_parts`, with an explicit bounds check.  The length comes from
+    /// `len_fields.offset_lens`.  Set by `C2RUST_ANALYZE_DYNAMIC_CHECKS=1`.
+    pub dynamic_checks: bool,
+
+    /// Whether a callee that a non-rewritten caller passes its locals to is copied for the
+    /// non-rewritten callers, which all share the copy, instead of being left unrewritten for
+    /// every caller.  See `raw_clone_calls`.  Set
+    /// by `C2RUST_ANALYZE_CLONE_CALLEES=1`.
+    pub clone_callees: bool,
 }
 
 pub struct AnalysisCtxt<'a, 'tcx> {
@@ -429,6 +688,11 @@ pub struct AnalysisCtxt<'a, 'tcx> {
     pub local_decls: &'a LocalDecls<'tcx>,
     pub local_tys: IndexVec<Local, LTy<'tcx>>,
     pub addr_of_local: IndexVec<Local, PointerId>,
+    /// Pointers for the addresses of fields of locals, such as `&x.a.b`, keyed by the local and
+    /// the field projection (`.a.b`).  Each prefix of a projection that appears in the function
+    /// has its own entry, so fields nested at any depth get permissions independent of their
+    /// siblings.  See [`addr_of_local_field`](Self::addr_of_local_field).
+    pub addr_of_local_fields: HashMap<(Local, &'tcx [PlaceElem<'tcx>]), PointerId>,
     /// Types for certain [`Rvalue`]s.  Some `Rvalue`s introduce fresh [`PointerId`]s; to keep
     /// those `PointerId`s consistent, the `Rvalue`'s type must be stored rather than recomputed on
     /// the fly.
@@ -472,6 +736,7 @@ pub struct AnalysisCtxtData<'tcx> {
     ptr_info: LocalPointerTable<PointerInfo>,
     local_tys: IndexVec<Local, LTy<'tcx>>,
     addr_of_local: IndexVec<Local, PointerId>,
+    addr_of_local_fields: HashMap<(Local, &'tcx [PlaceElem<'tcx>]), PointerId>,
     rvalue_tys: HashMap<Location, LTy<'tcx>>,
     string_literal_locs: Vec<Location>,
 }
@@ -516,7 +781,7 @@ fn fn_origin_args_params<'tcx>(
         let mut arg_origin_args = vec![];
 
         // gather new and existing OriginArgs and push new OriginParams
-        let sig = tcx.erase_late_bound_regions(tcx.fn_sig(fn_did));
+        let sig = util::body_fn_sig(tcx, fn_did);
         let ltcx = LabeledTyCtxt::<'tcx, &[OriginArg<'tcx>]>::new(tcx);
         let mut next_hypo_origin_id = 0;
         let mut origin_lty = |ty: Ty<'tcx>| {
@@ -776,10 +1041,15 @@ impl<'tcx> GlobalAnalysisCtxt<'tcx> {
             ptr_info: GlobalPointerTable::empty(),
             fn_sigs: HashMap::new(),
             fn_fields_used: MultiMap::new(),
+            fn_local_borrow_args: MultiMap::new(),
+            address_taken_fns: Vec::new(),
+            raw_clone_calls: HashSet::new(),
+            upstream_fn_facts: HashMap::new(),
             known_fns: all_known_fns()
                 .iter()
                 .map(|known_fn| (known_fn.name, known_fn))
                 .collect(),
+            known_fn_pointees: HashMap::new(),
             dont_rewrite_fns: FlagMap::new(),
             dont_rewrite_statics: FlagMap::new(),
             dont_rewrite_fields: FlagMap::new(),
@@ -788,12 +1058,59 @@ impl<'tcx> GlobalAnalysisCtxt<'tcx> {
             field_users: MultiMap::new(),
             static_tys: HashMap::new(),
             addr_of_static: HashMap::new(),
+            const_tys: HashMap::new(),
             adt_metadata: AdtMetadataTable::default(),
             fn_origins: FnOriginMap::default(),
             foreign_mentioned_tys: HashSet::new(),
+            user_data: UserDataCallbacks::default(),
+            fn_ptr_fields: HashMap::new(),
+            fn_table_fields: HashMap::new(),
+            refcount: RefcountInfo::default(),
+            thread_shared: ThreadSharedInfo::default(),
+            static_kinds: HashMap::new(),
+            static_inits: StaticInitInfo::default(),
+            vec_structs: VecStructInfo::default(),
+            flex_arrays: FlexArrayInfo::default(),
+            len_fields: LenFieldInfo::default(),
+            sentinel_params: HashSet::new(),
+            unions: UnionInfo::default(),
+            errno_results: ErrnoResultInfo::default(),
+            provenance_violations: HashMap::new(),
+            out_params: OutParamInfo::default(),
+            static_bufs: StaticBufInfo::default(),
+            file_io: FileIoInfo::default(),
+            closure_callbacks: ClosureCallbackInfo::default(),
+            linked_lists: LinkedListInfo::default(),
+            self_ref: SelfRefInfo::default(),
+            safe_wrapper_fns: HashSet::new(),
+            checked_offsets: false,
+            dynamic_checks: false,
+            clone_callees: false,
         }
     }
 
+    /// Check whether the function `did` should be given a generic type parameter `T`, which is
+    /// needed when its signature mentions an ADT with generic `void*` fields.  Functions that
+    /// already have type parameters of their own (or whose parent does) are left alone; they keep
+    /// using the ADT's default `c_void` argument.
+    pub fn fn_needs_type_param(&self, did: DefId) -> bool {
+        let generics = self.tcx.generics_of(did);
+        if generics.parent.is_some()
+            || generics
+                .params
+                .iter()
+                .any(|p| !matches!(p.kind, GenericParamDefKind::Lifetime))
+        {
+            return false;
+        }
+        let lsig = match self.fn_sigs.get(&did) {
+            Some(x) => x,
+            None => return false,
+        };
+        lsig.inputs_and_output()
+            .any(|lty| self.adt_metadata.mentions_generic_adt(lty.ty))
+    }
+
     /// Initialize `self.adt_metadata` and `self.fn_origins`.  This requires that all field types
     /// in the crate have already been labeled in `field_ltys`.
     pub fn construct_region_metadata(&mut self) {
@@ -845,7 +1162,12 @@ impl<'tcx> GlobalAnalysisCtxt<'tcx> {
             ref mut ptr_info,
             ref mut fn_sigs,
             fn_fields_used: _,
+            fn_local_borrow_args: _,
+            address_taken_fns: _,
+            raw_clone_calls: _,
+            upstream_fn_facts: _,
             known_fns: _,
+            known_fn_pointees: _,
             dont_rewrite_fns: _,
             dont_rewrite_statics: _,
             dont_rewrite_fields: _,
@@ -854,9 +1176,34 @@ impl<'tcx> GlobalAnalysisCtxt<'tcx> {
             field_users: _,
             ref mut static_tys,
             ref mut addr_of_static,
+            ref mut const_tys,
             adt_metadata: _,
             fn_origins: _,
             foreign_mentioned_tys: _,
+            user_data: _,
+            fn_ptr_fields: _,
+            fn_table_fields: _,
+            refcount: _,
+            thread_shared: _,
+            static_kinds: _,
+            static_inits: _,
+            vec_structs: _,
+            flex_arrays: _,
+            len_fields: _,
+            sentinel_params: _,
+            unions: _,
+            errno_results: _,
+            provenance_violations: _,
+            out_params: _,
+            static_bufs: _,
+            file_io: _,
+            closure_callbacks: _,
+            linked_lists: _,
+            self_ref: _,
+            safe_wrapper_fns: _,
+            checked_offsets: _,
+            dynamic_checks: _,
+            clone_callees: _,
         } = *self;
 
         *ptr_info = remap_global_ptr_info(ptr_info, map, counter.num_pointers());
@@ -884,6 +1231,10 @@ impl<'tcx> GlobalAnalysisCtxt<'tcx> {
                 *ptr = map[*ptr];
             }
         }
+
+        for labeled_const in const_tys.values_mut() {
+            *labeled_const = remap_lty_pointers(lcx, map, labeled_const);
+        }
     }
 
     pub fn assign_pointer_to_static(&mut self, did: DefId) {
@@ -895,6 +1246,21 @@ impl<'tcx> GlobalAnalysisCtxt<'tcx> {
         self.addr_of_static.insert(did, ptr);
     }
 
+    pub fn assign_pointer_to_const(&mut self, did: DefId) {
+        trace!("assign_pointer_to_const({:?})", did);
+        // Like statics, `const` items always have full type annotations.
+        let lty = self.assign_pointer_ids_with_info(self.tcx.type_of(did), PointerInfo::ANNOTATED);
+        self.const_tys.insert(did, lty);
+    }
+
+    /// Get the labeled type of the `static` or `const` item `did`.
+    pub fn item_lty(&self, did: DefId) -> LTy<'tcx> {
+        match self.static_tys.get(&did) {
+            Some(&lty) => lty,
+            None => self.const_tys[&did],
+        }
+    }
+
     pub fn assign_pointer_to_field(&mut self, field: &FieldDef) {
         let lty =
             self.assign_pointer_ids_with_info(self.tcx.type_of(field.did), PointerInfo::ANNOTATED);
@@ -923,6 +1289,21 @@ impl<'tcx> GlobalAnalysisCtxt<'tcx> {
         self.known_fns.get(symbol.name).copied()
     }
 
+    /// Add a user-supplied [`KnownFn`], replacing any existing one with the same name.
+    pub fn add_known_fn(&mut self, user_fn: UserKnownFn<'tcx>) {
+        let name = user_fn.known_fn.name;
+        self.known_fns.insert(name, user_fn.known_fn);
+        self.known_fn_pointees.insert(name, user_fn.pointees);
+    }
+
+    /// Get the pointee types declared for the [`KnownFn`] `def_id`, if any.
+    pub fn known_fn_pointees(&self, def_id: DefId) -> &[(Option<usize>, Ty<'tcx>)] {
+        let symbol = self.tcx.symbol_name(Instance::mono(self.tcx, def_id));
+        self.known_fn_pointees
+            .get(symbol.name)
+            .map_or(&[], |pointees| &pointees[..])
+    }
+
     /// Determine the [`PermissionSet`]s that should constrain [`PointerId`]s
     /// contained in the signatures of [`KnownFn`]s.
     ///
@@ -967,6 +1348,7 @@ impl<'a, 'tcx> AnalysisCtxt<'a, 'tcx> {
             local_decls: &mir.local_decls,
             local_tys: IndexVec::new(),
             addr_of_local: IndexVec::new(),
+            addr_of_local_fields: HashMap::new(),
             rvalue_tys: HashMap::new(),
             string_literal_locs: Default::default(),
         }
@@ -981,6 +1363,7 @@ impl<'a, 'tcx> AnalysisCtxt<'a, 'tcx> {
             ptr_info,
             local_tys,
             addr_of_local,
+            addr_of_local_fields,
             rvalue_tys,
             string_literal_locs,
         } = data;
@@ -990,6 +1373,7 @@ impl<'a, 'tcx> AnalysisCtxt<'a, 'tcx> {
             local_decls: &mir.local_decls,
             local_tys,
             addr_of_local,
+            addr_of_local_fields,
             rvalue_tys,
             string_literal_locs,
         }
@@ -1000,6 +1384,7 @@ impl<'a, 'tcx> AnalysisCtxt<'a, 'tcx> {
             ptr_info: self.ptr_info,
             local_tys: self.local_tys,
             addr_of_local: self.addr_of_local,
+            addr_of_local_fields: self.addr_of_local_fields,
             rvalue_tys: self.rvalue_tys,
             string_literal_locs: self.string_literal_locs,
         }
@@ -1112,9 +1497,11 @@ impl<'a, 'tcx> AnalysisCtxt<'a, 'tcx> {
                             base_lty.label,
                         )
                     }
-                    RvalueDesc::AddrOfLocal { local, proj } => {
-                        (self.type_of(local), proj, self.addr_of_local[local])
-                    }
+                    RvalueDesc::AddrOfLocal { local, proj } => (
+                        self.type_of(local),
+                        proj,
+                        self.addr_of_local_field(local, proj),
+                    ),
                 };
 
                 let mut pointee_lty = pointee_lty;
@@ -1184,6 +1571,18 @@ impl<'a, 'tcx> AnalysisCtxt<'a, 'tcx> {
         };
         util::lty_project(lty, proj, projection_lty)
     }
+
+    /// Get the `PointerId` for the address of `local` with projection `proj` applied, as in
+    /// `&x.a.b`.  This uses the entry in `addr_of_local_fields` for the longest prefix of `proj`
+    /// that has one, falling back to `addr_of_local` for the whole local.
+    pub fn addr_of_local_field(&self, local: Local, proj: &'tcx [PlaceElem<'tcx>]) -> PointerId {
+        let path = util::field_path_prefix(proj);
+        (1..=path.len())
+            .rev()
+            .find_map(|len| self.addr_of_local_fields.get(&(local, &path[..len])))
+            .copied()
+            .unwrap_or(self.addr_of_local[local])
+    }
 }
 
 impl<'tcx> AnalysisCtxtData<'tcx> {
@@ -1202,6 +1601,7 @@ impl<'tcx> AnalysisCtxtData<'tcx> {
             ptr_info,
             local_tys,
             addr_of_local,
+            addr_of_local_fields,
             rvalue_tys,
             string_literal_locs: _,
         } = self;
@@ -1219,6 +1619,10 @@ impl<'tcx> AnalysisCtxtData<'tcx> {
             }
         }
 
+        for ptr in addr_of_local_fields.values_mut() {
+            *ptr = map[*ptr];
+        }
+
         for lty in rvalue_tys.values_mut() {
             *lty = remap_lty_pointers(lcx, &map, lty);
         }
@@ -1330,6 +1734,22 @@ pub fn const_alloc_id(c: &Constant) -> Option<AllocId> {
     None
 }
 
+/// If `c` is a use of a local `const` item, return the `DefId` of the item.
+pub fn const_item_did(tcx: TyCtxt, c: &Constant) -> Option<DefId> {
+    let uv = match c.literal {
+        ConstantKind::Ty(ct) => match ct.kind() {
+            ConstKind::Unevaluated(uv) => uv,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let did = uv.def.did;
+    if uv.promoted.is_some() || !did.is_local() || tcx.def_kind(did) != DefKind::Const {
+        return None;
+    }
+    Some(did)
+}
+
 pub fn find_static_for_alloc(tcx: &TyCtxt, id: AllocId) -> Option<DefId> {
     match tcx.try_get_global_alloc(id) {
         None => {} //hmm, ok
@@ -1349,6 +1769,11 @@ impl<'tcx> TypeOf<'tcx> for Operand<'tcx> {
         match *self {
             Operand::Move(pl) | Operand::Copy(pl) => acx.type_of(pl),
             Operand::Constant(ref c) => {
+                if let Some(did) = const_item_did(acx.gacx.tcx, c) {
+                    if let Some(&lty) = acx.gacx.const_tys.get(&did) {
+                        return lty;
+                    }
+                }
                 // Constants of pointer type should only be pointers into static allocations.
                 // Find the defid of the static and look up the pointer ID in gacx.static_tys
                 if c.ty().is_any_ptr() {
@@ -1462,8 +1887,7 @@ impl Assignment<'_> {
         self.global.flags.and(&self.local.flags)
     }
 
-    #[allow(dead_code)]
-    pub fn _flags_mut(&mut self) -> PointerTableMut<FlagSet> {
+    pub fn flags_mut(&mut self) -> PointerTableMut<FlagSet> {
         self.global.flags.and_mut(&mut self.local.flags)
     }
 
@@ -0,0 +1,156 @@
+//! Sharing of pointer facts between the crates of a workspace.
+//!
+//! Each crate is analyzed on its own, so a binary that calls into a transpiled library sees the
+//! library's functions as unknown, even though the library's signatures may be rewritten.  When
+//! `C2RUST_ANALYZE_CRATE_FACTS_DIR` is set, the analysis of each crate writes the final
+//! permissions and flags of the pointers in its exported functions' signatures to a file in that
+//! directory.  Cargo compiles a library before the crates that depend on it, so when a dependent
+//! crate is analyzed, it can load these facts and treat calls into the library like calls to
+//! local functions whose signatures can't change.
+//!
+//! Exported functions whose signatures are rewritten also get a public `foo_shim` with the
+//! original signature, so callers in other crates that aren't rewritten can keep passing raw
+//! pointers.
+
+use crate::context::{FlagSet, GlobalAnalysisCtxt, GlobalAssignment, LFnSig, PermissionSet};
+use crate::pointer_id::PointerId;
+use rustc_hir::def_id::{DefId, LOCAL_CRATE};
+use rustc_middle::ty::TyCtxt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Facts about the signature of one exported function.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FnFacts {
+    /// The bits of the `PermissionSet` and `FlagSet` of each pointer in the signature, in the
+    /// order given by `sig_pointers`.  Positions that aren't pointers hold zeros.
+    pub ptrs: Vec<(u16, u16)>,
+}
+
+impl FnFacts {
+    pub fn perms(&self, i: usize) -> PermissionSet {
+        PermissionSet::from_bits_truncate(self.ptrs[i].0)
+    }
+
+    pub fn flags(&self, i: usize) -> FlagSet {
+        FlagSet::from_bits_truncate(self.ptrs[i].1)
+    }
+}
+
+/// Facts about the exported functions of one or more crates.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CrateFacts {
+    /// Facts for each function, keyed by its `DefPathHash`, which is stable across crates.
+    pub fns: HashMap<(u64, u64), FnFacts>,
+}
+
+/// Get the directory for sharing facts between crates, if one was set.
+pub fn facts_dir() -> Option<PathBuf> {
+    env::var_os("C2RUST_ANALYZE_CRATE_FACTS_DIR").map(PathBuf::from)
+}
+
+pub fn def_path_hash(tcx: TyCtxt, did: DefId) -> (u64, u64) {
+    tcx.def_path_hash(did).0.as_value()
+}
+
+/// List the `PointerId`s of every position in `lsig`, including non-pointer positions, which are
+/// `PointerId::NONE`.  This order depends only on the unrewritten signature, so it's the same in
+/// every crate.
+pub fn sig_pointers(lsig: &LFnSig) -> Vec<PointerId> {
+    lsig.inputs_and_output()
+        .flat_map(|lty| lty.iter())
+        .map(|lty| lty.label)
+        .collect()
+}
+
+/// Check whether `did` can be called from other crates.
+pub fn is_exported_fn(tcx: TyCtxt, did: DefId) -> bool {
+    did.is_local()
+        && !tcx.is_foreign_item(did)
+        && tcx.visibility(did).is_public()
+        && tcx.generics_of(did).count() == 0
+}
+
+/// Collect the final facts for every exported function of the current crate.
+pub fn collect_crate_facts(gacx: &GlobalAnalysisCtxt, gasn: &GlobalAssignment) -> CrateFacts {
+    let tcx = gacx.tcx;
+    let mut facts = CrateFacts::default();
+    for (&did, lsig) in &gacx.fn_sigs {
+        if !is_exported_fn(tcx, did) {
+            continue;
+        }
+        // Exported functions with safe wrappers keep their original signature under their
+        // original name.
+        let keep_sig = gacx.safe_wrapper_fns.contains(&did);
+        let ptrs = sig_pointers(lsig)
+            .into_iter()
+            .map(|ptr| {
+                if ptr.is_none() {
+                    return (0, 0);
+                }
+                let mut flags = gasn.flags[ptr];
+                if keep_sig {
+                    flags.insert(FlagSet::FIXED);
+                }
+                (gasn.perms[ptr].bits(), flags.bits())
+            })
+            .collect();
+        facts.fns.insert(def_path_hash(tcx, did), FnFacts { ptrs });
+    }
+    facts
+}
+
+/// Load and combine the facts of all crates analyzed so far.  Unreadable files are skipped with a
+/// warning.
+pub fn load_crate_facts(dir: &Path) -> CrateFacts {
+    let mut facts = CrateFacts::default();
+    let entries = match fs::read_dir(dir) {
+        Ok(x) => x,
+        // Nothing has been written yet.
+        Err(_) => return facts,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().map_or(true, |ext| ext != "facts") {
+            continue;
+        }
+        let f = match File::open(&path) {
+            Ok(f) => BufReader::new(f),
+            Err(e) => {
+                log::warn!("failed to open crate facts file {path:?}: {e}");
+                continue;
+            }
+        };
+        let crate_facts: CrateFacts = match bincode::deserialize_from(f) {
+            Ok(x) => x,
+            Err(e) => {
+                log::warn!("failed to parse crate facts file {path:?}: {e}");
+                continue;
+            }
+        };
+        eprintln!(
+            "loaded facts for {} fns from {path:?}",
+            crate_facts.fns.len()
+        );
+        facts.fns.extend(crate_facts.fns);
+    }
+    facts
+}
+
+/// Save the facts for the current crate in `dir`.
+pub fn save_crate_facts(tcx: TyCtxt, dir: &Path, facts: &CrateFacts) -> Result<(), bincode::Error> {
+    fs::create_dir_all(dir)?;
+    // A package's library and binary crates often share a name, so also include the
+    // `StableCrateId` to keep their files apart.
+    let name = format!(
+        "{}-{:016x}.facts",
+        tcx.crate_name(LOCAL_CRATE),
+        tcx.stable_crate_id(LOCAL_CRATE).to_u64()
+    );
+    let f = BufWriter::new(File::create(dir.join(name))?);
+    bincode::serialize_into(f, facts)
+}
@@ -1,14 +1,19 @@
+use std::collections::HashMap;
 use std::mem;
 
 use crate::context::{AnalysisCtxt, Assignment, FlagSet, PermissionSet, PointerId};
 use crate::pointee_type::PointeeTypes;
 use crate::pointer_id::{OwnedPointerTable, PointerTable, PointerTableMut};
 use crate::recent_writes::RecentWrites;
-use rustc_middle::mir::Body;
+use rustc_middle::mir::{Body, Location};
+use serde::{Deserialize, Serialize};
 
+mod non_null;
 mod type_check;
 
-#[derive(Clone, Debug)]
+pub use self::non_null::temp_def_before;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 enum Constraint {
     /// Pointer `.0` must have a subset of the permissions of pointer `.1`.
     Subset(PointerId, PointerId),
@@ -21,37 +26,104 @@ enum Constraint {
     NoPerms(PointerId, PermissionSet),
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct DataflowConstraints {
     constraints: Vec<Constraint>,
+    /// The MIR statement or terminator that produced each constraint, for explaining the results
+    /// to the user.  Constraints loaded from the cache have no origins.
+    #[serde(skip)]
+    origins: Vec<Option<Location>>,
+    /// The origin to use for constraints added from now on.
+    #[serde(skip)]
+    cur_origin: Option<Location>,
+}
+
+/// For each permission removed from a pointer by `DataflowConstraints::propagate`, the index of
+/// the constraint that removed it.  Only the first removal of each permission is recorded.
+#[derive(Clone, Debug, Default)]
+pub struct PermRemovals {
+    removed_by: HashMap<(PointerId, PermissionSet), usize>,
+}
+
+/// The reason a permission was removed from a pointer.  See
+/// `DataflowConstraints::removal_cause`.
+#[derive(Clone, Debug)]
+pub struct RemovalCause {
+    /// The MIR statement or terminator that produced the constraint, if known.
+    pub origin: Option<Location>,
+    /// If the permission was removed because the pointer was copied from another pointer that
+    /// lacks it, this is the other pointer.
+    pub from: Option<PointerId>,
+    pub desc: String,
 }
 
 impl DataflowConstraints {
+    fn push(&mut self, c: Constraint) {
+        self.constraints.push(c);
+        self.origins.push(self.cur_origin);
+    }
+
+    fn set_origin(&mut self, loc: Option<Location>) {
+        self.cur_origin = loc;
+    }
+
     fn add_subset(&mut self, a: PointerId, b: PointerId) {
-        self.constraints.push(Constraint::Subset(a, b));
+        self.push(Constraint::Subset(a, b));
     }
 
     fn add_subset_except(&mut self, a: PointerId, b: PointerId, except: PermissionSet) {
-        self.constraints
-            .push(Constraint::SubsetExcept(a, b, except));
+        self.push(Constraint::SubsetExcept(a, b, except));
     }
 
     fn add_all_perms(&mut self, ptr: PointerId, perms: PermissionSet) {
-        self.constraints.push(Constraint::AllPerms(ptr, perms));
+        self.push(Constraint::AllPerms(ptr, perms));
     }
 
     fn add_no_perms(&mut self, ptr: PointerId, perms: PermissionSet) {
-        self.constraints.push(Constraint::NoPerms(ptr, perms));
+        self.push(Constraint::NoPerms(ptr, perms));
+    }
+
+    /// Remove `UNIQUE` from each pointer in `ptrs`, which may alias another pointer according to
+    /// the alias pre-pass.  Each pointer is given with the location where it gets its value.  See
+    /// `crate::alias`.
+    pub fn add_alias_conflicts(&mut self, ptrs: impl IntoIterator<Item = (PointerId, Location)>) {
+        for (ptr, loc) in ptrs {
+            self.set_origin(Some(loc));
+            self.add_no_perms(ptr, PermissionSet::UNIQUE);
+        }
+        self.set_origin(None);
+    }
+
+    /// Get the reason `perm` was removed from `ptr`, if the removal was recorded in `removals`.
+    pub fn removal_cause(
+        &self,
+        removals: &PermRemovals,
+        ptr: PointerId,
+        perm: PermissionSet,
+    ) -> Option<RemovalCause> {
+        let i = *removals.removed_by.get(&(ptr, perm))?;
+        let c = &self.constraints[i];
+        let from = match *c {
+            Constraint::Subset(_, b) | Constraint::SubsetExcept(_, b, _) => Some(b),
+            Constraint::AllPerms(..) | Constraint::NoPerms(..) => None,
+        };
+        Some(RemovalCause {
+            origin: self.origins.get(i).copied().flatten(),
+            from,
+            desc: format!("{:?}", c),
+        })
     }
 
     /// Update the pointer permissions in `hypothesis` to satisfy these constraints.
     ///
     /// If `restrict_updates[ptr]` has some flags set, then those flags will be left unchanged in
-    /// `hypothesis[ptr]`.
+    /// `hypothesis[ptr]`.  The constraints responsible for any permissions removed are recorded in
+    /// `removals`.
     pub fn propagate(
         &self,
         hypothesis: &mut PointerTableMut<PermissionSet>,
         updates_forbidden: &PointerTable<PermissionSet>,
+        removals: &mut PermRemovals,
     ) -> bool {
         eprintln!("=== propagating ===");
         eprintln!("constraints:");
@@ -63,8 +135,10 @@ impl DataflowConstraints {
             eprintln!("  {}: {:?}", id, p);
         }
 
-        struct PropagatePerms;
-        impl PropagateRules<PermissionSet> for PropagatePerms {
+        struct PropagatePerms<'a> {
+            removals: &'a mut PermRemovals,
+        }
+        impl PropagateRules<PermissionSet> for PropagatePerms<'_> {
             fn subset(
                 &mut self,
                 a_ptr: PointerId,
@@ -135,9 +209,26 @@ impl DataflowConstraints {
                 let (old, new, updates_forbidden) = (*old, *new, *updates_forbidden);
                 (new & !updates_forbidden) | (old & updates_forbidden)
             }
+
+            fn record_change(
+                &mut self,
+                i: usize,
+                ptr: PointerId,
+                old: &PermissionSet,
+                new: &PermissionSet,
+            ) {
+                let removed = *old & !*new;
+                for bit in 0..u16::BITS {
+                    let perm = PermissionSet::from_bits_truncate(1 << bit);
+                    if !perm.is_empty() && removed.contains(perm) {
+                        self.removals.removed_by.entry((ptr, perm)).or_insert(i);
+                    }
+                }
+            }
         }
 
-        match self.propagate_inner(hypothesis, &mut PropagatePerms, Some(updates_forbidden)) {
+        let mut rules = PropagatePerms { removals };
+        match self.propagate_inner(hypothesis, &mut rules, Some(updates_forbidden)) {
             Ok(changed) => changed,
             Err(msg) => {
                 panic!("{}", msg);
@@ -179,7 +270,7 @@ impl DataflowConstraints {
             }
             i += 1;
 
-            for c in &self.constraints {
+            for (ci, c) in self.constraints.iter().enumerate() {
                 match *c {
                     Constraint::Subset(a, b) => {
                         if !xs.dirty(a) && !xs.dirty(b) {
@@ -191,6 +282,8 @@ impl DataflowConstraints {
                         let (new_a, new_b) = rules.subset(a, old_a, b, old_b);
                         let new_a = restrict_updates(rules, a, old_a, new_a);
                         let new_b = restrict_updates(rules, b, old_b, new_b);
+                        rules.record_change(ci, a, old_a, &new_a);
+                        rules.record_change(ci, b, old_b, &new_b);
                         xs.set(a, new_a);
                         xs.set(b, new_b);
                     }
@@ -205,6 +298,8 @@ impl DataflowConstraints {
                         let (new_a, new_b) = rules.subset_except(a, old_a, b, old_b, except);
                         let new_a = restrict_updates(rules, a, old_a, new_a);
                         let new_b = restrict_updates(rules, b, old_b, new_b);
+                        rules.record_change(ci, a, old_a, &new_a);
+                        rules.record_change(ci, b, old_b, &new_b);
                         xs.set(a, new_a);
                         xs.set(b, new_b);
                     }
@@ -217,6 +312,7 @@ impl DataflowConstraints {
                         let old = xs.get(ptr);
                         let new = rules.all_perms(ptr, perms, old);
                         let new = restrict_updates(rules, ptr, old, new);
+                        rules.record_change(ci, ptr, old, &new);
                         xs.set(ptr, new);
                     }
 
@@ -228,6 +324,7 @@ impl DataflowConstraints {
                         let old = xs.get(ptr);
                         let new = rules.no_perms(ptr, perms, old);
                         let new = restrict_updates(rules, ptr, old, new);
+                        rules.record_change(ci, ptr, old, &new);
                         xs.set(ptr, new);
                     }
                 }
@@ -334,6 +431,226 @@ impl DataflowConstraints {
             }
         }
     }
+
+    /// Mark every pointer that may be derived from a pointer already set in `marks`, by
+    /// following dataflow edges forward.  Returns `true` if any new pointers were marked.
+    pub fn propagate_forward(&self, marks: &mut PointerTableMut<bool>) -> bool {
+        struct Rules;
+        impl PropagateRules<bool> for Rules {
+            fn subset(
+                &mut self,
+                _a_ptr: PointerId,
+                a_val: &bool,
+                _b_ptr: PointerId,
+                b_val: &bool,
+            ) -> (bool, bool) {
+                (*a_val, *a_val || *b_val)
+            }
+
+            fn subset_except(
+                &mut self,
+                a_ptr: PointerId,
+                a_val: &bool,
+                b_ptr: PointerId,
+                b_val: &bool,
+                _except: PermissionSet,
+            ) -> (bool, bool) {
+                self.subset(a_ptr, a_val, b_ptr, b_val)
+            }
+
+            fn all_perms(&mut self, _ptr: PointerId, _perms: PermissionSet, val: &bool) -> bool {
+                *val
+            }
+
+            fn no_perms(&mut self, _ptr: PointerId, _perms: PermissionSet, val: &bool) -> bool {
+                *val
+            }
+
+            fn restrict_updates(&mut self, _old: &bool, new: &bool, _forbidden: &bool) -> bool {
+                *new
+            }
+        }
+
+        match self.propagate_inner(marks, &mut Rules, None) {
+            Ok(changed) => changed,
+            Err(msg) => {
+                panic!("{}", msg);
+            }
+        }
+    }
+
+    /// Mark every pointer that a pointer already set in `marks` may be derived from, by following
+    /// dataflow edges backward.  Returns `true` if any new pointers were marked.
+    pub fn propagate_backward(&self, marks: &mut PointerTableMut<bool>) -> bool {
+        struct Rules;
+        impl PropagateRules<bool> for Rules {
+            fn subset(
+                &mut self,
+                _a_ptr: PointerId,
+                a_val: &bool,
+                _b_ptr: PointerId,
+                b_val: &bool,
+            ) -> (bool, bool) {
+                (*a_val || *b_val, *b_val)
+            }
+
+            fn subset_except(
+                &mut self,
+                a_ptr: PointerId,
+                a_val: &bool,
+                b_ptr: PointerId,
+                b_val: &bool,
+                _except: PermissionSet,
+            ) -> (bool, bool) {
+                self.subset(a_ptr, a_val, b_ptr, b_val)
+            }
+
+            fn all_perms(&mut self, _ptr: PointerId, _perms: PermissionSet, val: &bool) -> bool {
+                *val
+            }
+
+            fn no_perms(&mut self, _ptr: PointerId, _perms: PermissionSet, val: &bool) -> bool {
+                *val
+            }
+
+            fn restrict_updates(&mut self, _old: &bool, new: &bool, _forbidden: &bool) -> bool {
+                *new
+            }
+        }
+
+        match self.propagate_inner(marks, &mut Rules, None) {
+            Ok(changed) => changed,
+            Err(msg) => {
+                panic!("{}", msg);
+            }
+        }
+    }
+
+    /// Mark every pointer that may alias a pointer already set in `marks`, by following dataflow
+    /// edges both forward and backward.  Marks never spread into, or through, a pointer set in
+    /// `barrier`.  Returns `true` if any new pointers were marked.
+    pub fn propagate_aliases(
+        &self,
+        marks: &mut PointerTableMut<bool>,
+        barrier: &PointerTable<bool>,
+    ) -> bool {
+        struct Rules;
+        impl PropagateRules<bool> for Rules {
+            fn subset(
+                &mut self,
+                _a_ptr: PointerId,
+                a_val: &bool,
+                _b_ptr: PointerId,
+                b_val: &bool,
+            ) -> (bool, bool) {
+                let marked = *a_val || *b_val;
+                (marked, marked)
+            }
+
+            fn subset_except(
+                &mut self,
+                a_ptr: PointerId,
+                a_val: &bool,
+                b_ptr: PointerId,
+                b_val: &bool,
+                _except: PermissionSet,
+            ) -> (bool, bool) {
+                self.subset(a_ptr, a_val, b_ptr, b_val)
+            }
+
+            fn all_perms(&mut self, _ptr: PointerId, _perms: PermissionSet, val: &bool) -> bool {
+                *val
+            }
+
+            fn no_perms(&mut self, _ptr: PointerId, _perms: PermissionSet, val: &bool) -> bool {
+                *val
+            }
+
+            fn restrict_updates(&mut self, old: &bool, new: &bool, barrier: &bool) -> bool {
+                if *barrier {
+                    *old
+                } else {
+                    *new
+                }
+            }
+        }
+
+        match self.propagate_inner(marks, &mut Rules, Some(barrier)) {
+            Ok(changed) => changed,
+            Err(msg) => {
+                panic!("{}", msg);
+            }
+        }
+    }
+
+    /// Propagate the origin flags (`FlagSet::ORIGIN`) forward along dataflow edges, so that each
+    /// pointer has the origins of every pointer it may be derived from.  `FIXED` pointers are left
+    /// unchanged.  Returns `true` if any flags changed.
+    pub fn propagate_origins(&self, asn: &mut Assignment) -> bool {
+        let (_, mut flags) = asn.all_mut();
+
+        struct Rules;
+        impl PropagateRules<FlagSet> for Rules {
+            fn subset(
+                &mut self,
+                _a_ptr: PointerId,
+                a_val: &FlagSet,
+                _b_ptr: PointerId,
+                b_val: &FlagSet,
+            ) -> (FlagSet, FlagSet) {
+                let mut b_flags = *b_val;
+                if !b_flags.contains(FlagSet::FIXED) {
+                    b_flags.insert(*a_val & FlagSet::ORIGIN);
+                }
+                (*a_val, b_flags)
+            }
+
+            fn subset_except(
+                &mut self,
+                a_ptr: PointerId,
+                a_val: &FlagSet,
+                b_ptr: PointerId,
+                b_val: &FlagSet,
+                _except: PermissionSet,
+            ) -> (FlagSet, FlagSet) {
+                self.subset(a_ptr, a_val, b_ptr, b_val)
+            }
+
+            fn all_perms(
+                &mut self,
+                _ptr: PointerId,
+                _perms: PermissionSet,
+                val: &FlagSet,
+            ) -> FlagSet {
+                *val
+            }
+
+            fn no_perms(
+                &mut self,
+                _ptr: PointerId,
+                _perms: PermissionSet,
+                val: &FlagSet,
+            ) -> FlagSet {
+                *val
+            }
+
+            fn restrict_updates(
+                &mut self,
+                _old: &FlagSet,
+                new: &FlagSet,
+                _updates_forbidden: &FlagSet,
+            ) -> FlagSet {
+                *new
+            }
+        }
+
+        match self.propagate_inner(&mut flags, &mut Rules, None) {
+            Ok(changed) => changed,
+            Err(msg) => {
+                panic!("{}", msg);
+            }
+        }
+    }
 }
 
 impl Constraint {
@@ -424,6 +741,8 @@ trait PropagateRules<T> {
     /// Apply a filter to restrict updates.  The result is similar to `new`, but all flags marked
     /// in `updates_forbidden` are adjusted to match their `old` values.
     fn restrict_updates(&mut self, old: &T, new: &T, updates_forbidden: &T) -> T;
+    /// Called when constraint `i` is about to update `ptr` from `old` to `new`.
+    fn record_change(&mut self, _i: usize, _ptr: PointerId, _old: &T, _new: &T) {}
 }
 
 pub fn generate_constraints<'tcx>(
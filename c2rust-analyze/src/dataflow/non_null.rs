@@ -0,0 +1,269 @@
+//! Flow-sensitive refinement of pointer nullability.
+//!
+//! The main dataflow analysis assigns a single `PermissionSet` to each pointer, so a pointer that
+//! is compared against null anywhere in the function loses `NON_NULL` everywhere.  However, code
+//! like `if !p.is_null() { q = p; }` establishes that `p` is non-null within the body of the `if`,
+//! so `q` can still be given `NON_NULL` (and the assignment rewritten to `q = p.unwrap()`).  This
+//! module finds the regions of the CFG where such null checks guarantee that a local is non-null.
+
+use crate::recent_writes::RecentWrites;
+use crate::util::{self, ty_callee, Callee};
+use either::Either;
+use rustc_data_structures::graph::dominators::Dominators;
+use rustc_middle::mir::{
+    BasicBlock, BinOp, Body, Local, LocalKind, Location, Operand, Rvalue, StatementKind,
+    TerminatorKind, UnOp,
+};
+use rustc_middle::ty::TyCtxt;
+
+/// A region of the CFG where `local` is known to be non-null.  The region consists of all
+/// locations dominated by `entry` where `local` still holds the value it had at `check_loc`.
+#[derive(Clone, Debug)]
+struct NonNullRegion {
+    local: Local,
+    /// The location where `local` was read for the null check.
+    check_loc: Location,
+    /// The successor of the null check that is taken when `local` is non-null.
+    entry: BasicBlock,
+}
+
+pub struct NonNullRegions {
+    regions: Vec<NonNullRegion>,
+    dominators: Dominators<BasicBlock>,
+}
+
+impl NonNullRegions {
+    pub fn new<'tcx>(
+        tcx: TyCtxt<'tcx>,
+        mir: &Body<'tcx>,
+        recent_writes: &RecentWrites,
+    ) -> NonNullRegions {
+        let predecessors = mir.basic_blocks.predecessors();
+        let mut regions = Vec::new();
+        for (bb, bb_data) in mir.basic_blocks().iter_enumerated() {
+            let term = bb_data.terminator();
+            let (discr, targets) = match term.kind {
+                TerminatorKind::SwitchInt {
+                    ref discr,
+                    ref targets,
+                    ..
+                } => (discr, targets),
+                _ => continue,
+            };
+            if targets.iter().count() != 1 {
+                continue;
+            }
+            let switch_loc = mir.terminator_loc(bb);
+            let (local, check_loc, is_null_if_true) =
+                match find_null_check(tcx, mir, recent_writes, switch_loc, discr) {
+                    Some(x) => x,
+                    None => continue,
+                };
+            let entry = if is_null_if_true {
+                targets.target_for_value(0)
+            } else {
+                targets.target_for_value(1)
+            };
+            // If `entry` can be reached some other way, then `local` might be null there.
+            if predecessors[entry].as_slice() != [bb] {
+                continue;
+            }
+            regions.push(NonNullRegion {
+                local,
+                check_loc,
+                entry,
+            });
+        }
+
+        NonNullRegions {
+            regions,
+            dominators: mir.basic_blocks.dominators(),
+        }
+    }
+
+    /// Check whether `local` is known to be non-null at `loc`.
+    pub fn is_non_null_at(
+        &self,
+        recent_writes: &RecentWrites,
+        loc: Location,
+        local: Local,
+    ) -> bool {
+        if !self.dominators.is_reachable(loc.block) {
+            return false;
+        }
+        self.regions.iter().any(|region| {
+            region.local == local
+                && self.dominators.is_dominated_by(loc.block, region.entry)
+                && recent_writes.same_value_at(region.check_loc, loc, local)
+        })
+    }
+}
+
+/// Check whether `discr` holds the result of a null check on a non-temporary local.  On success,
+/// returns the local, the location where it was read, and whether `discr` is `true` when the
+/// local is null.
+fn find_null_check<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &Body<'tcx>,
+    recent_writes: &RecentWrites,
+    loc: Location,
+    discr: &Operand<'tcx>,
+) -> Option<(Local, Location, bool)> {
+    let mut loc = loc;
+    let mut op = discr;
+    let mut is_null_if_true = true;
+    loop {
+        let (write_loc, def) = temp_def_before(tcx, mir, recent_writes, loc, op)?;
+        loc = write_loc;
+        let ptr_op = match def {
+            Either::Left(&Rvalue::Use(ref x)) => {
+                op = x;
+                continue;
+            }
+            Either::Left(&Rvalue::UnaryOp(UnOp::Not, ref x)) => {
+                is_null_if_true = !is_null_if_true;
+                op = x;
+                continue;
+            }
+            Either::Left(&Rvalue::BinaryOp(bop @ (BinOp::Eq | BinOp::Ne), ref ops)) => {
+                if bop == BinOp::Ne {
+                    is_null_if_true = !is_null_if_true;
+                }
+                if operand_is_null(tcx, mir, recent_writes, loc, &ops.1) {
+                    &ops.0
+                } else if operand_is_null(tcx, mir, recent_writes, loc, &ops.0) {
+                    &ops.1
+                } else {
+                    return None;
+                }
+            }
+            Either::Right((Callee::IsNull, args)) => &args[0],
+            _ => return None,
+        };
+        let (local, read_loc) = trace_ptr_to_local(tcx, mir, recent_writes, loc, ptr_op)?;
+        return Some((local, read_loc, is_null_if_true));
+    }
+}
+
+/// Follow the pointer `op` back through any temporaries that copy or cast it, and return the
+/// non-temporary local it was read from, along with the location of the read.
+fn trace_ptr_to_local<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &Body<'tcx>,
+    recent_writes: &RecentWrites,
+    loc: Location,
+    op: &Operand<'tcx>,
+) -> Option<(Local, Location)> {
+    let mut loc = loc;
+    let mut op = op;
+    loop {
+        let pl = op.place()?;
+        if !pl.projection.is_empty() {
+            return None;
+        }
+        if mir.local_kind(pl.local) != LocalKind::Temp {
+            if !mir.local_decls[pl.local].ty.is_unsafe_ptr() {
+                return None;
+            }
+            return Some((pl.local, loc));
+        }
+        match temp_def_before(tcx, mir, recent_writes, loc, op)? {
+            (write_loc, Either::Left(Rvalue::Use(x) | Rvalue::Cast(_, x, _))) => {
+                loc = write_loc;
+                op = x;
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// Check whether the value of `op` at `loc` is a null pointer constant, such as `ptr::null()` or
+/// `0 as *const T`.
+pub fn operand_is_null<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &Body<'tcx>,
+    recent_writes: &RecentWrites,
+    loc: Location,
+    op: &Operand<'tcx>,
+) -> bool {
+    if util::is_null_const_operand(op) {
+        return true;
+    }
+    match temp_def_before(tcx, mir, recent_writes, loc, op) {
+        Some((_, Either::Left(Rvalue::Cast(_, op, _)))) => util::is_null_const_operand(op),
+        Some((_, Either::Right((Callee::Null { .. }, _)))) => true,
+        _ => false,
+    }
+}
+
+/// Check whether the value of the local `op` at `loc` is the return value of a call to a function
+/// of this crate.  Unlike [`temp_def_before`], this also works for user variables, as in
+/// `let p = f(); if p.is_null() { ... }`.
+pub fn is_local_call_result<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &Body<'tcx>,
+    recent_writes: &RecentWrites,
+    loc: Location,
+    op: &Operand<'tcx>,
+) -> bool {
+    let pl = match op.place() {
+        Some(pl) if pl.projection.is_empty() => pl,
+        _ => return false,
+    };
+    if !matches!(mir.local_kind(pl.local), LocalKind::Temp | LocalKind::Var) {
+        return false;
+    }
+    let write_loc = match recent_writes.get_write_before(loc, pl.local) {
+        Some(x) => x,
+        None => return false,
+    };
+    match mir.stmt_at(write_loc) {
+        Either::Right(term) => match term.kind {
+            TerminatorKind::Call {
+                ref func,
+                destination,
+                ..
+            } => {
+                destination.as_local() == Some(pl.local)
+                    && matches!(ty_callee(tcx, func.ty(mir, tcx)), Callee::LocalDef { .. })
+            }
+            _ => false,
+        },
+        Either::Left(_) => false,
+    }
+}
+
+/// Find the definition of the temporary read by `op` at `loc`: either the `Rvalue` assigned to it,
+/// or the callee and arguments of the call that produced it.  Returns `None` if `op` is not a
+/// temporary or its definition can't be determined.
+pub fn temp_def_before<'a, 'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &'a Body<'tcx>,
+    recent_writes: &RecentWrites,
+    loc: Location,
+    op: &Operand<'tcx>,
+) -> Option<(
+    Location,
+    Either<&'a Rvalue<'tcx>, (Callee<'tcx>, &'a [Operand<'tcx>])>,
+)> {
+    let pl = op.place()?;
+    if !pl.projection.is_empty() || mir.local_kind(pl.local) != LocalKind::Temp {
+        return None;
+    }
+    let write_loc = recent_writes.get_write_before(loc, pl.local)?;
+    match mir.stmt_at(write_loc) {
+        Either::Left(stmt) => match stmt.kind {
+            StatementKind::Assign(ref x) => Some((write_loc, Either::Left(&x.1))),
+            _ => None,
+        },
+        Either::Right(term) => match term.kind {
+            TerminatorKind::Call {
+                ref func, ref args, ..
+            } => {
+                let callee = ty_callee(tcx, func.ty(mir, tcx));
+                Some((write_loc, Either::Right((callee, args))))
+            }
+            _ => None,
+        },
+    }
+}
@@ -1,22 +1,30 @@
+use super::non_null::{self, NonNullRegions};
 use super::DataflowConstraints;
+use crate::align;
 use crate::context::{AnalysisCtxt, LTy, PermissionSet, PointerId};
+use crate::crate_facts;
+use crate::ffi_escape;
+use crate::fn_tables;
 use crate::panic_detail;
 use crate::pointee_type::PointeeTypes;
 use crate::pointer_id::PointerTable;
 use crate::recent_writes::RecentWrites;
 use crate::util::{
-    self, describe_rvalue, is_transmutable_ptr_cast, ty_callee, Callee, RvalueDesc,
+    describe_rvalue, is_transmutable_ptr_cast, ty_callee, AtomicOp, Callee, RvalueDesc,
     UnknownDefCallee,
 };
 use assert_matches::assert_matches;
 use either::Either;
+use rustc_hir::def::DefKind;
 use rustc_hir::def_id::DefId;
 use rustc_middle::mir::{
-    AggregateKind, BinOp, Body, CastKind, Location, Mutability, Operand, Place, PlaceRef,
-    ProjectionElem, Rvalue, Statement, StatementKind, Terminator, TerminatorKind,
+    AggregateKind, BinOp, Body, CastKind, InlineAsmOperand, LocalKind, Location, Mutability,
+    Operand, Place, PlaceRef, ProjectionElem, Rvalue, Statement, StatementKind, Terminator,
+    TerminatorKind,
 };
 use rustc_middle::ty::adjustment::PointerCast;
-use rustc_middle::ty::{SubstsRef, Ty, TyKind};
+use rustc_middle::ty::{List, SubstsRef, Ty, TyKind};
+use rustc_target::spec::abi::Abi;
 
 /// Visitor that walks over the MIR, computing types of rvalues/operands/places and generating
 /// constraints as a side effect.
@@ -49,9 +57,11 @@ struct TypeChecker<'tcx, 'a> {
     /// structure defined in `crate::equiv`, so adding a constraint here has the effect of unifying
     /// the equivalence classes of the two `PointerId`s.
     equiv_constraints: Vec<(PointerId, PointerId)>,
+    /// Regions where null checks in the program establish that a pointer local is non-null.
+    non_null_regions: NonNullRegions,
 }
 
-impl<'tcx> TypeChecker<'tcx, '_> {
+impl<'tcx, 'a> TypeChecker<'tcx, 'a> {
     fn add_edge(&mut self, src: PointerId, dest: PointerId) {
         // Copying `src` to `dest` can discard permissions, but can't add new ones.
         self.constraints.add_subset(dest, src);
@@ -118,14 +128,22 @@ impl<'tcx> TypeChecker<'tcx, '_> {
 
         match cast_kind {
             CastKind::PointerFromExposedAddress => {
-                // We support only one case here, which is the case of null pointers
-                // constructed via casts such as `0 as *const T`
-                if !util::is_null_const_operand(op) {
-                    panic!("Creating non-null pointers from exposed addresses not supported");
+                if let Some((ptr, _)) = align::align_up_source(self.acx.tcx(), self.mir, op, to_ty)
+                {
+                    // Rounding a pointer up to an aligned address, as in `(p as usize + 7 & !7)
+                    // as *mut T`, is handled like `p.offset(n)`.
+                    let ptr_lty = self.acx.type_of(ptr);
+                    self.do_assign(to_lty, ptr_lty);
+                    self.constraints
+                        .add_all_perms(ptr_lty.label, PermissionSet::OFFSET_ADD);
+                } else {
+                    // Otherwise, the result is either null, as in `0 as *const T`, or a pointer
+                    // with no provenance.  Functions containing the latter are reported by
+                    // `provenance` and left unchanged.  In both cases, the target type of the cast
+                    // must not have `NON_NULL` permission.
+                    self.constraints
+                        .add_no_perms(to_lty.label, PermissionSet::NON_NULL);
                 }
-                // The target type of the cast must not have `NON_NULL` permission.
-                self.constraints
-                    .add_no_perms(to_lty.label, PermissionSet::NON_NULL);
             }
             CastKind::PointerExposeAddress => {
                 // Allow, as [`CastKind::PointerFromExposedAddress`] is the dangerous one,
@@ -256,9 +274,16 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                             self.do_assign(elem_lty, op_lty);
                         }
                     }
-                    AggregateKind::Adt(adt_did, ..) => {
+                    AggregateKind::Adt(adt_did, _, _, _, active_field) => {
                         let base_adt_def = self.acx.tcx().adt_def(adt_did);
                         let fields = &base_adt_def.non_enum_variant().fields;
+                        // A union literal has a single operand, which initializes only the
+                        // `active_field`.  The other fields keep their own `PointerId`s, so each
+                        // variant of a union is tracked separately.
+                        let fields = match active_field {
+                            Some(idx) => &fields[idx..=idx],
+                            None => &fields[..],
+                        };
                         for (field, op) in fields.iter().zip(ops.iter()) {
                             let op_lty = self.acx.type_of(op);
                             let unresolved_field_lty = self.acx.gacx.field_ltys[&field.did];
@@ -269,9 +294,13 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                             self.do_assign(resolved_field_lty, op_lty);
                         }
                     }
-                    AggregateKind::Tuple => {
-                        assert!(matches!(rvalue_lty.kind(), TyKind::Tuple(..)));
-                        // Pseudo-assign from each operand to the element type of the tuple.
+                    AggregateKind::Tuple | AggregateKind::Closure(..) => {
+                        assert!(matches!(
+                            rvalue_lty.kind(),
+                            TyKind::Tuple(..) | TyKind::Closure(..)
+                        ));
+                        // Pseudo-assign from each operand to the element type of the tuple, or
+                        // to the type of the closure's captured variable.
                         for (op, elem_lty) in ops.iter().zip(rvalue_lty.args.iter()) {
                             let op_lty = self.acx.type_of(op);
                             self.do_assign(elem_lty, op_lty);
@@ -388,6 +417,22 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 let rv_lty = self.acx.type_of_rvalue(rv, loc);
                 self.visit_rvalue(rv, rv_lty);
 
+                if let Rvalue::BinaryOp(BinOp::Eq | BinOp::Ne, ref ops) = *rv {
+                    self.visit_null_comparison(loc, ops);
+                }
+                if let Rvalue::BinaryOp(
+                    BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge,
+                    ref ops,
+                ) = *rv
+                {
+                    self.visit_ptr_comparison(ops);
+                }
+                if let Rvalue::BinaryOp(BinOp::Sub, ref ops)
+                | Rvalue::CheckedBinaryOp(BinOp::Sub, ref ops) = *rv
+                {
+                    self.visit_ptr_addr_diff(loc, ops);
+                }
+
                 if self.acx.has_field_projection(rv) {
                     // Fields don't get offset permissions propagated to their base pointer
                     self.do_assign_except(
@@ -395,6 +440,10 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                         rv_lty,
                         PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB,
                     )
+                } else if self.rvalue_is_checked_non_null(loc, rv) {
+                    // The source was checked for null, so the destination can stay `NON_NULL`.
+                    // The rewriter inserts an `unwrap()` if the source ends up nullable.
+                    self.do_assign_except(pl_lty, rv_lty, PermissionSet::NON_NULL);
                 } else {
                     self.do_assign(pl_lty, rv_lty);
                 }
@@ -406,10 +455,8 @@ impl<'tcx> TypeChecker<'tcx, '_> {
 
     pub fn visit_terminator(&mut self, term: &Terminator<'tcx>, loc: Location) {
         eprintln!("visit_terminator({:?})", term.kind);
-        let tcx = self.acx.tcx();
         let _g = panic_detail::set_current_span(term.source_info.span);
         // TODO(spernsteiner): other `TerminatorKind`s will be handled in the future
-        #[allow(clippy::single_match)]
         match term.kind {
             TerminatorKind::Call {
                 ref func,
@@ -418,23 +465,63 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 target: _,
                 ..
             } => {
-                let func = func.ty(self.mir, tcx);
                 self.visit_call(loc, func, args, destination);
             }
+            TerminatorKind::InlineAsm { ref operands, .. } => {
+                self.visit_inline_asm(operands);
+            }
             // TODO(spernsteiner): handle other `TerminatorKind`s
             _ => (),
         }
     }
 
+    /// Inline assembly may read, write, or offset any pointer passed to it.  The operands
+    /// themselves are `FIXED` (see `PointerInfo::OPAQUE_USE`), but the pointers they're copied
+    /// from still need the permissions the assembly might use.
+    fn visit_inline_asm(&mut self, operands: &[InlineAsmOperand<'tcx>]) {
+        for op in operands {
+            match *op {
+                InlineAsmOperand::In { ref value, .. }
+                | InlineAsmOperand::InOut {
+                    in_value: ref value,
+                    ..
+                } => {
+                    self.visit_operand(value);
+                    let lty = self.acx.type_of(value);
+                    if let TyKind::RawPtr(tm) = *lty.ty.kind() {
+                        let mut perms = PermissionSet::READ
+                            | PermissionSet::OFFSET_ADD
+                            | PermissionSet::OFFSET_SUB;
+                        if tm.mutbl == Mutability::Mut {
+                            perms |= PermissionSet::WRITE;
+                        }
+                        self.constraints.add_all_perms(lty.label, perms);
+                    }
+                }
+                _ => {}
+            }
+            match *op {
+                InlineAsmOperand::Out {
+                    place: Some(pl), ..
+                }
+                | InlineAsmOperand::InOut {
+                    out_place: Some(pl),
+                    ..
+                } => self.visit_place(pl, Mutability::Mut),
+                _ => {}
+            }
+        }
+    }
+
     pub fn visit_call(
         &mut self,
         loc: Location,
-        func: Ty<'tcx>,
+        func: &Operand<'tcx>,
         args: &[Operand<'tcx>],
         destination: Place<'tcx>,
     ) {
         let tcx = self.acx.tcx();
-        let callee = ty_callee(tcx, func);
+        let callee = ty_callee(tcx, func.ty(self.mir, tcx));
         eprintln!("callee = {callee:?}");
         match callee {
             Callee::Trivial => {}
@@ -450,6 +537,54 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 // As this is actually a known `fn`, we can treat it as a normal local call.
                 self.visit_local_call(def_id, substs, args, destination);
             }
+            Callee::UnknownDef(UnknownDefCallee::Direct {
+                ty: _,
+                def_id,
+                substs,
+                is_foreign: false,
+            }) if self.acx.gacx.upstream_fn_facts.contains_key(&def_id) => {
+                // A function from another crate of the workspace, whose signature was recorded
+                // when that crate was analyzed.  See `crate_facts`.
+                self.visit_local_call(def_id, substs, args, destination);
+            }
+            Callee::UnknownDef(UnknownDefCallee::Direct {
+                ty: _,
+                def_id,
+                substs,
+                is_foreign: true,
+            }) if ffi_escape::is_ffi_fn(self.acx.gacx, def_id) => {
+                // The arguments escape into foreign code.  The callee's signature is `FIXED`, and
+                // its parameters have the permissions it may use (see `ffi_escape`), so this works
+                // like a local call.  An argument copied into a temporary gets the same type as
+                // the original, so it's converted to the raw pointer type just once, at the call.
+                for arg_op in args {
+                    let def =
+                        non_null::temp_def_before(tcx, self.mir, self.recent_writes, loc, arg_op);
+                    if let Some((_, Either::Left(&Rvalue::Use(ref src_op)))) = def {
+                        if let Some(src_pl) = src_op.place() {
+                            let src_lty = self.acx.type_of(src_pl);
+                            let arg_lty = self.acx.type_of(arg_op);
+                            if !arg_lty.label.is_none() {
+                                self.do_unify(arg_lty, src_lty);
+                            }
+                        }
+                    }
+                }
+                self.visit_local_call(def_id, substs, args, destination);
+            }
+            Callee::UnknownDef(UnknownDefCallee::Indirect { .. }) => {
+                // A function pointer loaded from a function table field can only point to one of
+                // the functions stored in that field, so treat this as a call to each of them.
+                let acx = self.acx;
+                match fn_tables::indirect_call_targets(acx.gacx, self.mir, loc, func) {
+                    Some(targets) => {
+                        for &did in targets {
+                            self.visit_local_call(did, List::empty(), args, destination);
+                        }
+                    }
+                    None => log::error!("TODO: visit Callee::{callee:?}"),
+                }
+            }
             Callee::UnknownDef(_) => {
                 log::error!("TODO: visit Callee::{callee:?}");
             }
@@ -521,7 +656,9 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 let perms = PermissionSet::FREE;
                 self.constraints.add_all_perms(rv_lty.label, perms);
             }
-            Callee::Memcpy => {
+            // `memmove` has the same effect on permissions as `memcpy`; the only difference is that
+            // `dest` and `src` may overlap.
+            Callee::Memcpy | Callee::Memmove => {
                 let out_ptr = destination;
 
                 let dest_ptr = args[0]
@@ -598,9 +735,19 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 // self.do_equivalence_nested(pl_lty, rv_lty);
             }
             Callee::SizeOf { .. } => {}
+            Callee::PtrOffsetFrom => {
+                assert!(args.len() == 2);
+                self.visit_operand(&args[0]);
+                self.visit_operand(&args[1]);
+                // Both pointers must point into the same array, so they can be rewritten to
+                // slices and the distance computed from the lengths of the slices.
+                self.add_ptr_diff_perms(&args[0], &args[1]);
+            }
             Callee::IsNull => {
                 assert!(args.len() == 1);
                 self.visit_operand(&args[0]);
+                // The program checks whether this pointer is null, so it must be nullable.
+                self.mark_null_checked(loc, &args[0]);
             }
             Callee::Null { .. } => {
                 assert!(args.is_empty());
@@ -611,6 +758,73 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 self.constraints
                     .add_no_perms(pl_lty.label, PermissionSet::NON_NULL);
             }
+            Callee::VaArg => {
+                // The argument is read from the caller's variadic arguments, which carry no
+                // permissions.  `mark_va_arg_ptrs` keeps the result as a raw pointer.
+                self.visit_place(destination, Mutability::Mut);
+                for arg in args {
+                    self.visit_operand(arg);
+                }
+            }
+            Callee::ReadVolatile => {
+                // We handle this like a load, `destination = *args[0]`.
+                assert!(args.len() == 1);
+                self.visit_place(destination, Mutability::Mut);
+                self.visit_operand(&args[0]);
+                let ptr_lty = self.acx.type_of(&args[0]);
+                self.constraints
+                    .add_all_perms(ptr_lty.label, PermissionSet::READ);
+                let pl_lty = self.acx.type_of(destination);
+                self.do_assign(pl_lty, ptr_lty.args[0]);
+            }
+            Callee::WriteVolatile => {
+                // We handle this like a store, `*args[0] = args[1]`.
+                assert!(args.len() == 2);
+                self.visit_operand(&args[0]);
+                self.visit_operand(&args[1]);
+                let ptr_lty = self.acx.type_of(&args[0]);
+                self.constraints
+                    .add_all_perms(ptr_lty.label, PermissionSet::WRITE);
+                let rv_lty = self.acx.type_of(&args[1]);
+                self.do_assign(ptr_lty.args[0], rv_lty);
+            }
+            Callee::Atomic { op, .. } => {
+                // Loads and stores are handled like `*args[0]`, and the other operations like a
+                // load followed by a store.
+                self.visit_place(destination, Mutability::Mut);
+                for arg in args {
+                    self.visit_operand(arg);
+                }
+                let ptr_lty = self.acx.type_of(&args[0]);
+                let perms = match op {
+                    AtomicOp::Load => PermissionSet::READ,
+                    AtomicOp::Store => PermissionSet::WRITE,
+                    _ => PermissionSet::READ | PermissionSet::WRITE,
+                };
+                self.constraints.add_all_perms(ptr_lty.label, perms);
+
+                let pl_lty = self.acx.type_of(destination);
+                match op {
+                    AtomicOp::Load => self.do_assign(pl_lty, ptr_lty.args[0]),
+                    AtomicOp::Store => {
+                        let rv_lty = self.acx.type_of(&args[1]);
+                        self.do_assign(ptr_lty.args[0], rv_lty);
+                    }
+                    AtomicOp::Xchg => {
+                        let rv_lty = self.acx.type_of(&args[1]);
+                        self.do_assign(pl_lty, ptr_lty.args[0]);
+                        self.do_assign(ptr_lty.args[0], rv_lty);
+                    }
+                    AtomicOp::Cxchg { .. } => {
+                        // The result is a tuple of the previous value and a `bool`.
+                        let rv_lty = self.acx.type_of(&args[2]);
+                        self.do_assign(pl_lty.args[0], ptr_lty.args[0]);
+                        self.do_assign(ptr_lty.args[0], rv_lty);
+                    }
+                    // The other operations work only on integers.
+                    AtomicOp::Rmw { .. } => {}
+                }
+            }
         }
     }
 
@@ -645,6 +859,137 @@ impl<'tcx> TypeChecker<'tcx, '_> {
         self.do_assign(dest_lty, output_lty);
     }
 
+    /// Record that the program checks whether the pointer `op` is null.  Such a pointer must be
+    /// nullable, so we remove `NON_NULL` from it and from any temporaries it was copied from, up
+    /// to the first non-temporary place.
+    ///
+    /// If the checked value is the return value of a function in this crate, nothing is removed.
+    /// Its nullability comes from the callee's return type instead, which is nullable only if some
+    /// return site of the callee may yield null.  When every return site yields a non-null
+    /// pointer, the check is dead, and it's rewritten to a constant.  Likewise, an argument of a
+    /// function that only this crate can call is nullable only if some caller may pass null.
+    fn mark_null_checked(&mut self, loc: Location, op: &Operand<'tcx>) {
+        let tcx = self.acx.tcx();
+        let mut loc = loc;
+        let mut op = op;
+        let mut ptrs = Vec::new();
+        loop {
+            let pl = match op.place() {
+                Some(pl) => pl,
+                None => break,
+            };
+            let lty = self.acx.type_of(pl);
+            if !lty.ty.is_unsafe_ptr() {
+                break;
+            }
+            ptrs.push(lty.label);
+
+            if pl.projection.is_empty()
+                && self.mir.local_kind(pl.local) == LocalKind::Arg
+                && !self.is_externally_callable()
+            {
+                return;
+            }
+
+            if non_null::is_local_call_result(tcx, self.mir, self.recent_writes, loc, op) {
+                return;
+            }
+            match non_null::temp_def_before(tcx, self.mir, self.recent_writes, loc, op) {
+                Some((
+                    write_loc,
+                    Either::Left(Rvalue::Use(rhs_op) | Rvalue::Cast(_, rhs_op, _)),
+                )) => {
+                    loc = write_loc;
+                    op = rhs_op;
+                }
+                _ => break,
+            }
+        }
+        for ptr in ptrs {
+            self.constraints.add_no_perms(ptr, PermissionSet::NON_NULL);
+        }
+    }
+
+    /// Check whether the function being analyzed may be called from outside this crate, either
+    /// directly or through a function pointer passed to foreign code.
+    fn is_externally_callable(&self) -> bool {
+        let tcx = self.acx.tcx();
+        let did = self.mir.source.def_id();
+        if !matches!(tcx.def_kind(did), DefKind::Fn | DefKind::AssocFn) {
+            return true;
+        }
+        crate_facts::is_exported_fn(tcx, did) || tcx.fn_sig(did).abi() != Abi::Rust
+    }
+
+    /// Handle a comparison `ops.0 == ops.1` or `ops.0 != ops.1`.  If either side is a null
+    /// pointer, the other side is marked as nullable.
+    fn visit_null_comparison(&mut self, loc: Location, ops: &(Operand<'tcx>, Operand<'tcx>)) {
+        let tcx = self.acx.tcx();
+        let (mir, recent_writes) = (self.mir, self.recent_writes);
+        if non_null::operand_is_null(tcx, mir, recent_writes, loc, &ops.1) {
+            self.mark_null_checked(loc, &ops.0);
+        } else if non_null::operand_is_null(tcx, mir, recent_writes, loc, &ops.0) {
+            self.mark_null_checked(loc, &ops.1);
+        }
+    }
+
+    /// Handle a comparison between two pointers.  Pointers compared this way usually point into
+    /// the same array, as in `while p != end { ...; p = p.offset(1); }`, so if one side needs
+    /// offset permissions, the other side gets them too.  This lets both sides become slices.
+    fn visit_ptr_comparison(&mut self, ops: &(Operand<'tcx>, Operand<'tcx>)) {
+        let lty0 = self.acx.type_of(&ops.0);
+        let lty1 = self.acx.type_of(&ops.1);
+        if !lty0.ty.is_unsafe_ptr() || !lty1.ty.is_unsafe_ptr() {
+            return;
+        }
+        let except = PermissionSet::all() - (PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB);
+        self.add_edge_except(lty0.label, lty1.label, except);
+        self.add_edge_except(lty1.label, lty0.label, except);
+    }
+
+    /// Handle a subtraction `a - b`.  If both sides are pointers cast to integers, as in `end as
+    /// usize - start as usize`, the pointers are handled as in `offset_from`.
+    fn visit_ptr_addr_diff(&mut self, loc: Location, ops: &(Operand<'tcx>, Operand<'tcx>)) {
+        let tcx = self.acx.tcx();
+        let (mir, recent_writes) = (self.mir, self.recent_writes);
+        let addr_source = |op| match non_null::temp_def_before(tcx, mir, recent_writes, loc, op) {
+            Some((_, Either::Left(&Rvalue::Cast(CastKind::PointerExposeAddress, ref ptr, _)))) => {
+                Some(ptr)
+            }
+            _ => None,
+        };
+        if let (Some(ptr0), Some(ptr1)) = (addr_source(&ops.0), addr_source(&ops.1)) {
+            self.add_ptr_diff_perms(ptr0, ptr1);
+        }
+    }
+
+    /// Add the permissions needed to compute the distance between the pointers `op0` and `op1`.
+    fn add_ptr_diff_perms(&mut self, op0: &Operand<'tcx>, op1: &Operand<'tcx>) {
+        let perms = PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB;
+        for op in [op0, op1] {
+            let lty = self.acx.type_of(op);
+            if lty.ty.is_unsafe_ptr() {
+                self.constraints.add_all_perms(lty.label, perms);
+            }
+        }
+    }
+
+    /// Check whether `rv` reads a local that is known to be non-null at `loc` due to an earlier
+    /// null check.  When assigning from such a local, the destination can keep `NON_NULL` even if
+    /// the local itself is nullable.
+    fn rvalue_is_checked_non_null(&self, loc: Location, rv: &Rvalue<'tcx>) -> bool {
+        let op = match *rv {
+            Rvalue::Use(ref op) | Rvalue::Cast(_, ref op, _) => op,
+            _ => return false,
+        };
+        let pl = match op.place() {
+            Some(pl) if pl.projection.is_empty() => pl,
+            _ => return false,
+        };
+        self.non_null_regions
+            .is_non_null_at(self.recent_writes, loc, pl.local)
+    }
+
     /// Check whether the value of `op` at `loc` is equal to `mem::size_of::<ty>`.  Returns true if
     /// the value is definitely equal, or false if unsure.
     fn operand_is_size_of_t(&self, loc: Location, op: &Operand<'tcx>, ty: Ty<'tcx>) -> bool {
@@ -721,30 +1066,38 @@ pub fn visit<'tcx>(
         pointee_types,
         constraints: DataflowConstraints::default(),
         equiv_constraints: Vec::new(),
+        non_null_regions: NonNullRegions::new(acx.tcx(), mir, recent_writes),
     };
 
     for (ptr, perms) in acx.string_literal_perms() {
         tc.constraints.add_all_perms(ptr, perms);
     }
 
+    // The address of a field of a local, as in `&x.a.b`, can't have permissions that the address
+    // of the enclosing field or local lacks.  Sibling fields are unrelated, so they can end up
+    // with different permissions.
+    for (&(local, path), &ptr) in &acx.addr_of_local_fields {
+        let parent = acx.addr_of_local_field(local, &path[..path.len() - 1]);
+        tc.constraints.add_subset(ptr, parent);
+    }
+
     for (bb, bb_data) in mir.basic_blocks().iter_enumerated() {
         for (i, stmt) in bb_data.statements.iter().enumerate() {
-            tc.visit_statement(
-                stmt,
-                Location {
-                    block: bb,
-                    statement_index: i,
-                },
-            );
-        }
-        tc.visit_terminator(
-            bb_data.terminator(),
-            Location {
-                statement_index: bb_data.statements.len(),
+            let loc = Location {
                 block: bb,
-            },
-        );
+                statement_index: i,
+            };
+            tc.constraints.set_origin(Some(loc));
+            tc.visit_statement(stmt, loc);
+        }
+        let loc = Location {
+            statement_index: bb_data.statements.len(),
+            block: bb,
+        };
+        tc.constraints.set_origin(Some(loc));
+        tc.visit_terminator(bb_data.terminator(), loc);
     }
+    tc.constraints.set_origin(None);
 
     (tc.constraints, tc.equiv_constraints)
 }
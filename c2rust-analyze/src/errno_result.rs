@@ -0,0 +1,415 @@
+//! Detection of functions that report errors through errno-style integer return codes.
+//!
+//! C functions commonly return `0` on success and a negative error code on failure:
+//!
+//! ```ignore
+//! unsafe fn parse(s: *const c_char) -> c_int {
+//!     if s.is_null() {
+//!         return -(22 as c_int);
+//!     }
+//!     // ...
+//!     return 0 as c_int;
+//! }
+//! ```
+//!
+//! This is opt-in: a function is considered only if it's listed in the file named by
+//! `C2RUST_ANALYZE_ERRNO_RESULT_FNS_LIST` or marked `#[c2rust_analyze_test::errno_result]`.  If
+//! every value it returns is `0`, a negated constant, or the result of calling another such
+//! function, and it's only ever called directly, it's rewritten to return
+//! `Result<(), std::io::Error>`.  Its callers are rewritten to match: `parse(s) < 0` becomes
+//! `parse(s).is_err()`, and the propagation idiom `let r = parse(s); if r < 0 { return r; }`
+//! becomes `parse(s)?`.  Any other call is converted back to the original error code.
+
+use log::debug;
+use rustc_ast::LitKind;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{
+    BinOpKind, Block, Body, Expr, ExprKind, HirId, PatKind, QPath, Stmt, StmtKind, UnOp,
+};
+use rustc_middle::ty::{Ty, TyCtxt, TypeckResults};
+use rustc_span::Span;
+use std::collections::HashSet;
+
+/// Functions whose errno-style return codes are rewritten to `Result`.
+#[derive(Clone, Debug, Default)]
+pub struct ErrnoResultInfo {
+    /// The functions whose return type becomes `Result<(), std::io::Error>`.
+    pub fns: HashSet<DefId>,
+    /// Every function containing a use that needs rewriting, including a call to one of `fns`
+    /// or a `return` in one of `fns`.
+    pub users: HashSet<DefId>,
+}
+
+/// A use of an errno-returning function, or a value returned from one.
+#[derive(Clone, Copy, Debug)]
+pub enum ErrnoUse<'tcx> {
+    /// `return 0`, or a trailing `0` at the end of the function.
+    ReturnOk { expr: Span },
+    /// `return -(22 as c_int)`.  `code` is the operand of the negation.
+    ReturnErr { expr: Span, code: Span },
+    /// `f(x) < 0` or `f(x) != 0`, which check for an error, or `f(x) == 0` or `f(x) >= 0`, which
+    /// check for success.
+    Check {
+        expr: Span,
+        call: Span,
+        is_err: bool,
+    },
+    /// `f(x);`, discarding the result.
+    Ignore { call: Span },
+    /// `let r = f(x); if r < 0 { return r; }`, which passes an error on to the caller.  `pat` is
+    /// the binding `r`, and `check` is the whole `if` expression.
+    Propagate { pat: Span, call: Span, check: Span },
+    /// Any other call, whose result must be converted back to an integer of type `ret_ty`.
+    Convert { call: Span, ret_ty: Ty<'tcx> },
+}
+
+/// If `ex` is a call to a function in `fns`, return that function.
+fn errno_callee(typeck_results: &TypeckResults, ex: &Expr, fns: &HashSet<DefId>) -> Option<DefId> {
+    let func = match ex.kind {
+        ExprKind::Call(func, _) => func,
+        _ => return None,
+    };
+    let qpath = match func.kind {
+        ExprKind::Path(ref qpath) => qpath,
+        _ => return None,
+    };
+    match typeck_results.qpath_res(qpath, func.hir_id) {
+        Res::Def(DefKind::Fn, did) if fns.contains(&did) => Some(did),
+        _ => None,
+    }
+}
+
+fn strip_casts<'tcx>(mut ex: &'tcx Expr<'tcx>) -> &'tcx Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+fn is_zero(ex: &Expr) -> bool {
+    match strip_casts(ex).kind {
+        ExprKind::Lit(ref lit) => matches!(lit.node, LitKind::Int(0, _)),
+        _ => false,
+    }
+}
+
+/// If `ex` is a negated constant, as in `-(22 as c_int)` or `-EINVAL`, return the operand of the
+/// negation.
+fn negated_const<'tcx>(
+    typeck_results: &TypeckResults<'tcx>,
+    ex: &'tcx Expr<'tcx>,
+) -> Option<&'tcx Expr<'tcx>> {
+    let code = match strip_casts(ex).kind {
+        ExprKind::Unary(UnOp::Neg, code) => code,
+        _ => return None,
+    };
+    let is_const = match strip_casts(code).kind {
+        ExprKind::Lit(ref lit) => matches!(lit.node, LitKind::Int(x, _) if x != 0),
+        ExprKind::Path(ref qpath) => matches!(
+            typeck_results.qpath_res(qpath, code.hir_id),
+            Res::Def(DefKind::Const, _)
+        ),
+        _ => false,
+    };
+    if is_const {
+        Some(code)
+    } else {
+        None
+    }
+}
+
+/// If `ex` is a path to the local variable `hir_id`, as in `r`.
+fn is_local(ex: &Expr, hir_id: HirId) -> bool {
+    match ex.kind {
+        ExprKind::Path(QPath::Resolved(None, path)) => path.res == Res::Local(hir_id),
+        _ => false,
+    }
+}
+
+/// Counts the uses of a local variable.
+struct LocalUseCounter {
+    hir_id: HirId,
+    count: usize,
+}
+
+impl<'tcx> Visitor<'tcx> for LocalUseCounter {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        if is_local(ex, self.hir_id) {
+            self.count += 1;
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+struct ErrnoUseVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    body: &'tcx Body<'tcx>,
+    /// The candidate functions.
+    fns: &'a HashSet<DefId>,
+    /// The function being visited, if it's one of `fns`.
+    current_fn: Option<DefId>,
+    /// Calls and `return`s whose handling was already decided by an enclosing expression.
+    handled: HashSet<HirId>,
+    /// `func` expressions of calls, which are allowed to refer to candidate functions.
+    callees: HashSet<HirId>,
+    uses: Vec<ErrnoUse<'tcx>>,
+    /// Candidate functions that turned out not to be eligible.
+    rejected: HashSet<DefId>,
+}
+
+impl<'a, 'tcx> ErrnoUseVisitor<'a, 'tcx> {
+    /// Handle a value returned from `self.current_fn`.
+    fn visit_return_value(&mut self, ex: &'tcx Expr<'tcx>) {
+        let current_fn = match self.current_fn {
+            Some(x) => x,
+            None => return,
+        };
+        if errno_callee(self.typeck_results, ex, self.fns).is_some() {
+            // `return g(x)` already has the right type.
+            self.handled.insert(ex.hir_id);
+        } else if is_zero(ex) {
+            self.uses.push(ErrnoUse::ReturnOk { expr: ex.span });
+        } else if let Some(code) = negated_const(self.typeck_results, ex) {
+            self.uses.push(ErrnoUse::ReturnErr {
+                expr: ex.span,
+                code: code.span,
+            });
+        } else {
+            debug!("{current_fn:?}: unsupported return value {ex:?}");
+            self.rejected.insert(current_fn);
+        }
+    }
+
+    /// Check for `let r = g(x); if r < 0 { return r; }`, starting at `stmts[0]`.
+    fn try_match_propagate(&mut self, stmts: &'tcx [Stmt<'tcx>]) {
+        if self.current_fn.is_none() {
+            return;
+        }
+        let (local, if_ex) = match stmts {
+            [s0, s1, ..] => match (&s0.kind, &s1.kind) {
+                (&StmtKind::Local(local), &(StmtKind::Expr(ex) | StmtKind::Semi(ex))) => {
+                    (local, ex)
+                }
+                _ => return,
+            },
+            _ => return,
+        };
+        let call = match local.init {
+            Some(init) if errno_callee(self.typeck_results, init, self.fns).is_some() => init,
+            _ => return,
+        };
+        let var = match local.pat.kind {
+            PatKind::Binding(_, hir_id, _, None) if local.ty.is_none() => hir_id,
+            _ => return,
+        };
+        let (cond, then) = match if_ex.kind {
+            ExprKind::If(cond, then, None) => (cond, then),
+            _ => return,
+        };
+        let cond = match cond.kind {
+            ExprKind::DropTemps(e) => e,
+            _ => cond,
+        };
+        match cond.kind {
+            ExprKind::Binary(op, lhs, rhs)
+                if matches!(op.node, BinOpKind::Lt | BinOpKind::Ne)
+                    && is_local(lhs, var)
+                    && is_zero(rhs) => {}
+            _ => return,
+        }
+        let ret = match then.kind {
+            ExprKind::Block(block, None) => match (block.stmts, block.expr) {
+                ([], Some(e)) => e,
+                ([stmt], None) => match stmt.kind {
+                    StmtKind::Expr(e) | StmtKind::Semi(e) => e,
+                    _ => return,
+                },
+                _ => return,
+            },
+            _ => return,
+        };
+        match ret.kind {
+            ExprKind::Ret(Some(value)) if is_local(value, var) => {}
+            _ => return,
+        }
+
+        // `r` must not be used anywhere else, since it no longer holds the error code.
+        let mut counter = LocalUseCounter {
+            hir_id: var,
+            count: 0,
+        };
+        counter.visit_body(self.body);
+        if counter.count != 2 {
+            return;
+        }
+
+        self.handled.insert(call.hir_id);
+        self.handled.insert(ret.hir_id);
+        self.uses.push(ErrnoUse::Propagate {
+            pat: local.pat.span,
+            call: call.span,
+            check: if_ex.span,
+        });
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for ErrnoUseVisitor<'a, 'tcx> {
+    fn visit_block(&mut self, block: &'tcx Block<'tcx>) {
+        for i in 0..block.stmts.len() {
+            self.try_match_propagate(&block.stmts[i..]);
+        }
+        intravisit::walk_block(self, block);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'tcx Stmt<'tcx>) {
+        if let StmtKind::Semi(ex) = stmt.kind {
+            if !self.handled.contains(&ex.hir_id)
+                && errno_callee(self.typeck_results, ex, self.fns).is_some()
+            {
+                self.handled.insert(ex.hir_id);
+                self.uses.push(ErrnoUse::Ignore { call: ex.span });
+            }
+        }
+        intravisit::walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            ExprKind::Ret(Some(value)) if !self.handled.contains(&ex.hir_id) => {
+                self.visit_return_value(value);
+            }
+            ExprKind::Binary(op, lhs, rhs)
+                if is_zero(rhs) && errno_callee(self.typeck_results, lhs, self.fns).is_some() =>
+            {
+                let is_err = match op.node {
+                    BinOpKind::Lt | BinOpKind::Ne => Some(true),
+                    BinOpKind::Ge | BinOpKind::Eq => Some(false),
+                    _ => None,
+                };
+                if let Some(is_err) = is_err {
+                    self.handled.insert(lhs.hir_id);
+                    self.uses.push(ErrnoUse::Check {
+                        expr: ex.span,
+                        call: lhs.span,
+                        is_err,
+                    });
+                }
+            }
+            ExprKind::Call(func, _) => {
+                self.callees.insert(func.hir_id);
+                if let Some(did) = errno_callee(self.typeck_results, ex, self.fns) {
+                    if !self.handled.contains(&ex.hir_id) {
+                        let ret_ty = self.tcx.fn_sig(did).skip_binder().output();
+                        self.uses.push(ErrnoUse::Convert {
+                            call: ex.span,
+                            ret_ty,
+                        });
+                    }
+                }
+            }
+            ExprKind::Path(ref qpath) if !self.callees.contains(&ex.hir_id) => {
+                if let Res::Def(DefKind::Fn, did) = self.typeck_results.qpath_res(qpath, ex.hir_id)
+                {
+                    if self.fns.contains(&did) {
+                        debug!("{did:?}: used other than by a direct call at {:?}", ex.span);
+                        self.rejected.insert(did);
+                    }
+                }
+            }
+            _ => {}
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Collect the uses of the functions in `fns` within the body of `ldid`, along with any functions
+/// in `fns` that were found to be ineligible.
+fn collect_errno_uses_and_rejects<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ldid: LocalDefId,
+    fns: &HashSet<DefId>,
+) -> (Vec<ErrnoUse<'tcx>>, HashSet<DefId>) {
+    let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+        Some(x) => x,
+        None => return (Vec::new(), HashSet::new()),
+    };
+    let body = tcx.hir().body(hir_body_id);
+    let did = ldid.to_def_id();
+    let mut v = ErrnoUseVisitor {
+        tcx,
+        typeck_results: tcx.typeck_body(hir_body_id),
+        body,
+        fns,
+        current_fn: Some(did).filter(|did| fns.contains(did)),
+        handled: HashSet::new(),
+        callees: HashSet::new(),
+        uses: Vec::new(),
+        rejected: HashSet::new(),
+    };
+    // The trailing expression of the function body is returned, just like `return e`.
+    if let ExprKind::Block(block, None) = body.value.kind {
+        if let Some(tail) = block.expr {
+            if !matches!(tail.kind, ExprKind::Ret(_)) {
+                v.visit_return_value(tail);
+            }
+        }
+    }
+    v.visit_body(body);
+    (v.uses, v.rejected)
+}
+
+/// Collect the uses of the errno-returning functions in `fns` within the body of `ldid`.
+pub fn collect_errno_uses<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ldid: LocalDefId,
+    fns: &HashSet<DefId>,
+) -> Vec<ErrnoUse<'tcx>> {
+    collect_errno_uses_and_rejects(tcx, ldid, fns).0
+}
+
+/// Among the `candidates` selected by the user, find the functions whose errno-style return codes
+/// can be rewritten to `Result`.
+pub fn find_errno_result_fns(
+    tcx: TyCtxt,
+    all_fn_ldids: &[LocalDefId],
+    candidates: &HashSet<DefId>,
+) -> ErrnoResultInfo {
+    let mut fns = candidates
+        .iter()
+        .copied()
+        .filter(|&did| {
+            let ok = matches!(tcx.def_kind(did), DefKind::Fn)
+                && tcx.fn_sig(did).skip_binder().output().is_signed();
+            if !ok {
+                debug!("{did:?}: doesn't return a signed integer");
+            }
+            ok
+        })
+        .collect::<HashSet<_>>();
+
+    // Rejecting one function can invalidate others that return its result, so repeat until
+    // nothing changes.
+    loop {
+        let mut rejected = HashSet::new();
+        for &ldid in all_fn_ldids {
+            rejected.extend(collect_errno_uses_and_rejects(tcx, ldid, &fns).1);
+        }
+        if rejected.is_empty() {
+            break;
+        }
+        fns.retain(|did| !rejected.contains(did));
+    }
+
+    let mut users = HashSet::new();
+    if !fns.is_empty() {
+        for &ldid in all_fn_ldids {
+            if !collect_errno_uses(tcx, ldid, &fns).is_empty() {
+                users.insert(ldid.to_def_id());
+            }
+        }
+    }
+    ErrnoResultInfo { fns, users }
+}
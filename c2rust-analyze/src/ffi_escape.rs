@@ -0,0 +1,54 @@
+//! Pointers that escape into foreign functions.
+//!
+//! A pointer passed to an `extern "C"` function escapes the analysis: the callee's body isn't
+//! available, and unless it's described by a `KnownFn`, nothing says how it uses its arguments.
+//! The foreign function's signature stays `FIXED` (see `analyze::mark_foreign_fixed`), but the
+//! pointers in the caller don't have to.  They can be rewritten to safe types like any other
+//! pointer, and converted back to the declared raw pointer types only at the call, as in
+//! `f(p.as_mut_ptr())`.
+//!
+//! We assume that the callee may read through any pointer it's given and write through any `*mut`
+//! pointer, but that it doesn't offset or free it, and doesn't keep it after the call returns.
+//! The parameters of these functions get the corresponding permissions, and the dataflow analysis
+//! passes them back to the arguments.
+
+use crate::context::{GlobalAnalysisCtxt, PermissionSet};
+use crate::pointer_id::PointerId;
+use crate::thread_shared;
+use rustc_hir::def::DefKind;
+use rustc_hir::def_id::DefId;
+use rustc_hir::Mutability;
+use rustc_middle::ty::TyKind;
+
+/// Returns `true` if `did` is a foreign function whose pointer arguments escape the analysis.
+/// Foreign functions described by a `KnownFn` and those rewritten by other passes are excluded.
+pub fn is_ffi_fn(gacx: &GlobalAnalysisCtxt, did: DefId) -> bool {
+    let tcx = gacx.tcx;
+    tcx.is_foreign_item(did)
+        && matches!(tcx.def_kind(did), DefKind::Fn)
+        && gacx.fn_sigs.contains_key(&did)
+        && gacx.known_fn(did).is_none()
+        && !thread_shared::is_rewritten_pthread_fn(tcx, did)
+}
+
+/// Get the permissions for the pointers in the parameters of each foreign function that
+/// [`is_ffi_fn`].
+pub fn ffi_param_perms(gacx: &GlobalAnalysisCtxt) -> Vec<(PointerId, PermissionSet)> {
+    let mut perms = Vec::new();
+    for (&did, lsig) in &gacx.fn_sigs {
+        if !is_ffi_fn(gacx, did) {
+            continue;
+        }
+        for lty in lsig.inputs.iter().flat_map(|lty| lty.iter()) {
+            if lty.label.is_none() {
+                continue;
+            }
+            let mut ptr_perms = PermissionSet::READ;
+            if matches!(*lty.ty.kind(), TyKind::RawPtr(tm) if tm.mutbl == Mutability::Mut) {
+                ptr_perms.insert(PermissionSet::WRITE);
+            }
+            perms.push((lty.label, ptr_perms));
+        }
+    }
+    perms
+}
@@ -0,0 +1,333 @@
+//! Detection of `FILE*` locals with a single owner.
+//!
+//! Transpiled code that does file IO typically opens a file into a local, reads or writes it, and
+//! closes it again before returning:
+//!
+//! ```ignore
+//! let mut f: *mut FILE = fopen(path, b"rb\0" as *const u8 as *const c_char);
+//! if f.is_null() {
+//!     return -1;
+//! }
+//! let n = fread(buf as *mut c_void, 1, len, f);
+//! fclose(f);
+//! ```
+//!
+//! When the local is initialized by `fopen` with a literal mode of `r`, `w`, or `a` (optionally
+//! with `b`), and is used only as the stream argument of `fread` (for `r`) or `fwrite` (for `w` and
+//! `a`), in `fclose`, and in `is_null` checks, it has clear single ownership of the stream.  Such
+//! locals are rewritten to `Option<BufReader<File>>` or `Option<BufWriter<File>>`, with `None` in
+//! place of a null `FILE*`.  `fclose` becomes `take()`, so the file is closed at the same point.
+//!
+//! Any other use of the local, such as passing it to another function, storing it, or assigning
+//! to it, prevents it from being rewritten.
+
+use log::debug;
+use rustc_ast::LitKind;
+use rustc_hir as hir;
+use rustc_hir::def::{Namespace, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{BindingAnnotation, Expr, ExprKind, HirId, Local, PatKind};
+use rustc_middle::hir::nested_filter;
+use rustc_middle::ty::print::{FmtPrinter, Print};
+use rustc_middle::ty::{TyCtxt, TyKind, TypeckResults};
+use rustc_span::Span;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Debug, Default)]
+pub struct FileIoInfo {
+    /// Functions with at least one `FILE*` local that will be rewritten.  These are excluded from
+    /// the normal rewrites and handled entirely by `rewrite::gen_file_io_rewrites`.
+    pub fns: HashSet<DefId>,
+}
+
+/// How a file was opened, according to the `fopen` mode string.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileMode {
+    /// `"r"`: The file is opened with `File::open` and wrapped in a `BufReader`.
+    Read,
+    /// `"w"`: The file is opened with `File::create` and wrapped in a `BufWriter`.
+    Write,
+    /// `"a"`: The file is opened for appending with `OpenOptions` and wrapped in a `BufWriter`.
+    Append,
+}
+
+impl FileMode {
+    fn from_mode_str(mode: &str) -> Option<FileMode> {
+        Some(match mode {
+            "r" | "rb" => FileMode::Read,
+            "w" | "wb" => FileMode::Write,
+            "a" | "ab" => FileMode::Append,
+            _ => return None,
+        })
+    }
+
+    pub fn is_write(self) -> bool {
+        matches!(self, FileMode::Write | FileMode::Append)
+    }
+}
+
+/// A single use of a rewritten `FILE*` local.
+#[derive(Clone, Debug)]
+pub enum FileUse {
+    /// The local's declaration, as in `let mut f: *mut FILE = fopen(path, mode)`.  `ty` is the
+    /// type annotation, if there is one, and `init` is the `fopen` call.  If the path is a byte
+    /// string literal, `path_lit` holds its contents without the NUL terminator.
+    Open {
+        ty: Option<Span>,
+        init: Span,
+        path: Span,
+        path_lit: Option<String>,
+        mode: FileMode,
+    },
+    /// A null check, as in `f.is_null()`.  `var` is the path naming the local.
+    IsNull { expr: Span, var: Span },
+    /// A call to `fread(ptr, size, count, f)`, or to `fwrite` if `write` is set.  `ret_ty` is
+    /// the printed return type of the call, which the number of items is cast to.
+    ReadWrite {
+        call: Span,
+        ptr: Span,
+        size: Span,
+        count: Span,
+        var: Span,
+        write: bool,
+        ret_ty: String,
+    },
+    /// A call to `fclose(f)`.
+    Close {
+        call: Span,
+        var: Span,
+        mode: FileMode,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum StdioFn {
+    Fopen,
+    Fread,
+    Fwrite,
+    Fclose,
+}
+
+fn strip_casts<'tcx>(mut ex: &'tcx Expr<'tcx>) -> &'tcx Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+/// If `ex` is a NUL-terminated byte string literal, possibly cast to a pointer, return its
+/// contents without the terminator.
+fn c_str_literal(ex: &Expr) -> Option<String> {
+    let bytes = match strip_casts(ex).kind {
+        ExprKind::Lit(ref lit) => match lit.node {
+            LitKind::ByteStr(ref bytes) => bytes.clone(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let (&last, rest) = bytes.split_last()?;
+    if last != 0 || rest.contains(&0) {
+        return None;
+    }
+    String::from_utf8(rest.to_vec()).ok()
+}
+
+/// If `ex` is a path to a local variable, return the variable's `HirId`.
+fn local_var(ex: &Expr) -> Option<HirId> {
+    match ex.kind {
+        ExprKind::Path(hir::QPath::Resolved(_, path)) => match path.res {
+            Res::Local(hir_id) => Some(hir_id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Collects the uses of `FILE*` locals within a body.
+struct FileUseVisitor<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    /// Locals initialized by `fopen`, with the mode of each.
+    locals: HashMap<HirId, FileMode>,
+    /// Uses of the `locals`, along with the local each one belongs to.
+    uses: Vec<(HirId, FileUse)>,
+    /// Paths to the `locals` that were already handled as part of an enclosing expression.
+    handled: HashSet<HirId>,
+    /// Locals with a use that can't be rewritten.
+    rejected: HashSet<HirId>,
+}
+
+impl<'tcx> FileUseVisitor<'tcx> {
+    /// If `ex` is a call to one of the stdio functions we recognize, return which one, along with
+    /// the arguments.
+    fn stdio_call(&self, ex: &'tcx Expr<'tcx>) -> Option<(StdioFn, &'tcx [Expr<'tcx>])> {
+        let (func, args) = match ex.kind {
+            ExprKind::Call(func, args) => (func, args),
+            _ => return None,
+        };
+        if ex.span.from_expansion() {
+            return None;
+        }
+        let did = match *self.typeck_results.expr_ty(func).kind() {
+            TyKind::FnDef(did, _) if self.tcx.is_foreign_item(did) => did,
+            _ => return None,
+        };
+        let which = match self.tcx.item_name(did).as_str() {
+            "fopen" => StdioFn::Fopen,
+            "fread" => StdioFn::Fread,
+            "fwrite" => StdioFn::Fwrite,
+            "fclose" => StdioFn::Fclose,
+            _ => return None,
+        };
+        Some((which, args))
+    }
+
+    /// If `ex` is a path to one of the `locals`, return the local and its mode.
+    fn file_local(&self, ex: &Expr) -> Option<(HirId, FileMode)> {
+        let hir_id = local_var(ex)?;
+        self.locals.get(&hir_id).map(|&mode| (hir_id, mode))
+    }
+
+    fn visit_stdio_call(&mut self, ex: &'tcx Expr<'tcx>, which: StdioFn, args: &'tcx [Expr<'tcx>]) {
+        match (which, args) {
+            (StdioFn::Fread | StdioFn::Fwrite, [ptr, size, count, stream]) => {
+                let (local, mode) = match self.file_local(stream) {
+                    Some(x) => x,
+                    None => return,
+                };
+                self.handled.insert(stream.hir_id);
+                let write = which == StdioFn::Fwrite;
+                let printer = FmtPrinter::new(self.tcx, Namespace::TypeNS);
+                let ret_ty = self.typeck_results.expr_ty(ex).print(printer).unwrap();
+                if mode.is_write() != write {
+                    debug!("{local:?}: {which:?} at {:?} doesn't match mode", ex.span);
+                    self.rejected.insert(local);
+                    return;
+                }
+                self.uses.push((
+                    local,
+                    FileUse::ReadWrite {
+                        call: ex.span,
+                        ptr: ptr.span,
+                        size: size.span,
+                        count: count.span,
+                        var: stream.span,
+                        write,
+                        ret_ty: ret_ty.into_buffer(),
+                    },
+                ));
+            }
+            (StdioFn::Fclose, [stream]) => {
+                if let Some((local, mode)) = self.file_local(stream) {
+                    self.handled.insert(stream.hir_id);
+                    self.uses.push((
+                        local,
+                        FileUse::Close {
+                            call: ex.span,
+                            var: stream.span,
+                            mode,
+                        },
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'tcx> Visitor<'tcx> for FileUseVisitor<'tcx> {
+    // A use inside a closure must also be seen, so that it can reject the local.
+    type NestedFilter = nested_filter::OnlyBodies;
+
+    fn nested_visit_map(&mut self) -> Self::Map {
+        self.tcx.hir()
+    }
+
+    fn visit_local(&mut self, local: &'tcx Local<'tcx>) {
+        let init = match local.init {
+            Some(x) => x,
+            None => return intravisit::walk_local(self, local),
+        };
+        if let PatKind::Binding(BindingAnnotation::Mutable, hir_id, _, None) = local.pat.kind {
+            if let Some((StdioFn::Fopen, [path, mode])) = self.stdio_call(init) {
+                if let Some(mode) = c_str_literal(mode).and_then(|m| FileMode::from_mode_str(&m)) {
+                    self.locals.insert(hir_id, mode);
+                    self.uses.push((
+                        hir_id,
+                        FileUse::Open {
+                            ty: local.ty.map(|ty| ty.span),
+                            init: init.span,
+                            path: path.span,
+                            path_lit: c_str_literal(path),
+                            mode,
+                        },
+                    ));
+                }
+            }
+        }
+        intravisit::walk_local(self, local);
+    }
+
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        if let Some((which, args)) = self.stdio_call(ex) {
+            self.visit_stdio_call(ex, which, args);
+        }
+        match ex.kind {
+            ExprKind::MethodCall(ps, [recv], _) if ps.ident.as_str() == "is_null" => {
+                if let Some((local, _)) = self.file_local(recv) {
+                    self.handled.insert(recv.hir_id);
+                    self.uses.push((
+                        local,
+                        FileUse::IsNull {
+                            expr: ex.span,
+                            var: recv.span,
+                        },
+                    ));
+                }
+            }
+            ExprKind::Path(_) if !self.handled.contains(&ex.hir_id) => {
+                if let Some((local, _)) = self.file_local(ex) {
+                    debug!("{local:?}: unsupported use at {:?}", ex.span);
+                    self.rejected.insert(local);
+                }
+            }
+            _ => {}
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Collect the uses of the `FILE*` locals in the body of `ldid` that can be rewritten.
+pub fn collect_file_uses(tcx: TyCtxt, ldid: LocalDefId) -> Vec<FileUse> {
+    let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+    let mut v = FileUseVisitor {
+        tcx,
+        typeck_results: tcx.typeck_body(hir_body_id),
+        locals: HashMap::new(),
+        uses: Vec::new(),
+        handled: HashSet::new(),
+        rejected: HashSet::new(),
+    };
+    v.visit_body(tcx.hir().body(hir_body_id));
+    let rejected = v.rejected;
+    v.uses
+        .into_iter()
+        .filter(|(local, _)| !rejected.contains(local))
+        .map(|(_, u)| u)
+        .collect()
+}
+
+/// Find the functions with `FILE*` locals that can be rewritten to `BufReader` or `BufWriter`.
+pub fn find_file_io_fns(tcx: TyCtxt, all_fn_ldids: &[LocalDefId]) -> FileIoInfo {
+    let mut fns = HashSet::new();
+    for &ldid in all_fn_ldids {
+        if !collect_file_uses(tcx, ldid).is_empty() {
+            fns.insert(ldid.to_def_id());
+        }
+    }
+    FileIoInfo { fns }
+}
@@ -0,0 +1,435 @@
+//! Detection of structs that end in a flexible array member.
+//!
+//! C99 allows the last field of a struct to be an array of unspecified length, with the actual
+//! length chosen when the struct is allocated.  c2rust translates the tail as a zero-length array,
+//! and each allocation as a `malloc` of the struct size plus room for the elements:
+//!
+//! ```ignore
+//! pub struct packet {
+//!     pub len: size_t,
+//!     pub data: [u8; 0],
+//! }
+//!
+//! let p = malloc(size_of::<packet>() + n * size_of::<u8>()) as *mut packet;
+//! *(*p).data.as_mut_ptr().offset(i as isize) = x;
+//! ```
+//!
+//! None of the tail's elements are in bounds as far as Rust is concerned, so the normal rewrites
+//! can't make these accesses safe.  Instead, the tail becomes a trailing `Vec<T>`, and each
+//! allocation builds the struct with `Box::new` and then resizes the `Vec` to the number of
+//! elements that the `malloc` made room for.  Element accesses index into the `Vec`, and `free`
+//! turns the pointer back into a `Box` to drop it.
+//!
+//! Only simple uses of the tail and of pointers to the struct are supported: any other use, such
+//! as taking the address of the whole tail, an allocation whose size we can't split into the
+//! struct size and an element count, or a `realloc`, prevents the struct from being rewritten.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::rewrite;
+use crate::util::{self, Callee};
+use log::debug;
+use rustc_hir::def::DefKind;
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{BinOpKind, Expr, ExprKind, HirId, UnOp};
+use rustc_middle::ty::{ParamEnv, Ty, TyCtxt, TyKind, TypeckResults};
+use rustc_span::Span;
+use std::collections::{HashMap, HashSet};
+
+/// A single use of a struct with a flexible array member, or of its tail.
+#[derive(Clone, Copy, Debug)]
+pub enum FlexArrayUse<'tcx> {
+    /// An element of the tail is accessed, as in `*(*p).data.as_mut_ptr().offset(i)`.  `tail` is
+    /// the field expression `(*p).data`, and `index` is `i`, or `None` for
+    /// `*(*p).data.as_mut_ptr()`.
+    Elem {
+        expr: Span,
+        tail: Span,
+        index: Option<Span>,
+    },
+    /// A new struct is allocated, as in `malloc(size_of::<S>() + n * size_of::<T>()) as *mut S`.
+    /// `expr` is the whole cast expression, and `count` is `n`, or `None` if the allocation has no
+    /// room for elements.
+    Alloc {
+        expr: Span,
+        count: Option<Span>,
+        adt_ty: Ty<'tcx>,
+    },
+    /// The struct is freed, as in `free(p as *mut c_void)`.
+    Free { call: Span, ptr: Span },
+    /// Any other use of the tail, or any other allocation or reallocation of the struct.  This
+    /// prevents the struct from being rewritten.
+    Other,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FlexArrayInfo {
+    /// Structs with a flexible array member that will be rewritten, mapped to the tail field.
+    pub structs: HashMap<DefId, DefId>,
+    /// Functions that allocate or free any of the `structs` or access their tails.  These are
+    /// excluded from the normal rewrites and handled entirely by
+    /// `rewrite::gen_flex_array_rewrites`.
+    pub fns: HashSet<DefId>,
+}
+
+/// Visitor that finds all uses of the `structs` and their tails within a body.
+pub struct FlexArrayUseVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    structs: &'a HashMap<DefId, DefId>,
+    pub uses: Vec<(DefId, FlexArrayUse<'tcx>)>,
+    /// Tail field expressions that were already handled as part of an enclosing expression.
+    handled: HashSet<HirId>,
+}
+
+impl<'a, 'tcx> FlexArrayUseVisitor<'a, 'tcx> {
+    pub fn new(
+        tcx: TyCtxt<'tcx>,
+        typeck_results: &'tcx TypeckResults<'tcx>,
+        structs: &'a HashMap<DefId, DefId>,
+    ) -> FlexArrayUseVisitor<'a, 'tcx> {
+        FlexArrayUseVisitor {
+            tcx,
+            typeck_results,
+            structs,
+            uses: Vec::new(),
+            handled: HashSet::new(),
+        }
+    }
+
+    /// If `ty` is a raw pointer to one of the `structs`, return the struct's `DefId`.
+    fn flex_adt(&self, ty: Ty<'tcx>) -> Option<DefId> {
+        match *ty.kind() {
+            TyKind::RawPtr(tm) => match *tm.ty.kind() {
+                TyKind::Adt(adt_def, _) if self.structs.contains_key(&adt_def.did()) => {
+                    Some(adt_def.did())
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// If `ex` is an access to the tail of one of the `structs`, as in `(*p).data`, return the
+    /// struct's `DefId`.
+    fn tail_field(&self, ex: &Expr<'tcx>) -> Option<DefId> {
+        let base = match ex.kind {
+            ExprKind::Field(base, _) => base,
+            _ => return None,
+        };
+        let adt_def = match *self
+            .typeck_results
+            .expr_ty_adjusted(base)
+            .peel_refs()
+            .kind()
+        {
+            TyKind::Adt(adt_def, _) => adt_def,
+            _ => return None,
+        };
+        let &tail = self.structs.get(&adt_def.did())?;
+        let idx = *self.typeck_results.field_indices().get(ex.hir_id)?;
+        (adt_def.non_enum_variant().fields.get(idx)?.did == tail).then_some(adt_def.did())
+    }
+
+    /// If `ex` is a pointer to an element of a tail, as in `(*p).data.as_mut_ptr().offset(i)`,
+    /// return the struct, the tail field expression, and the index, if any.
+    fn elem_ptr(
+        &self,
+        ex: &'tcx Expr<'tcx>,
+    ) -> Option<(DefId, &'tcx Expr<'tcx>, Option<&'tcx Expr<'tcx>>)> {
+        let (base, index) = match ex.kind {
+            ExprKind::MethodCall(seg, [base, index], _)
+                if matches!(seg.ident.as_str(), "offset" | "add") =>
+            {
+                (base, Some(index))
+            }
+            _ => (ex, None),
+        };
+        let tail = match base.kind {
+            ExprKind::MethodCall(seg, [tail], _)
+                if matches!(seg.ident.as_str(), "as_ptr" | "as_mut_ptr") =>
+            {
+                tail
+            }
+            _ => return None,
+        };
+        let adt = self.tail_field(tail)?;
+        Some((adt, tail, index))
+    }
+
+    /// If `ex` is a call, return the callee according to `util::ty_callee`.
+    fn callee(&self, ex: &Expr<'tcx>) -> Option<Callee<'tcx>> {
+        match ex.kind {
+            ExprKind::Call(func, _) => {
+                Some(util::ty_callee(self.tcx, self.typeck_results.expr_ty(func)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Check whether `ex` is `size_of::<T>()`, possibly with casts.
+    fn is_size_of(&self, ex: &Expr<'tcx>, ty: Ty<'tcx>) -> bool {
+        matches!(self.callee(peel_casts(ex)), Some(Callee::SizeOf { ty: t }) if t == ty)
+    }
+
+    /// Split the size passed to `malloc` into the element count of the tail.  The size must have
+    /// the form `size_of::<S>() + n * size_of::<T>()`, in which case this returns `Some(Some(n))`,
+    /// or just `size_of::<S>()`, in which case this returns `Some(None)`.
+    fn alloc_count(
+        &self,
+        size: &'tcx Expr<'tcx>,
+        adt_ty: Ty<'tcx>,
+        elem_ty: Ty<'tcx>,
+    ) -> Option<Option<&'tcx Expr<'tcx>>> {
+        if self.is_size_of(size, adt_ty) {
+            return Some(None);
+        }
+        let (a, b) = binary_operands(peel_casts(size), BinOpKind::Add, "wrapping_add")?;
+        let product = if self.is_size_of(a, adt_ty) {
+            b
+        } else if self.is_size_of(b, adt_ty) {
+            a
+        } else {
+            return None;
+        };
+        let (x, y) = binary_operands(peel_casts(product), BinOpKind::Mul, "wrapping_mul")?;
+        if self.is_size_of(y, elem_ty) {
+            Some(Some(peel_casts(x)))
+        } else if self.is_size_of(x, elem_ty) {
+            Some(Some(peel_casts(y)))
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for FlexArrayUseVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            // `(*p).data`
+            ExprKind::Field(..) => {
+                if let Some(adt) = self.tail_field(ex) {
+                    if !self.handled.contains(&ex.hir_id) {
+                        debug!("unsupported use of flexible array member at {:?}", ex.span);
+                        self.uses.push((adt, FlexArrayUse::Other));
+                    }
+                }
+            }
+
+            // `(*p).data[i]` is already valid for a `Vec` tail.
+            ExprKind::Index(base, _) => {
+                if self.tail_field(base).is_some() {
+                    self.handled.insert(base.hir_id);
+                }
+            }
+
+            // `*(*p).data.as_mut_ptr().offset(i)`
+            ExprKind::Unary(UnOp::Deref, inner) => {
+                if let Some((adt, tail, index)) = self.elem_ptr(inner) {
+                    self.handled.insert(tail.hir_id);
+                    self.uses.push((
+                        adt,
+                        FlexArrayUse::Elem {
+                            expr: ex.span,
+                            tail: tail.span,
+                            index: index.map(|i| i.span),
+                        },
+                    ));
+                }
+            }
+
+            // `malloc(size_of::<S>() + n * size_of::<T>()) as *mut S`
+            ExprKind::Cast(inner, _) => {
+                let ty = self.typeck_results.expr_ty(ex);
+                if let Some(adt) = self.flex_adt(ty) {
+                    if let ExprKind::Call(_, [size]) = inner.kind {
+                        if let Some(Callee::Malloc) = self.callee(inner) {
+                            let adt_ty = self.tcx.type_of(adt);
+                            let elem_ty = tail_elem_ty(self.tcx, self.structs[&adt]);
+                            match self.alloc_count(size, adt_ty, elem_ty) {
+                                Some(count) => {
+                                    self.uses.push((
+                                        adt,
+                                        FlexArrayUse::Alloc {
+                                            expr: ex.span,
+                                            count: count.map(|c| c.span),
+                                            adt_ty,
+                                        },
+                                    ));
+                                    if let Some(count) = count {
+                                        self.visit_expr(count);
+                                    }
+                                    return;
+                                }
+                                None => {
+                                    debug!(
+                                        "unsupported flexible array allocation at {:?}",
+                                        ex.span
+                                    );
+                                    self.uses.push((adt, FlexArrayUse::Other));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            ExprKind::Call(_, args) => match self.callee(ex) {
+                // `free(p as *mut c_void)`
+                Some(Callee::Free) => {
+                    if let [arg] = args {
+                        if let ExprKind::Cast(ptr, _) = arg.kind {
+                            if let Some(adt) = self.flex_adt(self.typeck_results.expr_ty(ptr)) {
+                                self.uses.push((
+                                    adt,
+                                    FlexArrayUse::Free {
+                                        call: ex.span,
+                                        ptr: ptr.span,
+                                    },
+                                ));
+                                self.visit_expr(ptr);
+                                return;
+                            }
+                        }
+                    }
+                }
+                // `calloc` and `realloc` of the struct aren't supported.
+                Some(Callee::Calloc | Callee::Realloc) => {
+                    if let Some(adt) = args
+                        .iter()
+                        .find_map(|arg| self.flex_adt(self.typeck_results.expr_ty(peel_casts(arg))))
+                    {
+                        self.uses.push((adt, FlexArrayUse::Other));
+                    }
+                }
+                _ => {}
+            },
+
+            _ => {}
+        }
+
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+fn peel_casts<'a, 'tcx>(mut ex: &'a Expr<'tcx>) -> &'a Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+/// If `ex` is `a op b` or `a.method(b)`, return `a` and `b`.
+fn binary_operands<'tcx>(
+    ex: &'tcx Expr<'tcx>,
+    op: BinOpKind,
+    method: &str,
+) -> Option<(&'tcx Expr<'tcx>, &'tcx Expr<'tcx>)> {
+    match ex.kind {
+        ExprKind::Binary(bin_op, a, b) if bin_op.node == op => Some((a, b)),
+        ExprKind::MethodCall(seg, [a, b], _) if seg.ident.as_str() == method => Some((a, b)),
+        _ => None,
+    }
+}
+
+/// Get the element type of the tail field `field_did`.
+pub fn tail_elem_ty<'tcx>(tcx: TyCtxt<'tcx>, field_did: DefId) -> Ty<'tcx> {
+    match *tcx.type_of(field_did).kind() {
+        TyKind::Array(elem_ty, _) => elem_ty,
+        ref kind => unreachable!("flexible array member should be an array, but got {kind:?}"),
+    }
+}
+
+/// If the last field of `adt_did` is a zero-length array, return that field.
+fn flex_tail(tcx: TyCtxt, adt_did: DefId) -> Option<DefId> {
+    let field = tcx.adt_def(adt_did).non_enum_variant().fields.last()?;
+    match *tcx.type_of(field.did).kind() {
+        TyKind::Array(_, len) if len.try_eval_usize(tcx, ParamEnv::reveal_all()) == Some(0) => {
+            Some(field.did)
+        }
+        _ => None,
+    }
+}
+
+/// Run `FlexArrayUseVisitor` over the body of `ldid`.
+pub fn collect_flex_array_uses<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ldid: LocalDefId,
+    structs: &HashMap<DefId, DefId>,
+) -> Vec<(DefId, FlexArrayUse<'tcx>)> {
+    let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+    let mut v = FlexArrayUseVisitor::new(tcx, tcx.typeck_body(hir_body_id), structs);
+    v.visit_body(tcx.hir().body(hir_body_id));
+    v.uses
+}
+
+/// Find structs with a flexible array member whose tail can be rewritten to a `Vec<T>`.
+pub fn find_flex_array_structs(
+    gacx: &GlobalAnalysisCtxt,
+    all_fn_ldids: &[LocalDefId],
+) -> FlexArrayInfo {
+    let tcx = gacx.tcx;
+
+    // Candidates are local structs ending in a zero-length array that don't cross an FFI
+    // boundary.  Allocations zero-initialize every field but the tail, so those fields must
+    // support it.
+    let mut candidates = HashMap::new();
+    for &adt_did in &gacx.adt_metadata.struct_dids {
+        if !adt_did.is_local() || gacx.foreign_mentioned_tys.contains(&adt_did) {
+            continue;
+        }
+        if !matches!(tcx.def_kind(adt_did), DefKind::Struct) {
+            continue;
+        }
+        let tail = match flex_tail(tcx, adt_did) {
+            Some(x) => x,
+            None => continue,
+        };
+        let zeroizable = tcx
+            .adt_def(adt_did)
+            .all_fields()
+            .all(|f| f.did == tail || rewrite::gen_zeroize_expr(tcx, tcx.type_of(f.did)).is_some());
+        if !zeroizable || rewrite::gen_zeroize_expr(tcx, tail_elem_ty(tcx, tail)).is_none() {
+            continue;
+        }
+        candidates.insert(adt_did, tail);
+    }
+    if candidates.is_empty() {
+        return FlexArrayInfo::default();
+    }
+
+    let mut ok = candidates
+        .keys()
+        .map(|&did| (did, true))
+        .collect::<HashMap<_, _>>();
+    let mut fn_adts = HashMap::new();
+    for &ldid in all_fn_ldids {
+        let mut adts = HashSet::new();
+        for (did, u) in collect_flex_array_uses(tcx, ldid, &candidates) {
+            if let FlexArrayUse::Other = u {
+                ok.insert(did, false);
+            }
+            adts.insert(did);
+        }
+        if !adts.is_empty() {
+            fn_adts.insert(ldid, adts);
+        }
+    }
+
+    let mut info = FlexArrayInfo::default();
+    for (did, tail) in candidates {
+        if ok[&did] {
+            debug!("found flexible array struct {did:?}, with tail {tail:?}");
+            info.structs.insert(did, tail);
+        }
+    }
+    for (ldid, adts) in fn_adts {
+        if adts.iter().any(|did| info.structs.contains_key(did)) {
+            info.fns.insert(ldid.to_def_id());
+        }
+    }
+    info
+}
@@ -0,0 +1,183 @@
+//! Per-function caching of analysis results between runs.
+//!
+//! When `C2RUST_ANALYZE_CACHE_DIR` is set, the dataflow constraints generated for each function
+//! and the permissions computed for it by dataflow propagation and borrowck are saved in that
+//! directory.  On later runs, a function whose inputs are unchanged reuses the saved results
+//! instead of recomputing them.
+//!
+//! Entries are keyed on a hash of everything that goes into the computation, so a stale entry is
+//! never used.  For constraints, that's the function's MIR and local types, along with a
+//! fingerprint of the crate's global types and the `C2RUST_ANALYZE_*` options.  For permissions,
+//! that's the constraints along with the permissions of every pointer on entry.  Editing one
+//! function invalidates its own entries and those of functions whose inputs it affects, such as
+//! its callers, but leaves the rest alone.  Spans are left out of the keys, since they don't
+//! affect the results, so edits that only move a function within its file don't invalidate it.
+
+use crate::context::{AnalysisCtxt, GlobalAnalysisCtxt, PermissionSet};
+use crate::dataflow::DataflowConstraints;
+use crate::pointee_type::PointeeTypes;
+use crate::pointer_id::{GlobalPointerTable, PointerId, PointerTable};
+use crate::util::{bytes_to_hex_string, sha256_hash};
+use rustc_middle::mir::Body;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Get the cache directory, if caching is enabled.
+pub fn cache_dir() -> Option<PathBuf> {
+    env::var_os("C2RUST_ANALYZE_CACHE_DIR").map(PathBuf::from)
+}
+
+/// Compute a fingerprint of the crate-wide inputs to constraint generation: the labeled types of
+/// all items, the targets of function table fields, the known functions and upstream crate facts
+/// that decide how calls are handled, the global pointee types, and the options the analysis was
+/// run with.
+pub fn crate_fingerprint<'tcx>(
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    global_pointee_types: &GlobalPointerTable<PointeeTypes<'tcx>>,
+) -> String {
+    let mut parts = Vec::new();
+    for (did, lsig) in &gacx.fn_sigs {
+        parts.push(format!("sig {did:?} {lsig:?}"));
+    }
+    for (did, lty) in &gacx.field_ltys {
+        parts.push(format!("field {did:?} {lty:?}"));
+    }
+    for (did, lty) in &gacx.static_tys {
+        parts.push(format!("static {did:?} {lty:?}"));
+    }
+    for (did, ptr) in &gacx.addr_of_static {
+        parts.push(format!("addr_of_static {did:?} {ptr:?}"));
+    }
+    for (did, field) in &gacx.fn_table_fields {
+        parts.push(format!("fn_table {did:?} {field:?}"));
+    }
+    for &did in gacx.fn_sigs.keys() {
+        if let Some(known_fn) = gacx.known_fn(did) {
+            parts.push(format!("known_fn {did:?} {known_fn:?}"));
+        }
+    }
+    for (did, facts) in &gacx.upstream_fn_facts {
+        parts.push(format!("upstream {did:?} {facts:?}"));
+    }
+    for (var, val) in env::vars() {
+        if var.starts_with("C2RUST_ANALYZE_") && var != "C2RUST_ANALYZE_CACHE_DIR" {
+            parts.push(format!("env {var}={val}"));
+        }
+    }
+    // The maps above are unordered.
+    parts.sort();
+    parts.extend(
+        global_pointee_types
+            .iter()
+            .map(|(ptr, tys)| format!("{ptr:?} {}", pointee_types_text(tys))),
+    );
+    parts.push(env!("CARGO_PKG_VERSION").to_owned());
+    bytes_to_hex_string(&sha256_hash(&parts))
+}
+
+/// Describe `tys` for hashing.  The `sources` spans are left out, and the types are sorted,
+/// since `ltys` is unordered.
+fn pointee_types_text(tys: &PointeeTypes) -> String {
+    let mut ltys = tys
+        .ltys
+        .iter()
+        .map(|lty| format!("{lty:?}"))
+        .collect::<Vec<_>>();
+    ltys.sort();
+    format!("{ltys:?} {} {:?}", tys.incomplete, tys.resolution)
+}
+
+/// Describe `mir` for hashing: the types of its locals and the statements and terminators of
+/// its blocks.  Spans, scopes, and debug info are left out.
+fn mir_text(mir: &Body) -> Vec<String> {
+    let mut parts = vec![format!("args {}", mir.arg_count)];
+    for (local, decl) in mir.local_decls.iter_enumerated() {
+        parts.push(format!("{local:?}: {:?} {:?}", decl.mutability, decl.ty));
+    }
+    for (bb, data) in mir.basic_blocks().iter_enumerated() {
+        parts.push(format!("{bb:?} cleanup={}", data.is_cleanup));
+        for stmt in &data.statements {
+            parts.push(format!("    {stmt:?}"));
+        }
+        parts.push(format!("    {:?}", data.terminator().kind));
+    }
+    parts
+}
+
+/// Compute the cache key for the dataflow constraints of the function `mir`.
+pub fn constraints_key<'tcx>(
+    crate_fingerprint: &str,
+    acx: &AnalysisCtxt<'_, 'tcx>,
+    mir: &Body<'tcx>,
+    pointee_types: PointerTable<PointeeTypes<'tcx>>,
+) -> String {
+    let local_pointee_types = pointee_types
+        .local()
+        .iter()
+        .map(|(ptr, tys)| format!("{ptr:?} {}", pointee_types_text(tys)))
+        .collect::<Vec<_>>();
+    bytes_to_hex_string(&sha256_hash(&(
+        crate_fingerprint,
+        mir_text(mir),
+        format!("{:?}", acx.local_tys),
+        format!("{:?}", acx.addr_of_local),
+        local_pointee_types,
+    )))
+}
+
+/// Compute the cache key for the permissions computed for a function, given its constraints and
+/// the permissions and update masks of all pointers on entry.
+pub fn perms_key(
+    constraints_key: &str,
+    dataflow: &DataflowConstraints,
+    perms: PointerTable<PermissionSet>,
+    updates_forbidden: PointerTable<PermissionSet>,
+) -> String {
+    let perms = perms.iter().map(|(_, &p)| p).collect::<Vec<_>>();
+    let updates_forbidden = updates_forbidden
+        .iter()
+        .map(|(_, &p)| p)
+        .collect::<Vec<_>>();
+    bytes_to_hex_string(&sha256_hash(&(
+        constraints_key,
+        format!("{dataflow:?}"),
+        perms,
+        updates_forbidden,
+    )))
+}
+
+/// The cached dataflow constraints for a function, as returned by
+/// `dataflow::generate_constraints`.
+pub type CachedConstraints = (DataflowConstraints, Vec<(PointerId, PointerId)>);
+
+/// The cached result of propagating permissions through a function: every pointer whose
+/// permissions changed, along with its new permissions.
+pub type CachedPerms = Vec<(PointerId, PermissionSet)>;
+
+fn entry_path(dir: &Path, kind: &str, key: &str) -> PathBuf {
+    dir.join(kind).join(key)
+}
+
+/// Load the entry of the given `kind` for `key`, if one exists.
+pub fn load<T: DeserializeOwned>(dir: &Path, kind: &str, key: &str) -> Option<T> {
+    let path = entry_path(dir, kind, key);
+    let f = BufReader::new(File::open(&path).ok()?);
+    match bincode::deserialize_from(f) {
+        Ok(x) => Some(x),
+        Err(e) => {
+            log::warn!("failed to parse cache file {path:?}: {e}");
+            None
+        }
+    }
+}
+
+/// Save `x` as the entry of the given `kind` for `key`.
+pub fn save<T: Serialize>(dir: &Path, kind: &str, key: &str, x: &T) -> Result<(), bincode::Error> {
+    fs::create_dir_all(dir.join(kind))?;
+    let f = BufWriter::new(File::create(entry_path(dir, kind, key))?);
+    bincode::serialize_into(f, x)
+}
@@ -0,0 +1,442 @@
+//! Detection of `void*` struct fields that are used to hold C function pointers.
+//!
+//! Code translated from C sometimes stores a nullable function pointer in a `void*` field, using
+//! `transmute` to convert back to a function pointer whenever the field is called:
+//!
+//! ```ignore
+//! s.cb = f as *mut c_void;
+//! if !s.cb.is_null() {
+//!     transmute::<*mut c_void, unsafe extern "C" fn(i32)>(s.cb)(1);
+//! }
+//! ```
+//!
+//! If every use of such a field fits one of these patterns and all uses agree on the function
+//! pointer type, the field can be given the type `Option<unsafe extern "C" fn(i32)>` instead.
+//! Stores become `Some(f)` or `None`, the `transmute`s disappear (with an `.expect(..)` if the
+//! caller wanted a non-nullable function pointer), and `is_null()` becomes `is_none()`.
+
+use crate::context::{DontRewriteFieldReason, GlobalAnalysisCtxt};
+use crate::util;
+use log::debug;
+use rustc_ast::LitKind;
+use rustc_hir::def::Res;
+use rustc_hir::def_id::DefId;
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{Expr, ExprKind, HirId, Pat, PatKind, Unsafety};
+use rustc_middle::ty::{self, Ty, TyCtxt, TyKind, TypeckResults};
+use rustc_span::{sym, Span, Symbol};
+use std::collections::{HashMap, HashSet};
+
+/// A single use of a function pointer field.
+#[derive(Clone, Copy, Debug)]
+pub enum FnPtrFieldUse<'tcx> {
+    /// A function is stored into the field, as in `s.f = g as *mut c_void` or
+    /// `S { f: transmute::<Option<F>, *mut c_void>(g) }`.  `value` is the whole expression being
+    /// stored, and `func` is the function-typed subexpression.
+    StoreFn {
+        value: Span,
+        func: Span,
+        fn_ptr_ty: Ty<'tcx>,
+        /// `func` already has type `Option<F>`, rather than `F`.
+        is_option: bool,
+        /// `func` is a local function that is declared safe.
+        is_safe_local_fn: bool,
+    },
+    /// A null pointer is stored into the field, as in `s.f = ptr::null_mut()`.
+    StoreNull { value: Span },
+    /// The field is converted back to a function pointer, as in `transmute::<_, F>(s.f)`.
+    /// `call` is the `transmute` call, and `field` is the field access expression.
+    Load {
+        call: Span,
+        field: Span,
+        fn_ptr_ty: Ty<'tcx>,
+        /// The `transmute` produces `Option<F>`, rather than `F`.
+        is_option: bool,
+    },
+    /// The field is checked for null, as in `s.f.is_null()`.
+    IsNull { call: Span, field: Span },
+    /// Any other use of the field.  This prevents the field from being rewritten.
+    Other,
+}
+
+/// Visitor that finds all uses of the fields in `candidates` within a body.
+pub struct FnPtrFieldUseVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    candidates: &'a HashSet<DefId>,
+    pub uses: Vec<(DefId, FnPtrFieldUse<'tcx>)>,
+    /// Field access expressions that were already handled as part of an enclosing expression.
+    handled: HashSet<HirId>,
+}
+
+impl<'a, 'tcx> FnPtrFieldUseVisitor<'a, 'tcx> {
+    pub fn new(
+        tcx: TyCtxt<'tcx>,
+        typeck_results: &'tcx TypeckResults<'tcx>,
+        candidates: &'a HashSet<DefId>,
+    ) -> FnPtrFieldUseVisitor<'a, 'tcx> {
+        FnPtrFieldUseVisitor {
+            tcx,
+            typeck_results,
+            candidates,
+            uses: Vec::new(),
+            handled: HashSet::new(),
+        }
+    }
+
+    /// Get the `DefId` of the struct field `idx` of `adt_ty`.
+    fn adt_field_did(&self, adt_ty: Ty<'tcx>, idx: usize) -> Option<DefId> {
+        match *adt_ty.peel_refs().kind() {
+            TyKind::Adt(adt_def, _) if !adt_def.is_enum() => {
+                Some(adt_def.non_enum_variant().fields.get(idx)?.did)
+            }
+            _ => None,
+        }
+    }
+
+    /// If `ex` is an access of one of the `candidates` fields, return the field's `DefId`.
+    fn candidate_field(&self, ex: &Expr<'tcx>) -> Option<DefId> {
+        let base = match ex.kind {
+            ExprKind::Field(base, _) => base,
+            _ => return None,
+        };
+        let idx = *self.typeck_results.field_indices().get(ex.hir_id)?;
+        let did = self.adt_field_did(self.typeck_results.expr_ty_adjusted(base), idx)?;
+        self.candidates.contains(&did).then_some(did)
+    }
+
+    /// If `ex` is a call to a function with diagnostic item name `name`, return its arguments.
+    fn diagnostic_item_call(
+        &self,
+        ex: &'tcx Expr<'tcx>,
+        name: Symbol,
+    ) -> Option<&'tcx [Expr<'tcx>]> {
+        let (func, args) = match ex.kind {
+            ExprKind::Call(func, args) => (func, args),
+            _ => return None,
+        };
+        let qp = match func.kind {
+            ExprKind::Path(ref qp) => qp,
+            _ => return None,
+        };
+        match self.typeck_results.qpath_res(qp, func.hir_id) {
+            Res::Def(_, did) if self.tcx.is_diagnostic_item(name, did) => Some(args),
+            _ => None,
+        }
+    }
+
+    /// Split `ty` into a function pointer type and a flag indicating whether it was wrapped in
+    /// `Option`.
+    fn as_fn_ptr(&self, ty: Ty<'tcx>) -> Option<(Ty<'tcx>, bool)> {
+        match *ty.kind() {
+            TyKind::FnPtr(_) => Some((ty, false)),
+            TyKind::FnDef(..) => Some((self.tcx.mk_fn_ptr(ty.fn_sig(self.tcx)), false)),
+            TyKind::Adt(adt_def, substs)
+                if self.tcx.is_diagnostic_item(sym::Option, adt_def.did()) =>
+            {
+                let inner = substs.type_at(0);
+                matches!(*inner.kind(), TyKind::FnPtr(_)).then_some((inner, true))
+            }
+            _ => None,
+        }
+    }
+
+    /// Classify a value being stored into a function pointer field.
+    fn classify_store(&self, value: &'tcx Expr<'tcx>) -> FnPtrFieldUse<'tcx> {
+        // `g as *mut c_void` or `transmute::<F, *mut c_void>(g)`
+        let func = match value.kind {
+            ExprKind::Cast(inner, _) => Some(inner),
+            _ => self
+                .diagnostic_item_call(value, sym::transmute)
+                .and_then(|args| args.get(0)),
+        };
+        if let Some(func) = func {
+            let func_ty = self.typeck_results.expr_ty(func);
+            if let Some((fn_ptr_ty, is_option)) = self.as_fn_ptr(func_ty) {
+                let is_safe_local_fn = match *func_ty.kind() {
+                    TyKind::FnDef(did, _) => {
+                        did.is_local() && func_ty.fn_sig(self.tcx).unsafety() == Unsafety::Normal
+                    }
+                    _ => false,
+                };
+                return FnPtrFieldUse::StoreFn {
+                    value: value.span,
+                    func: func.span,
+                    fn_ptr_ty,
+                    is_option,
+                    is_safe_local_fn,
+                };
+            }
+        }
+
+        // `ptr::null_mut()` or `0 as *mut c_void`
+        let is_null = self
+            .diagnostic_item_call(value, sym::ptr_null_mut)
+            .or_else(|| self.diagnostic_item_call(value, sym::ptr_null))
+            .is_some()
+            || match value.kind {
+                ExprKind::Cast(inner, _) => match inner.kind {
+                    ExprKind::Lit(ref lit) => matches!(lit.node, LitKind::Int(0, _)),
+                    _ => false,
+                },
+                _ => false,
+            };
+        if is_null {
+            return FnPtrFieldUse::StoreNull { value: value.span };
+        }
+
+        FnPtrFieldUse::Other
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for FnPtrFieldUseVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            // `s.f = value`
+            ExprKind::Assign(lhs, rhs, _) => {
+                if let Some(did) = self.candidate_field(lhs) {
+                    self.handled.insert(lhs.hir_id);
+                    let u = self.classify_store(rhs);
+                    self.uses.push((did, u));
+                }
+            }
+
+            // `S { f: value, .. }`
+            ExprKind::Struct(_, fields, _) => {
+                let adt_ty = self.typeck_results.expr_ty(ex);
+                for field in fields {
+                    let idx = match self.typeck_results.field_indices().get(field.hir_id) {
+                        Some(&x) => x,
+                        None => continue,
+                    };
+                    let did = match self.adt_field_did(adt_ty, idx) {
+                        Some(x) if self.candidates.contains(&x) => x,
+                        _ => continue,
+                    };
+                    let u = self.classify_store(field.expr);
+                    self.uses.push((did, u));
+                }
+            }
+
+            // `transmute::<*mut c_void, F>(s.f)`
+            ExprKind::Call(_, [arg]) => {
+                if let Some(did) = self.candidate_field(arg) {
+                    if self.diagnostic_item_call(ex, sym::transmute).is_some() {
+                        self.handled.insert(arg.hir_id);
+                        let u = match self.as_fn_ptr(self.typeck_results.expr_ty(ex)) {
+                            Some((fn_ptr_ty, is_option)) => FnPtrFieldUse::Load {
+                                call: ex.span,
+                                field: arg.span,
+                                fn_ptr_ty,
+                                is_option,
+                            },
+                            None => FnPtrFieldUse::Other,
+                        };
+                        self.uses.push((did, u));
+                    }
+                }
+            }
+
+            // `s.f.is_null()`
+            ExprKind::MethodCall(seg, [recv], _) => {
+                if let Some(did) = self.candidate_field(recv) {
+                    if seg.ident.as_str() == "is_null" {
+                        self.handled.insert(recv.hir_id);
+                        self.uses.push((
+                            did,
+                            FnPtrFieldUse::IsNull {
+                                call: ex.span,
+                                field: recv.span,
+                            },
+                        ));
+                    }
+                }
+            }
+
+            // Any other field access is an unsupported use.
+            ExprKind::Field(..) => {
+                if let Some(did) = self.candidate_field(ex) {
+                    if !self.handled.contains(&ex.hir_id) {
+                        debug!("unsupported use of fn ptr field {did:?} at {:?}", ex.span);
+                        self.uses.push((did, FnPtrFieldUse::Other));
+                    }
+                }
+            }
+
+            _ => {}
+        }
+
+        intravisit::walk_expr(self, ex);
+    }
+
+    fn visit_pat(&mut self, pat: &'tcx Pat<'tcx>) {
+        // Destructuring patterns read the field without going through any of the supported
+        // expression forms.
+        if let PatKind::Struct(_, fields, _) = pat.kind {
+            let adt_ty = self.typeck_results.pat_ty(pat);
+            for field in fields {
+                let idx = match self.typeck_results.field_indices().get(field.hir_id) {
+                    Some(&x) => x,
+                    None => continue,
+                };
+                if let Some(did) = self.adt_field_did(adt_ty, idx) {
+                    if self.candidates.contains(&did) {
+                        self.uses.push((did, FnPtrFieldUse::Other));
+                    }
+                }
+            }
+        }
+
+        intravisit::walk_pat(self, pat);
+    }
+}
+
+/// Run `FnPtrFieldUseVisitor` over every body in the crate.
+pub fn collect_fn_ptr_field_uses<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    candidates: &HashSet<DefId>,
+) -> Vec<(DefId, FnPtrFieldUse<'tcx>)> {
+    let mut uses = Vec::new();
+    for ldid in tcx.hir().body_owners() {
+        let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+            Some(x) => x,
+            None => continue,
+        };
+        let mut v = FnPtrFieldUseVisitor::new(tcx, tcx.typeck_body(hir_body_id), candidates);
+        v.visit_body(tcx.hir().body(hir_body_id));
+        uses.extend(v.uses);
+    }
+    uses
+}
+
+/// Find `void*` struct fields that are only used to hold function pointers.  Returns the new
+/// function pointer type of each such field.  The field's new type is `Option` of this type.
+pub fn find_fn_ptr_fields<'tcx>(gacx: &GlobalAnalysisCtxt<'tcx>) -> HashMap<DefId, Ty<'tcx>> {
+    let tcx = gacx.tcx;
+
+    let mut candidates = HashSet::new();
+    for &adt_did in &gacx.adt_metadata.struct_dids {
+        if gacx.foreign_mentioned_tys.contains(&adt_did) {
+            continue;
+        }
+        for field in tcx.adt_def(adt_did).all_fields() {
+            let ty = tcx.type_of(field.did);
+            let is_void_ptr =
+                matches!(*ty.kind(), TyKind::RawPtr(tm) if util::is_c_void(tcx, tm.ty));
+            if !is_void_ptr {
+                continue;
+            }
+            // The field's type is replaced wholesale, so it must be written as a pointer rather
+            // than hidden behind a type alias.
+            let is_hir_ptr = match tcx.hir().get_if_local(field.did) {
+                Some(rustc_hir::Node::Field(fd)) => {
+                    matches!(fd.ty.kind, rustc_hir::TyKind::Ptr(_))
+                }
+                _ => false,
+            };
+            if !is_hir_ptr {
+                continue;
+            }
+            if gacx
+                .dont_rewrite_fields
+                .get(field.did)
+                .contains(DontRewriteFieldReason::USER_REQUEST)
+            {
+                continue;
+            }
+            candidates.insert(field.did);
+        }
+    }
+    if candidates.is_empty() {
+        return HashMap::new();
+    }
+
+    #[derive(Default)]
+    struct FieldInfo<'tcx> {
+        fn_ptr_ty: Option<Ty<'tcx>>,
+        any_fn_stored: bool,
+        all_stored_fns_safe: bool,
+        any_option_load: bool,
+        ok: bool,
+    }
+
+    let mut info = HashMap::<DefId, FieldInfo>::new();
+    for (did, u) in collect_fn_ptr_field_uses(tcx, &candidates) {
+        let fi = info.entry(did).or_insert_with(|| FieldInfo {
+            all_stored_fns_safe: true,
+            ok: true,
+            ..FieldInfo::default()
+        });
+        let fn_ptr_ty = match u {
+            FnPtrFieldUse::StoreFn {
+                fn_ptr_ty,
+                is_safe_local_fn,
+                ..
+            } => {
+                fi.any_fn_stored = true;
+                fi.all_stored_fns_safe &= is_safe_local_fn;
+                Some(fn_ptr_ty)
+            }
+            FnPtrFieldUse::Load {
+                fn_ptr_ty,
+                is_option,
+                ..
+            } => {
+                fi.any_option_load |= is_option;
+                Some(fn_ptr_ty)
+            }
+            FnPtrFieldUse::StoreNull { .. } | FnPtrFieldUse::IsNull { .. } => None,
+            FnPtrFieldUse::Other => {
+                fi.ok = false;
+                None
+            }
+        };
+        if let Some(ty) = fn_ptr_ty {
+            // Stored functions may be safe even when the field's function pointer type is
+            // unsafe, so compare the signatures ignoring safety.
+            let ty = unsafe_fn_ptr(tcx, ty);
+            match fi.fn_ptr_ty {
+                None => fi.fn_ptr_ty = Some(ty),
+                Some(old) if old == ty => {}
+                Some(old) => {
+                    debug!("fn ptr field {did:?} is used with both {old:?} and {ty:?}");
+                    fi.ok = false;
+                }
+            }
+        }
+    }
+
+    let mut fn_ptr_fields = HashMap::new();
+    for (did, fi) in info {
+        let fn_ptr_ty = match fi.fn_ptr_ty {
+            Some(x) if fi.ok => x,
+            _ => continue,
+        };
+        // If every function stored into the field is safe, the field can use a safe function
+        // pointer type.  An `Option<unsafe fn()>` result can't be coerced from `Option<fn()>`, so
+        // this requires that every load produces a bare function pointer.
+        let fn_ptr_ty = if fi.any_fn_stored && fi.all_stored_fns_safe && !fi.any_option_load {
+            safe_fn_ptr(tcx, fn_ptr_ty)
+        } else {
+            fn_ptr_ty
+        };
+        debug!("found fn ptr field {did:?}: {fn_ptr_ty:?}");
+        fn_ptr_fields.insert(did, fn_ptr_ty);
+    }
+    fn_ptr_fields
+}
+
+fn with_unsafety<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>, unsafety: Unsafety) -> Ty<'tcx> {
+    let sig = ty
+        .fn_sig(tcx)
+        .map_bound(|sig| ty::FnSig { unsafety, ..sig });
+    tcx.mk_fn_ptr(sig)
+}
+
+fn unsafe_fn_ptr<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Ty<'tcx> {
+    with_unsafety(tcx, ty, Unsafety::Unsafe)
+}
+
+fn safe_fn_ptr<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Ty<'tcx> {
+    with_unsafety(tcx, ty, Unsafety::Normal)
+}
@@ -0,0 +1,445 @@
+//! Analysis of struct fields that hold function pointers, such as the tables of operations that C
+//! code fills in once and then calls through indirectly:
+//!
+//! ```ignore
+//! static FILE_OPS: Ops = Ops {
+//!     read: Some(file_read as unsafe extern "C" fn(*mut File, *mut u8, usize) -> isize),
+//!     close: Some(file_close),
+//! };
+//! ((*ops).read.unwrap())(f, buf, len);
+//! ```
+//!
+//! For each struct field whose type is a function pointer or an `Option` of one, this module
+//! records which local functions are stored into it, either by struct literals (including the
+//! initializers of statics) or by assignments.  If every value stored into the field is one of
+//! these functions or `None`, the field's targets are known, and:
+//!
+//! * The pointers in the field's function pointer type are unified with the corresponding pointers
+//!   in the signature of each target, so the field and all of its targets are rewritten to the
+//!   same signature.  Casts of the targets to the old function pointer type are removed by
+//!   `rewrite::gen_fn_table_rewrites`.
+//! * An indirect call through a function pointer loaded from the field is treated as a call to
+//!   each of the targets, rather than to every address-taken function with a matching signature.
+//!
+//! Fields that also receive other values, such as a function pointer passed in as an argument,
+//! are handled as before.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::pointer_id::PointerId;
+use log::debug;
+use rustc_hir::def::{CtorOf, DefKind, Res};
+use rustc_hir::def_id::DefId;
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{BorrowKind, Expr, ExprKind, Mutability};
+use rustc_middle::mir::{
+    Body, Location, Operand, ProjectionElem, Rvalue, StatementKind, TerminatorKind,
+};
+use rustc_middle::ty::{DefIdTree, Ty, TyCtxt, TyKind, TypeckResults};
+use rustc_span::{sym, Span};
+use std::collections::{HashMap, HashSet};
+
+/// The functions that are stored into a function table field.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FnTableField {
+    /// Local functions stored into the field, sorted and without duplicates.
+    pub targets: Vec<DefId>,
+    /// Every value stored into the field is either one of `targets` or `None`.
+    pub complete: bool,
+}
+
+/// A value stored into a function table field.
+#[derive(Clone, Copy, Debug)]
+pub enum FnTableStore {
+    /// A local function, as in `Some(f)` or `Some(f as unsafe extern "C" fn(i32))`.  `func` is
+    /// the path naming the function, and `cast` is the enclosing cast expression, if there is one.
+    Fn {
+        did: DefId,
+        func: Span,
+        cast: Option<Span>,
+    },
+    /// `None`.
+    None,
+    /// Any other value, or a use of the field that might store another value, such as `&mut s.f`.
+    Other,
+}
+
+/// Visitor that finds all stores into the fields in `candidates` within a body.
+pub struct FnTableStoreVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    candidates: &'a HashSet<DefId>,
+    pub stores: Vec<(DefId, FnTableStore)>,
+}
+
+impl<'a, 'tcx> FnTableStoreVisitor<'a, 'tcx> {
+    pub fn new(
+        tcx: TyCtxt<'tcx>,
+        typeck_results: &'tcx TypeckResults<'tcx>,
+        candidates: &'a HashSet<DefId>,
+    ) -> FnTableStoreVisitor<'a, 'tcx> {
+        FnTableStoreVisitor {
+            tcx,
+            typeck_results,
+            candidates,
+            stores: Vec::new(),
+        }
+    }
+
+    /// Get the `DefId` of the struct field `idx` of `adt_ty`, if it's one of the `candidates`.
+    fn candidate_field_at(&self, adt_ty: Ty<'tcx>, idx: usize) -> Option<DefId> {
+        let did = match *adt_ty.peel_refs().kind() {
+            TyKind::Adt(adt_def, _) if adt_def.is_struct() => {
+                adt_def.non_enum_variant().fields.get(idx)?.did
+            }
+            _ => return None,
+        };
+        self.candidates.contains(&did).then_some(did)
+    }
+
+    /// If `ex` is an access of one of the `candidates` fields, return the field's `DefId`.
+    fn candidate_field(&self, ex: &Expr<'tcx>) -> Option<DefId> {
+        let base = match ex.kind {
+            ExprKind::Field(base, _) => base,
+            _ => return None,
+        };
+        let idx = *self.typeck_results.field_indices().get(ex.hir_id)?;
+        self.candidate_field_at(self.typeck_results.expr_ty_adjusted(base), idx)
+    }
+
+    /// If `ex` names the constructor of an enum variant, return the variant's `DefId`.
+    fn variant_ctor(&self, ex: &Expr<'tcx>) -> Option<DefId> {
+        let qp = match ex.kind {
+            ExprKind::Path(ref qp) => qp,
+            _ => return None,
+        };
+        match self.typeck_results.qpath_res(qp, ex.hir_id) {
+            Res::Def(DefKind::Ctor(CtorOf::Variant, _), ctor_did) => {
+                Some(self.tcx.parent(ctor_did))
+            }
+            _ => None,
+        }
+    }
+
+    /// Classify a value being stored into a function table field.
+    fn classify_store(&self, value: &'tcx Expr<'tcx>) -> FnTableStore {
+        let lang_items = self.tcx.lang_items();
+        let some_variant = lang_items.option_some_variant();
+        let none_variant = lang_items.option_none_variant();
+        match value.kind {
+            // `Some(f)`
+            ExprKind::Call(func, [arg])
+                if some_variant.is_some() && self.variant_ctor(func) == some_variant =>
+            {
+                self.classify_fn(arg)
+            }
+            // `None`
+            ExprKind::Path(_)
+                if none_variant.is_some() && self.variant_ctor(value) == none_variant =>
+            {
+                FnTableStore::None
+            }
+            // `f`, for fields with a bare function pointer type
+            _ => self.classify_fn(value),
+        }
+    }
+
+    /// Classify a function pointer value, which may be a path to a local function with an
+    /// optional cast.
+    fn classify_fn(&self, value: &'tcx Expr<'tcx>) -> FnTableStore {
+        let (func, cast) = match value.kind {
+            ExprKind::Cast(inner, _) => (inner, Some(value.span)),
+            _ => (value, None),
+        };
+        let qp = match func.kind {
+            ExprKind::Path(ref qp) => qp,
+            _ => return FnTableStore::Other,
+        };
+        match self.typeck_results.qpath_res(qp, func.hir_id) {
+            Res::Def(DefKind::Fn | DefKind::AssocFn, did) if did.is_local() => FnTableStore::Fn {
+                did,
+                func: func.span,
+                cast,
+            },
+            _ => FnTableStore::Other,
+        }
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for FnTableStoreVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            // `s.f = value`
+            ExprKind::Assign(lhs, rhs, _) => {
+                if let Some(did) = self.candidate_field(lhs) {
+                    let store = self.classify_store(rhs);
+                    self.stores.push((did, store));
+                }
+            }
+
+            // `S { f: value, .. }`
+            ExprKind::Struct(_, fields, _) => {
+                let adt_ty = self.typeck_results.expr_ty(ex);
+                for field in fields {
+                    let idx = match self.typeck_results.field_indices().get(field.hir_id) {
+                        Some(&x) => x,
+                        None => continue,
+                    };
+                    if let Some(did) = self.candidate_field_at(adt_ty, idx) {
+                        let store = self.classify_store(field.expr);
+                        self.stores.push((did, store));
+                    }
+                }
+            }
+
+            // `&mut s.f` or `addr_of_mut!(s.f)`, which can be used to store anything
+            ExprKind::AddrOf(BorrowKind::Ref | BorrowKind::Raw, Mutability::Mut, inner) => {
+                if let Some(did) = self.candidate_field(inner) {
+                    debug!(
+                        "fn table field {did:?} is borrowed mutably at {:?}",
+                        ex.span
+                    );
+                    self.stores.push((did, FnTableStore::Other));
+                }
+            }
+
+            _ => {}
+        }
+
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// If `ty` is a function pointer type or an `Option` of one, return the function pointer type.
+fn field_fn_ptr_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Option<Ty<'tcx>> {
+    match *ty.kind() {
+        TyKind::FnPtr(_) => Some(ty),
+        TyKind::Adt(adt_def, substs) if tcx.is_diagnostic_item(sym::Option, adt_def.did()) => {
+            let inner = substs.type_at(0);
+            matches!(*inner.kind(), TyKind::FnPtr(_)).then_some(inner)
+        }
+        _ => None,
+    }
+}
+
+/// Run `FnTableStoreVisitor` over every body in the crate, including the initializers of
+/// statics.
+pub fn collect_fn_table_stores(
+    tcx: TyCtxt,
+    candidates: &HashSet<DefId>,
+) -> Vec<(DefId, FnTableStore)> {
+    let mut stores = Vec::new();
+    for ldid in tcx.hir().body_owners() {
+        let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+            Some(x) => x,
+            None => continue,
+        };
+        let mut v = FnTableStoreVisitor::new(tcx, tcx.typeck_body(hir_body_id), candidates);
+        v.visit_body(tcx.hir().body(hir_body_id));
+        stores.extend(v.stores);
+    }
+    stores
+}
+
+/// Find the struct fields with function pointer types, along with the local functions that are
+/// stored into each one.  Fields that are never stored into are omitted.
+pub fn find_fn_table_fields(gacx: &GlobalAnalysisCtxt) -> HashMap<DefId, FnTableField> {
+    let tcx = gacx.tcx;
+
+    let mut candidates = HashSet::new();
+    for &adt_did in &gacx.adt_metadata.struct_dids {
+        let adt_def = tcx.adt_def(adt_did);
+        if !adt_def.is_struct() {
+            continue;
+        }
+        for field in adt_def.all_fields() {
+            if field_fn_ptr_ty(tcx, tcx.type_of(field.did)).is_some() {
+                candidates.insert(field.did);
+            }
+        }
+    }
+    if candidates.is_empty() {
+        return HashMap::new();
+    }
+
+    let mut fields = HashMap::<DefId, FnTableField>::new();
+    for (did, store) in collect_fn_table_stores(tcx, &candidates) {
+        let field = fields.entry(did).or_insert_with(|| FnTableField {
+            targets: Vec::new(),
+            complete: true,
+        });
+        match store {
+            FnTableStore::Fn { did: fn_did, .. } if gacx.fn_sigs.contains_key(&fn_did) => {
+                field.targets.push(fn_did);
+            }
+            FnTableStore::None => {}
+            FnTableStore::Fn { .. } | FnTableStore::Other => {
+                field.complete = false;
+            }
+        }
+    }
+    for field in fields.values_mut() {
+        field.targets.sort();
+        field.targets.dedup();
+    }
+    fields
+}
+
+/// Get the targets of the function table field `did`, if they're all known.
+pub fn field_targets<'a>(gacx: &'a GlobalAnalysisCtxt, did: DefId) -> Option<&'a [DefId]> {
+    let field = gacx.fn_table_fields.get(&did)?;
+    if !field.complete || field.targets.is_empty() {
+        return None;
+    }
+    Some(&field.targets)
+}
+
+/// List pairs of pointers that must be unified so that each function table field with known
+/// targets has the same signature as all of its targets.
+pub fn table_sig_equivs(gacx: &GlobalAnalysisCtxt) -> Vec<(PointerId, PointerId)> {
+    let tcx = gacx.tcx;
+    let mut dids = gacx.fn_table_fields.keys().copied().collect::<Vec<_>>();
+    dids.sort();
+
+    let mut equivs = Vec::new();
+    for did in dids {
+        let targets = match field_targets(gacx, did) {
+            Some(x) => x,
+            None => continue,
+        };
+        let field_lty = gacx.field_ltys[&did];
+        let fn_lty = match *field_lty.ty.kind() {
+            TyKind::FnPtr(_) => field_lty,
+            _ => field_lty.args[0],
+        };
+        // The args of a `FnPtr` type are its inputs followed by its output.
+        for &target in targets {
+            let lsig = &gacx.fn_sigs[&target];
+            if lsig.inputs.len() + 1 != fn_lty.args.len() {
+                debug!("fn table field {did:?} has a different arity than {target:?}");
+                continue;
+            }
+            let sig_ltys = lsig.inputs.iter().chain(Some(&lsig.output));
+            for (&field_arg, &sig_arg) in fn_lty.args.iter().zip(sig_ltys) {
+                if tcx.erase_regions(field_arg.ty) != tcx.erase_regions(sig_arg.ty) {
+                    debug!(
+                        "fn table field {did:?} type {:?} doesn't match {target:?} type {:?}",
+                        field_arg.ty, sig_arg.ty
+                    );
+                    continue;
+                }
+                for (a, b) in field_arg.iter().zip(sig_arg.iter()) {
+                    if !a.label.is_none() && !b.label.is_none() {
+                        equivs.push((a.label, b.label));
+                    }
+                }
+            }
+        }
+    }
+    equivs
+}
+
+/// Check whether `func_ty` is `Option::unwrap` or a similar method that returns the value inside
+/// an `Option`.
+fn is_option_unwrap<'tcx>(tcx: TyCtxt<'tcx>, func_ty: Ty<'tcx>) -> bool {
+    let did = match *func_ty.kind() {
+        TyKind::FnDef(did, _) => did,
+        _ => return false,
+    };
+    let impl_did = match tcx.impl_of_method(did) {
+        Some(x) => x,
+        None => return false,
+    };
+    let is_option = match tcx.type_of(impl_did).ty_adt_def() {
+        Some(adt_def) => tcx.is_diagnostic_item(sym::Option, adt_def.did()),
+        None => false,
+    };
+    is_option
+        && matches!(
+            tcx.item_name(did).as_str(),
+            "unwrap" | "expect" | "unwrap_unchecked"
+        )
+}
+
+/// Find the struct field that the function pointer `func`, called at `loc`, was loaded from.
+/// This follows copies between locals and calls to `Option::unwrap` back to a read of the field,
+/// as in `((*ops).read.unwrap())(..)`.
+fn fn_ptr_source_field<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &Body<'tcx>,
+    loc: Location,
+    func: &Operand<'tcx>,
+) -> Option<DefId> {
+    let mut pl = func.place()?;
+    let mut loc = loc;
+    let mut visited = HashSet::new();
+    visited.insert(loc.block);
+    loop {
+        if let Some((base, ProjectionElem::Field(field, _))) = pl.iter_projections().last() {
+            let adt_def = base.ty(mir, tcx).ty.ty_adt_def()?;
+            if !adt_def.is_struct() {
+                return None;
+            }
+            return Some(adt_def.non_enum_variant().fields[field.index()].did);
+        }
+        if !pl.projection.is_empty() {
+            return None;
+        }
+
+        // Look for the latest write to `pl` in the current block.
+        let stmts = &mir.basic_blocks()[loc.block].statements[..loc.statement_index];
+        let write = stmts
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, stmt)| match stmt.kind {
+                StatementKind::Assign(ref x) if x.0 == pl => Some((i, &x.1)),
+                _ => None,
+            });
+        if let Some((i, rv)) = write {
+            match *rv {
+                Rvalue::Use(Operand::Copy(src) | Operand::Move(src)) => {
+                    pl = src;
+                    loc.statement_index = i;
+                    continue;
+                }
+                _ => return None,
+            }
+        }
+
+        // Otherwise, continue in the block's only predecessor.
+        let pred = match mir.basic_blocks.predecessors()[loc.block][..] {
+            [pred] => pred,
+            _ => return None,
+        };
+        if !visited.insert(pred) {
+            return None;
+        }
+        if let TerminatorKind::Call {
+            ref func,
+            ref args,
+            destination,
+            ..
+        } = mir.basic_blocks()[pred].terminator().kind
+        {
+            if destination == pl {
+                if !is_option_unwrap(tcx, func.ty(mir, tcx)) {
+                    return None;
+                }
+                pl = args.get(0)?.place()?;
+            }
+        }
+        loc = mir.terminator_loc(pred);
+    }
+}
+
+/// If the function pointer `func` called at `loc` was loaded from a function table field whose
+/// targets are all known, return those targets.
+pub fn indirect_call_targets<'a, 'tcx>(
+    gacx: &'a GlobalAnalysisCtxt<'tcx>,
+    mir: &Body<'tcx>,
+    loc: Location,
+    func: &Operand<'tcx>,
+) -> Option<&'a [DefId]> {
+    let field = fn_ptr_source_field(gacx.tcx, mir, loc, func)?;
+    field_targets(gacx, field)
+}
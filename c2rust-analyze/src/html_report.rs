@@ -0,0 +1,342 @@
+//! Generating an HTML report of the analysis results.
+//!
+//! When `C2RUST_ANALYZE_HTML_REPORT` names a directory, the analysis writes a static HTML page
+//! there for each source file of the crate, with every pointer colored by what it's rewritten to:
+//! a reference, a `Box`, an `Option`, some other safe type, or left as a raw pointer.  Hovering
+//! over a pointer shows its permissions, flags, and rewritten type.  An `index.html` page lists
+//! the files with the number of pointers in each category.  The report is built from the same
+//! records as `--output-facts` (see `output_facts`), and each crate gets its own subdirectory.
+
+use crate::output_facts::PointerFact;
+use rustc_hir::def_id::LOCAL_CRATE;
+use rustc_middle::ty::TyCtxt;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Get the directory to write the HTML report to, if one was set.
+pub fn report_dir() -> Option<PathBuf> {
+    env::var_os("C2RUST_ANALYZE_HTML_REPORT").map(PathBuf::from)
+}
+
+/// What a pointer is rewritten to, from best to worst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Outcome {
+    Ref,
+    Box,
+    Option,
+    Other,
+    Raw,
+}
+
+impl Outcome {
+    const ALL: [Outcome; 5] = [
+        Outcome::Ref,
+        Outcome::Box,
+        Outcome::Option,
+        Outcome::Other,
+        Outcome::Raw,
+    ];
+
+    fn classify(fact: &PointerFact, rewrite_ty: &str) -> Outcome {
+        if fact.failure.is_some() || rewrite_ty.starts_with('*') {
+            return Outcome::Raw;
+        }
+        if rewrite_ty.starts_with('&') {
+            return Outcome::Ref;
+        }
+        let path = rewrite_ty.split('<').next().unwrap_or(rewrite_ty);
+        match path.rsplit("::").next() {
+            Some("Box") => Outcome::Box,
+            Some("Option") => Outcome::Option,
+            _ => Outcome::Other,
+        }
+    }
+
+    fn class(self) -> &'static str {
+        match self {
+            Outcome::Ref => "ref",
+            Outcome::Box => "box",
+            Outcome::Option => "option",
+            Outcome::Other => "other",
+            Outcome::Raw => "raw",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Outcome::Ref => "&amp;T",
+            Outcome::Box => "Box",
+            Outcome::Option => "Option",
+            Outcome::Other => "other",
+            Outcome::Raw => "raw",
+        }
+    }
+}
+
+const STYLE: &str = "\
+body { font-family: sans-serif; }
+table.src { border-collapse: collapse; font-family: monospace; white-space: pre; }
+table.src td { padding: 0 0.5em; vertical-align: top; }
+td.line { color: #888; text-align: right; }
+td.marks span { cursor: help; }
+table.counts td, table.counts th { padding: 0.2em 0.6em; text-align: right; }
+.ref { background: #c8f0c8; }
+.box { background: #c8dcf8; }
+.option { background: #f8f0b0; }
+.other { background: #e4d0f4; }
+.raw { background: #f8c0c0; }
+";
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// The hover text for `fact`.
+fn describe_fact(fact: &PointerFact, rewrite_ty: &str) -> String {
+    let mut s = format!("{} {}: {}", fact.ptr, fact.owner, fact.desc);
+    write!(s, "\nperms: {}", fact.perms.join(" | ")).unwrap();
+    write!(s, "\nflags: {}", fact.flags.join(" | ")).unwrap();
+    write!(s, "\ntype: {}", rewrite_ty).unwrap();
+    if let Some(ref failure) = fact.failure {
+        write!(s, "\nnot rewritten: {}", failure).unwrap();
+    }
+    s
+}
+
+/// Convert a 1-based column, counted in chars, to a byte offset within `line`.
+fn col_to_byte(line: &str, col: usize) -> usize {
+    line.char_indices()
+        .nth(col.saturating_sub(1))
+        .map_or(line.len(), |(i, _)| i)
+}
+
+/// A pointer to be shown in the report, resolved to a position in its file.
+struct Entry<'a> {
+    line: usize,
+    /// The byte range to highlight within `line`, if the pointer's span covers only part of a
+    /// single line.
+    range: Option<(usize, usize)>,
+    outcome: Outcome,
+    title: String,
+    fact: &'a PointerFact,
+}
+
+/// Render the page for one source file.
+fn render_file(name: &str, src: &str, entries: &[Entry]) -> String {
+    let mut by_line = BTreeMap::<usize, Vec<&Entry>>::new();
+    for e in entries {
+        by_line.entry(e.line).or_default().push(e);
+    }
+
+    let mut out = String::new();
+    write!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title>\
+         <style>{1}</style></head><body>\n<h1>{0}</h1>\n\
+         <p><a href=\"index.html\">index</a></p>\n<table class=\"src\">\n",
+        escape_html(name),
+        STYLE
+    )
+    .unwrap();
+    for (i, line) in src.lines().enumerate() {
+        let line_num = i + 1;
+        let line_entries = by_line.get(&line_num).map_or(&[][..], |v| &v[..]);
+
+        let mut marks = String::new();
+        for e in line_entries {
+            write!(
+                marks,
+                "<span class=\"{}\" title=\"{}\">&#9679;</span>",
+                e.outcome.class(),
+                escape_html(&e.title)
+            )
+            .unwrap();
+        }
+
+        // Highlight the spans on this line that don't overlap an earlier one.  Where several
+        // pointers share a span, it takes the color of the worst outcome among them.
+        let mut ranges = BTreeMap::<(usize, usize), Vec<&Entry>>::new();
+        for e in line_entries {
+            if let Some(range) = e.range {
+                ranges.entry(range).or_default().push(*e);
+            }
+        }
+        let mut code = String::new();
+        let mut pos = 0;
+        for (&(lo, hi), es) in &ranges {
+            if lo < pos || hi > line.len() {
+                continue;
+            }
+            let outcome = es.iter().map(|e| e.outcome).max().unwrap();
+            let title = es
+                .iter()
+                .map(|e| e.title.as_str())
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            code.push_str(&escape_html(&line[pos..lo]));
+            write!(
+                code,
+                "<span class=\"{}\" title=\"{}\">{}</span>",
+                outcome.class(),
+                escape_html(&title),
+                escape_html(&line[lo..hi])
+            )
+            .unwrap();
+            pos = hi;
+        }
+        code.push_str(&escape_html(&line[pos..]));
+
+        writeln!(
+            out,
+            "<tr><td class=\"line\">{}</td><td class=\"marks\">{}</td><td>{}</td></tr>",
+            line_num, marks, code
+        )
+        .unwrap();
+    }
+    out.push_str("</table>\n</body></html>\n");
+    out
+}
+
+/// Write a row of the table in `index.html`.  The last column gives the percentage of pointers
+/// that aren't left raw.
+fn write_count_row(out: &mut String, name: &str, counts: &[usize]) {
+    write!(out, "<tr><td>{}</td>", name).unwrap();
+    for count in counts {
+        write!(out, "<td>{}</td>", count).unwrap();
+    }
+    let total: usize = counts.iter().sum();
+    let raw = counts[Outcome::Raw as usize];
+    let pct = if total == 0 {
+        100.0
+    } else {
+        100.0 * (total - raw) as f64 / total as f64
+    };
+    writeln!(out, "<td>{:.1}%</td></tr>", pct).unwrap();
+}
+
+/// Render `index.html`, which lists the number of pointers with each outcome in each file.
+fn render_index(crate_name: &str, files: &BTreeMap<String, (String, Vec<Entry>)>) -> String {
+    let mut out = String::new();
+    write!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title>\
+         <style>{1}</style></head><body>\n<h1>Analysis results for {0}</h1>\n\
+         <table class=\"counts\">\n<tr><th>file</th>",
+        escape_html(crate_name),
+        STYLE
+    )
+    .unwrap();
+    for outcome in Outcome::ALL {
+        write!(
+            out,
+            "<th class=\"{}\">{}</th>",
+            outcome.class(),
+            outcome.label()
+        )
+        .unwrap();
+    }
+    out.push_str("<th>safe</th></tr>\n");
+
+    let mut totals = [0; Outcome::ALL.len()];
+    for (name, (page, entries)) in files {
+        let mut counts = [0; Outcome::ALL.len()];
+        for e in entries {
+            counts[e.outcome as usize] += 1;
+            totals[e.outcome as usize] += 1;
+        }
+        let link = format!(
+            "<a href=\"{}\">{}</a>",
+            escape_html(page),
+            escape_html(name)
+        );
+        write_count_row(&mut out, &link, &counts);
+    }
+    write_count_row(&mut out, "<b>total</b>", &totals);
+    out.push_str("</table>\n</body></html>\n");
+    out
+}
+
+/// The name of the page for the source file `name`.
+fn page_name(name: &str) -> String {
+    let mut page = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    page.push_str(".html");
+    page
+}
+
+/// Write the report for the current crate, showing the pointers in `facts`, to a subdirectory of
+/// `dir` named after the crate.
+pub fn write_report(tcx: TyCtxt, dir: &Path, facts: &[PointerFact]) -> io::Result<()> {
+    let crate_name = tcx.crate_name(LOCAL_CRATE).to_string();
+
+    let mut sources = BTreeMap::new();
+    for file in tcx.sess.source_map().files().iter() {
+        if let Some(ref src) = file.src {
+            sources.insert(file.name.prefer_local().to_string(), src.clone());
+        }
+    }
+
+    let mut files = BTreeMap::<String, (String, Vec<Entry>)>::new();
+    for fact in facts {
+        let (span, rewrite_ty) = match (&fact.span, &fact.rewrite_ty) {
+            (Some(span), Some(rewrite_ty)) => (span, rewrite_ty),
+            _ => continue,
+        };
+        let src = match sources.get(&span.file) {
+            Some(x) => x,
+            None => continue,
+        };
+        let range = if span.lo_line == span.hi_line {
+            src.lines().nth(span.lo_line - 1).map(|line| {
+                (
+                    col_to_byte(line, span.lo_col),
+                    col_to_byte(line, span.hi_col),
+                )
+            })
+        } else {
+            None
+        };
+        let entry = Entry {
+            line: span.lo_line,
+            range,
+            outcome: Outcome::classify(fact, rewrite_ty),
+            title: describe_fact(fact, rewrite_ty),
+            fact,
+        };
+        files
+            .entry(span.file.clone())
+            .or_insert_with(|| (page_name(&span.file), Vec::new()))
+            .1
+            .push(entry);
+    }
+
+    let crate_dir = dir.join(&crate_name);
+    fs::create_dir_all(&crate_dir)?;
+    for (name, (page, entries)) in &mut files {
+        entries.sort_by_key(|e| (e.line, e.fact.owner.clone(), e.fact.desc.clone()));
+        let html = render_file(name, &sources[name], entries);
+        fs::write(crate_dir.join(page), html)?;
+    }
+    fs::write(
+        crate_dir.join("index.html"),
+        render_index(&crate_name, &files),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,321 @@
+//! Reviewing rewrites one function at a time before they're applied.
+//!
+//! With `C2RUST_ANALYZE_INTERACTIVE` set, the rewrites for each function are shown on the
+//! terminal before anything is written, and the user chooses to accept them, reject them, or defer
+//! the decision.  A function whose rewrites are rejected or deferred is marked
+//! [`DontRewriteFnReason::USER_REQUEST`], and the rewrite loop runs again, so its callers are
+//! rewritten against its original signature (through shims where needed) instead of being left
+//! inconsistent with it.  Rewrites generated after the loop, such as those for statics and ADTs,
+//! are reviewed once more at the end; those can only be dropped.
+//!
+//! Decisions are recorded in the decisions file named by `C2RUST_ANALYZE_DECISIONS_FILE`, and
+//! later runs apply the recorded decision without asking again as long as the function's
+//! rewrites are unchanged.  Deferred functions are left unchanged in this run and asked about
+//! again next time.
+//!
+//! [`DontRewriteFnReason::USER_REQUEST`]: crate::context::DontRewriteFnReason::USER_REQUEST
+//!
+//! The prompts go through `/dev/tty`, since `cargo` doesn't pass its standard input and output
+//! through to `rustc`.
+
+use crate::rewrite::{self, Rewrite, RewriteSummary};
+use rustc_hir::def_id::LOCAL_CRATE;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Check whether rewrites should be reviewed interactively.
+pub fn enabled() -> bool {
+    env::var("C2RUST_ANALYZE_INTERACTIVE").map_or(false, |val| val == "1")
+}
+
+/// Get the file where decisions are recorded.
+pub fn decisions_path() -> PathBuf {
+    env::var_os("C2RUST_ANALYZE_DECISIONS_FILE").map_or_else(
+        || PathBuf::from("c2rust-analyze-decisions.json"),
+        PathBuf::from,
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    Accept,
+    Reject,
+}
+
+/// A recorded decision for the rewrites of one function.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct DecisionRecord {
+    #[serde(rename = "crate")]
+    krate: String,
+    owner: String,
+    /// The function's rewrites, as `old => new`.  Positions are left out so that the decision
+    /// still applies after unrelated edits move the function around.
+    rewrites: Vec<String>,
+    decision: Decision,
+}
+
+fn read_decisions(path: &Path) -> io::Result<Vec<DecisionRecord>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_decisions(path: &Path, records: &[DecisionRecord]) -> io::Result<()> {
+    let mut f = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut f, records)?;
+    writeln!(f)?;
+    f.flush()
+}
+
+/// The user's answer for one hunk.
+enum Answer {
+    Decide(Decision),
+    Defer,
+    /// Defer this hunk and all the remaining ones.
+    Quit,
+}
+
+struct Terminal {
+    input: BufReader<File>,
+    output: File,
+}
+
+impl Terminal {
+    fn open() -> io::Result<Terminal> {
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        Ok(Terminal {
+            input: BufReader::new(tty.try_clone()?),
+            output: tty,
+        })
+    }
+
+    fn ask(&mut self, owner: &str, summaries: &[RewriteSummary]) -> io::Result<Answer> {
+        writeln!(self.output, "\n{} ({} rewrites):", owner, summaries.len())?;
+        for summary in summaries {
+            writeln!(self.output, "  {}", summary)?;
+        }
+        loop {
+            write!(
+                self.output,
+                "accept, reject, defer, or quit (defer the rest)? [a/r/d/q] "
+            )?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(Answer::Quit);
+            }
+            match line.trim() {
+                "a" | "accept" => return Ok(Answer::Decide(Decision::Accept)),
+                "r" | "reject" => return Ok(Answer::Decide(Decision::Reject)),
+                "d" | "defer" => return Ok(Answer::Defer),
+                "q" | "quit" => return Ok(Answer::Quit),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Find the function whose body contains `span`, as an index into `owners`.  When functions are
+/// nested, the innermost one is chosen.
+fn find_owner(owners: &[(Span, String)], span: Span) -> Option<usize> {
+    owners
+        .iter()
+        .enumerate()
+        .filter(|(_, owner)| owner.0.contains(span))
+        .min_by_key(|(_, owner)| owner.0.hi() - owner.0.lo())
+        .map(|(i, _)| i)
+}
+
+/// The hunks for one function, or for the items of one file, sorted by position.
+type Hunk = Vec<(RewriteSummary, Span, Rewrite)>;
+
+/// An interactive review of the rewrites for one crate.  The decisions file is read when the
+/// review starts and written back by [`Review::finish`].
+pub struct Review<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    krate: String,
+    /// The functions that can own rewrites, each given by its body span and path.
+    owners: Vec<(Span, String)>,
+    path: PathBuf,
+    records: Vec<DecisionRecord>,
+    /// Indices of the records that matched or were made during this run.
+    used_records: HashSet<usize>,
+    /// Owners whose rewrites were reviewed during this run.
+    reviewed_owners: HashSet<String>,
+    /// Functions, as indices into `owners`, that are left unchanged at the user's request.
+    fixed: HashSet<usize>,
+    term: Option<Terminal>,
+    quit: bool,
+}
+
+impl<'tcx> Review<'tcx> {
+    pub fn new(tcx: TyCtxt<'tcx>, owners: Vec<(Span, String)>) -> io::Result<Review<'tcx>> {
+        let path = decisions_path();
+        let records = read_decisions(&path)?;
+        Ok(Review {
+            tcx,
+            krate: tcx.crate_name(LOCAL_CRATE).to_string(),
+            owners,
+            path,
+            records,
+            used_records: HashSet::new(),
+            reviewed_owners: HashSet::new(),
+            fixed: HashSet::new(),
+            term: None,
+            quit: false,
+        })
+    }
+
+    /// Group `rewrites` by the function in `owners` that contains them, in source order.
+    /// Rewrites outside any function are grouped by file.
+    fn group(
+        &self,
+        rewrites: Vec<(Span, Rewrite)>,
+    ) -> BTreeMap<(String, usize, String), (Option<usize>, Hunk)> {
+        let sm = self.tcx.sess.source_map();
+        let mut hunks = BTreeMap::<_, (Option<usize>, Hunk)>::new();
+        for (span, rw) in rewrites {
+            let summary = rewrite::summarize_rewrite(sm, span, &rw);
+            let owner = find_owner(&self.owners, span);
+            let key = match owner {
+                Some(i) => {
+                    let (owner_span, ref name) = self.owners[i];
+                    let lo = sm.lookup_char_pos(owner_span.lo());
+                    (
+                        lo.file.name.prefer_local().to_string(),
+                        lo.line,
+                        name.clone(),
+                    )
+                }
+                None => (
+                    summary.file.clone(),
+                    0,
+                    format!("items in {}", summary.file),
+                ),
+            };
+            let entry = hunks.entry(key).or_insert_with(|| (owner, Vec::new()));
+            entry.1.push((summary, span, rw));
+        }
+        for (_, hunk) in hunks.values_mut() {
+            hunk.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        hunks
+    }
+
+    /// Decide whether to apply `hunk`, using the recorded decision if there is one and asking
+    /// otherwise.  Returns `None` if the decision was deferred.
+    fn decide(&mut self, owner: &str, hunk: &Hunk) -> io::Result<Option<Decision>> {
+        let summaries = hunk.iter().map(|x| x.0.clone()).collect::<Vec<_>>();
+        let key = summaries
+            .iter()
+            .map(|s| format!("{} => {}", s.old, s.new))
+            .collect::<Vec<_>>();
+        self.reviewed_owners.insert(owner.to_owned());
+
+        let recorded = self
+            .records
+            .iter()
+            .position(|r| r.krate == self.krate && r.owner == owner && r.rewrites == key);
+        if let Some(i) = recorded {
+            let decision = self.records[i].decision;
+            eprintln!("using recorded decision for {}: {:?}", owner, decision);
+            self.used_records.insert(i);
+            return Ok(Some(decision));
+        }
+        if self.quit {
+            return Ok(None);
+        }
+        if self.term.is_none() {
+            self.term = Some(Terminal::open()?);
+        }
+        match self.term.as_mut().unwrap().ask(owner, &summaries)? {
+            Answer::Decide(decision) => {
+                self.used_records.insert(self.records.len());
+                self.records.push(DecisionRecord {
+                    krate: self.krate.clone(),
+                    owner: owner.to_owned(),
+                    rewrites: key,
+                    decision,
+                });
+                Ok(Some(decision))
+            }
+            Answer::Defer => Ok(None),
+            Answer::Quit => {
+                self.quit = true;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Review the rewrites generated by one iteration of the rewrite loop.  Returns the rewrites
+    /// to keep, along with the functions (as indices into `owners`) whose rewrites weren't
+    /// accepted.  The caller should mark those functions as not to be rewritten and run the loop
+    /// again.
+    ///
+    /// Functions rejected in an earlier iteration aren't asked about again: any rewrites left in
+    /// them adjust calls to other functions whose signatures changed, and are needed to keep the
+    /// function compiling, so they're kept.
+    pub fn review_fns(
+        &mut self,
+        rewrites: Vec<(Span, Rewrite)>,
+    ) -> io::Result<(Vec<(Span, Rewrite)>, Vec<usize>)> {
+        let mut selected = Vec::new();
+        let mut rejected = Vec::new();
+        for ((_, _, owner), (owner_idx, hunk)) in self.group(rewrites) {
+            let fixed = owner_idx.map_or(false, |i| self.fixed.contains(&i));
+            let accept = fixed || self.decide(&owner, &hunk)? == Some(Decision::Accept);
+            if accept {
+                selected.extend(hunk.into_iter().map(|(_, span, rw)| (span, rw)));
+            } else if let Some(i) = owner_idx {
+                self.fixed.insert(i);
+                rejected.push(i);
+            }
+        }
+        Ok((selected, rejected))
+    }
+
+    /// Review the rewrites generated after the rewrite loop has finished, and return the ones to
+    /// apply.  These are dropped from functions rejected by [`Review::review_fns`], since those
+    /// are left unchanged.
+    pub fn review_rest(
+        &mut self,
+        rewrites: Vec<(Span, Rewrite)>,
+    ) -> io::Result<Vec<(Span, Rewrite)>> {
+        let mut selected = Vec::new();
+        for ((_, _, owner), (owner_idx, hunk)) in self.group(rewrites) {
+            if owner_idx.map_or(false, |i| self.fixed.contains(&i)) {
+                continue;
+            }
+            if self.decide(&owner, &hunk)? == Some(Decision::Accept) {
+                selected.extend(hunk.into_iter().map(|(_, span, rw)| (span, rw)));
+            }
+        }
+        Ok(selected)
+    }
+
+    /// Write the decisions back to the decisions file.  Records for owners reviewed in this run
+    /// that no longer match their rewrites are dropped.
+    pub fn finish(self) -> io::Result<()> {
+        let records = self
+            .records
+            .into_iter()
+            .enumerate()
+            .filter(|(i, r)| {
+                self.used_records.contains(i)
+                    || r.krate != self.krate
+                    || !self.reviewed_owners.contains(&r.owner)
+            })
+            .map(|(_, r)| r)
+            .collect::<Vec<_>>();
+        write_decisions(&self.path, &records)
+    }
+}
@@ -0,0 +1,76 @@
+//! Updating JSON output files that are shared between crates.
+//!
+//! Under `cargo c2rust-analyze`, each crate in the build is analyzed by its own `rustc` process,
+//! and cargo may run several of them at once.  Files like the one given to `--output-facts`
+//! collect the results of every crate, so each process holds an exclusive lock on the file while
+//! it reads the current contents, adds its own results, and writes the file back.
+
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Lock `path`, parse its contents as JSON, apply `f`, and write the result back.  If `path`
+/// doesn't exist or is empty, `f` is applied to `init()` instead.
+pub fn update(
+    path: &Path,
+    init: impl FnOnce() -> Value,
+    f: impl FnOnce(&mut Value) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(path)?;
+    // The lock is released when `file` is closed.
+    lock(&file)?;
+
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    let mut value = if bytes.is_empty() {
+        init()
+    } else {
+        serde_json::from_slice(&bytes)?
+    };
+    f(&mut value)?;
+
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    let mut w = BufWriter::new(&file);
+    serde_json::to_writer_pretty(&mut w, &value)?;
+    writeln!(w)?;
+    w.flush()
+}
+
+/// Add `items` to the end of the JSON array in `path`, creating it if it doesn't exist.
+pub fn append_to_array(path: &Path, items: impl IntoIterator<Item = Value>) -> io::Result<()> {
+    update(
+        path,
+        || Value::Array(Vec::new()),
+        |value| match value {
+            Value::Array(all) => {
+                all.extend(items);
+                Ok(())
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} doesn't hold a JSON array", path.display()),
+            )),
+        },
+    )
+}
+
+#[cfg(unix)]
+fn lock(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    // SAFETY: `flock` only acts on the descriptor, which stays open for the lifetime of `file`.
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn lock(_file: &File) -> io::Result<()> {
+    Ok(())
+}
@@ -193,7 +193,17 @@ impl Display for KnownFn {
     }
 }
 
+/// Names of [`KnownFn`]s that return foreign memory, such as an `mmap`ed region.  This memory is
+/// managed outside of Rust, so pointers derived from it must never be boxed.  Other `extern`
+/// functions can be marked with `#[c2rust::foreign_memory]`.
+const FOREIGN_MEMORY_FNS: &[&str] = &["mmap"];
+
 impl KnownFn {
+    /// Whether the pointers returned by this function point to foreign memory.
+    pub fn returns_foreign_memory(&self) -> bool {
+        FOREIGN_MEMORY_FNS.contains(&self.name)
+    }
+
     pub fn inputs_and_output(&self) -> impl Iterator<Item = &KnownFnTy> {
         self.inputs.iter().chain([&self.output])
     }
@@ -262,6 +272,9 @@ impl KnownFn {
 /// we annotate any argument with [`NON_NULL`] if `NULL` might cause UB,
 /// and we annotate returns with [`NON_NULL`] only if it is strictly guaranteed to be non-`NULL`.
 ///
+/// Variadic `fn`s end their arguments with `_: ...`, as in the `c2rust transpile`d declarations.
+/// The variadic arguments themselves have no [`PermissionSet`]s.
+///
 /// [`NON_NULL`]: PermissionSet::NON_NULL
 macro_rules! known_fns {
     {
@@ -271,6 +284,7 @@ macro_rules! known_fns {
                 $(#[$attr:meta])?
                 fn $name:ident(
                     $($arg_name:ident: $arg_ty:ty$(: $arg_perms:tt)?,)*
+                    $(_: $variadic:tt,)?
                 ) -> $return_ty:ty$(: $return_perms:tt)?
             );*;
 
@@ -281,16 +295,14 @@ macro_rules! known_fns {
         const_slice!(KnownFn, [$(
             $(#[$attr])?
             {
-                unsafe extern "C" fn $name($($arg_name: $arg_ty,)*) -> $return_ty {
-                    $(let _ = $arg_name;)*
-                    todo!()
-                }
                 // ensure the definitions match
-                [$name, $module::$name];
+                let _: unsafe extern "C" fn($($arg_ty,)* $($variadic)?) -> $return_ty =
+                    $module::$name;
 
                 let source = stringify!(
                     fn $name(
                         $($arg_name: $arg_ty$(: $arg_perms)?,)*
+                        $(_: $variadic,)?
                     ) -> $return_ty$(: $return_perms)?
                 );
 
@@ -630,16 +642,38 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 seconds: c_uint,
             ) -> c_uint;
 
+            fn atexit(
+                cb: extern "C" fn(),
+            ) -> c_int;
+
+            fn atof(
+                s: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+            ) -> c_double;
+
             fn atoi(
                 s: *const c_char: [READ | OFFSET_ADD | NON_NULL],
             ) -> c_int;
 
+            fn atol(
+                s: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+            ) -> c_long;
+
             fn bind(
                 socket: c_int,
                 address: *const sockaddr: [READ],
                 address_len: socklen_t,
             ) -> c_int;
 
+            fn bsearch(
+                key: *const c_void: [READ | NON_NULL],
+                // `WRITE` because the return type is derived from `base`'s provenance.
+                base: *const c_void: [READ | WRITE | OFFSET_ADD],
+                num: size_t,
+                size: size_t,
+                // `compar` is called with `key` and elements of `base`.
+                compar: Option<unsafe extern "C" fn(*const c_void, *const c_void) -> c_int>: [READ | NON_NULL, READ | NON_NULL],
+            ) -> *mut c_void: [READ | WRITE | OFFSET_ADD | OFFSET_SUB];
+
             fn calloc(
                 nobj: size_t,
                 size: size_t,
@@ -743,6 +777,10 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 dirfd: c_int,
             ) -> c_int;
 
+            fn fclose(
+                file: *mut FILE: [READ | WRITE | NON_NULL],
+            ) -> c_int;
+
             // fn fcntl(
             //     fd: c_int,
             //     cmd: c_int,
@@ -750,24 +788,68 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
             //     ...
             // ) -> c_int,
 
+            fn fdopen(
+                fd: c_int,
+                mode: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+            ) -> *mut FILE: [READ | WRITE];
+
+            fn feof(
+                stream: *mut FILE: [READ | WRITE | NON_NULL],
+            ) -> c_int;
+
+            fn ferror(
+                stream: *mut FILE: [READ | WRITE | NON_NULL],
+            ) -> c_int;
+
             fn fflush(
                 file: *mut FILE: [READ | WRITE | NON_NULL],
             ) -> c_int;
 
+            fn fgetc(
+                stream: *mut FILE: [READ | WRITE | NON_NULL],
+            ) -> c_int;
+
+            fn fgets(
+                buf: *mut c_char: [WRITE | OFFSET_ADD | NON_NULL],
+                n: c_int,
+                stream: *mut FILE: [READ | WRITE | NON_NULL],
+                // Same as `buf`, or null on EOF or error.
+            ) -> *mut c_char: [WRITE | OFFSET_ADD];
+
+            fn fileno(
+                stream: *mut FILE: [READ | WRITE | NON_NULL],
+            ) -> c_int;
+
+            fn fopen(
+                filename: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                mode: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+            ) -> *mut FILE: [READ | WRITE];
+
             fn fork() -> pid_t;
 
-            // fn fprintf(
-            //     stream: *mut FILE: [READ | WRITE | NON_NULL],
-            //     format: *const c_char: [READ | OFFSET_ADD | NON_NULL],
-                   // TODO(kkysen) varargs, printf-style
-            //     ...
-            // ) -> c_int;
+            fn fprintf(
+                stream: *mut FILE: [READ | WRITE | NON_NULL],
+                format: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                _: ...,
+            ) -> c_int;
+
+            fn fputc(
+                c: c_int,
+                stream: *mut FILE: [READ | WRITE | NON_NULL],
+            ) -> c_int;
 
             fn fputs(
                 s: *const c_char: [READ | OFFSET_ADD | NON_NULL],
                 stream: *mut FILE: [READ | WRITE | NON_NULL],
             ) -> c_int;
 
+            fn fread(
+                ptr: *mut c_void: [WRITE | OFFSET_ADD | NON_NULL],
+                size: size_t,
+                nobj: size_t,
+                stream: *mut FILE: [READ | WRITE | NON_NULL],
+            ) -> size_t;
+
             fn free(
                 p: *mut c_void: [FREE],
             ) -> ();
@@ -778,16 +860,39 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 res: *mut addrinfo: [READ | WRITE],
             ) -> ();
 
+            fn freopen(
+                filename: *const c_char: [READ | OFFSET_ADD],
+                mode: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                file: *mut FILE: [READ | WRITE | NON_NULL],
+            ) -> *mut FILE: [READ | WRITE];
+
+            fn fseek(
+                stream: *mut FILE: [READ | WRITE | NON_NULL],
+                offset: c_long,
+                whence: c_int,
+            ) -> c_int;
+
             fn fstat(
                 fildes: c_int,
                 buf: *mut stat: [WRITE | NON_NULL],
             ) -> c_int;
 
+            fn ftell(
+                stream: *mut FILE: [READ | WRITE | NON_NULL],
+            ) -> c_long;
+
             fn ftruncate(
                 fd: c_int,
                 length: off_t,
             ) -> c_int;
 
+            fn fwrite(
+                ptr: *const c_void: [READ | OFFSET_ADD | NON_NULL],
+                size: size_t,
+                nobj: size_t,
+                stream: *mut FILE: [READ | WRITE | NON_NULL],
+            ) -> size_t;
+
             fn gai_strerror(
                 errcode: c_int,
             ) -> *const c_char: [READ | OFFSET_ADD | NON_NULL];
@@ -801,6 +906,8 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 res: *mut *mut addrinfo: [WRITE | NON_NULL, WRITE | NON_NULL],
             ) -> c_int;
 
+            fn getchar() -> c_int;
+
             fn getcwd(
                 // `READ` because the return type is `READ`.
                 buf: *mut c_char: [READ | WRITE | OFFSET_ADD],
@@ -838,6 +945,13 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 // as it may be statically allocated and reused.  It is not meant to be modified.
             ) -> *mut group: [READ];
 
+            fn getline(
+                // `*lineptr` may be `realloc`ed.
+                lineptr: *mut *mut c_char: [READ | WRITE | NON_NULL, READ | WRITE | OFFSET_ADD | FREE],
+                n: *mut size_t: [READ | WRITE | NON_NULL],
+                stream: *mut FILE: [READ | WRITE | NON_NULL],
+            ) -> ssize_t;
+
             fn getloadavg(
                 loadavg: *mut c_double: [WRITE | OFFSET_ADD | NON_NULL],
                 nelem: c_int,
@@ -1040,6 +1154,14 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
             // TODO(kkysen) Not in `libc` crate.
             // fn mempcpy;
 
+            #[cfg(target_os = "linux")]
+            fn memrchr(
+                // `WRITE` because the return type is derived from `cx`'s provenance.
+                cx: *const c_void: [READ | WRITE | OFFSET_ADD | NON_NULL],
+                c: c_int,
+                n: size_t,
+            ) -> *mut c_void: [READ | WRITE | OFFSET_ADD | OFFSET_SUB];
+
             fn memset(
                 dest: *mut c_void: [WRITE | OFFSET_ADD | NON_NULL],
                 c: c_int,
@@ -1054,6 +1176,10 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 flags: c_int,
             ) -> c_int;
 
+            fn mkstemp(
+                template: *mut c_char: [READ | WRITE | OFFSET_ADD | NON_NULL],
+            ) -> c_int;
+
             fn mmap(
                 // Not yet a `VALID` pointer.
                 addr: *mut c_void: [NONE],
@@ -1106,6 +1232,12 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 timeout: c_int,
             ) -> c_int;
 
+            fn posix_memalign(
+                memptr: *mut *mut c_void: [WRITE | NON_NULL, READ | WRITE | OFFSET_ADD | FREE],
+                align: size_t,
+                size: size_t,
+            ) -> c_int;
+
             // fn prctl(
             //     option: c_int,
                    // TODO(kkysen) varargs
@@ -1119,11 +1251,19 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 offset: off_t,
             ) -> ssize_t;
 
-            // fn printf(
-            //     format: *const c_char: [READ | OFFSET_ADD | NON_NULL],
-                   // TODO(kkysen) varargs, printf-style
-            //     ...
-            // ) -> c_int;
+            fn printf(
+                format: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                _: ...,
+            ) -> c_int;
+
+            fn putchar(
+                c: c_int,
+            ) -> c_int;
+
+            fn putenv(
+                // Not copied; the string becomes part of the environment.
+                string: *mut c_char: [READ | WRITE | OFFSET_ADD | NON_NULL],
+            ) -> c_int;
 
             fn puts(
                 s: *const c_char: [READ | OFFSET_ADD | NON_NULL],
@@ -1143,6 +1283,14 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 offset: off_t,
             ) -> ssize_t;
 
+            fn qsort(
+                base: *mut c_void: [READ | WRITE | OFFSET_ADD],
+                num: size_t,
+                size: size_t,
+                // `compar` is called with pairs of elements of `base`.
+                compar: Option<unsafe extern "C" fn(*const c_void, *const c_void) -> c_int>: [READ | NON_NULL, READ | NON_NULL],
+            ) -> ();
+
             fn raise(
                 signum: c_int,
             ) -> c_int;
@@ -1160,6 +1308,12 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 size: size_t,
             ) -> *mut c_void: [READ | WRITE | OFFSET_ADD | FREE];
 
+            fn realpath(
+                pathname: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                // If null, the result is `malloc`ed instead.
+                resolved: *mut c_char: [WRITE | OFFSET_ADD],
+            ) -> *mut c_char: [READ | WRITE | OFFSET_ADD];
+
             fn recv(
                 socket: c_int,
                 buf: *mut c_void: [WRITE | OFFSET_ADD | NON_NULL],
@@ -1167,11 +1321,19 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 flags: c_int,
             ) -> ssize_t;
 
+            fn remove(
+                filename: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+            ) -> c_int;
+
             fn rename(
                 oldname: *const c_char: [READ | OFFSET_ADD | NON_NULL],
                 newname: *const c_char: [READ | OFFSET_ADD | NON_NULL],
             ) -> c_int;
 
+            fn rewind(
+                stream: *mut FILE: [READ | WRITE | NON_NULL],
+            ) -> ();
+
             fn select(
                 nfds: c_int,
                 readfds: *mut fd_set: [READ | WRITE],
@@ -1254,6 +1416,14 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 uid: uid_t,
             ) -> c_int;
 
+            fn setvbuf(
+                stream: *mut FILE: [READ | WRITE | NON_NULL],
+                // Used by `stream` until it's closed.
+                buffer: *mut c_char: [READ | WRITE | OFFSET_ADD],
+                mode: c_int,
+                size: size_t,
+            ) -> c_int;
+
             fn shutdown(
                 socket: c_int,
                 how: c_int,
@@ -1275,13 +1445,12 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
             //     handler: sighandler_t,
             // ) -> sighandler_t;
 
-            // fn snprintf(
-            //     s: *mut c_char: [WRITE | OFFSET_ADD | NON_NULL],
-            //     n: size_t,
-            //     format: *const c_char: [READ | OFFSET_ADD | NON_NULL],
-                   // TODO(kkysen) varargs, printf-style
-            //     ...
-            // ) -> c_int;
+            fn snprintf(
+                s: *mut c_char: [WRITE | OFFSET_ADD | NON_NULL],
+                n: size_t,
+                format: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                _: ...,
+            ) -> c_int;
 
             fn socket(
                 domain: c_int,
@@ -1299,6 +1468,12 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 flags: c_uint,
             ) -> ssize_t;
 
+            fn sprintf(
+                s: *mut c_char: [WRITE | OFFSET_ADD | NON_NULL],
+                format: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                _: ...,
+            ) -> c_int;
+
             fn srand(
                 seed: c_uint,
             ) -> ();
@@ -1311,6 +1486,18 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 buf: *mut stat: [WRITE | NON_NULL],
             ) -> c_int;
 
+            fn strcasecmp(
+                s1: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                s2: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+            ) -> c_int;
+
+            fn strcat(
+                // `READ` because the end of the existing string must be found first.
+                s: *mut c_char: [READ | WRITE | OFFSET_ADD | NON_NULL],
+                ct: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                // Same as `s`.
+            ) -> *mut c_char: [READ | WRITE | OFFSET_ADD];
+
             fn strchr(
                 // `WRITE` because the return type is derived from `cs`'s provenance.
                 cs: *const c_char: [READ | WRITE | OFFSET_ADD | NON_NULL],
@@ -1322,6 +1509,17 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 ct: *const c_char: [READ | OFFSET_ADD | NON_NULL],
             ) -> c_int;
 
+            fn strcoll(
+                cs: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                ct: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+            ) -> c_int;
+
+            fn strcpy(
+                dst: *mut c_char: [WRITE | OFFSET_ADD | NON_NULL],
+                src: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                // Same as `dst`.
+            ) -> *mut c_char: [WRITE | OFFSET_ADD];
+
             fn strcspn(
                 cs: *const c_char: [READ | OFFSET_ADD | NON_NULL],
                 ct: *const c_char: [READ | OFFSET_ADD | NON_NULL],
@@ -1331,6 +1529,12 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 cs: *const c_char: [READ | OFFSET_ADD | NON_NULL],
             ) -> *mut c_char: [READ | WRITE | OFFSET_ADD | FREE];
 
+            // Not `WRITE` even though it's `*mut` since future calls may overwrite the returned memory,
+            // as it may be statically allocated and reused.  It is not meant to be modified.
+            fn strerror(
+                n: c_int,
+            ) -> *mut c_char: [READ | OFFSET_ADD | NON_NULL];
+
             fn strerror_r(
                 errnum: c_int,
                 buf: *mut c_char: [WRITE | OFFSET_ADD | NON_NULL],
@@ -1354,24 +1558,79 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 n: size_t,
             ) -> c_int;
 
+            fn strncat(
+                // `READ` because the end of the existing string must be found first.
+                s: *mut c_char: [READ | WRITE | OFFSET_ADD | NON_NULL],
+                ct: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                n: size_t,
+                // Same as `s`.
+            ) -> *mut c_char: [READ | WRITE | OFFSET_ADD];
+
             fn strncmp(
                 cs: *const c_char: [READ | OFFSET_ADD | NON_NULL],
                 ct: *const c_char: [READ | OFFSET_ADD | NON_NULL],
                 n: size_t,
             ) -> c_int;
 
+            fn strncpy(
+                dst: *mut c_char: [WRITE | OFFSET_ADD | NON_NULL],
+                src: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                n: size_t,
+                // Same as `dst`.
+            ) -> *mut c_char: [WRITE | OFFSET_ADD];
+
+            fn strndup(
+                cs: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                n: size_t,
+            ) -> *mut c_char: [READ | WRITE | OFFSET_ADD | FREE];
+
+            fn strnlen(
+                cs: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                maxlen: size_t,
+            ) -> size_t;
+
+            fn strpbrk(
+                // `WRITE` because the return type is derived from `cs`'s provenance.
+                cs: *const c_char: [READ | WRITE | OFFSET_ADD | NON_NULL],
+                ct: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+            ) -> *mut c_char: [READ | WRITE | OFFSET_ADD | OFFSET_SUB];
+
             fn strrchr(
                 // `WRITE` because the return type is derived from `cs`'s provenance.
                 cs: *const c_char: [READ | WRITE | OFFSET_ADD | NON_NULL],
                 c: c_int,
             ) -> *mut c_char: [READ | WRITE | OFFSET_ADD | OFFSET_SUB];
 
+            fn strspn(
+                cs: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                ct: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+            ) -> size_t;
+
             fn strstr(
                 // `WRITE` because it's returned.
                 cs: *const c_char: [READ | WRITE | OFFSET_ADD | NON_NULL],
                 ct: *const c_char: [READ | OFFSET_ADD | NON_NULL],
             ) -> *mut c_char: [READ | WRITE | OFFSET_ADD | OFFSET_SUB];
 
+            fn strtod(
+                s: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                endp: *mut *mut c_char: [WRITE, WRITE | OFFSET_ADD],
+            ) -> c_double;
+
+            fn strtok(
+                // Null to continue with the previous string, which is kept in static storage.
+                s: *mut c_char: [READ | WRITE | OFFSET_ADD],
+                t: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                // Points into the previous or current `s`.
+            ) -> *mut c_char: [READ | WRITE | OFFSET_ADD];
+
+            fn strtok_r(
+                s: *mut c_char: [READ | WRITE | OFFSET_ADD],
+                t: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                p: *mut *mut c_char: [READ | WRITE | NON_NULL, READ | WRITE | OFFSET_ADD],
+                // Points into `s` or `*p`.
+            ) -> *mut c_char: [READ | WRITE | OFFSET_ADD];
+
             fn strtol(
                 s: *const c_char: [READ | OFFSET_ADD | NON_NULL],
                 endp: *mut *mut c_char: [WRITE, WRITE | OFFSET_ADD],
@@ -1390,6 +1649,12 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 base: c_int,
             ) -> c_ulong;
 
+            fn strtoull(
+                s: *const c_char: [READ | OFFSET_ADD | NON_NULL],
+                endp: *mut *mut c_char: [WRITE, WRITE | OFFSET_ADD],
+                base: c_int,
+            ) -> c_ulonglong;
+
             fn sysconf(
                 name: c_int,
             ) -> c_long;
@@ -1401,6 +1666,10 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
             //     ...
             // );
 
+            fn system(
+                s: *const c_char: [READ | OFFSET_ADD],
+            ) -> c_int;
+
             fn time(
                 time: *mut time_t: [WRITE],
             ) -> time_t;
@@ -1409,6 +1678,8 @@ pub const fn all_known_fns() -> &'static [KnownFn] {
                 tm: *mut tm: [READ | WRITE | NON_NULL],
             ) -> time_t;
 
+            fn tmpfile() -> *mut FILE: [READ | WRITE];
+
             // TODO(kkysen) Not in `libc` crate.
             // fn tzset;
 
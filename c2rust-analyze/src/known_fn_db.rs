@@ -0,0 +1,192 @@
+//! User-supplied [`KnownFn`]s, for functions from third-party C libraries.
+//!
+//! The built-in [`KnownFn`]s only cover `libc`.  Calls to other `extern` functions, such as those
+//! from zlib or OpenSSL, give no information about how the callee uses its pointer arguments, so
+//! the analysis can't do much with the pointers passed to them.  Users can describe such functions
+//! in a TOML file named by `C2RUST_ANALYZE_KNOWN_FNS_DB`:
+//!
+//! ```toml
+//! [[fn]]
+//! name = "inflate"
+//! # One entry per declared argument.  `perms` has a permission set for each `*` in the
+//! # argument's type, from the outside in.
+//! args = [
+//!     { perms = ["READ | WRITE | NON_NULL"] },
+//!     {},
+//! ]
+//!
+//! [[fn]]
+//! name = "gzwrite"
+//! # `pointee` gives the type that a `void*` argument actually points to.
+//! args = [
+//!     { perms = ["READ | WRITE | NON_NULL"] },
+//!     { perms = ["READ | OFFSET_ADD | NON_NULL"], pointee = "u8" },
+//!     {},
+//! ]
+//! ret = {}
+//! ```
+//!
+//! Entries are keyed by link name and replace any built-in [`KnownFn`] of the same name.  Like
+//! the built-in ones, arguments should list every permission the function might need, and return
+//! values only the permissions it guarantees.  Pointee types are limited to primitive types.
+
+use crate::context::PermissionSet;
+use crate::known_fn::{KnownFn, KnownFnTy};
+use rustc_middle::ty::{Ty, TyCtxt};
+use std::fs;
+use std::io;
+use toml_edit::{Document, Item, TableLike};
+
+/// A [`KnownFn`] loaded from the database, along with the pointee types it declares for its
+/// arguments (`Some(i)`) and return value (`None`).
+pub struct UserKnownFn<'tcx> {
+    pub known_fn: &'static KnownFn,
+    pub pointees: Vec<(Option<usize>, Ty<'tcx>)>,
+}
+
+/// Load the database at `path`.  Malformed entries cause a panic that names the entry.
+pub fn load_known_fn_db<'tcx>(tcx: TyCtxt<'tcx>, path: &str) -> io::Result<Vec<UserKnownFn<'tcx>>> {
+    let src = fs::read_to_string(path)?;
+    let doc = src
+        .parse::<Document>()
+        .unwrap_or_else(|e| panic!("failed to parse {path}: {e}"));
+    let entries = match doc.get("fn") {
+        Some(item) => item
+            .as_array_of_tables()
+            .unwrap_or_else(|| panic!("{path}: `fn` must be an array of tables")),
+        None => return Ok(Vec::new()),
+    };
+
+    let mut fns = Vec::new();
+    for entry in entries.iter() {
+        let name = entry
+            .get("name")
+            .and_then(Item::as_str)
+            .unwrap_or_else(|| panic!("{path}: every `fn` needs a `name`"));
+        let what = |desc: &str| format!("{path}: {desc} of `{name}`");
+
+        let mut pointees = Vec::new();
+        let mut inputs = Vec::new();
+        if let Some(args) = entry.get("args") {
+            let args = args
+                .as_array()
+                .unwrap_or_else(|| panic!("{}: `args` must be an array", what("arguments")));
+            for (i, arg) in args.iter().enumerate() {
+                let arg = arg.as_inline_table().unwrap_or_else(|| {
+                    panic!("{} must be a table", what(&format!("argument {i}")))
+                });
+                let (known_ty, pointee) =
+                    parse_known_fn_ty(tcx, arg, &what(&format!("argument {i}")));
+                inputs.push(known_ty);
+                if let Some(ty) = pointee {
+                    pointees.push((Some(i), ty));
+                }
+            }
+        }
+
+        let output = match entry.get("ret").and_then(Item::as_table_like) {
+            Some(ret) => {
+                let (known_ty, pointee) = parse_known_fn_ty(tcx, ret, &what("return value"));
+                if let Some(ty) = pointee {
+                    pointees.push((None, ty));
+                }
+                known_ty
+            }
+            None => KnownFnTy {
+                name: "",
+                ty: "",
+                perms: &[],
+                source: "",
+            },
+        };
+
+        let known_fn = KnownFn {
+            name: leak_str(name.to_owned()),
+            inputs: Box::leak(inputs.into_boxed_slice()),
+            output,
+            source: leak_str(format!("{name} (from {path})")),
+        };
+        fns.push(UserKnownFn {
+            known_fn: Box::leak(Box::new(known_fn)),
+            pointees,
+        });
+    }
+    Ok(fns)
+}
+
+fn parse_known_fn_ty<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    t: &dyn TableLike,
+    what: &str,
+) -> (KnownFnTy, Option<Ty<'tcx>>) {
+    let mut perms = Vec::new();
+    if let Some(item) = t.get("perms") {
+        let arr = item
+            .as_array()
+            .unwrap_or_else(|| panic!("{what}: `perms` must be an array"));
+        for val in arr.iter() {
+            let s = val
+                .as_str()
+                .unwrap_or_else(|| panic!("{what}: each entry of `perms` must be a string"));
+            perms.push(parse_perms(s).unwrap_or_else(|e| panic!("{what}: {e}")));
+        }
+    }
+
+    let pointee = t.get("pointee").map(|item| {
+        let name = item
+            .as_str()
+            .unwrap_or_else(|| panic!("{what}: `pointee` must be a string"));
+        primitive_ty(tcx, name)
+            .unwrap_or_else(|| panic!("{what}: unsupported pointee type `{name}`"))
+    });
+
+    let known_ty = KnownFnTy {
+        name: "",
+        ty: "",
+        perms: Box::leak(perms.into_boxed_slice()),
+        source: leak_str(what.to_owned()),
+    };
+    (known_ty, pointee)
+}
+
+/// Parse a permission set written as in the built-in [`KnownFn`]s, like `READ | WRITE`.
+fn parse_perms(s: &str) -> Result<PermissionSet, String> {
+    let mut perms = PermissionSet::empty();
+    for name in s.split('|').map(str::trim).filter(|name| !name.is_empty()) {
+        perms |= match name {
+            "READ" => PermissionSet::READ,
+            "WRITE" => PermissionSet::WRITE,
+            "UNIQUE" => PermissionSet::UNIQUE,
+            "LINEAR" => PermissionSet::LINEAR,
+            "OFFSET_ADD" => PermissionSet::OFFSET_ADD,
+            "OFFSET_SUB" => PermissionSet::OFFSET_SUB,
+            "FREE" => PermissionSet::FREE,
+            "NON_NULL" => PermissionSet::NON_NULL,
+            _ => return Err(format!("unknown permission `{name}`")),
+        };
+    }
+    Ok(perms)
+}
+
+fn primitive_ty<'tcx>(tcx: TyCtxt<'tcx>, name: &str) -> Option<Ty<'tcx>> {
+    let types = &tcx.types;
+    Some(match name {
+        "u8" | "c_uchar" => types.u8,
+        "i8" | "c_char" | "c_schar" => types.i8,
+        "u16" | "c_ushort" => types.u16,
+        "i16" | "c_short" => types.i16,
+        "u32" | "c_uint" => types.u32,
+        "i32" | "c_int" => types.i32,
+        "u64" | "c_ulonglong" => types.u64,
+        "i64" | "c_longlong" => types.i64,
+        "usize" => types.usize,
+        "isize" => types.isize,
+        "f32" | "c_float" => types.f32,
+        "f64" | "c_double" => types.f64,
+        _ => return None,
+    })
+}
+
+fn leak_str(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
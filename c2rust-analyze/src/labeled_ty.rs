@@ -202,10 +202,19 @@ impl<'tcx, L: Copy> LabeledTyCtxt<'tcx, L> {
                 let args = elems.iter().map(|ty| self.label(ty, f)).collect::<Vec<_>>();
                 self.mk(ty, self.mk_slice(&args), label)
             }
+            Closure(_, substs) => {
+                // The captured variables, in field order.
+                let args = substs
+                    .as_closure()
+                    .upvar_tys()
+                    .map(|ty| self.label(ty, f))
+                    .collect::<Vec<_>>();
+                self.mk(ty, self.mk_slice(&args), label)
+            }
 
             // Types that aren't actually supported by this code yet
-            Dynamic(..) | Closure(..) | Generator(..) | GeneratorWitness(..) | Projection(..)
-            | Opaque(..) | Param(..) | Bound(..) | Placeholder(..) | Infer(..) | Error(..) => {
+            Dynamic(..) | Generator(..) | GeneratorWitness(..) | Projection(..) | Opaque(..)
+            | Param(..) | Bound(..) | Placeholder(..) | Infer(..) | Error(..) => {
                 self.mk(ty, &[], label)
             }
         }
@@ -404,12 +413,12 @@ impl<'tcx, L: Copy> LabeledTyCtxt<'tcx, L> {
                 lty.ty
             }
             Tuple(_) => self.tcx.mk_tup(args.iter().cloned()),
+            // Closure types are never written out, so they're left as they are.
+            Closure(..) => lty.ty,
 
             // Types that aren't actually supported by this code yet
-            Dynamic(..) | Closure(..) | Generator(..) | GeneratorWitness(..) | Projection(..)
-            | Opaque(..) | Param(..) | Bound(..) | Placeholder(..) | Infer(..) | Error(..) => {
-                lty.ty
-            }
+            Dynamic(..) | Generator(..) | GeneratorWitness(..) | Projection(..) | Opaque(..)
+            | Param(..) | Bound(..) | Placeholder(..) | Infer(..) | Error(..) => lty.ty,
         };
 
         func(ty, &args, lty.label)
@@ -0,0 +1,384 @@
+//! Association of pointer fields with the integer fields that hold their lengths.
+//!
+//! C structs often carry a pointer to an array together with the number of elements it holds:
+//!
+//! ```ignore
+//! pub struct list {
+//!     pub items: *mut i32,
+//!     pub n_items: size_t,
+//! }
+//!
+//! while i < (*l).n_items {
+//!     total += *(*l).items.offset(i as isize);
+//!     i += 1;
+//! }
+//! ```
+//!
+//! We pair a pointer field with a length field when the code bounds its indices by that field: a
+//! local `i` is compared against `(*l).n_items`, and the same `i` is used to offset
+//! `(*l).items` of the same struct.  A pointer field is paired only if one integer field has
+//! strictly more such evidence than any other.
+//!
+//! When a paired pointer field is rewritten to a slice, storing a raw pointer into it would
+//! otherwise be an unsupported cast.  Instead, the raw pointer is converted with
+//! `slice::from_raw_parts`, taking the length from the paired field: either the value stored to
+//! the length field alongside the pointer, or the length field itself if it was set first.
+//!
+//! With `--dynamic-checks`, an offset of a paired field that remains a raw pointer, as in
+//! `(*l).items.offset(i)` above, is rewritten to an offset of `slice::from_raw_parts` using the
+//! length field from the comparison, `(*l).n_items`, with an explicit bounds check.  The check is
+//! left out when the offset is only reached while the comparison holds, before any change to `i`,
+//! as in the loop above: the loop condition already tests the bound once per iteration.
+
+use crate::context::GlobalAnalysisCtxt;
+use log::debug;
+use rustc_ast::LitKind;
+use rustc_hir as hir;
+use rustc_hir::def::Res;
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{BinOpKind, Block, Expr, ExprKind, HirId, StmtKind, UnOp};
+use rustc_middle::ty::{Ty, TyCtxt, TyKind, TypeckResults};
+use rustc_span::Span;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Debug, Default)]
+pub struct LenFieldInfo {
+    /// Pointer fields, mapped to the integer field that holds the number of elements they point
+    /// to.
+    pub fields: HashMap<DefId, DefId>,
+    /// Stores to a paired pointer field, as in `(*l).items = p`, mapped to an expression that
+    /// gives the number of elements stored.  Keys are the spans of the assignment expressions.
+    pub lens: HashMap<Span, Span>,
+    /// Offsets of a paired pointer field, as in `(*l).items.offset(i)`, mapped to the paired
+    /// length field of the same struct, as it appears in the comparison that bounds the index.
+    /// Keys are the spans of the offset calls.  Used by `GlobalAnalysisCtxt::dynamic_checks`.
+    pub offset_lens: HashMap<Span, Span>,
+}
+
+/// An access to a field of a struct, as in `(*l).items`.
+#[derive(Clone, Copy, Debug)]
+struct FieldAccess {
+    field: DefId,
+    /// The local variable that holds the struct or a pointer to it.  Two accesses with the same
+    /// `owner` refer to the same struct.
+    owner: HirId,
+}
+
+/// Visitor that collects evidence for pairing pointer fields with length fields within a body.
+struct LenFieldVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    /// Structs whose fields are rewritten by another pass, and so shouldn't be paired.
+    excluded: &'a HashSet<DefId>,
+    /// Integer fields compared against a local, as `(local, owner, field)`, with the span of the
+    /// field access in the comparison.
+    bounds: HashMap<(HirId, HirId, DefId), Span>,
+    /// Pointer fields offset by a local, as `(local, owner, field)`, with the spans of the
+    /// offset calls.
+    offsets: HashMap<(HirId, HirId, DefId), Vec<Span>>,
+}
+
+impl<'a, 'tcx> LenFieldVisitor<'a, 'tcx> {
+    /// If `ex` accesses a field of a local struct, or of a struct behind a local pointer, return
+    /// the field and the local.  Only fields whose type satisfies `filter` are returned.
+    fn field_access(
+        &self,
+        ex: &'tcx Expr<'tcx>,
+        filter: impl Fn(Ty<'tcx>) -> bool,
+    ) -> Option<FieldAccess> {
+        field_access(self.tcx, self.typeck_results, self.excluded, ex, filter)
+    }
+
+    /// Evidence pairs found in this body, as `(ptr field, len field)`.
+    fn pairs(&self) -> impl Iterator<Item = (DefId, DefId)> + '_ {
+        self.offsets.keys().flat_map(move |&(local, owner, ptr)| {
+            let parent = self.tcx.parent(ptr);
+            self.bounds
+                .keys()
+                .filter(move |&&(l, o, len)| {
+                    l == local && o == owner && self.tcx.parent(len) == parent
+                })
+                .map(move |&(_, _, len)| (ptr, len))
+        })
+    }
+
+    /// Offsets bounded by a length field, as `(ptr field, len field, offset span, len span)`.
+    fn offset_sites(&self) -> impl Iterator<Item = (DefId, DefId, Span, Span)> + '_ {
+        self.offsets
+            .iter()
+            .flat_map(move |(&(local, owner, ptr), spans)| {
+                let parent = self.tcx.parent(ptr);
+                self.bounds
+                    .iter()
+                    .filter(move |&(&(l, o, len), _)| {
+                        l == local && o == owner && self.tcx.parent(len) == parent
+                    })
+                    .flat_map(move |(&(_, _, len), &len_span)| {
+                        spans.iter().map(move |&span| (ptr, len, span, len_span))
+                    })
+            })
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for LenFieldVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            // `i < (*l).n_items`
+            ExprKind::Binary(op, a, b)
+                if matches!(
+                    op.node,
+                    BinOpKind::Lt | BinOpKind::Le | BinOpKind::Gt | BinOpKind::Ge | BinOpKind::Ne
+                ) =>
+            {
+                let bound = |idx: &'tcx Expr<'tcx>, len: &'tcx Expr<'tcx>| {
+                    let local = local_var(peel_casts(idx))?;
+                    let len = peel_casts(len);
+                    let fa = self.field_access(len, |ty| ty.is_integral())?;
+                    Some(((local, fa.owner, fa.field), len.span))
+                };
+                if let Some((x, span)) = bound(a, b).or_else(|| bound(b, a)) {
+                    self.bounds.insert(x, span);
+                }
+            }
+
+            // `(*l).items.offset(i as isize)`
+            ExprKind::MethodCall(seg, [ptr, idx], _)
+                if matches!(
+                    seg.ident.as_str(),
+                    "offset" | "add" | "wrapping_offset" | "wrapping_add"
+                ) =>
+            {
+                let fa = self.field_access(ptr, |ty| ty.is_unsafe_ptr());
+                if let (Some(fa), Some(local)) = (fa, local_var(peel_casts(idx))) {
+                    self.offsets
+                        .entry((local, fa.owner, fa.field))
+                        .or_default()
+                        .push(ex.span);
+                }
+            }
+
+            _ => {}
+        }
+
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Visitor that finds the length stored alongside each store to a paired pointer field.
+struct LenStoreVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    fields: &'a HashMap<DefId, DefId>,
+    /// Locals that are assigned somewhere in the body.  The value of such a local may differ
+    /// between the store to the length field and the store to the pointer field.
+    assigned: HashSet<HirId>,
+    lens: HashMap<Span, Span>,
+}
+
+impl<'a, 'tcx> LenStoreVisitor<'a, 'tcx> {
+    /// If `ex` is a store to a field of a struct, as in `(*l).items = p`, return the field
+    /// access and the stored value.
+    fn field_store(&self, ex: &'tcx Expr<'tcx>) -> Option<(FieldAccess, &'tcx Expr<'tcx>)> {
+        match ex.kind {
+            ExprKind::Assign(lhs, rhs, _) => {
+                let no_excluded = HashSet::new();
+                let fa = field_access(self.tcx, self.typeck_results, &no_excluded, lhs, |_| true)?;
+                Some((fa, rhs))
+            }
+            _ => None,
+        }
+    }
+
+    /// Check whether the value of `ex` is the same wherever it appears in the body.
+    fn is_stable(&self, ex: &Expr) -> bool {
+        match peel_casts(ex).kind {
+            ExprKind::Lit(ref lit) => matches!(lit.node, LitKind::Int(..)),
+            _ => local_var(peel_casts(ex)).map_or(false, |l| !self.assigned.contains(&l)),
+        }
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for LenStoreVisitor<'a, 'tcx> {
+    fn visit_block(&mut self, block: &'tcx Block<'tcx>) {
+        let stores = block
+            .stmts
+            .iter()
+            .filter_map(|stmt| match stmt.kind {
+                StmtKind::Semi(ex) | StmtKind::Expr(ex) => Some(ex),
+                _ => None,
+            })
+            .filter_map(|ex| self.field_store(ex).map(|(fa, rhs)| (ex, fa, rhs)))
+            .collect::<Vec<_>>();
+        for (i, &(ex, fa, _)) in stores.iter().enumerate() {
+            let len_field = match self.fields.get(&fa.field) {
+                Some(&x) => x,
+                None => continue,
+            };
+            // `(*l).items = p; (*l).n_items = n;` uses `n`, as does `(*l).n_items = n;
+            // (*l).items = p;`.  If `n` might change in between, the second form uses
+            // `(*l).n_items` instead.
+            let len_store = stores
+                .iter()
+                .enumerate()
+                .find(|&(_, &(_, lfa, _))| lfa.field == len_field && lfa.owner == fa.owner);
+            let len = match len_store {
+                Some((_, &(_, _, value))) if self.is_stable(value) => value.span,
+                Some((j, &(len_ex, ..))) if j < i => match len_ex.kind {
+                    ExprKind::Assign(lhs, ..) => lhs.span,
+                    _ => unreachable!(),
+                },
+                _ => continue,
+            };
+            self.lens.insert(ex.span, len);
+        }
+        intravisit::walk_block(self, block);
+    }
+}
+
+fn field_access<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    excluded: &HashSet<DefId>,
+    ex: &'tcx Expr<'tcx>,
+    filter: impl Fn(Ty<'tcx>) -> bool,
+) -> Option<FieldAccess> {
+    let base = match ex.kind {
+        ExprKind::Field(base, _) => base,
+        _ => return None,
+    };
+    let adt_def = match *typeck_results.expr_ty_adjusted(base).peel_refs().kind() {
+        TyKind::Adt(adt_def, _) if adt_def.is_struct() => adt_def,
+        _ => return None,
+    };
+    if !adt_def.did().is_local() || excluded.contains(&adt_def.did()) {
+        return None;
+    }
+    let idx = *typeck_results.field_indices().get(ex.hir_id)?;
+    let field = adt_def.non_enum_variant().fields.get(idx)?.did;
+    if !filter(tcx.type_of(field)) {
+        return None;
+    }
+    let owner = match base.kind {
+        ExprKind::Unary(UnOp::Deref, ptr) => local_var(ptr)?,
+        _ => local_var(base)?,
+    };
+    Some(FieldAccess { field, owner })
+}
+
+fn local_var(ex: &Expr) -> Option<HirId> {
+    match ex.kind {
+        ExprKind::Path(hir::QPath::Resolved(_, path)) => match path.res {
+            Res::Local(hir_id) => Some(hir_id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn peel_casts<'a, 'tcx>(mut ex: &'a Expr<'tcx>) -> &'a Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+/// Pair pointer fields with the integer fields that hold their lengths, and find the length for
+/// each store to a paired pointer field.
+pub fn find_len_fields(gacx: &GlobalAnalysisCtxt, all_fn_ldids: &[LocalDefId]) -> LenFieldInfo {
+    let tcx = gacx.tcx;
+    // Structs that become a `Vec` have their own handling of the length field.
+    let excluded = gacx
+        .vec_structs
+        .structs
+        .keys()
+        .copied()
+        .collect::<HashSet<_>>();
+
+    let bodies = all_fn_ldids
+        .iter()
+        .filter_map(|&ldid| tcx.hir().maybe_body_owned_by(ldid))
+        .collect::<Vec<_>>();
+
+    let mut evidence = HashMap::<DefId, HashMap<DefId, usize>>::new();
+    let mut offset_sites = Vec::new();
+    for &hir_body_id in &bodies {
+        let mut v = LenFieldVisitor {
+            tcx,
+            typeck_results: tcx.typeck_body(hir_body_id),
+            excluded: &excluded,
+            bounds: HashMap::new(),
+            offsets: HashMap::new(),
+        };
+        v.visit_body(tcx.hir().body(hir_body_id));
+        for (ptr, len) in v.pairs() {
+            *evidence.entry(ptr).or_default().entry(len).or_default() += 1;
+        }
+        offset_sites.extend(v.offset_sites());
+    }
+
+    let mut info = LenFieldInfo::default();
+    for (ptr, counts) in evidence {
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+        match counts[..] {
+            [(len, _)] => {
+                info.fields.insert(ptr, len);
+            }
+            [(len, n1), (_, n2), ..] if n1 > n2 => {
+                info.fields.insert(ptr, len);
+            }
+            _ => {
+                debug!("ambiguous length field for {ptr:?}: {counts:?}");
+            }
+        }
+    }
+    if info.fields.is_empty() {
+        return info;
+    }
+    debug!(
+        "paired pointer fields with length fields: {:?}",
+        info.fields
+    );
+
+    for (ptr, len, span, len_span) in offset_sites {
+        if info.fields.get(&ptr) == Some(&len) {
+            info.offset_lens.insert(span, len_span);
+        }
+    }
+
+    for &hir_body_id in &bodies {
+        let body = tcx.hir().body(hir_body_id);
+        let mut v = LenStoreVisitor {
+            tcx,
+            typeck_results: tcx.typeck_body(hir_body_id),
+            fields: &info.fields,
+            assigned: HashSet::new(),
+            lens: HashMap::new(),
+        };
+        // Collect assigned locals first, so `is_stable` is accurate for every block.
+        intravisit::walk_body(&mut AssignedLocals(&mut v.assigned), body);
+        v.visit_body(body);
+        info.lens.extend(v.lens);
+    }
+    info
+}
+
+/// Visitor that collects the locals that are assigned or mutably borrowed within a body.
+struct AssignedLocals<'a>(&'a mut HashSet<HirId>);
+
+impl<'a, 'tcx> Visitor<'tcx> for AssignedLocals<'a> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            ExprKind::Assign(lhs, ..)
+            | ExprKind::AssignOp(_, lhs, _)
+            | ExprKind::AddrOf(_, hir::Mutability::Mut, lhs) => {
+                if let Some(l) = local_var(lhs) {
+                    self.0.insert(l);
+                }
+            }
+            _ => {}
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
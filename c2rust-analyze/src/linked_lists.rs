@@ -0,0 +1,580 @@
+//! Detection of linked-list node structs, for the optional linked-list rewrite mode.
+//!
+//! A node struct links to other nodes of the same type through intrusive pointer fields:
+//!
+//! ```ignore
+//! struct Node {
+//!     val: i32,
+//!     prev: *mut Node,
+//!     next: *mut Node,
+//! }
+//! ```
+//!
+//! When `C2RUST_ANALYZE_LINKED_LISTS=1` is set, we look for structs with one or two fields that
+//! point to the struct itself, and handle them according to their shape:
+//!
+//! * In a singly-linked list, each node is owned by the `next` link of the node before it.  The
+//!   analysis only infers this when it sees the list being freed, so the `next` link is given the
+//!   `FREE` permission up front.  If the link is also `UNIQUE`, the normal rewrites turn it into
+//!   `Option<Box<Node>>`.
+//!
+//! * In a doubly-linked list, each node is pointed to by both the `next` link of its predecessor
+//!   and the `prev` link of its successor, so no pointer to a node is ever `UNIQUE`.  Instead, the
+//!   nodes are moved into an arena (a thread-local `Vec<Option<Node>>`), and every pointer to a
+//!   node is rewritten to an `Option<usize>` index into it.  Allocations push a new node, each
+//!   `free` empties the node's slot, and each access to a field goes through the arena.
+//!
+//! As with `crate::refcount`, only simple uses of pointers to doubly-linked nodes are supported,
+//! and any other use, such as taking the address of a field, a cast, or pointer arithmetic,
+//! prevents the struct from being rewritten.  Every field of an arena node must be `Copy`, since
+//! field values are copied out of the arena.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::rewrite;
+use crate::util::{self, Callee};
+use log::debug;
+use rustc_ast::LitKind;
+use rustc_hir as hir;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{Expr, ExprKind, UnOp};
+use rustc_middle::ty::adjustment::Adjust;
+use rustc_middle::ty::{
+    GenericArgKind, ParamEnv, Ty, TyCtxt, TyKind, TypeckResults, WithOptConstParam,
+};
+use rustc_span::{Span, DUMMY_SP};
+use std::collections::{HashMap, HashSet};
+
+/// The shape of a linked list, given by the fields of its node struct that link to other nodes.
+#[derive(Clone, Copy, Debug)]
+pub enum ListShape {
+    Singly { next: DefId },
+    Doubly { next: DefId, prev: DefId },
+}
+
+/// A single use of a pointer to a doubly-linked node.
+#[derive(Clone, Debug)]
+pub enum ListPtrUse {
+    /// A new node is allocated, as in `malloc(n) as *mut Node`.  `expr` is the whole cast
+    /// expression.
+    Alloc { expr: Span },
+    /// The node is freed, as in `free(p as *mut c_void)`.
+    Free { call: Span, ptr: Span },
+    /// A null pointer, as in `0 as *mut Node` or `ptr::null_mut()`.
+    Null { expr: Span },
+    /// The pointer is checked for null, as in `p.is_null()`.
+    IsNull { expr: Span, ptr: Span },
+    /// A field is read through the pointer, as in `(*p).x` or `(*p).pos.x`.  `proj` is the
+    /// projection applied to the node, as in `.pos.x`.
+    Read { expr: Span, ptr: Span, proj: String },
+    /// A field is written through the pointer, as in `(*p).x = v` or `(*p).x += v`.  `op` is the
+    /// assignment operator.
+    Write {
+        expr: Span,
+        ptr: Span,
+        proj: String,
+        op: String,
+        rhs: Span,
+    },
+    /// A pointer type is written in the source, as in `*mut Node`.
+    Ty { ty: Span },
+    /// Any other use of the pointer.  This prevents the struct from being rewritten.
+    Other,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LinkedListInfo {
+    /// Linked-list node structs, along with the shape of the list they form.
+    pub structs: HashMap<DefId, ListShape>,
+    /// Doubly-linked node structs whose pointers are rewritten to indices into an arena.
+    pub arena_structs: HashSet<DefId>,
+    /// Struct fields whose types mention pointers to any of the `arena_structs`, including the
+    /// links themselves.  These are excluded from the normal rewrites.
+    pub arena_fields: HashSet<DefId>,
+    /// Functions that mention pointers to any of the `arena_structs`.  These are excluded from
+    /// the normal rewrites and handled entirely by `rewrite::gen_linked_list_rewrites`.
+    pub fns: HashSet<DefId>,
+}
+
+/// If `ty` is a raw pointer to one of the `candidates`, return the struct's `DefId`.
+fn list_adt(candidates: &HashSet<DefId>, ty: Ty) -> Option<DefId> {
+    match *ty.kind() {
+        TyKind::RawPtr(tm) => match *tm.ty.kind() {
+            TyKind::Adt(adt_def, _) if candidates.contains(&adt_def.did()) => Some(adt_def.did()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Check whether `ty` contains a raw pointer to one of the `candidates`, and if so, return the
+/// struct's `DefId`.
+fn mentions_list_adt(candidates: &HashSet<DefId>, ty: Ty) -> Option<DefId> {
+    ty.walk().find_map(|arg| match arg.unpack() {
+        GenericArgKind::Type(ty) => list_adt(candidates, ty),
+        _ => None,
+    })
+}
+
+/// Check whether `ex` is the literal `0`, possibly with casts, as in `0 as libc::c_int`.
+fn is_zero(mut ex: &Expr) -> bool {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    match ex.kind {
+        ExprKind::Lit(ref lit) => matches!(lit.node, LitKind::Int(0, _)),
+        _ => false,
+    }
+}
+
+/// Visitor that finds all uses of pointers to the `candidates` structs within a body.
+pub struct ListPtrUseVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: Option<&'tcx TypeckResults<'tcx>>,
+    candidates: &'a HashSet<DefId>,
+    pub uses: Vec<(DefId, ListPtrUse)>,
+}
+
+impl<'a, 'tcx> ListPtrUseVisitor<'a, 'tcx> {
+    pub fn new(
+        tcx: TyCtxt<'tcx>,
+        typeck_results: Option<&'tcx TypeckResults<'tcx>>,
+        candidates: &'a HashSet<DefId>,
+    ) -> ListPtrUseVisitor<'a, 'tcx> {
+        ListPtrUseVisitor {
+            tcx,
+            typeck_results,
+            candidates,
+            uses: Vec::new(),
+        }
+    }
+
+    fn typeck_results(&self) -> &'tcx TypeckResults<'tcx> {
+        self.typeck_results
+            .expect("expressions should only be visited inside a body")
+    }
+
+    /// If `ex` is a pointer to one of the `candidates`, return the struct's `DefId`.
+    fn list_expr(&self, ex: &Expr<'tcx>) -> Option<DefId> {
+        list_adt(self.candidates, self.typeck_results().expr_ty(ex))
+    }
+
+    /// If `ex` is a call, return the callee according to `util::ty_callee`.
+    fn callee(&self, ex: &Expr<'tcx>) -> Option<Callee<'tcx>> {
+        match ex.kind {
+            ExprKind::Call(func, _) => Some(util::ty_callee(
+                self.tcx,
+                self.typeck_results().expr_ty(func),
+            )),
+            _ => None,
+        }
+    }
+
+    /// If `ex` is a field of a node, as in `(*p).x` or `(*p).pos.x`, return the pointer `p`, the
+    /// struct it points to, and the projection `.pos.x`.
+    fn node_place(&self, ex: &'tcx Expr<'tcx>) -> Option<(&'tcx Expr<'tcx>, DefId, String)> {
+        let mut fields = Vec::new();
+        let mut cur = ex;
+        loop {
+            match cur.kind {
+                ExprKind::Field(base, ident) => {
+                    fields.push(ident);
+                    cur = base;
+                }
+                ExprKind::Unary(UnOp::Deref, ptr) if !fields.is_empty() => {
+                    let adt = self.list_expr(ptr)?;
+                    let proj = fields.iter().rev().map(|f| format!(".{f}")).collect();
+                    return Some((ptr, adt, proj));
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// If `ex` is a place that involves dereferencing a pointer to a node, such as `*p` or
+    /// `(*p).arr[i]`, return the struct it points to.
+    fn derefs_node(&self, mut ex: &'tcx Expr<'tcx>) -> Option<DefId> {
+        loop {
+            match ex.kind {
+                ExprKind::Field(base, _) | ExprKind::Index(base, _) => ex = base,
+                ExprKind::Unary(UnOp::Deref, ptr) => {
+                    if let Some(adt) = self.list_expr(ptr) {
+                        return Some(adt);
+                    }
+                    ex = ptr;
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for ListPtrUseVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            // `(*p).x = v`, `(*p).x += v`
+            ExprKind::Assign(lhs, rhs, _) | ExprKind::AssignOp(_, lhs, rhs) => {
+                if let Some((ptr, adt, proj)) = self.node_place(lhs) {
+                    let op = match ex.kind {
+                        ExprKind::AssignOp(op, _, _) => format!("{}=", op.node.as_str()),
+                        _ => "=".into(),
+                    };
+                    self.uses.push((
+                        adt,
+                        ListPtrUse::Write {
+                            expr: ex.span,
+                            ptr: ptr.span,
+                            proj,
+                            op,
+                            rhs: rhs.span,
+                        },
+                    ));
+                    self.visit_expr(ptr);
+                    self.visit_expr(rhs);
+                    return;
+                }
+                if let Some(adt) = self.derefs_node(lhs) {
+                    debug!("unsupported write to list node at {:?}", lhs.span);
+                    self.uses.push((adt, ListPtrUse::Other));
+                }
+            }
+
+            // `(*p).x`
+            ExprKind::Field(..) => {
+                if let Some((ptr, adt, proj)) = self.node_place(ex) {
+                    // Field values are copied out of the arena, so they can't be borrowed.
+                    let borrowed = self
+                        .typeck_results()
+                        .expr_adjustments(ex)
+                        .iter()
+                        .any(|adj| matches!(adj.kind, Adjust::Borrow(_)));
+                    if borrowed {
+                        debug!("unsupported borrow of list node field at {:?}", ex.span);
+                        self.uses.push((adt, ListPtrUse::Other));
+                    } else {
+                        self.uses.push((
+                            adt,
+                            ListPtrUse::Read {
+                                expr: ex.span,
+                                ptr: ptr.span,
+                                proj,
+                            },
+                        ));
+                    }
+                    self.visit_expr(ptr);
+                    return;
+                }
+            }
+
+            // `&(*p).x`, `*p`
+            ExprKind::AddrOf(_, _, pl) | ExprKind::Unary(UnOp::Deref, pl) => {
+                let adt = match ex.kind {
+                    ExprKind::AddrOf(..) => self.derefs_node(pl),
+                    _ => self.list_expr(pl),
+                };
+                if let Some(adt) = adt {
+                    debug!("unsupported use of list node at {:?}", ex.span);
+                    self.uses.push((adt, ListPtrUse::Other));
+                }
+            }
+
+            // `malloc(n) as *mut Node`, `0 as *mut Node`
+            ExprKind::Cast(inner, _) => {
+                if let Some(adt) = self.list_expr(ex) {
+                    if matches!(self.callee(inner), Some(Callee::Malloc | Callee::Calloc)) {
+                        self.uses.push((adt, ListPtrUse::Alloc { expr: ex.span }));
+                        // The whole expression is replaced, including the `*mut Node` type.
+                        return;
+                    }
+                    if is_zero(inner) {
+                        self.uses.push((adt, ListPtrUse::Null { expr: ex.span }));
+                        return;
+                    }
+                }
+                // Casts to and from node pointers are only supported for `malloc` and `free`,
+                // which are handled separately.
+                if let Some(adt) = self.list_expr(inner).or_else(|| self.list_expr(ex)) {
+                    debug!("unsupported cast of list node pointer at {:?}", ex.span);
+                    self.uses.push((adt, ListPtrUse::Other));
+                }
+            }
+
+            ExprKind::Call(_, args) => match self.callee(ex) {
+                // `free(p as *mut c_void)`
+                Some(Callee::Free) => {
+                    if let [arg] = args {
+                        if let ExprKind::Cast(ptr, _) = arg.kind {
+                            if let Some(adt) = self.list_expr(ptr) {
+                                self.uses.push((
+                                    adt,
+                                    ListPtrUse::Free {
+                                        call: ex.span,
+                                        ptr: ptr.span,
+                                    },
+                                ));
+                                self.visit_expr(ptr);
+                                return;
+                            }
+                        }
+                    }
+                }
+                // `ptr::null_mut()`
+                Some(Callee::Null { .. }) => {
+                    if let Some(adt) = self.list_expr(ex) {
+                        self.uses.push((adt, ListPtrUse::Null { expr: ex.span }));
+                        return;
+                    }
+                }
+                _ => {}
+            },
+
+            // `p.is_null()`
+            ExprKind::MethodCall(ps, [recv, ..], _) => {
+                if let Some(adt) = self.list_expr(recv) {
+                    if ps.ident.as_str() == "is_null" {
+                        self.uses.push((
+                            adt,
+                            ListPtrUse::IsNull {
+                                expr: ex.span,
+                                ptr: recv.span,
+                            },
+                        ));
+                    } else {
+                        debug!(
+                            "unsupported method call on list node pointer at {:?}",
+                            ex.span
+                        );
+                        self.uses.push((adt, ListPtrUse::Other));
+                    }
+                }
+            }
+
+            _ => {}
+        }
+
+        intravisit::walk_expr(self, ex);
+    }
+
+    fn visit_ty(&mut self, ty: &'tcx hir::Ty<'tcx>) {
+        // `*mut Node`
+        if let hir::TyKind::Ptr(ref mt) = ty.kind {
+            if let hir::TyKind::Path(hir::QPath::Resolved(None, path)) = mt.ty.kind {
+                if let Res::Def(DefKind::Struct, did) = path.res {
+                    if self.candidates.contains(&did) {
+                        self.uses.push((did, ListPtrUse::Ty { ty: ty.span }));
+                        return;
+                    }
+                }
+            }
+        }
+        intravisit::walk_ty(self, ty);
+    }
+}
+
+/// Run `ListPtrUseVisitor` over the signature and body of `ldid`.
+pub fn collect_list_ptr_uses(
+    tcx: TyCtxt,
+    ldid: LocalDefId,
+    candidates: &HashSet<DefId>,
+) -> Vec<(DefId, ListPtrUse)> {
+    let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+    let mut v = ListPtrUseVisitor::new(tcx, Some(tcx.typeck_body(hir_body_id)), candidates);
+    let hir_id = tcx.hir().local_def_id_to_hir_id(ldid);
+    if let Some(decl) = tcx.hir().fn_decl_by_hir_id(hir_id) {
+        for ty in decl.inputs {
+            v.visit_ty(ty);
+        }
+        if let hir::FnRetTy::Return(ty) = decl.output {
+            v.visit_ty(ty);
+        }
+    }
+    v.visit_body(tcx.hir().body(hir_body_id));
+    v.uses
+}
+
+/// Find the node pointer types written in the declarations of the `fields`.
+pub fn collect_field_ty_uses(
+    tcx: TyCtxt,
+    fields: &HashSet<DefId>,
+    candidates: &HashSet<DefId>,
+) -> Vec<(DefId, ListPtrUse)> {
+    let mut v = ListPtrUseVisitor::new(tcx, None, candidates);
+    for &did in fields {
+        let field_def = match tcx.hir().get_if_local(did) {
+            Some(hir::Node::Field(field_def)) => field_def,
+            _ => continue,
+        };
+        v.visit_ty(field_def.ty);
+    }
+    v.uses
+}
+
+/// Determine the shape of the list formed by `did`, if it's a linked-list node struct.
+fn list_shape(tcx: TyCtxt, did: DefId) -> Option<ListShape> {
+    let links = tcx
+        .adt_def(did)
+        .all_fields()
+        .filter(|field| match *tcx.type_of(field.did).kind() {
+            TyKind::RawPtr(tm) => {
+                matches!(*tm.ty.kind(), TyKind::Adt(adt_def, _) if adt_def.did() == did)
+            }
+            _ => false,
+        })
+        .map(|field| (field.did, field.name))
+        .collect::<Vec<_>>();
+    match links[..] {
+        [(next, _)] => Some(ListShape::Singly { next }),
+        [(a, a_name), (b, _)] => {
+            // Links are usually named `prev` and `next`.  If not, assume the first one is `next`.
+            let (next, prev) = if a_name.as_str().contains("prev") {
+                (b, a)
+            } else {
+                (a, b)
+            };
+            Some(ListShape::Doubly { next, prev })
+        }
+        // Nodes with more links form trees or graphs, not lists.
+        _ => None,
+    }
+}
+
+/// Check whether the nodes of `did` can be stored in an arena: every field must be `Copy` and
+/// have a zero value, and only the links may contain pointers.
+fn arena_compatible(tcx: TyCtxt, did: DefId, candidates: &HashSet<DefId>) -> bool {
+    for field in tcx.adt_def(did).all_fields() {
+        let ty = tcx.type_of(field.did);
+        if list_adt(candidates, ty).is_some() {
+            continue;
+        }
+        let has_ptr = ty.walk().any(|arg| match arg.unpack() {
+            GenericArgKind::Type(ty) => matches!(ty.kind(), TyKind::RawPtr(_) | TyKind::Ref(..)),
+            _ => false,
+        });
+        if has_ptr {
+            debug!("list node {did:?} has pointer field {:?}", field.did);
+            return false;
+        }
+        if !ty.is_copy_modulo_regions(tcx.at(DUMMY_SP), ParamEnv::empty()) {
+            debug!("list node {did:?} has non-Copy field {:?}", field.did);
+            return false;
+        }
+        if rewrite::gen_zeroize_expr(tcx, ty).is_none() {
+            debug!(
+                "list node {did:?} has field {:?} with no zero value",
+                field.did
+            );
+            return false;
+        }
+    }
+    true
+}
+
+/// Find linked-list node structs, and the doubly-linked ones whose pointers can be rewritten to
+/// arena indices.
+pub fn find_linked_lists(gacx: &GlobalAnalysisCtxt, all_fn_ldids: &[LocalDefId]) -> LinkedListInfo {
+    let tcx = gacx.tcx;
+
+    let mut structs = HashMap::new();
+    for &adt_did in &gacx.adt_metadata.struct_dids {
+        if !adt_did.is_local() || gacx.foreign_mentioned_tys.contains(&adt_did) {
+            continue;
+        }
+        if !matches!(tcx.def_kind(adt_did), DefKind::Struct) {
+            continue;
+        }
+        if tcx.generics_of(adt_did).count() > 0 {
+            continue;
+        }
+        // Structs handled by the `Rc<RefCell<T>>` and `Arc<Mutex<T>>` rewrites are left alone.
+        if gacx.refcount.structs.contains_key(&adt_did)
+            || gacx.thread_shared.structs.contains(&adt_did)
+        {
+            continue;
+        }
+        if let Some(shape) = list_shape(tcx, adt_did) {
+            match shape {
+                ListShape::Singly { next } => {
+                    debug!("found singly-linked list node {adt_did:?}, linked by {next:?}");
+                }
+                ListShape::Doubly { next, prev } => debug!(
+                    "found doubly-linked list node {adt_did:?}, linked by {next:?} and {prev:?}"
+                ),
+            }
+            structs.insert(adt_did, shape);
+        }
+    }
+
+    let mut candidates = structs
+        .iter()
+        .filter(|(_, shape)| matches!(shape, ListShape::Doubly { .. }))
+        .map(|(&did, _)| did)
+        .collect::<HashSet<_>>();
+    let compatible = candidates
+        .iter()
+        .copied()
+        .filter(|&did| arena_compatible(tcx, did, &candidates))
+        .collect::<HashSet<_>>();
+    candidates = compatible;
+
+    // The arena lives in a `thread_local`, so nodes must not be reachable from statics.
+    let mut rejected = HashSet::new();
+    for &did in gacx.static_tys.keys() {
+        if let Some(did) = mentions_list_adt(&candidates, tcx.type_of(did)) {
+            rejected.insert(did);
+        }
+    }
+    candidates.retain(|did| !rejected.contains(did));
+
+    let mut info = LinkedListInfo {
+        structs,
+        ..LinkedListInfo::default()
+    };
+    if candidates.is_empty() {
+        return info;
+    }
+
+    let mut fns = HashMap::new();
+    for &ldid in all_fn_ldids {
+        let mir = tcx.mir_built(WithOptConstParam::unknown(ldid));
+        let mir = mir.borrow();
+        let adts = mir
+            .local_decls
+            .iter()
+            .filter_map(|decl| mentions_list_adt(&candidates, decl.ty))
+            .collect::<HashSet<_>>();
+        if !adts.is_empty() {
+            fns.insert(ldid, adts);
+        }
+    }
+
+    for &ldid in fns.keys() {
+        for (did, u) in collect_list_ptr_uses(tcx, ldid, &candidates) {
+            if let ListPtrUse::Other = u {
+                rejected.insert(did);
+            }
+        }
+    }
+    candidates.retain(|did| !rejected.contains(did));
+
+    for &adt_did in &gacx.adt_metadata.struct_dids {
+        for field in tcx.adt_def(adt_did).all_fields() {
+            if mentions_list_adt(&candidates, tcx.type_of(field.did)).is_some() {
+                info.arena_fields.insert(field.did);
+            }
+        }
+    }
+    for (ldid, adts) in fns {
+        if adts.iter().any(|did| candidates.contains(did)) {
+            info.fns.insert(ldid.to_def_id());
+        }
+    }
+    for did in &candidates {
+        debug!("rewriting list node {did:?} to use an arena");
+    }
+    info.arena_structs = candidates;
+    info
+}
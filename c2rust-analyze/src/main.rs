@@ -7,38 +7,78 @@ extern crate rustc_data_structures;
 extern crate rustc_driver;
 extern crate rustc_hir;
 extern crate rustc_index;
+extern crate rustc_infer;
 extern crate rustc_interface;
 extern crate rustc_middle;
 extern crate rustc_mir_build;
 extern crate rustc_session;
 extern crate rustc_span;
 extern crate rustc_target;
+extern crate rustc_trait_selection;
 extern crate rustc_type_ir;
 
+mod alias;
+mod align;
 mod analyze;
 mod annotate;
+mod array_lens;
 mod borrowck;
+mod closure_callbacks;
+mod config_file;
 mod context;
+mod crate_facts;
 mod dataflow;
 mod equiv;
+mod errno_result;
+mod ffi_escape;
+mod file_io;
+mod flex_array;
+mod fn_cache;
+mod fn_ptr_fields;
+mod fn_tables;
+mod html_report;
+mod interactive;
+mod json_file;
 mod known_fn;
+mod known_fn_db;
 mod labeled_ty;
+mod len_fields;
+mod linked_lists;
 mod log;
+mod migration_stats;
+mod out_params;
+mod output_facts;
 mod panic_detail;
+mod pdg_facts;
 mod pointee_type;
 mod pointer_id;
+mod provenance;
 mod recent_writes;
+mod refcount;
 mod rewrite;
+mod rewrite_manifest;
+mod sarif;
+mod self_ref;
+mod sentinel;
+mod stable_facts;
+mod static_bufs;
+mod static_inits;
+mod static_kinds;
+mod thread_shared;
 mod trivial;
 mod type_desc;
+mod unions;
+mod user_data;
 mod util;
+mod vec_structs;
 
 use crate::log::init_logger;
 use analyze::AnalysisCallbacks;
 use anyhow::anyhow;
 use anyhow::ensure;
 use anyhow::Context;
-use clap::{ArgAction, Parser, ValueEnum};
+use clap::{ArgAction, CommandFactory, Parser, ValueEnum};
+use regex::Regex;
 use rustc_driver::RunCompiler;
 use rustc_driver::TimePassesCallbacks;
 use rustc_session::config::CrateType;
@@ -46,6 +86,7 @@ use std::borrow::Borrow;
 use std::env;
 use std::ffi::OsStr;
 use std::ffi::OsString;
+use std::fs;
 use std::iter;
 use std::path::Path;
 use std::path::PathBuf;
@@ -55,7 +96,7 @@ use std::process::ExitStatus;
 
 /// Statically analyze and try to lift to safe Rust.
 #[derive(Debug, Parser)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, args_override_self = true)]
 struct Args {
     /// Set `$RUSTFLAGS` for the wrapped `cargo`.
     ///
@@ -75,10 +116,24 @@ struct Args {
     rustflags: Option<OsString>,
 
     /// Comma-separated list of paths to rewrite.  Any item whose path does not start with a prefix
-    /// from this list will be marked non-rewritable (`FIXED`).
+    /// from this list will be marked non-rewritable (`FIXED`), unless it has the
+    /// `#[c2rust::force_rewrite]` attribute.
     #[clap(long, action(ArgAction::Append))]
     rewrite_paths: Vec<OsString>,
 
+    /// Only rewrite functions whose path matches this regex.  The regex is matched against the
+    /// whole path as printed by `rustc`, like `foo::bar` or `<Foo as Bar>::baz`, but isn't
+    /// anchored, so use `^` and `$` to match a single function exactly.  Can be given more than
+    /// once, in which case a function matching any of the regexes is rewritten.  All other
+    /// functions are marked non-rewritable (`FIXED`), as with `--rewrite-paths`.
+    #[clap(long, action(ArgAction::Append))]
+    include_fn: Vec<String>,
+
+    /// Don't rewrite functions whose path matches this regex, even if they're selected by
+    /// `--include-fn` or `--rewrite-paths`.  Can be given more than once.
+    #[clap(long, action(ArgAction::Append))]
+    exclude_fn: Vec<String>,
+
     /// Whether to rewrite source files on disk.  The default is to print the rewritten source code
     /// to stdout as part of the tool's debug output.
     #[clap(long, value_enum)]
@@ -88,6 +143,33 @@ struct Args {
     #[clap(long, hide(true), conflicts_with("rewrite_mode"))]
     rewrite_in_place: bool,
 
+    /// The file to write the diff to for `--rewrite-mode patch`.  Paths in the diff are relative
+    /// to the workspace root, so it can be applied there with `git apply` or `patch -p1`.
+    #[clap(long, default_value = "c2rust-analyze.patch")]
+    patch_file: PathBuf,
+
+    /// Print each planned rewrite as `file:line:col old => new`, followed by the MIR rewrite kinds
+    /// that produced it, instead of applying the rewrites.  No files are modified.
+    #[clap(long, conflicts_with_all(&["rewrite_mode", "rewrite_in_place"]))]
+    dry_run: bool,
+
+    /// Before applying the rewrites, show the ones for each function and ask whether to accept,
+    /// reject, or defer them.  Functions whose rewrites aren't accepted are left unchanged, with
+    /// their callers adjusted to match.  Decisions are recorded in `--decisions-file`, and later
+    /// runs reuse them for functions whose rewrites haven't changed.
+    #[clap(long, conflicts_with("dry_run"))]
+    interactive: bool,
+
+    /// Don't change any code.  Instead, add a `// c2rust: ...` comment after each declaration of
+    /// a raw pointer, giving the permissions and flags inferred for it, so the results of the
+    /// analysis can be reviewed before enabling rewrites.
+    #[clap(long, conflicts_with_all(&["dry_run", "interactive"]))]
+    annotate_only: bool,
+
+    /// The file where `--interactive` records accepted and rejected rewrites.
+    #[clap(long, default_value = "c2rust-analyze-decisions.json")]
+    decisions_file: PathBuf,
+
     /// Use `todo!()` placeholders in shims for casts that must be implemented manually.
     ///
     /// When a function requires a shim, and the shim requires a cast that can't be generated
@@ -97,14 +179,231 @@ struct Args {
     #[clap(long)]
     use_manual_shims: bool,
 
+    /// Rewrite pointers to reference-counted structs as `Rc<RefCell<T>>`.
+    ///
+    /// A struct is considered reference-counted if every `free` of it is guarded by a check that
+    /// one of its fields is zero.  Without this option, such pointers are left as raw pointers.
+    /// Functions like `obj_ref`/`obj_unref` that only update the count are removed, and calls to
+    /// them become `clone()` and `drop()`.
+    #[clap(long)]
+    use_rc: bool,
+
+    /// Rewrite offsets that can't be proven in bounds to slice operations that panic at run time.
+    ///
+    /// Offsetting a pointer to a single element, such as a pointer to a struct field, is only
+    /// valid for an offset of zero.  By default, functions that do this aren't rewritten.  With
+    /// this option, the pointer becomes a one-element slice, and an out-of-bounds offset panics.
+    #[clap(long)]
+    checked_offsets: bool,
+
+    /// Rewrite offsets of raw pointers with a known length to slices with run-time bounds checks.
+    ///
+    /// When a loop bounds its index by a struct's length field and offsets the struct's pointer
+    /// field by the same index, an offset of the raw pointer that can't otherwise be rewritten
+    /// becomes an offset of `slice::from_raw_parts(ptr, len)`, preceded by an `assert!` that the
+    /// offset is within the length.  By default, functions that do this aren't rewritten.
+    #[clap(long)]
+    dynamic_checks: bool,
+
+    /// Copy callees that non-rewritten callers pass their locals to, instead of leaving them
+    /// unrewritten.
+    ///
+    /// When a caller that isn't rewritten passes a pointer to one of its locals to a function
+    /// that reads and writes through it, that function normally isn't rewritten either, for all
+    /// of its callers.  With this option, such a caller instead calls `foo_raw`, an unchanged
+    /// copy of the original `foo`, and `foo` is rewritten for everyone else.
+    ///
+    /// This is not general context sensitivity: there is one copy shared by all the
+    /// non-rewritten callers, and the rewritten callers all share the rewritten `foo`.  Only
+    /// leaf functions are copied, those that don't mention other local functions, statics, or
+    /// fields, and only when no item named `foo_raw` already exists in the same module.
+    #[clap(long)]
+    clone_callees: bool,
+
+    /// Rewrite linked-list node structs to safe representations.
+    ///
+    /// A struct with one field pointing to its own type forms a singly-linked list, whose nodes
+    /// are owned by the `next` link and become `Option<Box<T>>`.  A struct with two such fields
+    /// forms a doubly-linked list, whose nodes are moved into an arena, with each pointer to a
+    /// node becoming an `Option<usize>` index.
+    #[clap(long)]
+    linked_lists: bool,
+
+    /// Give exported `extern "C"` functions a safe Rust API where possible.
+    ///
+    /// A `#[no_mangle]` function whose body is fully safe after rewriting is renamed to
+    /// `foo_safe` and made safe, and a `#[no_mangle] extern "C"` wrapper with the original name
+    /// and signature calls it, so C callers are unaffected.
+    #[clap(long)]
+    safe_wrappers: bool,
+
+    /// Remove `unsafe` blocks and `unsafe fn` qualifiers that are no longer needed after
+    /// rewriting.
+    ///
+    /// An `unsafe` block whose statements are all safe after rewriting becomes a plain block, and
+    /// an `unsafe fn` whose body is fully safe after rewriting becomes a safe `fn`.
+    #[clap(long)]
+    minimize_unsafe: bool,
+
+    /// Read a list of functions whose errno-style return codes should be rewritten to `Result`
+    /// from this file path.
+    ///
+    /// A listed function that returns `0` on success and a negated constant on failure gets the
+    /// return type `Result<(), std::io::Error>`, and its callers are updated to match.  The file
+    /// uses the same format as `--fixed-defs-list`.
+    #[clap(long)]
+    errno_result_fns_list: Option<PathBuf>,
+
+    /// Read a list of functions whose out-parameters should be rewritten to return values from
+    /// this file path.
+    ///
+    /// A listed function that writes a value through a `*mut T` parameter and returns `0` on
+    /// success gets the return type `Option<T>` or `Result<T, S>` instead, and its callers are
+    /// updated to match.  The file uses the same format as `--fixed-defs-list`.
+    #[clap(long)]
+    out_param_fns_list: Option<PathBuf>,
+
+    /// Read a list of functions that return pointers into static buffers from this file path.
+    ///
+    /// A listed function whose return values all point to the start of the same static array
+    /// returns an owned `Vec<u8>` copy of the string in the buffer instead, or a `&'static` slice
+    /// if the static is immutable, and its callers are updated to match.  The file uses the same
+    /// format as `--fixed-defs-list`.
+    #[clap(long)]
+    static_buf_fns_list: Option<PathBuf>,
+
+    /// Read a list of functions whose callback and `void*` user-data parameters should be
+    /// replaced by a closure from this file path.
+    ///
+    /// A listed function that only passes its user data back to its callback takes an
+    /// `impl FnMut` instead, and each caller passes a closure that calls the original callback
+    /// with the original user data.  The file uses the same format as `--fixed-defs-list`.
+    #[clap(long)]
+    closure_callback_fns_list: Option<PathBuf>,
+
+    /// Read a list of self-referential structs whose self-pointers should be rewritten to indices
+    /// from this file path.
+    ///
+    /// A listed struct that stores pointers into one of its own array fields stores `usize`
+    /// indices into the array instead.  The file uses the same format as `--fixed-defs-list`.
+    #[clap(long)]
+    self_ref_index_structs_list: Option<PathBuf>,
+
+    /// Read a list of self-referential structs that should be made `!Unpin` from this file path.
+    ///
+    /// A listed struct that stores pointers into its own fields keeps its raw pointers, and gets a
+    /// `PhantomPinned` field so that it can't be moved once pinned.  The file uses the same format
+    /// as `--fixed-defs-list`.
+    #[clap(long)]
+    self_ref_pin_structs_list: Option<PathBuf>,
+
     /// Read a list of defs that should be marked non-rewritable (`FIXED`) from this file path.
     /// Run `c2rust-analyze` without this option and check the debug output for a full list of defs
     /// in the crate being analyzed; the file passed to this option should list a subset of those
-    /// defs.
+    /// defs.  Alternatively, individual items can be marked with `#[c2rust::skip_rewrite]` to add
+    /// them to this list, or with `#[c2rust::force_rewrite]` to exempt them from it.
     #[clap(long)]
     fixed_defs_list: Option<PathBuf>,
 
-    /// `cargo` args.
+    /// Share facts about exported functions between the crates of a workspace through this
+    /// directory.
+    ///
+    /// Each crate is still analyzed separately, but after a library is analyzed, the final
+    /// permissions of the pointers in its public functions' signatures are saved here.  Crates
+    /// that call those functions, such as binaries in the same workspace, load the saved facts
+    /// and rewrite their calls to match.  Public functions whose signatures change also get a
+    /// public `foo_shim` for callers in other crates that aren't rewritten.  Use an empty
+    /// directory for each run, so that facts from older runs aren't picked up.
+    #[clap(long)]
+    crate_facts_dir: Option<PathBuf>,
+
+    /// Read permissions and pointee types for external functions, such as those from zlib or
+    /// OpenSSL, from this TOML file.  Calls to functions listed there are handled like calls to
+    /// the built-in `libc` functions instead of leaving their arguments unrewritten.  See
+    /// `known_fn_db` for the file format.
+    #[clap(long)]
+    known_fns_db: Option<PathBuf>,
+
+    /// Seed the analysis with the permissions observed at run time, as recorded in this PDG file
+    /// by `c2rust-pdg`.
+    ///
+    /// Pointers that were read, written, offset, or freed during the instrumented run start out
+    /// with those permissions, and pointers whose uses were observed to overlap with another
+    /// pointer to the same object lose `UNIQUE`.  The static analysis then propagates these
+    /// permissions along with the ones it infers itself.
+    #[clap(long)]
+    pdg_facts: Option<PathBuf>,
+
+    /// Save the final permissions and flags of each crate's global pointers in this directory.
+    ///
+    /// Pointers are identified by the item whose type contains them and their position in that
+    /// type, so results from different runs can be compared even after unrelated changes to the
+    /// crate.
+    #[clap(long)]
+    save_analysis_facts: Option<PathBuf>,
+
+    /// Load the results saved by `--save-analysis-facts` in an earlier run from this directory.
+    /// The saved permissions are used as the starting point for the analysis, and the changes in
+    /// the final results are printed.
+    #[clap(long)]
+    load_analysis_facts: Option<PathBuf>,
+
+    /// Write the final results for every pointer to this file as JSON: its span, permissions,
+    /// flags, rewritten type, and the reason its function or item isn't rewritten, if any.
+    #[clap(long)]
+    output_facts: Option<PathBuf>,
+
+    /// Write an HTML report to this directory showing each source file with its pointers colored
+    /// by what they're rewritten to, with the inferred permissions shown on hover.
+    #[clap(long)]
+    html_report: Option<PathBuf>,
+
+    /// Write diagnostics to this file in SARIF format: functions where the analysis failed,
+    /// functions, statics, and fields that aren't rewritten, and borrowck conflicts.
+    #[clap(long)]
+    sarif: Option<PathBuf>,
+
+    /// Write the migration statistics printed at the end of the run to this file as JSON.
+    #[clap(long)]
+    stats_json: Option<PathBuf>,
+
+    /// Write every rewrite to this file as JSON, with its span, the code it's replaced with, and,
+    /// for expression rewrites, the HIR expression, MIR rewrite kinds, and pointers behind it.
+    #[clap(long)]
+    rewrite_manifest: Option<PathBuf>,
+
+    /// Cache the dataflow constraints and permissions computed for each function in this
+    /// directory, and reuse them in later runs for functions whose inputs haven't changed.
+    #[clap(long)]
+    cache_dir: Option<PathBuf>,
+
+    /// Explain why the pointers in a variable weren't given `UNIQUE` or `NON_NULL`, given as
+    /// `path::to::fn::var`.  May be passed several times.
+    ///
+    /// Each missing permission is traced back through the assignments that copied it to the
+    /// statement or borrow conflict that removed it first, and the chain is printed along with
+    /// the other analysis results.
+    #[clap(long, action(ArgAction::Append))]
+    explain: Vec<OsString>,
+
+    /// Run a may-alias pre-pass before the borrow check, and remove `UNIQUE` from pointers that
+    /// may alias another pointer through memory.
+    ///
+    /// `andersen` is more precise, and `steensgaard` is faster on large functions but may merge
+    /// unrelated objects.  The default, `borrowck`, relies on the borrow check alone.
+    #[clap(long, value_enum)]
+    alias_analysis: Option<AliasAnalysisMode>,
+
+    /// Leave a function unrewritten if propagating permissions through it takes longer than this
+    /// many seconds in total.
+    ///
+    /// The budget is checked between iterations of the analysis, so a function may overrun it by
+    /// the length of one iteration.  Skipped functions are listed with the other failures at the
+    /// end of the run.
+    #[clap(long)]
+    fn_time_budget: Option<f64>,
+
+    /// `cargo` args.  If none are given, runs `cargo build`.
     cargo_args: Vec<OsString>,
 }
 
@@ -134,6 +433,22 @@ enum RewriteMode {
     /// Rewrite each function separately, and write the results for each to a separate file.
     #[value(name = "pointwise")]
     Pointwise,
+    /// Write the rewrites as a unified diff to the `--patch-file`, leaving the sources unchanged.
+    #[value(name = "patch")]
+    Patch,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum AliasAnalysisMode {
+    /// Use only the borrow check to find pointers that can't be `UNIQUE`.
+    #[value(name = "borrowck")]
+    Borrowck,
+    /// Inclusion-based points-to analysis.
+    #[value(name = "andersen")]
+    Andersen,
+    /// Unification-based points-to analysis.
+    #[value(name = "steensgaard")]
+    Steensgaard,
 }
 
 fn exit_with_status(status: ExitStatus) {
@@ -382,17 +697,90 @@ where
     }
 }
 
+/// Parse [`Args`] from the command line, adding any options from the crate's
+/// [`config_file::CONFIG_FILE_NAME`].  The config file is looked up next to the `Cargo.toml` given
+/// by `--manifest-path`, or in the current directory.  Options on the command line come after the
+/// ones from the file, so they take precedence.
+fn parse_args() -> anyhow::Result<Args> {
+    let args = Args::parse();
+
+    let args_for_cargo =
+        iter::once(OsStr::new("cargo")).chain(args.cargo_args.iter().map(OsString::as_os_str));
+    let InterceptedCargoArgs { manifest_path, .. } =
+        InterceptedCargoArgs::parse_from(args_for_cargo);
+    let dir = match manifest_path.as_deref().and_then(|path| path.parent()) {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+        _ => env::current_dir()?,
+    };
+
+    let command = Args::command();
+    let known_options = command
+        .get_arguments()
+        .filter_map(|arg| arg.get_long())
+        .collect::<Vec<_>>();
+    let config_args = match config_file::load_config_args(&dir, &known_options)? {
+        Some(x) => x,
+        None => return Ok(args),
+    };
+
+    let mut cli_args = env::args_os();
+    let argv0 = cli_args.next();
+    Ok(Args::parse_from(
+        argv0.into_iter().chain(config_args).chain(cli_args),
+    ))
+}
+
 /// Run as a `cargo` wrapper/plugin, the default invocation.
 fn cargo_wrapper(rustc_wrapper: &Path) -> anyhow::Result<()> {
     let Args {
         rustflags,
         rewrite_paths,
+        include_fn,
+        exclude_fn,
         mut rewrite_mode,
         rewrite_in_place,
+        patch_file,
+        dry_run,
+        interactive,
+        annotate_only,
+        decisions_file,
         use_manual_shims,
+        use_rc,
+        checked_offsets,
+        dynamic_checks,
+        clone_callees,
+        linked_lists,
+        safe_wrappers,
+        minimize_unsafe,
+        errno_result_fns_list,
+        out_param_fns_list,
+        static_buf_fns_list,
+        closure_callback_fns_list,
+        self_ref_index_structs_list,
+        self_ref_pin_structs_list,
         fixed_defs_list,
+        crate_facts_dir,
+        known_fns_db,
+        pdg_facts,
+        save_analysis_facts,
+        load_analysis_facts,
+        output_facts,
+        html_report,
+        sarif,
+        stats_json,
+        rewrite_manifest,
+        cache_dir,
+        explain,
+        alias_analysis,
+        fn_time_budget,
         cargo_args,
-    } = Args::parse();
+    } = parse_args()?;
+
+    let cargo_args = if cargo_args.is_empty() {
+        vec![OsString::from("build")]
+    } else {
+        cargo_args
+    };
 
     let args_for_cargo =
         iter::once(OsStr::new("cargo")).chain(cargo_args.iter().map(OsString::as_os_str));
@@ -417,6 +805,87 @@ fn cargo_wrapper(rustc_wrapper: &Path) -> anyhow::Result<()> {
     // so that we don't need all of the [`rustc_wrapper`]s to have to do it.
     let sysroot = resolve_sysroot()?;
 
+    // `rustc` isn't always run from the current directory, so pass an absolute path.
+    let crate_facts_dir = crate_facts_dir
+        .map(|dir| env::current_dir().map(|cwd| cwd.join(dir)))
+        .transpose()?;
+    let save_analysis_facts = save_analysis_facts
+        .map(|dir| env::current_dir().map(|cwd| cwd.join(dir)))
+        .transpose()?;
+    let load_analysis_facts = load_analysis_facts
+        .map(|dir| env::current_dir().map(|cwd| cwd.join(dir)))
+        .transpose()?;
+    let output_facts = output_facts
+        .map(|path| env::current_dir().map(|cwd| cwd.join(path)))
+        .transpose()?;
+    let html_report = html_report
+        .map(|dir| env::current_dir().map(|cwd| cwd.join(dir)))
+        .transpose()?;
+    let sarif = sarif
+        .map(|path| env::current_dir().map(|cwd| cwd.join(path)))
+        .transpose()?;
+    let stats_json = stats_json
+        .map(|path| env::current_dir().map(|cwd| cwd.join(path)))
+        .transpose()?;
+    let rewrite_manifest = rewrite_manifest
+        .map(|path| env::current_dir().map(|cwd| cwd.join(path)))
+        .transpose()?;
+    let cache_dir = cache_dir
+        .map(|dir| env::current_dir().map(|cwd| cwd.join(dir)))
+        .transpose()?;
+    let known_fns_db = known_fns_db
+        .map(|path| env::current_dir().map(|cwd| cwd.join(path)))
+        .transpose()?;
+    let pdg_facts = pdg_facts
+        .map(|path| env::current_dir().map(|cwd| cwd.join(path)))
+        .transpose()?;
+    let errno_result_fns_list = errno_result_fns_list
+        .map(|path| env::current_dir().map(|cwd| cwd.join(path)))
+        .transpose()?;
+    let out_param_fns_list = out_param_fns_list
+        .map(|path| env::current_dir().map(|cwd| cwd.join(path)))
+        .transpose()?;
+    let static_buf_fns_list = static_buf_fns_list
+        .map(|path| env::current_dir().map(|cwd| cwd.join(path)))
+        .transpose()?;
+    let closure_callback_fns_list = closure_callback_fns_list
+        .map(|path| env::current_dir().map(|cwd| cwd.join(path)))
+        .transpose()?;
+    let self_ref_index_structs_list = self_ref_index_structs_list
+        .map(|path| env::current_dir().map(|cwd| cwd.join(path)))
+        .transpose()?;
+    let self_ref_pin_structs_list = self_ref_pin_structs_list
+        .map(|path| env::current_dir().map(|cwd| cwd.join(path)))
+        .transpose()?;
+
+    let patch_file = env::current_dir()?.join(patch_file);
+    let decisions_file = env::current_dir()?.join(decisions_file);
+    let patch_file_used = matches!(rewrite_mode, Some(RewriteMode::Patch)).then_some(&patch_file);
+
+    // Each crate adds its records to the `--output-facts`, `--sarif`, `--stats-json`,
+    // `--rewrite-manifest`, and `--patch-file` files, so clear out any results from an earlier run
+    // first.
+    for path in [
+        output_facts.as_ref(),
+        sarif.as_ref(),
+        stats_json.as_ref(),
+        rewrite_manifest.as_ref(),
+        patch_file_used,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+
+    // Check the `--include-fn` and `--exclude-fn` regexes here, so a typo is reported once instead
+    // of by every `rustc` invocation.
+    for pattern in include_fn.iter().chain(&exclude_fn) {
+        Regex::new(pattern).with_context(|| format!("invalid function regex `{pattern}`"))?;
+    }
+
     let cargo = Cargo::new();
 
     cargo.run(|cmd| {
@@ -438,25 +907,179 @@ fn cargo_wrapper(rustc_wrapper: &Path) -> anyhow::Result<()> {
             cmd.env("C2RUST_ANALYZE_FIXED_DEFS_LIST", fixed_defs_list);
         }
 
+        if let Some(ref crate_facts_dir) = crate_facts_dir {
+            cmd.env("C2RUST_ANALYZE_CRATE_FACTS_DIR", crate_facts_dir);
+        }
+
+        if let Some(ref known_fns_db) = known_fns_db {
+            cmd.env("C2RUST_ANALYZE_KNOWN_FNS_DB", known_fns_db);
+        }
+
+        if let Some(ref pdg_facts) = pdg_facts {
+            cmd.env("C2RUST_ANALYZE_PDG_FACTS", pdg_facts);
+        }
+
+        if let Some(ref dir) = save_analysis_facts {
+            cmd.env("C2RUST_ANALYZE_SAVE_ANALYSIS_FACTS", dir);
+        }
+
+        if let Some(ref dir) = load_analysis_facts {
+            cmd.env("C2RUST_ANALYZE_LOAD_ANALYSIS_FACTS", dir);
+        }
+
+        if let Some(ref path) = output_facts {
+            cmd.env("C2RUST_ANALYZE_OUTPUT_FACTS", path);
+        }
+
+        if let Some(ref dir) = html_report {
+            cmd.env("C2RUST_ANALYZE_HTML_REPORT", dir);
+        }
+
+        if let Some(ref path) = sarif {
+            cmd.env("C2RUST_ANALYZE_SARIF", path);
+        }
+
+        if let Some(ref path) = stats_json {
+            cmd.env("C2RUST_ANALYZE_STATS_JSON", path);
+        }
+
+        if let Some(ref path) = rewrite_manifest {
+            cmd.env("C2RUST_ANALYZE_REWRITE_MANIFEST", path);
+        }
+
+        if let Some(ref dir) = cache_dir {
+            cmd.env("C2RUST_ANALYZE_CACHE_DIR", dir);
+        }
+
+        if !explain.is_empty() {
+            cmd.env("C2RUST_ANALYZE_EXPLAIN", explain.join(OsStr::new(",")));
+        }
+
         if !rewrite_paths.is_empty() {
             let rewrite_paths = rewrite_paths.join(OsStr::new(","));
             cmd.env("C2RUST_ANALYZE_REWRITE_PATHS", rewrite_paths);
         }
 
+        // Regexes may contain commas, so separate them with newlines instead.
+        if !include_fn.is_empty() {
+            cmd.env("C2RUST_ANALYZE_INCLUDE_FN", include_fn.join("\n"));
+        }
+
+        if !exclude_fn.is_empty() {
+            cmd.env("C2RUST_ANALYZE_EXCLUDE_FN", exclude_fn.join("\n"));
+        }
+
         if let Some(rewrite_mode) = rewrite_mode {
             let val = match rewrite_mode {
                 RewriteMode::None => "none",
                 RewriteMode::InPlace => "inplace",
                 RewriteMode::Alongside => "alongside",
                 RewriteMode::Pointwise => "pointwise",
+                RewriteMode::Patch => "patch",
             };
             cmd.env("C2RUST_ANALYZE_REWRITE_MODE", val);
         }
 
+        if let Some(RewriteMode::Patch) = rewrite_mode {
+            cmd.env("C2RUST_ANALYZE_PATCH_FILE", &patch_file);
+        }
+
+        if dry_run {
+            cmd.env("C2RUST_ANALYZE_DRY_RUN", "1");
+        }
+
+        if interactive {
+            cmd.env("C2RUST_ANALYZE_INTERACTIVE", "1");
+            cmd.env("C2RUST_ANALYZE_DECISIONS_FILE", &decisions_file);
+            // Build one crate at a time, so prompts for different crates don't interleave.
+            cmd.env("CARGO_BUILD_JOBS", "1");
+        }
+
+        if annotate_only {
+            cmd.env("C2RUST_ANALYZE_ANNOTATE_ONLY", "1");
+        }
+
+        if let Some(alias_analysis) = alias_analysis {
+            let val = match alias_analysis {
+                AliasAnalysisMode::Borrowck => "borrowck",
+                AliasAnalysisMode::Andersen => "andersen",
+                AliasAnalysisMode::Steensgaard => "steensgaard",
+            };
+            cmd.env("C2RUST_ANALYZE_ALIAS_ANALYSIS", val);
+        }
+
+        if let Some(secs) = fn_time_budget {
+            cmd.env("C2RUST_ANALYZE_FN_TIME_BUDGET", secs.to_string());
+        }
+
         if use_manual_shims {
             cmd.env("C2RUST_ANALYZE_USE_MANUAL_SHIMS", "1");
         }
 
+        if use_rc {
+            cmd.env("C2RUST_ANALYZE_USE_RC", "1");
+        }
+
+        if checked_offsets {
+            cmd.env("C2RUST_ANALYZE_CHECKED_OFFSETS", "1");
+        }
+
+        if dynamic_checks {
+            cmd.env("C2RUST_ANALYZE_DYNAMIC_CHECKS", "1");
+        }
+
+        if clone_callees {
+            cmd.env("C2RUST_ANALYZE_CLONE_CALLEES", "1");
+        }
+
+        if linked_lists {
+            cmd.env("C2RUST_ANALYZE_LINKED_LISTS", "1");
+        }
+
+        if safe_wrappers {
+            cmd.env("C2RUST_ANALYZE_SAFE_WRAPPERS", "1");
+        }
+
+        if minimize_unsafe {
+            cmd.env("C2RUST_ANALYZE_MINIMIZE_UNSAFE", "1");
+        }
+
+        if let Some(ref errno_result_fns_list) = errno_result_fns_list {
+            cmd.env(
+                "C2RUST_ANALYZE_ERRNO_RESULT_FNS_LIST",
+                errno_result_fns_list,
+            );
+        }
+
+        if let Some(ref out_param_fns_list) = out_param_fns_list {
+            cmd.env("C2RUST_ANALYZE_OUT_PARAM_FNS_LIST", out_param_fns_list);
+        }
+
+        if let Some(ref static_buf_fns_list) = static_buf_fns_list {
+            cmd.env("C2RUST_ANALYZE_STATIC_BUF_FNS_LIST", static_buf_fns_list);
+        }
+
+        if let Some(ref closure_callback_fns_list) = closure_callback_fns_list {
+            cmd.env(
+                "C2RUST_ANALYZE_CLOSURE_CALLBACK_FNS_LIST",
+                closure_callback_fns_list,
+            );
+        }
+
+        if let Some(ref self_ref_index_structs_list) = self_ref_index_structs_list {
+            cmd.env(
+                "C2RUST_ANALYZE_SELF_REF_INDEX_STRUCTS_LIST",
+                self_ref_index_structs_list,
+            );
+        }
+
+        if let Some(ref self_ref_pin_structs_list) = self_ref_pin_structs_list {
+            cmd.env(
+                "C2RUST_ANALYZE_SELF_REF_PIN_STRUCTS_LIST",
+                self_ref_pin_structs_list,
+            );
+        }
+
         Ok(())
     })?;
 
@@ -0,0 +1,160 @@
+//! A summary of how far the migration to safe Rust got, printed at the end of every run.
+//!
+//! The summary counts the raw pointers that are rewritten to safe types, the `unsafe` blocks that
+//! are removed, and the functions that end up fully safe, and lists the most common reasons
+//! pointers stay raw.  Only pointers that are written out in the source are counted: those in
+//! function signatures, variable declarations, statics, and fields.  When
+//! `C2RUST_ANALYZE_STATS_JSON` names a file, the summary is also written there as JSON, so that
+//! progress can be tracked across runs.  When several crates are analyzed in one build, each
+//! appends its summary to the same file.
+
+use crate::json_file;
+use crate::output_facts::{FactKind, PointerFact};
+use crate::rewrite::UnsafetyCounts;
+use rustc_hir::def_id::LOCAL_CRATE;
+use rustc_middle::ty::TyCtxt;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The number of reasons to list in `MigrationStats::raw_reasons`.
+const MAX_REASONS: usize = 10;
+
+/// Get the file to write the statistics to as JSON, if one was set.
+pub fn json_path() -> Option<PathBuf> {
+    env::var_os("C2RUST_ANALYZE_STATS_JSON").map(PathBuf::from)
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ReasonCount {
+    pub reason: String,
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MigrationStats {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    /// Raw pointers written in the source.
+    pub raw_ptrs: usize,
+    /// Raw pointers that are rewritten to safe types.
+    pub raw_ptrs_eliminated: usize,
+    pub unsafe_blocks: usize,
+    pub unsafe_blocks_removed: usize,
+    pub fns: usize,
+    /// Functions with no `unsafe` qualifier and no unsafe operations after rewriting.
+    pub safe_fns: usize,
+    /// The most common reasons pointers stay raw, most common first.  A pointer can be counted
+    /// under several reasons.
+    pub raw_reasons: Vec<ReasonCount>,
+}
+
+/// The reasons `fact`, whose rewritten type is still a raw pointer, stays raw.
+fn raw_reasons(fact: &PointerFact) -> Vec<String> {
+    if let Some(ref failure) = fact.failure {
+        // Drop the details of the panic, if any, so that failures of the same kind are counted
+        // together.
+        let reason = failure.split(": ").next().unwrap_or(failure);
+        return reason.split(" | ").map(|s| s.to_owned()).collect();
+    }
+    if fact.flags.iter().any(|flag| flag == "FIXED") {
+        return vec!["FIXED".to_owned()];
+    }
+    vec!["no safe type fits its permissions".to_owned()]
+}
+
+impl MigrationStats {
+    pub fn new(tcx: TyCtxt, facts: &[PointerFact], unsafety: UnsafetyCounts) -> MigrationStats {
+        let mut raw_ptrs = 0;
+        let mut raw_ptrs_eliminated = 0;
+        let mut reason_counts = HashMap::<String, usize>::new();
+        for fact in facts {
+            let is_decl = matches!(
+                fact.kind,
+                FactKind::Sig | FactKind::Var | FactKind::Static | FactKind::Field
+            );
+            let orig_raw = fact
+                .orig_ty
+                .as_ref()
+                .map_or(false, |ty| ty.starts_with('*'));
+            if !is_decl || !orig_raw {
+                continue;
+            }
+            raw_ptrs += 1;
+            let rewrite_raw = fact
+                .rewrite_ty
+                .as_ref()
+                .map_or(true, |ty| ty.starts_with('*'));
+            if fact.failure.is_none() && !rewrite_raw {
+                raw_ptrs_eliminated += 1;
+                continue;
+            }
+            for reason in raw_reasons(fact) {
+                *reason_counts.entry(reason).or_default() += 1;
+            }
+        }
+
+        let mut raw_reasons = reason_counts
+            .into_iter()
+            .map(|(reason, count)| ReasonCount { reason, count })
+            .collect::<Vec<_>>();
+        raw_reasons.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.reason.cmp(&b.reason)));
+        raw_reasons.truncate(MAX_REASONS);
+
+        MigrationStats {
+            krate: tcx.crate_name(LOCAL_CRATE).to_string(),
+            raw_ptrs,
+            raw_ptrs_eliminated,
+            unsafe_blocks: unsafety.blocks,
+            unsafe_blocks_removed: unsafety.blocks_removed,
+            fns: unsafety.fns,
+            safe_fns: unsafety.safe_fns,
+            raw_reasons,
+        }
+    }
+
+    /// Print the statistics to stderr.
+    pub fn print(&self) {
+        fn pct(n: usize, total: usize) -> f64 {
+            if total == 0 {
+                100.0
+            } else {
+                100.0 * n as f64 / total as f64
+            }
+        }
+
+        eprintln!("migration statistics for {}:", self.krate);
+        eprintln!(
+            "  raw pointers eliminated: {} / {} ({:.1}%)",
+            self.raw_ptrs_eliminated,
+            self.raw_ptrs,
+            pct(self.raw_ptrs_eliminated, self.raw_ptrs)
+        );
+        eprintln!(
+            "  unsafe blocks removed: {} / {} ({:.1}%)",
+            self.unsafe_blocks_removed,
+            self.unsafe_blocks,
+            pct(self.unsafe_blocks_removed, self.unsafe_blocks)
+        );
+        eprintln!(
+            "  functions fully safe: {} / {} ({:.1}%)",
+            self.safe_fns,
+            self.fns,
+            pct(self.safe_fns, self.fns)
+        );
+        if !self.raw_reasons.is_empty() {
+            eprintln!("  top reasons pointers stayed raw:");
+            for rc in &self.raw_reasons {
+                eprintln!("    {:5} {}", rc.count, rc.reason);
+            }
+        }
+    }
+
+    /// Write the statistics to `path` as JSON.  If `path` already holds the statistics for
+    /// another crate, they're kept, and these are added after them.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        json_file::append_to_array(path, [serde_json::to_value(self)?])
+    }
+}
@@ -0,0 +1,452 @@
+//! Detection of out-parameters: pointer arguments that a function only writes through, in order to
+//! return a value alongside an integer status.
+//!
+//! ```ignore
+//! unsafe fn parse_digit(c: u8, out: *mut i32) -> c_int {
+//!     if !(c >= b'0' && c <= b'9') {
+//!         return -1;
+//!     }
+//!     *out = (c - b'0') as i32;
+//!     return 0;
+//! }
+//! ```
+//!
+//! Such a function is rewritten to return `Option<T>` (or `Result<T, S>` if it has several
+//! failure statuses) without the out-parameter.  Within the body, the out-parameter becomes a
+//! local variable, `return 0` becomes `return Some(out)`, and any other status becomes `None` (or
+//! `Err(status)`).  Calls are rewritten to produce the original status, storing the value into the
+//! original destination on success.
+//!
+//! Only functions selected by the user are considered, since the rewrite changes their public
+//! signatures.
+//!
+//! The local is zero-initialized, so the rewrite is only valid if every successful return is
+//! preceded by a write of the whole value.  This is checked syntactically: the write must come
+//! earlier in the same block as the `return`, or in an enclosing block.
+
+use crate::rewrite;
+use log::debug;
+use rustc_ast::LitKind;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{Block, Body, BorrowKind, Expr, ExprKind, HirId, Mutability, PatKind, QPath};
+use rustc_hir::{StmtKind, UnOp};
+use rustc_middle::ty::{Ty, TyCtxt, TyKind, TypeckResults};
+use rustc_span::Span;
+use std::collections::{HashMap, HashSet};
+
+/// A function whose out-parameter is rewritten to part of its return value.
+#[derive(Clone, Copy, Debug)]
+pub struct OutParamFn<'tcx> {
+    /// The index of the out-parameter among the function's arguments.
+    pub index: usize,
+    /// The type `T` that the out-parameter points to.
+    pub ty: Ty<'tcx>,
+    /// If every failure returns the same status, that status.  The function then returns
+    /// `Option<T>`; otherwise, it returns `Result<T, S>`, where `S` is the original return type.
+    pub fail_status: Option<i128>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OutParamInfo<'tcx> {
+    pub fns: HashMap<DefId, OutParamFn<'tcx>>,
+    /// Every function that calls one of `fns`.
+    pub callers: HashSet<DefId>,
+}
+
+/// A use of an out-parameter or of a function that has one.
+#[derive(Clone, Copy, Debug)]
+pub enum OutParamUse {
+    /// `*out`, as the destination of an assignment.  This becomes the local `out`.
+    Deref { expr: Span },
+    /// A successful `return 0`.  `expr` is the returned value.
+    ReturnOk { expr: Span },
+    /// A failed return.  `expr` is the returned status.
+    ReturnErr { expr: Span },
+    /// A call `f(x, out)`.  `out_arg` is the span to delete to remove the out-parameter argument,
+    /// including a separating comma.
+    Call {
+        call: Span,
+        callee: DefId,
+        out_arg: Span,
+        dest: OutDest,
+    },
+}
+
+/// Where a caller wants the value written by a callee's out-parameter.
+#[derive(Clone, Copy, Debug)]
+pub enum OutDest {
+    /// `&mut place`, which can be assigned directly.  The span is that of `place`.
+    Place(Span),
+    /// A local variable holding the pointer, which must be dereferenced.
+    Ptr(Span),
+}
+
+fn strip_casts<'tcx>(mut ex: &'tcx Expr<'tcx>) -> &'tcx Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+/// Evaluate `ex` if it's an integer literal, possibly negated, as in `-(1 as c_int)`.
+fn int_literal(ex: &Expr) -> Option<i128> {
+    match strip_casts(ex).kind {
+        ExprKind::Lit(ref lit) => match lit.node {
+            LitKind::Int(x, _) => i128::try_from(x).ok(),
+            _ => None,
+        },
+        ExprKind::Unary(UnOp::Neg, inner) => int_literal(inner).map(|x| -x),
+        _ => None,
+    }
+}
+
+fn local_of(ex: &Expr) -> Option<HirId> {
+    match ex.kind {
+        ExprKind::Path(QPath::Resolved(None, path)) => match path.res {
+            Res::Local(hir_id) => Some(hir_id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// If `ex` is a direct call to a function in `fns`, return the function and the call's
+/// arguments.
+fn out_param_callee<'tcx>(
+    typeck_results: &TypeckResults<'tcx>,
+    ex: &'tcx Expr<'tcx>,
+    fns: &HashMap<DefId, OutParamFn>,
+) -> Option<(DefId, &'tcx [Expr<'tcx>])> {
+    let (func, args) = match ex.kind {
+        ExprKind::Call(func, args) => (func, args),
+        _ => return None,
+    };
+    let qpath = match func.kind {
+        ExprKind::Path(ref qpath) => qpath,
+        _ => return None,
+    };
+    match typeck_results.qpath_res(qpath, func.hir_id) {
+        Res::Def(DefKind::Fn, did) if fns.contains_key(&did) => Some((did, args)),
+        _ => None,
+    }
+}
+
+/// Checks the body of a function with a candidate out-parameter.
+struct OutParamBodyVisitor<'a> {
+    /// The binding of the out-parameter.
+    param: HirId,
+    /// The outermost block of the function body, whose trailing expression is returned.
+    body_block: HirId,
+    /// Whether the out-parameter has been written, for each enclosing block.
+    written: Vec<bool>,
+    /// `*out` expressions that are the destination of an assignment.
+    allowed_derefs: HashSet<HirId>,
+    uses: &'a mut Vec<OutParamUse>,
+    fail_statuses: HashSet<i128>,
+    ok: bool,
+}
+
+impl<'a> OutParamBodyVisitor<'a> {
+    fn is_param_deref(&self, ex: &Expr) -> bool {
+        match ex.kind {
+            ExprKind::Unary(UnOp::Deref, inner) => local_of(inner) == Some(self.param),
+            _ => false,
+        }
+    }
+
+    fn visit_return_value(&mut self, ex: &Expr) {
+        match int_literal(ex) {
+            Some(0) => {
+                if !self.written.last().copied().unwrap_or(false) {
+                    debug!(
+                        "successful return at {:?} without a preceding write",
+                        ex.span
+                    );
+                    self.ok = false;
+                }
+                self.uses.push(OutParamUse::ReturnOk { expr: ex.span });
+            }
+            Some(status) => {
+                self.fail_statuses.insert(status);
+                self.uses.push(OutParamUse::ReturnErr { expr: ex.span });
+            }
+            None => {
+                debug!("unsupported return value at {:?}", ex.span);
+                self.ok = false;
+            }
+        }
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for OutParamBodyVisitor<'a> {
+    fn visit_block(&mut self, block: &'tcx Block<'tcx>) {
+        // A nested block inherits the state of its enclosing block, but writes within it don't
+        // propagate back out.
+        let written = self.written.last().copied().unwrap_or(false);
+        self.written.push(written);
+        for stmt in block.stmts {
+            self.visit_stmt(stmt);
+            if let StmtKind::Semi(ex) | StmtKind::Expr(ex) = stmt.kind {
+                if let ExprKind::Assign(lhs, _, _) = ex.kind {
+                    if self.is_param_deref(lhs) {
+                        *self.written.last_mut().unwrap() = true;
+                    }
+                }
+            }
+        }
+        if let Some(ex) = block.expr {
+            if block.hir_id == self.body_block {
+                self.visit_return_value(ex);
+            }
+            self.visit_expr(ex);
+        }
+        self.written.pop();
+    }
+
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        if !self.ok {
+            return;
+        }
+        match ex.kind {
+            ExprKind::Assign(lhs, _, _) if self.is_param_deref(lhs) => {
+                self.allowed_derefs.insert(lhs.hir_id);
+            }
+            ExprKind::Unary(UnOp::Deref, _) if self.is_param_deref(ex) => {
+                if !self.allowed_derefs.contains(&ex.hir_id) {
+                    debug!("out-parameter is read at {:?}", ex.span);
+                    self.ok = false;
+                    return;
+                }
+                self.uses.push(OutParamUse::Deref { expr: ex.span });
+                // Don't visit the path to the out-parameter, which is allowed here.
+                return;
+            }
+            ExprKind::Path(..) if local_of(ex) == Some(self.param) => {
+                debug!(
+                    "out-parameter is used other than by `*out = ...` at {:?}",
+                    ex.span
+                );
+                self.ok = false;
+                return;
+            }
+            ExprKind::Ret(Some(value)) => self.visit_return_value(value),
+            // A closure body isn't visited, so it might use the out-parameter.
+            ExprKind::Ret(None) | ExprKind::Closure(..) => self.ok = false,
+            _ => {}
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Check whether `ldid` has an out-parameter.  If so, return it along with the uses within the
+/// body that need rewriting.
+fn find_out_param<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ldid: LocalDefId,
+) -> Option<(OutParamFn<'tcx>, Vec<OutParamUse>)> {
+    let did = ldid.to_def_id();
+    if !matches!(tcx.def_kind(did), DefKind::Fn) {
+        return None;
+    }
+    let sig = tcx.fn_sig(did).skip_binder();
+    if !sig.output().is_integral() {
+        return None;
+    }
+    let hir_body_id = tcx.hir().maybe_body_owned_by(ldid)?;
+    let body: &Body = tcx.hir().body(hir_body_id);
+    let block = match body.value.kind {
+        ExprKind::Block(block, None) => block,
+        _ => return None,
+    };
+
+    let mut found = None;
+    for (index, (param, &input_ty)) in body.params.iter().zip(sig.inputs()).enumerate() {
+        let ty = match *input_ty.kind() {
+            TyKind::RawPtr(tm) if tm.mutbl == Mutability::Mut => tm.ty,
+            _ => continue,
+        };
+        if ty.is_unsafe_ptr() || rewrite::gen_zeroize_expr(tcx, ty).is_none() {
+            continue;
+        }
+        let binding = match param.pat.kind {
+            PatKind::Binding(_, hir_id, _, None) => hir_id,
+            _ => continue,
+        };
+
+        let mut uses = Vec::new();
+        let mut v = OutParamBodyVisitor {
+            param: binding,
+            body_block: block.hir_id,
+            written: Vec::new(),
+            allowed_derefs: HashSet::new(),
+            uses: &mut uses,
+            fail_statuses: HashSet::new(),
+            ok: true,
+        };
+        v.visit_block(block);
+        let (ok, fail_statuses) = (v.ok, v.fail_statuses);
+        let has_write = uses.iter().any(|u| matches!(u, OutParamUse::Deref { .. }));
+        if !ok || !has_write || fail_statuses.is_empty() {
+            continue;
+        }
+        if found.is_some() {
+            debug!("{did:?}: more than one out-parameter");
+            return None;
+        }
+        let fail_status = if fail_statuses.len() == 1 {
+            fail_statuses.into_iter().next()
+        } else {
+            None
+        };
+        found = Some((
+            OutParamFn {
+                index,
+                ty,
+                fail_status,
+            },
+            uses,
+        ));
+    }
+    found
+}
+
+/// Collects calls to functions with out-parameters, and other references to them.
+struct CallVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    fns: &'a HashMap<DefId, OutParamFn<'tcx>>,
+    callees: HashSet<HirId>,
+    uses: Vec<OutParamUse>,
+    rejected: HashSet<DefId>,
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for CallVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            ExprKind::Call(func, _) => {
+                self.callees.insert(func.hir_id);
+                if let Some((callee, args)) = out_param_callee(self.typeck_results, ex, self.fns) {
+                    let index = self.fns[&callee].index;
+                    let arg = &args[index];
+                    let source_map = self.tcx.sess.source_map();
+                    // Remove the argument along with one of the commas next to it.
+                    let out_arg = if index > 0 {
+                        args[index - 1].span.between(arg.span).to(arg.span)
+                    } else if args.len() > 1 {
+                        arg.span.until(args[1].span)
+                    } else {
+                        arg.span
+                    };
+                    let dest = match strip_casts(arg).kind {
+                        ExprKind::AddrOf(BorrowKind::Ref, Mutability::Mut, place) => {
+                            OutDest::Place(place.span)
+                        }
+                        _ => OutDest::Ptr(strip_casts(arg).span),
+                    };
+                    // Other pointer expressions would need parentheses when dereferenced.
+                    let simple_dest = match dest {
+                        OutDest::Place(_) => true,
+                        OutDest::Ptr(_) => local_of(strip_casts(arg)).is_some(),
+                    };
+                    if !simple_dest
+                        || out_arg.from_expansion()
+                        || !source_map.is_span_accessible(out_arg)
+                    {
+                        debug!("{callee:?}: unsupported call at {:?}", ex.span);
+                        self.rejected.insert(callee);
+                    }
+                    self.uses.push(OutParamUse::Call {
+                        call: ex.span,
+                        callee,
+                        out_arg,
+                        dest,
+                    });
+                }
+            }
+            ExprKind::Path(ref qpath) if !self.callees.contains(&ex.hir_id) => {
+                if let Res::Def(DefKind::Fn, did) = self.typeck_results.qpath_res(qpath, ex.hir_id)
+                {
+                    if self.fns.contains_key(&did) {
+                        debug!("{did:?}: used other than by a direct call at {:?}", ex.span);
+                        self.rejected.insert(did);
+                    }
+                }
+            }
+            _ => {}
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+fn collect_calls_and_rejects<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ldid: LocalDefId,
+    fns: &HashMap<DefId, OutParamFn<'tcx>>,
+) -> (Vec<OutParamUse>, HashSet<DefId>) {
+    let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+        Some(x) => x,
+        None => return (Vec::new(), HashSet::new()),
+    };
+    let mut v = CallVisitor {
+        tcx,
+        typeck_results: tcx.typeck_body(hir_body_id),
+        fns,
+        callees: HashSet::new(),
+        uses: Vec::new(),
+        rejected: HashSet::new(),
+    };
+    v.visit_body(tcx.hir().body(hir_body_id));
+    (v.uses, v.rejected)
+}
+
+/// Collect the uses within the body of `ldid` that need rewriting: uses of its own out-parameter,
+/// if it's one of `info.fns`, and calls to any of `info.fns`.
+pub fn collect_out_param_uses(
+    tcx: TyCtxt,
+    ldid: LocalDefId,
+    info: &OutParamInfo,
+) -> Vec<OutParamUse> {
+    let mut uses = Vec::new();
+    if info.fns.contains_key(&ldid.to_def_id()) {
+        let (_, body_uses) =
+            find_out_param(tcx, ldid).expect("out-parameter should have been found already");
+        uses.extend(body_uses);
+    }
+    uses.extend(collect_calls_and_rejects(tcx, ldid, &info.fns).0);
+    uses
+}
+
+/// Find the functions among `candidates` whose out-parameters can be rewritten to return values.
+pub fn find_out_param_fns<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    all_fn_ldids: &[LocalDefId],
+    candidates: &HashSet<DefId>,
+) -> OutParamInfo<'tcx> {
+    let mut fns = HashMap::new();
+    for &ldid in all_fn_ldids {
+        if !candidates.contains(&ldid.to_def_id()) {
+            continue;
+        }
+        if let Some((f, _)) = find_out_param(tcx, ldid) {
+            fns.insert(ldid.to_def_id(), f);
+        }
+    }
+    if fns.is_empty() {
+        return OutParamInfo::default();
+    }
+
+    let mut callers = HashSet::new();
+    let mut rejected = HashSet::new();
+    for &ldid in all_fn_ldids {
+        let (uses, r) = collect_calls_and_rejects(tcx, ldid, &fns);
+        if !uses.is_empty() {
+            callers.insert(ldid.to_def_id());
+        }
+        rejected.extend(r);
+    }
+    // Rejecting a function only removes some calls, so no other function is affected.
+    fns.retain(|did, _| !rejected.contains(did));
+    OutParamInfo { fns, callers }
+}
@@ -0,0 +1,235 @@
+//! Exporting the final analysis results for each pointer as JSON.
+//!
+//! When `C2RUST_ANALYZE_OUTPUT_FACTS` names a file, the analysis writes one record there for each
+//! pointer in the crate: function signatures, locals, rvalues, statics, and fields.  Each record
+//! gives the pointer's source span, its final permissions and flags, its type before and after
+//! rewriting, and the reason its owner was left unrewritten, if any.  This is meant for external tools that
+//! consume the analysis results, so unlike the debug output on stderr, the format is kept stable.
+//!
+//! Local `PointerId`s are only unique within a function, so records are identified by the
+//! combination of `crate`, `owner`, and `ptr`.  When several crates are analyzed in one build,
+//! each appends its records to the same file.
+
+use crate::context::{FlagSet, GlobalAnalysisCtxt, LTy, PermissionSet};
+use crate::json_file;
+use crate::pointee_type::PointeeTypes;
+use crate::pointer_id::PointerId;
+use crate::rewrite;
+use rustc_hir::def_id::{DefId, LOCAL_CRATE};
+use rustc_middle::ty::{Ty, TyCtxt};
+use rustc_span::Span;
+use serde::Serialize;
+use std::env;
+use std::fmt::Debug;
+use std::io;
+use std::ops::Index;
+use std::path::{Path, PathBuf};
+
+/// A source location range.  Lines and columns are 1-based.
+#[derive(Clone, Debug, Serialize)]
+pub struct SpanFact {
+    pub file: String,
+    pub lo_line: usize,
+    pub lo_col: usize,
+    pub hi_line: usize,
+    pub hi_col: usize,
+}
+
+/// Where a pointer appears.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FactKind {
+    /// A function signature.
+    Sig,
+    /// A variable declared in a function body.
+    Var,
+    /// Any other MIR local, including the locals for arguments, the return value, and
+    /// temporaries.
+    MirLocal,
+    /// The address of a MIR local.
+    AddrOf,
+    /// The result of an rvalue.
+    Rvalue,
+    /// A `static` or its address.
+    Static,
+    /// A struct field.
+    Field,
+}
+
+/// The analysis results for a single pointer.
+#[derive(Clone, Debug, Serialize)]
+pub struct PointerFact {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    /// The `PointerId`, as printed in the debug output (`l3`, `g12`).
+    pub ptr: String,
+    /// The def path of the function, static, or field whose type contains the pointer.
+    pub owner: String,
+    pub kind: FactKind,
+    /// Which part of `owner` the pointer belongs to, such as a local or a signature position.
+    pub desc: String,
+    pub span: Option<SpanFact>,
+    pub perms: Vec<String>,
+    pub flags: Vec<String>,
+    /// The type of the pointer before rewriting, or `None` if it isn't written in the source, as
+    /// with the address of a local.
+    pub orig_ty: Option<String>,
+    /// The rewritten type of the pointer, or `None` if no type is computed for this pointer.
+    pub rewrite_ty: Option<String>,
+    /// Why the owner is not rewritten, or `None` if it is.
+    pub failure: Option<String>,
+}
+
+/// Convert `span` to a `SpanFact`, or return `None` if it's a dummy span.  Spans from macro
+/// expansions are replaced with the span of the macro call.
+pub fn span_fact(tcx: TyCtxt, span: Span) -> Option<SpanFact> {
+    if span.is_dummy() {
+        return None;
+    }
+    let span = span.source_callsite();
+    let source_map = tcx.sess.source_map();
+    let lo = source_map.lookup_char_pos(span.lo());
+    let hi = source_map.lookup_char_pos(span.hi());
+    Some(SpanFact {
+        file: lo.file.name.prefer_local().to_string(),
+        lo_line: lo.line,
+        lo_col: lo.col.0 + 1,
+        hi_line: hi.line,
+        hi_col: hi.col.0 + 1,
+    })
+}
+
+/// Get the file to write pointer facts to, if one was set.
+pub fn output_path() -> Option<PathBuf> {
+    env::var_os("C2RUST_ANALYZE_OUTPUT_FACTS").map(PathBuf::from)
+}
+
+/// Split the `Debug` output of a `bitflags` set, like `READ | UNIQUE`, into its flag names.
+fn flag_names(flags: impl Debug) -> Vec<String> {
+    let s = format!("{:?}", flags);
+    if s == "(empty)" {
+        return Vec::new();
+    }
+    s.split(" | ").map(|name| name.to_owned()).collect()
+}
+
+/// Describe why the function `did` is not rewritten, or return `None` if it is.
+pub fn fn_failure(gacx: &GlobalAnalysisCtxt, did: DefId) -> Option<String> {
+    let reason = gacx.dont_rewrite_fns.get(did);
+    if reason.is_empty() {
+        return None;
+    }
+    Some(match gacx.fns_failed.get(&did) {
+        Some(detail) => format!("{:?}: {}", reason, detail.to_string_short()),
+        None => format!("{:?}", reason),
+    })
+}
+
+/// Describe why the static `did` is not rewritten, or return `None` if it is.
+pub fn static_failure(gacx: &GlobalAnalysisCtxt, did: DefId) -> Option<String> {
+    let reason = gacx.dont_rewrite_statics.get(did);
+    (!reason.is_empty()).then(|| format!("{:?}", reason))
+}
+
+/// Describe why the field `did` is not rewritten, or return `None` if it is.
+pub fn field_failure(gacx: &GlobalAnalysisCtxt, did: DefId) -> Option<String> {
+    let reason = gacx.dont_rewrite_fields.get(did);
+    (!reason.is_empty()).then(|| format!("{:?}", reason))
+}
+
+/// Accumulates `PointerFact`s for the whole crate.
+pub struct OutputFacts<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    facts: Vec<PointerFact>,
+}
+
+impl<'tcx> OutputFacts<'tcx> {
+    pub fn new(tcx: TyCtxt<'tcx>) -> OutputFacts<'tcx> {
+        OutputFacts {
+            tcx,
+            facts: Vec::new(),
+        }
+    }
+
+    /// Record the results for a single pointer.  Does nothing if `ptr` is `PointerId::NONE`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_ptr(
+        &mut self,
+        owner: &str,
+        kind: FactKind,
+        desc: &str,
+        span: Span,
+        ptr: PointerId,
+        perms: &impl Index<PointerId, Output = PermissionSet>,
+        flags: &impl Index<PointerId, Output = FlagSet>,
+        orig_ty: Option<Ty<'tcx>>,
+        rewrite_ty: Option<Ty<'tcx>>,
+        failure: Option<&str>,
+    ) {
+        if ptr.is_none() {
+            return;
+        }
+        let span = span_fact(self.tcx, span);
+        self.facts.push(PointerFact {
+            krate: self.tcx.crate_name(LOCAL_CRATE).to_string(),
+            ptr: ptr.to_string(),
+            owner: owner.to_owned(),
+            kind,
+            desc: desc.to_owned(),
+            span,
+            perms: flag_names(perms[ptr]),
+            flags: flag_names(flags[ptr]),
+            orig_ty: orig_ty.map(|ty| format!("{:?}", ty)),
+            rewrite_ty: rewrite_ty.map(|ty| format!("{:?}", ty)),
+            failure: failure.map(|s| s.to_owned()),
+        });
+    }
+
+    /// Record the results for every pointer in `lty`, along with its rewritten type.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_lty(
+        &mut self,
+        gacx: &GlobalAnalysisCtxt<'tcx>,
+        owner: &str,
+        kind: FactKind,
+        desc: &str,
+        span: Span,
+        lty: LTy<'tcx>,
+        perms: &impl Index<PointerId, Output = PermissionSet>,
+        flags: &impl Index<PointerId, Output = FlagSet>,
+        pointee_types: &impl Index<PointerId, Output = PointeeTypes<'tcx>>,
+        failure: Option<&str>,
+    ) {
+        let rw_tys = rewrite::rewritten_ptr_tys(gacx, perms, flags, pointee_types, lty);
+        for (ptr, ty) in rw_tys {
+            let orig_ty = lty.iter().find(|l| l.label == ptr).map(|l| l.ty);
+            self.add_ptr(
+                owner,
+                kind,
+                desc,
+                span,
+                ptr,
+                perms,
+                flags,
+                orig_ty,
+                Some(ty),
+                failure,
+            );
+        }
+    }
+
+    pub fn facts(&self) -> &[PointerFact] {
+        &self.facts
+    }
+
+    /// Write all the recorded facts to `path` as a JSON array.  If `path` already holds the facts
+    /// for another crate, the new facts are added after them.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let facts = self
+            .facts
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        json_file::append_to_array(path, facts)
+    }
+}
@@ -33,6 +33,12 @@ impl PanicDetail {
         self.backtrace.is_some()
     }
 
+    /// The source location that was being processed when the panic occurred, or `DUMMY_SP` if
+    /// it's unknown.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
     /// Return a short (usually one-line) description of this panic.
     pub fn to_string_short(&self) -> String {
         let loc_str = self
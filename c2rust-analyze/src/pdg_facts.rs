@@ -0,0 +1,83 @@
+//! Permissions observed at run time, read from a PDG produced by `c2rust-pdg`.
+//!
+//! The PDG records every operation performed on each pointer during an instrumented run of the
+//! program.  A pointer that was loaded from, stored to, offset, or freed at run time certainly
+//! needs the corresponding permission, and a pointer whose uses were interleaved with those of
+//! another pointer to the same object can't be `UNIQUE`.  These facts seed the initial
+//! assignment, and the static analysis then propagates them along with its own constraints, so
+//! both kinds of evidence end up in the same `PermissionSet`s.
+//!
+//! The absence of an operation in the PDG only means that it didn't happen in the runs that were
+//! recorded, so observed facts only ever add permissions (or remove `UNIQUE`).
+
+use crate::context::PermissionSet;
+use c2rust_pdg::graph::{Graph, Graphs, NodeId, NodeKind};
+use rustc_index::vec::IndexVec;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Get the path of the PDG to read, if one was provided with `--pdg-facts`.  The `PDG_FILE`
+/// variable used by older scripts is also accepted.
+pub fn pdg_facts_path() -> Option<PathBuf> {
+    env::var_os("C2RUST_ANALYZE_PDG_FACTS")
+        .or_else(|| env::var_os("PDG_FILE"))
+        .map(PathBuf::from)
+}
+
+pub fn load_pdg_facts(path: &Path) -> Graphs {
+    let f = File::open(path).unwrap_or_else(|e| panic!("failed to open PDG {path:?}: {e}"));
+    bincode::deserialize_from(BufReader::new(f))
+        .unwrap_or_else(|e| panic!("failed to read PDG {path:?}: {e}"))
+}
+
+/// Compute the permissions observed for each node of `g`, or `None` for nodes whose `NodeInfo`
+/// wasn't computed.
+pub fn observed_perms(g: &Graph) -> IndexVec<NodeId, Option<PermissionSet>> {
+    // Only the objects created by a heap allocation are owned.  Correct programs never free
+    // anything else, so this just guards against misattributed `Free` nodes.
+    let owned = matches!(
+        g.nodes.iter().next().map(|n| n.kind),
+        Some(NodeKind::Alloc(_))
+    );
+
+    // Find the nodes that flow to a `Free`.  Nodes are sorted by timestamp, so each node's
+    // `source` comes before it.
+    let mut freed = IndexVec::from_elem_n(false, g.nodes.len());
+    for (id, n) in g.nodes.iter_enumerated().rev() {
+        if matches!(n.kind, NodeKind::Free) {
+            freed[id] = true;
+        }
+        if let (true, Some(source)) = (freed[id], n.source) {
+            freed[source] = true;
+        }
+    }
+
+    g.nodes
+        .iter_enumerated()
+        .map(|(id, n)| {
+            let info = n.info.as_ref()?;
+            let mut perms = PermissionSet::empty();
+            if info.flows_to.load.is_some() {
+                perms.insert(PermissionSet::READ);
+            }
+            if info.flows_to.store.is_some() {
+                perms.insert(PermissionSet::WRITE);
+            }
+            if info.flows_to.pos_offset.is_some() {
+                perms.insert(PermissionSet::OFFSET_ADD);
+            }
+            if info.flows_to.neg_offset.is_some() {
+                perms.insert(PermissionSet::OFFSET_SUB);
+            }
+            if owned && freed[id] {
+                perms.insert(PermissionSet::FREE);
+            }
+            if info.unique {
+                perms.insert(PermissionSet::UNIQUE);
+            }
+            Some(perms)
+        })
+        .collect()
+}
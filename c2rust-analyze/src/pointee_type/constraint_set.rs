@@ -1,7 +1,8 @@
 use crate::context::LTy;
 use crate::pointer_id::PointerId;
+use rustc_span::Span;
 use std::cell::Cell;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 pub enum Constraint<'tcx> {
@@ -29,6 +30,9 @@ pub struct ConstraintSet<'tcx> {
     pub constraints: Vec<Constraint<'tcx>>,
     constraint_dedup: HashSet<Constraint<'tcx>>,
     pub var_table: VarTable<'tcx>,
+    /// The first use site of each type in a `ContainsType` constraint.  This is used to explain
+    /// where conflicting pointee types came from.
+    pub sources: HashMap<CTy<'tcx>, Span>,
 }
 
 impl<'tcx> ConstraintSet<'tcx> {
@@ -38,8 +42,9 @@ impl<'tcx> ConstraintSet<'tcx> {
         }
     }
 
-    pub fn contains_type(&mut self, p: PointerId, cty: CTy<'tcx>) {
+    pub fn contains_type(&mut self, p: PointerId, cty: CTy<'tcx>, span: Span) {
         self.add(Constraint::ContainsType(p, cty));
+        self.sources.entry(cty).or_insert(span);
     }
 
     pub fn all_types_compatible_with(&mut self, p: PointerId, cty: CTy<'tcx>) {
@@ -11,7 +11,7 @@ mod solve;
 mod type_check;
 
 pub use self::constraint_set::{CTy, Constraint, ConstraintSet};
-pub use self::solve::{solve_constraints, PointeeTypes};
+pub use self::solve::{solve_constraints, ConflictResolution, PointeeTypes};
 
 pub fn generate_constraints<'tcx>(
     acx: &AnalysisCtxt<'_, 'tcx>,
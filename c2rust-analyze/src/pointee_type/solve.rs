@@ -1,7 +1,10 @@
 use super::constraint_set::{CTy, Constraint, ConstraintSet, VarTable};
 use crate::context::LTy;
 use crate::pointer_id::{OwnedPointerTable, PointerId, PointerTable, PointerTableMut};
+use crate::util;
 use log::warn;
+use rustc_middle::ty::{Ty, TyCtxt, TyKind};
+use rustc_span::Span;
 use std::collections::{HashMap, HashSet};
 use std::ptr;
 
@@ -133,6 +136,25 @@ pub struct PointeeTypes<'tcx> {
     /// If set, `ltys` is incomplete - the analysis identified pointee types that couldn't be
     /// exported into global scope.
     pub incomplete: bool,
+    /// The first place where the pointer was used at each type in `ltys`, if known.
+    pub sources: HashMap<Ty<'tcx>, Span>,
+    /// How to handle this pointer if `ltys` contains several incompatible types.  This is set by
+    /// [`resolve_conflict`](Self::resolve_conflict) once all pointee types are known.
+    pub resolution: Option<ConflictResolution<'tcx>>,
+}
+
+/// The policy applied to a pointer that is used with several incompatible pointee types.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConflictResolution<'tcx> {
+    /// Each of the other types can be read from a prefix of this struct type, as in the common C
+    /// pattern where a `struct Derived` begins with the fields of a `struct Base`.
+    CommonPrefix(LTy<'tcx>),
+    /// All the types are integers, floats, or arrays of those, and one of them is `u8` or `i8`.
+    /// The pointer is treated as pointing to bytes, and the other types are reads and writes of
+    /// those bytes.
+    Bytes(LTy<'tcx>),
+    /// No common type exists.  The pointee type is left unchanged.
+    KeepRaw,
 }
 
 impl<'tcx> PointeeTypes<'tcx> {
@@ -145,12 +167,103 @@ impl<'tcx> PointeeTypes<'tcx> {
         Some(lty)
     }
 
+    /// Check whether this pointer is used with several distinct pointee types.  A `void*` field
+    /// with this property can be rewritten to use a generic type parameter instead.
+    pub fn is_polymorphic(&self) -> bool {
+        if self.incomplete {
+            return false;
+        }
+        let mut tys = self.ltys.iter().map(|lty| lty.ty);
+        let first = match tys.next() {
+            Some(ty) => ty,
+            None => return false,
+        };
+        tys.any(|ty| ty != first)
+    }
+
+    /// Get the type to use for this pointer after resolving conflicts.  This is the sole `LTy`
+    /// if there is one, or otherwise the type chosen by `resolution`.
+    pub fn get_resolved_lty(&self) -> Option<LTy<'tcx>> {
+        match self.resolution {
+            Some(ConflictResolution::CommonPrefix(lty)) | Some(ConflictResolution::Bytes(lty)) => {
+                Some(lty)
+            }
+            Some(ConflictResolution::KeepRaw) | None => self.get_sole_lty(),
+        }
+    }
+
+    /// Get the distinct types in `ltys`, sorted for stable output.
+    pub fn distinct_tys(&self) -> Vec<Ty<'tcx>> {
+        let mut tys = self.ltys.iter().map(|lty| lty.ty).collect::<Vec<_>>();
+        tys.sort_by_cached_key(|ty| format!("{ty:?}"));
+        tys.dedup();
+        tys
+    }
+
+    /// If this pointer is used with several incompatible pointee types, choose how to handle it
+    /// and record the choice in `resolution`.  In order of preference, we look for a struct type
+    /// whose prefix can be read as each of the other types, then fall back to a byte view if all
+    /// the types are plain numeric data and the pointer is already used to access bytes, and
+    /// otherwise keep the pointer raw.  Returns the resolution, if there was a conflict.
+    pub fn resolve_conflict(&mut self, tcx: TyCtxt<'tcx>) -> Option<ConflictResolution<'tcx>> {
+        self.resolution = None;
+        if self.incomplete {
+            return None;
+        }
+        let tys = self.distinct_tys();
+        if tys.len() < 2 {
+            return None;
+        }
+
+        // Check the candidates in sorted order so the choice doesn't depend on `HashSet` order.
+        let prefix = tys.iter().copied().find(|&prefix_ty| {
+            matches!(prefix_ty.kind(), TyKind::Adt(..))
+                && tys.iter().all(|&ty| {
+                    ty == prefix_ty || util::is_layout_compatible_read(tcx, prefix_ty, ty)
+                })
+        });
+        let byte = tys.iter().copied().find(|&ty| is_byte(tcx, ty));
+        let lty_of = |ty| self.ltys.iter().copied().find(|lty| lty.ty == ty).unwrap();
+        let resolution = match (prefix, byte) {
+            (Some(ty), _) => ConflictResolution::CommonPrefix(lty_of(ty)),
+            (None, Some(ty)) if tys.iter().all(|&ty| is_numeric_data(ty)) => {
+                ConflictResolution::Bytes(lty_of(ty))
+            }
+            _ => ConflictResolution::KeepRaw,
+        };
+        self.resolution = Some(resolution);
+        Some(resolution)
+    }
+
     pub fn merge(&mut self, other: PointeeTypes<'tcx>) {
         self.ltys.extend(other.ltys);
         self.incomplete |= other.incomplete;
+        for (ty, span) in other.sources {
+            self.sources.entry(ty).or_insert(span);
+        }
+        self.resolution = match (self.resolution, other.resolution) {
+            (None, r) | (r, None) => r,
+            (Some(r1), Some(r2)) if r1 == r2 => Some(r1),
+            // The two pointers were resolved differently, so there's no single type that works
+            // for both.
+            _ => Some(ConflictResolution::KeepRaw),
+        };
+    }
+}
+
+/// Check whether `ty` is an integer, a float, or an array of those.
+fn is_numeric_data(ty: Ty) -> bool {
+    match *ty.kind() {
+        TyKind::Int(_) | TyKind::Uint(_) | TyKind::Float(_) => true,
+        TyKind::Array(elem_ty, _) => is_numeric_data(elem_ty),
+        _ => false,
     }
 }
 
+fn is_byte<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> bool {
+    ty == tcx.types.u8 || ty == tcx.types.i8
+}
+
 /// Copy `LTy`s from `pointee_tys` into `ty_sets` for processing by the analysis.
 fn import<'tcx>(
     pointee_tys: PointerTable<PointeeTypes<'tcx>>,
@@ -167,6 +280,7 @@ fn import<'tcx>(
 /// Compute concrete `LTy`s for all the `CTy`s in `ty_sets`, and add them into `pointee_tys`.
 fn export<'tcx>(
     var_table: &VarTable<'tcx>,
+    sources: &HashMap<CTy<'tcx>, Span>,
     ty_sets: PointerTable<HashSet<CTy<'tcx>>>,
     mut pointee_tys: PointerTableMut<PointeeTypes<'tcx>>,
 ) {
@@ -174,6 +288,9 @@ fn export<'tcx>(
         let out = &mut pointee_tys[ptr];
         for &cty in ctys {
             if let CTy::Ty(lty) = var_table.cty_rep(cty) {
+                if let Some(&span) = sources.get(&cty) {
+                    out.sources.entry(lty.ty).or_insert(span);
+                }
                 let mut ok = true;
                 lty.for_each_label(&mut |p| {
                     if p.is_local() {
@@ -205,5 +322,10 @@ pub fn solve_constraints<'tcx>(
     import(pointee_tys.borrow(), ty_sets.borrow_mut());
     init_type_sets(cset, ty_sets.borrow_mut());
     propagate_types(cset, ty_sets.borrow_mut());
-    export(&cset.var_table, ty_sets.borrow(), pointee_tys.borrow_mut());
+    export(
+        &cset.var_table,
+        &cset.sources,
+        ty_sets.borrow(),
+        pointee_tys.borrow_mut(),
+    );
 }
@@ -1,18 +1,24 @@
 use super::constraint_set::{CTy, ConstraintSet};
-use crate::context::{AnalysisCtxt, LTy, PointerId};
+use crate::align;
+use crate::context::{AnalysisCtxt, LFnSig, LTy, PointerId};
+use crate::fn_tables;
 use crate::panic_detail;
-use crate::util::{describe_rvalue, ty_callee, Callee, RvalueDesc, UnknownDefCallee};
+use crate::util::{self, describe_rvalue, ty_callee, Callee, RvalueDesc, UnknownDefCallee};
 use log::*;
 use rustc_middle::mir::{
-    BinOp, Body, Location, Operand, Place, PlaceRef, ProjectionElem, Rvalue, Statement,
-    StatementKind, Terminator, TerminatorKind,
+    BinOp, Body, CastKind, LocalKind, Location, Operand, Place, PlaceRef, ProjectionElem, Rvalue,
+    Statement, StatementKind, Terminator, TerminatorKind,
 };
-use rustc_middle::ty::{Ty, TyKind};
+use rustc_middle::ty::adjustment::PointerCast;
+use rustc_middle::ty::TyKind;
+use rustc_span::Span;
 
 struct TypeChecker<'tcx, 'a> {
     acx: &'a AnalysisCtxt<'a, 'tcx>,
     mir: &'a Body<'tcx>,
     constraints: ConstraintSet<'tcx>,
+    /// Span of the statement or terminator currently being visited.
+    span: Span,
 }
 
 impl<'tcx> TypeChecker<'tcx, '_> {
@@ -22,7 +28,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
         }
         let cty = ty.into();
         trace!("use_pointer_at_type({ptr:?}, {cty:?})");
-        self.constraints.contains_type(ptr, cty);
+        self.constraints.contains_type(ptr, cty, self.span);
     }
 
     fn define_pointer(&mut self, ptr: PointerId) {
@@ -51,6 +57,54 @@ impl<'tcx> TypeChecker<'tcx, '_> {
         self.constraints.subset(lhs, rhs);
     }
 
+    /// If `op` is a temporary holding a pointer that was cast to `*mut c_void` or
+    /// `*const c_void`, as in `memcpy(dest as *mut c_void, ...)`, return the pointee type of the
+    /// pointer before the cast.  This looks through chains of casts, as in
+    /// `p as *const T as *const c_void`, and stops at the first pointee type that isn't `c_void`.
+    fn void_cast_source_pointee(&self, op: &Operand<'tcx>) -> Option<LTy<'tcx>> {
+        let tcx = self.acx.tcx();
+        let mut op = op;
+        loop {
+            let lty = self.acx.type_of(op);
+            if !matches!(lty.ty.kind(), TyKind::RawPtr(..) | TyKind::Ref(..)) {
+                return None;
+            }
+            let pointee_lty = lty.args[0];
+            if !util::is_c_void(tcx, pointee_lty.ty) {
+                return Some(pointee_lty);
+            }
+            op = self.cast_def(op.place()?)?;
+        }
+    }
+
+    /// If `pl` is a temporary whose only definition is a pointer cast, return the operand of the
+    /// cast.
+    fn cast_def(&self, pl: Place<'tcx>) -> Option<&Operand<'tcx>> {
+        if !pl.projection.is_empty() || self.mir.local_kind(pl.local) != LocalKind::Temp {
+            return None;
+        }
+        let mut def = None;
+        for bb_data in self.mir.basic_blocks().iter() {
+            for stmt in &bb_data.statements {
+                if let StatementKind::Assign(ref x) = stmt.kind {
+                    if x.0.local == pl.local {
+                        if def.is_some() {
+                            return None;
+                        }
+                        def = Some(&x.1);
+                    }
+                }
+            }
+        }
+        match *def? {
+            Rvalue::Cast(CastKind::Misc, ref op, _)
+            | Rvalue::Cast(CastKind::Pointer(PointerCast::MutToConstPointer), ref op, _) => {
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
     /// Visit a `Place`, adding constraints as needed.
     ///
     /// As a convenience, this returns the `LTy` of the place, identical to `acx.type_of(pl)`.
@@ -122,11 +176,20 @@ impl<'tcx> TypeChecker<'tcx, '_> {
             Rvalue::Len(pl) => {
                 self.visit_place(pl);
             }
-            Rvalue::Cast(_kind, ref op, _ty) => {
+            Rvalue::Cast(kind, ref op, ty) => {
                 self.visit_operand(op);
 
                 let op_lty = self.acx.type_of(op);
                 self.assign(lty.label, op_lty.label);
+
+                // Rounding a pointer up to an aligned address keeps its pointee type.
+                if kind == CastKind::PointerFromExposedAddress {
+                    let tcx = self.acx.tcx();
+                    if let Some((ptr, _)) = align::align_up_source(tcx, self.mir, op, ty) {
+                        let ptr_lty = self.acx.type_of(ptr);
+                        self.assign(lty.label, ptr_lty.label);
+                    }
+                }
             }
             Rvalue::BinaryOp(bin_op, ref ops) | Rvalue::CheckedBinaryOp(bin_op, ref ops) => {
                 assert_ne!(bin_op, BinOp::Offset, "BinOp::Offset special case NYI");
@@ -172,6 +235,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
             stmt.source_info.span
         );
         let _g = panic_detail::set_current_span(stmt.source_info.span);
+        self.span = stmt.source_info.span;
 
         match stmt.kind {
             StatementKind::Assign(ref x) => {
@@ -187,14 +251,14 @@ impl<'tcx> TypeChecker<'tcx, '_> {
         }
     }
 
-    pub fn visit_terminator(&mut self, term: &Terminator<'tcx>, _loc: Location) {
+    pub fn visit_terminator(&mut self, term: &Terminator<'tcx>, loc: Location) {
         trace!(
             "visit_terminator({:?} @ {:?})",
             term.kind,
             term.source_info.span
         );
         let _g = panic_detail::set_current_span(term.source_info.span);
-        let tcx = self.acx.tcx();
+        self.span = term.source_info.span;
 
         match term.kind {
             TerminatorKind::Call {
@@ -209,16 +273,34 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 }
                 let dest_lty = self.visit_place(destination);
 
-                let func = func.ty(self.mir, tcx);
-                self.visit_call(func, args, dest_lty);
+                self.visit_call(loc, func, args, dest_lty);
             }
             _ => (),
         }
     }
 
-    pub fn visit_call(&mut self, func: Ty<'tcx>, args: &[Operand<'tcx>], dest_lty: LTy<'tcx>) {
+    /// Add constraints for a call to a function with signature `sig`.
+    fn visit_sig_call(&mut self, sig: LFnSig<'tcx>, args: &[Operand<'tcx>], dest_lty: LTy<'tcx>) {
+        // Process pseudo-assignments from `args` to the types declared in `sig`.
+        for (arg_op, &input_lty) in args.iter().zip(sig.inputs.iter()) {
+            let arg_lty = self.acx.type_of(arg_op);
+            self.assign(input_lty.label, arg_lty.label);
+        }
+
+        // Process a pseudo-assignment from the return type declared in `sig` to `dest`.
+        let output_lty = sig.output;
+        self.assign(dest_lty.label, output_lty.label);
+    }
+
+    pub fn visit_call(
+        &mut self,
+        loc: Location,
+        func: &Operand<'tcx>,
+        args: &[Operand<'tcx>],
+        dest_lty: LTy<'tcx>,
+    ) {
         let tcx = self.acx.tcx();
-        let callee = ty_callee(tcx, func);
+        let callee = ty_callee(tcx, func.ty(self.mir, tcx));
         eprintln!("callee = {callee:?}");
         match callee {
             Callee::Trivial => {}
@@ -232,16 +314,38 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 if substs.non_erasable_generics().next().is_some() {
                     todo!("call to generic function {def_id:?} {substs:?}");
                 }
-
-                // Process pseudo-assignments from `args` to the types declared in `sig`.
-                for (arg_op, &input_lty) in args.iter().zip(sig.inputs.iter()) {
-                    let arg_lty = self.acx.type_of(arg_op);
-                    self.assign(input_lty.label, arg_lty.label);
+                self.visit_sig_call(*sig, args, dest_lty);
+            }
+            Callee::UnknownDef(UnknownDefCallee::Indirect { ty, fn_sig: _ }) => {
+                let acx = self.acx;
+                // If the function pointer was loaded from a function table field, the call can
+                // only reach the functions stored in that field.
+                if let Some(targets) =
+                    fn_tables::indirect_call_targets(acx.gacx, self.mir, loc, func)
+                {
+                    for did in targets {
+                        self.visit_sig_call(acx.gacx.fn_sigs[did], args, dest_lty);
+                    }
+                    return;
                 }
 
-                // Process a pseudo-assignment from the return type declared in `sig` to `dest`.
-                let output_lty = sig.output;
-                self.assign(dest_lty.label, output_lty.label);
+                // Otherwise, the call may reach any function whose address is taken and whose
+                // signature matches the function pointer type.  Treat it as a call to each of
+                // them, so pointee types flow between the arguments and all the possible callees.
+                let ptr_sig = tcx.erase_regions(tcx.erase_late_bound_regions(ty.fn_sig(tcx)));
+                for &ldid in &acx.gacx.address_taken_fns {
+                    let did = ldid.to_def_id();
+                    let sig = match acx.gacx.fn_sigs.get(&did) {
+                        Some(&x) => x,
+                        None => continue,
+                    };
+                    let callee_sig =
+                        tcx.erase_regions(tcx.erase_late_bound_regions(tcx.fn_sig(did)));
+                    if callee_sig != ptr_sig {
+                        continue;
+                    }
+                    self.visit_sig_call(sig, args, dest_lty);
+                }
             }
             Callee::UnknownDef(UnknownDefCallee::Direct {
                 ty: _,
@@ -249,8 +353,40 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 substs: _,
                 is_foreign: true,
             }) if self.acx.gacx.known_fn(def_id).is_some() => {
-                // TODO: no good handling for this currently - might need to expand KnownFn to
-                // include information about expected/required pointee types
+                // Only user-supplied `KnownFn`s declare pointee types, and only for some of their
+                // arguments.  See `known_fn_db`.
+                let acx = self.acx;
+                for &(idx, ty) in acx.gacx.known_fn_pointees(def_id) {
+                    let pointee_lty = acx.lcx().label(ty, &mut |_| PointerId::NONE);
+                    match idx {
+                        Some(i) => {
+                            let arg_op = match args.get(i) {
+                                Some(x) => x,
+                                None => continue,
+                            };
+                            // Look through casts like `buf as *const c_void`, so the pointee type
+                            // also reaches the pointer that was cast.
+                            let mut op = arg_op;
+                            loop {
+                                self.use_pointer_at_type(acx.type_of(op).label, pointee_lty);
+                                op = match op.place().and_then(|pl| self.cast_def(pl)) {
+                                    Some(x) => x,
+                                    None => break,
+                                };
+                            }
+                        }
+                        None => self.define_pointer_with_type(dest_lty.label, pointee_lty),
+                    }
+                }
+            }
+            Callee::UnknownDef(UnknownDefCallee::Direct {
+                def_id,
+                is_foreign: false,
+                ..
+            }) if self.acx.gacx.upstream_fn_facts.contains_key(&def_id) => {
+                // A function from another crate of the workspace.  See `crate_facts`.
+                let sig = self.acx.gacx.fn_sigs[&def_id];
+                self.visit_sig_call(sig, args, dest_lty);
             }
             Callee::UnknownDef(_) => {
                 log::error!("TODO: visit Callee::{callee:?}");
@@ -303,7 +439,7 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 // type.
             }
 
-            Callee::Memcpy => {
+            Callee::Memcpy | Callee::Memmove => {
                 // We treat the `memcpy` as loading from `*src` and then storing to `*dest`.  The
                 // type of the load and store is unknown at this point (it definitely isn't the
                 // actual type of `*src`/`*dest`, which is `void`), so we introduce a new inference
@@ -317,6 +453,17 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 self.use_pointer_at_type(dest_arg_lty.label, var);
                 self.use_pointer_at_type(src_arg_lty.label, var);
                 self.assign(dest_lty.label, dest_arg_lty.label);
+
+                // The arguments are usually typed pointers cast to `void*`, as in
+                // `memcpy(dest as *mut c_void, src as *const c_void, n)`.  The pointee type before
+                // the cast is the type being copied, so the inference variable is resolved even
+                // if the pointers have no other uses.
+                if let Some(pointee_lty) = self.void_cast_source_pointee(&args[0]) {
+                    self.use_pointer_at_type(dest_arg_lty.label, pointee_lty);
+                }
+                if let Some(pointee_lty) = self.void_cast_source_pointee(&args[1]) {
+                    self.use_pointer_at_type(src_arg_lty.label, pointee_lty);
+                }
             }
             Callee::Memset => {
                 // We treat this much like `memcpy`, but with only a store, not a load.
@@ -327,14 +474,31 @@ impl<'tcx> TypeChecker<'tcx, '_> {
                 let dest_arg_lty = self.acx.type_of(&args[0]);
                 self.use_pointer_at_type(dest_lty.label, var);
                 self.assign(dest_lty.label, dest_arg_lty.label);
+
+                if let Some(pointee_lty) = self.void_cast_source_pointee(&args[0]) {
+                    self.use_pointer_at_type(dest_arg_lty.label, pointee_lty);
+                }
             }
             Callee::SizeOf { .. } => {}
+            Callee::PtrOffsetFrom => {
+                // No constraints.
+            }
             Callee::IsNull => {
                 // No constraints.
             }
             Callee::Null { .. } => {
                 // No constraints.
             }
+            Callee::VaArg => {
+                // No constraints.  The type of the argument is chosen by the caller.
+            }
+            Callee::ReadVolatile | Callee::WriteVolatile | Callee::Atomic { .. } => {
+                // The pointer is accessed at its declared pointee type, as with `*p`.
+                let arg_lty = self.acx.type_of(&args[0]);
+                if let Some(&pointee_lty) = arg_lty.args.first() {
+                    self.use_pointer_at_type(arg_lty.label, pointee_lty);
+                }
+            }
         }
     }
 }
@@ -344,6 +508,7 @@ pub fn visit<'tcx>(acx: &AnalysisCtxt<'_, 'tcx>, mir: &Body<'tcx>) -> Constraint
         acx,
         mir,
         constraints: ConstraintSet::default(),
+        span: mir.span,
     };
 
     for (bb, bb_data) in mir.basic_blocks().iter_enumerated() {
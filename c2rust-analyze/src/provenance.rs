@@ -0,0 +1,188 @@
+//! Detection of pointer operations that violate Rust's provenance rules.
+//!
+//! In C, a pointer can be turned into an integer and back, and the difference between two pointers
+//! can be added to a third.  In Rust, each pointer carries the provenance of the allocation it was
+//! derived from, and only accesses within that allocation are valid.  Two patterns in translated
+//! code break this:
+//!
+//! ```ignore
+//! // A pointer built from an integer has no provenance.
+//! let p = addr as *mut T;
+//!
+//! // `p` keeps its own provenance, so the result may not be usable to access `*q`.
+//! let r = p.offset((q as usize - p as usize) as isize);
+//! ```
+//!
+//! Neither can be expressed with safe references, so these aren't ordinary permission conflicts:
+//! the code must be changed by hand.  Functions containing an int-to-pointer cast are left
+//! unchanged (`DontRewriteFnReason::INT_TO_PTR_CAST`), and every violation is listed separately
+//! in the final report.  The round-up idiom recognized by `crate::align` is not a violation.
+
+use crate::align;
+use crate::util::{self, ty_callee, Callee};
+use rustc_middle::mir::{
+    Body, CastKind, Local, LocalKind, Operand, Rvalue, StatementKind, TerminatorKind,
+};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProvenanceViolationKind {
+    /// A pointer is created from an integer, as in `addr as *mut T`.
+    IntToPtr,
+    /// A pointer is offset by the distance to a pointer derived from a different local, so the
+    /// result may point into an allocation that its provenance doesn't cover.
+    CrossAllocation,
+}
+
+impl fmt::Display for ProvenanceViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProvenanceViolationKind::IntToPtr => write!(f, "int-to-pointer cast"),
+            ProvenanceViolationKind::CrossAllocation => {
+                write!(f, "cross-allocation pointer arithmetic")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ProvenanceViolation {
+    pub kind: ProvenanceViolationKind,
+    pub span: Span,
+    pub desc: String,
+}
+
+/// Find the local that `op` was ultimately derived from, following copies, pointer casts, and
+/// calls to `offset` through locals that are assigned exactly once.  Pointers loaded from memory
+/// or assigned more than once are their own roots.
+fn ptr_root<'tcx>(tcx: TyCtxt<'tcx>, mir: &Body<'tcx>, op: &Operand<'tcx>) -> Option<Local> {
+    let pl = op.place()?;
+    if !pl.projection.is_empty() {
+        return None;
+    }
+    let mut local = pl.local;
+    // Bound the walk, in case of cycles through loop-carried locals.
+    for _ in 0..mir.local_decls.len() {
+        let next = match single_def(tcx, mir, local) {
+            Some(next) => next,
+            None => break,
+        };
+        match next.place() {
+            Some(pl) if pl.projection.is_empty() => local = pl.local,
+            _ => break,
+        }
+    }
+    Some(local)
+}
+
+/// If `local` is assigned exactly once, and that assignment copies, casts, or offsets another
+/// pointer, return that pointer.
+fn single_def<'a, 'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir: &'a Body<'tcx>,
+    local: Local,
+) -> Option<&'a Operand<'tcx>> {
+    if mir.local_kind(local) == LocalKind::Arg {
+        return None;
+    }
+    let mut def = None;
+    for bb_data in mir.basic_blocks().iter() {
+        for stmt in &bb_data.statements {
+            if let StatementKind::Assign(ref x) = stmt.kind {
+                if x.0.local == local {
+                    if def.is_some() {
+                        return None;
+                    }
+                    def = Some(match x.1 {
+                        Rvalue::Use(ref op) => Some(op),
+                        Rvalue::Cast(CastKind::Misc | CastKind::Pointer(_), ref op, ty)
+                            if ty.is_unsafe_ptr() && op.ty(mir, tcx).is_unsafe_ptr() =>
+                        {
+                            Some(op)
+                        }
+                        _ => None,
+                    });
+                }
+            }
+        }
+        if let TerminatorKind::Call {
+            ref func,
+            ref args,
+            destination,
+            ..
+        } = bb_data.terminator().kind
+        {
+            if destination.local == local {
+                if def.is_some() {
+                    return None;
+                }
+                def = Some(match ty_callee(tcx, func.ty(mir, tcx)) {
+                    Callee::PtrOffset { .. } => args.first(),
+                    _ => None,
+                });
+            }
+        }
+    }
+    def?
+}
+
+/// Find the provenance violations in `mir`.
+pub fn find_violations<'tcx>(tcx: TyCtxt<'tcx>, mir: &Body<'tcx>) -> Vec<ProvenanceViolation> {
+    let mut violations = Vec::new();
+    for bb_data in mir.basic_blocks().iter() {
+        for stmt in &bb_data.statements {
+            let rv = match stmt.kind {
+                StatementKind::Assign(ref x) => &x.1,
+                _ => continue,
+            };
+            if let Rvalue::Cast(CastKind::PointerFromExposedAddress, ref op, ty) = *rv {
+                if util::is_null_const_operand(op)
+                    || align::align_up_source(tcx, mir, op, ty).is_some()
+                {
+                    continue;
+                }
+                let desc = if align::any_addr_source(tcx, mir, op).is_some() {
+                    "pointer is rebuilt from the address of another pointer; \
+                     offset the original pointer instead"
+                } else {
+                    "pointer is created from an integer that has no provenance"
+                };
+                violations.push(ProvenanceViolation {
+                    kind: ProvenanceViolationKind::IntToPtr,
+                    span: stmt.source_info.span,
+                    desc: desc.into(),
+                });
+            }
+        }
+
+        let term = bb_data.terminator();
+        let args = match term.kind {
+            TerminatorKind::Call {
+                ref func, ref args, ..
+            } if matches!(ty_callee(tcx, func.ty(mir, tcx)), Callee::PtrOffset { .. }) => args,
+            _ => continue,
+        };
+        let (base, amount) = match **args {
+            [ref base, ref amount] => (base, amount),
+            _ => continue,
+        };
+        let (to, from) = match align::addr_difference(tcx, mir, amount) {
+            Some(x) => x,
+            None => continue,
+        };
+        let base_root = ptr_root(tcx, mir, base);
+        let to_root = ptr_root(tcx, mir, to);
+        if base_root.is_some() && base_root == ptr_root(tcx, mir, from) && to_root != base_root {
+            violations.push(ProvenanceViolation {
+                kind: ProvenanceViolationKind::CrossAllocation,
+                span: term.source_info.span,
+                desc: "pointer is offset by the distance to a pointer that may be in another \
+                    allocation; use that pointer directly"
+                    .into(),
+            });
+        }
+    }
+    violations
+}
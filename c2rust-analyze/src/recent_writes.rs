@@ -1,7 +1,7 @@
-use log::error;
 use rustc_index::vec::IndexVec;
 use rustc_middle::mir::{
-    BasicBlock, Body, Local, Location, Place, Rvalue, StatementKind, TerminatorKind,
+    BasicBlock, Body, InlineAsmOperand, Local, Location, Place, Rvalue, StatementKind,
+    TerminatorKind,
 };
 use std::collections::HashMap;
 
@@ -64,6 +64,16 @@ impl BlockWrites {
         loc: Location,
         l: Local,
     ) -> Option<Location> {
+        match self.get_written_before(loc, l) {
+            Written::Never => None,
+            Written::At(write_loc) => Some(write_loc),
+            Written::Multiple => None,
+        }
+    }
+
+    /// Get the state of `l` just before `loc`.  A partial write within this block is reported as
+    /// `Written::Multiple`.
+    fn get_written_before(&self, loc: Location, l: Local) -> Written {
         if let Some(writes) = self.writes.get(&l) {
             // `l` was written at some point within this block.  Find the first `statement_index`
             // in `writes` that's strictly less than `loc.statement_index`.
@@ -85,9 +95,9 @@ impl BlockWrites {
                 if write.partial {
                     // If the local was only partially written here, then this is only one of
                     // several rewrites potentially affecting its value.
-                    return None;
+                    return Written::Multiple;
                 } else {
-                    return Some(Location {
+                    return Written::At(Location {
                         block: loc.block,
                         statement_index: write.statement_index,
                     });
@@ -97,11 +107,7 @@ impl BlockWrites {
 
         // `l` was not written between the start of the block and `loc`.  Report its most recent
         // write prior to block entry.
-        match self.entry[l] {
-            Written::Never => None,
-            Written::At(write_loc) => Some(write_loc),
-            Written::Multiple => None,
-        }
+        self.entry[l]
     }
 
     fn exit_state(&self, block: BasicBlock) -> IndexVec<Local, Written> {
@@ -146,6 +152,23 @@ impl RecentWrites {
         self.blocks[loc.block].get_write_before_ignoring_addr_taken(loc, l)
     }
 
+    /// Check whether `l` definitely holds the same value at `loc1` and `loc2`.  This is the case
+    /// when the most recent write to `l` is the same at both locations, or when `l` hasn't been
+    /// written before either location (as with function arguments).  Returns `false` if `l` has
+    /// had its address taken.
+    pub fn same_value_at(&self, loc1: Location, loc2: Location, l: Local) -> bool {
+        if self.addr_taken[l] {
+            return false;
+        }
+        let w1 = self.blocks[loc1.block].get_written_before(loc1, l);
+        let w2 = self.blocks[loc2.block].get_written_before(loc2, l);
+        match (w1, w2) {
+            (Written::Never, Written::Never) => true,
+            (Written::At(a), Written::At(b)) => a == b,
+            _ => false,
+        }
+    }
+
     /// Record a write to a `Place` occurring at `loc`.
     fn record_place_written(&mut self, loc: Location, pl: Place) {
         if pl.is_indirect() {
@@ -310,7 +333,20 @@ fn scan_blocks(mir: &Body, rw: &mut RecentWrites) {
             }
             TerminatorKind::Call { destination, .. } => rw.record_place_written(loc, destination),
             TerminatorKind::Yield { resume_arg, .. } => rw.record_place_written(loc, resume_arg),
-            TerminatorKind::InlineAsm { .. } => error!("recent_writes InlineAsm NYI"),
+            TerminatorKind::InlineAsm { ref operands, .. } => {
+                for op in operands {
+                    match *op {
+                        InlineAsmOperand::Out {
+                            place: Some(pl), ..
+                        }
+                        | InlineAsmOperand::InOut {
+                            out_place: Some(pl),
+                            ..
+                        } => rw.record_place_written(loc, pl),
+                        _ => {}
+                    }
+                }
+            }
             _ => {}
         }
     }
@@ -0,0 +1,907 @@
+//! Detection of reference-counted structs, for the optional `Rc<RefCell<T>>` rewrite mode.
+//!
+//! C code often shares ownership of a heap object by storing a reference count in the object
+//! itself, with the last owner freeing it:
+//!
+//! ```ignore
+//! unsafe fn obj_release(p: *mut Obj) {
+//!     (*p).refcount -= 1;
+//!     if (*p).refcount == 0 {
+//!         free(p as *mut c_void);
+//!     }
+//! }
+//! ```
+//!
+//! Pointers to such an object are never `UNIQUE`, so the normal rewrites can't turn them into
+//! `Box`, and they are left as raw pointers.  When `C2RUST_ANALYZE_USE_RC=1` is set, we instead
+//! look for structs whose every `free` is guarded by a check that one of its fields is zero, and
+//! rewrite all pointers to those structs as `Rc<RefCell<T>>`.  Allocations become
+//! `Rc::new(RefCell::new(..))`, each copy of a pointer becomes a `clone()`, each guarded `free`
+//! becomes a `drop`, and each dereference goes through `borrow()` or `borrow_mut()`.  The
+//! original reference count field is kept, but no longer controls when the object is freed.
+//!
+//! Reference counts are usually manipulated through a pair of `obj_ref`/`obj_unref` functions,
+//! which do nothing but increment the count, or decrement it and free the object at zero.  We
+//! detect such functions (see [`RcApiFn`]), remove them, and replace calls to them with `clone()`
+//! and `drop()`, along with the count field and any assignments like `(*p).refcount = 1`.  This is
+//! only done if the count field has no other uses.  Otherwise, removing the functions would leave
+//! the count stale, so they are kept and called as before, which keeps the count accurate.
+//!
+//! Only simple uses of the pointers are supported: any other use, such as a null pointer, a cast,
+//! pointer arithmetic, or storing the pointer in a struct field or `static`, prevents the struct
+//! from being rewritten.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::rewrite;
+use crate::util::{self, Callee};
+use log::debug;
+use rustc_ast::LitKind;
+use rustc_hir as hir;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{BinOpKind, Block, Expr, ExprKind, HirId, Local, StmtKind, UnOp};
+use rustc_middle::ty::adjustment::{Adjust, AutoBorrow, AutoBorrowMutability};
+use rustc_middle::ty::{GenericArgKind, Ty, TyCtxt, TyKind, TypeckResults, WithOptConstParam};
+use rustc_span::Span;
+use std::collections::{HashMap, HashSet};
+
+/// A single use of a pointer to a reference-counted struct.
+#[derive(Clone, Copy, Debug)]
+pub enum RcPtrUse<'tcx> {
+    /// A new object is allocated, as in `malloc(n) as *mut S`.  `expr` is the whole cast
+    /// expression.
+    Alloc { expr: Span, pointee_ty: Ty<'tcx> },
+    /// The object is freed, as in `free(p as *mut c_void)`.  `guard` is the field that was
+    /// checked for zero in the enclosing `if`, or `None` if there is no such check.
+    Free {
+        call: Span,
+        ptr: Span,
+        guard: Option<DefId>,
+    },
+    /// The pointer is dereferenced, as in `(*p).x`.  `mutbl` is set if the resulting place is
+    /// written or mutably borrowed.
+    Deref { expr: Span, ptr: Span, mutbl: bool },
+    /// A pointer held in a local is copied, as in `let q = p` or `f(p)`.
+    Copy { expr: Span },
+    /// A pointer type is written in the source, as in `*mut S`.  `pointee` is the span of `S`.
+    Ty { ty: Span, pointee: Span },
+    /// A call to one of the `RefcountInfo::api_fns`, as in `obj_unref(p)`.  `ptr` is the span of
+    /// the argument, and `local` is set if the argument is a local variable.
+    ApiCall {
+        call: Span,
+        ptr: Span,
+        local: bool,
+        kind: RcApiFn,
+    },
+    /// Any other use of the pointer.  This prevents the struct from being rewritten.
+    Other,
+}
+
+/// A function that does nothing but update the reference count of its argument.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RcApiFn {
+    /// `(*p).refcount += 1`, optionally followed by `return p`.  `returns_ptr` is set in the
+    /// latter case.
+    Ref { returns_ptr: bool },
+    /// `(*p).refcount -= 1; if (*p).refcount == 0 { free(p) }`.
+    Unref,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RefcountInfo {
+    /// Reference-counted structs that will be rewritten, mapped to the field that holds the
+    /// reference count.
+    pub structs: HashMap<DefId, DefId>,
+    /// Functions that mention pointers to any of the `structs`.  These are excluded from the
+    /// normal rewrites and handled entirely by `rewrite::gen_refcount_rewrites`.
+    pub fns: HashSet<DefId>,
+    /// Reference counting functions among the `fns`.  These are removed, and calls to them become
+    /// `clone()` or `drop()`.
+    pub api_fns: HashMap<DefId, RcApiFn>,
+    /// Structs whose reference count field is no longer needed, and is removed.
+    pub removed_count_fields: HashSet<DefId>,
+    /// Assignments like `(*p).refcount = 1` to the removed count fields.  Each span covers the
+    /// whole statement.
+    pub count_inits: Vec<Span>,
+}
+
+/// Visitor that finds all uses of pointers to the `candidates` structs within a body.
+pub struct RcPtrUseVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    candidates: &'a HashSet<DefId>,
+    api_fns: &'a HashMap<DefId, RcApiFn>,
+    pub uses: Vec<(DefId, RcPtrUse<'tcx>)>,
+    /// Pointer expressions that were already handled as part of an enclosing expression.
+    handled: HashSet<HirId>,
+    /// Deref expressions whose places are written or mutably borrowed.
+    mut_derefs: HashSet<HirId>,
+    /// Refcount checks of the enclosing `if`s, as `(local, field)` pairs.
+    guards: Vec<(HirId, DefId)>,
+}
+
+impl<'a, 'tcx> RcPtrUseVisitor<'a, 'tcx> {
+    pub fn new(
+        tcx: TyCtxt<'tcx>,
+        typeck_results: &'tcx TypeckResults<'tcx>,
+        candidates: &'a HashSet<DefId>,
+        api_fns: &'a HashMap<DefId, RcApiFn>,
+    ) -> RcPtrUseVisitor<'a, 'tcx> {
+        RcPtrUseVisitor {
+            tcx,
+            typeck_results,
+            candidates,
+            api_fns,
+            uses: Vec::new(),
+            handled: HashSet::new(),
+            mut_derefs: HashSet::new(),
+            guards: Vec::new(),
+        }
+    }
+
+    /// If `ty` is a raw pointer to one of the `candidates`, return the struct's `DefId`.
+    fn rc_adt(&self, ty: Ty<'tcx>) -> Option<DefId> {
+        rc_adt(self.candidates, ty)
+    }
+
+    /// If `ex` is a pointer to one of the `candidates`, return the struct's `DefId`.
+    fn rc_expr(&self, ex: &Expr<'tcx>) -> Option<DefId> {
+        self.rc_adt(self.typeck_results.expr_ty(ex))
+    }
+
+    /// If `ex` is a path to a local variable, return the variable's `HirId`.
+    fn local_var(&self, ex: &Expr<'tcx>) -> Option<HirId> {
+        local_var(ex)
+    }
+
+    /// If `ex` is a call, return the callee according to `util::ty_callee`.
+    fn callee(&self, ex: &Expr<'tcx>) -> Option<Callee<'tcx>> {
+        match ex.kind {
+            ExprKind::Call(func, _) => {
+                Some(util::ty_callee(self.tcx, self.typeck_results.expr_ty(func)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Record `ex` as a copy of a pointer, if it's a pointer held in a local variable.
+    fn visit_copy(&mut self, ex: &Expr<'tcx>) {
+        if let Some(adt) = self.rc_expr(ex) {
+            if self.local_var(ex).is_some() {
+                self.handled.insert(ex.hir_id);
+                self.uses.push((adt, RcPtrUse::Copy { expr: ex.span }));
+            }
+        }
+    }
+
+    /// Find the `*p` expression at the root of a place like `(*p).x[i]`, along with the struct
+    /// that `p` points to.
+    fn place_root_deref(&self, mut ex: &'tcx Expr<'tcx>) -> Option<(&'tcx Expr<'tcx>, DefId)> {
+        loop {
+            match ex.kind {
+                ExprKind::Field(base, _) | ExprKind::Index(base, _) => ex = base,
+                ExprKind::Unary(UnOp::Deref, ptr) => {
+                    return self.rc_expr(ptr).map(|adt| (ex, adt));
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// Any pointer that wasn't handled by an enclosing expression is used in an unsupported way.
+    fn check_handled(&mut self, ex: &Expr<'tcx>) {
+        if self.handled.contains(&ex.hir_id) {
+            return;
+        }
+        if let Some(adt) = self.rc_expr(ex) {
+            debug!("unsupported use of rc pointer at {:?}", ex.span);
+            self.uses.push((adt, RcPtrUse::Other));
+        }
+    }
+
+    /// Check whether `cond` has the form `(*p).f == 0`, where `f` is a field of one of the
+    /// `candidates`.  Returns the local `p` and the field `f`.
+    fn refcount_check(&self, cond: &'tcx Expr<'tcx>) -> Option<(HirId, DefId)> {
+        let cond = peel_drop_temps(cond);
+        let (lhs, rhs) = match cond.kind {
+            ExprKind::Binary(op, lhs, rhs) if op.node == BinOpKind::Eq => (lhs, rhs),
+            _ => return None,
+        };
+        let field_ex = if is_int(rhs, 0) {
+            lhs
+        } else if is_int(lhs, 0) {
+            rhs
+        } else {
+            return None;
+        };
+        local_ptr_field(self.tcx, self.typeck_results, self.candidates, field_ex)
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for RcPtrUseVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        // Places that are mutably borrowed by an adjustment, as in `(*p).x.push(1)`, need
+        // `borrow_mut()`.
+        let mut_adjust = self.typeck_results.expr_adjustments(ex).iter().any(|adj| {
+            matches!(
+                adj.kind,
+                Adjust::Borrow(AutoBorrow::Ref(_, AutoBorrowMutability::Mut { .. }))
+            )
+        });
+        if mut_adjust {
+            if let Some((d, _)) = self.place_root_deref(ex) {
+                self.mut_derefs.insert(d.hir_id);
+            }
+        }
+
+        match ex.kind {
+            ExprKind::Assign(lhs, rhs, _) => {
+                if let Some((d, _)) = self.place_root_deref(lhs) {
+                    self.mut_derefs.insert(d.hir_id);
+                }
+                // `q = p`
+                if self.rc_expr(lhs).is_some() && self.local_var(lhs).is_some() {
+                    self.handled.insert(lhs.hir_id);
+                    self.visit_copy(rhs);
+                }
+            }
+
+            ExprKind::AssignOp(_, lhs, _) => {
+                if let Some((d, _)) = self.place_root_deref(lhs) {
+                    self.mut_derefs.insert(d.hir_id);
+                }
+            }
+
+            // `&(*p).x` would borrow from a temporary `Ref`, which usually doesn't live long
+            // enough.
+            ExprKind::AddrOf(_, _, pl) => {
+                if let Some((_, adt)) = self.place_root_deref(pl) {
+                    self.uses.push((adt, RcPtrUse::Other));
+                }
+            }
+
+            // `*p`
+            ExprKind::Unary(UnOp::Deref, ptr) => {
+                if let Some(adt) = self.rc_expr(ptr) {
+                    self.handled.insert(ptr.hir_id);
+                    self.uses.push((
+                        adt,
+                        RcPtrUse::Deref {
+                            expr: ex.span,
+                            ptr: ptr.span,
+                            mutbl: self.mut_derefs.contains(&ex.hir_id),
+                        },
+                    ));
+                }
+            }
+
+            // `malloc(n) as *mut S`
+            ExprKind::Cast(inner, _) => {
+                if let Some(adt) = self.rc_expr(ex) {
+                    if matches!(self.callee(inner), Some(Callee::Malloc | Callee::Calloc)) {
+                        let pointee_ty = match *self.typeck_results.expr_ty(ex).kind() {
+                            TyKind::RawPtr(tm) => tm.ty,
+                            _ => unreachable!(),
+                        };
+                        self.uses.push((
+                            adt,
+                            RcPtrUse::Alloc {
+                                expr: ex.span,
+                                pointee_ty,
+                            },
+                        ));
+                        // The whole expression is replaced, including the `*mut S` type.
+                        return;
+                    }
+                }
+            }
+
+            ExprKind::Call(_, args) => match self.callee(ex) {
+                // `free(p as *mut c_void)`
+                Some(Callee::Free) => {
+                    if let [arg] = args {
+                        if let ExprKind::Cast(ptr, _) = arg.kind {
+                            if let Some(adt) = self.rc_expr(ptr) {
+                                let guard = self.local_var(ptr).and_then(|local| {
+                                    self.guards
+                                        .iter()
+                                        .rev()
+                                        .find(|&&(l, _)| l == local)
+                                        .map(|&(_, field)| field)
+                                });
+                                self.uses.push((
+                                    adt,
+                                    RcPtrUse::Free {
+                                        call: ex.span,
+                                        ptr: ptr.span,
+                                        guard,
+                                    },
+                                ));
+                                // The whole call is replaced, including the cast.
+                                return;
+                            }
+                        }
+                    }
+                }
+                // `obj_unref(p)`, where `obj_unref` is one of the `api_fns`
+                Some(Callee::LocalDef { def_id, .. }) if self.api_fns.contains_key(&def_id) => {
+                    if let [arg] = args {
+                        if let Some(adt) = self.rc_expr(arg) {
+                            let local = self.local_var(arg).is_some();
+                            if local {
+                                self.handled.insert(arg.hir_id);
+                            }
+                            self.handled.insert(ex.hir_id);
+                            self.uses.push((
+                                adt,
+                                RcPtrUse::ApiCall {
+                                    call: ex.span,
+                                    ptr: arg.span,
+                                    local,
+                                    kind: self.api_fns[&def_id],
+                                },
+                            ));
+                            self.visit_expr(arg);
+                            return;
+                        }
+                    }
+                }
+                // `f(p)`, where `f` is a local function.  The callee is rewritten to match, so
+                // the pointer it returns is also supported.
+                Some(Callee::LocalDef { .. }) => {
+                    self.handled.insert(ex.hir_id);
+                    for arg in args {
+                        self.visit_copy(arg);
+                    }
+                }
+                _ => {}
+            },
+
+            // `return p`
+            ExprKind::Ret(Some(val)) => self.visit_copy(val),
+
+            // `{ ...; p }`
+            ExprKind::Block(block, _) => {
+                self.handled.insert(ex.hir_id);
+                if let Some(val) = block.expr {
+                    self.visit_copy(val);
+                }
+            }
+
+            // `if (*p).refcount == 0 { ... }`
+            ExprKind::If(cond, then, els) => {
+                if let Some(guard) = self.refcount_check(cond) {
+                    self.check_handled(ex);
+                    self.visit_expr(cond);
+                    self.guards.push(guard);
+                    self.visit_expr(then);
+                    self.guards.pop();
+                    if let Some(els) = els {
+                        self.visit_expr(els);
+                    }
+                    return;
+                }
+            }
+
+            _ => {}
+        }
+
+        self.check_handled(ex);
+        intravisit::walk_expr(self, ex);
+    }
+
+    fn visit_local(&mut self, local: &'tcx Local<'tcx>) {
+        // `let q = p`
+        if let Some(init) = local.init {
+            self.visit_copy(init);
+        }
+        intravisit::walk_local(self, local);
+    }
+
+    fn visit_ty(&mut self, ty: &'tcx hir::Ty<'tcx>) {
+        // `*mut S`
+        if let hir::TyKind::Ptr(ref mt) = ty.kind {
+            if let hir::TyKind::Path(hir::QPath::Resolved(None, path)) = mt.ty.kind {
+                if let Res::Def(DefKind::Struct, did) = path.res {
+                    if self.candidates.contains(&did) {
+                        self.uses.push((
+                            did,
+                            RcPtrUse::Ty {
+                                ty: ty.span,
+                                pointee: mt.ty.span,
+                            },
+                        ));
+                        return;
+                    }
+                }
+            }
+        }
+        intravisit::walk_ty(self, ty);
+    }
+}
+
+fn peel_drop_temps<'tcx>(mut ex: &'tcx Expr<'tcx>) -> &'tcx Expr<'tcx> {
+    while let ExprKind::DropTemps(inner) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+/// Check whether `ex` is the integer literal `n`, possibly with casts, as in `0 as libc::c_int`.
+fn is_int(ex: &Expr, n: u128) -> bool {
+    match peel_casts(ex).kind {
+        ExprKind::Lit(ref lit) => matches!(lit.node, LitKind::Int(x, _) if x == n),
+        _ => false,
+    }
+}
+
+fn peel_casts<'a, 'tcx>(mut ex: &'a Expr<'tcx>) -> &'a Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+/// If `ex` is a path to a local variable, return the variable's `HirId`.
+fn local_var(ex: &Expr) -> Option<HirId> {
+    match ex.kind {
+        ExprKind::Path(hir::QPath::Resolved(_, path)) => match path.res {
+            Res::Local(hir_id) => Some(hir_id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Check whether `ex` has the form `(*p).f`, where `p` is a local pointer to one of the
+/// `candidates`.  Returns the local `p` and the field `f`.
+fn local_ptr_field<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &TypeckResults<'tcx>,
+    candidates: &HashSet<DefId>,
+    ex: &Expr<'tcx>,
+) -> Option<(HirId, DefId)> {
+    let base = match ex.kind {
+        ExprKind::Field(base, _) => base,
+        _ => return None,
+    };
+    let ptr = match base.kind {
+        ExprKind::Unary(UnOp::Deref, ptr) => ptr,
+        _ => return None,
+    };
+    let adt = rc_adt(candidates, typeck_results.expr_ty(ptr))?;
+    let local = local_var(ptr)?;
+    let idx = *typeck_results.field_indices().get(ex.hir_id)?;
+    let field = tcx.adt_def(adt).non_enum_variant().fields.get(idx)?.did;
+    Some((local, field))
+}
+
+fn rc_adt(candidates: &HashSet<DefId>, ty: Ty) -> Option<DefId> {
+    match *ty.kind() {
+        TyKind::RawPtr(tm) => match *tm.ty.kind() {
+            TyKind::Adt(adt_def, _) if candidates.contains(&adt_def.did()) => Some(adt_def.did()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Check whether `ty` contains a raw pointer to one of the `candidates`, and if so, return the
+/// struct's `DefId`.
+fn mentions_rc_adt(candidates: &HashSet<DefId>, ty: Ty) -> Option<DefId> {
+    ty.walk().find_map(|arg| match arg.unpack() {
+        GenericArgKind::Type(ty) => rc_adt(candidates, ty),
+        _ => None,
+    })
+}
+
+/// Run `RcPtrUseVisitor` over the signature and body of `ldid`.
+pub fn collect_rc_ptr_uses<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ldid: LocalDefId,
+    candidates: &HashSet<DefId>,
+    api_fns: &HashMap<DefId, RcApiFn>,
+) -> Vec<(DefId, RcPtrUse<'tcx>)> {
+    let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+    let mut v = RcPtrUseVisitor::new(tcx, tcx.typeck_body(hir_body_id), candidates, api_fns);
+    let hir_id = tcx.hir().local_def_id_to_hir_id(ldid);
+    if let Some(decl) = tcx.hir().fn_decl_by_hir_id(hir_id) {
+        for ty in decl.inputs {
+            v.visit_ty(ty);
+        }
+        if let hir::FnRetTy::Return(ty) = decl.output {
+            v.visit_ty(ty);
+        }
+    }
+    v.visit_body(tcx.hir().body(hir_body_id));
+    v.uses
+}
+
+/// Find the set of local functions that mention pointers to any of the `candidates`.
+fn find_rc_fns(
+    tcx: TyCtxt,
+    all_fn_ldids: &[LocalDefId],
+    candidates: &HashSet<DefId>,
+) -> HashMap<LocalDefId, HashSet<DefId>> {
+    let mut fns = HashMap::new();
+    for &ldid in all_fn_ldids {
+        let mir = tcx.mir_built(WithOptConstParam::unknown(ldid));
+        let mir = mir.borrow();
+        let adts = mir
+            .local_decls
+            .iter()
+            .filter_map(|decl| mentions_rc_adt(candidates, decl.ty))
+            .collect::<HashSet<_>>();
+        if !adts.is_empty() {
+            fns.insert(ldid, adts);
+        }
+    }
+    fns
+}
+
+/// Find reference-counted structs whose pointers can be rewritten to `Rc<RefCell<T>>`.
+pub fn find_refcounted_structs<'tcx>(
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    all_fn_ldids: &[LocalDefId],
+) -> RefcountInfo {
+    let tcx = gacx.tcx;
+
+    // Candidates are local structs that can be zero-initialized and don't cross an FFI boundary.
+    let mut candidates = HashSet::new();
+    for &adt_did in &gacx.adt_metadata.struct_dids {
+        if !adt_did.is_local() || gacx.foreign_mentioned_tys.contains(&adt_did) {
+            continue;
+        }
+        if !matches!(tcx.def_kind(adt_did), DefKind::Struct) {
+            continue;
+        }
+        if rewrite::gen_zeroize_expr(tcx, tcx.type_of(adt_did)).is_none() {
+            continue;
+        }
+        candidates.insert(adt_did);
+    }
+
+    // Pointers stored in struct fields or statics can't be rewritten, since `Rc` isn't `Copy`.
+    let mut rejected = HashSet::new();
+    for &adt_did in &gacx.adt_metadata.struct_dids {
+        for field in tcx.adt_def(adt_did).all_fields() {
+            if let Some(did) = mentions_rc_adt(&candidates, tcx.type_of(field.did)) {
+                rejected.insert(did);
+            }
+        }
+    }
+    for &did in gacx.static_tys.keys() {
+        if let Some(did) = mentions_rc_adt(&candidates, tcx.type_of(did)) {
+            rejected.insert(did);
+        }
+    }
+    candidates.retain(|did| !rejected.contains(did));
+    if candidates.is_empty() {
+        return RefcountInfo::default();
+    }
+
+    #[derive(Default)]
+    struct StructInfo {
+        count_field: Option<DefId>,
+        ok: bool,
+    }
+
+    let rc_fns = find_rc_fns(tcx, all_fn_ldids, &candidates);
+    let mut info = HashMap::<DefId, StructInfo>::new();
+    for &ldid in rc_fns.keys() {
+        for (did, u) in collect_rc_ptr_uses(tcx, ldid, &candidates, &HashMap::new()) {
+            let si = info.entry(did).or_insert_with(|| StructInfo {
+                ok: true,
+                ..StructInfo::default()
+            });
+            match u {
+                RcPtrUse::Free { guard, .. } => match (guard, si.count_field) {
+                    (None, _) => {
+                        debug!("rc struct {did:?} is freed without a refcount check");
+                        si.ok = false;
+                    }
+                    (Some(field), None) => si.count_field = Some(field),
+                    (Some(field), Some(old)) if field == old => {}
+                    (Some(field), Some(old)) => {
+                        debug!("rc struct {did:?} uses both {old:?} and {field:?} as refcount");
+                        si.ok = false;
+                    }
+                },
+                RcPtrUse::Other => si.ok = false,
+                RcPtrUse::Alloc { .. }
+                | RcPtrUse::Deref { .. }
+                | RcPtrUse::Copy { .. }
+                | RcPtrUse::Ty { .. }
+                | RcPtrUse::ApiCall { .. } => {}
+            }
+        }
+    }
+
+    let mut refcount = RefcountInfo::default();
+    for (did, si) in info {
+        // A struct that is never freed isn't reference counted.
+        if let Some(count_field) = si.count_field.filter(|_| si.ok) {
+            debug!("found rc struct {did:?}, with refcount field {count_field:?}");
+            refcount.structs.insert(did, count_field);
+        }
+    }
+    for (ldid, adts) in rc_fns {
+        if adts.iter().any(|did| refcount.structs.contains_key(did)) {
+            refcount.fns.insert(ldid.to_def_id());
+        }
+    }
+    find_refcount_api(tcx, &mut refcount);
+    refcount
+}
+
+/// Collect the statements of `block` and its trailing expression, if it contains no `let`s or
+/// items.
+fn block_exprs<'tcx>(block: &'tcx Block<'tcx>) -> Option<Vec<&'tcx Expr<'tcx>>> {
+    let mut exprs = block
+        .stmts
+        .iter()
+        .map(|stmt| match stmt.kind {
+            StmtKind::Semi(ex) | StmtKind::Expr(ex) => Some(ex),
+            StmtKind::Local(_) | StmtKind::Item(_) => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    exprs.extend(block.expr);
+    Some(exprs)
+}
+
+/// Check whether `ldid` is a reference counting function for one of the `structs`, which maps
+/// each struct to its count field.  Returns the struct and the kind of function.
+fn refcount_api_fn<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ldid: LocalDefId,
+    structs: &HashMap<DefId, DefId>,
+) -> Option<(DefId, RcApiFn)> {
+    let hir_body_id = tcx.hir().maybe_body_owned_by(ldid)?;
+    let typeck_results = tcx.typeck_body(hir_body_id);
+    let body = tcx.hir().body(hir_body_id);
+    let candidates = structs.keys().copied().collect::<HashSet<_>>();
+
+    let param = match body.params {
+        [param] => param,
+        _ => return None,
+    };
+    let p = match param.pat.kind {
+        hir::PatKind::Binding(_, hir_id, _, None) => hir_id,
+        _ => return None,
+    };
+    let adt = rc_adt(&candidates, typeck_results.pat_ty(param.pat))?;
+    let count_field = structs[&adt];
+
+    let is_p = |ex: &Expr<'tcx>| local_var(peel_casts(ex)) == Some(p);
+    let is_count = |ex: &Expr<'tcx>| {
+        local_ptr_field(tcx, typeck_results, &candidates, ex) == Some((p, count_field))
+    };
+    // `(*p).refcount += 1` or `(*p).refcount -= 1`
+    let is_update = |ex: &Expr<'tcx>, op: BinOpKind| match ex.kind {
+        ExprKind::AssignOp(o, lhs, rhs) => o.node == op && is_count(lhs) && is_int(rhs, 1),
+        _ => false,
+    };
+
+    let exprs = match body.value.kind {
+        ExprKind::Block(block, None) => block_exprs(block)?,
+        _ => return None,
+    };
+    let kind = match *exprs {
+        [inc] if is_update(inc, BinOpKind::Add) => RcApiFn::Ref { returns_ptr: false },
+        [inc, ret] if is_update(inc, BinOpKind::Add) => {
+            let val = match ret.kind {
+                ExprKind::Ret(Some(val)) => val,
+                _ => ret,
+            };
+            if !is_p(val) {
+                return None;
+            }
+            RcApiFn::Ref { returns_ptr: true }
+        }
+        [dec, check] if is_update(dec, BinOpKind::Sub) => {
+            // `if (*p).refcount == 0 { free(p as *mut c_void); }`
+            let (cond, then) = match check.kind {
+                ExprKind::If(cond, then, None) => (peel_drop_temps(cond), then),
+                _ => return None,
+            };
+            match cond.kind {
+                ExprKind::Binary(op, lhs, rhs) if op.node == BinOpKind::Eq => {
+                    if !(is_count(lhs) && is_int(rhs, 0) || is_int(lhs, 0) && is_count(rhs)) {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+            let free = match then.kind {
+                ExprKind::Block(block, None) => match *block_exprs(block)? {
+                    [free] => free,
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            match free.kind {
+                ExprKind::Call(func, [arg]) => {
+                    let callee = util::ty_callee(tcx, typeck_results.expr_ty(func));
+                    if !matches!(callee, Callee::Free) || !is_p(arg) {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+            RcApiFn::Unref
+        }
+        _ => return None,
+    };
+    Some((adt, kind))
+}
+
+/// Visitor that finds uses of reference counting functions and count fields that prevent removing
+/// them.
+struct RefcountApiUseVisitor<'a, 'tcx> {
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    api_fns: &'a HashMap<DefId, (DefId, RcApiFn)>,
+    count_fields: &'a HashSet<DefId>,
+    /// Functions in `api_fns` that are used other than by calling them directly.
+    escaped_fns: HashSet<DefId>,
+    /// Count fields that are used other than by the `count_inits`.
+    used_fields: HashSet<DefId>,
+    /// Assignments like `(*p).refcount = 1`, along with the field they assign.
+    count_inits: Vec<(DefId, Span)>,
+}
+
+impl<'a, 'tcx> RefcountApiUseVisitor<'a, 'tcx> {
+    /// If `ex` is an access to one of the `count_fields`, return the field's `DefId`.
+    fn count_field(&self, ex: &Expr<'tcx>) -> Option<DefId> {
+        let base = match ex.kind {
+            ExprKind::Field(base, _) => base,
+            _ => return None,
+        };
+        let adt_def = self.typeck_results.expr_ty_adjusted(base).ty_adt_def()?;
+        let idx = *self.typeck_results.field_indices().get(ex.hir_id)?;
+        let did = adt_def.non_enum_variant().fields.get(idx)?.did;
+        Some(did).filter(|did| self.count_fields.contains(did))
+    }
+
+    /// If `ex` is a path to one of the `api_fns`, return the function's `DefId`.
+    fn api_fn(&self, ex: &Expr<'tcx>) -> Option<DefId> {
+        match ex.kind {
+            ExprKind::Path(ref qp) => match self.typeck_results.qpath_res(qp, ex.hir_id) {
+                Res::Def(DefKind::Fn, did) if self.api_fns.contains_key(&did) => Some(did),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for RefcountApiUseVisitor<'a, 'tcx> {
+    fn visit_stmt(&mut self, stmt: &'tcx hir::Stmt<'tcx>) {
+        // `(*p).refcount = 1;`
+        if let StmtKind::Semi(ex) = stmt.kind {
+            if let ExprKind::Assign(lhs, rhs, _) = ex.kind {
+                let rhs_is_lit = matches!(peel_casts(rhs).kind, ExprKind::Lit(_));
+                let lhs_is_simple = match lhs.kind {
+                    ExprKind::Field(base, _) => match base.kind {
+                        ExprKind::Unary(UnOp::Deref, ptr) => local_var(ptr).is_some(),
+                        _ => local_var(base).is_some(),
+                    },
+                    _ => false,
+                };
+                if rhs_is_lit && lhs_is_simple {
+                    if let Some(field) = self.count_field(lhs) {
+                        self.count_inits.push((field, stmt.span));
+                        return;
+                    }
+                }
+            }
+        }
+        intravisit::walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            // `obj_ref(p)`
+            ExprKind::Call(func, args) if self.api_fn(func).is_some() => {
+                for arg in args {
+                    self.visit_expr(arg);
+                }
+                return;
+            }
+            ExprKind::Path(_) => {
+                if let Some(did) = self.api_fn(ex) {
+                    debug!("refcount function {did:?} is used at {:?}", ex.span);
+                    self.escaped_fns.insert(did);
+                }
+            }
+            ExprKind::Field(..) => {
+                if let Some(did) = self.count_field(ex) {
+                    self.used_fields.insert(did);
+                }
+            }
+            ExprKind::Struct(_, fields, _) => {
+                if let Some(adt_def) = self.typeck_results.expr_ty(ex).ty_adt_def() {
+                    for field in fields {
+                        let idx = match self.typeck_results.field_indices().get(field.hir_id) {
+                            Some(&x) => x,
+                            None => continue,
+                        };
+                        let did = adt_def.non_enum_variant().fields[idx].did;
+                        if self.count_fields.contains(&did) {
+                            self.used_fields.insert(did);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Find the reference counting functions among `refcount.fns`, and the count fields that are no
+/// longer needed once those functions are removed.
+fn find_refcount_api(tcx: TyCtxt, refcount: &mut RefcountInfo) {
+    let mut api_fns = HashMap::new();
+    for &did in &refcount.fns {
+        if let Some(x) = refcount_api_fn(tcx, did.expect_local(), &refcount.structs) {
+            debug!("found refcount function {did:?}: {x:?}");
+            api_fns.insert(did, x);
+        }
+    }
+
+    let count_fields = refcount.structs.values().copied().collect::<HashSet<_>>();
+    let mut escaped_fns = HashSet::new();
+    let mut used_fields = HashSet::new();
+    let mut count_inits = Vec::new();
+    for ldid in tcx.hir().body_owners() {
+        if api_fns.contains_key(&ldid.to_def_id()) {
+            continue;
+        }
+        // Derived impls are regenerated to match when a field is removed.
+        if tcx.def_span(ldid).from_expansion() {
+            continue;
+        }
+        let hir_body_id = tcx.hir().body_owned_by(ldid);
+        let mut v = RefcountApiUseVisitor {
+            typeck_results: tcx.typeck_body(hir_body_id),
+            api_fns: &api_fns,
+            count_fields: &count_fields,
+            escaped_fns,
+            used_fields,
+            count_inits,
+        };
+        v.visit_body(tcx.hir().body(hir_body_id));
+        escaped_fns = v.escaped_fns;
+        used_fields = v.used_fields;
+        count_inits = v.count_inits;
+    }
+
+    // A function that is used other than by calling it is kept, and it still updates the count.
+    for (did, &(adt, _)) in &api_fns {
+        if escaped_fns.contains(did) {
+            used_fields.insert(refcount.structs[&adt]);
+        }
+    }
+    // The functions can only be removed if nothing else reads or writes the count, since removing
+    // some of the updates would make it wrong.
+    for (did, (adt, kind)) in api_fns {
+        if !used_fields.contains(&refcount.structs[&adt]) {
+            refcount.api_fns.insert(did, kind);
+        }
+    }
+    for (&adt, field) in &refcount.structs {
+        if !used_fields.contains(field) {
+            debug!("removing refcount field {field:?} of {adt:?}");
+            refcount.removed_count_fields.insert(adt);
+        }
+    }
+    refcount.count_inits = count_inits
+        .into_iter()
+        .filter(|(field, _)| !used_fields.contains(field))
+        .map(|(_, span)| span)
+        .collect();
+}
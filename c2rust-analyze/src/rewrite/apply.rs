@@ -336,6 +336,15 @@ impl<S: Sink> Emitter<'_, S> {
             }),
             Rewrite::RemovedCast(ref rw) => self.emit(rw, prec),
             Rewrite::LitZero => self.emit_str("0"),
+            // Binary operators all bind more loosely than `as`.  Rather than track the relative
+            // precedence of each operator, parenthesize any binary operator used as an operand.
+            Rewrite::Binary(ref op, ref a, ref b) => self.emit_parenthesized(prec > 0, |slf| {
+                slf.emit(a, 1)?;
+                slf.emit_str(" ")?;
+                slf.emit_str(op)?;
+                slf.emit_str(" ")?;
+                slf.emit(b, 1)
+            }),
 
             Rewrite::Print(ref s) => self.emit_str(s),
             Rewrite::_TyGenericParams(ref rws) => {
@@ -375,6 +384,11 @@ impl<S: Sink> Emitter<'_, S> {
                 })
             }
 
+            Rewrite::Try(ref rw) => {
+                self.emit(rw, 3)?;
+                self.emit_str("?")
+            }
+
             Rewrite::Block(ref stmts, ref expr) => {
                 self.emit_str("{\n")?;
                 for stmt in stmts {
@@ -390,6 +404,14 @@ impl<S: Sink> Emitter<'_, S> {
                 self.emit_str("}")
             }
 
+            Rewrite::Unsafe(ref safety, ref rw) => {
+                self.emit_str("unsafe {\n    // SAFETY: ")?;
+                self.emit_str(safety)?;
+                self.emit_str("\n    ")?;
+                self.emit(rw, 0)?;
+                self.emit_str("\n}")
+            }
+
             Rewrite::Let(ref vars) => {
                 self.emit_str("let (")?;
                 for (ref name, _) in vars {
@@ -417,6 +439,20 @@ impl<S: Sink> Emitter<'_, S> {
                 self.emit_str("| ")?;
                 self.emit(rw, 0)
             }
+            Rewrite::ForLoop(ref pat, ref iter, body) => {
+                self.emit_str("for ")?;
+                self.emit_str(pat)?;
+                self.emit_str(" in ")?;
+                self.emit(iter, 0)?;
+                self.emit_str(" ")?;
+                // The body is a block, so it never needs parentheses.
+                self.emit_sub(0, body)
+            }
+            Rewrite::ClosureCall(body) => {
+                self.emit_str("(|| ")?;
+                self.emit_sub(0, body)?;
+                self.emit_str(")()")
+            }
 
             Rewrite::TyPtr(ref rw, mutbl) => {
                 match mutbl {
@@ -465,12 +501,13 @@ impl<S: Sink> Emitter<'_, S> {
             }
 
             Rewrite::DefineFn {
+                ref header,
                 ref name,
                 ref arg_tys,
                 ref return_ty,
                 ref body,
             } => {
-                self.emit_fmt(format_args!("\nunsafe fn {name}("))?;
+                self.emit_fmt(format_args!("\n{header} {name}("))?;
                 for (i, arg_ty) in arg_tys.iter().enumerate() {
                     if i > 0 {
                         self.emit_str(", ")?;
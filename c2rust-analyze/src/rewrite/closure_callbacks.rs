@@ -0,0 +1,139 @@
+//! Rewrites for registration fns whose callback and user-data parameters are replaced by a
+//! closure.  See `crate::closure_callbacks` for the analysis that finds them.
+
+use crate::closure_callbacks::{self, ClosureCallbackUse};
+use crate::context::GlobalAnalysisCtxt;
+use crate::rewrite::Rewrite;
+use rustc_hir::def::Namespace;
+use rustc_hir::PatKind;
+use rustc_middle::ty::print::{FmtPrinter, Print};
+use rustc_middle::ty::{Ty, TyCtxt, TyKind};
+use rustc_span::Span;
+use std::collections::HashSet;
+
+fn print_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> String {
+    let printer = FmtPrinter::new(tcx, Namespace::TypeNS);
+    ty.print(printer).unwrap().into_buffer()
+}
+
+/// The span to delete to remove the element at `index` from a comma-separated list, including
+/// one of the commas next to it.
+fn list_elem_removal_span(spans: &[Span], index: usize) -> Span {
+    let span = spans[index];
+    if index > 0 {
+        spans[index - 1].between(span).to(span)
+    } else if spans.len() > 1 {
+        span.until(spans[1])
+    } else {
+        span
+    }
+}
+
+pub fn gen_closure_callback_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let info = &gacx.closure_callbacks;
+    let mut rewrites = Vec::new();
+    if info.fns.is_empty() {
+        return rewrites;
+    }
+
+    for (&did, reg) in &info.fns {
+        let ldid = did.expect_local();
+        let decl = tcx
+            .hir()
+            .fn_decl_by_hir_id(tcx.hir().local_def_id_to_hir_id(ldid))
+            .unwrap_or_else(|| panic!("{did:?} should be a function"));
+        let body = tcx.hir().body(tcx.hir().body_owned_by(ldid));
+
+        // `cb: unsafe extern "C" fn(*mut c_void, i32) -> i32` -> `mut cb: impl FnMut(i32) -> i32`
+        let cb_sig = match *tcx.fn_sig(did).skip_binder().inputs()[reg.cb_arg].kind() {
+            TyKind::FnPtr(sig) => sig.skip_binder(),
+            _ => unreachable!("callback parameter should be a function pointer"),
+        };
+        let inputs = cb_sig
+            .inputs()
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != reg.cb_data_arg)
+            .map(|(_, &ty)| print_ty(tcx, ty))
+            .collect::<Vec<_>>();
+        let mut fn_mut_ty = format!("impl FnMut({})", inputs.join(", "));
+        if !cb_sig.output().is_unit() {
+            fn_mut_ty.push_str(&format!(" -> {}", print_ty(tcx, cb_sig.output())));
+        }
+        rewrites.push((decl.inputs[reg.cb_arg].span, Rewrite::Print(fn_mut_ty)));
+        let cb_pat = body.params[reg.cb_arg].pat;
+        let name = match cb_pat.kind {
+            PatKind::Binding(_, _, ident, None) => ident,
+            _ => unreachable!("callback parameter should be a simple binding"),
+        };
+        rewrites.push((cb_pat.span, Rewrite::Text(format!("mut {name}"))));
+
+        // `(cb: ..., data: *mut c_void)` -> `(cb: ...)`
+        let param_spans = body.params.iter().map(|p| p.span).collect::<Vec<_>>();
+        rewrites.push((
+            list_elem_removal_span(&param_spans, reg.data_arg),
+            Rewrite::Text(String::new()),
+        ));
+    }
+
+    let users = info
+        .fns
+        .keys()
+        .chain(&info.callers)
+        .copied()
+        .collect::<HashSet<_>>();
+    for did in users {
+        let ldid = match did.as_local() {
+            Some(x) => x,
+            None => continue,
+        };
+        for u in closure_callbacks::collect_closure_callback_uses(tcx, ldid, info) {
+            match u {
+                // `cb(data, i)` -> `cb(i)`
+                ClosureCallbackUse::CallCallback { args } => {
+                    let reg = &info.fns[&did];
+                    rewrites.push((
+                        list_elem_removal_span(&args, reg.cb_data_arg),
+                        Rewrite::Text(String::new()),
+                    ));
+                }
+                // `for_each(add_to, &mut total as *mut _)` ->
+                // `for_each(|arg0| add_to(&mut total as *mut _, arg0))`
+                ClosureCallbackUse::Call {
+                    callee,
+                    args,
+                    cb_fn,
+                } => {
+                    let reg = &info.fns[&callee];
+                    let cb_ty = tcx.fn_sig(callee).skip_binder().inputs()[reg.cb_arg];
+                    let num_cb_args = match *cb_ty.kind() {
+                        TyKind::FnPtr(sig) => sig.skip_binder().inputs().len(),
+                        _ => unreachable!("callback parameter should be a function pointer"),
+                    };
+                    let params = (0..num_cb_args - 1)
+                        .map(|i| format!("arg{i}"))
+                        .collect::<Vec<_>>();
+                    let mut cb_args = params
+                        .iter()
+                        .map(|p| Rewrite::Text(p.clone()))
+                        .collect::<Vec<_>>();
+                    cb_args.insert(reg.cb_data_arg, Rewrite::Extract(args[reg.data_arg]));
+                    let cb_call = Rewrite::Call(
+                        tcx.sess.source_map().span_to_snippet(cb_fn).unwrap(),
+                        cb_args,
+                    );
+                    rewrites.push((
+                        args[reg.cb_arg],
+                        Rewrite::Closure1(params.join(", "), Box::new(cb_call)),
+                    ));
+                    rewrites.push((
+                        list_elem_removal_span(&args, reg.data_arg),
+                        Rewrite::Text(String::new()),
+                    ));
+                }
+            }
+        }
+    }
+    rewrites
+}
@@ -0,0 +1,93 @@
+//! Rewrites for functions that return errno-style integer codes, which become functions returning
+//! `Result<(), std::io::Error>`.  See `crate::errno_result` for the analysis that finds them.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::errno_result::{self, ErrnoUse};
+use crate::rewrite::Rewrite;
+use rustc_hir::def::Namespace;
+use rustc_hir::FnRetTy;
+use rustc_middle::ty::print::{FmtPrinter, Print};
+use rustc_span::Span;
+
+const RESULT_TY: &str = "Result<(), std::io::Error>";
+
+pub fn gen_errno_result_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let info = &gacx.errno_results;
+    let mut rewrites = Vec::new();
+    if info.fns.is_empty() {
+        return rewrites;
+    }
+
+    // `-> libc::c_int` -> `-> Result<(), std::io::Error>`
+    for &did in &info.fns {
+        let decl = tcx
+            .hir()
+            .fn_decl_by_hir_id(tcx.hir().local_def_id_to_hir_id(did.expect_local()))
+            .unwrap_or_else(|| panic!("{did:?} should be a function"));
+        if let FnRetTy::Return(ty) = decl.output {
+            rewrites.push((ty.span, Rewrite::Print(RESULT_TY.into())));
+        }
+    }
+
+    for &did in &info.users {
+        let ldid = match did.as_local() {
+            Some(x) => x,
+            None => continue,
+        };
+        for u in errno_result::collect_errno_uses(tcx, ldid, &info.fns) {
+            match u {
+                // `0` -> `Ok(())`
+                ErrnoUse::ReturnOk { expr } => {
+                    rewrites.push((expr, Rewrite::Text("Ok(())".into())));
+                }
+                // `-(22 as c_int)` -> `Err(std::io::Error::from_raw_os_error((22 as c_int) as i32))`
+                ErrnoUse::ReturnErr { expr, code } => {
+                    let code = Rewrite::Cast(
+                        Box::new(Rewrite::Sub(0, code)),
+                        Box::new(Rewrite::Print("i32".into())),
+                    );
+                    let err = Rewrite::Call("std::io::Error::from_raw_os_error".into(), vec![code]);
+                    rewrites.push((expr, Rewrite::Call("Err".into(), vec![err])));
+                }
+                // `f(x) < 0` -> `f(x).is_err()`
+                ErrnoUse::Check { expr, call, is_err } => {
+                    let method = if is_err { "is_err" } else { "is_ok" };
+                    rewrites.push((
+                        expr,
+                        Rewrite::MethodCall(method.into(), Box::new(Rewrite::Sub(0, call)), vec![]),
+                    ));
+                }
+                // `f(x);` -> `let _ = f(x);`
+                ErrnoUse::Ignore { call } => {
+                    rewrites.push((call, Rewrite::Let1("_".into(), Box::new(Rewrite::Identity))));
+                }
+                // `let r = f(x); if r < 0 { return r; }` -> `let () = f(x)?; {}`
+                ErrnoUse::Propagate { pat, call, check } => {
+                    rewrites.push((pat, Rewrite::Text("()".into())));
+                    rewrites.push((call, Rewrite::Try(Box::new(Rewrite::Identity))));
+                    rewrites.push((check, Rewrite::Text("{}".into())));
+                }
+                // `f(x)` -> `f(x).map_or_else(|e| -e.raw_os_error().unwrap_or(1) as i32, |()| 0)`
+                ErrnoUse::Convert { call, ret_ty } => {
+                    let printer = FmtPrinter::new(tcx, Namespace::TypeNS);
+                    let ret_ty = ret_ty.print(printer).unwrap().into_buffer();
+                    rewrites.push((
+                        call,
+                        Rewrite::MethodCall(
+                            "map_or_else".into(),
+                            Box::new(Rewrite::Identity),
+                            vec![
+                                Rewrite::Text(format!(
+                                    "|e| -e.raw_os_error().unwrap_or(1) as {ret_ty}"
+                                )),
+                                Rewrite::Text("|()| 0".into()),
+                            ],
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    rewrites
+}
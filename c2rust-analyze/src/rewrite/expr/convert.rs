@@ -1,12 +1,13 @@
 use crate::panic_detail;
 use crate::rewrite::expr::distribute::DistRewrite;
-use crate::rewrite::expr::mir_op::{self, ZeroizeType};
+use crate::rewrite::expr::mir_op::{self, RawPtrOf, ZeroizeType};
 use crate::rewrite::expr::unlower::MirOriginDesc;
-use crate::rewrite::{LifetimeName, Rewrite};
+use crate::rewrite::{printf, LifetimeName, Rewrite};
 use assert_matches::assert_matches;
 use log::*;
+use rustc_ast::LitKind;
 use rustc_hir as hir;
-use rustc_hir::def::Namespace;
+use rustc_hir::def::{Namespace, Res};
 use rustc_hir::intravisit::{self, Visitor};
 use rustc_hir::{ExprKind, HirId};
 use rustc_middle::hir::nested_filter;
@@ -18,6 +19,9 @@ use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write as _;
 
+/// The ordering used for plain loads and stores through pointers to atomic types.
+const ATOMIC_ORDERING: &str = "std::sync::atomic::Ordering::SeqCst";
+
 macro_rules! format_rewrite {
     ($($args:tt)*) => {
         Rewrite::Text(format!($($args)*))
@@ -30,6 +34,9 @@ struct ConvertVisitor<'tcx> {
     mir_rewrites: HashMap<HirId, Vec<DistRewrite>>,
     rewrites: HashMap<HirId, (Span, Rewrite)>,
     subsumed_child_rewrites: RefCell<HashSet<HirId>>,
+    /// Comparisons between two slice pointers, as marked by `RewriteKind::SlicePtrCmp`.  The value
+    /// is `true` if the slices are mutable.  Used by `try_rewrite_ptr_loop`.
+    slice_ptr_cmps: RefCell<HashMap<HirId, bool>>,
     /// When `true`, any `Expr` where rustc added an implicit adjustment will be rewritten to make
     /// that adjustment explicit.  Any node that emits a non-adjustment rewrite sets this flag when
     /// visiting its children.  This is important to ensure that implicit ref/deref operations are
@@ -104,6 +111,29 @@ impl<'tcx> ConvertVisitor<'tcx> {
             (&Yield(e, _), 0) => e,
             _ => panic!("bad subexpression index {} for {:?}", idx, ex),
         };
+        self.sub_rewrite(idx, sub_ex)
+    }
+
+    /// Like `get_subexpr` for a binary operator, but skips over any casts applied to the operand,
+    /// as in `x as usize`.  The casts are discarded, along with any rewrites on them.
+    fn get_subexpr_skip_casts(&self, ex: &'tcx hir::Expr<'tcx>, idx: usize) -> Rewrite {
+        let mut sub_ex = match (&ex.kind, idx) {
+            (&ExprKind::Binary(_, x, _), 0) => x,
+            (&ExprKind::Binary(_, _, y), 1) => y,
+            (kind, _) => panic!("expected binary operator, but got {kind:?}"),
+        };
+        let mut subsumed = self.subsumed_child_rewrites.borrow_mut();
+        while let ExprKind::Cast(inner, _) = sub_ex.kind {
+            subsumed.insert(sub_ex.hir_id);
+            sub_ex = inner;
+        }
+        drop(subsumed);
+        self.sub_rewrite(idx, sub_ex)
+    }
+
+    /// Build the rewrite for the subexpression `sub_ex`, which is referred to as subexpression
+    /// `idx` of its parent.
+    fn sub_rewrite(&self, idx: usize, sub_ex: &'tcx hir::Expr<'tcx>) -> Rewrite {
         let rw_sub = Rewrite::Sub(idx, sub_ex.span);
         if let Some(child_span_rw) = self.rewrites.get(&sub_ex.hir_id) {
             let child_rw = &child_span_rw.1;
@@ -120,6 +150,52 @@ impl<'tcx> ConvertVisitor<'tcx> {
         rw_sub
     }
 
+    /// Find the pointer whose address is used in the integer expression `ex`, as in `p` in
+    /// `(p as usize & 7) == 0`.  The expressions between `ex` and the pointer are added to
+    /// `path`, innermost first.
+    fn find_exposed_ptr(
+        &self,
+        ex: &'tcx hir::Expr<'tcx>,
+        path: &mut Vec<HirId>,
+    ) -> Option<&'tcx hir::Expr<'tcx>> {
+        let children: &[&'tcx hir::Expr<'tcx>] = match ex.kind {
+            ExprKind::Cast(inner, _) => {
+                if self.typeck_results.expr_ty(inner).is_unsafe_ptr() {
+                    path.push(ex.hir_id);
+                    return Some(inner);
+                }
+                &[inner]
+            }
+            ExprKind::Binary(_, x, y) => &[x, y],
+            ExprKind::Unary(_, x) | ExprKind::DropTemps(x) => &[x],
+            ExprKind::MethodCall(_, args, _) => args,
+            _ => &[],
+        };
+        let ptr = children
+            .iter()
+            .find_map(|&child| self.find_exposed_ptr(child, path))?;
+        path.push(ex.hir_id);
+        Some(ptr)
+    }
+
+    /// Get the pointer whose address is used in `ex`, discarding the integer operations around
+    /// it, and convert it to a raw pointer as described by `raw_ptr_of`.
+    fn get_exposed_ptr(&self, ex: &'tcx hir::Expr<'tcx>, raw_ptr_of: RawPtrOf) -> Rewrite {
+        let mut path = Vec::new();
+        let ptr = self
+            .find_exposed_ptr(ex, &mut path)
+            .unwrap_or_else(|| panic!("no exposed pointer found in {ex:?}"));
+        // `ex` itself is the expression being rewritten, not a child.
+        path.pop();
+        self.subsumed_child_rewrites.borrow_mut().extend(path);
+        let rw = self.sub_rewrite(0, ptr);
+        match raw_ptr_of {
+            RawPtrOf::Raw => rw,
+            RawPtrOf::Slice => Rewrite::MethodCall("as_ptr".into(), Box::new(rw), vec![]),
+            RawPtrOf::Ref => Rewrite::Call("std::ptr::from_ref".into(), vec![rw]),
+        }
+    }
+
     fn rewrite_from_mir_rw(
         &self,
         ex: Option<&'tcx hir::Expr<'tcx>>,
@@ -147,6 +223,30 @@ impl<'tcx> ConvertVisitor<'tcx> {
                 Rewrite::Ref(Box::new(elem), mutbl_from_bool(mutbl))
             }
 
+            mir_op::RewriteKind::CheckedOffsetSlice { mutbl } => {
+                // `p.offset(i)` -> `{ let (arr, idx) = (p, i as usize); assert!(idx <= arr.len());
+                // &arr[idx ..] }`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let arr = self.get_subexpr(ex, 0);
+                let idx = Rewrite::Cast(
+                    Box::new(self.get_subexpr(ex, 1)),
+                    Box::new(Rewrite::Print("usize".to_owned())),
+                );
+                let rw_let = Rewrite::Let(vec![("arr".into(), arr), ("idx".into(), idx)]);
+                let check = Rewrite::Text(
+                    "assert!(idx <= arr.len(), \"offset {} out of bounds for length {}\", \
+                        idx, arr.len())"
+                        .into(),
+                );
+                let elem = Rewrite::SliceRange(
+                    Box::new(Rewrite::Text("arr".into())),
+                    Some(Box::new(Rewrite::Text("idx".into()))),
+                    None,
+                );
+                let ref_elem = Rewrite::Ref(Box::new(elem), mutbl_from_bool(mutbl));
+                Rewrite::Block(vec![rw_let, check], Some(Box::new(ref_elem)))
+            }
+
             mir_op::RewriteKind::OptionMapOffsetSlice { mutbl } => {
                 // `p.offset(i)` -> `p.as_ref().map(|p| &p[i as usize ..])`
                 assert!(matches!(hir_rw, Rewrite::Identity));
@@ -171,6 +271,108 @@ impl<'tcx> ConvertVisitor<'tcx> {
                 Rewrite::Block(vec![rw_let], Some(Box::new(call)))
             }
 
+            mir_op::RewriteKind::OffsetFromSlice => {
+                // `end.offset_from(start)` -> `start.len() as isize - end.len() as isize`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                Rewrite::Block(
+                    vec![Rewrite::Let(vec![
+                        ("end".into(), self.get_subexpr(ex, 0)),
+                        ("start".into(), self.get_subexpr(ex, 1)),
+                    ])],
+                    Some(Box::new(format_rewrite!(
+                        "start.len() as isize - end.len() as isize"
+                    ))),
+                )
+            }
+
+            mir_op::RewriteKind::PtrAddrDiffSlice { ref pointee } => {
+                // `end as usize - start as usize` ->
+                // `(start.len() - end.len()) * std::mem::size_of::<T>()`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                Rewrite::Block(
+                    vec![Rewrite::Let(vec![
+                        ("end".into(), self.get_subexpr_skip_casts(ex, 0)),
+                        ("start".into(), self.get_subexpr_skip_casts(ex, 1)),
+                    ])],
+                    Some(Box::new(format_rewrite!(
+                        "(start.len() - end.len()) * std::mem::size_of::<{pointee}>()"
+                    ))),
+                )
+            }
+
+            mir_op::RewriteKind::PtrAddrDiffSliceElems => {
+                // `(end as usize - start as usize) / size_of::<T>()` -> `start.len() - end.len()`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let diff_ex = match ex.kind {
+                    ExprKind::Binary(_, x, _) => x,
+                    ref kind => panic!("expected binary operator, but got {kind:?}"),
+                };
+                // The subtraction's own `PtrAddrDiffSlice` rewrite is replaced by this one.
+                self.subsumed_child_rewrites
+                    .borrow_mut()
+                    .insert(diff_ex.hir_id);
+                Rewrite::Block(
+                    vec![Rewrite::Let(vec![
+                        ("end".into(), self.get_subexpr_skip_casts(diff_ex, 0)),
+                        ("start".into(), self.get_subexpr_skip_casts(diff_ex, 1)),
+                    ])],
+                    Some(Box::new(format_rewrite!("start.len() - end.len()"))),
+                )
+            }
+
+            mir_op::RewriteKind::IsAligned { align, ne, ptr } => {
+                // `(p as usize & 7) == 0` -> `p.align_offset(8) == 0`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let ptr = self.get_exposed_ptr(ex, ptr);
+                let check = match align {
+                    Some(align) => Rewrite::Binary(
+                        "==".into(),
+                        Box::new(Rewrite::MethodCall(
+                            "align_offset".into(),
+                            Box::new(ptr),
+                            vec![Rewrite::Text(align.to_string())],
+                        )),
+                        Box::new(Rewrite::Text("0".into())),
+                    ),
+                    None => Rewrite::MethodCall("is_aligned".into(), Box::new(ptr), vec![]),
+                };
+                if ne {
+                    Rewrite::Call("!".into(), vec![check])
+                } else {
+                    check
+                }
+            }
+
+            mir_op::RewriteKind::AlignUpSlice { align, mutbl } => {
+                // `((p as usize + 7) & !7) as *mut T` ->
+                // `&mut p[p.as_ptr().align_offset(8)..]`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let mutbl = if mutbl { "mut " } else { "" };
+                Rewrite::Block(
+                    vec![Rewrite::Let(vec![(
+                        "ptr".into(),
+                        self.get_exposed_ptr(ex, RawPtrOf::Raw),
+                    )])],
+                    Some(Box::new(format_rewrite!(
+                        "&{mutbl}ptr[ptr.as_ptr().align_offset({align})..]"
+                    ))),
+                )
+            }
+
+            mir_op::RewriteKind::AlignUpRaw { align } => {
+                // `((p as usize + 7) & !7) as *mut T` -> `p.wrapping_add(p.align_offset(8))`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                Rewrite::Block(
+                    vec![Rewrite::Let(vec![(
+                        "ptr".into(),
+                        self.get_exposed_ptr(ex, RawPtrOf::Raw),
+                    )])],
+                    Some(Box::new(format_rewrite!(
+                        "ptr.wrapping_add(ptr.align_offset({align}))"
+                    ))),
+                )
+            }
+
             mir_op::RewriteKind::RemoveAsPtr => {
                 // `slice.as_ptr()` -> `slice`
                 assert!(matches!(hir_rw, Rewrite::Identity));
@@ -191,6 +393,22 @@ impl<'tcx> ConvertVisitor<'tcx> {
                 }
             }
 
+            mir_op::RewriteKind::TransmuteRef {
+                ref from_ty,
+                ref to_ty,
+            } => {
+                // `p as *const B` -> `unsafe { std::mem::transmute::<&A, &B>(p) }`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let call = Rewrite::Call(
+                    format!("std::mem::transmute::<&{from_ty}, &{to_ty}>"),
+                    vec![self.get_subexpr(ex, 0)],
+                );
+                let safety = format!(
+                    "`{from_ty}` can be read as `{to_ty}`, and the result is only used for reading"
+                );
+                Rewrite::Unsafe(safety, Box::new(call))
+            }
+
             mir_op::RewriteKind::RawToRef { mutbl } => {
                 // &raw _ to &_ or &raw mut _ to &mut _
                 match hir_rw {
@@ -202,11 +420,78 @@ impl<'tcx> ConvertVisitor<'tcx> {
                 }
             }
 
+            mir_op::RewriteKind::DowngradeRefMut
+                if matches!(hir_rw, Rewrite::Identity)
+                    && matches!(
+                        ex.kind,
+                        ExprKind::AddrOf(hir::BorrowKind::Ref, hir::Mutability::Mut, _)
+                    ) =>
+            {
+                // `&mut x` -> `&x`
+                Rewrite::Ref(Box::new(self.get_subexpr(ex, 0)), hir::Mutability::Not)
+            }
+
             mir_op::RewriteKind::IsNullToIsNone => {
                 // `p.is_null()` -> `p.is_none()`
                 assert!(matches!(hir_rw, Rewrite::Identity));
                 Rewrite::MethodCall("is_none".into(), Box::new(self.get_subexpr(ex, 0)), vec![])
             }
+            mir_op::RewriteKind::SlicePtrCmp { mutbl } => {
+                // No change here; the enclosing loop may be rewritten by `try_rewrite_ptr_loop`.
+                self.slice_ptr_cmps.borrow_mut().insert(ex.hir_id, mutbl);
+                hir_rw
+            }
+
+            mir_op::RewriteKind::NotIsNullToIsSome => {
+                // `!p.is_null()` -> `p.is_some()`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let is_null_ex = match ex.kind {
+                    ExprKind::Unary(hir::UnOp::Not, inner) => inner,
+                    ref kind => panic!("expected `!_`, but got {kind:?}"),
+                };
+                // The receiver is taken directly from the `is_null` call, so the rewrite on the
+                // call itself is dropped.
+                self.subsumed_child_rewrites
+                    .borrow_mut()
+                    .insert(is_null_ex.hir_id);
+                Rewrite::MethodCall(
+                    "is_some".into(),
+                    Box::new(self.get_subexpr(is_null_ex, 0)),
+                    vec![],
+                )
+            }
+            mir_op::RewriteKind::PtrCmpNullToIsNone { null_idx, ne } => {
+                // `p == null` -> `p.is_none()`
+                // `p != null` -> `p.is_some()`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let null_ex = match (&ex.kind, null_idx) {
+                    (&ExprKind::Binary(_, x, _), 0) => x,
+                    (&ExprKind::Binary(_, _, y), 1) => y,
+                    (kind, _) => panic!("expected binary operator, but got {kind:?}"),
+                };
+                let mut subsumed = self.subsumed_child_rewrites.borrow_mut();
+                let mut e = null_ex;
+                loop {
+                    subsumed.insert(e.hir_id);
+                    match e.kind {
+                        ExprKind::Cast(inner, _) => e = inner,
+                        _ => break,
+                    }
+                }
+                drop(subsumed);
+                let method = if ne { "is_some" } else { "is_none" };
+                Rewrite::MethodCall(
+                    method.into(),
+                    Box::new(self.get_subexpr(ex, 1 - null_idx)),
+                    vec![],
+                )
+            }
+            mir_op::RewriteKind::PtrCmpNullToConst { value } => {
+                // `p == null` -> `false`
+                // `p != null` -> `true`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                Rewrite::Text(value.to_string())
+            }
             mir_op::RewriteKind::IsNullToConstFalse => {
                 // `p.is_null()` -> `false`
                 assert!(matches!(hir_rw, Rewrite::Identity));
@@ -226,12 +511,30 @@ impl<'tcx> ConvertVisitor<'tcx> {
             mir_op::RewriteKind::MemcpySafe {
                 elem_size,
                 dest_single,
+                dest_option,
+                src_single,
+                src_option,
+            }
+            | mir_op::RewriteKind::MemmoveSafe {
+                elem_size,
+                dest_single,
+                dest_option,
                 src_single,
+                src_option,
             } => {
                 // `memcpy(dest, src, n)` to a `copy_from_slice` call
                 assert!(matches!(hir_rw, Rewrite::Identity));
-                assert!(!dest_single, "&T -> &[T] conversion for memcpy dest NYI");
-                assert!(!src_single, "&T -> &[T] conversion for memcpy src NYI");
+                if let mir_op::RewriteKind::MemmoveSafe { .. } = *rw {
+                    // `memmove(p.offset(i), p.offset(j), n)` to a `copy_within` call.  Other
+                    // `memmove`s are handled like `memcpy`.
+                    if !dest_single && !src_single && !dest_option && !src_option {
+                        if let Some(rw) = self.try_rewrite_copy_within(ex, elem_size) {
+                            return rw;
+                        }
+                    }
+                }
+                let dest_slice = slice_access_expr("dest", dest_single, dest_option, true);
+                let src_slice = slice_access_expr("src", src_single, src_option, false);
                 Rewrite::Block(
                     vec![
                         Rewrite::Let(vec![
@@ -245,8 +548,8 @@ impl<'tcx> ConvertVisitor<'tcx> {
                         )]),
                         Rewrite::MethodCall(
                             "copy_from_slice".into(),
-                            Box::new(format_rewrite!("dest[..n]")),
-                            vec![format_rewrite!("&src[..n]")],
+                            Box::new(format_rewrite!("{dest_slice}[..n]")),
+                            vec![format_rewrite!("&{src_slice}[..n]")],
                         ),
                     ],
                     Some(Box::new(format_rewrite!("dest"))),
@@ -286,6 +589,333 @@ impl<'tcx> ConvertVisitor<'tcx> {
                 )
             }
 
+            mir_op::RewriteKind::MallocSafe {
+                ref zero_ty,
+                elem_size,
+                single,
+            } => {
+                // `malloc(n)` -> `Box::new(z)` or a zero-initialized boxed slice
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let zeroize_expr = generate_zeroize_expr(zero_ty);
+                let mut stmts = vec![
+                    Rewrite::Let(vec![("byte_len".into(), self.get_subexpr(ex, 0))]),
+                    Rewrite::Let1(
+                        "n".into(),
+                        Box::new(format_rewrite!("byte_len as usize / {elem_size}")),
+                    ),
+                ];
+                let expr = if single {
+                    stmts.push(format_rewrite!("assert_eq!(n, 1)"));
+                    format_rewrite!("Box::new({zeroize_expr})")
+                } else {
+                    format_rewrite!("(0..n).map(|_| {zeroize_expr}).collect::<Box<[_]>>()")
+                };
+                Rewrite::Block(stmts, Some(Box::new(expr)))
+            }
+
+            mir_op::RewriteKind::CallocSafe {
+                ref zero_ty,
+                elem_size,
+                single,
+            } => {
+                // `calloc(count, size)` -> `Box::new(z)` or a zero-initialized boxed slice
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let zeroize_expr = generate_zeroize_expr(zero_ty);
+                let mut stmts = vec![
+                    Rewrite::Let(vec![
+                        ("count".into(), self.get_subexpr(ex, 0)),
+                        ("size".into(), self.get_subexpr(ex, 1)),
+                    ]),
+                    Rewrite::Let1(
+                        "n".into(),
+                        Box::new(format_rewrite!(
+                            "count as usize * size as usize / {elem_size}"
+                        )),
+                    ),
+                ];
+                let expr = if single {
+                    stmts.push(format_rewrite!("assert_eq!(n, 1)"));
+                    format_rewrite!("Box::new({zeroize_expr})")
+                } else {
+                    format_rewrite!("(0..n).map(|_| {zeroize_expr}).collect::<Box<[_]>>()")
+                };
+                Rewrite::Block(stmts, Some(Box::new(expr)))
+            }
+
+            mir_op::RewriteKind::ReallocSafe {
+                ref zero_ty,
+                elem_size,
+                src_single,
+                dest_single,
+                src_option,
+            } => {
+                // `realloc(p, n)` -> `Vec::from(p)` + `resize_with` + `into_boxed_slice`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let zeroize_expr = generate_zeroize_expr(zero_ty);
+                let to_vec = if src_single {
+                    "|p| vec![*p]"
+                } else {
+                    "Vec::from"
+                };
+                let src_vec = if src_option {
+                    format_rewrite!("src_ptr.map({to_vec}).unwrap_or_default()")
+                } else {
+                    format_rewrite!("({to_vec})(src_ptr)")
+                };
+                let mut stmts = vec![
+                    Rewrite::Let(vec![
+                        ("src_ptr".into(), self.get_subexpr(ex, 0)),
+                        ("dest_byte_len".into(), self.get_subexpr(ex, 1)),
+                    ]),
+                    Rewrite::Let1(
+                        "dest_n".into(),
+                        Box::new(format_rewrite!("dest_byte_len as usize / {elem_size}")),
+                    ),
+                    Rewrite::Let1("mut v".into(), Box::new(src_vec)),
+                    format_rewrite!("v.resize_with(dest_n, || {zeroize_expr})"),
+                ];
+                let expr = if dest_single {
+                    stmts.push(format_rewrite!("assert_eq!(dest_n, 1)"));
+                    format_rewrite!("Box::new(v.into_iter().next().unwrap())")
+                } else {
+                    format_rewrite!("v.into_boxed_slice()")
+                };
+                Rewrite::Block(stmts, Some(Box::new(expr)))
+            }
+
+            mir_op::RewriteKind::FreeSafe { take } => {
+                // `free(p)` -> `drop(p)`, or `drop(p.take())`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let mut arg = self.get_subexpr(ex, 0);
+                if take {
+                    arg = Rewrite::MethodCall("take".to_string(), Box::new(arg), vec![]);
+                }
+                Rewrite::Call("std::mem::drop".to_string(), vec![arg])
+            }
+
+            mir_op::RewriteKind::RemoveStmt => {
+                // Statements are removed entirely by `visit_stmt`.  This only happens when the
+                // expression isn't a statement by itself, so it's replaced with `()` instead.
+                Rewrite::Text("()".into())
+            }
+
+            mir_op::RewriteKind::MemsetFill {
+                ref zero_ty,
+                elem_size,
+                dest_option,
+            } => {
+                // `memset(dest, 0, n)` to `dest[..n].fill(0)`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let dest_slice = slice_access_expr("dest", false, dest_option, true);
+                Rewrite::Block(
+                    vec![
+                        Rewrite::Let(vec![
+                            ("dest".into(), self.get_subexpr(ex, 0)),
+                            ("val".into(), self.get_subexpr(ex, 1)),
+                            ("byte_len".into(), self.get_subexpr(ex, 2)),
+                        ]),
+                        Rewrite::Let(vec![(
+                            "n".into(),
+                            format_rewrite!("byte_len as usize / {elem_size}"),
+                        )]),
+                        format_rewrite!("assert_eq!(val, 0, \"non-zero memset NYI\")"),
+                        Rewrite::MethodCall(
+                            "fill".into(),
+                            Box::new(format_rewrite!("{dest_slice}[..n]")),
+                            vec![Rewrite::Text(generate_zeroize_expr(zero_ty))],
+                        ),
+                    ],
+                    Some(Box::new(format_rewrite!("dest"))),
+                )
+            }
+
+            mir_op::RewriteKind::CStrFnSafe {
+                func,
+                ref ret_ty,
+                cstr_args,
+            } => {
+                // `strlen(s)` and similar to equivalent operations on NUL-terminated slices, or on
+                // `&CStr`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                const FIND_NUL: &str = "iter().position(|&c| c == 0).unwrap()";
+                const UNTIL_NUL: &str = "iter().take_while(|&&c| c != 0).map(|&c| c as u8)";
+                // The bytes of the `i`th argument `s`, not including the NUL terminator.
+                let bytes = |i: usize, s: &str| {
+                    if cstr_args[i] {
+                        format!("{s}.to_bytes().iter().copied()")
+                    } else {
+                        format!("{s}.{UNTIL_NUL}")
+                    }
+                };
+                match func {
+                    mir_op::CStrFn::Strlen => {
+                        let len = if cstr_args[0] {
+                            "to_bytes().len()"
+                        } else {
+                            FIND_NUL
+                        };
+                        Rewrite::Block(
+                            vec![Rewrite::Let(vec![("s".into(), self.get_subexpr(ex, 0))])],
+                            Some(Box::new(format_rewrite!("s.{len} as {ret_ty}"))),
+                        )
+                    }
+                    mir_op::CStrFn::Strcmp => Rewrite::Block(
+                        vec![Rewrite::Let(vec![
+                            ("s1".into(), self.get_subexpr(ex, 0)),
+                            ("s2".into(), self.get_subexpr(ex, 1)),
+                        ])],
+                        Some(Box::new(format_rewrite!(
+                            "{}.cmp({}) as {ret_ty}",
+                            bytes(0, "s1"),
+                            bytes(1, "s2")
+                        ))),
+                    ),
+                    mir_op::CStrFn::Strcpy | mir_op::CStrFn::Strcat => {
+                        let dest_off = if func == mir_op::CStrFn::Strcat {
+                            format_rewrite!("dest.{FIND_NUL}")
+                        } else {
+                            format_rewrite!("0")
+                        };
+                        // A `&CStr` holds `u8`s, so its bytes are converted one at a time.
+                        const COPY_CSTR: &str = "dest[off..off + n].iter_mut()\
+                            .zip(src.to_bytes_with_nul()).for_each(|(d, &c)| *d = c as _)";
+                        let (n, copy) = if cstr_args[1] {
+                            ("src.to_bytes_with_nul().len()".to_owned(), COPY_CSTR)
+                        } else {
+                            (
+                                format!("src.{FIND_NUL} + 1"),
+                                "dest[off..off + n].copy_from_slice(&src[..n])",
+                            )
+                        };
+                        Rewrite::Block(
+                            vec![
+                                Rewrite::Let(vec![
+                                    ("dest".into(), self.get_subexpr(ex, 0)),
+                                    ("src".into(), self.get_subexpr(ex, 1)),
+                                ]),
+                                Rewrite::Let(vec![
+                                    ("off".into(), dest_off),
+                                    ("n".into(), format_rewrite!("{n}")),
+                                ]),
+                                format_rewrite!("{copy}"),
+                            ],
+                            Some(Box::new(format_rewrite!("dest"))),
+                        )
+                    }
+                }
+            }
+
+            mir_op::RewriteKind::SprintfSafe {
+                ref fmt,
+                ref args,
+                bounded,
+                ref ret_ty,
+            } => {
+                // `sprintf(dest, b"%d\0" as ..., x)` -> `format!("{}", x)`, copied into `dest`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let fmt_idx = if bounded { 2 } else { 1 };
+                // The format string is replaced by its translation, so the rewrites of the
+                // literal and its casts are no longer needed.
+                if let ExprKind::Call(_, call_args) = ex.kind {
+                    let mut subsumed = self.subsumed_child_rewrites.borrow_mut();
+                    let mut e = &call_args[fmt_idx];
+                    loop {
+                        subsumed.insert(e.hir_id);
+                        match e.kind {
+                            ExprKind::Cast(inner, _) => e = inner,
+                            _ => break,
+                        }
+                    }
+                }
+
+                let mut format_args = vec![format_rewrite!("{fmt:?}")];
+                for (i, &kind) in args.iter().enumerate() {
+                    let arg = self.get_subexpr(ex, fmt_idx + 1 + i);
+                    format_args.push(printf::convert_arg(kind, arg));
+                }
+                // All bindings are made at once, so the arguments can't see `dest` or `s`.
+                let mut bindings = vec![("dest".into(), self.get_subexpr(ex, 0))];
+                if bounded {
+                    let size = Rewrite::Cast(
+                        Box::new(self.get_subexpr(ex, 1)),
+                        Box::new(Rewrite::Print("usize".into())),
+                    );
+                    bindings.push(("size".into(), size));
+                }
+                bindings.push(("s".into(), Rewrite::Call("format!".into(), format_args)));
+
+                const COPY: &str =
+                    "dest[..n].iter_mut().zip(s.bytes()).for_each(|(d, c)| *d = c as _)";
+                let mut stmts = vec![Rewrite::Let(bindings)];
+                if bounded {
+                    // `snprintf` truncates the output to fit, and writes nothing if `size` is 0.
+                    stmts.push(format_rewrite!(
+                        "if size > 0 {{ let n = s.len().min(size - 1); {COPY}; dest[n] = 0; }}"
+                    ));
+                } else {
+                    stmts.push(Rewrite::Let1(
+                        "n".into(),
+                        Box::new(format_rewrite!("s.len()")),
+                    ));
+                    stmts.push(format_rewrite!("{COPY}"));
+                    stmts.push(format_rewrite!("dest[n] = 0"));
+                }
+                // Both functions return the length of the whole formatted string.
+                Rewrite::Block(
+                    stmts,
+                    Some(Box::new(format_rewrite!("s.len() as {ret_ty}"))),
+                )
+            }
+
+            mir_op::RewriteKind::CStrLiteral { signed, cstr } => {
+                // `b"hi\0" as *const u8 as *const c_char` -> `CStr::from_bytes_with_nul(b"hi\0")`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                // The casts are replaced along with the literal, so their own rewrites are no
+                // longer needed.
+                let mut lit_ex = ex;
+                while let ExprKind::Cast(inner, _) = lit_ex.kind {
+                    self.subsumed_child_rewrites
+                        .borrow_mut()
+                        .insert(inner.hir_id);
+                    lit_ex = inner;
+                }
+                let bytes = match lit_ex.kind {
+                    ExprKind::Lit(ref lit) => match lit.node {
+                        LitKind::ByteStr(ref bytes) => bytes.clone(),
+                        ref node => panic!("expected byte string literal, but got {node:?}"),
+                    },
+                    ref kind => panic!("expected byte string literal, but got {kind:?}"),
+                };
+                // `try_visit_cstr_literal_cast` checked that the literal is a valid C string, so
+                // the `unwrap`s below never panic.
+                if cstr {
+                    let cstr = Rewrite::Call(
+                        "std::ffi::CStr::from_bytes_with_nul".into(),
+                        vec![Rewrite::Extract(lit_ex.span)],
+                    );
+                    Rewrite::MethodCall("unwrap".into(), Box::new(cstr), vec![])
+                } else if signed {
+                    // There's no safe conversion from `&[u8]` to `&[i8]`, so we write out the
+                    // bytes as an `i8` array instead.
+                    let mut s = String::from("&[");
+                    for (i, &b) in bytes.iter().enumerate() {
+                        if i > 0 {
+                            s.push_str(", ");
+                        }
+                        write!(s, "{}", b as i8).unwrap();
+                    }
+                    s.push_str("i8]");
+                    Rewrite::Text(s)
+                } else {
+                    let cstr = Rewrite::Call(
+                        "std::ffi::CStr::from_bytes_with_nul".into(),
+                        vec![Rewrite::Extract(lit_ex.span)],
+                    );
+                    let cstr = Rewrite::MethodCall("unwrap".into(), Box::new(cstr), vec![]);
+                    Rewrite::MethodCall("to_bytes_with_nul".into(), Box::new(cstr), vec![])
+                }
+            }
+
             mir_op::RewriteKind::CellGet => {
                 // `*x` to `Cell::get(x)`
                 assert!(matches!(hir_rw, Rewrite::Identity));
@@ -293,18 +923,184 @@ impl<'tcx> ConvertVisitor<'tcx> {
             }
 
             mir_op::RewriteKind::CellSet => {
-                // `*x` to `Cell::set(x)`
+                // `*x = y` to `x.set(y)`, or `*x += y` to `x.set(x.get() + y)`
                 assert!(matches!(hir_rw, Rewrite::Identity));
-                let deref_lhs = assert_matches!(ex.kind, ExprKind::Assign(lhs, ..) => lhs);
+                let deref_lhs = assert_matches!(
+                    ex.kind,
+                    ExprKind::Assign(lhs, ..) | ExprKind::AssignOp(_, lhs, _) => lhs
+                );
                 let lhs = self.get_subexpr(deref_lhs, 0);
-                let rhs = self.get_subexpr(ex, 1);
+                let mut rhs = self.get_subexpr(ex, 1);
+                if let ExprKind::AssignOp(op, ..) = ex.kind {
+                    let op = op.node.as_str().to_string();
+                    let old = Rewrite::MethodCall("get".to_string(), Box::new(lhs.clone()), vec![]);
+                    rhs = Rewrite::Binary(op, Box::new(old), Box::new(rhs));
+                }
                 Rewrite::MethodCall("set".to_string(), Box::new(lhs), vec![rhs])
             }
 
+            mir_op::RewriteKind::AtomicLoad => {
+                // `*x` to `x.load(Ordering::SeqCst)`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                Rewrite::MethodCall(
+                    "load".to_string(),
+                    Box::new(self.get_subexpr(ex, 0)),
+                    vec![Rewrite::Text(ATOMIC_ORDERING.into())],
+                )
+            }
+
+            mir_op::RewriteKind::AtomicStore => {
+                // `*x = y` to `x.store(y, Ordering::SeqCst)`, or `*x += y` to
+                // `x.store(x.load(Ordering::SeqCst) + y, Ordering::SeqCst)`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let deref_lhs = assert_matches!(
+                    ex.kind,
+                    ExprKind::Assign(lhs, ..) | ExprKind::AssignOp(_, lhs, _) => lhs
+                );
+                let lhs = self.get_subexpr(deref_lhs, 0);
+                let mut rhs = self.get_subexpr(ex, 1);
+                if let ExprKind::AssignOp(op, ..) = ex.kind {
+                    let op = op.node.as_str().to_string();
+                    let old = Rewrite::MethodCall(
+                        "load".to_string(),
+                        Box::new(lhs.clone()),
+                        vec![Rewrite::Text(ATOMIC_ORDERING.into())],
+                    );
+                    rhs = Rewrite::Binary(op, Box::new(old), Box::new(rhs));
+                }
+                Rewrite::MethodCall(
+                    "store".to_string(),
+                    Box::new(lhs),
+                    vec![rhs, Rewrite::Text(ATOMIC_ORDERING.into())],
+                )
+            }
+
+            mir_op::RewriteKind::AtomicMethod {
+                method,
+                ref orderings,
+                cxchg,
+            } => {
+                // `atomic_xadd_seqcst(p, x)` to `p.fetch_add(x, Ordering::SeqCst)`
+                assert!(matches!(hir_rw, Rewrite::Identity));
+                let num_args = assert_matches!(ex.kind, ExprKind::Call(_, args) => args.len());
+                let mut args = (1..num_args)
+                    .map(|i| self.get_subexpr(ex, i))
+                    .collect::<Vec<_>>();
+                args.extend(
+                    orderings
+                        .iter()
+                        .map(|o| Rewrite::Text(format!("std::sync::atomic::Ordering::{o}"))),
+                );
+                let call = Rewrite::MethodCall(
+                    method.to_string(),
+                    Box::new(self.get_subexpr(ex, 0)),
+                    args,
+                );
+                if cxchg {
+                    // The intrinsic returns the previous value and whether it was replaced.
+                    Rewrite::MethodCall(
+                        "map_or_else".to_string(),
+                        Box::new(call),
+                        vec![
+                            Rewrite::Text("|x| (x, false)".into()),
+                            Rewrite::Text("|x| (x, true)".into()),
+                        ],
+                    )
+                } else {
+                    call
+                }
+            }
+
             _ => convert_cast_rewrite(rw, hir_rw),
         }
     }
 
+    /// Try to rewrite `memmove(dest, src, n)` into a `copy_within` call on a single slice.  This
+    /// is possible when `dest` and `src` are both of the form `base.offset(i)` for the same local
+    /// `base`, and both offsets are known to be non-negative (see `is_nonneg_offset`), since
+    /// `copy_within` takes them as `usize`s.  Returns `None` if the arguments don't have this
+    /// form.
+    fn try_rewrite_copy_within(
+        &self,
+        ex: &'tcx hir::Expr<'tcx>,
+        elem_size: u64,
+    ) -> Option<Rewrite> {
+        let args = match ex.kind {
+            ExprKind::Call(_, args) if args.len() == 3 => args,
+            _ => return None,
+        };
+        let dest_offset = offset_call_parts(&args[0])?;
+        let src_offset = offset_call_parts(&args[1])?;
+        let base_local = local_path_res(dest_offset.1)?;
+        if local_path_res(src_offset.1) != Some(base_local) {
+            return None;
+        }
+        let offset_arg = |call: &'tcx hir::Expr<'tcx>| match call.kind {
+            ExprKind::MethodCall(_, args, _) => &args[1],
+            _ => unreachable!(),
+        };
+        if !self.is_nonneg_offset(offset_arg(dest_offset.0))
+            || !self.is_nonneg_offset(offset_arg(src_offset.0))
+        {
+            return None;
+        }
+
+        // The `offset` calls and any casts around them are replaced entirely by the `copy_within`
+        // rewrite, so their own rewrites are no longer needed.
+        {
+            let mut subsumed = self.subsumed_child_rewrites.borrow_mut();
+            for arg in &args[..2] {
+                let mut e = arg;
+                loop {
+                    subsumed.insert(e.hir_id);
+                    match e.kind {
+                        ExprKind::Cast(inner, _) => e = inner,
+                        _ => break,
+                    }
+                }
+            }
+            subsumed.insert(src_offset.1.hir_id);
+        }
+
+        let usize_ty = || Box::new(Rewrite::Print("usize".to_owned()));
+        let base = Rewrite::Ref(
+            Box::new(Rewrite::Deref(Box::new(self.get_subexpr(dest_offset.0, 0)))),
+            hir::Mutability::Mut,
+        );
+        let dest_off = Rewrite::Cast(Box::new(self.get_subexpr(dest_offset.0, 1)), usize_ty());
+        let src_off = Rewrite::Cast(Box::new(self.get_subexpr(src_offset.0, 1)), usize_ty());
+        Some(Rewrite::Block(
+            vec![
+                Rewrite::Let(vec![
+                    ("base".into(), base),
+                    ("dest_off".into(), dest_off),
+                    ("src_off".into(), src_off),
+                    ("byte_len".into(), self.get_subexpr(ex, 2)),
+                ]),
+                Rewrite::Let(vec![(
+                    "n".into(),
+                    format_rewrite!("byte_len as usize / {elem_size}"),
+                )]),
+                format_rewrite!("base.copy_within(src_off..src_off + n, dest_off)"),
+            ],
+            Some(Box::new(format_rewrite!("&mut base[dest_off..]"))),
+        ))
+    }
+
+    /// Check whether the argument `ex` of an `offset` call is known to be non-negative: an integer
+    /// literal, possibly cast, or a cast from an unsigned integer, such as `i as isize` for
+    /// `i: usize`.
+    fn is_nonneg_offset(&self, ex: &'tcx hir::Expr<'tcx>) -> bool {
+        match ex.kind {
+            ExprKind::Lit(_) => true,
+            ExprKind::Cast(inner, _) => {
+                matches!(inner.kind, ExprKind::Lit(_))
+                    || matches!(self.typeck_results.expr_ty(inner).kind(), TyKind::Uint(_))
+            }
+            _ => false,
+        }
+    }
+
     /// Generate an `Option::map` call from the rewrites in `mir_rws`.  After seeing an
     /// `OptionMapBegin` in a list of MIR rewrites, pass the remaining rewrites to this method.  If
     /// it returns `Ok((new_hir_rw, remaining_mir_rws))`, then the `OptionMapBegin` and some
@@ -375,6 +1171,119 @@ impl<'tcx> ConvertVisitor<'tcx> {
         Ok((hir_rw, mir_rws))
     }
 
+    /// Try to rewrite a loop of the form `while p != end { ...; p = p.offset(1); }`, where `p` and
+    /// `end` are slices, into `for p in p[..p.len() - end.len()].iter() { ... }`.  Inside the
+    /// body, `p` must be used only as `*p`, which works the same whether `p` is the original
+    /// pointer or a reference to the current element.
+    fn try_rewrite_ptr_loop(&mut self, ex: &'tcx hir::Expr<'tcx>) -> Option<Rewrite> {
+        let block = match ex.kind {
+            ExprKind::Loop(block, None, hir::LoopSource::While, _) => block,
+            _ => return None,
+        };
+        let (cond, then_ex) = match (block.stmts, block.expr.map(|e| &e.kind)) {
+            ([], Some(&ExprKind::If(cond, then_ex, Some(_)))) => (cond, then_ex),
+            _ => return None,
+        };
+        let cond = match cond.kind {
+            ExprKind::DropTemps(e) => e,
+            _ => cond,
+        };
+        let mutbl = *self.slice_ptr_cmps.borrow().get(&cond.hir_id)?;
+        let (p, end) = match cond.kind {
+            ExprKind::Binary(op, l, r) if op.node == hir::BinOpKind::Ne => {
+                (local_path_res(l)?, local_path_res(r)?)
+            }
+            _ => return None,
+        };
+        let body = match then_ex.kind {
+            ExprKind::Block(body, None) if body.expr.is_none() => body,
+            _ => return None,
+        };
+
+        // The last statement must be `p = p.offset(1);`.
+        let (incr_stmt, body_stmts) = body.stmts.split_last()?;
+        let incr_ex = match incr_stmt.kind {
+            hir::StmtKind::Semi(e) => e,
+            _ => return None,
+        };
+        let (lhs, rhs) = match incr_ex.kind {
+            ExprKind::Assign(lhs, rhs, _) => (lhs, rhs),
+            _ => return None,
+        };
+        let (offset_ex, base_ex) = offset_call_parts(rhs)?;
+        if local_path_res(lhs) != Some(p) || local_path_res(base_ex) != Some(p) {
+            return None;
+        }
+        let mut offset_arg = match offset_ex.kind {
+            ExprKind::MethodCall(_, args, _) => &args[1],
+            _ => return None,
+        };
+        while let ExprKind::Cast(inner, _) = offset_arg.kind {
+            offset_arg = inner;
+        }
+        if !matches!(offset_arg.kind, ExprKind::Lit(ref lit) if matches!(lit.node, LitKind::Int(1, _)))
+        {
+            return None;
+        }
+
+        // Check the rest of the body.
+        let mut checker = PtrLoopBodyChecker {
+            loop_id: ex.hir_id,
+            p,
+            end,
+            ok: true,
+        };
+        for stmt in body_stmts {
+            intravisit::walk_stmt(&mut checker, stmt);
+        }
+        if !checker.ok {
+            return None;
+        }
+
+        // The loop leaves `p` unchanged, so `p` must not be used after the loop.  To ensure that
+        // "after" has its usual meaning, the loop also must not be nested inside another loop.
+        let hir_map = self.tcx.hir();
+        for (_, node) in hir_map.parent_iter(ex.hir_id) {
+            if let hir::Node::Expr(parent) = node {
+                if matches!(parent.kind, ExprKind::Loop(..) | ExprKind::Closure(..)) {
+                    return None;
+                }
+            }
+        }
+        let body_id = hir_map.body_owned_by(hir_map.enclosing_body_owner(ex.hir_id));
+        let mut finder = LocalUseFinder {
+            local: p,
+            after: ex.span,
+            found: false,
+        };
+        finder.visit_body(hir_map.body(body_id));
+        if finder.found {
+            return None;
+        }
+
+        // Remove the increment.  `end` is no longer needed inside the loop, so it's fine that
+        // the comparison is discarded as well.
+        self.rewrites.insert(
+            incr_stmt.hir_id,
+            (incr_stmt.span, Rewrite::Text(String::new())),
+        );
+        let p_name = hir_map.name(p);
+        let end_name = hir_map.name(end);
+        let len = Rewrite::Let1(
+            "__len".into(),
+            Box::new(format_rewrite!("{p_name}.len() - {end_name}.len()")),
+        );
+        let slice = Rewrite::SliceRange(
+            Box::new(format_rewrite!("{p_name}")),
+            None,
+            Some(Box::new(format_rewrite!("__len"))),
+        );
+        let method = if mutbl { "iter_mut" } else { "iter" };
+        let iter = Rewrite::MethodCall(method.into(), Box::new(slice), vec![]);
+        let for_loop = Rewrite::ForLoop(p_name.to_string(), Box::new(iter), then_ex.span);
+        Some(Rewrite::Block(vec![len], Some(Box::new(for_loop))))
+    }
+
     fn rewrite_from_mir_rws(
         &self,
         ex: Option<&'tcx hir::Expr<'tcx>>,
@@ -409,6 +1318,32 @@ impl<'tcx> Visitor<'tcx> for ConvertVisitor<'tcx> {
         self.tcx.hir()
     }
 
+    fn visit_stmt(&mut self, stmt: &'tcx hir::Stmt<'tcx>) {
+        // Remove statements marked with `RemoveStmt`, along with the whitespace after them.  Any
+        // rewrites on subexpressions are discarded along with the statement.
+        if let hir::StmtKind::Semi(ex) = stmt.kind {
+            let remove = self.mir_rewrites.get(&ex.hir_id).map_or(false, |rws| {
+                rws.iter()
+                    .any(|x| matches!(x.rw, mir_op::RewriteKind::RemoveStmt))
+            });
+            if remove {
+                self.mir_rewrites.remove(&ex.hir_id);
+                intravisit::walk_expr(self, ex);
+                let span = stmt.span.source_callsite();
+                let span = self
+                    .tcx
+                    .sess
+                    .source_map()
+                    .span_extend_while(span, char::is_whitespace)
+                    .unwrap_or(span);
+                self.rewrites
+                    .insert(ex.hir_id, (span, Rewrite::Text(String::new())));
+                return;
+            }
+        }
+        intravisit::walk_stmt(self, stmt);
+    }
+
     fn visit_expr(&mut self, ex: &'tcx hir::Expr<'tcx>) {
         let _g = panic_detail::set_current_span(ex.span);
         let mut hir_rw = Rewrite::Identity;
@@ -470,6 +1405,12 @@ impl<'tcx> Visitor<'tcx> for ConvertVisitor<'tcx> {
         }
         hir_rw = self.rewrite_from_mir_rws(Some(ex), mir_rws, hir_rw);
 
+        if matches!(hir_rw, Rewrite::Identity) {
+            if let Some(rw) = self.try_rewrite_ptr_loop(ex) {
+                hir_rw = rw;
+            }
+        }
+
         if !matches!(hir_rw, Rewrite::Identity) {
             eprintln!(
                 "rewrite {:?} at {:?} (materialize? {})",
@@ -480,6 +1421,79 @@ impl<'tcx> Visitor<'tcx> for ConvertVisitor<'tcx> {
     }
 }
 
+/// Checks the body of a loop for `try_rewrite_ptr_loop`.  The body may use `p` only as `*p`, may
+/// not use `end` at all, and may not `continue` the loop, since that would skip the increment.
+struct PtrLoopBodyChecker {
+    loop_id: HirId,
+    p: HirId,
+    end: HirId,
+    ok: bool,
+}
+
+impl<'tcx> Visitor<'tcx> for PtrLoopBodyChecker {
+    fn visit_expr(&mut self, ex: &'tcx hir::Expr<'tcx>) {
+        match ex.kind {
+            ExprKind::Unary(hir::UnOp::Deref, inner) if local_path_res(inner) == Some(self.p) => {
+                return;
+            }
+            ExprKind::Continue(dest) if dest.target_id == Ok(self.loop_id) => {
+                self.ok = false;
+            }
+            _ => {}
+        }
+        if let Some(id) = local_path_res(ex) {
+            if id == self.p || id == self.end {
+                self.ok = false;
+            }
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Searches for any use of `local` that appears after the span `after`.
+struct LocalUseFinder {
+    local: HirId,
+    after: Span,
+    found: bool,
+}
+
+impl<'tcx> Visitor<'tcx> for LocalUseFinder {
+    fn visit_expr(&mut self, ex: &'tcx hir::Expr<'tcx>) {
+        if local_path_res(ex) == Some(self.local) && ex.span.lo() >= self.after.hi() {
+            self.found = true;
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// If `ex` (ignoring any casts) is a call `base.offset(i)`, return the `offset` call expression
+/// and `base`.
+fn offset_call_parts<'tcx>(
+    ex: &'tcx hir::Expr<'tcx>,
+) -> Option<(&'tcx hir::Expr<'tcx>, &'tcx hir::Expr<'tcx>)> {
+    let mut ex = ex;
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    match ex.kind {
+        ExprKind::MethodCall(seg, args, _) if seg.ident.as_str() == "offset" && args.len() == 2 => {
+            Some((ex, &args[0]))
+        }
+        _ => None,
+    }
+}
+
+/// If `ex` is a path referring to a local variable, return the `HirId` of that local.
+fn local_path_res(ex: &hir::Expr) -> Option<HirId> {
+    match ex.kind {
+        ExprKind::Path(hir::QPath::Resolved(None, path)) => match path.res {
+            Res::Local(hir_id) => Some(hir_id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 fn mutbl_from_bool(m: bool) -> hir::Mutability {
     if m {
         hir::Mutability::Mut
@@ -576,7 +1590,7 @@ fn generate_zeroize_code(zero_ty: &ZeroizeType, lv: &str) -> String {
         ",
             generate_zeroize_code(elem_zero_ty, "(*elem)")
         ),
-        ZeroizeType::Struct(ref fields) => {
+        ZeroizeType::Struct(_, ref fields) => {
             eprintln!("zeroize: {} fields on {lv}: {fields:?}", fields.len());
             let mut s = String::new();
             writeln!(s, "{{").unwrap();
@@ -594,6 +1608,46 @@ fn generate_zeroize_code(zero_ty: &ZeroizeType, lv: &str) -> String {
     }
 }
 
+/// Generate an expression that borrows the pointer named `name` as a slice, for use in bulk
+/// operations like `copy_from_slice`.  `single` indicates that the pointer refers to a single
+/// element rather than a slice, and `option` indicates that it's nullable.  The result is
+/// parenthesized so it can be indexed directly.
+fn slice_access_expr(name: &str, single: bool, option: bool, mutbl: bool) -> String {
+    let base = match (option, mutbl) {
+        (true, true) => format!("{name}.as_deref_mut().unwrap()"),
+        (true, false) => format!("{name}.as_deref().unwrap()"),
+        (false, true) => format!("&mut *{name}"),
+        (false, false) => format!("&*{name}"),
+    };
+    match (single, mutbl) {
+        (true, true) => format!("std::slice::from_mut({base})"),
+        (true, false) => format!("std::slice::from_ref({base})"),
+        (false, _) => format!("({base})"),
+    }
+}
+
+/// Generate an expression that produces a zero value of type `zero_ty`.  This is used to
+/// initialize new allocations.
+pub fn generate_zeroize_expr(zero_ty: &ZeroizeType) -> String {
+    match *zero_ty {
+        ZeroizeType::Int => "0".to_string(),
+        ZeroizeType::Bool => "false".to_string(),
+        ZeroizeType::Iterable(ref elem_zero_ty) => format!(
+            "std::array::from_fn(|_| {})",
+            generate_zeroize_expr(elem_zero_ty)
+        ),
+        ZeroizeType::Struct(ref name, ref fields) => {
+            let mut s = String::new();
+            write!(s, "{name} {{ ").unwrap();
+            for (field_name, field_zero_ty) in fields {
+                write!(s, "{field_name}: {}, ", generate_zeroize_expr(field_zero_ty)).unwrap();
+            }
+            write!(s, "}}").unwrap();
+            s
+        }
+    }
+}
+
 fn take_prefix_while<'a, T>(slice: &mut &'a [T], mut pred: impl FnMut(&'a T) -> bool) -> &'a [T] {
     let i = slice.iter().position(|x| !pred(x)).unwrap_or(slice.len());
     let (a, b) = slice.split_at(i);
@@ -613,6 +1667,20 @@ pub fn convert_cast_rewrite(kind: &mir_op::RewriteKind, hir_rw: Rewrite) -> Rewr
             let elem = Rewrite::Index(Box::new(arr), Box::new(idx));
             Rewrite::Ref(Box::new(elem), mutbl_from_bool(mutbl))
         }
+        mir_op::RewriteKind::SliceAsRawPtr { mutbl } => {
+            // `p` -> `p.as_ptr()` or `p.as_mut_ptr()`
+            let method = if mutbl { "as_mut_ptr" } else { "as_ptr" };
+            Rewrite::MethodCall(method.to_string(), Box::new(hir_rw), vec![])
+        }
+        mir_op::RewriteKind::SliceFromSingle { mutbl } => {
+            // `p` -> `std::slice::from_ref(p)`
+            let func = if mutbl {
+                "std::slice::from_mut"
+            } else {
+                "std::slice::from_ref"
+            };
+            Rewrite::Call(func.to_owned(), vec![hir_rw])
+        }
 
         mir_op::RewriteKind::MutToImm => {
             // `p` -> `&*p`
@@ -633,6 +1701,17 @@ pub fn convert_cast_rewrite(kind: &mir_op::RewriteKind, hir_rw: Rewrite) -> Rewr
             Rewrite::Call("std::option::Option::Some".to_string(), vec![hir_rw])
         }
 
+        mir_op::RewriteKind::OptionUnwrapOrNull { mutbl } => {
+            // `p` -> `p.unwrap_or(std::ptr::null())`
+            let null = if mutbl {
+                "std::ptr::null_mut"
+            } else {
+                "std::ptr::null"
+            };
+            let null = Rewrite::Call(null.to_string(), vec![]);
+            Rewrite::MethodCall("unwrap_or".to_string(), Box::new(hir_rw), vec![null])
+        }
+
         mir_op::RewriteKind::OptionMapBegin => {
             // `p` -> `p.unwrap()`
             Rewrite::MethodCall("unwrap /*map_begin*/".to_string(), Box::new(hir_rw), vec![])
@@ -661,6 +1740,11 @@ pub fn convert_cast_rewrite(kind: &mir_op::RewriteKind, hir_rw: Rewrite) -> Rewr
             Rewrite::MethodCall(ref_method, Box::new(hir_rw), vec![])
         }
 
+        mir_op::RewriteKind::OptionTake => {
+            // `p` -> `p.take()`
+            Rewrite::MethodCall("take".to_string(), Box::new(hir_rw), vec![])
+        }
+
         mir_op::RewriteKind::CastRefToRaw { mutbl } => {
             // `addr_of!(*p)` is cleaner than `p as *const _`; we don't know the pointee
             // type here, so we can't emit `p as *const T`.
@@ -675,16 +1759,83 @@ pub fn convert_cast_rewrite(kind: &mir_op::RewriteKind, hir_rw: Rewrite) -> Rewr
             let rw_pl = Rewrite::Deref(Box::new(hir_rw));
             Rewrite::Ref(Box::new(rw_pl), mutbl_from_bool(mutbl))
         }
+        mir_op::RewriteKind::UnsafeRawAsOptionRef { mutbl } => {
+            // `p` -> `p.as_ref()` or `p.as_mut()`
+            let method = if mutbl { "as_mut" } else { "as_ref" };
+            Rewrite::MethodCall(method.to_string(), Box::new(hir_rw), vec![])
+        }
+        mir_op::RewriteKind::UnsafeCastRawToSlice { mutbl, len } => {
+            // `p` -> `std::slice::from_raw_parts(p, len as usize)`
+            let func = if mutbl {
+                "std::slice::from_raw_parts_mut"
+            } else {
+                "std::slice::from_raw_parts"
+            };
+            let len = Rewrite::Cast(
+                Box::new(Rewrite::Extract(len)),
+                Box::new(Rewrite::Print("usize".to_owned())),
+            );
+            Rewrite::Call(func.to_string(), vec![hir_rw, len])
+        }
+        mir_op::RewriteKind::UnsafeCastRawToSentinelSlice { mutbl, null } => {
+            // `p` -> `{ let ptr = p; std::slice::from_raw_parts(ptr, len) }`, where `len` is
+            // `(0..).position(|i| (*ptr.add(i)).is_null()).unwrap() + 1`
+            let func = if mutbl {
+                "std::slice::from_raw_parts_mut"
+            } else {
+                "std::slice::from_raw_parts"
+            };
+            let is_sentinel = if null {
+                "(*ptr.add(i)).is_null()"
+            } else {
+                "*ptr.add(i) == 0"
+            };
+            let len = format!("(0..).position(|i| {is_sentinel}).unwrap() + 1");
+            Rewrite::Block(
+                vec![Rewrite::Let1("ptr".to_owned(), Box::new(hir_rw))],
+                Some(Box::new(Rewrite::Call(
+                    func.to_string(),
+                    vec![Rewrite::Text("ptr".to_owned()), Rewrite::Text(len)],
+                ))),
+            )
+        }
+        mir_op::RewriteKind::UnsafeCStrFromPtr => {
+            // `p` -> `std::ffi::CStr::from_ptr(p)`
+            Rewrite::Call("std::ffi::CStr::from_ptr".to_string(), vec![hir_rw])
+        }
+        mir_op::RewriteKind::Reborrow { mutbl } => {
+            // `p` -> `&*p` / `&mut *p`
+            let rw_pl = Rewrite::Deref(Box::new(hir_rw));
+            Rewrite::Ref(Box::new(rw_pl), mutbl_from_bool(mutbl))
+        }
 
         mir_op::RewriteKind::CellNew => {
             // `x` to `Cell::new(x)`
             Rewrite::Call("std::cell::Cell::new".to_string(), vec![hir_rw])
         }
 
+        mir_op::RewriteKind::AtomicNew { ref ty } => {
+            // `x` to `AtomicI32::new(x)`
+            Rewrite::Call(format!("{ty}::new"), vec![hir_rw])
+        }
+
         mir_op::RewriteKind::CellFromMut => {
             // `x` to `Cell::from_mut(x)`
             Rewrite::Call("std::cell::Cell::from_mut".to_string(), vec![hir_rw])
         }
+        mir_op::RewriteKind::BoxLeak => {
+            // `p` to `Box::leak(p)`
+            Rewrite::Call("Box::leak".to_string(), vec![hir_rw])
+        }
+        mir_op::RewriteKind::RefCellNew => {
+            // `x` to `RefCell::new(x)`
+            Rewrite::Call("std::cell::RefCell::new".to_string(), vec![hir_rw])
+        }
+        mir_op::RewriteKind::RefCellBorrow { mutbl } => {
+            // `x` to `x.borrow()` or `x.borrow_mut()`
+            let method = if mutbl { "borrow_mut" } else { "borrow" };
+            Rewrite::MethodCall(method.to_string(), Box::new(hir_rw), vec![])
+        }
         mir_op::RewriteKind::AsPtr => {
             // `x` to `x.as_ptr()`
             Rewrite::MethodCall("as_ptr".to_string(), Box::new(hir_rw), vec![])
@@ -699,6 +1850,23 @@ pub fn convert_cast_rewrite(kind: &mir_op::RewriteKind, hir_rw: Rewrite) -> Rewr
                 hir::Mutability::Not,
             )),
         ),
+        mir_op::RewriteKind::DowngradeRefMut => {
+            // The borrow was produced by an earlier rewrite or by an adjustment.  Fold it into
+            // the existing rewrite if possible; otherwise, leave it alone, since `&mut T` coerces
+            // to `&T` anyway.
+            match fold_mut_to_imm(hir_rw) {
+                Ok(rw) => rw,
+                Err(rw) => rw,
+            }
+        }
+
+        mir_op::RewriteKind::CastRawPointee { ref pointee, mutbl } => Rewrite::Cast(
+            Box::new(hir_rw),
+            Box::new(Rewrite::TyPtr(
+                Box::new(Rewrite::Print(pointee.clone())),
+                mutbl_from_bool(mutbl),
+            )),
+        ),
 
         _ => panic!(
             "rewrite {:?} is not supported by convert_cast_rewrite",
@@ -755,6 +1923,7 @@ pub fn convert_rewrites(
         mir_rewrites,
         rewrites: HashMap::new(),
         subsumed_child_rewrites: RefCell::new(HashSet::new()),
+        slice_ptr_cmps: RefCell::new(HashMap::new()),
         materialize_adjustments: false,
     };
     v.visit_body(hir);
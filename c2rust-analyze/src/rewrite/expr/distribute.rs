@@ -5,6 +5,7 @@ use log::*;
 use rustc_hir::HirId;
 use rustc_middle::mir::Location;
 use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap};
 
@@ -82,6 +83,8 @@ impl Ord for Priority {
 #[derive(Clone, Debug)]
 pub struct DistRewrite {
     pub rw: mir_op::RewriteKind,
+    /// The MIR statement or terminator the rewrite came from.
+    pub loc: Location,
     pub desc: MirOriginDesc,
 }
 
@@ -89,11 +92,76 @@ impl From<RewriteInfo> for DistRewrite {
     fn from(x: RewriteInfo) -> DistRewrite {
         DistRewrite {
             rw: x.rw,
+            loc: x.loc.loc,
             desc: x.desc,
         }
     }
 }
 
+/// A MIR rewrite on code generated by a macro, which can't be applied to the source.  See
+/// [`MirOriginDesc::InMacroExpansion`].
+#[derive(Clone, Debug)]
+pub struct MacroRewrite {
+    pub loc: Location,
+    /// The span of the macro call.
+    pub span: Span,
+    pub rw: mir_op::RewriteKind,
+}
+
+/// Sort the rewrites attached to a single `HirId`, returning `true` if any ties had to be broken.
+///
+/// A single `HirId` can have rewrites from multiple different pieces of MIR at the same
+/// `Priority`, for example when an expression is lowered more than once or when it's both an
+/// rvalue and one of that rvalue's operands.  `mir_rewrites` only establishes an ordering between
+/// rewrites on the same `Location`, so these ties are broken as follows:
+///
+/// 1. Rewrites on a deeper `SubLoc` path come first, since they apply to a more deeply nested part
+///    of the MIR, which is evaluated before its parents.
+/// 2. Rewrites on an earlier `Location` come before those on a later one.
+/// 3. Rewrites on the same `Location` keep the order given by `mir_rewrites`.
+///
+/// Repeated copies of the same rewrite at the same `Priority` are dropped, since they come from
+/// the same expression being lowered to several pieces of MIR.  Likewise, a `RefCell` that's
+/// both read and written by one expression, as in `(*p).x += 1`, gets only `borrow_mut()`.
+fn order_rewrites(infos: &mut Vec<RewriteInfo>) -> bool {
+    infos.sort_by(|a, b| {
+        a.priority
+            .cmp(&b.priority)
+            .then_with(|| b.loc.sub.len().cmp(&a.loc.sub.len()))
+            .then_with(|| a.loc.cmp(&b.loc))
+    });
+
+    let len_before = infos.len();
+    let mut kept: Vec<RewriteInfo> = Vec::with_capacity(len_before);
+    for info in infos.drain(..) {
+        let mut same_priority = kept
+            .iter_mut()
+            .rev()
+            .take_while(|k| k.priority == info.priority);
+        let dup = same_priority.any(|k| match (&k.rw, &info.rw) {
+            (
+                &mir_op::RewriteKind::RefCellBorrow { mutbl: m1 },
+                &mir_op::RewriteKind::RefCellBorrow { mutbl: m2 },
+            ) => {
+                k.rw = mir_op::RewriteKind::RefCellBorrow { mutbl: m1 || m2 };
+                true
+            }
+            (rw1, rw2) => rw1 == rw2,
+        });
+        if !dup {
+            kept.push(info);
+        }
+    }
+    *infos = kept;
+
+    let multiple_locs = infos
+        .iter()
+        .group_by(|i| i.priority)
+        .into_iter()
+        .any(|(_, group)| !group.map(|i| &i.loc).all_equal());
+    multiple_locs || infos.len() != len_before
+}
+
 /// Distributes MIR rewrites to HIR nodes.  This takes a list of MIR rewrites (from `mir_op`) and a
 /// map from MIR location to `HirId` (from `unlower`) and produces a map from `HirId` to a list of
 /// MIR rewrites.
@@ -117,13 +185,18 @@ impl From<RewriteInfo> for DistRewrite {
 /// The rewrites for each `HirId` are sorted in [`Priority`] order, matching the order in which the
 /// expression and related parts are evaluated.  For example, the [`Expr`][MirOriginDesc::Expr]
 /// itself is evaluated first, and any [`Adjustment`][MirOriginDesc::Adjustment]s are applied
-/// afterward.
+/// afterward.  Rewrites from different MIR locations at the same `Priority` are ordered by
+/// `SubLoc` depth and then by `Location`.
+///
+/// Rewrites on MIR generated by a macro are returned separately, since they have no place in the
+/// source code.
 pub fn distribute(
     tcx: TyCtxt,
     unlower_map: BTreeMap<PreciseLoc, MirOrigin>,
     mir_rewrites: HashMap<Location, Vec<MirRewrite>>,
-) -> HashMap<HirId, Vec<DistRewrite>> {
+) -> (HashMap<HirId, Vec<DistRewrite>>, Vec<MacroRewrite>) {
     let mut info_map = HashMap::<HirId, Vec<RewriteInfo>>::new();
+    let mut macro_rewrites = Vec::new();
 
     for (loc, mir_rws) in mir_rewrites {
         for mir_rw in mir_rws {
@@ -132,7 +205,14 @@ pub fn distribute(
                 sub: mir_rw.sub_loc,
             };
 
-            let origin = match unlower_map.get(&key) {
+            // Code generated by a macro is recorded only for the whole statement.
+            let origin = unlower_map.get(&key).or_else(|| {
+                let whole = PreciseLoc { loc, sub: vec![] };
+                unlower_map
+                    .get(&whole)
+                    .filter(|x| x.desc == MirOriginDesc::InMacroExpansion)
+            });
+            let origin = match origin {
                 Some(x) => x,
                 None => {
                     error!("unlower_map has no origin for {:?}", key);
@@ -140,6 +220,15 @@ pub fn distribute(
                 }
             };
 
+            if origin.desc == MirOriginDesc::InMacroExpansion {
+                macro_rewrites.push(MacroRewrite {
+                    loc,
+                    span: origin.span.source_callsite(),
+                    rw: mir_rw.kind,
+                });
+                continue;
+            }
+
             let priority = match origin.desc {
                 MirOriginDesc::Expr => Priority::Eval,
                 MirOriginDesc::Adjustment(i) => Priority::Adjust(i),
@@ -167,38 +256,71 @@ pub fn distribute(
         }
     }
 
-    // If a single `HirId` has rewrites from multiple different pieces of MIR at the same
-    // `Priority`, it's ambiguous how to order those rewrites.  (`mir_rewrites` only establishes an
-    // ordering between rewrites on the same `Location`.)  For now, we complain if we see this
-    // ambiguity; in the future, we may need to add rules to resolve it in a particular way, such
-    // as prioritizing one `SubLoc` over another.
     for (&hir_id, infos) in &mut info_map {
-        infos.sort_by_key(|i| i.priority);
-        let all_same_loc = infos
-            .iter()
-            .group_by(|i| i.priority)
-            .into_iter()
-            .all(|(_, group)| group.map(|i| (&i.loc, i.desc)).all_equal());
-        if !all_same_loc {
-            info!("rewrite info:");
-            for i in infos {
-                info!(
+        if order_rewrites(infos) {
+            debug!(
+                "resolved rewrites from multiple locations for {:?}:",
+                tcx.hir().span(hir_id)
+            );
+            for i in infos.iter() {
+                debug!(
                     "  {:?}, {:?}, {:?}: {:?}",
                     i.loc.loc, i.loc.sub, i.desc, i.rw
                 );
             }
-            let ex = tcx.hir().expect_expr(hir_id);
-            error!(
-                "multiple distinct locations produced rewrites for {:?} {:?}",
-                ex.span, ex,
-            );
         }
     }
 
-    // Discard parts of `RewriteInfo` that are only used for the ambiguity check, and return only
-    // the `RewriteKind`s.
-    info_map
+    // Discard the parts of `RewriteInfo` that are only used for ordering, and return only the
+    // `RewriteKind`s.
+    let info_map = info_map
         .into_iter()
         .map(|(k, vs)| (k, vs.into_iter().map(DistRewrite::from).collect()))
-        .collect()
+        .collect();
+    (info_map, macro_rewrites)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rewrite::expr::mir_op::{RewriteKind, SubLoc};
+    use rustc_middle::mir::BasicBlock;
+
+    fn info(stmt: usize, sub: Vec<SubLoc>, rw: RewriteKind) -> RewriteInfo {
+        RewriteInfo {
+            rw,
+            loc: PreciseLoc {
+                loc: Location {
+                    block: BasicBlock::from_usize(0),
+                    statement_index: stmt,
+                },
+                sub,
+            },
+            desc: MirOriginDesc::Expr,
+            priority: Priority::Eval,
+        }
+    }
+
+    /// Ties at the same `Priority` are broken by `SubLoc` depth and then by `Location`, and
+    /// duplicate rewrites are dropped.
+    #[test]
+    fn order_rewrites_ties() {
+        let slice = RewriteKind::OffsetSlice { mutbl: false };
+        let mut infos = vec![
+            info(3, vec![SubLoc::Rvalue], slice.clone()),
+            info(1, vec![SubLoc::Rvalue], RewriteKind::RemoveStmt),
+            info(
+                2,
+                vec![SubLoc::Rvalue, SubLoc::RvalueOperand(0)],
+                slice.clone(),
+            ),
+            info(1, vec![SubLoc::Rvalue], slice.clone()),
+        ];
+        assert!(order_rewrites(&mut infos));
+        let order = infos
+            .iter()
+            .map(|i| (i.loc.loc.statement_index, i.rw.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![(2, slice), (1, RewriteKind::RemoveStmt)]);
+    }
 }
@@ -7,25 +7,42 @@
 //! all adjustments, as this would make even non-rewritten code extremely verbose, so we try to
 //! materialize adjustments only on code that's subject to some rewrite.
 
+use crate::align;
+use crate::array_lens;
 use crate::context::{AnalysisCtxt, Assignment, DontRewriteFnReason, FlagSet, LTy, PermissionSet};
+use crate::dataflow;
+use crate::ffi_escape;
+use crate::fn_tables;
 use crate::panic_detail;
 use crate::pointee_type::PointeeTypes;
 use crate::pointer_id::{PointerId, PointerTable};
+use crate::recent_writes::RecentWrites;
+use crate::rewrite::printf::{self, ArgKind};
 use crate::type_desc::{self, Ownership, Quantity, TypeDesc};
-use crate::util::{self, ty_callee, Callee};
-use log::{error, trace};
+use crate::util::{self, ty_callee, AtomicOp, Callee, UnknownDefCallee};
+use either::Either;
+use log::{error, trace, warn};
 use rustc_ast::Mutability;
+use rustc_middle::mir::interpret::GlobalAlloc;
 use rustc_middle::mir::{
-    BasicBlock, Body, BorrowKind, Location, Operand, Place, PlaceElem, PlaceRef, Rvalue, Statement,
-    StatementKind, Terminator, TerminatorKind,
+    AggregateKind, BasicBlock, BinOp, Body, BorrowKind, CastKind, Constant, Local, LocalKind,
+    Location, Operand, Place, PlaceElem, PlaceRef, Rvalue, Statement, StatementKind, Terminator,
+    TerminatorKind, UnOp, START_BLOCK,
 };
+use rustc_middle::ty::adjustment::PointerCast;
 use rustc_middle::ty::print::FmtPrinter;
 use rustc_middle::ty::print::Print;
 use rustc_middle::ty::{ParamEnv, Ty, TyCtxt, TyKind};
-use std::collections::HashMap;
+use rustc_span::Span;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
 use std::ops::Index;
 
 use rustc_hir::def::Namespace;
+use rustc_hir::def_id::DefId;
+
+/// The definition of a MIR temporary, as found by `ExprRewriteVisitor::temp_def`.
+type TempDef<'a, 'tcx> = Either<&'a Rvalue<'tcx>, (Callee<'tcx>, &'a [Operand<'tcx>])>;
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
 pub enum SubLoc {
@@ -56,19 +73,69 @@ pub enum RewriteKind {
     OffsetSlice { mutbl: bool },
     /// Replace `ptr.offset(i)` with something like `ptr.as_ref().map(|p| &p[i..])`.
     OptionMapOffsetSlice { mutbl: bool },
+    /// Replace `ptr.offset(i)` with `&ptr[i..]`, preceded by an explicit `assert!` that `i` is
+    /// within the length of `ptr`.  Used for `GlobalAnalysisCtxt::dynamic_checks`.
+    CheckedOffsetSlice { mutbl: bool },
+    /// Replace `end.offset_from(start)` with `start.len() as isize - end.len() as isize`, where
+    /// `start` and `end` are slices into the same array.
+    OffsetFromSlice,
+    /// Replace `end as usize - start as usize` with `(start.len() - end.len()) *
+    /// std::mem::size_of::<pointee>()`, where `start` and `end` are slices into the same array.
+    PtrAddrDiffSlice { pointee: String },
+    /// Replace `(end as usize - start as usize) / std::mem::size_of::<T>()` with `start.len() -
+    /// end.len()`, where `start` and `end` are slices of `T` into the same array.
+    PtrAddrDiffSliceElems,
+    /// Replace `(p as usize & (align - 1)) == 0` or `p as usize % align == 0` with
+    /// `p.align_offset(align) == 0`, or with `p.is_aligned()` if `align` is `None`, meaning the
+    /// alignment of `p`'s pointee type.  `ne` is set for `!=` comparisons.
+    IsAligned {
+        align: Option<u64>,
+        ne: bool,
+        ptr: RawPtrOf,
+    },
+    /// Replace `((p as usize + (align - 1)) & !(align - 1)) as *mut T` with
+    /// `&p[p.as_ptr().align_offset(align)..]`, where `p` is a slice.
+    AlignUpSlice { align: u64, mutbl: bool },
+    /// Replace `((p as usize + (align - 1)) & !(align - 1)) as *mut T` with
+    /// `p.wrapping_add(p.align_offset(align))`, where `p` is a raw pointer.
+    AlignUpRaw { align: u64 },
     /// Replace `slice` with `&slice[0]`.
     SliceFirst { mutbl: bool },
+    /// Replace `slice` with `slice.as_ptr()` or `slice.as_mut_ptr()`.  Unlike `SliceFirst`, this
+    /// works on empty slices too.
+    SliceAsRawPtr { mutbl: bool },
+    /// Replace `ptr` with `std::slice::from_ref(ptr)` or `std::slice::from_mut(ptr)`, turning a
+    /// reference to a single element into a one-element slice.
+    SliceFromSingle { mutbl: bool },
     /// Replace `ptr` with `&*ptr`, converting `&mut T` to `&T`.
     MutToImm,
     /// Remove a call to `as_ptr` or `as_mut_ptr`.
     RemoveAsPtr,
     /// Remove a cast, changing `x as T` to just `x`.
     RemoveCast,
+    /// Replace a cast between pointers to different structs, `p as *const B`, with
+    /// `std::mem::transmute::<&A, &B>(p)` in an explicit `unsafe` block.  This is only emitted when
+    /// `A` can be read as `B` and the result is used only for reading.
+    TransmuteRef { from_ty: String, to_ty: String },
     /// Replace &raw with & or &raw mut with &mut
     RawToRef { mutbl: bool },
+    /// Replace `&mut x` with `&x`, for a reference that is never written through.
+    DowngradeRefMut,
 
     /// Replace `ptr.is_null()` with `ptr.is_none()`.
     IsNullToIsNone,
+    /// Replace `!ptr.is_null()` with `ptr.is_some()`.
+    NotIsNullToIsSome,
+    /// Replace `ptr == null` with `ptr.is_none()`, or `ptr != null` with `ptr.is_some()`.
+    /// `null_idx` is the index of the null operand, which is discarded.
+    PtrCmpNullToIsNone { null_idx: usize, ne: bool },
+    /// Replace `ptr == null` or `ptr != null` with a constant.  We use this in cases where the
+    /// rewritten type of `ptr` is non-optional because we inferred `ptr` to be non-nullable.
+    PtrCmpNullToConst { value: bool },
+    /// Marks a comparison between two slice pointers with the same ownership.  This doesn't change
+    /// the comparison itself, but lets `convert` turn a loop of the form `while p != end { ...; p
+    /// = p.offset(1); }` into a `for` loop over `p`.  `mutbl` is set if the slices are mutable.
+    SlicePtrCmp { mutbl: bool },
     /// Replace `ptr.is_null()` with the constant `false`.  We use this in cases where the rewritten
     /// type of `ptr` is non-optional because we inferred `ptr` to be non-nullable.
     IsNullToConstFalse,
@@ -80,11 +147,25 @@ pub enum RewriteKind {
     /// Replace a call to `memcpy(dest, src, n)` with a safe copy operation that works on slices
     /// instead of raw pointers.  `elem_size` is the size of the original, unrewritten pointee
     /// type, which is used to convert the byte length `n` to an element count.  `dest_single` and
-    /// `src_single` are set when `dest`/`src` is a pointer to a single item rather than a slice.
+    /// `src_single` are set when `dest`/`src` is a pointer to a single item rather than a slice,
+    /// and `dest_option` and `src_option` are set when `dest`/`src` is nullable.
     MemcpySafe {
         elem_size: u64,
         dest_single: bool,
+        dest_option: bool,
+        src_single: bool,
+        src_option: bool,
+    },
+    /// Replace a call to `memmove(dest, src, n)` with a safe copy operation.  If `dest` and `src`
+    /// are both offsets into the same slice, this becomes a call to `copy_within`; otherwise, it
+    /// becomes `copy_from_slice`, like `MemcpySafe`.  The fields have the same meaning as in
+    /// `MemcpySafe`.
+    MemmoveSafe {
+        elem_size: u64,
+        dest_single: bool,
+        dest_option: bool,
         src_single: bool,
+        src_option: bool,
     },
     /// Replace a call to `memset(ptr, 0, n)` with a safe zeroize operation.  `elem_size` is the
     /// size of the type being zeroized, which is used to convert the byte length `n` to an element
@@ -94,6 +175,78 @@ pub enum RewriteKind {
         elem_size: u64,
         dest_single: bool,
     },
+    /// Replace a call to `memset(ptr, 0, n)` with a call to `slice.fill(0)`.  This is used instead
+    /// of `MemsetZeroize` when `ptr` is rewritten to a slice and `zero_ty` is a primitive type.
+    /// `elem_size` is used to convert the byte length `n` to an element count, and `dest_option`
+    /// is set when `ptr` is nullable.
+    MemsetFill {
+        zero_ty: ZeroizeType,
+        elem_size: u64,
+        dest_option: bool,
+    },
+    /// Replace a call to `malloc(n)` with a safe `Box::new` operation.  The new allocation will be
+    /// zero-initialized.  `elem_size` is the size of the pointee type, which is used to convert the
+    /// byte length `n` to an element count.  `single` is set when the result is a `Box` of a
+    /// single item rather than a boxed slice.
+    MallocSafe {
+        zero_ty: ZeroizeType,
+        elem_size: u64,
+        single: bool,
+    },
+    /// Replace a call to `calloc(count, size)` with a safe allocation of zero-initialized
+    /// elements, like `MallocSafe`.  `elem_size` is the size of the pointee type, which is used to
+    /// convert the byte length `count * size` to an element count.  `single` is set when the
+    /// result is a `Box` of a single item rather than a boxed slice.
+    CallocSafe {
+        zero_ty: ZeroizeType,
+        elem_size: u64,
+        single: bool,
+    },
+    /// Replace a call to `realloc(p, n)` with a safe resize operation that goes through `Vec`.
+    /// Newly added elements are zero-initialized.  `elem_size` is the size of the pointee type,
+    /// which is used to convert the byte length `n` to an element count.  `src_single` and
+    /// `dest_single` are set when the input/output is a `Box` of a single item rather than a
+    /// boxed slice, and `src_option` is set when the input is nullable.
+    ReallocSafe {
+        zero_ty: ZeroizeType,
+        elem_size: u64,
+        src_single: bool,
+        dest_single: bool,
+        src_option: bool,
+    },
+    /// Replace a call to `free(p)` with a safe `drop(p)` operation.  If `take` is set, the
+    /// pointer is moved out with `p.take()` instead, leaving `None` in its place.
+    FreeSafe { take: bool },
+    /// Remove the statement containing this expression entirely.  This is used for `free(NULL)`,
+    /// and for `p = NULL` right after `free(p)` when the free already leaves `None` in `p`.
+    RemoveStmt,
+
+    /// Replace a call to a C string function, such as `strlen(s)`, with equivalent safe code that
+    /// operates on NUL-terminated slices.  `ret_ty` is the printed return type of the original
+    /// function, which is used to cast integer results back to the C type.  `cstr_args[i]` is set
+    /// if the `i`th argument is a `&CStr` rather than a slice.
+    CStrFnSafe {
+        func: CStrFn,
+        ret_ty: String,
+        cstr_args: [bool; 2],
+    },
+    /// Replace a call to `sprintf(dest, fmt, ...)`, or `snprintf(dest, n, fmt, ...)` if `bounded`
+    /// is set, with a `format!` whose result is copied into the `dest` slice along with a NUL
+    /// terminator.  `fmt` is the translated Rust format string, and `args` gives the conversion
+    /// of each argument after the format string.  `ret_ty` is the printed return type of the
+    /// original function, which is used to cast the length of the formatted string.
+    SprintfSafe {
+        fmt: String,
+        args: Vec<ArgKind>,
+        bounded: bool,
+        ret_ty: String,
+    },
+
+    /// Replace a byte string literal cast to a C string pointer, such as `b"hi\0" as *const u8 as
+    /// *const c_char`, with a safe slice of the literal's bytes.  If `signed` is set, the element
+    /// type of the result is `i8` rather than `u8`.  If `cstr` is set, the result is a `&CStr`
+    /// instead.
+    CStrLiteral { signed: bool, cstr: bool },
 
     /// Convert `Option<T>` to `T` by calling `.unwrap()`.
     OptionUnwrap,
@@ -112,6 +265,11 @@ pub enum RewriteKind {
     /// Downgrade ownership of an `Option` to `Option<&_>` or `Option<&mut _>` by calling
     /// `as_ref()`/`as_mut()` and optionally `as_deref()`/`as_deref_mut()`.
     OptionDowngrade { mutbl: bool, deref: bool },
+    /// Move an owned `Option` out of a place that can't be moved from, such as a struct field, by
+    /// calling `take()`.  This leaves `None` in the original place.
+    OptionTake,
+    /// Convert `Option<*const T>` or `Option<*mut T>` to a raw pointer that's null for `None`.
+    OptionUnwrapOrNull { mutbl: bool },
 
     /// Cast `&T` to `*const T` or `&mut T` to `*mut T`.
     CastRefToRaw { mutbl: bool },
@@ -120,8 +278,28 @@ pub enum RewriteKind {
     CastRawToRaw { to_mutbl: bool },
     /// Cast `*const T` to `& T` or `*mut T` to `&mut T`.
     UnsafeCastRawToRef { mutbl: bool },
+    /// Convert `*const T` to `Option<&T>` or `*mut T` to `Option<&mut T>` with `as_ref()` or
+    /// `as_mut()`, producing `None` for a null pointer.
+    UnsafeRawAsOptionRef { mutbl: bool },
+    /// Convert `*const T` to `&[T]` or `*mut T` to `&mut [T]` with `slice::from_raw_parts`, using
+    /// the expression at `len` as the number of elements.
+    UnsafeCastRawToSlice { mutbl: bool, len: Span },
+    /// Convert `*const T` to `&[T]` or `*mut T` to `&mut [T]` with `slice::from_raw_parts`,
+    /// where the array ends with a null pointer (if `null`) or a zero.  The slice includes the
+    /// sentinel.
+    UnsafeCastRawToSentinelSlice { mutbl: bool, null: bool },
+    /// Cast `*const c_char` to `&CStr` with `std::ffi::CStr::from_ptr(p)`.
+    UnsafeCStrFromPtr,
+    /// Borrow `Box<T>` as `&T` or `&mut T`, producing `&*p` or `&mut *p`.
+    Reborrow { mutbl: bool },
+    /// Convert `Box<T>` to `&'static mut T` with `Box::leak(p)`.
+    BoxLeak,
     /// Cast *mut T to *const Cell<T>
     CastRawMutToCellPtr { ty: String },
+    /// Cast a raw pointer to a different pointee type, producing `p as *const U` or `p as *mut U`
+    /// where `U` is `pointee`.  This converts between `*mut c_void` and `*mut T` when accessing a
+    /// `void*` field that was given a type parameter.
+    CastRawPointee { pointee: String, mutbl: bool },
 
     /// Replace `y` in `let x = y` with `Cell::new(y)`, i.e. `let x = Cell::new(y)`
     /// TODO: ensure `y` implements `Copy`
@@ -132,8 +310,90 @@ pub enum RewriteKind {
     CellSet,
     /// Wrap `&mut T` in `Cell::from_mut` to get `&Cell<T>`.
     CellFromMut,
+    /// Replace `y` in `x = y` with `RefCell::new(y)`, where `x` is a `RefCell` local.
+    RefCellNew,
+    /// Borrow the contents of a `RefCell`, producing `x.borrow()` or `x.borrow_mut()`.  This is
+    /// applied to a `&RefCell<T>` pointer before it's dereferenced, and to a `RefCell` local before
+    /// one of its fields is accessed.
+    RefCellBorrow { mutbl: bool },
     /// `x` to `x.as_ptr()`
     AsPtr,
+
+    /// Replace `y` in `x = y` with `AtomicI32::new(y)` or similar, where `x` is rewritten to an
+    /// atomic type.  `ty` is the path of the atomic type.
+    AtomicNew { ty: String },
+    /// Replace `*y` with `y.load(Ordering::SeqCst)`, where `y` is a pointer to an atomic type
+    AtomicLoad,
+    /// Replace `*y = x` with `y.store(x, Ordering::SeqCst)`, where `y` is a pointer to an atomic
+    /// type
+    AtomicStore,
+    /// Replace a call to an atomic intrinsic, such as `atomic_xadd_seqcst(p, x)`, with a call to
+    /// the equivalent method of the atomic type, as in `p.fetch_add(x, Ordering::SeqCst)`.  The
+    /// `orderings` are passed after the other arguments.  If `cxchg` is set, the `Result` of
+    /// `compare_exchange` is converted back to the `(T, bool)` returned by the intrinsic.
+    AtomicMethod {
+        method: &'static str,
+        orderings: Vec<&'static str>,
+        cxchg: bool,
+    },
+}
+
+impl RewriteKind {
+    /// Check whether this rewrite introduces an operation that requires `unsafe`, such as a call
+    /// to `slice::from_raw_parts`.  Rewrites that wrap their unsafe operation in their own
+    /// `unsafe` block, like `TransmuteRef`, don't count.
+    pub fn is_unsafe(&self) -> bool {
+        matches!(
+            *self,
+            RewriteKind::UnsafeCastRawToRef { .. }
+                | RewriteKind::UnsafeRawAsOptionRef { .. }
+                | RewriteKind::UnsafeCastRawToSlice { .. }
+                | RewriteKind::UnsafeCastRawToSentinelSlice { .. }
+                | RewriteKind::UnsafeCStrFromPtr
+        )
+    }
+}
+
+/// C string functions that can be rewritten by `RewriteKind::CStrFnSafe`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CStrFn {
+    /// `strlen(s)`
+    Strlen,
+    /// `strcmp(s1, s2)`
+    Strcmp,
+    /// `strcpy(dest, src)`
+    Strcpy,
+    /// `strcat(dest, src)`
+    Strcat,
+}
+
+impl CStrFn {
+    fn from_name(name: &str) -> Option<CStrFn> {
+        Some(match name {
+            "strlen" => CStrFn::Strlen,
+            "strcmp" => CStrFn::Strcmp,
+            "strcpy" => CStrFn::Strcpy,
+            "strcat" => CStrFn::Strcat,
+            _ => return None,
+        })
+    }
+
+    /// Returns `true` if the first argument is written by this function.
+    fn writes_dest(self) -> bool {
+        matches!(self, CStrFn::Strcpy | CStrFn::Strcat)
+    }
+}
+
+/// How to get a raw pointer from the rewritten form of a pointer, in order to call raw pointer
+/// methods like `align_offset` on it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RawPtrOf {
+    /// The pointer is still a raw pointer: `p`.
+    Raw,
+    /// The pointer is a slice: `p.as_ptr()`.
+    Slice,
+    /// The pointer is a reference to a single element: `std::ptr::from_ref(p)`.
+    Ref,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -144,8 +404,9 @@ pub enum ZeroizeType {
     Bool,
     /// Iterate over `x.iter_mut()` and zeroize each element.
     Iterable(Box<ZeroizeType>),
-    /// Zeroize each named field.
-    Struct(Vec<(String, ZeroizeType)>),
+    /// Zeroize each named field.  The first element is the name of the struct type, which is
+    /// needed when building a zero-initialized value from scratch.
+    Struct(String, Vec<(String, ZeroizeType)>),
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -154,6 +415,19 @@ pub struct MirRewrite {
     pub sub_loc: Vec<SubLoc>,
 }
 
+/// A cast between two pointer types that couldn't be built, usually because the permissions
+/// inferred for the two sides conflict.  Rather than failing the whole function, the rewriter
+/// leaves out the cast, and the caller marks `ptrs` as `FIXED` so they stay raw pointers.
+#[derive(Clone, Debug)]
+pub struct UnresolvedCast {
+    /// The span of the statement or terminator containing the cast.
+    pub span: Span,
+    /// The pointers on either side of the cast that aren't `FIXED` yet.
+    pub ptrs: Vec<PointerId>,
+    /// Why the cast couldn't be built.
+    pub reason: String,
+}
+
 struct ExprRewriteVisitor<'a, 'tcx> {
     acx: &'a AnalysisCtxt<'a, 'tcx>,
     perms: PointerTable<'a, PermissionSet>,
@@ -161,9 +435,23 @@ struct ExprRewriteVisitor<'a, 'tcx> {
     pointee_types: PointerTable<'a, PointeeTypes<'tcx>>,
     rewrites: &'a mut HashMap<Location, Vec<MirRewrite>>,
     mir: &'a Body<'tcx>,
+    recent_writes: &'a RecentWrites,
     loc: Location,
     sub_loc: Vec<SubLoc>,
     errors: DontRewriteFnReason,
+    unresolved: Vec<UnresolvedCast>,
+    /// Whether the current function has a type parameter `T` for the generic `void*` fields of
+    /// the ADTs in its signature.
+    in_generic_fn: bool,
+    /// Whether the operand being visited is passed to a sentinel-terminated parameter.  See
+    /// `crate::sentinel`.
+    in_sentinel_arg: bool,
+    /// Whether the operand or result being visited crosses the boundary of a call to a foreign
+    /// function.  See `crate::ffi_escape`.
+    in_ffi_call: bool,
+    /// Casts of array references to slices whose results are rewritten to keep the array type.
+    /// See `crate::array_lens`.
+    array_unsizes: HashSet<Location>,
 }
 
 impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
@@ -173,9 +461,14 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
         pointee_types: PointerTable<'a, PointeeTypes<'tcx>>,
         rewrites: &'a mut HashMap<Location, Vec<MirRewrite>>,
         mir: &'a Body<'tcx>,
+        recent_writes: &'a RecentWrites,
     ) -> ExprRewriteVisitor<'a, 'tcx> {
         let perms = asn.perms();
         let flags = asn.flags();
+        let array_unsizes = array_lens::find_array_ptrs(acx, &perms, &flags, &pointee_types, mir)
+            .values()
+            .map(|array_ptr| array_ptr.unsize_loc)
+            .collect();
         ExprRewriteVisitor {
             acx,
             perms,
@@ -183,12 +476,18 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
             pointee_types,
             rewrites,
             mir,
+            recent_writes,
             loc: Location {
                 block: BasicBlock::from_usize(0),
                 statement_index: 0,
             },
             sub_loc: Vec::new(),
             errors: DontRewriteFnReason::empty(),
+            unresolved: Vec::new(),
+            in_generic_fn: acx.gacx.fn_needs_type_param(mir.source.def_id()),
+            in_sentinel_arg: false,
+            in_ffi_call: false,
+            array_unsizes,
         }
     }
 
@@ -266,6 +565,30 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
             && !self.flags[ptr].contains(FlagSet::FIXED)
     }
 
+    /// Check whether using the pointer stored in `pl` as a value of type `to_lty` moves ownership
+    /// out of `pl`.  This applies when `pl` holds an owned, nullable pointer like
+    /// `Option<Box<T>>`, and `to_lty` is also owned.  Moving out of a local is fine, but other
+    /// places, such as struct fields, can only be moved from by calling `take()`.
+    fn needs_option_take(&self, pl: Place<'tcx>, from_lty: LTy<'tcx>, to_lty: LTy<'tcx>) -> bool {
+        if pl.projection.is_empty() || !self.is_nullable(from_lty.label) {
+            return false;
+        }
+        if to_lty.label.is_none() || self.flags[to_lty.label].contains(FlagSet::FIXED) {
+            return false;
+        }
+        let from_desc = type_desc::perms_to_desc(
+            from_lty.ty,
+            self.perms[from_lty.label],
+            self.flags[from_lty.label],
+        );
+        let to_desc = type_desc::perms_to_desc(
+            to_lty.ty,
+            self.perms[to_lty.label],
+            self.flags[to_lty.label],
+        );
+        !from_desc.own.is_copy() && !to_desc.own.is_copy()
+    }
+
     fn visit_statement(&mut self, stmt: &Statement<'tcx>, loc: Location) {
         let _g = panic_detail::set_current_span(stmt.source_info.span);
         eprintln!(
@@ -299,10 +622,24 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                             }
                             // this is an assignment like `*x = 2` but `x` has CELL permissions
                             self.emit(RewriteKind::CellSet);
+                        } else if desc.own == Ownership::Atomic {
+                            if pl.projection.len() > 1 || desc.qty != Quantity::Single {
+                                // NYI: atomics inside structs, arrays, or ptr-to-ptr
+                                self.err(DontRewriteFnReason::COMPLEX_CELL);
+                            }
+                            self.emit(RewriteKind::AtomicStore);
                         }
                     }
                 }
 
+                if pl.projection.is_empty()
+                    && matches!(rv, Rvalue::Use(..) | Rvalue::Aggregate(..))
+                    && self.local_is_ref_cell(pl.local)
+                {
+                    // this is an assignment like `let x = S { .. }` but `x` is a `RefCell`
+                    self.enter_rvalue(|v| v.emit(RewriteKind::RefCellNew))
+                }
+
                 #[allow(clippy::single_match)]
                 match rv {
                     Rvalue::Use(rv_op) => {
@@ -318,6 +655,13 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                                 self.err(DontRewriteFnReason::COMPLEX_CELL);
                             }
                             self.enter_rvalue(|v| v.emit(RewriteKind::CellNew))
+                        } else if desc.own == Ownership::Atomic {
+                            if !pl.projection.is_empty() || desc.qty != Quantity::Single {
+                                // NYI: atomics inside structs, arrays, or ptr-to-ptr
+                                self.err(DontRewriteFnReason::COMPLEX_CELL);
+                            }
+                            let ty = type_desc::atomic_ty_path(local_ty).unwrap();
+                            self.enter_rvalue(|v| v.emit(RewriteKind::AtomicNew { ty }))
                         }
 
                         if let Some(rv_place) = rv_op.place() {
@@ -327,14 +671,34 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                                 let local_lty = self.acx.local_tys[rv_place.local];
                                 let local_ptr = local_lty.label;
                                 let flags = self.flags[local_ptr];
-                                if !flags.contains(FlagSet::FIXED) && flags.contains(FlagSet::CELL)
+                                if !flags.contains(FlagSet::FIXED)
+                                    && flags.intersects(FlagSet::CELL | FlagSet::ATOMIC)
                                 {
                                     // this is an assignment like `let x = *y` but `y` has CELL permissions
-                                    if pl.projection.len() > 1 || desc.qty != Quantity::Single {
-                                        // NYI: `Cell` inside structs, arrays, or ptr-to-ptr
-                                        self.err(DontRewriteFnReason::COMPLEX_CELL);
+                                    let ptr_desc = type_desc::perms_to_desc(
+                                        local_lty.ty,
+                                        self.perms[local_ptr],
+                                        flags,
+                                    );
+                                    // A `RefCell` pointer is borrowed in `visit_place_ref`
+                                    // instead.
+                                    if ptr_desc.own == Ownership::Cell {
+                                        if rv_place.projection.len() > 1
+                                            || ptr_desc.qty != Quantity::Single
+                                        {
+                                            // NYI: `Cell` inside structs, arrays, or ptr-to-ptr
+                                            self.err(DontRewriteFnReason::COMPLEX_CELL);
+                                        }
+                                        self.enter_rvalue(|v| v.emit(RewriteKind::CellGet))
+                                    } else if ptr_desc.own == Ownership::Atomic {
+                                        if rv_place.projection.len() > 1
+                                            || ptr_desc.qty != Quantity::Single
+                                        {
+                                            // NYI: atomics inside structs, arrays, or ptr-to-ptr
+                                            self.err(DontRewriteFnReason::COMPLEX_CELL);
+                                        }
+                                        self.enter_rvalue(|v| v.emit(RewriteKind::AtomicLoad))
                                     }
-                                    self.enter_rvalue(|v| v.emit(RewriteKind::CellGet))
                                 }
                             }
                         }
@@ -345,7 +709,13 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                 let rv_lty = self.acx.type_of_rvalue(rv, loc);
                 self.enter_rvalue(|v| v.visit_rvalue(rv, Some(rv_lty)));
                 // The cast from `rv_lty` to `pl_lty` should be applied to the RHS.
-                self.enter_rvalue(|v| v.emit_cast_lty_lty(rv_lty, pl_lty));
+                match self.stored_slice_len(pl, stmt.source_info.span) {
+                    Some(len) => {
+                        self.enter_rvalue(|v| v.emit_cast_lty_lty_len(rv_lty, pl_lty, len))
+                    }
+                    None => self.enter_rvalue(|v| v.emit_cast_lty_lty(rv_lty, pl_lty)),
+                }
+                self.visit_generic_void_field_access(pl, rv);
                 self.enter_dest(|v| v.visit_place(pl, true));
             }
             StatementKind::FakeRead(..) => {}
@@ -380,7 +750,7 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                 ref func,
                 ref args,
                 destination,
-                target: _,
+                target,
                 ..
             } => {
                 let func_ty = func.ty(self.mir, tcx);
@@ -389,29 +759,77 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                 // Special cases for particular functions.
                 match ty_callee(tcx, func_ty) {
                     Callee::PtrOffset { .. } => {
-                        self.visit_ptr_offset(&args[0], pl_ty);
+                        self.visit_ptr_offset(&args[0], &args[1], pl_ty);
+                    }
+                    Callee::PtrOffsetFrom => {
+                        self.visit_ptr_offset_from(args);
                     }
                     Callee::SliceAsPtr { elem_ty, .. } => {
                         self.visit_slice_as_ptr(elem_ty, &args[0], pl_ty);
                     }
 
-                    Callee::LocalDef { def_id, substs: _ } => {
+                    // Functions from other crates of the workspace have an `LFnSig` only if
+                    // facts were recorded for them.  See `crate_facts`.
+                    Callee::LocalDef { def_id, substs: _ }
+                    | Callee::UnknownDef(UnknownDefCallee::Direct {
+                        def_id,
+                        is_foreign: false,
+                        ..
+                    }) => {
                         // TODO: handle substs (if nonempty)
                         if let Some(lsig) = self.acx.gacx.fn_sigs.get(&def_id) {
                             self.enter_rvalue(|v| {
                                 for (i, op) in args.iter().enumerate() {
                                     if let Some(&lty) = lsig.inputs.get(i) {
+                                        v.in_sentinel_arg =
+                                            v.acx.gacx.sentinel_params.contains(&(def_id, i));
                                         v.enter_call_arg(i, |v| v.visit_operand(op, Some(lty)));
+                                        v.in_sentinel_arg = false;
                                     } else {
                                         // This is a call to a variadic function, and we've gone
-                                        // past the end of the declared arguments.
-                                        // TODO: insert a cast to turn `op` back into its original
-                                        // declared type (i.e. upcast the chosen reference type
-                                        // back to a raw pointer)
-                                        continue;
+                                        // past the end of the declared arguments.  The callee
+                                        // reads these with `VaListImpl::arg` at their original
+                                        // raw pointer types.
+                                        v.enter_call_arg(i, |v| v.visit_variadic_arg(op));
                                     }
                                 }
 
+                                // Let the registration fn's new type parameter be inferred
+                                // from the user-data argument.
+                                if let Some(reg) =
+                                    v.acx.gacx.user_data.registration_fns.get(&def_id)
+                                {
+                                    let mutbl = reg.mutbl;
+                                    v.enter_call_arg(reg.data_arg, |v| {
+                                        v.emit(RewriteKind::CastRawPointee {
+                                            pointee: "_".into(),
+                                            mutbl,
+                                        })
+                                    });
+                                }
+
+                                if !pl_ty.label.is_none() {
+                                    v.emit_cast_lty_lty(lsig.output, pl_ty);
+                                }
+                            });
+                        }
+                    }
+
+                    // A call through a function table field.  The signatures of the field's
+                    // targets are all unified with the field's type, so any of them describes
+                    // the call.
+                    Callee::UnknownDef(UnknownDefCallee::Indirect { .. }) => {
+                        let acx = self.acx;
+                        let targets =
+                            fn_tables::indirect_call_targets(acx.gacx, self.mir, loc, func);
+                        if let Some(&did) = targets.and_then(|targets| targets.first()) {
+                            let lsig = acx.gacx.fn_sigs[&did];
+                            self.enter_rvalue(|v| {
+                                for (i, op) in args.iter().enumerate() {
+                                    if let Some(&lty) = lsig.inputs.get(i) {
+                                        v.enter_call_arg(i, |v| v.visit_operand(op, Some(lty)));
+                                    }
+                                }
                                 if !pl_ty.label.is_none() {
                                     v.emit_cast_lty_lty(lsig.output, pl_ty);
                                 }
@@ -419,14 +837,14 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                         }
                     }
 
-                    Callee::Memcpy => {
+                    callee @ (Callee::Memcpy | Callee::Memmove) => {
                         self.enter_rvalue(|v| {
-                            // TODO: Only emit `MemcpySafe` if the rewritten argument types and
+                            // We only emit `MemcpySafe` if the rewritten argument types and
                             // pointees are suitable.  Specifically, the `src` and `dest` arguments
                             // must both be rewritten to safe references, their pointee types must
-                            // be the same, and the pointee type must implement `Copy`.  If these
-                            // conditions don't hold, leave the `memcpy` call intact and emit casts
-                            // back to `void*` on the `dest` and `src` arguments.
+                            // be the same and implement `Copy`, and `dest` must be writable.
+                            // TODO: If these conditions don't hold, leave the `memcpy` call intact
+                            // and emit casts back to `void*` on the `dest` and `src` arguments.
                             let dest_lty = v.acx.type_of(&args[0]);
                             let dest_pointee = v.pointee_lty(dest_lty);
                             let src_lty = v.acx.type_of(&args[1]);
@@ -437,21 +855,74 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                                 // TODO: emit void* casts before bailing out, as described above
                                 None => return,
                             };
+                            if dest_lty.label.is_none()
+                                || src_lty.label.is_none()
+                                || v.flags[dest_lty.label].contains(FlagSet::FIXED)
+                                || v.flags[src_lty.label].contains(FlagSet::FIXED)
+                            {
+                                return;
+                            }
 
                             let orig_pointee_ty = pointee_lty.ty;
+                            // `copy_from_slice` and `copy_within` require `T: Copy`.
+                            if !orig_pointee_ty.is_copy_modulo_regions(
+                                tcx.at(term.source_info.span),
+                                ParamEnv::reveal_all(),
+                            ) {
+                                return;
+                            }
+                            let dest_desc = type_desc::perms_to_desc_with_pointee(
+                                tcx,
+                                orig_pointee_ty,
+                                dest_lty.ty,
+                                v.perms[dest_lty.label],
+                                v.flags[dest_lty.label],
+                            );
+                            let src_desc = type_desc::perms_to_desc_with_pointee(
+                                tcx,
+                                orig_pointee_ty,
+                                src_lty.ty,
+                                v.perms[src_lty.label],
+                                v.flags[src_lty.label],
+                            );
+                            if !matches!(dest_desc.own, Ownership::Mut | Ownership::Box) {
+                                return;
+                            }
+                            if !matches!(
+                                src_desc.own,
+                                Ownership::Imm | Ownership::Mut | Ownership::Box
+                            ) {
+                                return;
+                            }
+
                             let ty_layout = tcx
                                 .layout_of(ParamEnv::reveal_all().and(orig_pointee_ty))
                                 .unwrap();
                             let elem_size = ty_layout.layout.size().bytes();
-                            let dest_single = !v.perms[dest_lty.label]
-                                .intersects(PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB);
-                            let src_single = !v.perms[src_lty.label]
-                                .intersects(PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB);
-                            v.emit(RewriteKind::MemcpySafe {
-                                elem_size,
-                                src_single,
-                                dest_single,
-                            });
+                            // The byte length can't be converted to an element count for
+                            // zero-sized types.
+                            if elem_size == 0 {
+                                return;
+                            }
+                            let dest_single = dest_desc.qty == Quantity::Single;
+                            let src_single = src_desc.qty == Quantity::Single;
+                            if let Callee::Memmove = callee {
+                                v.emit(RewriteKind::MemmoveSafe {
+                                    elem_size,
+                                    dest_single,
+                                    dest_option: dest_desc.option,
+                                    src_single,
+                                    src_option: src_desc.option,
+                                });
+                            } else {
+                                v.emit(RewriteKind::MemcpySafe {
+                                    elem_size,
+                                    dest_single,
+                                    dest_option: dest_desc.option,
+                                    src_single,
+                                    src_option: src_desc.option,
+                                });
+                            }
 
                             if !pl_ty.label.is_none()
                                 && v.perms[pl_ty.label].intersects(PermissionSet::USED)
@@ -482,6 +953,9 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                                 .layout_of(ParamEnv::reveal_all().and(orig_pointee_ty))
                                 .unwrap();
                             let elem_size = ty_layout.layout.size().bytes();
+                            if elem_size == 0 {
+                                return;
+                            }
                             let dest_single = !v.perms[dest_lty.label]
                                 .intersects(PermissionSet::OFFSET_ADD | PermissionSet::OFFSET_SUB);
 
@@ -493,11 +967,36 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                                 None => return,
                             };
 
-                            v.emit(RewriteKind::MemsetZeroize {
-                                zero_ty,
-                                elem_size,
-                                dest_single,
+                            let dest_desc = (!dest_lty.label.is_none()
+                                && !v.flags[dest_lty.label].contains(FlagSet::FIXED))
+                            .then(|| {
+                                type_desc::perms_to_desc_with_pointee(
+                                    tcx,
+                                    orig_pointee_ty,
+                                    dest_lty.ty,
+                                    v.perms[dest_lty.label],
+                                    v.flags[dest_lty.label],
+                                )
                             });
+                            let can_fill = !dest_single
+                                && matches!(zero_ty, ZeroizeType::Int | ZeroizeType::Bool)
+                                && dest_desc.map_or(false, |desc| {
+                                    matches!(desc.own, Ownership::Mut | Ownership::Box)
+                                });
+
+                            if can_fill {
+                                v.emit(RewriteKind::MemsetFill {
+                                    zero_ty,
+                                    elem_size,
+                                    dest_option: dest_desc.map_or(false, |desc| desc.option),
+                                });
+                            } else {
+                                v.emit(RewriteKind::MemsetZeroize {
+                                    zero_ty,
+                                    elem_size,
+                                    dest_single,
+                                });
+                            }
 
                             if !pl_ty.label.is_none()
                                 && v.perms[pl_ty.label].intersects(PermissionSet::USED)
@@ -508,6 +1007,225 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                         });
                     }
 
+                    callee @ (Callee::Malloc | Callee::Calloc) => {
+                        self.enter_rvalue(|v| {
+                            if v.flags[pl_ty.label].contains(FlagSet::FIXED) {
+                                return;
+                            }
+                            let pointee_lty = match v.pointee_lty(pl_ty) {
+                                Some(x) => x,
+                                None => return,
+                            };
+                            let orig_pointee_ty = pointee_lty.ty;
+                            let dest_desc = type_desc::perms_to_desc_with_pointee(
+                                tcx,
+                                orig_pointee_ty,
+                                pl_ty.ty,
+                                v.perms[pl_ty.label],
+                                v.flags[pl_ty.label],
+                            );
+                            // Only allocations that are eventually freed (and are uniquely owned
+                            // until then) become `Box`es.  An allocation that's never freed becomes
+                            // a leaked `Box`, which can be borrowed for `'static`, as long as it's
+                            // only accessed through references.  Other allocations are left as
+                            // they are.
+                            // TODO: emit void* cast on the result in the non-`Box` case
+                            let leak = !v.perms[pl_ty.label].contains(PermissionSet::FREE)
+                                && matches!(dest_desc.own, Ownership::Imm | Ownership::Mut);
+                            if dest_desc.own != Ownership::Box && !leak {
+                                return;
+                            }
+
+                            let ty_layout = match tcx
+                                .layout_of(ParamEnv::reveal_all().and(orig_pointee_ty))
+                            {
+                                Ok(x) => x,
+                                Err(_) => return,
+                            };
+                            let elem_size = ty_layout.layout.size().bytes();
+                            if elem_size == 0 {
+                                return;
+                            }
+                            let single = dest_desc.qty == Quantity::Single;
+
+                            let zero_ty = match ZeroizeType::from_ty(tcx, orig_pointee_ty) {
+                                Some(x) => x,
+                                None => return,
+                            };
+                            if let Callee::Calloc = callee {
+                                v.emit(RewriteKind::CallocSafe {
+                                    zero_ty,
+                                    elem_size,
+                                    single,
+                                });
+                            } else {
+                                v.emit(RewriteKind::MallocSafe {
+                                    zero_ty,
+                                    elem_size,
+                                    single,
+                                });
+                            }
+
+                            // `MallocSafe` and `CallocSafe` produce a non-optional `Box<T>` or `Box<[T]>`.  Cast
+                            // that to the rewritten type of the destination.
+                            let mut malloc_desc = TypeDesc {
+                                own: Ownership::Box,
+                                qty: if single {
+                                    Quantity::Single
+                                } else {
+                                    Quantity::Slice
+                                },
+                                option: false,
+                                pointee_ty: orig_pointee_ty,
+                            };
+                            if leak {
+                                v.emit(RewriteKind::BoxLeak);
+                                malloc_desc.own = Ownership::Mut;
+                            }
+                            v.emit_cast_desc_desc(malloc_desc, dest_desc);
+                        });
+                    }
+
+                    Callee::Realloc => {
+                        self.enter_rvalue(|v| {
+                            let src_lty = v.acx.type_of(&args[0]);
+                            if src_lty.label.is_none()
+                                || v.flags[src_lty.label].contains(FlagSet::FIXED)
+                                || v.flags[pl_ty.label].contains(FlagSet::FIXED)
+                            {
+                                return;
+                            }
+                            let src_pointee = v.pointee_lty(src_lty);
+                            let dest_pointee = v.pointee_lty(pl_ty);
+                            let common_pointee = dest_pointee.filter(|&x| Some(x) == src_pointee);
+                            let pointee_lty = match common_pointee {
+                                Some(x) => x,
+                                // TODO: emit void* casts before bailing out
+                                None => return,
+                            };
+                            let orig_pointee_ty = pointee_lty.ty;
+
+                            let src_desc = type_desc::perms_to_desc_with_pointee(
+                                tcx,
+                                orig_pointee_ty,
+                                src_lty.ty,
+                                v.perms[src_lty.label],
+                                v.flags[src_lty.label],
+                            );
+                            let dest_desc = type_desc::perms_to_desc_with_pointee(
+                                tcx,
+                                orig_pointee_ty,
+                                pl_ty.ty,
+                                v.perms[pl_ty.label],
+                                v.flags[pl_ty.label],
+                            );
+                            // Like `malloc`, we can only rewrite `realloc` calls that operate on
+                            // owned allocations.
+                            if src_desc.own != Ownership::Box || dest_desc.own != Ownership::Box {
+                                return;
+                            }
+
+                            let ty_layout = match tcx
+                                .layout_of(ParamEnv::reveal_all().and(orig_pointee_ty))
+                            {
+                                Ok(x) => x,
+                                Err(_) => return,
+                            };
+                            let elem_size = ty_layout.layout.size().bytes();
+                            if elem_size == 0 {
+                                return;
+                            }
+                            let zero_ty = match ZeroizeType::from_ty(tcx, orig_pointee_ty) {
+                                Some(x) => x,
+                                None => return,
+                            };
+                            let src_single = src_desc.qty == Quantity::Single;
+                            let dest_single = dest_desc.qty == Quantity::Single;
+
+                            v.emit(RewriteKind::ReallocSafe {
+                                zero_ty,
+                                elem_size,
+                                src_single,
+                                dest_single,
+                                src_option: src_desc.option,
+                            });
+
+                            let realloc_desc = TypeDesc {
+                                own: Ownership::Box,
+                                qty: dest_desc.qty,
+                                option: false,
+                                pointee_ty: orig_pointee_ty,
+                            };
+                            v.emit_cast_desc_desc(realloc_desc, dest_desc);
+                        });
+                    }
+
+                    Callee::Free => {
+                        // `free(NULL)` does nothing, so it can be removed regardless of types.
+                        if self.is_null_through_casts(self.loc, &args[0]) {
+                            self.enter_rvalue(|v| v.emit(RewriteKind::RemoveStmt));
+                            return;
+                        }
+                        let null_after = self.free_then_null(&args[0], target);
+                        self.enter_rvalue(|v| {
+                            let arg_lty = v.acx.type_of(&args[0]);
+                            if arg_lty.label.is_none()
+                                || v.flags[arg_lty.label].contains(FlagSet::FIXED)
+                            {
+                                return;
+                            }
+                            let arg_desc = type_desc::perms_to_desc(
+                                arg_lty.ty,
+                                v.perms[arg_lty.label],
+                                v.flags[arg_lty.label],
+                            );
+                            // Pointers that aren't rewritten to `Box` keep the original `free`
+                            // call.
+                            if arg_desc.own != Ownership::Box {
+                                return;
+                            }
+                            // `free(p); p = NULL;` becomes `drop(p.take());`.  For fields,
+                            // `needs_option_take` has already added the `take()` where `p` is
+                            // read.
+                            let take = match null_after {
+                                Some((src, null_loc)) => {
+                                    v.rewrites.entry(null_loc).or_insert_with(Vec::new).push(
+                                        MirRewrite {
+                                            kind: RewriteKind::RemoveStmt,
+                                            sub_loc: Vec::new(),
+                                        },
+                                    );
+                                    src.projection.is_empty()
+                                }
+                                None => false,
+                            };
+                            v.emit(RewriteKind::FreeSafe { take });
+                        });
+                    }
+
+                    Callee::UnknownDef(UnknownDefCallee::Direct {
+                        def_id,
+                        is_foreign: true,
+                        ..
+                    }) => {
+                        let name = tcx.item_name(def_id);
+                        if let Some(func) = CStrFn::from_name(name.as_str()) {
+                            self.visit_cstr_fn(func, args, pl_ty);
+                        } else if name.as_str() == "sprintf" || name.as_str() == "snprintf" {
+                            self.visit_sprintf(name.as_str() == "snprintf", args, pl_ty);
+                        } else if ffi_escape::is_ffi_fn(self.acx.gacx, def_id) {
+                            self.visit_ffi_call(def_id, args, pl_ty);
+                        }
+                    }
+
+                    Callee::Atomic {
+                        op,
+                        ordering,
+                        fail_ordering,
+                    } => {
+                        self.visit_atomic_call(op, ordering, fail_ordering, args);
+                    }
+
                     Callee::IsNull => {
                         self.enter_rvalue(|v| {
                             let arg_lty = v.acx.type_of(&args[0]);
@@ -543,7 +1261,9 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
             TerminatorKind::GeneratorDrop => {}
             TerminatorKind::FalseEdge { .. } => {}
             TerminatorKind::FalseUnwind { .. } => {}
-            TerminatorKind::InlineAsm { .. } => todo!("terminator {:?}", term),
+            // The operands of inline assembly are marked `FIXED` (see `PointerInfo::OPAQUE_USE`),
+            // so any needed casts are inserted where their values are computed, not here.
+            TerminatorKind::InlineAsm { .. } => {}
         }
     }
 
@@ -554,6 +1274,13 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
         match *rv {
             Rvalue::Use(ref op) => {
                 self.enter_rvalue_operand(0, |v| v.visit_operand(op, expect_ty));
+                // With overflow checks enabled, `a - b` becomes `tmp = CheckedSub(a, b); x =
+                // move tmp.0`, and the expression is mapped to the final `Use`.
+                if let Some((def_loc, Rvalue::CheckedBinaryOp(BinOp::Sub, ops))) =
+                    self.checked_op_def(op)
+                {
+                    self.visit_ptr_addr_diff(def_loc, ops);
+                }
             }
             Rvalue::Repeat(ref op, _) => {
                 self.enter_rvalue_operand(0, |v| v.visit_operand(op, None));
@@ -563,8 +1290,34 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                     BorrowKind::Mut { .. } => true,
                     BorrowKind::Shared | BorrowKind::Shallow | BorrowKind::Unique => false,
                 };
-                self.enter_rvalue_place(0, |v| v.visit_place(pl, mutbl));
 
+                // A `&mut` borrow whose result is never written through can be a shared borrow
+                // instead, which avoids requiring `mut` on the borrowed place.
+                let downgrade = match expect_ty {
+                    Some(expect_ty)
+                        if mutbl
+                            && !expect_ty.label.is_none()
+                            && !self.flags[expect_ty.label].contains(FlagSet::FIXED) =>
+                    {
+                        let desc = type_desc::perms_to_desc(
+                            expect_ty.ty,
+                            self.perms[expect_ty.label],
+                            self.flags[expect_ty.label],
+                        );
+                        matches!(desc.own, Ownership::Imm | Ownership::Atomic)
+                    }
+                    _ => false,
+                };
+
+                self.enter_rvalue_place(0, |v| v.visit_place(pl, mutbl && !downgrade));
+                if self.place_borrows_ref_cell(pl) {
+                    // NYI: the reference would outlive the temporary `Ref` or `RefMut` guard
+                    self.err(DontRewriteFnReason::COMPLEX_CELL);
+                }
+
+                if downgrade {
+                    self.emit(RewriteKind::DowngradeRefMut);
+                }
                 if let Some(expect_ty) = expect_ty {
                     if self.is_nullable(expect_ty.label) {
                         // Nullable (`Option`) output is expected, but `Ref` always produces a
@@ -578,6 +1331,10 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
             }
             Rvalue::AddressOf(mutbl, pl) => {
                 self.enter_rvalue_place(0, |v| v.visit_place(pl, mutbl == Mutability::Mut));
+                if self.place_borrows_ref_cell(pl) {
+                    // NYI: the reference would outlive the temporary `Ref` or `RefMut` guard
+                    self.err(DontRewriteFnReason::COMPLEX_CELL);
+                }
                 if let Some(expect_ty) = expect_ty {
                     let desc = type_desc::perms_to_desc_with_pointee(
                         self.acx.tcx(),
@@ -587,9 +1344,13 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                         self.flags[expect_ty.label],
                     );
                     match desc.own {
-                        Ownership::Cell => self.emit(RewriteKind::RawToRef { mutbl: false }),
+                        Ownership::Cell | Ownership::RefCell | Ownership::Atomic => {
+                            self.emit(RewriteKind::RawToRef { mutbl: false })
+                        }
+                        // `addr_of_mut!` only produces `&mut` if the result is actually written
+                        // through.
                         Ownership::Imm | Ownership::Mut => self.emit(RewriteKind::RawToRef {
-                            mutbl: mutbl == Mutability::Mut,
+                            mutbl: mutbl == Mutability::Mut && desc.own == Ownership::Mut,
                         }),
                         _ => (),
                     }
@@ -601,7 +1362,18 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
             Rvalue::Len(pl) => {
                 self.enter_rvalue_place(0, |v| v.visit_place(pl, false));
             }
-            Rvalue::Cast(_kind, ref op, ty) => {
+            Rvalue::Cast(kind, ref op, ty) => {
+                if let Some(rv_lty) = expect_ty {
+                    if self.try_visit_cstr_literal_cast(op, rv_lty) {
+                        return;
+                    }
+                    if kind == CastKind::PointerFromExposedAddress
+                        && self.try_visit_align_up(op, ty, rv_lty)
+                    {
+                        return;
+                    }
+                }
+
                 if util::is_null_const_operand(op) && ty.is_unsafe_ptr() {
                     // Special case: convert `0 as *const T` to `None`.
                     if let Some(rv_lty) = expect_ty {
@@ -612,6 +1384,13 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                 }
 
                 self.enter_rvalue_operand(0, |v| v.visit_operand(op, None));
+                if kind == CastKind::Pointer(PointerCast::Unsize)
+                    && self.array_unsizes.contains(&self.loc)
+                {
+                    // `&arr as &[T]` -> `&arr`, for a pointer that keeps the array type.
+                    self.emit(RewriteKind::RemoveCast);
+                    return;
+                }
                 if let Some(rv_lty) = expect_ty {
                     let op_lty = self.acx.type_of(op);
                     let op_pointee = self.pointee_lty(op_lty);
@@ -642,28 +1421,79 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                             // identical.  This means we can delete the cast.
                             self.emit(RewriteKind::RemoveCast);
                         }
+                    } else if let (Some(op_pointee), Some(rv_pointee)) = (op_pointee, rv_pointee) {
+                        if op_pointee.ty.is_adt() && rv_pointee.ty.is_adt() {
+                            self.visit_struct_ptr_cast(
+                                op_lty,
+                                op_pointee.ty,
+                                rv_lty,
+                                rv_pointee.ty,
+                            );
+                        }
                     }
                 }
             }
-            Rvalue::BinaryOp(_bop, ref ops) => {
+            Rvalue::BinaryOp(bop, ref ops) => {
+                let is_null_cmp = matches!(bop, BinOp::Eq | BinOp::Ne)
+                    && self.visit_ptr_cmp_null(bop == BinOp::Ne, ops);
                 self.enter_rvalue_operand(0, |v| v.visit_operand(&ops.0, None));
                 self.enter_rvalue_operand(1, |v| v.visit_operand(&ops.1, None));
+                let is_cmp = matches!(
+                    bop,
+                    BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge
+                );
+                if is_cmp && !is_null_cmp {
+                    self.visit_safe_ptr_cmp(ops);
+                }
+                if matches!(bop, BinOp::Eq | BinOp::Ne) {
+                    self.visit_aligned_check(bop, ops);
+                }
+                if bop == BinOp::Sub {
+                    self.visit_ptr_addr_diff(self.loc, ops);
+                }
+                if bop == BinOp::Div {
+                    self.visit_ptr_addr_diff_div(ops);
+                }
             }
             Rvalue::CheckedBinaryOp(_bop, ref ops) => {
                 self.enter_rvalue_operand(0, |v| v.visit_operand(&ops.0, None));
                 self.enter_rvalue_operand(1, |v| v.visit_operand(&ops.1, None));
             }
             Rvalue::NullaryOp(..) => {}
-            Rvalue::UnaryOp(_uop, ref op) => {
+            Rvalue::UnaryOp(uop, ref op) => {
+                if uop == UnOp::Not {
+                    if let Some((Callee::IsNull, args)) = self.temp_call_def(op) {
+                        let arg_lty = self.acx.type_of(&args[0]);
+                        if self.is_nullable(arg_lty.label) {
+                            self.emit(RewriteKind::NotIsNullToIsSome);
+                        }
+                    }
+                }
                 self.enter_rvalue_operand(0, |v| v.visit_operand(op, None));
             }
             Rvalue::Discriminant(pl) => {
                 self.enter_rvalue_place(0, |v| v.visit_place(pl, false));
             }
-            Rvalue::Aggregate(ref _kind, ref ops) => {
+            Rvalue::Aggregate(ref kind, ref ops) => {
                 for (i, op) in ops.iter().enumerate() {
                     self.enter_rvalue_operand(i, |v| v.visit_operand(op, None));
                 }
+                if let AggregateKind::Adt(adt_did, variant_idx, _, _, active_field) = **kind {
+                    let tcx = self.acx.tcx();
+                    let variant = tcx.adt_def(adt_did).variant(variant_idx);
+                    for (i, _op) in ops.iter().enumerate() {
+                        let field_idx = active_field.unwrap_or(i);
+                        let fdid = variant.fields[field_idx].did;
+                        if let Some(mutbl) = self.generic_void_field_mutbl(adt_did, fdid) {
+                            self.enter_rvalue_operand(i, |v| {
+                                v.emit(RewriteKind::CastRawPointee {
+                                    pointee: "T".into(),
+                                    mutbl,
+                                })
+                            });
+                        }
+                    }
+                }
             }
             Rvalue::ShallowInitBox(ref op, _ty) => {
                 self.enter_rvalue_operand(0, |v| v.visit_operand(op, None));
@@ -680,10 +1510,17 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
         match *op {
             Operand::Copy(pl) | Operand::Move(pl) => {
                 self.enter_operand_place(|v| v.visit_place(pl, false));
+                if pl.projection.is_empty() && self.local_is_ref_cell(pl.local) {
+                    // NYI: copying or moving out of a whole `RefCell` local
+                    self.err(DontRewriteFnReason::COMPLEX_CELL);
+                }
 
                 if let Some(expect_ty) = expect_ty {
                     let ptr_lty = self.acx.type_of(pl);
                     if !ptr_lty.label.is_none() {
+                        if self.needs_option_take(pl, ptr_lty, expect_ty) {
+                            self.emit(RewriteKind::OptionTake);
+                        }
                         self.emit_cast_lty_lty(ptr_lty, expect_ty);
                     }
                 }
@@ -707,6 +1544,27 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
         }
     }
 
+    /// Like [`Self::visit_operand_desc`], but a raw pointer may be converted to a slice of `len`
+    /// elements.
+    fn visit_operand_desc_len(
+        &mut self,
+        op: &Operand<'tcx>,
+        expect_desc: TypeDesc<'tcx>,
+        len: Span,
+    ) {
+        match *op {
+            Operand::Copy(pl) | Operand::Move(pl) => {
+                self.visit_place(pl, false);
+
+                let ptr_lty = self.acx.type_of(pl);
+                if !ptr_lty.label.is_none() {
+                    self.emit_cast_lty_desc_len(ptr_lty, expect_desc, len);
+                }
+            }
+            Operand::Constant(..) => {}
+        }
+    }
+
     fn visit_place(&mut self, pl: Place<'tcx>, in_mutable_context: bool) {
         let mut ltys = Vec::with_capacity(1 + pl.projection.len());
         ltys.push(self.acx.type_of(pl.local));
@@ -761,11 +1619,21 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
                         }
                         v.emit(RewriteKind::OptionUnwrap);
                     }
+                    if v.ptr_is_ref_cell(base_lty) {
+                        v.emit(RewriteKind::RefCellBorrow {
+                            mutbl: in_mutable_context,
+                        });
+                    }
                 });
             }
             PlaceElem::Field(_idx, _ty) => {
                 self.enter_place_field_base(|v| {
-                    v.visit_place_ref(base_pl, proj_ltys, in_mutable_context)
+                    v.visit_place_ref(base_pl, proj_ltys, in_mutable_context);
+                    if base_pl.projection.is_empty() && v.local_is_ref_cell(base_pl.local) {
+                        v.emit(RewriteKind::RefCellBorrow {
+                            mutbl: in_mutable_context,
+                        });
+                    }
                 });
             }
             PlaceElem::Index(_) | PlaceElem::ConstantIndex { .. } | PlaceElem::Subslice { .. } => {
@@ -777,33 +1645,160 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
         }
     }
 
-    fn visit_ptr_offset(&mut self, op: &Operand<'tcx>, result_ty: LTy<'tcx>) {
-        // Compute the expected type for the argument, and emit a cast if needed.
-        let result_ptr = result_ty.label;
-        let result_desc =
-            type_desc::perms_to_desc(result_ty.ty, self.perms[result_ptr], self.flags[result_ptr]);
+    /// Check whether `lty` is a pointer that's rewritten to `&RefCell<T>`.
+    fn ptr_is_ref_cell(&self, lty: LTy<'tcx>) -> bool {
+        let ptr = lty.label;
+        if ptr.is_none() || self.flags[ptr].contains(FlagSet::FIXED) {
+            return false;
+        }
+        let desc = type_desc::perms_to_desc(lty.ty, self.perms[ptr], self.flags[ptr]);
+        desc.own == Ownership::RefCell
+    }
 
-        let arg_expect_desc = TypeDesc {
-            own: result_desc.own,
-            qty: match result_desc.qty {
-                Quantity::Single => Quantity::Slice,
-                Quantity::Slice => Quantity::Slice,
-                Quantity::OffsetPtr => Quantity::OffsetPtr,
-                Quantity::Array => unreachable!("perms_to_desc should not return Quantity::Array"),
-            },
-            option: result_desc.option,
-            pointee_ty: result_desc.pointee_ty,
-        };
+    /// Check whether `local` is rewritten to a `RefCell`, which happens when pointers to it are
+    /// rewritten to `&RefCell<T>`.
+    fn local_is_ref_cell(&self, local: Local) -> bool {
+        let ptr = self.acx.addr_of_local[local];
+        if ptr.is_none() || self.flags[ptr].contains(FlagSet::FIXED) {
+            return false;
+        }
+        let desc = type_desc::local_perms_to_desc(
+            self.acx.local_tys[local].ty,
+            self.perms[ptr],
+            self.flags[ptr],
+        );
+        desc.own == Ownership::RefCell
+    }
 
-        self.enter_rvalue(|v| {
-            v.enter_call_arg(0, |v| v.visit_operand_desc(op, arg_expect_desc));
+    /// Check whether `pl` refers to data inside a `RefCell`, either through a `&RefCell<T>`
+    /// pointer or as a field of a `RefCell` local.  Borrowing such a place produces a reference
+    /// that's only valid while the `borrow()` or `borrow_mut()` guard is alive.
+    fn place_borrows_ref_cell(&self, pl: Place<'tcx>) -> bool {
+        if !pl.projection.is_empty() && self.local_is_ref_cell(pl.local) {
+            return true;
+        }
+        let mut lty = self.acx.type_of(pl.local);
+        for proj in pl.projection {
+            if matches!(proj, PlaceElem::Deref) && self.ptr_is_ref_cell(lty) {
+                return true;
+            }
+            lty = self.acx.projection_lty(lty, &proj);
+        }
+        false
+    }
 
-            // Emit `OffsetSlice` for the offset itself.
-            let mutbl = matches!(result_desc.own, Ownership::Mut);
-            if !result_desc.option {
-                v.emit(RewriteKind::OffsetSlice { mutbl });
-            } else {
+    /// Emit casts for an assignment `pl = rv` that stores to or loads from a generic `void*`
+    /// field.  Stores convert the value from `*mut c_void` to `*mut T`.  Loads convert the value
+    /// back to `*mut c_void`, since the rest of the function still works with `void*` pointers.
+    fn visit_generic_void_field_access(&mut self, pl: Place<'tcx>, rv: &Rvalue<'tcx>) {
+        if let Some(mutbl) = self.generic_void_field(pl) {
+            self.enter_rvalue(|v| {
+                v.emit(RewriteKind::CastRawPointee {
+                    pointee: "T".into(),
+                    mutbl,
+                })
+            });
+        } else if let Rvalue::Use(ref op) = *rv {
+            if let Some(mutbl) = op.place().and_then(|rv_pl| self.generic_void_field(rv_pl)) {
+                self.enter_rvalue(|v| {
+                    v.emit(RewriteKind::CastRawPointee {
+                        pointee: "core::ffi::c_void".into(),
+                        mutbl,
+                    })
+                });
+            }
+        }
+    }
+
+    /// If `pl` is a field access on a generic `void*` field, and the current function has the
+    /// type parameter `T` for that field, return whether the field is a `*mut` pointer.
+    fn generic_void_field(&self, pl: Place<'tcx>) -> Option<bool> {
+        let (base, elem) = pl.as_ref().last_projection()?;
+        let field = match elem {
+            PlaceElem::Field(field, _) => field,
+            _ => return None,
+        };
+        let tcx = self.acx.tcx();
+        let base_ty = Place::ty_from(base.local, base.projection, self.mir, tcx).ty;
+        let adt_def = match *base_ty.kind() {
+            TyKind::Adt(adt_def, _) if !adt_def.is_enum() => adt_def,
+            _ => return None,
+        };
+        let fdid = adt_def.non_enum_variant().fields[field.index()].did;
+        self.generic_void_field_mutbl(adt_def.did(), fdid)
+    }
+
+    /// If `fdid` is one of the generic `void*` fields of `adt_did` (see
+    /// `AdtMetadata::generic_void_fields`) and the current function has the type parameter `T`
+    /// for that field, return whether the field is a `*mut` pointer.
+    fn generic_void_field_mutbl(&self, adt_did: DefId, fdid: DefId) -> Option<bool> {
+        if !self.in_generic_fn {
+            return None;
+        }
+        let adt = self.acx.gacx.adt_metadata.table.get(&adt_did)?;
+        if !adt.generic_void_fields.contains(&fdid) {
+            return None;
+        }
+        match *self.acx.tcx().type_of(fdid).kind() {
+            TyKind::RawPtr(tm) => Some(tm.mutbl == Mutability::Mut),
+            _ => None,
+        }
+    }
+
+    fn visit_ptr_offset(&mut self, op: &Operand<'tcx>, idx: &Operand<'tcx>, result_ty: LTy<'tcx>) {
+        // With dynamic checks, a raw pointer whose length is known can be converted to a slice,
+        // so the offset can be bounds-checked at run time.
+        let slice_len = if self.acx.gacx.dynamic_checks {
+            let span = self.mir.source_info(self.loc).span;
+            self.acx.gacx.len_fields.offset_lens.get(&span).copied()
+        } else {
+            None
+        };
+        // The explicit check is redundant if the comparison that bounds the index by the length
+        // has already been tested, as with the condition of the enclosing loop.
+        let checked = slice_len.map_or(false, |len| !self.offset_bounded_by_cond(idx, len));
+
+        // Offsetting a `FIXED` pointer produces a raw pointer, so the result must stay raw too.
+        let result_ptr = result_ty.label;
+        let op_ptr = self.acx.type_of(op).label;
+        if !op_ptr.is_none() && self.flags[op_ptr].contains(FlagSet::FIXED) && slice_len.is_none() {
+            if !self.flags[result_ptr].contains(FlagSet::FIXED) {
+                self.unresolved_cast(vec![result_ptr], "offset of a FIXED pointer".into());
+            }
+            return;
+        }
+
+        // Compute the expected type for the argument, and emit a cast if needed.
+        let result_desc =
+            type_desc::perms_to_desc(result_ty.ty, self.perms[result_ptr], self.flags[result_ptr]);
+
+        let arg_expect_desc = TypeDesc {
+            own: result_desc.own,
+            qty: match result_desc.qty {
+                Quantity::Single => Quantity::Slice,
+                Quantity::Slice => Quantity::Slice,
+                Quantity::OffsetPtr => Quantity::OffsetPtr,
+                Quantity::Array => unreachable!("perms_to_desc should not return Quantity::Array"),
+                Quantity::CStr => unreachable!("offset pointers are never CSTR"),
+            },
+            option: result_desc.option,
+            pointee_ty: result_desc.pointee_ty,
+        };
+
+        self.enter_rvalue(|v| {
+            v.enter_call_arg(0, |v| match slice_len {
+                Some(len) => v.visit_operand_desc_len(op, arg_expect_desc, len),
+                None => v.visit_operand_desc(op, arg_expect_desc),
+            });
+
+            // Emit `OffsetSlice` for the offset itself.
+            let mutbl = matches!(result_desc.own, Ownership::Mut);
+            if result_desc.option {
                 v.emit(RewriteKind::OptionMapOffsetSlice { mutbl });
+            } else if checked {
+                v.emit(RewriteKind::CheckedOffsetSlice { mutbl });
+            } else {
+                v.emit(RewriteKind::OffsetSlice { mutbl });
             }
 
             // The `OffsetSlice` operation returns something of the same type as its input.
@@ -812,6 +1807,174 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
         });
     }
 
+    /// Follow the integer operand `op` back through temporaries that copy it or cast it between
+    /// `usize` and `isize`, and return the place it was originally read from.  `loc` is the
+    /// location where `op` is read.
+    fn int_source(&self, loc: Location, op: &Operand<'tcx>) -> Option<Place<'tcx>> {
+        let tcx = self.acx.tcx();
+        let (mut loc, mut op) = (loc, op);
+        while let Some((def_loc, def)) = self.temp_def_at(loc, op) {
+            op = match def {
+                Either::Left(Rvalue::Use(op)) => op,
+                Either::Left(&Rvalue::Cast(CastKind::Misc, ref op, ty))
+                    if ty.is_ptr_sized_integral()
+                        && op.ty(self.mir, tcx).is_ptr_sized_integral() =>
+                {
+                    op
+                }
+                _ => break,
+            };
+            loc = def_loc;
+        }
+        op.place()
+    }
+
+    /// Check whether the offset at the current location, by `idx`, is only reached while a
+    /// comparison `i < len` or `i <= len` holds, where `i` is the unsigned local that `idx` was
+    /// computed from and `len` is the length expression at `len_span`.  This is the case for an
+    /// offset in the body of `while i < len { ... }` that comes before any assignment to `i`.
+    ///
+    /// Every path to the offset must pass through the block that branches on the comparison and
+    /// take its `true` edge, and no block between that edge and the offset may assign to `i`, to
+    /// the local that `len` is read from, or through a pointer.  Calls other than the offset
+    /// itself are also rejected, since they could modify `len` through an alias.
+    fn offset_bounded_by_cond(&self, idx: &Operand<'tcx>, len_span: Span) -> bool {
+        let tcx = self.acx.tcx();
+        let idx_local = match self.int_source(self.loc, idx) {
+            Some(pl) if pl.projection.is_empty() => pl.local,
+            _ => return false,
+        };
+        // A negative signed index would wrap around when converted to `usize`.
+        if self.mir.local_decls[idx_local].ty != tcx.types.usize {
+            return false;
+        }
+        let addr_taken = self.mir.basic_blocks().iter().any(|bb_data| {
+            bb_data.statements.iter().any(|stmt| match stmt.kind {
+                StatementKind::Assign(ref x) => match x.1 {
+                    Rvalue::Ref(_, _, pl) | Rvalue::AddressOf(_, pl) => pl.local == idx_local,
+                    _ => false,
+                },
+                _ => false,
+            })
+        });
+        if addr_taken {
+            return false;
+        }
+
+        for (bb, bb_data) in self.mir.basic_blocks().iter_enumerated() {
+            let (cond, targets) = match bb_data.terminator().kind {
+                TerminatorKind::SwitchInt {
+                    discr: Operand::Move(cond),
+                    ref targets,
+                    ..
+                } if cond.projection.is_empty() => (cond.local, targets),
+                _ => continue,
+            };
+            let true_target = match targets.iter().collect::<Vec<_>>()[..] {
+                [(0, false_target)] if false_target != targets.otherwise() => targets.otherwise(),
+                _ => continue,
+            };
+            let len_local = bb_data.statements.iter().enumerate().find_map(|(j, stmt)| {
+                let stmt_loc = Location {
+                    block: bb,
+                    statement_index: j,
+                };
+                let (pl, rv) = match stmt.kind {
+                    StatementKind::Assign(ref x) => (x.0, &x.1),
+                    _ => return None,
+                };
+                if pl != cond.into() || !stmt.source_info.span.contains(len_span) {
+                    return None;
+                }
+                let (i, len) = match *rv {
+                    Rvalue::BinaryOp(BinOp::Lt | BinOp::Le, ref ops) => (&ops.0, &ops.1),
+                    Rvalue::BinaryOp(BinOp::Gt | BinOp::Ge, ref ops) => (&ops.1, &ops.0),
+                    _ => return None,
+                };
+                if len.ty(self.mir, tcx) != tcx.types.usize
+                    || self.int_source(stmt_loc, i)? != idx_local.into()
+                {
+                    return None;
+                }
+                Some(self.int_source(stmt_loc, len)?.local)
+            });
+            if let Some(len_local) = len_local {
+                if self.cond_holds_at_loc(bb, true_target, idx_local, len_local) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Check that the current location is only reachable through the edge from `cond_bb` to
+    /// `true_target`, and that no block on the way modifies `idx_local`, `len_local`, or memory.
+    fn cond_holds_at_loc(
+        &self,
+        cond_bb: BasicBlock,
+        true_target: BasicBlock,
+        idx_local: Local,
+        len_local: Local,
+    ) -> bool {
+        let loc = self.loc;
+        let preds = self.mir.basic_blocks.predecessors();
+        // Collect the blocks that can reach the offset without passing through `cond_bb`.
+        let mut region = HashSet::new();
+        let mut stack = vec![loc.block];
+        while let Some(bb) = stack.pop() {
+            if bb == cond_bb || !region.insert(bb) {
+                continue;
+            }
+            stack.extend(preds[bb].iter().copied());
+        }
+        if region.contains(&START_BLOCK) || !region.contains(&true_target) {
+            return false;
+        }
+        let cond_term = self.mir.basic_blocks()[cond_bb].terminator();
+        if cond_term
+            .successors()
+            .any(|bb| bb != true_target && region.contains(&bb))
+        {
+            return false;
+        }
+
+        let preserves = |stmt: &Statement<'tcx>| {
+            let pl = match stmt.kind {
+                StatementKind::Assign(ref x) => x.0,
+                StatementKind::SetDiscriminant { ref place, .. } => **place,
+                StatementKind::Deinit(ref place) => **place,
+                _ => return true,
+            };
+            pl.local != idx_local && pl.local != len_local && !pl.is_indirect()
+        };
+        region.iter().all(|&bb| {
+            let bb_data = &self.mir.basic_blocks()[bb];
+            if bb == loc.block {
+                // The terminator here is the offset itself.  The statements after it only matter
+                // if the block can be reached again without passing through `cond_bb`.
+                let reentered = bb_data
+                    .terminator()
+                    .successors()
+                    .any(|bb| region.contains(&bb));
+                let end = if reentered {
+                    bb_data.statements.len()
+                } else {
+                    loc.statement_index
+                };
+                return bb_data.statements[..end].iter().all(preserves);
+            }
+            bb_data.statements.iter().all(preserves)
+                && matches!(
+                    bb_data.terminator().kind,
+                    TerminatorKind::Goto { .. }
+                        | TerminatorKind::SwitchInt { .. }
+                        | TerminatorKind::Assert { .. }
+                        | TerminatorKind::FalseEdge { .. }
+                        | TerminatorKind::FalseUnwind { .. }
+                )
+        })
+    }
+
     fn visit_slice_as_ptr(&mut self, elem_ty: Ty<'tcx>, op: &Operand<'tcx>, result_lty: LTy<'tcx>) {
         let op_lty = self.acx.type_of(op);
         let op_ptr = op_lty.label;
@@ -841,6 +2004,796 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
         });
     }
 
+    /// Visit a call to a C string function.  The call is rewritten only if every string argument
+    /// has been rewritten to a non-optional slice or `&CStr`, so that the NUL terminator can be
+    /// found safely.
+    fn visit_cstr_fn(&mut self, func: CStrFn, args: &[Operand<'tcx>], result_lty: LTy<'tcx>) {
+        let tcx = self.acx.tcx();
+        let mut cstr_args = [false; 2];
+        for (i, arg) in args.iter().enumerate() {
+            let arg_lty = self.acx.type_of(arg);
+            if arg_lty.label.is_none() || self.flags[arg_lty.label].contains(FlagSet::FIXED) {
+                return;
+            }
+            let desc = type_desc::perms_to_desc(
+                arg_lty.ty,
+                self.perms[arg_lty.label],
+                self.flags[arg_lty.label],
+            );
+            if desc.option || desc.qty == Quantity::Single {
+                return;
+            }
+            let ok_own = if i == 0 && func.writes_dest() {
+                matches!(desc.own, Ownership::Mut | Ownership::Box)
+            } else {
+                matches!(desc.own, Ownership::Imm | Ownership::Mut | Ownership::Box)
+            };
+            if !ok_own {
+                return;
+            }
+            cstr_args[i] = desc.qty == Quantity::CStr;
+        }
+
+        let printer = FmtPrinter::new(tcx, Namespace::TypeNS);
+        let ret_ty = result_lty.ty.print(printer).unwrap().into_buffer();
+        self.enter_rvalue(|v| {
+            v.emit(RewriteKind::CStrFnSafe {
+                func,
+                ret_ty,
+                cstr_args,
+            });
+
+            // `strcpy` and `strcat` return their `dest` argument.
+            if func.writes_dest()
+                && !result_lty.label.is_none()
+                && v.perms[result_lty.label].intersects(PermissionSet::USED)
+            {
+                let dest_lty = v.acx.type_of(&args[0]);
+                v.emit_cast_lty_lty(dest_lty, result_lty);
+            }
+        });
+    }
+
+    /// Visit a call to a foreign function whose arguments escape the analysis (see
+    /// `crate::ffi_escape`).  The callee keeps its raw pointer signature, so each argument is
+    /// converted back to the declared parameter type, and the result is converted to the type of
+    /// its destination.
+    fn visit_ffi_call(&mut self, def_id: DefId, args: &[Operand<'tcx>], result_lty: LTy<'tcx>) {
+        let lsig = match self.acx.gacx.fn_sigs.get(&def_id) {
+            Some(&x) => x,
+            None => return,
+        };
+        self.enter_rvalue(|v| {
+            v.in_ffi_call = true;
+            for (i, op) in args.iter().enumerate() {
+                // Variadic arguments past the end of the declared parameters are left alone.
+                if let Some(&lty) = lsig.inputs.get(i) {
+                    v.enter_call_arg(i, |v| v.visit_operand(op, Some(lty)));
+                }
+            }
+            if !result_lty.label.is_none() {
+                v.emit_cast_lty_lty(lsig.output, result_lty);
+            }
+            v.in_ffi_call = false;
+        });
+    }
+
+    /// Visit an argument passed to the `...` of a C-variadic function.  A pointer that was
+    /// rewritten to a safe reference is cast back to its original raw pointer type.
+    fn visit_variadic_arg(&mut self, op: &Operand<'tcx>) {
+        let tcx = self.acx.tcx();
+        let desc = match *op.ty(self.mir, tcx).kind() {
+            TyKind::RawPtr(mt) => TypeDesc {
+                own: match mt.mutbl {
+                    Mutability::Not => Ownership::Raw,
+                    Mutability::Mut => Ownership::RawMut,
+                },
+                qty: Quantity::Single,
+                option: false,
+                pointee_ty: mt.ty,
+            },
+            _ => return self.visit_operand(op, None),
+        };
+        self.visit_operand_desc(op, desc);
+    }
+
+    /// Visit a call to an atomic intrinsic.  If its pointer argument was rewritten to `&AtomicI32`
+    /// or similar, the call becomes a method call on that reference.  Otherwise, the pointer is
+    /// `FIXED` (see `analyze::mark_atomic_ptrs`) and the call is left unchanged.
+    fn visit_atomic_call(
+        &mut self,
+        op: AtomicOp,
+        ordering: &'static str,
+        fail_ordering: &'static str,
+        args: &[Operand<'tcx>],
+    ) {
+        let ptr_lty = self.acx.type_of(&args[0]);
+        if ptr_lty.label.is_none() || self.flags[ptr_lty.label].contains(FlagSet::FIXED) {
+            return;
+        }
+        let desc = type_desc::perms_to_desc(
+            ptr_lty.ty,
+            self.perms[ptr_lty.label],
+            self.flags[ptr_lty.label],
+        );
+        if desc.own != Ownership::Atomic {
+            return;
+        }
+
+        let cxchg = matches!(op, AtomicOp::Cxchg { .. });
+        let orderings = if cxchg {
+            vec![ordering, fail_ordering]
+        } else {
+            vec![ordering]
+        };
+        self.enter_rvalue(|v| {
+            v.emit(RewriteKind::AtomicMethod {
+                method: op.method(),
+                orderings,
+                cxchg,
+            });
+            // The method is called on a single, non-optional `&AtomicI32`.
+            let expect_desc = TypeDesc {
+                qty: Quantity::Single,
+                option: false,
+                ..desc
+            };
+            v.enter_call_arg(0, |v| v.visit_operand_desc(&args[0], expect_desc));
+            for (i, arg) in args.iter().enumerate().skip(1) {
+                v.enter_call_arg(i, |v| v.visit_operand(arg, Some(ptr_lty.args[0])));
+            }
+        });
+    }
+
+    /// Visit a call to `sprintf`, or to `snprintf` if `bounded` is set.  The call is rewritten
+    /// only if the destination has been rewritten to a non-optional mutable slice, and the format
+    /// string is a literal that can be translated for `format!`.
+    fn visit_sprintf(&mut self, bounded: bool, args: &[Operand<'tcx>], result_lty: LTy<'tcx>) {
+        let tcx = self.acx.tcx();
+        let fmt_idx = if bounded { 2 } else { 1 };
+        if args.len() <= fmt_idx {
+            return;
+        }
+
+        let dest_lty = self.acx.type_of(&args[0]);
+        if dest_lty.label.is_none() || self.flags[dest_lty.label].contains(FlagSet::FIXED) {
+            return;
+        }
+        let desc = type_desc::perms_to_desc(
+            dest_lty.ty,
+            self.perms[dest_lty.label],
+            self.flags[dest_lty.label],
+        );
+        if desc.option
+            || desc.qty == Quantity::Single
+            || !matches!(desc.own, Ownership::Mut | Ownership::Box)
+        {
+            return;
+        }
+
+        let fmt = match self
+            .byte_str_literal(&args[fmt_idx])
+            .and_then(|c| byte_str_literal_bytes(tcx, c))
+            .and_then(printf::c_str_contents)
+        {
+            Some(x) => x,
+            None => return,
+        };
+        let (fmt, mut kinds) = match printf::translate_format(&fmt) {
+            Some(x) => x,
+            None => return,
+        };
+        let var_args = &args[fmt_idx + 1..];
+        if kinds.len() != var_args.len() {
+            return;
+        }
+        // A `%s` argument that is still a raw pointer is passed to `CStr::from_ptr`, but one that
+        // was rewritten to a slice needs to be read up to its terminator instead.
+        for (kind, arg) in kinds.iter_mut().zip(var_args) {
+            if *kind != ArgKind::CStr {
+                continue;
+            }
+            let arg_lty = self.acx.type_of(arg);
+            if arg_lty.label.is_none() || self.flags[arg_lty.label].contains(FlagSet::FIXED) {
+                continue;
+            }
+            let desc = type_desc::perms_to_desc(
+                arg_lty.ty,
+                self.perms[arg_lty.label],
+                self.flags[arg_lty.label],
+            );
+            match desc.own {
+                Ownership::Raw | Ownership::RawMut => {}
+                Ownership::Imm | Ownership::Mut | Ownership::Box
+                    if !desc.option && desc.qty != Quantity::Single =>
+                {
+                    *kind = ArgKind::CStrSlice;
+                }
+                _ => return,
+            }
+        }
+
+        let printer = FmtPrinter::new(tcx, Namespace::TypeNS);
+        let ret_ty = result_lty.ty.print(printer).unwrap().into_buffer();
+        self.enter_rvalue(|v| {
+            v.emit(RewriteKind::SprintfSafe {
+                fmt,
+                args: kinds,
+                bounded,
+                ret_ty,
+            })
+        });
+    }
+
+    /// Find the definition of the temporary read by `op` at `loc`, along with the location of that
+    /// definition.  See [`dataflow::temp_def_before`].
+    fn temp_def_at(
+        &self,
+        loc: Location,
+        op: &Operand<'tcx>,
+    ) -> Option<(Location, TempDef<'a, 'tcx>)> {
+        dataflow::temp_def_before(self.acx.tcx(), self.mir, self.recent_writes, loc, op)
+    }
+
+    /// Like `temp_def_at`, but for an operand of the statement currently being visited.
+    fn temp_def(&self, op: &Operand<'tcx>) -> Option<TempDef<'a, 'tcx>> {
+        self.temp_def_at(self.loc, op).map(|(_, def)| def)
+    }
+
+    /// If `op` reads field 0 of a temporary holding the result of a checked arithmetic operation,
+    /// as in `move tmp.0` where `tmp = CheckedSub(a, b)`, return the `Rvalue` of that operation
+    /// and its location.
+    fn checked_op_def(&self, op: &Operand<'tcx>) -> Option<(Location, &'a Rvalue<'tcx>)> {
+        self.checked_op_def_at(self.loc, op)
+    }
+
+    /// Like `checked_op_def`, but for an operand read at `loc`.
+    fn checked_op_def_at(
+        &self,
+        loc: Location,
+        op: &Operand<'tcx>,
+    ) -> Option<(Location, &'a Rvalue<'tcx>)> {
+        let pl = op.place()?;
+        match pl.projection[..] {
+            [PlaceElem::Field(f, _)] if f.index() == 0 => {}
+            _ => return None,
+        }
+        match self.temp_def_at(loc, &Operand::Copy(pl.local.into()))? {
+            (def_loc, Either::Left(rv @ Rvalue::CheckedBinaryOp(..))) => Some((def_loc, rv)),
+            _ => None,
+        }
+    }
+
+    /// If `op` is a temporary holding the result of a function call, return the callee and the
+    /// arguments of that call.
+    fn temp_call_def(&self, op: &Operand<'tcx>) -> Option<(Callee<'tcx>, &'a [Operand<'tcx>])> {
+        self.temp_def(op)?.right()
+    }
+
+    /// Check whether `op` is a null pointer: either a null constant, a cast of one, or the result
+    /// of `ptr::null()`/`ptr::null_mut()`.  `loc` is the location where `op` is read.
+    fn is_null_operand(&self, loc: Location, op: &Operand<'tcx>) -> bool {
+        if util::is_null_const_operand(op) {
+            return true;
+        }
+        match self.temp_def_at(loc, op) {
+            Some((_, Either::Left(Rvalue::Cast(_, op, _)))) => util::is_null_const_operand(op),
+            Some((_, Either::Right((callee, _)))) => matches!(callee, Callee::Null { .. }),
+            _ => false,
+        }
+    }
+
+    /// Like `is_null_operand`, but also looks through pointer casts, as in
+    /// `ptr::null_mut::<T>() as *mut c_void`.
+    fn is_null_through_casts(&self, loc: Location, op: &Operand<'tcx>) -> bool {
+        let (mut loc, mut op) = (loc, op);
+        loop {
+            if self.is_null_operand(loc, op) {
+                return true;
+            }
+            match self.temp_def_at(loc, op) {
+                Some((def_loc, Either::Left(Rvalue::Use(inner) | Rvalue::Cast(_, inner, _)))) => {
+                    loc = def_loc;
+                    op = inner;
+                }
+                _ => return false,
+            }
+        }
+    }
+
+    /// Check for the `free(p); p = NULL;` idiom, where `op` is the argument of the `free` call and
+    /// `target` is the block it returns to.  If the freed pointer was read from a nullable place
+    /// that's set to null right after the call, return that place and the location of the null
+    /// assignment.
+    fn free_then_null(
+        &self,
+        op: &Operand<'tcx>,
+        target: Option<BasicBlock>,
+    ) -> Option<(Place<'tcx>, Location)> {
+        let tcx = self.acx.tcx();
+
+        // Follow the chain of temporaries back to the place the pointer was read from.
+        let (mut loc, mut op) = (self.loc, op);
+        while let Some((def_loc, def)) = self.temp_def_at(loc, op) {
+            match def {
+                Either::Left(Rvalue::Use(inner) | Rvalue::Cast(_, inner, _)) => {
+                    loc = def_loc;
+                    op = inner;
+                }
+                _ => return None,
+            }
+        }
+        let src = op.place()?;
+        if src.projection.is_empty() && self.mir.local_kind(src.local) == LocalKind::Temp {
+            return None;
+        }
+        let src_ptr = self.acx.type_of(src).label;
+        if src_ptr.is_none()
+            || self.flags[src_ptr].contains(FlagSet::FIXED)
+            || !self.is_nullable(src_ptr)
+        {
+            return None;
+        }
+
+        // Look for `src = NULL` at the start of the block `free` returns to.  Computing the null
+        // pointer may take a call to `ptr::null_mut()`, so the assignment may be in the block
+        // after that.
+        let mut bb = target?;
+        for _ in 0..2 {
+            let bb_data = &self.mir.basic_blocks()[bb];
+            for (i, stmt) in bb_data.statements.iter().enumerate() {
+                match stmt.kind {
+                    StatementKind::StorageLive(_)
+                    | StatementKind::StorageDead(_)
+                    | StatementKind::Nop => {}
+                    StatementKind::Assign(ref x) if x.0 == src => {
+                        let loc = Location {
+                            block: bb,
+                            statement_index: i,
+                        };
+                        let is_null = match x.1 {
+                            Rvalue::Use(ref op) => self.is_null_through_casts(loc, op),
+                            Rvalue::Cast(_, ref op, _) => self.is_null_through_casts(loc, op),
+                            _ => false,
+                        };
+                        if !is_null {
+                            return None;
+                        }
+                        return Some((src, loc));
+                    }
+                    // Temporaries used to compute the null pointer.
+                    StatementKind::Assign(ref x)
+                        if x.0.projection.is_empty()
+                            && self.mir.local_kind(x.0.local) == LocalKind::Temp => {}
+                    _ => return None,
+                }
+            }
+            match bb_data.terminator().kind {
+                TerminatorKind::Call {
+                    ref func,
+                    target: Some(next),
+                    ..
+                } if matches!(ty_callee(tcx, func.ty(self.mir, tcx)), Callee::Null { .. }) => {
+                    bb = next;
+                }
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Visit a comparison `ops.0 == ops.1` (or `!=`, if `ne` is set).  If one side is a null
+    /// pointer and the other is a pointer that will be rewritten to a safe type, the comparison is
+    /// replaced with a call to `is_none()`/`is_some()`, or with a constant if the pointer is
+    /// non-nullable.  Returns `true` if the comparison was rewritten.
+    fn visit_ptr_cmp_null(&mut self, ne: bool, ops: &(Operand<'tcx>, Operand<'tcx>)) -> bool {
+        let (null_idx, ptr_op) = if self.is_null_operand(self.loc, &ops.1) {
+            (1, &ops.0)
+        } else if self.is_null_operand(self.loc, &ops.0) {
+            (0, &ops.1)
+        } else {
+            return false;
+        };
+        let ptr_lty = self.acx.type_of(ptr_op);
+        if !ptr_lty.ty.is_unsafe_ptr()
+            || ptr_lty.label.is_none()
+            || self.flags[ptr_lty.label].contains(FlagSet::FIXED)
+        {
+            return false;
+        }
+        if self.perms[ptr_lty.label].contains(PermissionSet::NON_NULL) {
+            self.emit(RewriteKind::PtrCmpNullToConst { value: ne });
+        } else {
+            self.emit(RewriteKind::PtrCmpNullToIsNone { null_idx, ne });
+        }
+        true
+    }
+
+    /// Visit a comparison between two pointers that are both rewritten to safe references.  Safe
+    /// references compare by value rather than by address, so each side is converted back to a
+    /// raw pointer, which preserves the meaning of the original comparison.
+    fn visit_safe_ptr_cmp(&mut self, ops: &(Operand<'tcx>, Operand<'tcx>)) {
+        let mut descs = Vec::with_capacity(2);
+        for op in [&ops.0, &ops.1] {
+            let lty = self.acx.type_of(op);
+            let ptr = lty.label;
+            if !lty.ty.is_unsafe_ptr() || ptr.is_none() || self.flags[ptr].contains(FlagSet::FIXED)
+            {
+                return;
+            }
+            let desc = type_desc::perms_to_desc(lty.ty, self.perms[ptr], self.flags[ptr]);
+            if desc.option
+                || !matches!(desc.own, Ownership::Imm | Ownership::Mut | Ownership::Box)
+                || desc.qty == Quantity::Array
+            {
+                return;
+            }
+            descs.push(desc);
+        }
+
+        for (i, desc) in descs.iter().enumerate() {
+            self.enter_rvalue_operand(i, |v| match desc.qty {
+                Quantity::Single => v.emit(RewriteKind::CastRefToRaw { mutbl: false }),
+                _ => v.emit(RewriteKind::AsPtr),
+            });
+        }
+
+        // `OffsetPtr` is currently rewritten to a slice, the same as `Slice`.
+        let is_slice = |d: &TypeDesc| matches!(d.qty, Quantity::Slice | Quantity::OffsetPtr);
+        if descs.iter().all(is_slice) && descs[0].own == descs[1].own {
+            self.emit(RewriteKind::SlicePtrCmp {
+                mutbl: descs[0].own != Ownership::Imm,
+            });
+        }
+    }
+
+    /// Get the `TypeDesc` of the pointer `op`, if it will be rewritten to a safe reference to a
+    /// slice.
+    fn safe_slice_desc(&self, op: &Operand<'tcx>) -> Option<TypeDesc<'tcx>> {
+        let lty = self.acx.type_of(op);
+        let ptr = lty.label;
+        if !lty.ty.is_unsafe_ptr() || ptr.is_none() || self.flags[ptr].contains(FlagSet::FIXED) {
+            return None;
+        }
+        let desc = type_desc::perms_to_desc(lty.ty, self.perms[ptr], self.flags[ptr]);
+        if !matches!(desc.own, Ownership::Imm | Ownership::Mut | Ownership::Box)
+            || !matches!(desc.qty, Quantity::Slice | Quantity::OffsetPtr)
+        {
+            return None;
+        }
+        Some(desc)
+    }
+
+    /// Visit a cast between pointers to two different structs, as in `p as *const B` where `p:
+    /// *const A`.  If `A` can be read as `B` and the result is used only for reading, the cast is
+    /// replaced with a `transmute` between references.  Otherwise, there is no safe equivalent of
+    /// the cast, so the function is left unchanged.
+    fn visit_struct_ptr_cast(
+        &mut self,
+        op_lty: LTy<'tcx>,
+        op_pointee: Ty<'tcx>,
+        rv_lty: LTy<'tcx>,
+        rv_pointee: Ty<'tcx>,
+    ) {
+        let is_fixed = |ptr: PointerId| ptr.is_none() || self.flags[ptr].contains(FlagSet::FIXED);
+        let op_fixed = is_fixed(op_lty.label);
+        let rv_fixed = is_fixed(rv_lty.label);
+        if op_fixed && rv_fixed {
+            // Both sides remain raw pointers, so the cast is still valid.
+            return;
+        }
+
+        let tcx = self.acx.tcx();
+        let desc_of = |lty: LTy<'tcx>, pointee_ty| {
+            type_desc::perms_to_desc_with_pointee(
+                tcx,
+                pointee_ty,
+                lty.ty,
+                self.perms[lty.label],
+                self.flags[lty.label],
+            )
+        };
+        let rv_desc = if op_fixed || rv_fixed {
+            Err("only one side of the cast can be rewritten")
+        } else if !util::is_layout_compatible_read(tcx, op_pointee, rv_pointee) {
+            Err("layouts are not compatible")
+        } else {
+            let op_desc = desc_of(op_lty, op_pointee);
+            let rv_desc = desc_of(rv_lty, rv_pointee);
+            if rv_desc.own != Ownership::Imm || rv_desc.qty != Quantity::Single {
+                Err("result is not a read-only pointer to a single value")
+            } else if !matches!(
+                op_desc.own,
+                Ownership::Imm | Ownership::Mut | Ownership::Box
+            ) || op_desc.qty == Quantity::Array
+            {
+                Err("input has unsupported ownership or quantity")
+            } else {
+                Ok(rv_desc)
+            }
+        };
+        let rv_desc = match rv_desc {
+            Ok(x) => x,
+            Err(reason) => {
+                warn!("can't rewrite cast from {op_pointee:?} to {rv_pointee:?}: {reason}");
+                self.err(DontRewriteFnReason::PTR_TO_PTR_CAST);
+                return;
+            }
+        };
+
+        let imm_single = |pointee_ty| TypeDesc {
+            own: Ownership::Imm,
+            qty: Quantity::Single,
+            option: false,
+            pointee_ty,
+        };
+        self.enter_rvalue_operand(0, |v| v.emit_cast_lty_desc(op_lty, imm_single(op_pointee)));
+        let print_ty = |ty: Ty<'tcx>| {
+            let printer = FmtPrinter::new(tcx, Namespace::TypeNS);
+            ty.print(printer).unwrap().into_buffer()
+        };
+        self.emit(RewriteKind::TransmuteRef {
+            from_ty: print_ty(op_pointee),
+            to_ty: print_ty(rv_pointee),
+        });
+        self.emit_cast_desc_desc(imm_single(rv_pointee), rv_desc);
+    }
+
+    /// Visit a call `args[0].offset_from(args[1])`.  If both pointers are rewritten to slices, the
+    /// call is replaced with the difference of the slices' lengths.
+    fn visit_ptr_offset_from(&mut self, args: &[Operand<'tcx>]) {
+        let descs = match (
+            self.safe_slice_desc(&args[0]),
+            self.safe_slice_desc(&args[1]),
+        ) {
+            (Some(desc0), Some(desc1)) => [desc0, desc1],
+            _ => return,
+        };
+        self.enter_rvalue(|v| {
+            for (i, (op, desc)) in args.iter().zip(descs).enumerate() {
+                let expect_desc = TypeDesc {
+                    option: false,
+                    ..desc
+                };
+                v.enter_call_arg(i, |v| v.visit_operand_desc(op, expect_desc));
+            }
+            v.emit(RewriteKind::OffsetFromSlice);
+        });
+    }
+
+    /// Visit a subtraction `ops.0 - ops.1`.  If both operands are temporaries holding pointers cast
+    /// to integers, as in `end as usize - start as usize`, and both pointers are rewritten to
+    /// non-optional slices, the subtraction is replaced with a computation based on the slices'
+    /// lengths.  `loc` is the location of the subtraction.
+    fn visit_ptr_addr_diff(&mut self, loc: Location, ops: &(Operand<'tcx>, Operand<'tcx>)) {
+        let pointee_ty = match self.ptr_addr_diff_pointee(loc, ops) {
+            Some(x) => x,
+            None => return,
+        };
+        let printer = FmtPrinter::new(self.acx.tcx(), Namespace::TypeNS);
+        let pointee = pointee_ty.print(printer).unwrap().into_buffer();
+        self.emit(RewriteKind::PtrAddrDiffSlice { pointee });
+    }
+
+    /// Visit a division `ops.0 / ops.1`.  If `ops.0` is the result of a subtraction that
+    /// `visit_ptr_addr_diff` would rewrite, and `ops.1` is the result of `size_of::<T>()` for the
+    /// slices' element type `T`, as in `(end as usize - start as usize) / size_of::<T>()`, the
+    /// whole division is replaced with the difference of the slices' lengths.
+    fn visit_ptr_addr_diff_div(&mut self, ops: &(Operand<'tcx>, Operand<'tcx>)) {
+        // With overflow checks enabled, the subtraction is a `CheckedSub` followed by a `Use` of
+        // its result, as in `visit_rvalue`.
+        let (sub_loc, sub_ops) = match self.temp_def_at(self.loc, &ops.0) {
+            Some((def_loc, Either::Left(Rvalue::BinaryOp(BinOp::Sub, sub_ops)))) => {
+                (def_loc, sub_ops)
+            }
+            Some((def_loc, Either::Left(Rvalue::Use(op)))) => {
+                match self.checked_op_def_at(def_loc, op) {
+                    Some((def_loc, Rvalue::CheckedBinaryOp(BinOp::Sub, sub_ops))) => {
+                        (def_loc, sub_ops)
+                    }
+                    _ => return,
+                }
+            }
+            _ => return,
+        };
+        let pointee_ty = match self.ptr_addr_diff_pointee(sub_loc, sub_ops) {
+            Some(x) => x,
+            None => return,
+        };
+        let tcx = self.acx.tcx();
+        match self.temp_call_def(&ops.1) {
+            Some((Callee::SizeOf { ty }, _)) if ty == tcx.erase_regions(pointee_ty) => {}
+            _ => return,
+        }
+        self.emit(RewriteKind::PtrAddrDiffSliceElems);
+    }
+
+    /// If `ops` are temporaries holding pointers cast to integers, as in `end as usize - start as
+    /// usize`, and both pointers are rewritten to non-optional slices of the same type, return
+    /// the element type of the slices.  `loc` is the location of the subtraction.
+    fn ptr_addr_diff_pointee(
+        &self,
+        loc: Location,
+        ops: &(Operand<'tcx>, Operand<'tcx>),
+    ) -> Option<Ty<'tcx>> {
+        let addr_source = |op| match self.temp_def_at(loc, op)? {
+            (_, Either::Left(Rvalue::Cast(CastKind::PointerExposeAddress, ptr, _))) => Some(ptr),
+            _ => None,
+        };
+        let ptr0 = addr_source(&ops.0)?;
+        let ptr1 = addr_source(&ops.1)?;
+        let desc0 = self.safe_slice_desc(ptr0)?;
+        let desc1 = self.safe_slice_desc(ptr1)?;
+        if desc0.option || desc1.option || desc0.pointee_ty != desc1.pointee_ty {
+            return None;
+        }
+        Some(desc0.pointee_ty)
+    }
+
+    /// Check how the rewritten form of the pointer `op` can be turned back into a raw pointer.
+    /// Returns `None` if there's no way to do so that preserves the pointer's address, as for
+    /// nullable pointers.
+    fn raw_ptr_of(&self, op: &Operand<'tcx>) -> Option<RawPtrOf> {
+        let lty = self.acx.type_of(op);
+        let ptr = lty.label;
+        if !lty.ty.is_unsafe_ptr() {
+            return None;
+        }
+        if ptr.is_none() || self.flags[ptr].contains(FlagSet::FIXED) {
+            return Some(RawPtrOf::Raw);
+        }
+        let desc = type_desc::perms_to_desc(lty.ty, self.perms[ptr], self.flags[ptr]);
+        if desc.option {
+            return None;
+        }
+        match (desc.own, desc.qty) {
+            (Ownership::Imm | Ownership::Mut | Ownership::Cell, Quantity::Slice) => {
+                Some(RawPtrOf::Slice)
+            }
+            (Ownership::Imm | Ownership::Mut | Ownership::Cell, Quantity::Single) => {
+                Some(RawPtrOf::Ref)
+            }
+            _ => None,
+        }
+    }
+
+    /// Visit a comparison `ops.0 == ops.1` or `ops.0 != ops.1`.  If it checks the alignment of a
+    /// pointer, as in `(p as usize & 7) == 0`, the comparison is replaced with a call to
+    /// `align_offset` or `is_aligned` on the pointer, which works whether or not the pointer is
+    /// rewritten.
+    fn visit_aligned_check(&mut self, bop: BinOp, ops: &(Operand<'tcx>, Operand<'tcx>)) {
+        let tcx = self.acx.tcx();
+        let (ptr, align) = match align::aligned_check_source(tcx, self.mir, bop, ops) {
+            Some(x) => x,
+            None => return,
+        };
+        let raw_ptr_of = match self.raw_ptr_of(ptr) {
+            Some(x) => x,
+            None => return,
+        };
+        let pointee_ty = match *self.acx.type_of(ptr).ty.kind() {
+            TyKind::RawPtr(tm) => tm.ty,
+            _ => return,
+        };
+        let pointee_align = tcx
+            .layout_of(ParamEnv::reveal_all().and(pointee_ty))
+            .ok()
+            .map(|layout| layout.layout.align().abi.bytes());
+        self.emit(RewriteKind::IsAligned {
+            align: Some(align).filter(|&align| Some(align) != pointee_align),
+            ne: bop == BinOp::Ne,
+            ptr: raw_ptr_of,
+        });
+    }
+
+    /// Visit a cast of `op` to the pointer type `ty`.  If `op` rounds a pointer `p` up to an
+    /// aligned address, as in `(p as usize + 7) & !7`, the cast is replaced with a call to
+    /// `align_offset` on `p`.  The dataflow analysis treats this like an offset of `p`, so `p`
+    /// may have become a slice.  Returns `true` if the cast was handled.
+    fn try_visit_align_up(&mut self, op: &Operand<'tcx>, ty: Ty<'tcx>, rv_lty: LTy<'tcx>) -> bool {
+        let (ptr, align) = match align::align_up_source(self.acx.tcx(), self.mir, op, ty) {
+            Some(x) => x,
+            None => return false,
+        };
+        let ptr_lty = self.acx.type_of(ptr);
+        let rw = match self.raw_ptr_of(ptr) {
+            Some(RawPtrOf::Raw) => RewriteKind::AlignUpRaw { align },
+            Some(RawPtrOf::Slice) => {
+                let desc = type_desc::perms_to_desc(
+                    ptr_lty.ty,
+                    self.perms[ptr_lty.label],
+                    self.flags[ptr_lty.label],
+                );
+                RewriteKind::AlignUpSlice {
+                    align,
+                    mutbl: desc.own == Ownership::Mut,
+                }
+            }
+            _ => return false,
+        };
+        self.enter_rvalue_operand(0, |v| v.visit_operand(op, None));
+        self.emit(rw);
+        // The result has the same type as `ptr`, which may need a cast to match `rv_lty`.
+        self.emit_cast_lty_lty(ptr_lty, rv_lty);
+        true
+    }
+
+    /// Check whether `op` is a byte string literal, possibly wrapped in some casts that were
+    /// assigned to temporaries.  For example, `b"hi\0" as *const u8` produces a MIR temporary
+    /// holding the literal, which is then cast to `*const u8`.  Returns the literal's constant.
+    fn byte_str_literal<'b>(&self, op: &'b Operand<'tcx>) -> Option<&'b Constant<'tcx>>
+    where
+        'a: 'b,
+    {
+        let (mut loc, mut op) = (self.loc, op);
+        // Each step moves back by one cast or copy, so a small limit is enough.
+        for _ in 0..4 {
+            if let Operand::Constant(ref c) = *op {
+                let is_byte_array_ref = match *c.ty().kind() {
+                    TyKind::Ref(_, ty, _) => matches!(
+                        *ty.kind(),
+                        TyKind::Array(elem_ty, _) if elem_ty == self.acx.tcx().types.u8
+                    ),
+                    _ => false,
+                };
+                let is_literal = is_byte_array_ref && crate::analyze::is_string_literal(c);
+                return if is_literal { Some(c) } else { None };
+            }
+            (loc, op) = match self.temp_def_at(loc, op)? {
+                (def_loc, Either::Left(Rvalue::Use(op) | Rvalue::Cast(_, op, _))) => (def_loc, op),
+                _ => return None,
+            };
+        }
+        None
+    }
+
+    /// Handle a cast of a byte string literal to a C string pointer.  If the result of the cast
+    /// is rewritten to a safe reference, the whole chain of casts is replaced with a `&CStr` or a
+    /// safe slice of the literal's bytes.  Returns `true` if the cast was handled.
+    fn try_visit_cstr_literal_cast(&mut self, op: &Operand<'tcx>, rv_lty: LTy<'tcx>) -> bool {
+        let tcx = self.acx.tcx();
+        let ptr = rv_lty.label;
+        if ptr.is_none() || self.flags[ptr].contains(FlagSet::FIXED) {
+            return false;
+        }
+        let signed = match *rv_lty.ty.kind() {
+            TyKind::RawPtr(tm) if tm.ty == tcx.types.i8 => true,
+            TyKind::RawPtr(tm) if tm.ty == tcx.types.u8 => false,
+            _ => return false,
+        };
+        let desc = type_desc::perms_to_desc(rv_lty.ty, self.perms[ptr], self.flags[ptr]);
+        if desc.own != Ownership::Imm {
+            return false;
+        }
+        // The literal must be a valid C string, with a NUL terminator and no interior NULs, so
+        // that `CStr::from_bytes_with_nul` can't fail on it.
+        let bytes = match self.byte_str_literal(op) {
+            Some(c) => byte_str_literal_bytes(tcx, c),
+            None => return false,
+        };
+        match bytes {
+            Some([rest @ .., 0]) if !rest.contains(&0) => {}
+            _ => return false,
+        }
+
+        let cstr = desc.qty == Quantity::CStr;
+        self.emit(RewriteKind::CStrLiteral { signed, cstr });
+        // `CStrLiteral` produces a non-optional `&[u8]`, `&[i8]`, or `&CStr`.
+        let qty = if cstr {
+            Quantity::CStr
+        } else {
+            Quantity::Slice
+        };
+        let literal_desc = TypeDesc {
+            own: Ownership::Imm,
+            qty,
+            option: false,
+            pointee_ty: desc.pointee_ty,
+        };
+        self.emit_cast_desc_desc(literal_desc, desc);
+        true
+    }
+
     fn emit(&mut self, rw: RewriteKind) {
         self.rewrites
             .entry(self.loc)
@@ -854,35 +2807,164 @@ impl<'a, 'tcx> ExprRewriteVisitor<'a, 'tcx> {
     fn emit_cast_desc_desc(&mut self, from: TypeDesc<'tcx>, to: TypeDesc<'tcx>) {
         let perms = self.perms;
         let flags = self.flags;
-        let mut builder = CastBuilder::new(self.acx.tcx(), &perms, &flags, |rk| self.emit(rk));
+        let mut builder = CastBuilder::new(
+            self.acx.tcx(),
+            &perms,
+            &flags,
+            self.acx.gacx.checked_offsets,
+            |rk| self.emit(rk),
+        );
         builder.build_cast_desc_desc(from, to);
     }
 
     fn emit_cast_lty_desc(&mut self, from_lty: LTy<'tcx>, to: TypeDesc<'tcx>) {
         let perms = self.perms;
         let flags = self.flags;
-        let mut builder = CastBuilder::new(self.acx.tcx(), &perms, &flags, |rk| self.emit(rk));
-        builder.build_cast_lty_desc(from_lty, to);
+        let old_len = self.num_rewrites_at_loc();
+        let mut builder = CastBuilder::new(
+            self.acx.tcx(),
+            &perms,
+            &flags,
+            self.acx.gacx.checked_offsets,
+            |rk| self.emit(rk),
+        );
+        let r = builder.try_build_cast_lty_desc(from_lty, to);
+        self.finish_cast(old_len, &[from_lty], r);
+    }
+
+    /// Like [`Self::emit_cast_lty_desc`], but a raw pointer may be converted to a slice of `len`
+    /// elements.
+    fn emit_cast_lty_desc_len(&mut self, from_lty: LTy<'tcx>, to: TypeDesc<'tcx>, len: Span) {
+        let perms = self.perms;
+        let flags = self.flags;
+        let old_len = self.num_rewrites_at_loc();
+        let mut builder = CastBuilder::new(
+            self.acx.tcx(),
+            &perms,
+            &flags,
+            self.acx.gacx.checked_offsets,
+            |rk| self.emit(rk),
+        );
+        builder.slice_len = Some(len);
+        let r = builder.try_build_cast_lty_desc(from_lty, to);
+        self.finish_cast(old_len, &[from_lty], r);
     }
 
     #[allow(dead_code)]
     fn emit_cast_desc_lty(&mut self, from: TypeDesc<'tcx>, to_lty: LTy<'tcx>) {
         let perms = self.perms;
         let flags = self.flags;
-        let mut builder = CastBuilder::new(self.acx.tcx(), &perms, &flags, |rk| self.emit(rk));
-        builder.build_cast_desc_lty(from, to_lty);
+        let old_len = self.num_rewrites_at_loc();
+        let mut builder = CastBuilder::new(
+            self.acx.tcx(),
+            &perms,
+            &flags,
+            self.acx.gacx.checked_offsets,
+            |rk| self.emit(rk),
+        );
+        let r = builder.try_build_cast_desc_lty(from, to_lty);
+        self.finish_cast(old_len, &[to_lty], r);
     }
 
     fn emit_cast_lty_lty(&mut self, from_lty: LTy<'tcx>, to_lty: LTy<'tcx>) {
         let perms = self.perms;
         let flags = self.flags;
-        let mut builder = CastBuilder::new(self.acx.tcx(), &perms, &flags, |rk| self.emit(rk));
-        builder.build_cast_lty_lty(from_lty, to_lty);
+        let sentinel_terminated = self.in_sentinel_arg;
+        let ffi_boundary = self.in_ffi_call;
+        let old_len = self.num_rewrites_at_loc();
+        let mut builder = CastBuilder::new(
+            self.acx.tcx(),
+            &perms,
+            &flags,
+            self.acx.gacx.checked_offsets,
+            |rk| self.emit(rk),
+        );
+        builder.sentinel_terminated = sentinel_terminated;
+        builder.ffi_boundary = ffi_boundary;
+        let r = builder.try_build_cast_lty_lty(from_lty, to_lty);
+        self.finish_cast(old_len, &[from_lty, to_lty], r);
+    }
+
+    /// Like [`Self::emit_cast_lty_lty`], but a raw pointer may be converted to a slice of `len`
+    /// elements.
+    fn emit_cast_lty_lty_len(&mut self, from_lty: LTy<'tcx>, to_lty: LTy<'tcx>, len: Span) {
+        let perms = self.perms;
+        let flags = self.flags;
+        let old_len = self.num_rewrites_at_loc();
+        let mut builder = CastBuilder::new(
+            self.acx.tcx(),
+            &perms,
+            &flags,
+            self.acx.gacx.checked_offsets,
+            |rk| self.emit(rk),
+        );
+        builder.slice_len = Some(len);
+        let r = builder.try_build_cast_lty_lty(from_lty, to_lty);
+        self.finish_cast(old_len, &[from_lty, to_lty], r);
+    }
+
+    fn num_rewrites_at_loc(&self) -> usize {
+        self.rewrites.get(&self.loc).map_or(0, |rws| rws.len())
+    }
+
+    /// Handle the result of building a cast between `ltys`.  On failure, this discards the
+    /// rewrites emitted for the partial cast, which started at index `old_len`, and records an
+    /// [`UnresolvedCast`] for the non-`FIXED` pointers among `ltys`.  If they're all `FIXED`
+    /// already, there's no way to resolve the conflict, so the rewrite of the whole function
+    /// fails instead.
+    fn finish_cast(&mut self, old_len: usize, ltys: &[LTy<'tcx>], r: Result<(), String>) {
+        let reason = match r {
+            Ok(()) => return,
+            Err(reason) => reason,
+        };
+        if let Some(rws) = self.rewrites.get_mut(&self.loc) {
+            rws.truncate(old_len);
+        }
+        let flags = self.flags;
+        let ptrs = ltys
+            .iter()
+            .map(|lty| lty.label)
+            .filter(|&ptr| !ptr.is_none() && !flags[ptr].contains(FlagSet::FIXED))
+            .collect::<Vec<_>>();
+        if ptrs.is_empty() {
+            panic!("failed to build cast: {reason}");
+        }
+        self.unresolved_cast(ptrs, reason);
+    }
+
+    fn unresolved_cast(&mut self, ptrs: Vec<PointerId>, reason: String) {
+        warn!("unresolved cast at {:?}: {}", self.loc, reason);
+        self.unresolved.push(UnresolvedCast {
+            span: self.mir.source_info(self.loc).span,
+            ptrs,
+            reason,
+        });
+    }
+
+    /// If `pl` is a pointer field that's paired with a length field, and the assignment at `span`
+    /// stores a known length alongside it, return the span of the length expression.  See
+    /// `crate::len_fields`.
+    fn stored_slice_len(&self, pl: Place<'tcx>, span: Span) -> Option<Span> {
+        let len_fields = &self.acx.gacx.len_fields;
+        let (base, elem) = pl.iter_projections().last()?;
+        let field = match elem {
+            PlaceElem::Field(field, _) => field,
+            _ => return None,
+        };
+        let adt_def = match *base.ty(self.mir, self.acx.tcx()).ty.kind() {
+            TyKind::Adt(adt_def, _) if adt_def.is_struct() => adt_def,
+            _ => return None,
+        };
+        let field_did = adt_def.non_enum_variant().fields[field.index()].did;
+        if !len_fields.fields.contains_key(&field_did) {
+            return None;
+        }
+        len_fields.lens.get(&span).copied()
     }
 }
 
 impl ZeroizeType {
-    fn from_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Option<ZeroizeType> {
+    pub fn from_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Option<ZeroizeType> {
         Some(match *ty.kind() {
             TyKind::Int(_) | TyKind::Uint(_) => ZeroizeType::Int,
             TyKind::Bool => ZeroizeType::Bool,
@@ -898,7 +2980,17 @@ impl ZeroizeType {
                     let zero = ZeroizeType::from_ty(tcx, ty)?;
                     fields.push((name, zero));
                 }
-                ZeroizeType::Struct(fields)
+                // The name is used in a struct expression, so generic arguments need a
+                // turbofish: `S::<T> { .. }`, not `S<T> { .. }`.
+                let mut name = tcx.def_path_str(adt_def.did());
+                let args = substs
+                    .non_erasable_generics()
+                    .map(|arg| arg.to_string())
+                    .collect::<Vec<_>>();
+                if !args.is_empty() {
+                    write!(name, "::<{}>", args.join(", ")).unwrap();
+                }
+                ZeroizeType::Struct(name, fields)
             }
             TyKind::Array(elem_ty, _) => {
                 let elem_zero = ZeroizeType::from_ty(tcx, elem_ty)?;
@@ -914,6 +3006,19 @@ pub struct CastBuilder<'a, 'tcx, PT1, PT2, F> {
     perms: &'a PT1,
     flags: &'a PT2,
     emit: F,
+    /// The number of elements behind the pointer being cast, if known.  This allows casting a
+    /// raw pointer to a slice.
+    pub slice_len: Option<Span>,
+    /// Whether to allow casting a pointer to a single element to a slice.  See
+    /// `GlobalAnalysisCtxt::checked_offsets`.
+    checked_offsets: bool,
+    /// Whether the pointer being cast points to an array terminated by a null or zero element.
+    /// This allows casting a raw pointer to a slice that ends at the sentinel.
+    pub sentinel_terminated: bool,
+    /// Whether the cast converts between a safe pointer and the raw pointer type used in the
+    /// signature of a foreign function.  Null raw pointers then stand for `None`, and slices are
+    /// converted with `as_ptr()` so they may be empty.
+    pub ffi_boundary: bool,
 }
 
 impl<'a, 'tcx, PT1, PT2, F> CastBuilder<'a, 'tcx, PT1, PT2, F>
@@ -926,6 +3031,7 @@ where
         tcx: TyCtxt<'tcx>,
         perms: &'a PT1,
         flags: &'a PT2,
+        checked_offsets: bool,
         emit: F,
     ) -> CastBuilder<'a, 'tcx, PT1, PT2, F> {
         CastBuilder {
@@ -933,6 +3039,10 @@ where
             perms,
             flags,
             emit,
+            slice_len: None,
+            checked_offsets,
+            sentinel_terminated: false,
+            ffi_boundary: false,
         }
     }
 
@@ -1009,8 +3119,38 @@ where
             }
         }
 
+        // A pointer returned by foreign code may be null, so it becomes `None` rather than
+        // panicking or producing a null reference.
+        if self.ffi_boundary
+            && !from.option
+            && to.option
+            && matches!(from.own, Ownership::Raw | Ownership::RawMut)
+            && matches!(to.own, Ownership::Imm | Ownership::Mut)
+            && from.qty == Quantity::Single
+            && to.qty == Quantity::Single
+        {
+            let mutbl = to.own == Ownership::Mut;
+            if mutbl && from.own == Ownership::Raw {
+                (self.emit)(RewriteKind::CastRawToRaw { to_mutbl: true });
+            }
+            (self.emit)(RewriteKind::UnsafeRawAsOptionRef { mutbl });
+            from.own = to.own;
+            from.option = true;
+        }
+
         let mut in_option_map = false;
-        if from.option && !to.option {
+        let mut unwrap_or_null = false;
+        if from.option
+            && !to.option
+            && self.ffi_boundary
+            && matches!(to.own, Ownership::Raw | Ownership::RawMut)
+        {
+            // Foreign code expects a null pointer for `None`.  The remaining casts are applied
+            // inside `Option::map`, and the result is unwrapped at the end.
+            (self.emit)(RewriteKind::OptionMapBegin);
+            from.option = false;
+            unwrap_or_null = true;
+        } else if from.option && !to.option {
             // Unwrap first, then perform remaining casts.
             (self.emit)(RewriteKind::OptionUnwrap);
             from.option = false;
@@ -1038,6 +3178,48 @@ where
         // on which we can safely adjust `Quantity`.
         from.own = self.cast_ownership(from, to, true)?;
 
+        // With a known length, a raw pointer to a single element can become a slice.  This is
+        // unsafe, but no more so than the raw pointer it replaces.
+        if let Some(len) = self.slice_len {
+            if from.qty == Quantity::Single
+                && matches!(from.own, Ownership::Raw | Ownership::RawMut)
+                && matches!(to.qty, Quantity::Slice | Quantity::OffsetPtr)
+                && matches!(to.own, Ownership::Imm | Ownership::Mut)
+            {
+                let mutbl = to.own == Ownership::Mut;
+                if mutbl && from.own == Ownership::Raw {
+                    (self.emit)(RewriteKind::CastRawToRaw { to_mutbl: true });
+                }
+                (self.emit)(RewriteKind::UnsafeCastRawToSlice { mutbl, len });
+                from.own = to.own;
+                from.qty = Quantity::Slice;
+            }
+        }
+
+        // Similarly, the length of a sentinel-terminated array can be found by scanning for the
+        // sentinel, which is no less safe than the loop in the callee that does the same.
+        if self.sentinel_terminated
+            && from.qty == Quantity::Single
+            && matches!(from.own, Ownership::Raw | Ownership::RawMut)
+            && matches!(to.qty, Quantity::Slice | Quantity::OffsetPtr)
+            && matches!(to.own, Ownership::Imm | Ownership::Mut)
+        {
+            let null = match *from.pointee_ty.kind() {
+                TyKind::RawPtr(_) => Some(true),
+                TyKind::Int(_) | TyKind::Uint(_) => Some(false),
+                _ => None,
+            };
+            if let Some(null) = null {
+                let mutbl = to.own == Ownership::Mut;
+                if mutbl && from.own == Ownership::Raw {
+                    (self.emit)(RewriteKind::CastRawToRaw { to_mutbl: true });
+                }
+                (self.emit)(RewriteKind::UnsafeCastRawToSentinelSlice { mutbl, null });
+                from.own = to.own;
+                from.qty = Quantity::Slice;
+            }
+        }
+
         // Safe casts that change `Quantity`.
         while from.qty != to.qty {
             // Mutability of `from`.  `None` here means that safe `Quantity` conversions aren't
@@ -1046,7 +3228,7 @@ where
             let opt_mutbl = match from.own {
                 // Note that `Cell` + `Slice` is `&[Cell<T>]`, not `&Cell<[T]>`, so it can be
                 // handled like any other `&[_]`.
-                Ownership::Imm | Ownership::Cell => Some(false),
+                Ownership::Imm | Ownership::Cell | Ownership::Atomic => Some(false),
                 Ownership::Mut => Some(true),
                 _ => None,
             };
@@ -1057,6 +3239,23 @@ where
                     return Err(format!("TODO: cast Array to {:?}", to.qty));
                     //from.qty = Quantity::Slice;
                 }
+                // `&CStr` converts only to and from raw pointers.  `CStr::from_ptr` is unsafe, but
+                // no more so than the raw pointer it replaces.
+                (Quantity::CStr, Quantity::Single)
+                    if matches!(to.own, Ownership::Raw | Ownership::RawMut) =>
+                {
+                    (self.emit)(RewriteKind::AsPtr);
+                    from.own = Ownership::Raw;
+                    from.qty = Quantity::Single;
+                }
+                (Quantity::Single, Quantity::CStr)
+                    if matches!(from.own, Ownership::Raw | Ownership::RawMut) =>
+                {
+                    (self.emit)(RewriteKind::UnsafeCStrFromPtr);
+                    from.own = Ownership::Imm;
+                    from.qty = Quantity::CStr;
+                }
+                (Quantity::CStr, _) | (_, Quantity::CStr) => break,
                 // Bidirectional conversions between `Slice` and `OffsetPtr`.
                 (Quantity::Slice, Quantity::OffsetPtr) | (Quantity::OffsetPtr, Quantity::Slice) => {
                     // Currently a no-op, since `Slice` and `OffsetPtr` are identical.
@@ -1065,6 +3264,21 @@ where
                 // `Slice` and `OffsetPtr` convert to `Single` the same way.
                 // TODO: when converting to `Ownership::Raw`/`RawMut`, use `slice.as_ptr()` to
                 // avoid panic on 0-length inputs
+                // At the FFI boundary, a slice that's converted to a raw pointer may be empty.
+                (_, Quantity::Single)
+                    if self.ffi_boundary
+                        && matches!(to.own, Ownership::Raw | Ownership::RawMut)
+                        && matches!(from.own, Ownership::Imm | Ownership::Mut) =>
+                {
+                    let mutbl = from.own == Ownership::Mut;
+                    (self.emit)(RewriteKind::SliceAsRawPtr { mutbl });
+                    from.own = if mutbl {
+                        Ownership::RawMut
+                    } else {
+                        Ownership::Raw
+                    };
+                    from.qty = Quantity::Single;
+                }
                 (_, Quantity::Single) => {
                     let rw = match opt_mutbl {
                         Some(mutbl) => RewriteKind::SliceFirst { mutbl },
@@ -1074,6 +3288,20 @@ where
                     from.qty = Quantity::Single;
                 }
 
+                // A pointer to a single element can only be offset by zero, which we generally
+                // can't prove.  In checked mode, it becomes a one-element slice, so an
+                // out-of-bounds offset panics at run time instead of preventing the rewrite.
+                (Quantity::Single, Quantity::Slice | Quantity::OffsetPtr)
+                    if self.checked_offsets =>
+                {
+                    let mutbl = match opt_mutbl {
+                        Some(mutbl) => mutbl,
+                        None => break,
+                    };
+                    (self.emit)(RewriteKind::SliceFromSingle { mutbl });
+                    from.qty = Quantity::Slice;
+                }
+
                 // Unsupported cases
                 (Quantity::Single, _) => break,
                 (_, Quantity::Array) => break,
@@ -1093,6 +3321,11 @@ where
             assert!(to.option);
             (self.emit)(RewriteKind::OptionMapEnd);
             from.option = true;
+        } else if unwrap_or_null {
+            (self.emit)(RewriteKind::OptionMapEnd);
+            (self.emit)(RewriteKind::OptionUnwrapOrNull {
+                mutbl: to.own == Ownership::RawMut,
+            });
         } else if !from.option && to.option {
             // Wrap at the end, after performing all other steps of the cast.
             (self.emit)(RewriteKind::OptionSome);
@@ -1136,12 +3369,12 @@ where
         Ok(match from.own {
             Ownership::Box => match to.own {
                 Ownership::Raw | Ownership::Imm => {
-                    return Err("TODO: cast Box to Imm".to_string());
-                    //Some(Ownership::Imm)
+                    (self.emit)(RewriteKind::Reborrow { mutbl: false });
+                    Some(Ownership::Imm)
                 }
-                Ownership::RawMut | Ownership::Mut => {
-                    return Err("TODO: cast Box to Mut".to_string());
-                    //Some(Ownership::Mut)
+                Ownership::RawMut | Ownership::Cell | Ownership::Mut => {
+                    (self.emit)(RewriteKind::Reborrow { mutbl: true });
+                    Some(Ownership::Mut)
                 }
                 _ => None,
             },
@@ -1167,13 +3400,28 @@ where
                 }
                 _ => None,
             },
-            Ownership::Cell => match to.own {
+            Ownership::Cell | Ownership::RefCell => match to.own {
                 Ownership::RawMut | Ownership::Raw if !early => {
                     (self.emit)(RewriteKind::AsPtr);
                     Some(Ownership::RawMut)
                 }
                 _ => None,
             },
+            // The atomic types have the same layout as the types they wrap, so these are plain
+            // pointer casts.  `as_ptr` isn't stable for them yet.
+            Ownership::Atomic => match to.own {
+                Ownership::RawMut | Ownership::Raw if !early => {
+                    let printer = FmtPrinter::new(self.tcx, Namespace::TypeNS);
+                    let pointee = from.pointee_ty.print(printer).unwrap().into_buffer();
+                    (self.emit)(RewriteKind::CastRefToRaw { mutbl: false });
+                    (self.emit)(RewriteKind::CastRawPointee {
+                        pointee,
+                        mutbl: true,
+                    });
+                    Some(Ownership::RawMut)
+                }
+                _ => None,
+            },
             Ownership::Imm => match to.own {
                 Ownership::Raw | Ownership::RawMut if !early => {
                     (self.emit)(RewriteKind::CastRefToRaw { mutbl: false });
@@ -1199,10 +3447,18 @@ where
                     (self.emit)(RewriteKind::UnsafeCastRawToRef { mutbl: false });
                     Some(Ownership::Cell)
                 }
+                Ownership::Atomic if !early => {
+                    (self.emit)(RewriteKind::CastRawPointee {
+                        pointee: atomic_ty_str(self.tcx, to.pointee_ty),
+                        mutbl: false,
+                    });
+                    (self.emit)(RewriteKind::UnsafeCastRawToRef { mutbl: false });
+                    Some(Ownership::Atomic)
+                }
                 _ => None,
             },
             Ownership::Raw => match to.own {
-                Ownership::RawMut | Ownership::Mut if !early => {
+                Ownership::RawMut | Ownership::Mut | Ownership::Atomic if !early => {
                     (self.emit)(RewriteKind::CastRawToRaw { to_mutbl: true });
                     Some(Ownership::RawMut)
                 }
@@ -1216,6 +3472,14 @@ where
     }
 
     pub fn build_cast_lty_desc(&mut self, from_lty: LTy<'tcx>, to: TypeDesc<'tcx>) {
+        self.try_build_cast_lty_desc(from_lty, to).unwrap()
+    }
+
+    pub fn try_build_cast_lty_desc(
+        &mut self,
+        from_lty: LTy<'tcx>,
+        to: TypeDesc<'tcx>,
+    ) -> Result<(), String> {
         let from = type_desc::perms_to_desc_with_pointee(
             self.tcx,
             to.pointee_ty,
@@ -1223,10 +3487,18 @@ where
             self.perms[from_lty.label],
             self.flags[from_lty.label],
         );
-        self.build_cast_desc_desc(from, to);
+        self.try_build_cast_desc_desc(from, to)
     }
 
     pub fn build_cast_desc_lty(&mut self, from: TypeDesc<'tcx>, to_lty: LTy<'tcx>) {
+        self.try_build_cast_desc_lty(from, to_lty).unwrap()
+    }
+
+    pub fn try_build_cast_desc_lty(
+        &mut self,
+        from: TypeDesc<'tcx>,
+        to_lty: LTy<'tcx>,
+    ) -> Result<(), String> {
         let to = type_desc::perms_to_desc_with_pointee(
             self.tcx,
             from.pointee_ty,
@@ -1234,22 +3506,30 @@ where
             self.perms[to_lty.label],
             self.flags[to_lty.label],
         );
-        self.build_cast_desc_desc(from, to);
+        self.try_build_cast_desc_desc(from, to)
     }
 
     pub fn build_cast_lty_lty(&mut self, from_lty: LTy<'tcx>, to_lty: LTy<'tcx>) {
+        self.try_build_cast_lty_lty(from_lty, to_lty).unwrap()
+    }
+
+    pub fn try_build_cast_lty_lty(
+        &mut self,
+        from_lty: LTy<'tcx>,
+        to_lty: LTy<'tcx>,
+    ) -> Result<(), String> {
         let Self { perms, flags, .. } = *self;
 
         if from_lty.label.is_none() && to_lty.label.is_none() {
             // Input and output are both non-pointers.
-            return;
+            return Ok(());
         }
 
         let from_raw = matches!(from_lty.ty.kind(), TyKind::RawPtr(..));
         let to_raw = matches!(to_lty.ty.kind(), TyKind::RawPtr(..));
         if !from_raw && !to_raw {
             // TODO: hack to work around issues with already-safe code
-            return;
+            return Ok(());
         }
 
         let from_fixed = flags[from_lty.label].contains(FlagSet::FIXED);
@@ -1262,35 +3542,68 @@ where
             (false, false) => {
                 let from = lty_to_desc(from_lty);
                 let to = lty_to_desc(to_lty);
-                self.build_cast_desc_desc(from, to);
+                self.try_build_cast_desc_desc(from, to)
             }
 
             (false, true) => {
                 let from = lty_to_desc(from_lty);
-                self.build_cast_desc_lty(from, to_lty);
+                self.try_build_cast_desc_lty(from, to_lty)
             }
 
             (true, false) => {
                 let to = lty_to_desc(to_lty);
-                self.build_cast_lty_desc(from_lty, to);
+                self.try_build_cast_lty_desc(from_lty, to)
             }
 
             (true, true) => {
                 // No-op.  Both sides are `FIXED`, so we assume the existing code is already valid.
+                Ok(())
             }
         }
     }
 }
 
+/// Get the bytes of the byte string literal `c`, including its NUL terminator.
+/// Print the atomic type that replaces `ty` behind an `Ownership::Atomic` pointer, such as
+/// `core::sync::atomic::AtomicPtr<T>` for `*mut T`.
+fn atomic_ty_str<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> String {
+    let path = type_desc::atomic_ty_path(ty)
+        .unwrap_or_else(|| panic!("no atomic type corresponds to {ty:?}"));
+    match *ty.kind() {
+        TyKind::RawPtr(tm) => {
+            let printer = FmtPrinter::new(tcx, Namespace::TypeNS);
+            let inner = tm.ty.print(printer).unwrap().into_buffer();
+            format!("{path}<{inner}>")
+        }
+        _ => path,
+    }
+}
+
+fn byte_str_literal_bytes<'tcx>(tcx: TyCtxt<'tcx>, c: &Constant<'tcx>) -> Option<&'tcx [u8]> {
+    let alloc_id = crate::context::const_alloc_id(c)?;
+    match tcx.try_get_global_alloc(alloc_id)? {
+        GlobalAlloc::Memory(alloc) => {
+            let alloc = alloc.inner();
+            Some(alloc.inspect_with_uninit_and_ptr_outside_interpreter(0..alloc.len()))
+        }
+        _ => None,
+    }
+}
+
 pub fn gen_mir_rewrites<'tcx>(
     acx: &AnalysisCtxt<'_, 'tcx>,
     asn: &Assignment,
     pointee_types: PointerTable<PointeeTypes<'tcx>>,
     mir: &Body<'tcx>,
-) -> (HashMap<Location, Vec<MirRewrite>>, DontRewriteFnReason) {
+    recent_writes: &RecentWrites,
+) -> (
+    HashMap<Location, Vec<MirRewrite>>,
+    DontRewriteFnReason,
+    Vec<UnresolvedCast>,
+) {
     let mut out = HashMap::new();
 
-    let mut v = ExprRewriteVisitor::new(acx, asn, pointee_types, &mut out, mir);
+    let mut v = ExprRewriteVisitor::new(acx, asn, pointee_types, &mut out, mir, recent_writes);
 
     for (bb_id, bb) in mir.basic_blocks().iter_enumerated() {
         for (i, stmt) in bb.statements.iter().enumerate() {
@@ -1311,5 +3624,6 @@ pub fn gen_mir_rewrites<'tcx>(
     }
 
     let errors = v.errors;
-    (out, errors)
+    let unresolved = v.unresolved;
+    (out, errors, unresolved)
 }
@@ -1,49 +1,158 @@
+use self::distribute::MacroRewrite;
 use self::mir_op::MirRewrite;
 use self::unlower::{MirOrigin, PreciseLoc};
-use crate::context::{AnalysisCtxt, Assignment};
+use crate::context::{AnalysisCtxt, Assignment, FlagSet};
 use crate::pointee_type::PointeeTypes;
-use crate::pointer_id::PointerTable;
+use crate::pointer_id::{PointerId, PointerTable};
+use crate::recent_writes::RecentWrites;
 use crate::rewrite::Rewrite;
+use log::*;
 use rustc_hir::def_id::DefId;
-use rustc_hir::BodyId;
-use rustc_middle::mir::{Body, Location};
-use rustc_middle::ty::TyCtxt;
+use rustc_hir::{BodyId, HirId};
+use rustc_middle::mir::visit::{PlaceContext, Visitor};
+use rustc_middle::mir::{Body, Local, Location};
+use rustc_middle::ty::{Ty, TyCtxt};
 use rustc_span::Span;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 mod convert;
 mod distribute;
 mod hir_only_casts;
 mod mir_op;
+mod out_params;
 mod unlower;
 
 // Helpers used by the shim builder.
 pub use self::convert::convert_cast_rewrite;
 pub use self::mir_op::CastBuilder;
 
+pub use self::mir_op::UnresolvedCast;
+pub use self::out_params::gen_out_param_rewrites;
+
+/// Generate an expression that produces a zero value of type `ty`, or `None` if there is no
+/// supported way to zero-initialize `ty`.
+pub fn gen_zeroize_expr<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Option<String> {
+    let zero_ty = mir_op::ZeroizeType::from_ty(tcx, ty)?;
+    Some(convert::generate_zeroize_expr(&zero_ty))
+}
+
+/// Like [`gen_zeroize_expr`], but leaves out the field named `skip` of the struct `ty`, for use
+/// when that field is removed from the struct definition.
+pub fn gen_zeroize_expr_without_field<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ty: Ty<'tcx>,
+    skip: &str,
+) -> Option<String> {
+    let mut zero_ty = mir_op::ZeroizeType::from_ty(tcx, ty)?;
+    if let mir_op::ZeroizeType::Struct(_, ref mut fields) = zero_ty {
+        fields.retain(|(name, _)| name != skip);
+    }
+    Some(convert::generate_zeroize_expr(&zero_ty))
+}
+
+/// Where the rewrite of one expression came from, for `--dry-run` and the rewrite manifest.
+#[derive(Clone, Debug)]
+pub struct ExprRewriteOrigin {
+    pub hir_id: HirId,
+    /// The MIR rewrites that were lifted into the rewrite of this expression.
+    pub kinds: Vec<mir_op::RewriteKind>,
+    /// The pointers used by the MIR statements those rewrites apply to.
+    pub ptrs: Vec<PointerId>,
+}
+
+/// Collect the locals used by the statement or terminator at `loc`.
+fn locals_at(mir: &Body, loc: Location) -> Vec<Local> {
+    struct LocalCollector(Vec<Local>);
+    impl<'tcx> Visitor<'tcx> for LocalCollector {
+        fn visit_local(&mut self, local: Local, _context: PlaceContext, _location: Location) {
+            self.0.push(local);
+        }
+    }
+
+    let mut v = LocalCollector(Vec::new());
+    mir.stmt_at(loc).either(
+        |stmt| v.visit_statement(stmt, loc),
+        |term| v.visit_terminator(term, loc),
+    );
+    v.0
+}
+
+/// Collect the pointers in the types of the locals and the rvalue at `loc`.
+fn ptrs_at(acx: &AnalysisCtxt, mir: &Body, loc: Location) -> Vec<PointerId> {
+    let mut ptrs = Vec::new();
+    let local_ptrs = locals_at(mir, loc).into_iter().flat_map(|local| {
+        let labels = acx.local_tys[local].iter().map(|lty| lty.label);
+        labels.chain([acx.addr_of_local[local]])
+    });
+    let rvalue_ptrs = acx
+        .rvalue_tys
+        .get(&loc)
+        .into_iter()
+        .flat_map(|lty| lty.iter().map(|lty| lty.label));
+    for ptr in local_ptrs.chain(rvalue_ptrs) {
+        if !ptr.is_none() && !ptrs.contains(&ptr) {
+            ptrs.push(ptr);
+        }
+    }
+    ptrs
+}
+
+/// Generate rewrites for the expressions in the body of `def_id`.  Also returns the casts that
+/// couldn't be built (see [`UnresolvedCast`]), where the rewrite of each expression came
+/// from, keyed by the expression's span, and the locations of any rewrites that introduce unsafe
+/// operations (see `RewriteKind::is_unsafe`).
 pub fn gen_expr_rewrites<'tcx>(
     acx: &mut AnalysisCtxt<'_, 'tcx>,
     asn: &Assignment,
     pointee_types: PointerTable<PointeeTypes<'tcx>>,
     def_id: DefId,
     mir: &Body<'tcx>,
+    recent_writes: &RecentWrites,
     hir_body_id: BodyId,
-) -> Vec<(Span, Rewrite)> {
-    let (mir_rewrites, errors) = mir_op::gen_mir_rewrites(acx, asn, pointee_types, mir);
+) -> (
+    Vec<(Span, Rewrite)>,
+    Vec<UnresolvedCast>,
+    HashMap<Span, ExprRewriteOrigin>,
+    Vec<Location>,
+) {
+    let (mir_rewrites, errors, mut unresolved) =
+        mir_op::gen_mir_rewrites(acx, asn, pointee_types, mir, recent_writes);
     if !errors.is_empty() {
         acx.gacx.dont_rewrite_fns.add(def_id, errors);
     }
+    let unsafe_locs = mir_rewrites
+        .iter()
+        .filter(|(_, rws)| rws.iter().any(|rw| rw.kind.is_unsafe()))
+        .map(|(&loc, _)| loc)
+        .collect();
     let unlower_map = unlower::unlower(acx.tcx(), mir, hir_body_id);
     debug_print_unlower_map(acx.tcx(), mir, &unlower_map, &mir_rewrites);
-    let rewrites_by_expr = distribute::distribute(acx.tcx(), unlower_map, mir_rewrites);
+    let (rewrites_by_expr, macro_rewrites) =
+        distribute::distribute(acx.tcx(), unlower_map, mir_rewrites);
+    unresolved.extend(unresolved_macro_rewrites(acx, asn, mir, &macro_rewrites));
 
     eprintln!("distributed rewrites:");
+    let mut origins = HashMap::<Span, ExprRewriteOrigin>::new();
     for (&hir_id, dist_rws) in &rewrites_by_expr {
         let ex = acx.tcx().hir().expect_expr(hir_id);
         eprintln!("  {:?}:", ex.span);
         for rw in dist_rws {
             eprintln!("    {rw:?}");
         }
+        let empty = ExprRewriteOrigin {
+            hir_id,
+            kinds: Vec::new(),
+            ptrs: Vec::new(),
+        };
+        let origin = origins.entry(ex.span.source_callsite()).or_insert(empty);
+        for rw in dist_rws {
+            origin.kinds.push(rw.rw.clone());
+            for ptr in ptrs_at(acx, mir, rw.loc) {
+                if !origin.ptrs.contains(&ptr) {
+                    origin.ptrs.push(ptr);
+                }
+            }
+        }
     }
 
     let address_of_rewrites = hir_only_casts::remove_hir_only_casts(acx.tcx(), hir_body_id, |ex| {
@@ -51,7 +160,49 @@ pub fn gen_expr_rewrites<'tcx>(
     });
     let mut hir_rewrites = convert::convert_rewrites(acx.tcx(), hir_body_id, rewrites_by_expr);
     hir_rewrites.extend(address_of_rewrites);
-    hir_rewrites
+    (hir_rewrites, unresolved, origins, unsafe_locs)
+}
+
+/// Rewrites on code generated by a macro can't be applied, since that code is part of the macro's
+/// definition.  Instead, the pointers used by each statement that needs one are reported as
+/// unresolved, so that they stay raw pointers and the rest of the function can still be
+/// rewritten.
+fn unresolved_macro_rewrites<'tcx>(
+    acx: &AnalysisCtxt<'_, 'tcx>,
+    asn: &Assignment,
+    mir: &Body<'tcx>,
+    macro_rewrites: &[MacroRewrite],
+) -> Vec<UnresolvedCast> {
+    let flags = asn.flags();
+    let mut unresolved = Vec::new();
+    let mut seen = HashSet::new();
+    for mrw in macro_rewrites {
+        if !seen.insert(mrw.loc) {
+            continue;
+        }
+        let mut ptrs = Vec::new();
+        for local in locals_at(mir, mrw.loc) {
+            let labels = acx.local_tys[local].iter().map(|lty| lty.label);
+            for ptr in labels.chain([acx.addr_of_local[local]]) {
+                if !ptr.is_none() && !flags[ptr].contains(FlagSet::FIXED) && !ptrs.contains(&ptr) {
+                    ptrs.push(ptr);
+                }
+            }
+        }
+        if ptrs.is_empty() {
+            error!(
+                "can't apply rewrite {:?} inside macro expansion at {:?}",
+                mrw.rw, mrw.span
+            );
+            continue;
+        }
+        unresolved.push(UnresolvedCast {
+            span: mrw.span,
+            ptrs,
+            reason: "rewrite inside macro expansion".into(),
+        });
+    }
+    unresolved
 }
 
 fn debug_print_unlower_map<'tcx>(
@@ -0,0 +1,173 @@
+//! Rewrites for functions with out-parameters, which become functions returning `Option<T>` or
+//! `Result<T, S>`.  See `crate::out_params` for the analysis that finds them.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::out_params::{self, OutDest, OutParamUse};
+use crate::rewrite::Rewrite;
+use rustc_hir::def::Namespace;
+use rustc_hir::{ExprKind, FnRetTy, PatKind};
+use rustc_middle::ty::print::{FmtPrinter, Print};
+use rustc_middle::ty::{Ty, TyCtxt};
+use rustc_span::{BytePos, Span};
+use std::collections::{HashMap, HashSet};
+
+fn print_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> String {
+    let printer = FmtPrinter::new(tcx, Namespace::TypeNS);
+    ty.print(printer).unwrap().into_buffer()
+}
+
+/// The span to delete to remove the element at `index` from a comma-separated list, including
+/// one of the commas next to it.
+fn list_elem_removal_span(spans: &[Span], index: usize) -> Span {
+    let span = spans[index];
+    if index > 0 {
+        spans[index - 1].between(span).to(span)
+    } else if spans.len() > 1 {
+        span.until(spans[1])
+    } else {
+        span
+    }
+}
+
+pub fn gen_out_param_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let info = &gacx.out_params;
+    let mut rewrites = Vec::new();
+    if info.fns.is_empty() {
+        return rewrites;
+    }
+
+    // The name of each function's out-parameter.
+    let mut names = HashMap::new();
+    for (&did, f) in &info.fns {
+        let ldid = did.expect_local();
+        let hir_id = tcx.hir().local_def_id_to_hir_id(ldid);
+        let decl = tcx
+            .hir()
+            .fn_decl_by_hir_id(hir_id)
+            .unwrap_or_else(|| panic!("{did:?} should be a function"));
+        let body = tcx.hir().body(tcx.hir().body_owned_by(ldid));
+        let out_ty = print_ty(tcx, f.ty);
+
+        // `-> c_int` -> `-> Option<T>` or `-> Result<T, c_int>`
+        if let FnRetTy::Return(ty) = decl.output {
+            let rw = if f.fail_status.is_some() {
+                Rewrite::TyCtor("Option".into(), vec![Rewrite::Print(out_ty.clone())])
+            } else {
+                Rewrite::TyCtor(
+                    "Result".into(),
+                    vec![Rewrite::Print(out_ty.clone()), Rewrite::Identity],
+                )
+            };
+            rewrites.push((ty.span, rw));
+        }
+
+        // `(x: u8, out: *mut T)` -> `(x: u8)`
+        let param_spans = body.params.iter().map(|p| p.span).collect::<Vec<_>>();
+        rewrites.push((
+            list_elem_removal_span(&param_spans, f.index),
+            Rewrite::Text(String::new()),
+        ));
+
+        // The out-parameter becomes a local of the same name, so assignments to `*out` only need
+        // the dereference removed.
+        let name = match body.params[f.index].pat.kind {
+            PatKind::Binding(_, _, ident, None) => ident.to_string(),
+            _ => unreachable!("out-parameter should be a simple binding"),
+        };
+        names.insert(did, name.clone());
+        let block_span = match body.value.kind {
+            ExprKind::Block(block, None) => block.span,
+            _ => unreachable!("function body should be a block"),
+        };
+        let zero =
+            super::gen_zeroize_expr(tcx, f.ty).expect("out-parameter type should be zeroizable");
+        let after_brace = block_span
+            .with_lo(block_span.lo() + BytePos(1))
+            .shrink_to_lo();
+        rewrites.push((
+            after_brace,
+            Rewrite::Text(format!("\n    let mut {name}: {out_ty} = {zero};")),
+        ));
+    }
+
+    let users = info
+        .fns
+        .keys()
+        .chain(&info.callers)
+        .copied()
+        .collect::<HashSet<_>>();
+    for did in users {
+        let ldid = match did.as_local() {
+            Some(x) => x,
+            None => continue,
+        };
+        for u in out_params::collect_out_param_uses(tcx, ldid, info) {
+            match u {
+                // `*out` -> `out`
+                OutParamUse::Deref { expr } => {
+                    rewrites.push((expr, Rewrite::Text(names[&did].clone())));
+                }
+                // `0` -> `Some(out)` or `Ok(out)`
+                OutParamUse::ReturnOk { expr } => {
+                    let ctor = if info.fns[&did].fail_status.is_some() {
+                        "Some"
+                    } else {
+                        "Ok"
+                    };
+                    rewrites.push((
+                        expr,
+                        Rewrite::Call(ctor.into(), vec![Rewrite::Text(names[&did].clone())]),
+                    ));
+                }
+                // `-1` -> `None` or `Err(-1)`
+                OutParamUse::ReturnErr { expr } => {
+                    let rw = if info.fns[&did].fail_status.is_some() {
+                        Rewrite::Text("None".into())
+                    } else {
+                        Rewrite::Call("Err".into(), vec![Rewrite::Identity])
+                    };
+                    rewrites.push((expr, rw));
+                }
+                // `f(x, &mut y)` -> `f(x).map_or(-1, |out_value| { y = out_value; 0 })`
+                OutParamUse::Call {
+                    call,
+                    callee,
+                    out_arg,
+                    dest,
+                } => {
+                    rewrites.push((out_arg, Rewrite::Text(String::new())));
+                    let dest = match dest {
+                        OutDest::Place(span) => Rewrite::Extract(span),
+                        OutDest::Ptr(span) => Rewrite::Deref(Box::new(Rewrite::Extract(span))),
+                    };
+                    let store = Rewrite::Closure1(
+                        "out_value".into(),
+                        Box::new(Rewrite::Block(
+                            vec![Rewrite::Binary(
+                                "=".into(),
+                                Box::new(dest),
+                                Box::new(Rewrite::Text("out_value".into())),
+                            )],
+                            Some(Box::new(Rewrite::LitZero)),
+                        )),
+                    );
+                    let rw = match info.fns[&callee].fail_status {
+                        Some(status) => Rewrite::MethodCall(
+                            "map_or".into(),
+                            Box::new(Rewrite::Identity),
+                            vec![Rewrite::Text(status.to_string()), store],
+                        ),
+                        None => Rewrite::MethodCall(
+                            "map_or_else".into(),
+                            Box::new(Rewrite::Identity),
+                            vec![Rewrite::Text("|status| status".into()), store],
+                        ),
+                    };
+                    rewrites.push((call, rw));
+                }
+            }
+        }
+    }
+    rewrites
+}
@@ -11,6 +11,7 @@ use rustc_middle::hir::nested_filter;
 use rustc_middle::mir::{self, Body, Location, Operand};
 use rustc_middle::ty::adjustment::{Adjust, AutoBorrow, AutoBorrowMutability, PointerCast};
 use rustc_middle::ty::{TyCtxt, TypeckResults};
+use rustc_span::hygiene::ExpnKind;
 use rustc_span::Span;
 use std::collections::btree_map::{BTreeMap, Entry};
 use std::collections::HashMap;
@@ -64,6 +65,11 @@ pub enum MirOriginDesc {
     /// This MIR loads from a MIR temporary the intermediate value used as input for adjustment `i`
     /// of the HIR expression.
     LoadFromTempForAdjustment(usize),
+    /// This MIR was generated by a macro, and the HIR expression is the outermost expression of
+    /// the macro's expansion.  The code that produced the MIR is part of the macro's definition,
+    /// so rewrites here can't be applied to the source.  This is recorded only with an empty
+    /// `SubLoc` path, and covers the entire statement.
+    InMacroExpansion,
 }
 
 struct UnlowerVisitor<'a, 'tcx> {
@@ -78,6 +84,9 @@ struct UnlowerVisitor<'a, 'tcx> {
     /// list retrieved from the `SpanIndex`.  This is used in cases where some MIR statements have
     /// their spans set to a parent expr but really belong to the child.
     append_extra_locations: HashMap<HirId, Vec<Location>>,
+
+    /// The outermost expression of the macro expansion currently being visited, if any.
+    expansion_root: Option<&'tcx hir::Expr<'tcx>>,
 }
 
 impl<'a, 'tcx> UnlowerVisitor<'a, 'tcx> {
@@ -196,6 +205,37 @@ impl<'a, 'tcx> UnlowerVisitor<'a, 'tcx> {
         false
     }
 
+    /// Attribute the MIR generated by the macro expansion whose outermost expression is `root` to
+    /// `root` itself.  Locations already mapped to an expression, such as those for the macro's
+    /// arguments and the result of `root`, are left alone.
+    fn record_expansion(&mut self, root: &hir::Expr) {
+        let callsite = root.span.source_callsite();
+        for (bb, bb_data) in self.mir.basic_blocks().iter_enumerated() {
+            for i in 0..=bb_data.statements.len() {
+                let loc = Location {
+                    block: bb,
+                    statement_index: i,
+                };
+                let span = self.location_span(loc);
+                if !is_macro_expansion(span)
+                    || span.source_callsite() != callsite
+                    || self.should_ignore_statement(loc)
+                {
+                    continue;
+                }
+                let key = PreciseLoc { loc, sub: vec![] };
+                let mapped = self
+                    .unlower_map
+                    .range(key..)
+                    .next()
+                    .map_or(false, |(k, _)| k.loc == loc);
+                if !mapped {
+                    self.record_desc(loc, &[], root, MirOriginDesc::InMacroExpansion);
+                }
+            }
+        }
+    }
+
     fn visit_expr_inner(&mut self, ex: &'tcx hir::Expr<'tcx>) {
         let _g = panic_detail::set_current_span(ex.span);
 
@@ -316,6 +356,13 @@ impl<'a, 'tcx> UnlowerVisitor<'a, 'tcx> {
                 // the final result into a temporary.
                 let (_mir_pl, mut cursor) = match self.make_visit_expr_cursor(&locs) {
                     Some(x @ (pl, _)) if is_var(pl) => x,
+                    Some((_, cursor)) if matches!(ex.kind, hir::ExprKind::AssignOp(..)) => {
+                        // A compound assignment through a pointer, like `*p += 1`, stores
+                        // directly into the LHS place rather than into a temporary.  Record the
+                        // final statement so the whole assignment can be rewritten.
+                        self.record(cursor.loc, &[], ex);
+                        return;
+                    }
                     _ => {
                         warn("expected final Assign to store into var");
                         return;
@@ -594,12 +641,47 @@ impl<'a, 'tcx> Visitor<'tcx> for UnlowerVisitor<'a, 'tcx> {
         self.tcx.hir()
     }
 
+    fn visit_nested_body(&mut self, _id: hir::BodyId) {
+        // Closures and constants nested in this body are lowered to MIR bodies of their own, and
+        // are unlowered separately against those bodies.  Their spans fall within this body's
+        // spans, so visiting them here could attribute this body's MIR to their expressions.
+    }
+
     fn visit_expr(&mut self, ex: &'tcx hir::Expr<'tcx>) {
-        self.visit_expr_inner(ex);
-        intravisit::walk_expr(self, ex);
+        if !is_macro_expansion(ex.span) {
+            // This includes macro arguments, which are written at the call site.
+            self.visit_expr_inner(ex);
+            intravisit::walk_expr(self, ex);
+            return;
+        }
+
+        match self.expansion_root {
+            None => {
+                // The outermost expression of a macro expansion.  Its result is produced at the
+                // call site, so it's mapped like any other expression.  The MIR for the rest of
+                // the expansion is attributed to it afterward.
+                self.expansion_root = Some(ex);
+                self.visit_expr_inner(ex);
+                intravisit::walk_expr(self, ex);
+                self.record_expansion(ex);
+                self.expansion_root = None;
+            }
+            Some(_) => {
+                // Code from the macro's definition.  Several expressions may share a span here,
+                // so the MIR can't be matched up with them reliably.
+                self.append_extra_locations.remove(&ex.hir_id);
+                intravisit::walk_expr(self, ex);
+            }
+        }
     }
 }
 
+/// Returns `true` if `span` is part of the expansion of a macro.  Desugarings, such as `for` loops
+/// and `?`, are handled like ordinary code.
+fn is_macro_expansion(span: Span) -> bool {
+    span.from_expansion() && matches!(span.ctxt().outer_expn_data().kind, ExpnKind::Macro(..))
+}
+
 /// The MIR representation of some part of a `hir::Expr` or its adjustments.
 #[derive(Clone, Copy, Debug)]
 enum ExprMir<'a, 'tcx> {
@@ -1058,6 +1140,7 @@ pub fn unlower<'tcx>(
         span_index,
         unlower_map: BTreeMap::new(),
         append_extra_locations: HashMap::new(),
+        expansion_root: None,
     };
     visitor.visit_body(hir);
 
@@ -0,0 +1,191 @@
+//! Rewrites for `FILE*` locals that become `Option<BufReader<File>>` or
+//! `Option<BufWriter<File>>`.  See `crate::file_io` for the analysis that finds them.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::file_io::{self, FileMode, FileUse};
+use crate::rewrite::Rewrite;
+use rustc_span::Span;
+
+fn buf_ty(mode: FileMode) -> &'static str {
+    if mode.is_write() {
+        "std::io::BufWriter"
+    } else {
+        "std::io::BufReader"
+    }
+}
+
+pub fn gen_file_io_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let mut rewrites = Vec::new();
+    for &did in &gacx.file_io.fns {
+        let ldid = match did.as_local() {
+            Some(x) => x,
+            None => continue,
+        };
+        for u in file_io::collect_file_uses(tcx, ldid) {
+            match u {
+                FileUse::Open {
+                    ty,
+                    init,
+                    path,
+                    path_lit,
+                    mode,
+                } => {
+                    // `*mut FILE` -> `Option<BufReader<File>>`
+                    if let Some(ty) = ty {
+                        let file_ty = Rewrite::TyCtor(
+                            buf_ty(mode).into(),
+                            vec![Rewrite::Print("std::fs::File".into())],
+                        );
+                        rewrites.push((ty, Rewrite::TyCtor("Option".into(), vec![file_ty])));
+                    }
+
+                    // `fopen(path, b"r\0" ...)` -> `File::open(path).ok().map(BufReader::new)`,
+                    // where `path` is converted with `OsStr::from_bytes(CStr::from_ptr(path)
+                    // .to_bytes())`, which keeps non-UTF-8 paths intact
+                    let path = match path_lit {
+                        Some(lit) => Rewrite::Text(format!("{lit:?}")),
+                        None => {
+                            let cstr = Rewrite::Call(
+                                "std::ffi::CStr::from_ptr".into(),
+                                vec![Rewrite::Sub(0, path)],
+                            );
+                            let bytes =
+                                Rewrite::MethodCall("to_bytes".into(), Box::new(cstr), vec![]);
+                            Rewrite::Call(
+                                "<std::ffi::OsStr as std::os::unix::ffi::OsStrExt>::from_bytes"
+                                    .into(),
+                                vec![bytes],
+                            )
+                        }
+                    };
+                    let open = match mode {
+                        FileMode::Read => Rewrite::Call("std::fs::File::open".into(), vec![path]),
+                        FileMode::Write => {
+                            Rewrite::Call("std::fs::File::create".into(), vec![path])
+                        }
+                        FileMode::Append => Rewrite::MethodCall(
+                            "open".into(),
+                            Box::new(Rewrite::Text(
+                                "std::fs::OpenOptions::new().append(true).create(true)".into(),
+                            )),
+                            vec![path],
+                        ),
+                    };
+                    let ok = Rewrite::MethodCall("ok".into(), Box::new(open), vec![]);
+                    let buf_new = Rewrite::Text(format!("{}::new", buf_ty(mode)));
+                    rewrites.push((
+                        init,
+                        Rewrite::MethodCall("map".into(), Box::new(ok), vec![buf_new]),
+                    ));
+                }
+
+                // `f.is_null()` -> `f.is_none()`
+                FileUse::IsNull { expr, var } => {
+                    rewrites.push((
+                        expr,
+                        Rewrite::MethodCall(
+                            "is_none".into(),
+                            Box::new(Rewrite::Sub(0, var)),
+                            vec![],
+                        ),
+                    ));
+                }
+
+                // `fread(ptr, size, count, f)` -> read into `from_raw_parts_mut(ptr, size * count)`
+                // until it's full or the file ends.  `fwrite` writes all of the same slice.
+                FileUse::ReadWrite {
+                    call,
+                    ptr,
+                    size,
+                    count,
+                    var,
+                    write,
+                    ret_ty,
+                } => {
+                    let (ptr_ty, from_raw_parts) = if write {
+                        ("*const u8", "std::slice::from_raw_parts")
+                    } else {
+                        ("*mut u8", "std::slice::from_raw_parts_mut")
+                    };
+                    let usize_ty = || Box::new(Rewrite::Print("usize".into()));
+                    let file = Rewrite::MethodCall(
+                        "unwrap".into(),
+                        Box::new(Rewrite::MethodCall(
+                            "as_mut".into(),
+                            Box::new(Rewrite::Sub(3, var)),
+                            vec![],
+                        )),
+                        vec![],
+                    );
+                    let mut stmts = vec![
+                        Rewrite::Let(vec![
+                            (
+                                "ptr".into(),
+                                Rewrite::Cast(
+                                    Box::new(Rewrite::Sub(0, ptr)),
+                                    Box::new(Rewrite::Print(ptr_ty.into())),
+                                ),
+                            ),
+                            (
+                                "size".into(),
+                                Rewrite::Cast(Box::new(Rewrite::Sub(1, size)), usize_ty()),
+                            ),
+                            (
+                                "count".into(),
+                                Rewrite::Cast(Box::new(Rewrite::Sub(2, count)), usize_ty()),
+                            ),
+                            ("f".into(), file),
+                        ]),
+                        Rewrite::Let1(
+                            "buf".into(),
+                            Box::new(Rewrite::Text(format!(
+                                "{from_raw_parts}(ptr, size * count)"
+                            ))),
+                        ),
+                    ];
+                    let result = if write {
+                        // `fwrite` only reports the whole count if every item was written.
+                        format!(
+                            "match std::io::Write::write_all(f, buf) {{ \
+                                Ok(()) => count as {ret_ty}, Err(_) => 0 }}"
+                        )
+                    } else {
+                        stmts.push(Rewrite::Text("let mut n = 0".into()));
+                        stmts.push(Rewrite::Text(
+                            "while n < buf.len() { match std::io::Read::read(f, &mut buf[n..]) { \
+                                Ok(0) | Err(_) => break, Ok(k) => n += k } }"
+                                .into(),
+                        ));
+                        format!("(if size == 0 {{ 0 }} else {{ n / size }}) as {ret_ty}")
+                    };
+                    rewrites.push((
+                        call,
+                        Rewrite::Block(stmts, Some(Box::new(Rewrite::Text(result)))),
+                    ));
+                }
+
+                // `fclose(f)` -> `f.take().map_or(-1, |_| 0)`, which closes the file.  A
+                // `BufWriter` is flushed first, so that write errors are reported as in C.
+                FileUse::Close { call, var, mode } => {
+                    let close = if mode.is_write() {
+                        "|mut f| std::io::Write::flush(&mut f).map_or(-1, |()| 0)"
+                    } else {
+                        "|_| 0"
+                    };
+                    let take =
+                        Rewrite::MethodCall("take".into(), Box::new(Rewrite::Sub(0, var)), vec![]);
+                    rewrites.push((
+                        call,
+                        Rewrite::MethodCall(
+                            "map_or".into(),
+                            Box::new(take),
+                            vec![Rewrite::Text("-1".into()), Rewrite::Text(close.into())],
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    rewrites
+}
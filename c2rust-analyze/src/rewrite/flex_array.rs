@@ -0,0 +1,125 @@
+//! Rewrites for the uses of structs with a flexible array member, whose tail becomes a trailing
+//! `Vec<T>`.  See `crate::flex_array` for the analysis that finds them.
+//!
+//! The tail's type is rewritten by `gen_adt_ty_rewrites`.  Functions that allocate or free these
+//! structs or access their tails are excluded from the normal rewrites, so this module handles
+//! those uses, leaving pointers to the structs as raw pointers.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::flex_array::{self, FlexArrayUse};
+use crate::rewrite::{self, Rewrite};
+use rustc_hir::def::Namespace;
+use rustc_middle::ty::print::{FmtPrinter, Print};
+use rustc_middle::ty::{Ty, TyCtxt};
+use rustc_span::Span;
+use std::fmt::Write as _;
+
+/// Build a struct literal for `adt_ty` with every field zeroed and an empty tail, as in
+/// `S { len: 0, data: Vec::new() }`.
+fn gen_empty_struct_expr<'tcx>(tcx: TyCtxt<'tcx>, adt_ty: Ty<'tcx>, tail: &str) -> String {
+    let adt_def = adt_ty.ty_adt_def().unwrap();
+    let printer = FmtPrinter::new(tcx, Namespace::TypeNS);
+    let mut s = adt_ty.print(printer).unwrap().into_buffer();
+    s.push_str(" { ");
+    for field in adt_def.all_fields() {
+        let name = field.name;
+        if name.as_str() == tail {
+            write!(s, "{name}: Vec::new(), ").unwrap();
+        } else {
+            let zero = rewrite::gen_zeroize_expr(tcx, tcx.type_of(field.did))
+                .expect("flexible array struct should have been checked for zeroize support");
+            write!(s, "{name}: {zero}, ").unwrap();
+        }
+    }
+    s.push('}');
+    s
+}
+
+pub fn gen_flex_array_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let info = &gacx.flex_arrays;
+    let mut rewrites = Vec::new();
+    if info.structs.is_empty() {
+        return rewrites;
+    }
+
+    for &did in &info.fns {
+        let ldid = match did.as_local() {
+            Some(x) => x,
+            None => continue,
+        };
+        for (adt, u) in flex_array::collect_flex_array_uses(tcx, ldid, &info.structs) {
+            let (span, rw) = match u {
+                // `*(*p).data.as_mut_ptr().offset(i)` -> `(*p).data[i as usize]`
+                FlexArrayUse::Elem { expr, tail, index } => {
+                    let index = match index {
+                        Some(index) => Rewrite::Cast(
+                            Box::new(Rewrite::Sub(1, index)),
+                            Box::new(Rewrite::Print("usize".into())),
+                        ),
+                        None => Rewrite::LitZero,
+                    };
+                    (
+                        expr,
+                        Rewrite::Index(Box::new(Rewrite::Sub(0, tail)), Box::new(index)),
+                    )
+                }
+                // `malloc(size_of::<S>() + n * size_of::<T>()) as *mut S` ->
+                // `{ let (tail_len, ptr) = (n as usize, Box::into_raw(Box::new(S { .. })));
+                //    (*ptr).data.resize_with(tail_len, || 0); ptr }`
+                FlexArrayUse::Alloc {
+                    expr,
+                    count,
+                    adt_ty,
+                } => {
+                    let tail = tcx.item_name(info.structs[&adt]);
+                    let empty = gen_empty_struct_expr(tcx, adt_ty, tail.as_str());
+                    let boxed = Rewrite::Call(
+                        "Box::into_raw".into(),
+                        vec![Rewrite::Call("Box::new".into(), vec![Rewrite::Text(empty)])],
+                    );
+                    match count {
+                        Some(count) => {
+                            let elem_ty = flex_array::tail_elem_ty(tcx, info.structs[&adt]);
+                            let zero = rewrite::gen_zeroize_expr(tcx, elem_ty).expect(
+                                "flexible array struct should have been checked for zeroize support",
+                            );
+                            let len = Rewrite::Cast(
+                                Box::new(Rewrite::Sub(0, count)),
+                                Box::new(Rewrite::Print("usize".into())),
+                            );
+                            let resize = Rewrite::Text(format!(
+                                "(*ptr).{tail}.resize_with(tail_len, || {zero})"
+                            ));
+                            (
+                                expr,
+                                Rewrite::Block(
+                                    vec![
+                                        Rewrite::Let(vec![
+                                            ("tail_len".into(), len),
+                                            ("ptr".into(), boxed),
+                                        ]),
+                                        resize,
+                                    ],
+                                    Some(Box::new(Rewrite::Text("ptr".into()))),
+                                ),
+                            )
+                        }
+                        None => (expr, boxed),
+                    }
+                }
+                // `free(p as *mut c_void)` -> `drop(Box::from_raw(p))`
+                FlexArrayUse::Free { call, ptr } => {
+                    let boxed = Rewrite::Call("Box::from_raw".into(), vec![Rewrite::Sub(0, ptr)]);
+                    (call, Rewrite::Call("std::mem::drop".into(), vec![boxed]))
+                }
+                FlexArrayUse::Other => {
+                    // `find_flex_array_structs` rejects any struct with an `Other` use.
+                    unreachable!("unsupported use of flexible array struct")
+                }
+            };
+            rewrites.push((span, rw));
+        }
+    }
+    rewrites
+}
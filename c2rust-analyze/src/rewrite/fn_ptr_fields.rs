@@ -0,0 +1,74 @@
+//! Rewrites for `void*` fields that hold function pointers.  See `crate::fn_ptr_fields` for the
+//! analysis that finds them.
+//!
+//! The field types themselves are rewritten by `gen_adt_ty_rewrites`.  This module handles the
+//! uses of those fields, which are rewritten in every function, whether or not the function is
+//! otherwise being rewritten.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::fn_ptr_fields::{self, FnPtrFieldUse};
+use crate::rewrite::Rewrite;
+use rustc_span::Span;
+use std::collections::HashSet;
+
+pub fn gen_fn_ptr_field_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let mut rewrites = Vec::new();
+    if gacx.fn_ptr_fields.is_empty() {
+        return rewrites;
+    }
+
+    let fields = gacx.fn_ptr_fields.keys().copied().collect::<HashSet<_>>();
+    for (_, u) in fn_ptr_fields::collect_fn_ptr_field_uses(gacx.tcx, &fields) {
+        let (span, rw) = match u {
+            // `g as *mut c_void` -> `Some(g)`
+            FnPtrFieldUse::StoreFn {
+                value,
+                func,
+                is_option,
+                ..
+            } => {
+                let func_rw = Rewrite::Sub(0, func);
+                if is_option {
+                    (value, func_rw)
+                } else {
+                    (value, Rewrite::Call("Some".into(), vec![func_rw]))
+                }
+            }
+            // `ptr::null_mut()` -> `None`
+            FnPtrFieldUse::StoreNull { value } => (value, Rewrite::Text("None".into())),
+            // `transmute::<_, Option<F>>(s.f)` -> `s.f`
+            // `transmute::<_, F>(s.f)` -> `s.f.expect("non-null function pointer")`
+            FnPtrFieldUse::Load {
+                call,
+                field,
+                is_option,
+                ..
+            } => {
+                let field_rw = Rewrite::Sub(0, field);
+                if is_option {
+                    (call, field_rw)
+                } else {
+                    (
+                        call,
+                        Rewrite::MethodCall(
+                            "expect".into(),
+                            Box::new(field_rw),
+                            vec![Rewrite::Text("\"non-null function pointer\"".into())],
+                        ),
+                    )
+                }
+            }
+            // `s.f.is_null()` -> `s.f.is_none()`
+            FnPtrFieldUse::IsNull { call, field } => (
+                call,
+                Rewrite::MethodCall("is_none".into(), Box::new(Rewrite::Sub(0, field)), vec![]),
+            ),
+            FnPtrFieldUse::Other => {
+                // `find_fn_ptr_fields` rejects any field with an `Other` use.
+                unreachable!("unsupported use of fn ptr field")
+            }
+        };
+        rewrites.push((span, rw));
+    }
+    rewrites
+}
@@ -0,0 +1,49 @@
+//! Rewrites for the functions stored into function table fields.  See `crate::fn_tables` for the
+//! analysis.
+//!
+//! The field types and the signatures of the stored functions are rewritten by the usual passes,
+//! and they stay consistent because their pointers are unified.  But a store like
+//! `Some(f as unsafe extern "C" fn(*mut i32))` still casts `f` to the old type, so this module
+//! removes the cast and lets `f` be coerced to the field's new type instead.
+
+use crate::context::{FlagSet, GlobalAnalysisCtxt, GlobalAssignment};
+use crate::fn_tables::{self, FnTableStore};
+use crate::rewrite::Rewrite;
+use rustc_span::Span;
+use std::collections::HashSet;
+
+pub fn gen_fn_table_rewrites(
+    gacx: &GlobalAnalysisCtxt,
+    gasn: &GlobalAssignment,
+) -> Vec<(Span, Rewrite)> {
+    let mut rewrites = Vec::new();
+
+    // Fields whose function pointer types will be rewritten.
+    let fields = gacx
+        .fn_table_fields
+        .keys()
+        .copied()
+        .filter(|&did| fn_tables::field_targets(gacx, did).is_some())
+        .filter(|did| {
+            gacx.field_ltys[did]
+                .iter()
+                .any(|lty| !lty.label.is_none() && !gasn.flags[lty.label].contains(FlagSet::FIXED))
+        })
+        .collect::<HashSet<_>>();
+    if fields.is_empty() {
+        return rewrites;
+    }
+
+    for (_, store) in fn_tables::collect_fn_table_stores(gacx.tcx, &fields) {
+        // `f as unsafe extern "C" fn(..)` -> `f`
+        if let FnTableStore::Fn {
+            func,
+            cast: Some(cast),
+            ..
+        } = store
+        {
+            rewrites.push((cast, Rewrite::Sub(0, func)));
+        }
+    }
+    rewrites
+}
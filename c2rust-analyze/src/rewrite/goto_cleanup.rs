@@ -0,0 +1,231 @@
+//! Rewrites for the `goto fail` cleanup idiom.  C functions often release their resources on
+//! error by jumping to a shared cleanup label:
+//!
+//! ```c
+//! int f(void) {
+//!     char *x = malloc(16);
+//!     if (!x) goto fail;
+//!     if (g(x) < 0) goto fail;
+//!     return 0;
+//! fail:
+//!     free(x);
+//!     return -1;
+//! }
+//! ```
+//!
+//! The transpiler emulates the `goto` with a labeled loop that is exited by `break`:
+//!
+//! ```ignore
+//! 'c_10: loop {
+//!     if x.is_null() {
+//!         break 'c_10;
+//!     }
+//!     if g(x) < 0 {
+//!         break 'c_10;
+//!     }
+//!     return 0;
+//! }
+//! free(x as *mut libc::c_void);
+//! return -1;
+//! ```
+//!
+//! When the loop never actually repeats, it becomes a closure returning a `Result`, where each
+//! `break` is an early `return Err(())` and each `return e` is `return Ok(e)`.  The cleanup code
+//! after the loop runs only on the error path:
+//!
+//! ```ignore
+//! {
+//!     let result = (|| {
+//!         if x.is_null() {
+//!             return Err(());
+//!         }
+//!         // ...
+//!         return Ok(0);
+//!     })();
+//!     if let Ok(ret) = result {
+//!         return ret;
+//!     }
+//! }
+//! free(x as *mut libc::c_void);
+//! return -1;
+//! ```
+
+use crate::rewrite::Rewrite;
+use log::debug;
+use rustc_hir::def_id::LocalDefId;
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{Block, Expr, ExprKind, HirId, LoopSource, StmtKind};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use std::collections::HashSet;
+
+/// A labeled loop or block that emulates a `goto` to the cleanup code following it.
+struct GotoCleanup {
+    /// The whole loop expression, including its label.
+    expr: Span,
+    /// The loop body, including the braces.
+    body: Span,
+    /// `break` expressions that exit the loop.
+    breaks: Vec<Span>,
+    /// `return` expressions within the loop, along with their return values.
+    returns: Vec<(Span, Option<Span>)>,
+}
+
+/// Collects the `break`s and `return`s in the body of a candidate loop, and checks that the body
+/// can be moved into a closure.
+struct JumpVisitor {
+    /// The `HirId` of the loop expression.
+    loop_id: HirId,
+    /// Loops and blocks nested inside the loop body.  `break` and `continue` may target these
+    /// freely.
+    inner: HashSet<HirId>,
+    breaks: Vec<Span>,
+    returns: Vec<(Span, Option<Span>)>,
+    ok: bool,
+}
+
+impl<'tcx> Visitor<'tcx> for JumpVisitor {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        if !self.ok {
+            return;
+        }
+        match ex.kind {
+            ExprKind::Loop(..) | ExprKind::Block(..) => {
+                self.inner.insert(ex.hir_id);
+            }
+            ExprKind::Break(dest, value) => match dest.target_id {
+                Ok(id) if id == self.loop_id && value.is_none() => {
+                    self.breaks.push(ex.span);
+                }
+                Ok(id) if self.inner.contains(&id) => {}
+                _ => self.ok = false,
+            },
+            ExprKind::Continue(dest) => match dest.target_id {
+                Ok(id) if self.inner.contains(&id) => {}
+                _ => self.ok = false,
+            },
+            ExprKind::Ret(value) => {
+                // A `return` produced by desugaring, as for `?`, can't be rewritten in place.
+                if ex.span.from_expansion() || ex.span.desugaring_kind().is_some() {
+                    self.ok = false;
+                } else {
+                    self.returns.push((ex.span, value.map(|e| e.span)));
+                }
+            }
+            _ => {}
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Check whether `block` always exits by `return` or by `break` out of the loop `loop_id`, so
+/// the loop never repeats and never falls through.
+fn ends_in_jump(block: &Block, loop_id: HirId) -> bool {
+    let last = match (block.expr, block.stmts.last()) {
+        (Some(e), _) => e,
+        (None, Some(stmt)) => match stmt.kind {
+            StmtKind::Expr(e) | StmtKind::Semi(e) => e,
+            _ => return false,
+        },
+        (None, None) => return false,
+    };
+    match last.kind {
+        ExprKind::Ret(_) => true,
+        ExprKind::Break(dest, None) => dest.target_id == Ok(loop_id),
+        _ => false,
+    }
+}
+
+/// Check whether `ex` is a labeled loop or block that emulates a `goto` to the code after it.
+fn match_goto_cleanup<'tcx>(ex: &'tcx Expr<'tcx>) -> Option<GotoCleanup> {
+    let body = match ex.kind {
+        ExprKind::Loop(body, Some(_), LoopSource::Loop, _) => body,
+        ExprKind::Block(body, Some(_)) => body,
+        _ => return None,
+    };
+    if ex.span.from_expansion() || !ends_in_jump(body, ex.hir_id) {
+        return None;
+    }
+
+    let mut v = JumpVisitor {
+        loop_id: ex.hir_id,
+        inner: HashSet::new(),
+        breaks: Vec::new(),
+        returns: Vec::new(),
+        ok: true,
+    };
+    v.visit_block(body);
+    // Without both a success path and a failure path, there's nothing to gain.
+    if !v.ok || v.breaks.is_empty() || v.returns.is_empty() {
+        return None;
+    }
+    Some(GotoCleanup {
+        expr: ex.span,
+        body: body.span,
+        breaks: v.breaks,
+        returns: v.returns,
+    })
+}
+
+/// Find the `goto` emulation loops in the top-level block of `ldid`'s body.  Only a loop that is
+/// followed by some cleanup code is considered.
+fn find_goto_cleanups(tcx: TyCtxt, ldid: LocalDefId) -> Vec<GotoCleanup> {
+    let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+    let block = match tcx.hir().body(hir_body_id).value.kind {
+        ExprKind::Block(block, None) => block,
+        _ => return Vec::new(),
+    };
+    let mut found = Vec::new();
+    for (i, stmt) in block.stmts.iter().enumerate() {
+        let ex = match stmt.kind {
+            StmtKind::Expr(e) | StmtKind::Semi(e) => e,
+            _ => continue,
+        };
+        let has_cleanup = i + 1 < block.stmts.len() || block.expr.is_some();
+        if !has_cleanup {
+            continue;
+        }
+        if let Some(gc) = match_goto_cleanup(ex) {
+            found.push(gc);
+        }
+    }
+    found
+}
+
+pub fn gen_goto_cleanup_rewrites(tcx: TyCtxt, all_fn_ldids: &[LocalDefId]) -> Vec<(Span, Rewrite)> {
+    let mut rewrites = Vec::new();
+    for &ldid in all_fn_ldids {
+        for gc in find_goto_cleanups(tcx, ldid) {
+            debug!("goto cleanup in {ldid:?} at {:?}", gc.expr);
+            // `'c_10: loop { ... }` -> `{ let result = (|| { ... })(); ... }`
+            rewrites.push((
+                gc.expr,
+                Rewrite::Block(
+                    vec![Rewrite::Let1(
+                        "result".into(),
+                        Box::new(Rewrite::ClosureCall(gc.body)),
+                    )],
+                    Some(Box::new(Rewrite::Text(
+                        "if let Ok(ret) = result {\n        return ret;\n    }".into(),
+                    ))),
+                ),
+            ));
+            // `break 'c_10` -> `return Err(())`
+            for span in gc.breaks {
+                rewrites.push((span, Rewrite::Text("return Err(())".into())));
+            }
+            // `return e` -> `return Ok(e)`
+            for (span, value) in gc.returns {
+                let rw = match value {
+                    Some(value) => Rewrite::Call("return Ok".into(), vec![Rewrite::Sub(0, value)]),
+                    None => Rewrite::Text("return Ok(())".into()),
+                };
+                rewrites.push((span, rw));
+            }
+        }
+    }
+    rewrites
+}
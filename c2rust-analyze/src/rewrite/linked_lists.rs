@@ -0,0 +1,206 @@
+//! Rewrites for pointers to doubly-linked list nodes, which become `Option<usize>` indices into a
+//! per-struct arena.  See `crate::linked_lists` for the analysis that finds them.
+//!
+//! Functions that use these pointers are excluded from the normal rewrites, so this module
+//! handles both the types and the expressions involving the pointers, and also defines the arena
+//! itself alongside each node struct.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::linked_lists::{self, ListPtrUse};
+use crate::rewrite::{self, Rewrite};
+use rustc_hir::def_id::DefId;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use std::fmt::Write as _;
+
+/// Define the arena for `did`, along with helpers for accessing it, after the struct definition:
+///
+/// ```ignore
+/// thread_local! {
+///     static NODE_ARENA: std::cell::RefCell<Vec<Option<Node>>> =
+///         std::cell::RefCell::new(Vec::new());
+/// }
+///
+/// impl Node {
+///     fn arena_alloc(node: Node) -> usize { .. }
+///     fn arena_free(i: usize) { .. }
+///     fn arena_get<R>(i: usize, f: impl FnOnce(&Node) -> R) -> R { .. }
+///     fn arena_get_mut<R>(i: usize, f: impl FnOnce(&mut Node) -> R) -> R { .. }
+/// }
+/// ```
+///
+/// A freed node's slot is set to `None`, so a use after free panics.
+fn gen_arena_def_rewrite(tcx: TyCtxt, did: DefId) -> (Span, Rewrite) {
+    let item = tcx.hir().expect_item(did.expect_local());
+    let name = item.ident;
+    let arena = arena_name(tcx, did);
+
+    let mut s = String::new();
+    writeln!(s).unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "thread_local! {{").unwrap();
+    writeln!(
+        s,
+        "    static {arena}: std::cell::RefCell<Vec<Option<{name}>>> = \
+         std::cell::RefCell::new(Vec::new());"
+    )
+    .unwrap();
+    writeln!(s, "}}").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "impl {name} {{").unwrap();
+    writeln!(s, "    fn arena_alloc(node: {name}) -> usize {{").unwrap();
+    writeln!(s, "        {arena}.with(|arena| {{").unwrap();
+    writeln!(s, "            let mut arena = arena.borrow_mut();").unwrap();
+    writeln!(s, "            arena.push(Some(node));").unwrap();
+    writeln!(s, "            arena.len() - 1").unwrap();
+    writeln!(s, "        }})").unwrap();
+    writeln!(s, "    }}").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "    fn arena_free(i: usize) {{").unwrap();
+    writeln!(
+        s,
+        "        {arena}.with(|arena| arena.borrow_mut()[i] = None);"
+    )
+    .unwrap();
+    writeln!(s, "    }}").unwrap();
+    for (suffix, borrow, as_ref, self_ref) in [
+        ("", "borrow", "as_ref", "&"),
+        ("_mut", "borrow_mut", "as_mut", "&mut "),
+    ] {
+        writeln!(s).unwrap();
+        writeln!(
+            s,
+            "    fn arena_get{suffix}<R>(i: usize, f: impl FnOnce({self_ref}{name}) -> R) -> R {{"
+        )
+        .unwrap();
+        writeln!(
+            s,
+            "        {arena}.with(|arena| f(arena.{borrow}()[i].{as_ref}().expect(\"use of freed node\")))"
+        )
+        .unwrap();
+        writeln!(s, "    }}").unwrap();
+    }
+    s.push('}');
+    (item.span.shrink_to_hi(), Rewrite::Text(s))
+}
+
+/// The name of the `thread_local` holding the arena for `did`, as in `NODE_ARENA`.
+fn arena_name(tcx: TyCtxt, did: DefId) -> String {
+    format!("{}_ARENA", tcx.item_name(did).as_str().to_uppercase())
+}
+
+/// A new node with every field zeroed, as in `Node { val: 0, prev: None, next: None, }`.
+fn gen_zero_node(gacx: &GlobalAnalysisCtxt, did: DefId) -> String {
+    let tcx = gacx.tcx;
+    let mut s = format!("{} {{ ", tcx.def_path_str(did));
+    for field in tcx.adt_def(did).all_fields() {
+        let zero = if gacx.linked_lists.arena_fields.contains(&field.did) {
+            "None".to_owned()
+        } else {
+            rewrite::gen_zeroize_expr(tcx, tcx.type_of(field.did))
+                .expect("arena node should have been checked for zeroize support")
+        };
+        write!(s, "{}: {}, ", field.name, zero).unwrap();
+    }
+    s.push('}');
+    s
+}
+
+pub fn gen_linked_list_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let info = &gacx.linked_lists;
+    let mut rewrites = Vec::new();
+    if info.arena_structs.is_empty() {
+        return rewrites;
+    }
+
+    for &did in &info.arena_structs {
+        rewrites.push(gen_arena_def_rewrite(tcx, did));
+    }
+
+    let field_uses =
+        linked_lists::collect_field_ty_uses(tcx, &info.arena_fields, &info.arena_structs);
+    let fn_uses = info
+        .fns
+        .iter()
+        .filter_map(|did| did.as_local())
+        .flat_map(|ldid| linked_lists::collect_list_ptr_uses(tcx, ldid, &info.arena_structs));
+    for (adt, u) in field_uses.into_iter().chain(fn_uses) {
+        let path = tcx.def_path_str(adt);
+        let unwrap =
+            |ptr| Rewrite::MethodCall("unwrap".into(), Box::new(Rewrite::Sub(0, ptr)), vec![]);
+        let (span, rw) = match u {
+            // `*mut Node` -> `Option<usize>`
+            ListPtrUse::Ty { ty } => (
+                ty,
+                Rewrite::TyCtor(
+                    "std::option::Option".into(),
+                    vec![Rewrite::Print("usize".into())],
+                ),
+            ),
+            // `malloc(n) as *mut Node` -> `Some(Node::arena_alloc(Node { .. }))`
+            ListPtrUse::Alloc { expr } => {
+                let node = Rewrite::Text(gen_zero_node(gacx, adt));
+                let idx = Rewrite::Call(format!("{path}::arena_alloc"), vec![node]);
+                (expr, Rewrite::Call("Some".into(), vec![idx]))
+            }
+            // `free(p as *mut c_void)` -> `Node::arena_free(p.unwrap())`
+            ListPtrUse::Free { call, ptr } => (
+                call,
+                Rewrite::Call(format!("{path}::arena_free"), vec![unwrap(ptr)]),
+            ),
+            // `0 as *mut Node` -> `None`
+            ListPtrUse::Null { expr } => (expr, Rewrite::Text("None".into())),
+            // `p.is_null()` -> `p.is_none()`
+            ListPtrUse::IsNull { expr, ptr } => (
+                expr,
+                Rewrite::MethodCall("is_none".into(), Box::new(Rewrite::Sub(0, ptr)), vec![]),
+            ),
+            // `(*p).x` -> `Node::arena_get(p.unwrap(), |node| node.x)`
+            ListPtrUse::Read { expr, ptr, proj } => {
+                let access = Rewrite::Closure1(
+                    "node".into(),
+                    Box::new(Rewrite::Text(format!("node{proj}"))),
+                );
+                (
+                    expr,
+                    Rewrite::Call(format!("{path}::arena_get"), vec![unwrap(ptr), access]),
+                )
+            }
+            // `(*p).x = v` -> `{
+            //     let (arena_idx, arena_val) = (p.unwrap(), v);
+            //     Node::arena_get_mut(arena_idx, |node| node.x = arena_val)
+            // }`
+            //
+            // The value is computed before the arena is borrowed, since computing it may read
+            // from the arena.
+            ListPtrUse::Write {
+                expr,
+                ptr,
+                proj,
+                op,
+                rhs,
+            } => {
+                let bind = Rewrite::Let(vec![
+                    ("arena_idx".into(), unwrap(ptr)),
+                    ("arena_val".into(), Rewrite::Sub(1, rhs)),
+                ]);
+                let access = Rewrite::Closure1(
+                    "node".into(),
+                    Box::new(Rewrite::Text(format!("node{proj} {op} arena_val"))),
+                );
+                let update = Rewrite::Call(
+                    format!("{path}::arena_get_mut"),
+                    vec![Rewrite::Text("arena_idx".into()), access],
+                );
+                (expr, Rewrite::Block(vec![bind], Some(Box::new(update))))
+            }
+            ListPtrUse::Other => {
+                // `find_linked_lists` rejects any struct with an `Other` use.
+                unreachable!("unsupported use of list node pointer")
+            }
+        };
+        rewrites.push((span, rw));
+    }
+    rewrites
+}
@@ -25,23 +25,75 @@
 
 use rustc_hir::Mutability;
 use rustc_middle::ty::TyCtxt;
+use rustc_span::source_map::SourceMap;
 use rustc_span::{FileName, Span};
+use similar::TextDiff;
 use std::collections::HashMap;
+use std::env;
 use std::fmt;
-use std::fs;
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 mod apply;
+mod closure_callbacks;
+mod errno_result;
 mod expr;
+mod file_io;
+mod flex_array;
+mod fn_ptr_fields;
+mod fn_tables;
+mod goto_cleanup;
+mod linked_lists;
+mod printf;
+mod refcount;
+mod safe_wrappers;
+mod self_ref;
 mod shim;
 mod span_index;
+mod static_bufs;
 mod statics;
+mod thread_shared;
 mod ty;
-
-pub use self::expr::gen_expr_rewrites;
-pub use self::shim::{gen_shim_call_rewrites, gen_shim_definition_rewrite, ManualShimCasts};
-pub use self::statics::gen_static_rewrites;
-pub use self::ty::dump_rewritten_local_tys;
+mod unions;
+mod unsafety;
+mod user_data;
+mod vec_structs;
+
+pub use self::closure_callbacks::gen_closure_callback_rewrites;
+pub use self::errno_result::gen_errno_result_rewrites;
+pub use self::expr::{
+    gen_expr_rewrites, gen_out_param_rewrites, gen_zeroize_expr, gen_zeroize_expr_without_field,
+    ExprRewriteOrigin,
+};
+pub use self::file_io::gen_file_io_rewrites;
+pub use self::flex_array::gen_flex_array_rewrites;
+pub use self::fn_ptr_fields::gen_fn_ptr_field_rewrites;
+pub use self::fn_tables::gen_fn_table_rewrites;
+pub use self::goto_cleanup::gen_goto_cleanup_rewrites;
+pub use self::linked_lists::gen_linked_list_rewrites;
+pub use self::printf::gen_printf_rewrites;
+pub use self::refcount::gen_refcount_rewrites;
+pub use self::safe_wrappers::{
+    gen_safe_wrapper_call_rewrites, gen_safe_wrapper_rewrites, is_exported_extern_fn,
+};
+pub use self::self_ref::gen_self_ref_rewrites;
+pub use self::shim::{
+    exported_shim_fns, gen_raw_clone_definition_rewrites, gen_shim_call_rewrites,
+    gen_shim_definition_rewrite, raw_clone_name, ManualShimCasts,
+};
+pub use self::static_bufs::gen_static_buf_rewrites;
+pub use self::statics::{gen_static_init_rewrites, gen_static_kind_rewrites, gen_static_rewrites};
+pub use self::thread_shared::gen_thread_shared_rewrites;
+pub use self::ty::{dump_rewritten_local_tys, rewritten_ptr_tys};
 pub use self::ty::{gen_adt_ty_rewrites, gen_ty_rewrites};
+pub use self::unions::gen_union_rewrites;
+pub use self::unsafety::{
+    body_unsafety, count_unsafety, find_safe_fns, gen_unsafety_rewrites, UnsafetyCounts,
+};
+pub use self::user_data::gen_user_data_call_rewrites;
+pub use self::vec_structs::gen_vec_struct_rewrites;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum LifetimeName {
@@ -80,13 +132,21 @@ pub enum Rewrite<S = Span> {
     RemovedCast(Box<Rewrite>),
     /// The integer literal `0`.
     LitZero,
+    /// `a + b`, or any other binary operator, given as text
+    Binary(String, Box<Rewrite>, Box<Rewrite>),
     /// Function calls
     Call(String, Vec<Rewrite>),
     /// Method calls
     MethodCall(String, Box<Rewrite>, Vec<Rewrite>),
+    /// The `?` operator, as in `rw?`
+    Try(Box<Rewrite>),
     /// A block of statements, followed by an optional result expression.  This rewrite inserts a
     /// semicolon after each statement.
     Block(Vec<Rewrite>, Option<Box<Rewrite>>),
+    /// `unsafe { e }`, preceded by a `// SAFETY:` comment with the given explanation.  Used for
+    /// unsafe operations introduced by a rewrite, which don't appear in the MIR and so aren't
+    /// covered by `unsafety::body_unsafety`.
+    Unsafe(String, Box<Rewrite>),
     /// A multi-variable `let` binding, like `let (x, y) = (rw0, rw1)`.  Note that this rewrite
     /// does not include a trailing semicolon.
     ///
@@ -101,6 +161,12 @@ pub enum Rewrite<S = Span> {
     /// Single-argument closure.  As with `Let` and `Let1`, the body must be carefully constructed
     /// to avoid potential shadowing.
     Closure1(String, Box<Rewrite>),
+    /// `for pat in iter { ... }`.  The loop body is taken from the source code at the given span
+    /// (which should include the braces), with any rewrites inside it applied.
+    ForLoop(String, Box<Rewrite>, S),
+    /// `(|| { ... })()`, a closure that is called immediately.  As with `ForLoop`, the closure
+    /// body is taken from the source code at the given span, with any rewrites inside it applied.
+    ClosureCall(S),
 
     // Type builders
     /// Emit a complete pretty-printed type, discarding the original annotation.
@@ -122,8 +188,9 @@ pub enum Rewrite<S = Span> {
     StaticMut(Mutability, S),
 
     // `fn` builders
-    /// Define a function.
+    /// Define a function.  `header` is everything before the name, as in `unsafe fn`.
     DefineFn {
+        header: String,
         name: String,
         arg_tys: Vec<Rewrite>,
         return_ty: Option<Box<Rewrite>>,
@@ -184,13 +251,16 @@ impl Rewrite {
             Cast(ref expr, ref ty) => Cast(try_subst(expr)?, try_subst(ty)?),
             RemovedCast(ref rw) => RemovedCast(try_subst(rw)?),
             LitZero => LitZero,
+            Binary(ref op, ref a, ref b) => Binary(String::clone(op), try_subst(a)?, try_subst(b)?),
             Call(ref func, ref args) => Call(String::clone(func), try_subst_vec(args)?),
             MethodCall(ref func, ref receiver, ref args) => MethodCall(
                 String::clone(func),
                 try_subst(receiver)?,
                 try_subst_vec(args)?,
             ),
+            Try(ref rw) => Try(try_subst(rw)?),
             Block(ref stmts, ref expr) => Block(try_subst_vec(stmts)?, try_subst_option(expr)?),
+            Unsafe(ref safety, ref rw) => Unsafe(String::clone(safety), try_subst(rw)?),
             Let(ref vars) => {
                 let mut new_vars = Vec::with_capacity(vars.len());
                 for (ref name, ref rw) in vars {
@@ -200,6 +270,8 @@ impl Rewrite {
             }
             Let1(ref name, ref rw) => Let1(String::clone(name), try_subst(rw)?),
             Closure1(ref name, ref rw) => Closure1(String::clone(name), try_subst(rw)?),
+            ForLoop(ref pat, ref iter, body) => ForLoop(String::clone(pat), try_subst(iter)?, body),
+            ClosureCall(body) => ClosureCall(body),
 
             Print(ref s) => Print(String::clone(s)),
             TyPtr(ref rw, mutbl) => TyPtr(try_subst(rw)?, mutbl),
@@ -210,11 +282,13 @@ impl Rewrite {
             StaticMut(mutbl, span) => StaticMut(mutbl, span),
 
             DefineFn {
+                ref header,
                 ref name,
                 ref arg_tys,
                 ref return_ty,
                 ref body,
             } => DefineFn {
+                header: String::clone(header),
                 name: String::clone(name),
                 arg_tys: try_subst_vec(arg_tys)?,
                 return_ty: try_subst_option(return_ty)?,
@@ -251,6 +325,149 @@ impl apply::Sink for FormatterSink<'_, '_> {
     }
 }
 
+/// Renders a `Rewrite` for `--dry-run` and the rewrite manifest.  Subexpressions are filled in
+/// with their original source text, or, if `subs` is set, replaced with `$N` placeholders and
+/// recorded there.
+struct SnippetSink<'a> {
+    sm: &'a SourceMap,
+    out: String,
+    subs: Option<Vec<(usize, Span)>>,
+}
+
+impl SnippetSink<'_> {
+    fn emit_snippet(&mut self, span: Span) {
+        match self.sm.span_to_snippet(span) {
+            Ok(s) => self.out.push_str(&s),
+            Err(_) => self.out.push_str(&format!("<span {:?}>", span)),
+        }
+    }
+}
+
+impl apply::Sink for SnippetSink<'_> {
+    type Error = fmt::Error;
+    const PARENTHESIZE_EXPRS: bool = true;
+
+    fn emit_str(&mut self, s: &str) -> fmt::Result {
+        self.out.push_str(s);
+        Ok(())
+    }
+    fn emit_orig_str(&mut self, s: &str, _line: usize) -> fmt::Result {
+        self.out.push_str(s);
+        Ok(())
+    }
+    fn emit_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        self.out.write_fmt(args)
+    }
+    fn emit_expr(&mut self) -> fmt::Result {
+        self.out.push_str("$e");
+        Ok(())
+    }
+    fn emit_sub(&mut self, idx: usize, span: Span) -> fmt::Result {
+        match self.subs {
+            Some(ref mut subs) => {
+                write!(self.out, "${}", idx)?;
+                subs.push((idx, span));
+            }
+            None => self.emit_snippet(span),
+        }
+        Ok(())
+    }
+    fn emit_span(&mut self, span: Span) -> fmt::Result {
+        self.emit_snippet(span);
+        Ok(())
+    }
+}
+
+/// Collapse each run of whitespace in `s` into a single space, so that it fits on one line.
+fn one_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// A rewrite rendered as text, for `--dry-run` and `--interactive`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RewriteSummary {
+    pub file: String,
+    /// The 1-based line and column where the rewritten code starts.
+    pub line: usize,
+    pub col: usize,
+    /// The original code, with whitespace collapsed so it fits on one line.
+    pub old: String,
+    /// The code it's rewritten to, in the same form.
+    pub new: String,
+}
+
+impl fmt::Display for RewriteSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{} {} => {}",
+            self.file, self.line, self.col, self.old, self.new
+        )
+    }
+}
+
+/// Render `rw` as the code it produces, with the original source text for any subexpressions.
+/// `$e` stands for the rewritten expression itself.
+pub fn render_rewrite(sm: &SourceMap, rw: &Rewrite) -> String {
+    let mut sink = SnippetSink {
+        sm,
+        out: String::new(),
+        subs: None,
+    };
+    apply::emit_rewrite(&mut sink, rw).unwrap();
+    sink.out
+}
+
+/// Render `rw` as a template, with a `$N` placeholder for subexpression `N`.  Also returns the
+/// span of each subexpression, by index.
+pub fn render_rewrite_template(sm: &SourceMap, rw: &Rewrite) -> (String, Vec<(usize, Span)>) {
+    let mut sink = SnippetSink {
+        sm,
+        out: String::new(),
+        subs: Some(Vec::new()),
+    };
+    apply::emit_rewrite(&mut sink, rw).unwrap();
+    (sink.out, sink.subs.unwrap())
+}
+
+/// Render the rewrite of `span` to `rw` as text.
+pub fn summarize_rewrite(sm: &SourceMap, span: Span, rw: &Rewrite) -> RewriteSummary {
+    let lo = sm.lookup_char_pos(span.lo());
+    let old = sm.span_to_snippet(span).unwrap_or_default();
+    RewriteSummary {
+        file: lo.file.name.prefer_local().to_string(),
+        line: lo.line,
+        col: lo.col.0 + 1,
+        old: one_line(&old),
+        new: one_line(&render_rewrite(sm, rw)),
+    }
+}
+
+/// Print each of `rewrites` as `file:line:col old => new` instead of applying them, for
+/// `--dry-run`.  The MIR `RewriteKind`s behind each expression rewrite, found in `origins`, are
+/// printed after it; type and item rewrites have none.
+pub fn print_dry_run(
+    tcx: TyCtxt,
+    rewrites: &[(Span, Rewrite)],
+    origins: &HashMap<Span, ExprRewriteOrigin>,
+) {
+    let sm = tcx.sess.source_map();
+    let mut lines = Vec::with_capacity(rewrites.len());
+    for &(span, ref rw) in rewrites {
+        let summary = summarize_rewrite(sm, span, rw);
+        let mut line = summary.to_string();
+        if let Some(origin) = origins.get(&span.source_callsite()) {
+            let kinds = origin.kinds.iter().map(|kind| format!("{:?}", kind));
+            write!(line, " [{}]", kinds.collect::<Vec<_>>().join(", ")).unwrap();
+        }
+        lines.push((summary, line));
+    }
+    lines.sort();
+    for (_, line) in lines {
+        println!("{}", line);
+    }
+}
+
 /// Return a copy of `src` with `annotations` added as comments.  If `line_map` is provided, the
 /// line numbers in `annotations` are remapped using `line_map` before the annotations are inserted
 /// in `src`.
@@ -318,6 +535,34 @@ pub enum UpdateFiles {
     /// rewriting mode is `AlongsidePointwise("bar")`, then the rewritten code is written to
     /// `foo.bar.rs`.
     AlongsidePointwise(rustc_span::symbol::Symbol),
+    /// Append a unified diff of the changes to the file named by `C2RUST_ANALYZE_PATCH_FILE`,
+    /// without modifying the original source files.
+    Patch,
+}
+
+/// Get the file to write the diff to in `UpdateFiles::Patch` mode.
+fn patch_path() -> PathBuf {
+    env::var_os("C2RUST_ANALYZE_PATCH_FILE")
+        .map_or_else(|| PathBuf::from("c2rust-analyze.patch"), PathBuf::from)
+}
+
+/// Produce a unified diff between `old_src` and `new_src`, the old and new contents of `path`.
+/// The diff uses `a/` and `b/` prefixes on the file names, like `git diff`.
+fn unified_diff(path: &Path, old_src: &str, new_src: &str) -> String {
+    // Use paths relative to the working directory, which for `rustc` run by `cargo` is the
+    // workspace root.
+    let path = env::current_dir()
+        .ok()
+        .and_then(|cwd| path.strip_prefix(cwd).ok())
+        .unwrap_or(path);
+    let diff = TextDiff::from_lines(old_src, new_src);
+    diff.unified_diff()
+        .context_radius(3)
+        .header(
+            &format!("a/{}", path.display()),
+            &format!("b/{}", path.display()),
+        )
+        .to_string()
 }
 
 pub fn apply_rewrites(
@@ -326,7 +571,9 @@ pub fn apply_rewrites(
     mut annotations: HashMap<FileName, Vec<(usize, String)>>,
     update_files: UpdateFiles,
 ) {
-    let emit = |filename, src: String| {
+    let sm = tcx.sess.source_map();
+    let mut patch = String::new();
+    let mut emit = |filename, src: String| {
         println!("\n\n ===== BEGIN {:?} =====", filename);
         for line in src.lines() {
             // Omit filecheck directives from the debug output, as filecheck can get confused due
@@ -344,6 +591,13 @@ pub fn apply_rewrites(
             let mut path_ok = false;
             if let FileName::Real(ref rfn) = filename {
                 if let Some(path) = rfn.local_path() {
+                    if update_files == UpdateFiles::Patch {
+                        let sf = sm.get_source_file(&filename).unwrap();
+                        if let Some(ref old_src) = sf.src {
+                            patch.push_str(&unified_diff(path, old_src, &src));
+                        }
+                        return;
+                    }
                     let path = match update_files {
                         UpdateFiles::InPlace => path.to_owned(),
                         UpdateFiles::Alongside => path.with_extension("new.rs"),
@@ -353,7 +607,7 @@ pub fn apply_rewrites(
                             eprintln!("writing to {:?}", p);
                             p
                         }
-                        UpdateFiles::No => unreachable!(),
+                        UpdateFiles::No | UpdateFiles::Patch => unreachable!(),
                     };
                     fs::write(path, src).unwrap();
                     path_ok = true;
@@ -376,7 +630,6 @@ pub fn apply_rewrites(
     if !annotations.is_empty() {
         let mut leftover_annotations = annotations.into_iter().collect::<Vec<_>>();
         leftover_annotations.sort();
-        let sm = tcx.sess.source_map();
         for (filename, annotations) in leftover_annotations {
             let sf = sm.get_source_file(&filename).unwrap();
             let src = match sf.src {
@@ -387,6 +640,17 @@ pub fn apply_rewrites(
             emit(filename, src);
         }
     }
+
+    if update_files == UpdateFiles::Patch {
+        // Several crates in the same workspace may write to the same patch file.
+        let path = patch_path();
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .unwrap_or_else(|e| panic!("failed to open {:?}: {}", path, e));
+        f.write_all(patch.as_bytes()).unwrap();
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,389 @@
+//! Rewrites for calls to `printf` and `fprintf` with literal format strings.  The transpiler
+//! passes the format string as a byte string literal:
+//!
+//! ```ignore
+//! printf(b"%s: %5d\n\0" as *const u8 as *const libc::c_char, name, count);
+//! fprintf(stderr, b"error %d\n\0" as *const u8 as *const libc::c_char, code);
+//! ```
+//!
+//! When the format string uses only conversions that have an equivalent in Rust's formatting
+//! syntax, the call becomes a `print!` or `eprint!`:
+//!
+//! ```ignore
+//! print!("{}: {:5}\n", std::ffi::CStr::from_ptr(name).to_string_lossy(), count);
+//! eprint!("error {}\n", code);
+//! ```
+//!
+//! Only calls whose result is discarded are rewritten, and `fprintf` only when the stream is
+//! `stdout` or `stderr`.  Note that Rust's `stdout` is buffered separately from C's, so output
+//! from a rewritten call may be reordered relative to output from a remaining C stdio call.
+//!
+//! Calls to `sprintf` and `snprintf` are handled during the MIR rewrite pass instead (see
+//! `mir_op::RewriteKind::SprintfSafe`), since they depend on whether the destination buffer was
+//! rewritten to a slice.  They use the same format string translation.
+
+use crate::rewrite::Rewrite;
+use log::debug;
+use rustc_ast::{LitKind, Mutability};
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::LocalDefId;
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{Expr, ExprKind, QPath, Stmt, StmtKind};
+use rustc_middle::ty::{TyCtxt, TypeckResults};
+use rustc_span::Span;
+
+/// How a `printf` argument is converted before it's passed to `print!`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArgKind {
+    /// The argument is used unchanged.
+    Plain,
+    /// `%c`: The argument is an `int` holding a character, as in `x as u8 as char`.
+    Char,
+    /// `%s`: The argument is a pointer to a NUL-terminated string, as in
+    /// `CStr::from_ptr(x).to_string_lossy()`.
+    CStr,
+    /// `%s` where the argument was rewritten to a NUL-terminated slice of bytes.  Everything up to
+    /// the terminator is converted, as in `String::from_utf8_lossy(..)`.
+    CStrSlice,
+}
+
+/// Translate a `printf` format string into a Rust format string, and return the way each
+/// argument should be converted.  Returns `None` if the format string uses a feature with no
+/// equivalent in Rust, such as `%n`, `%e`, or a `*` width.
+pub fn translate_format(fmt: &str) -> Option<(String, Vec<ArgKind>)> {
+    let mut out = String::new();
+    let mut args = Vec::new();
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => out.push_str("{{"),
+            '}' => out.push_str("}}"),
+            '%' => {
+                if chars.peek() == Some(&'%') {
+                    chars.next();
+                    out.push('%');
+                    continue;
+                }
+
+                let (mut left, mut zero, mut plus, mut alt) = (false, false, false, false);
+                while let Some(&f) = chars.peek() {
+                    match f {
+                        '-' => left = true,
+                        '0' => zero = true,
+                        '+' => plus = true,
+                        '#' => alt = true,
+                        _ => break,
+                    }
+                    chars.next();
+                }
+
+                let mut width = String::new();
+                while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                    width.push(d);
+                    chars.next();
+                }
+
+                let mut precision = None;
+                if chars.peek() == Some(&'.') {
+                    chars.next();
+                    let mut p = String::new();
+                    while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                        p.push(d);
+                        chars.next();
+                    }
+                    precision = Some(if p.is_empty() { "0".into() } else { p });
+                }
+
+                // Integer arguments are printed at their Rust type, so the length modifier is
+                // only needed to check that the conversion is supported.  `h` and `hh` truncate
+                // the argument, which Rust doesn't do.
+                while let Some(&m) = chars.peek() {
+                    match m {
+                        'l' | 'z' | 'j' | 't' => {
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+
+                let conv = chars.next()?;
+                let kind = match conv {
+                    'c' => ArgKind::Char,
+                    's' => ArgKind::CStr,
+                    _ => ArgKind::Plain,
+                };
+                let (ty, precision) = match (conv, precision) {
+                    ('d' | 'i' | 'u' | 'c', None) => ("", None),
+                    ('x', None) => ("x", None),
+                    ('X', None) => ("X", None),
+                    ('o', None) => ("o", None),
+                    ('f', p) => ("", Some(p.unwrap_or_else(|| "6".into()))),
+                    ('s', p) => ("", p),
+                    _ => return None,
+                };
+                // C's `%#o` adds a leading `0`, where Rust's `{:#o}` adds `0o`.
+                if alt && !matches!(conv, 'x' | 'X') {
+                    return None;
+                }
+                // Rust only zero-pads and adds signs to numbers.
+                if (zero || plus) && kind != ArgKind::Plain {
+                    return None;
+                }
+
+                let mut spec = String::new();
+                if left {
+                    spec.push('<');
+                } else if kind != ArgKind::Plain && !width.is_empty() {
+                    // Strings are left-aligned by default in Rust, but right-aligned in C.
+                    spec.push('>');
+                }
+                if plus {
+                    spec.push('+');
+                }
+                if alt {
+                    spec.push('#');
+                }
+                // C ignores the `0` flag when `-` is also given.
+                if zero && !left {
+                    spec.push('0');
+                }
+                spec.push_str(&width);
+                if let Some(p) = precision {
+                    spec.push('.');
+                    spec.push_str(&p);
+                }
+                spec.push_str(ty);
+
+                if spec.is_empty() {
+                    out.push_str("{}");
+                } else {
+                    out.push_str("{:");
+                    out.push_str(&spec);
+                    out.push('}');
+                }
+                args.push(kind);
+            }
+            _ => out.push(c),
+        }
+    }
+    Some((out, args))
+}
+
+/// A call to `printf` or `fprintf` that can be rewritten.
+struct PrintfCall {
+    /// The whole call expression.
+    expr: Span,
+    /// `print` or `eprint`.
+    macro_name: &'static str,
+    /// The translated format string, as a Rust string literal.
+    fmt: String,
+    /// The arguments following the format string.
+    args: Vec<(Span, ArgKind)>,
+}
+
+fn strip_casts<'tcx>(mut ex: &'tcx Expr<'tcx>) -> &'tcx Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+/// If `ex` is a path to a foreign item, return the item's name.
+fn foreign_item_name(tcx: TyCtxt, typeck_results: &TypeckResults, ex: &Expr) -> Option<String> {
+    let qpath = match ex.kind {
+        ExprKind::Path(ref qpath) => qpath,
+        _ => return None,
+    };
+    match typeck_results.qpath_res(qpath, ex.hir_id) {
+        Res::Def(DefKind::Fn | DefKind::Static(_), did) if tcx.is_foreign_item(did) => {
+            Some(tcx.item_name(did).to_string())
+        }
+        _ => None,
+    }
+}
+
+/// If `ex` is a NUL-terminated byte string literal, possibly cast to a pointer, return its
+/// contents without the terminator.
+fn c_str_literal(ex: &Expr) -> Option<String> {
+    let lit = match strip_casts(ex).kind {
+        ExprKind::Lit(ref lit) => lit,
+        _ => return None,
+    };
+    match lit.node {
+        LitKind::ByteStr(ref bytes) => c_str_contents(bytes),
+        _ => None,
+    }
+}
+
+/// Return the contents of a NUL-terminated C string without the terminator.  Returns `None` if
+/// `bytes` isn't terminated, contains an interior NUL, or isn't valid UTF-8.
+pub fn c_str_contents(bytes: &[u8]) -> Option<String> {
+    let (&last, rest) = bytes.split_last()?;
+    if last != 0 || rest.contains(&0) {
+        return None;
+    }
+    String::from_utf8(rest.to_vec()).ok()
+}
+
+/// Convert the rewritten argument `arg` as described by `kind`.
+pub fn convert_arg(kind: ArgKind, arg: Rewrite) -> Rewrite {
+    match kind {
+        ArgKind::Plain => arg,
+        ArgKind::Char => Rewrite::Cast(
+            Box::new(Rewrite::Cast(
+                Box::new(arg),
+                Box::new(Rewrite::Print("u8".into())),
+            )),
+            Box::new(Rewrite::Print("char".into())),
+        ),
+        ArgKind::CStr => Rewrite::MethodCall(
+            "to_string_lossy".into(),
+            Box::new(Rewrite::Call("std::ffi::CStr::from_ptr".into(), vec![arg])),
+            vec![],
+        ),
+        ArgKind::CStrSlice => {
+            // `s` -> `String::from_utf8_lossy(&s.iter().take_while(..).map(..).collect::<..>())`
+            let iter = Rewrite::MethodCall("iter".into(), Box::new(arg), vec![]);
+            let iter = Rewrite::MethodCall(
+                "take_while".into(),
+                Box::new(iter),
+                vec![Rewrite::Text("|&&c| c != 0".into())],
+            );
+            let iter = Rewrite::MethodCall(
+                "map".into(),
+                Box::new(iter),
+                vec![Rewrite::Text("|&c| c as u8".into())],
+            );
+            let bytes = Rewrite::MethodCall("collect::<Vec<u8>>".into(), Box::new(iter), vec![]);
+            Rewrite::Call(
+                "String::from_utf8_lossy".into(),
+                vec![Rewrite::Ref(Box::new(bytes), Mutability::Not)],
+            )
+        }
+    }
+}
+
+/// Check whether `ex` is a call to `printf` or `fprintf` that can be rewritten.
+fn match_printf_call(tcx: TyCtxt, typeck_results: &TypeckResults, ex: &Expr) -> Option<PrintfCall> {
+    let (func, args) = match ex.kind {
+        ExprKind::Call(func, args) => (func, args),
+        _ => return None,
+    };
+    if ex.span.from_expansion() {
+        return None;
+    }
+    let (macro_name, args) = match foreign_item_name(tcx, typeck_results, func)?.as_str() {
+        "printf" => ("print", args),
+        "fprintf" => {
+            let (stream, args) = args.split_first()?;
+            let stream = foreign_item_name(tcx, typeck_results, strip_casts(stream))?;
+            match stream.as_str() {
+                "stdout" => ("print", args),
+                "stderr" => ("eprint", args),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    let (fmt_arg, args) = args.split_first()?;
+    let (fmt, kinds) = translate_format(&c_str_literal(fmt_arg)?)?;
+    if kinds.len() != args.len() {
+        debug!(
+            "printf call at {:?} has the wrong number of arguments",
+            ex.span
+        );
+        return None;
+    }
+    Some(PrintfCall {
+        expr: ex.span,
+        macro_name,
+        fmt: format!("{fmt:?}"),
+        args: args.iter().map(|a| a.span).zip(kinds).collect(),
+    })
+}
+
+struct PrintfVisitor<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    found: Vec<PrintfCall>,
+}
+
+impl<'tcx> Visitor<'tcx> for PrintfVisitor<'tcx> {
+    fn visit_stmt(&mut self, stmt: &'tcx Stmt<'tcx>) {
+        // The call's return value is the number of bytes written, which `print!` doesn't provide,
+        // so only calls whose result is discarded are rewritten.
+        if let StmtKind::Semi(ex) = stmt.kind {
+            if let Some(pc) = match_printf_call(self.tcx, self.typeck_results, ex) {
+                self.found.push(pc);
+            }
+        }
+        intravisit::walk_stmt(self, stmt);
+    }
+}
+
+fn find_printf_calls(tcx: TyCtxt, ldid: LocalDefId) -> Vec<PrintfCall> {
+    let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+    let mut v = PrintfVisitor {
+        tcx,
+        typeck_results: tcx.typeck_body(hir_body_id),
+        found: Vec::new(),
+    };
+    v.visit_body(tcx.hir().body(hir_body_id));
+    v.found
+}
+
+pub fn gen_printf_rewrites(tcx: TyCtxt, all_fn_ldids: &[LocalDefId]) -> Vec<(Span, Rewrite)> {
+    let mut rewrites = Vec::new();
+    for &ldid in all_fn_ldids {
+        for pc in find_printf_calls(tcx, ldid) {
+            debug!("printf call in {ldid:?} at {:?}", pc.expr);
+            // `printf(b"%d %s\n\0" as *const u8 as *const c_char, x, s)` ->
+            // `print!("{} {}\n", x, CStr::from_ptr(s).to_string_lossy())`
+            let mut macro_args = vec![Rewrite::Text(pc.fmt)];
+            for (i, (span, kind)) in pc.args.into_iter().enumerate() {
+                macro_args.push(convert_arg(kind, Rewrite::Sub(i, span)));
+            }
+            rewrites.push((
+                pc.expr,
+                Rewrite::Call(format!("{}!", pc.macro_name), macro_args),
+            ));
+        }
+    }
+    rewrites
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_translate_plain() {
+        assert_eq!(
+            translate_format("100%% {done}\n"),
+            Some(("100% {{done}}\n".into(), vec![]))
+        );
+    }
+
+    #[test]
+    fn test_translate_conversions() {
+        use ArgKind::*;
+        assert_eq!(
+            translate_format("%d %5u %-3ld %08x %#X %.2f %f %c %10s %.3s"),
+            Some((
+                "{} {:5} {:<3} {:08x} {:#X} {:.2} {:.6} {} {:>10} {:.3}".into(),
+                vec![Plain, Plain, Plain, Plain, Plain, Plain, Plain, Char, CStr, CStr],
+            ))
+        );
+    }
+
+    #[test]
+    fn test_translate_unsupported() {
+        for fmt in ["%e", "%p", "%n", "%*d", "%hd", "%.3d", "%#o", "%05s", "%"] {
+            assert_eq!(translate_format(fmt), None, "{fmt:?}");
+        }
+    }
+}
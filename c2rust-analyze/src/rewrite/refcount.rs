@@ -0,0 +1,169 @@
+//! Rewrites for pointers to reference-counted structs, which become `Rc<RefCell<T>>`.  See
+//! `crate::refcount` for the analysis that finds them.
+//!
+//! Functions that use these pointers are excluded from the normal rewrites, so this module
+//! handles both the types and the expressions involving the pointers.  Reference counting
+//! functions and unused count fields are removed here as well.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::refcount::{self, RcApiFn, RcPtrUse};
+use crate::rewrite::{self, Rewrite};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use std::collections::HashSet;
+
+/// Extend `span` over any whitespace that follows it, so that removing it doesn't leave a blank
+/// line behind.
+fn removal_span(tcx: TyCtxt, span: Span) -> Span {
+    tcx.sess
+        .source_map()
+        .span_extend_while(span, char::is_whitespace)
+        .unwrap_or(span)
+}
+
+pub fn gen_refcount_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let mut rewrites = Vec::new();
+    if gacx.refcount.structs.is_empty() {
+        return rewrites;
+    }
+
+    let structs = gacx
+        .refcount
+        .structs
+        .keys()
+        .copied()
+        .collect::<HashSet<_>>();
+
+    // Remove the count fields that are no longer used, along with their initializations.
+    for &did in &gacx.refcount.removed_count_fields {
+        let field = gacx.refcount.structs[&did];
+        let hir_id = tcx.hir().local_def_id_to_hir_id(field.expect_local());
+        let field_span = tcx.hir().span(hir_id);
+        let span = tcx
+            .sess
+            .source_map()
+            .span_extend_while(field_span, |c| c == ',' || c.is_whitespace())
+            .unwrap_or(field_span);
+        rewrites.push((span, Rewrite::Text(String::new())));
+    }
+    for &span in &gacx.refcount.count_inits {
+        rewrites.push((removal_span(tcx, span), Rewrite::Text(String::new())));
+    }
+
+    for &did in &gacx.refcount.fns {
+        let ldid = match did.as_local() {
+            Some(x) => x,
+            None => continue,
+        };
+
+        // Reference counting functions are removed entirely, including their attributes.
+        if gacx.refcount.api_fns.contains_key(&did) {
+            let hir_id = tcx.hir().local_def_id_to_hir_id(ldid);
+            let span = tcx
+                .hir()
+                .attrs(hir_id)
+                .iter()
+                .fold(tcx.hir().span_with_body(hir_id), |span, attr| {
+                    span.to(attr.span)
+                });
+            rewrites.push((removal_span(tcx, span), Rewrite::Text(String::new())));
+            continue;
+        }
+
+        for (_, u) in refcount::collect_rc_ptr_uses(tcx, ldid, &structs, &gacx.refcount.api_fns) {
+            let (span, rw) = match u {
+                // `*mut S` -> `Rc<RefCell<S>>`
+                RcPtrUse::Ty { ty, pointee } => (
+                    ty,
+                    Rewrite::TyCtor(
+                        "std::rc::Rc".into(),
+                        vec![Rewrite::TyCtor(
+                            "std::cell::RefCell".into(),
+                            vec![Rewrite::Extract(pointee)],
+                        )],
+                    ),
+                ),
+                // `malloc(n) as *mut S` -> `Rc::new(RefCell::new(S { .. }))`
+                RcPtrUse::Alloc { expr, pointee_ty } => {
+                    let adt_did = pointee_ty.ty_adt_def().map(|adt_def| adt_def.did());
+                    let zero = match adt_did {
+                        Some(adt_did) if gacx.refcount.removed_count_fields.contains(&adt_did) => {
+                            let field = gacx.refcount.structs[&adt_did];
+                            rewrite::gen_zeroize_expr_without_field(
+                                tcx,
+                                pointee_ty,
+                                tcx.item_name(field).as_str(),
+                            )
+                        }
+                        _ => rewrite::gen_zeroize_expr(tcx, pointee_ty),
+                    }
+                    .expect("rc struct should have been checked for zeroize support");
+                    let cell =
+                        Rewrite::Call("std::cell::RefCell::new".into(), vec![Rewrite::Text(zero)]);
+                    (expr, Rewrite::Call("std::rc::Rc::new".into(), vec![cell]))
+                }
+                // `free(p as *mut c_void)` -> `drop(p)`
+                RcPtrUse::Free { call, ptr, .. } => (
+                    call,
+                    Rewrite::Call("std::mem::drop".into(), vec![Rewrite::Sub(0, ptr)]),
+                ),
+                // `*p` -> `*p.borrow()` or `*p.borrow_mut()`
+                RcPtrUse::Deref { expr, ptr, mutbl } => {
+                    let method = if mutbl { "borrow_mut" } else { "borrow" };
+                    let guard =
+                        Rewrite::MethodCall(method.into(), Box::new(Rewrite::Sub(0, ptr)), vec![]);
+                    (expr, Rewrite::Deref(Box::new(guard)))
+                }
+                // `p` -> `p.clone()`
+                RcPtrUse::Copy { expr } => (
+                    expr,
+                    Rewrite::MethodCall("clone".into(), Box::new(Rewrite::Identity), vec![]),
+                ),
+                // `obj_ref(p)` -> `p.clone()`
+                RcPtrUse::ApiCall {
+                    call,
+                    ptr,
+                    kind: RcApiFn::Ref { returns_ptr: true },
+                    ..
+                } => (
+                    call,
+                    Rewrite::MethodCall("clone".into(), Box::new(Rewrite::Sub(0, ptr)), vec![]),
+                ),
+                // Each copy of the pointer already holds its own reference, so `obj_ref(p)` has
+                // nothing left to do, unless `p` is a temporary that needs to be dropped.
+                RcPtrUse::ApiCall {
+                    call,
+                    ptr,
+                    local,
+                    kind: RcApiFn::Ref { returns_ptr: false },
+                } => {
+                    if local {
+                        (call, Rewrite::Text("()".into()))
+                    } else {
+                        (
+                            call,
+                            Rewrite::Call("std::mem::drop".into(), vec![Rewrite::Sub(0, ptr)]),
+                        )
+                    }
+                }
+                // `obj_unref(p)` -> `drop(p)`
+                RcPtrUse::ApiCall {
+                    call,
+                    ptr,
+                    kind: RcApiFn::Unref,
+                    ..
+                } => (
+                    call,
+                    Rewrite::Call("std::mem::drop".into(), vec![Rewrite::Sub(0, ptr)]),
+                ),
+                RcPtrUse::Other => {
+                    // `find_refcounted_structs` rejects any struct with an `Other` use.
+                    unreachable!("unsupported use of rc pointer")
+                }
+            };
+            rewrites.push((span, rw));
+        }
+    }
+    rewrites
+}
@@ -0,0 +1,167 @@
+//! Safe wrappers for exported `extern "C"` functions.
+//!
+//! A `#[no_mangle] pub unsafe extern "C" fn foo(..)` is called from C, so its signature has to
+//! keep the original raw pointer types.  When `C2RUST_ANALYZE_SAFE_WRAPPERS=1` is set and the body
+//! of such a function has no unsafe operations left after rewriting (see `unsafety`), we split it
+//! in two:
+//!
+//! * The rewritten function is renamed to `foo_safe`, and loses its `#[no_mangle]` attribute and
+//!   its `unsafe` and `extern "C"` qualifiers.  Rewritten Rust callers call it directly.
+//! * A new `#[no_mangle] pub unsafe extern "C" fn foo(..)` with the original signature casts its
+//!   arguments to the rewritten types, calls `foo_safe`, and casts the result back, in the same way
+//!   as the shims generated for non-rewritten callers.
+//!
+//! Non-rewritten callers keep calling `foo`, so they don't need shims for these functions.
+
+use crate::context::{GlobalAnalysisCtxt, GlobalAssignment};
+use crate::rewrite::shim::{self, ManualShimCasts};
+use crate::rewrite::Rewrite;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{Expr, ExprKind, Unsafety};
+use rustc_middle::hir::nested_filter;
+use rustc_middle::middle::codegen_fn_attrs::CodegenFnAttrFlags;
+use rustc_middle::ty::{TyCtxt, TypeckResults};
+use rustc_span::sym;
+use rustc_span::Span;
+use rustc_target::spec::abi::Abi;
+use std::collections::HashSet;
+
+/// Check whether `def_id` is a `#[no_mangle] pub unsafe extern "C" fn`, or one with any other
+/// non-Rust ABI.
+pub fn is_exported_extern_fn(tcx: TyCtxt, def_id: DefId) -> bool {
+    if tcx.def_kind(def_id) != DefKind::Fn || !def_id.is_local() {
+        return false;
+    }
+    if !tcx
+        .codegen_fn_attrs(def_id)
+        .flags
+        .contains(CodegenFnAttrFlags::NO_MANGLE)
+    {
+        return false;
+    }
+    let sig = tcx.fn_sig(def_id);
+    tcx.visibility(def_id).is_public()
+        && sig.abi() != Abi::Rust
+        && sig.unsafety() == Unsafety::Unsafe
+}
+
+fn safe_name(tcx: TyCtxt, def_id: DefId) -> String {
+    format!("{}_safe", tcx.item_name(def_id))
+}
+
+/// Generate rewrites that turn `def_id` into a safe function `foo_safe`, and define the extern
+/// wrapper `foo` after it.
+pub fn gen_safe_wrapper_rewrites<'tcx>(
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    gasn: &GlobalAssignment,
+    def_id: DefId,
+    manual_casts: ManualShimCasts,
+) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let source_map = tcx.sess.source_map();
+    let ldid = def_id.expect_local();
+    let item = tcx.hir().expect_item(ldid);
+    let name = item.ident.as_str().to_owned();
+    let mut rewrites = Vec::new();
+
+    // `foo` -> `foo_safe`
+    rewrites.push((item.ident.span, Rewrite::Text(safe_name(tcx, def_id))));
+
+    // `pub unsafe extern "C" fn` -> `pub fn`
+    let header = item.span.with_hi(item.ident.span.lo());
+    let old_header = source_map
+        .span_to_snippet(header)
+        .unwrap_or_else(|e| panic!("failed to get header of {def_id:?}: {e:?}"));
+    let quals = item.vis_span.shrink_to_hi().with_hi(item.ident.span.lo());
+    rewrites.push((quals, Rewrite::Text(" fn ".into())));
+
+    // Remove `#[no_mangle]`, along with the whitespace after it.
+    let hir_id = tcx.hir().local_def_id_to_hir_id(ldid);
+    for attr in tcx.hir().attrs(hir_id) {
+        if attr.has_name(sym::no_mangle) {
+            let span = source_map
+                .span_extend_while(attr.span, char::is_whitespace)
+                .unwrap_or(attr.span);
+            rewrites.push((span, Rewrite::Text(String::new())));
+        }
+    }
+
+    // The wrapper keeps the original header, including the `pub`.
+    let wrapper = shim::gen_shim_fn(
+        gacx,
+        gasn,
+        def_id,
+        format!("#[no_mangle]\n{}", old_header.trim_end()),
+        name,
+        safe_name(tcx, def_id),
+        manual_casts,
+    );
+    rewrites.push((item.span.shrink_to_hi(), wrapper));
+
+    rewrites
+}
+
+/// Visitor that renames direct calls to functions with safe wrappers.
+struct SafeCallVisitor<'a, 'tcx> {
+    gacx: &'a GlobalAnalysisCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    rewrites: Vec<(Span, Rewrite)>,
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for SafeCallVisitor<'a, 'tcx> {
+    type NestedFilter = nested_filter::OnlyBodies;
+
+    fn nested_visit_map(&mut self) -> Self::Map {
+        self.gacx.tcx.hir()
+    }
+
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        if let ExprKind::Call(callee, _) = ex.kind {
+            if let ExprKind::Path(ref qp) = callee.kind {
+                if let Res::Def(DefKind::Fn, def_id) =
+                    self.typeck_results.qpath_res(qp, callee.hir_id)
+                {
+                    if self.gacx.safe_wrapper_fns.contains(&def_id) {
+                        let name = safe_name(self.gacx.tcx, def_id);
+                        self.rewrites
+                            .push((qp.last_segment_span(), Rewrite::Text(name)));
+                    }
+                }
+            }
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// In each of the `rewritten_fns`, change calls to `foo` into calls to `foo_safe` for each
+/// function `foo` that has a safe wrapper.  The arguments were already rewritten to the safe
+/// types, so they match the signature of `foo_safe`.
+pub fn gen_safe_wrapper_call_rewrites(
+    gacx: &GlobalAnalysisCtxt,
+    rewritten_fns: &HashSet<LocalDefId>,
+) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let mut rewrites = Vec::new();
+    if gacx.safe_wrapper_fns.is_empty() {
+        return rewrites;
+    }
+    for &ldid in rewritten_fns {
+        if gacx.dont_rewrite_fn(ldid.to_def_id()) {
+            continue;
+        }
+        let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+            Some(x) => x,
+            None => continue,
+        };
+        let mut v = SafeCallVisitor {
+            gacx,
+            typeck_results: tcx.typeck_body(hir_body_id),
+            rewrites,
+        };
+        v.visit_body(tcx.hir().body(hir_body_id));
+        rewrites = v.rewrites;
+    }
+    rewrites
+}
@@ -0,0 +1,208 @@
+//! Rewrites for self-referential structs.  See `crate::self_ref` for the analysis that finds them.
+//!
+//! With the index strategy, the functions that use the self-pointers are excluded from the normal
+//! rewrites, so this module handles both the pointer fields' types and every use of them.  With
+//! the pin strategy, the pointers are left alone, and this module only adds the `PhantomPinned`
+//! field to the struct definition and to each struct literal.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::rewrite::Rewrite;
+use crate::self_ref::{self, SelfPtrUse, SelfRefStrategy};
+use rustc_hir as hir;
+use rustc_hir::def_id::DefId;
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{Expr, ExprKind};
+use rustc_middle::ty::{TyCtxt, TyKind, TypeckResults};
+use rustc_span::{BytePos, Span};
+use std::collections::HashSet;
+
+/// The field added to structs using the pin strategy.
+const PIN_FIELD: &str = "_pin: std::marker::PhantomPinned";
+
+/// `(e)`.  The emitter only parenthesizes casts based on the precedence of the enclosing
+/// rewrite, so a sum used as the operand of a cast needs explicit parentheses.
+fn paren(rw: Rewrite) -> Rewrite {
+    Rewrite::Call(String::new(), vec![rw])
+}
+
+/// `rw as ty`
+fn cast(rw: Rewrite, ty: &str) -> Rewrite {
+    Rewrite::Cast(Box::new(rw), Box::new(Rewrite::Print(ty.into())))
+}
+
+/// `(base as isize + delta as isize) as usize`, the index `delta` elements after `base`.
+fn offset_index(base: Rewrite, delta: Span) -> Rewrite {
+    let sum = Rewrite::Binary(
+        "+".into(),
+        Box::new(cast(base, "isize")),
+        Box::new(cast(Rewrite::Sub(1, delta), "isize")),
+    );
+    cast(paren(sum), "usize")
+}
+
+/// Add `field` at the end of the struct definition or literal whose closing brace is `close`.
+/// The new field goes on its own line if the last existing field does, and a comma is added
+/// after the last existing field if it doesn't already have one.
+fn gen_add_field_rewrite(tcx: TyCtxt, close: Span, field: &str) -> (Span, Rewrite) {
+    let prev = tcx
+        .sess
+        .source_map()
+        .span_to_prev_source(close)
+        .unwrap_or_default();
+    let trimmed = prev.trim_end();
+    let gap = &prev[trimmed.len()..];
+    let comma = if trimmed.ends_with(',') || trimmed.ends_with('{') {
+        ""
+    } else {
+        ","
+    };
+    let text = match gap.rfind('\n') {
+        Some(i) => format!("{comma}\n{}    {field},{gap}}}", &gap[i + 1..]),
+        None => format!("{comma} {field} }}"),
+    };
+    let span = close.with_lo(close.lo() - BytePos(gap.len() as u32));
+    (span, Rewrite::Text(text))
+}
+
+/// The closing brace of `span`, which should end with one.
+fn closing_brace(span: Span) -> Span {
+    span.with_lo(span.hi() - BytePos(1))
+}
+
+/// Visitor that finds struct literals of the `structs` that set every field explicitly.
+struct StructLitVisitor<'a, 'tcx> {
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    structs: &'a HashSet<DefId>,
+    spans: Vec<Span>,
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for StructLitVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        if let ExprKind::Struct(_, _, None) = ex.kind {
+            if let TyKind::Adt(adt_def, _) = *self.typeck_results.expr_ty(ex).kind() {
+                if self.structs.contains(&adt_def.did()) {
+                    self.spans.push(ex.span);
+                }
+            }
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+pub fn gen_self_ref_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let info = &gacx.self_ref;
+    let mut rewrites = Vec::new();
+    if info.structs.is_empty() {
+        return rewrites;
+    }
+
+    // Pin strategy: add the `PhantomPinned` field to each definition and struct literal.
+    let pin_structs = info
+        .structs_with(SelfRefStrategy::Pin)
+        .map(|(did, _)| did)
+        .collect::<HashSet<_>>();
+    for &did in &pin_structs {
+        let item = tcx.hir().expect_item(did.expect_local());
+        let is_pub = match item.kind {
+            hir::ItemKind::Struct(ref variant, _) => variant.fields().last().map_or(false, |f| {
+                tcx.visibility(tcx.hir().local_def_id(f.hir_id)).is_public()
+            }),
+            _ => unreachable!("self-referential struct {did:?} should be a struct"),
+        };
+        let field = if is_pub {
+            format!("pub {PIN_FIELD}")
+        } else {
+            PIN_FIELD.to_owned()
+        };
+        rewrites.push(gen_add_field_rewrite(tcx, closing_brace(item.span), &field));
+    }
+    if !pin_structs.is_empty() {
+        for ldid in tcx.hir().body_owners() {
+            let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+                Some(x) => x,
+                None => continue,
+            };
+            let mut v = StructLitVisitor {
+                typeck_results: tcx.typeck_body(hir_body_id),
+                structs: &pin_structs,
+                spans: Vec::new(),
+            };
+            v.visit_body(tcx.hir().body(hir_body_id));
+            for span in v.spans {
+                rewrites.push(gen_add_field_rewrite(tcx, closing_brace(span), PIN_FIELD));
+            }
+        }
+    }
+
+    // Index strategy: `*mut T` -> `usize` in the definition, and every use of the pointers.
+    let ptrs = info.index_ptrs();
+    if ptrs.is_empty() {
+        return rewrites;
+    }
+    for (did, _) in info.structs_with(SelfRefStrategy::Index) {
+        let item = tcx.hir().expect_item(did.expect_local());
+        let variant = match item.kind {
+            hir::ItemKind::Struct(ref variant, _) => variant,
+            _ => unreachable!("self-referential struct {did:?} should be a struct"),
+        };
+        for field in variant.fields() {
+            let field_did = tcx.hir().local_def_id(field.hir_id).to_def_id();
+            if ptrs.contains_key(&field_did) {
+                rewrites.push((field.ty.span, Rewrite::Print("usize".into())));
+            }
+        }
+    }
+
+    for ldid in tcx.hir().body_owners() {
+        for (_, u) in self_ref::collect_self_ptr_uses(tcx, ldid, &ptrs) {
+            match u {
+                // `(*p).buf.as_mut_ptr()` -> `0`
+                // `(*p).buf.as_mut_ptr().offset(i)` -> `i as usize`
+                SelfPtrUse::Addr { expr, index } => {
+                    let index = match index {
+                        Some(index) => cast(Rewrite::Sub(0, index), "usize"),
+                        None => Rewrite::LitZero,
+                    };
+                    rewrites.push((expr, index));
+                }
+                // `ptr::null_mut()` -> `0`
+                SelfPtrUse::Null { expr } => rewrites.push((expr, Rewrite::LitZero)),
+                // `(*p).cur.offset(n)` -> `((*p).cur as isize + n as isize) as usize`
+                SelfPtrUse::Advance { expr, ptr, delta } => {
+                    rewrites.push((expr, offset_index(Rewrite::Sub(0, ptr), delta)));
+                }
+                // `*(*p).cur` -> `(*p).buf[(*p).cur]`
+                // `*(*p).cur.offset(n)` -> `(*p).buf[((*p).cur as isize + n as isize) as usize]`
+                SelfPtrUse::Deref {
+                    expr,
+                    field,
+                    ident,
+                    target,
+                    delta,
+                } => {
+                    // `field` is used twice: once with the field renamed, as the array, and once as
+                    // originally written, as the index.
+                    let index = match delta {
+                        Some(delta) => offset_index(Rewrite::Extract(field), delta),
+                        None => Rewrite::Extract(field),
+                    };
+                    rewrites.push((ident, Rewrite::Text(tcx.item_name(target).to_string())));
+                    rewrites.push((
+                        expr,
+                        Rewrite::Index(Box::new(Rewrite::Sub(0, field)), Box::new(index)),
+                    ));
+                }
+                // `(*p).cur.offset_from((*p).buf.as_ptr())` -> `((*p).cur as isize)`
+                SelfPtrUse::Distance { expr, ptr } => {
+                    rewrites.push((expr, paren(cast(Rewrite::Sub(0, ptr), "isize"))));
+                }
+                SelfPtrUse::Other => {
+                    // `find_self_ref_structs` rejects any struct with an `Other` use.
+                    unreachable!("unsupported use of self-pointer")
+                }
+            }
+        }
+    }
+    rewrites
+}
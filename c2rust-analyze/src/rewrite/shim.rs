@@ -1,16 +1,17 @@
-use crate::context::LTy;
 use crate::context::{FlagSet, GlobalAnalysisCtxt, GlobalAssignment};
+use crate::context::{LFnSig, LTy};
+use crate::crate_facts;
 use crate::rewrite::expr::{self, CastBuilder};
 use crate::rewrite::ty;
 use crate::rewrite::Rewrite;
 use crate::type_desc::{self, TypeDesc};
 use rustc_hir::def::{DefKind, Res};
-use rustc_hir::def_id::DefId;
+use rustc_hir::def_id::{DefId, LocalDefId};
 use rustc_hir::intravisit::{self, Visitor};
 use rustc_hir::{Expr, ExprKind, FnRetTy};
 use rustc_middle::hir::nested_filter;
 use rustc_middle::ty::{DefIdTree, TyCtxt, TypeckResults};
-use rustc_span::Span;
+use rustc_span::{sym, Span};
 use std::collections::HashSet;
 use std::mem;
 
@@ -18,6 +19,7 @@ struct ShimCallVisitor<'a, 'tcx> {
     gacx: &'a GlobalAnalysisCtxt<'tcx>,
     gasn: &'a GlobalAssignment,
     typeck_results: &'tcx TypeckResults<'tcx>,
+    caller: LocalDefId,
     rewrites: Vec<(Span, Rewrite)>,
     mentioned_fns: HashSet<DefId>,
 }
@@ -26,8 +28,11 @@ impl<'a, 'tcx> ShimCallVisitor<'a, 'tcx> {
     fn handle_def_mention(&mut self, def_id: DefId, span: Span) {
         let tcx = self.gacx.tcx;
 
-        // We only care about mentions of local functions, not including trait methods.
-        if !def_id.is_local() {
+        // We only care about mentions of local functions, not including trait methods, and of
+        // functions from other crates of the workspace, which have shims if their signatures
+        // changed.  See `crate_facts`.
+        let is_upstream = self.gacx.upstream_fn_facts.contains_key(&def_id);
+        if !def_id.is_local() && !is_upstream {
             return;
         }
 
@@ -46,20 +51,30 @@ impl<'a, 'tcx> ShimCallVisitor<'a, 'tcx> {
             _ => return,
         }
 
+        // Calls to a callee that was copied for this caller go to the copy, which keeps the
+        // original signature.
+        let is_raw_clone = def_id.as_local().map_or(false, |callee| {
+            self.gacx.raw_clone_calls.contains(&(self.caller, callee))
+        });
+        if is_raw_clone && !self.gacx.dont_rewrite_fn(def_id) {
+            self.rewrites
+                .push((span.shrink_to_hi(), Rewrite::Text("_raw".to_owned())));
+            return;
+        }
+
+        // Exported functions with safe wrappers keep their original signature under their
+        // original name, so non-rewritten callers can keep calling them directly.
+        if self.gacx.safe_wrapper_fns.contains(&def_id) {
+            return;
+        }
+
         // Only functions whose signatures might change are relevant here.  Check that the function
         // has at least one non-`FIXED` pointer in its signature.
         let lsig = match self.gacx.fn_sigs.get(&def_id) {
             Some(x) => x,
             None => return,
         };
-        let has_non_fixed_ptr = lsig
-            .inputs_and_output()
-            .flat_map(|lty| lty.iter())
-            .any(|lty| {
-                let ptr = lty.label;
-                !ptr.is_none() && !self.gasn.flags[ptr].contains(FlagSet::FIXED)
-            });
-        if !has_non_fixed_ptr {
+        if !has_non_fixed_ptr(self.gasn, lsig) {
             return;
         }
 
@@ -67,10 +82,22 @@ impl<'a, 'tcx> ShimCallVisitor<'a, 'tcx> {
         let insert_span = span.shrink_to_hi();
         self.rewrites
             .push((insert_span, Rewrite::Text("_shim".to_owned())));
-        self.mentioned_fns.insert(def_id);
+        // Shims for functions from other crates were generated when their crate was rewritten.
+        if !is_upstream {
+            self.mentioned_fns.insert(def_id);
+        }
     }
 }
 
+fn has_non_fixed_ptr(gasn: &GlobalAssignment, lsig: &LFnSig) -> bool {
+    lsig.inputs_and_output()
+        .flat_map(|lty| lty.iter())
+        .any(|lty| {
+            let ptr = lty.label;
+            !ptr.is_none() && !gasn.flags[ptr].contains(FlagSet::FIXED)
+        })
+}
+
 impl<'a, 'tcx> Visitor<'tcx> for ShimCallVisitor<'a, 'tcx> {
     type NestedFilter = nested_filter::OnlyBodies;
 
@@ -139,6 +166,7 @@ pub fn gen_shim_call_rewrites<'tcx>(
             gacx,
             gasn,
             typeck_results,
+            caller: skip_def_id,
             rewrites,
             mentioned_fns,
         };
@@ -151,6 +179,91 @@ pub fn gen_shim_call_rewrites<'tcx>(
     (rewrites, mentioned_fns)
 }
 
+/// List the exported functions of the current crate whose signatures might change, other than
+/// those with safe wrappers.  Callers in other crates of the workspace may not be rewritten, so
+/// these all get shims.  See `crate_facts`.
+pub fn exported_shim_fns<'tcx>(
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    gasn: &GlobalAssignment,
+) -> Vec<DefId> {
+    let tcx = gacx.tcx;
+    let mut def_ids = gacx
+        .fn_sigs
+        .iter()
+        .filter(|&(&did, lsig)| {
+            crate_facts::is_exported_fn(tcx, did)
+                && tcx.def_kind(did) == DefKind::Fn
+                && !gacx.safe_wrapper_fns.contains(&did)
+                && has_non_fixed_ptr(gasn, lsig)
+        })
+        .map(|(&did, _)| did)
+        .collect::<Vec<_>>();
+    def_ids.sort();
+    def_ids
+}
+
+/// Get the name of the copy of `ldid` made for its non-rewritten callers, `foo_raw` for `foo`.
+/// Returns `None` if the module defining `ldid` already has an item by that name.
+pub fn raw_clone_name(tcx: TyCtxt, ldid: LocalDefId) -> Option<String> {
+    let name = format!("{}_raw", tcx.item_name(ldid.to_def_id()));
+    let module = tcx.parent_module_from_def_id(ldid);
+    let taken = tcx.hir().items().any(|id| {
+        tcx.parent_module_from_def_id(id.def_id) == module
+            && tcx.hir().item(id).ident.as_str() == name
+    });
+    (!taken).then_some(name)
+}
+
+/// For each callee in `gacx.raw_clone_calls` that is still rewritten, insert `foo_raw`, a copy
+/// of the original definition of `foo`, right after it.  The calls from non-rewritten callers
+/// are redirected to the copy by `gen_shim_call_rewrites`.
+///
+/// The copy keeps the attributes of the original, except the ones that set its symbol name, such
+/// as `#[no_mangle]`, which would make the two definitions clash at link time.
+pub fn gen_raw_clone_definition_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let source_map = tcx.sess.source_map();
+
+    let mut callees = gacx
+        .raw_clone_calls
+        .iter()
+        .map(|&(_, callee)| callee)
+        .filter(|&callee| !gacx.dont_rewrite_fn(callee.to_def_id()))
+        .collect::<Vec<_>>();
+    callees.sort();
+    callees.dedup();
+
+    let mut rewrites = Vec::new();
+    for ldid in callees {
+        let item = tcx.hir().expect_item(ldid);
+        let snippet = |span: Span| {
+            source_map
+                .span_to_snippet(span)
+                .unwrap_or_else(|e| panic!("failed to get source of {ldid:?}: {e:?}"))
+        };
+        let hir_id = tcx.hir().local_def_id_to_hir_id(ldid);
+        let mut attrs = String::new();
+        for attr in tcx.hir().attrs(hir_id) {
+            if attr.span.from_expansion()
+                || [sym::no_mangle, sym::export_name, sym::link_section]
+                    .iter()
+                    .any(|&name| attr.has_name(name))
+            {
+                continue;
+            }
+            attrs.push_str(&snippet(attr.span));
+            attrs.push('\n');
+        }
+        let header = snippet(item.span.with_hi(item.ident.span.lo()));
+        let rest = snippet(item.span.with_lo(item.ident.span.hi()));
+        let name = raw_clone_name(tcx, ldid)
+            .unwrap_or_else(|| panic!("name of the copy of {ldid:?} is taken"));
+        let text = format!("\n\n{attrs}{header}{name}{rest}");
+        rewrites.push((item.span.shrink_to_hi(), Rewrite::Text(text)));
+    }
+    rewrites
+}
+
 /// Convert an `LTy` to a pair of `TypeDesc`s, one computed normally and one with `FIXED` added.
 /// Returns `None` if the input `LTy` already has `FIXED` set.
 fn lty_to_desc_pair<'tcx>(
@@ -189,9 +302,41 @@ pub fn gen_shim_definition_rewrite<'tcx>(
     manual_casts: ManualShimCasts,
 ) -> (Span, Rewrite) {
     let tcx = gacx.tcx;
+    let owner_node = tcx.hir().expect_owner(def_id.as_local().unwrap());
+    let name = owner_node.ident().unwrap().as_str().to_owned();
+    // Shims of public functions are public too, so callers in other crates can use them.
+    let header = if tcx.visibility(def_id).is_public() {
+        "pub unsafe fn"
+    } else {
+        "unsafe fn"
+    };
+    let rw = gen_shim_fn(
+        gacx,
+        gasn,
+        def_id,
+        header.into(),
+        format!("{name}_shim"),
+        name,
+        manual_casts,
+    );
+    (owner_node.span().shrink_to_hi(), rw)
+}
+
+/// Define a function `header name(..)` that takes the original, unrewritten argument types of
+/// `def_id`, casts them to the rewritten types, calls `callee` (which should have the rewritten
+/// signature of `def_id`), and casts the result back to the original return type.
+pub fn gen_shim_fn<'tcx>(
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    gasn: &GlobalAssignment,
+    def_id: DefId,
+    header: String,
+    name: String,
+    callee: String,
+    manual_casts: ManualShimCasts,
+) -> Rewrite {
+    let tcx = gacx.tcx;
 
     let owner_node = tcx.hir().expect_owner(def_id.as_local().unwrap());
-    let insert_span = owner_node.span().shrink_to_hi();
 
     let fn_decl = owner_node.fn_decl().unwrap();
     let arg_tys = fn_decl
@@ -207,6 +352,10 @@ pub fn gen_shim_definition_rewrite<'tcx>(
     // `def_id` should always refer to a rewritten function, and all rewritten functions have
     // valid `fn_sigs` entries.
     let lsig = gacx.fn_sigs[&def_id];
+    // Rust has no way to forward the shim's own `...` arguments to the callee.
+    if lsig.c_variadic {
+        panic!("can't generate a shim for C-variadic function {def_id:?}");
+    }
 
     // 1 cast per arg, 1 call, 1 cast for the result.  The final result is returned using the
     // trailing expression of the block.
@@ -218,7 +367,7 @@ pub fn gen_shim_definition_rewrite<'tcx>(
         let mut hir_rw = Rewrite::FnArg(i);
 
         if let Some((arg_desc, fixed_desc)) = lty_to_desc_pair(tcx, gasn, arg_lty) {
-            let mut cast_builder = CastBuilder::new(tcx, &gasn.perms, &gasn.flags, |rk| {
+            let mut cast_builder = CastBuilder::new(tcx, &gasn.perms, &gasn.flags, false, |rk| {
                 hir_rw = expr::convert_cast_rewrite(&rk, mem::take(&mut hir_rw));
             });
             match cast_builder.try_build_cast_desc_desc(fixed_desc, arg_desc) {
@@ -246,13 +395,13 @@ pub fn gen_shim_definition_rewrite<'tcx>(
     }
 
     // Generate the call: `let safe_result = f(safe_arg0, safe_arg1);`
-    let call_rw = Rewrite::Call(owner_node.ident().unwrap().as_str().to_owned(), arg_exprs);
+    let call_rw = Rewrite::Call(callee, arg_exprs);
     stmts.push(Rewrite::Let1("safe_result".into(), Box::new(call_rw)));
 
     // Generate `let result = safe_result as ...;`
     let mut result_rw = Rewrite::Print("safe_result".into());
     if let Some((return_desc, fixed_desc)) = lty_to_desc_pair(tcx, gasn, lsig.output) {
-        let mut cast_builder = CastBuilder::new(tcx, &gasn.perms, &gasn.flags, |rk| {
+        let mut cast_builder = CastBuilder::new(tcx, &gasn.perms, &gasn.flags, false, |rk| {
             result_rw = expr::convert_cast_rewrite(&rk, mem::take(&mut result_rw));
         });
         match cast_builder.try_build_cast_desc_desc(return_desc, fixed_desc) {
@@ -275,11 +424,11 @@ pub fn gen_shim_definition_rewrite<'tcx>(
     // Build the function body.
     let body_rw = Rewrite::Block(stmts, Some(Box::new(Rewrite::Print("result".into()))));
 
-    let rw = Rewrite::DefineFn {
-        name: format!("{}_shim", owner_node.ident().unwrap().as_str()),
+    Rewrite::DefineFn {
+        header,
+        name,
         arg_tys,
         return_ty,
         body: Box::new(body_rw),
-    };
-    (insert_span, rw)
+    }
 }
@@ -0,0 +1,105 @@
+//! Rewrites for functions that return pointers into static buffers, which become functions
+//! returning `Vec<u8>` or `&'static [T]`.  See `crate::static_bufs` for the analysis that finds
+//! them.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::rewrite::Rewrite;
+use crate::static_bufs::{self, StaticBufUse};
+use rustc_hir::def::Namespace;
+use rustc_hir::{FnRetTy, Mutability};
+use rustc_middle::ty::print::{FmtPrinter, Print};
+use rustc_middle::ty::{Ty, TyCtxt};
+use rustc_span::Span;
+use std::collections::HashSet;
+
+fn print_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> String {
+    let printer = FmtPrinter::new(tcx, Namespace::TypeNS);
+    ty.print(printer).unwrap().into_buffer()
+}
+
+pub fn gen_static_buf_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let info = &gacx.static_bufs;
+    let mut rewrites = Vec::new();
+    if info.fns.is_empty() {
+        return rewrites;
+    }
+
+    // `-> *mut c_char` -> `-> Vec<u8>` or `-> &'static [c_char]`
+    for (&did, f) in &info.fns {
+        let decl = tcx
+            .hir()
+            .fn_decl_by_hir_id(tcx.hir().local_def_id_to_hir_id(did.expect_local()))
+            .unwrap_or_else(|| panic!("{did:?} should be a function"));
+        if let FnRetTy::Return(ty) = decl.output {
+            let rw = match f.mutbl {
+                Mutability::Not => {
+                    Rewrite::Print(format!("&'static [{}]", print_ty(tcx, f.elem_ty)))
+                }
+                Mutability::Mut => Rewrite::TyCtor("Vec".into(), vec![Rewrite::Print("u8".into())]),
+            };
+            rewrites.push((ty.span, rw));
+        }
+    }
+
+    let users = info
+        .fns
+        .keys()
+        .chain(&info.callers)
+        .copied()
+        .collect::<HashSet<_>>();
+    for did in users {
+        let ldid = match did.as_local() {
+            Some(x) => x,
+            None => continue,
+        };
+        for u in static_bufs::collect_static_buf_uses(tcx, ldid, info) {
+            match u {
+                StaticBufUse::Return { expr, static_path } => {
+                    let rw = match info.fns[&did].mutbl {
+                        // `BUF.as_ptr()` -> `&BUF`
+                        Mutability::Not => {
+                            Rewrite::Ref(Box::new(Rewrite::Extract(static_path)), Mutability::Not)
+                        }
+                        // `BUF.as_mut_ptr()` ->
+                        // `CStr::from_ptr(BUF.as_ptr() as *const c_char).to_bytes_with_nul().to_vec()`
+                        Mutability::Mut => {
+                            let ptr = Rewrite::Cast(
+                                Box::new(Rewrite::MethodCall(
+                                    "as_ptr".into(),
+                                    Box::new(Rewrite::Extract(static_path)),
+                                    vec![],
+                                )),
+                                Box::new(Rewrite::Print("*const std::os::raw::c_char".into())),
+                            );
+                            let cstr = Rewrite::Call("std::ffi::CStr::from_ptr".into(), vec![ptr]);
+                            let bytes = Rewrite::MethodCall(
+                                "to_bytes_with_nul".into(),
+                                Box::new(cstr),
+                                vec![],
+                            );
+                            Rewrite::MethodCall("to_vec".into(), Box::new(bytes), vec![])
+                        }
+                    };
+                    rewrites.push((expr, rw));
+                }
+                // `f(x)` -> `f(x).as_ptr() as *mut c_char`
+                StaticBufUse::Call { call, callee } => {
+                    let ret_ty = print_ty(tcx, info.fns[&callee].ret_ty);
+                    rewrites.push((
+                        call,
+                        Rewrite::Cast(
+                            Box::new(Rewrite::MethodCall(
+                                "as_ptr".into(),
+                                Box::new(Rewrite::Identity),
+                                vec![],
+                            )),
+                            Box::new(Rewrite::Print(ret_ty)),
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    rewrites
+}
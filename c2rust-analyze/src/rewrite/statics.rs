@@ -1,11 +1,19 @@
-use crate::context::GlobalAssignment;
 use crate::context::{FlagSet, PermissionSet};
-use crate::pointer_id::PointerId;
+use crate::context::{GlobalAnalysisCtxt, GlobalAssignment};
+use crate::pointee_type::PointeeTypes;
+use crate::pointer_id::{GlobalPointerTable, PointerId};
+use crate::rewrite::ty;
 use crate::rewrite::Rewrite;
+use crate::static_inits::InitValue;
+use crate::static_kinds::{self, StaticKind, StaticUse};
+use crate::type_desc::{self, Ownership, Quantity};
 use rustc_hir::def_id::DefId;
 use rustc_hir::{ItemKind, Mutability, Node};
-use rustc_middle::ty::TyCtxt;
+use rustc_infer::infer::TyCtxtInferExt;
+use rustc_middle::ty::{self, ParamEnv, Ty, TyCtxt, TyKind};
 use rustc_span::Span;
+use rustc_trait_selection::infer::InferCtxtExt;
+use std::collections::HashSet;
 
 /// For every static, if its write permission does not match its declared mutability, emit a rewrite
 /// changing the declaration to match observed/analyzed usage.
@@ -35,6 +43,11 @@ pub fn gen_static_rewrites<'tcx>(
     };
     let perms = gasn.perms[ptr];
     let written_to = perms.contains(PermissionSet::WRITE);
+    // A non-`mut` static must be `Sync`, so a `static mut` of a type that isn't, such as one
+    // containing a raw pointer, stays `mut` even if it's never written.
+    if is_mutable && !written_to && !is_sync(tcx, tcx.type_of(def_id)) {
+        return None;
+    }
     if written_to != is_mutable {
         let ident = tcx
             .opt_item_ident(def_id)
@@ -56,3 +69,353 @@ pub fn gen_static_rewrites<'tcx>(
         None
     }
 }
+
+/// Check whether `ty` implements `Sync`.
+fn is_sync<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> bool {
+    let sync_trait = match tcx.lang_items().sync_trait() {
+        Some(x) => x,
+        None => return false,
+    };
+    tcx.infer_ctxt().enter(|infcx| {
+        infcx
+            .type_implements_trait(sync_trait, ty, ty::List::empty(), ParamEnv::reveal_all())
+            .must_apply_modulo_regions()
+    })
+}
+
+const ATOMIC_ORDERING: &str = "std::sync::atomic::Ordering::SeqCst";
+
+/// `S.lock().unwrap()`, where `S` is the expression being rewritten.
+fn lock_static() -> Rewrite {
+    let locked = Rewrite::MethodCall("lock".into(), Box::new(Rewrite::Identity), vec![]);
+    Rewrite::MethodCall("unwrap".into(), Box::new(locked), vec![])
+}
+
+/// `{ let v = rw; v }`.  Reading through a block like this drops any lock guard created by `rw`
+/// at the end of the `let`, rather than at the end of the enclosing statement.
+fn read_in_block(rw: Rewrite) -> Rewrite {
+    Rewrite::Block(
+        vec![Rewrite::Let1("v".into(), Box::new(rw))],
+        Some(Box::new(Rewrite::Text("v".into()))),
+    )
+}
+
+/// Generate rewrites for the declaration of a `static mut` of the given `kind`, as classified by
+/// `crate::static_kinds`.
+fn gen_static_kind_decl_rewrites(
+    tcx: TyCtxt,
+    def_id: DefId,
+    kind: StaticKind,
+    rewrites: &mut Vec<(Span, Rewrite)>,
+) {
+    let item = match tcx.hir().get_if_local(def_id) {
+        Some(Node::Item(item)) => item,
+        _ => panic!("def id {:?} not found", def_id),
+    };
+    let (hir_ty, body_id) = match item.kind {
+        ItemKind::Static(ty, _, body_id) => (ty, body_id),
+        _ => panic!("expected item {:?} to be a `static`", item),
+    };
+    let init_span = tcx.hir().body(body_id).value.span;
+
+    let (ty_rw, init_rw) = match kind {
+        StaticKind::ReadOnly => return,
+        // `static mut S: T = e;` -> `static S: OnceLock<T> = OnceLock::new();`
+        StaticKind::InitOnce => (
+            Rewrite::TyCtor(
+                "std::sync::OnceLock".into(),
+                vec![Rewrite::Extract(hir_ty.span)],
+            ),
+            Rewrite::Text("std::sync::OnceLock::new()".into()),
+        ),
+        // `static mut S: i32 = e;` -> `static S: AtomicI32 = AtomicI32::new(e);`
+        StaticKind::Counter => {
+            let name = static_kinds::atomic_ty_name(tcx.type_of(def_id))
+                .expect("counter static should have an atomic type");
+            let path = format!("std::sync::atomic::{name}");
+            (
+                Rewrite::Text(path.clone()),
+                Rewrite::Call(format!("{path}::new"), vec![Rewrite::Identity]),
+            )
+        }
+        // `static mut S: T = e;` -> `static S: Mutex<T> = Mutex::new(e);`
+        StaticKind::Guarded => (
+            Rewrite::TyCtor(
+                "std::sync::Mutex".into(),
+                vec![Rewrite::Extract(hir_ty.span)],
+            ),
+            Rewrite::Call("std::sync::Mutex::new".into(), vec![Rewrite::Identity]),
+        ),
+    };
+
+    let ident = tcx
+        .opt_item_ident(def_id)
+        .expect("def_id has no ident when trying to generate rewrite for static item");
+    let span = ident.span.with_hi(item.span.hi());
+    rewrites.push((item.span, Rewrite::StaticMut(Mutability::Not, span)));
+    rewrites.push((hir_ty.span, ty_rw));
+    rewrites.push((init_span, init_rw));
+}
+
+/// Rewrite each `static mut` classified by `crate::static_kinds`, along with all of its uses, to
+/// the corresponding safe alternative.
+pub fn gen_static_kind_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let mut rewrites = Vec::new();
+    let candidates = gacx
+        .static_kinds
+        .iter()
+        .filter(|&(_, &kind)| kind != StaticKind::ReadOnly)
+        .map(|(&did, _)| did)
+        .collect::<HashSet<_>>();
+    if candidates.is_empty() {
+        return rewrites;
+    }
+
+    for &did in &candidates {
+        gen_static_kind_decl_rewrites(tcx, did, gacx.static_kinds[&did], &mut rewrites);
+    }
+
+    // `OnceLock` is still unstable on our toolchain, so the rewritten crate needs the feature
+    // gate, unless it already enables it.
+    let uses_once_lock = candidates
+        .iter()
+        .any(|did| gacx.static_kinds[did] == StaticKind::InitOnce);
+    let has_once_cell = tcx
+        .features()
+        .declared_lib_features
+        .iter()
+        .any(|&(name, _)| name.as_str() == "once_cell");
+    if uses_once_lock && !has_once_cell {
+        let crate_start = tcx.hir().root_module().spans.inner_span.shrink_to_lo();
+        rewrites.push((
+            crate_start,
+            Rewrite::Text("#![feature(once_cell)]\n".into()),
+        ));
+    }
+
+    for (did, u) in static_kinds::collect_static_uses(tcx, &candidates) {
+        let kind = gacx.static_kinds[&did];
+        match (kind, u) {
+            // `S` -> `S.get().copied().unwrap_or(e)`, where `e` is the original initializer
+            (
+                StaticKind::InitOnce,
+                StaticUse::Read {
+                    expr,
+                    projected: false,
+                    ..
+                },
+            ) => {
+                let item = match tcx.hir().get_if_local(did) {
+                    Some(Node::Item(item)) => item,
+                    _ => unreachable!(),
+                };
+                let init_span = match item.kind {
+                    ItemKind::Static(_, _, body_id) => tcx.hir().body(body_id).value.span,
+                    _ => unreachable!(),
+                };
+                let get = Rewrite::MethodCall("get".into(), Box::new(Rewrite::Identity), vec![]);
+                let copied = Rewrite::MethodCall("copied".into(), Box::new(get), vec![]);
+                rewrites.push((
+                    expr,
+                    Rewrite::MethodCall(
+                        "unwrap_or".into(),
+                        Box::new(copied),
+                        vec![Rewrite::Extract(init_span)],
+                    ),
+                ));
+            }
+            // `S = e` -> `S.set(e).ok().expect(..)`
+            (
+                StaticKind::InitOnce,
+                StaticUse::Assign {
+                    expr,
+                    lhs,
+                    rhs,
+                    projected: false,
+                    ..
+                },
+            ) => {
+                let set = Rewrite::MethodCall(
+                    "set".into(),
+                    Box::new(Rewrite::Sub(0, lhs)),
+                    vec![Rewrite::Sub(1, rhs)],
+                );
+                let ok = Rewrite::MethodCall("ok".into(), Box::new(set), vec![]);
+                rewrites.push((
+                    expr,
+                    Rewrite::MethodCall(
+                        "expect".into(),
+                        Box::new(ok),
+                        vec![Rewrite::Text(
+                            "\"static initialized more than once\"".into(),
+                        )],
+                    ),
+                ));
+            }
+
+            // `S` -> `S.load(SeqCst)`
+            (
+                StaticKind::Counter,
+                StaticUse::Read {
+                    expr,
+                    projected: false,
+                    ..
+                },
+            ) => {
+                rewrites.push((
+                    expr,
+                    Rewrite::MethodCall(
+                        "load".into(),
+                        Box::new(Rewrite::Identity),
+                        vec![Rewrite::Text(ATOMIC_ORDERING.into())],
+                    ),
+                ));
+            }
+            // `S = e` -> `S.store(e, SeqCst)`, and `S += e` -> `S.fetch_add(e, SeqCst)`
+            (
+                StaticKind::Counter,
+                StaticUse::Assign {
+                    expr,
+                    lhs,
+                    rhs,
+                    projected: false,
+                    ..
+                },
+            ) => {
+                rewrites.push((
+                    expr,
+                    Rewrite::MethodCall(
+                        "store".into(),
+                        Box::new(Rewrite::Sub(0, lhs)),
+                        vec![Rewrite::Sub(1, rhs), Rewrite::Text(ATOMIC_ORDERING.into())],
+                    ),
+                ));
+            }
+            (
+                StaticKind::Counter,
+                StaticUse::AssignOp {
+                    expr,
+                    lhs,
+                    rhs,
+                    op,
+                    projected: false,
+                    ..
+                },
+            ) => {
+                let method = static_kinds::atomic_op_method(op)
+                    .expect("counter static should only use atomic operators");
+                rewrites.push((
+                    expr,
+                    Rewrite::MethodCall(
+                        method.into(),
+                        Box::new(Rewrite::Sub(0, lhs)),
+                        vec![Rewrite::Sub(1, rhs), Rewrite::Text(ATOMIC_ORDERING.into())],
+                    ),
+                ));
+            }
+
+            // `S` -> `{ let v = *S.lock().unwrap(); v }`
+            // `S.x` -> `{ let v = S.lock().unwrap().x; v }`
+            (
+                StaticKind::Guarded,
+                StaticUse::Read {
+                    expr,
+                    root,
+                    projected,
+                },
+            ) => {
+                if projected {
+                    rewrites.push((expr, read_in_block(Rewrite::Identity)));
+                    rewrites.push((root, lock_static()));
+                } else {
+                    let value = Rewrite::Deref(Box::new(lock_static()));
+                    rewrites.push((expr, read_in_block(value)));
+                }
+            }
+            // `S = e` -> `*S.lock().unwrap() = e`
+            // `S.x = e` -> `S.lock().unwrap().x = e`
+            (
+                StaticKind::Guarded,
+                StaticUse::Assign {
+                    root, projected, ..
+                },
+            )
+            | (
+                StaticKind::Guarded,
+                StaticUse::AssignOp {
+                    root, projected, ..
+                },
+            ) => {
+                if projected {
+                    rewrites.push((root, lock_static()));
+                } else {
+                    rewrites.push((root, Rewrite::Deref(Box::new(lock_static()))));
+                }
+            }
+
+            (kind, u) => {
+                // `find_static_kinds` only assigns each kind to statics whose uses it supports.
+                unreachable!("unsupported use {u:?} of {kind:?} static {did:?}")
+            }
+        }
+    }
+    rewrites
+}
+
+/// Rewrite the declared types of statics that hold pointers, along with the pointer values in
+/// their initializers, as recorded by `crate::static_inits`.
+pub fn gen_static_init_rewrites<'tcx>(
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    gasn: &GlobalAssignment,
+    pointee_types: &GlobalPointerTable<PointeeTypes<'tcx>>,
+) -> Vec<(Span, Rewrite)> {
+    let mut rewrites = Vec::new();
+
+    let mut dids = gacx
+        .static_inits
+        .statics
+        .iter()
+        .copied()
+        .collect::<Vec<_>>();
+    dids.sort();
+    for did in dids {
+        rewrites.extend(ty::gen_static_ty_rewrites(gacx, gasn, pointee_types, did));
+    }
+
+    for init in &gacx.static_inits.inits {
+        // Initializers of existing references are already in the right form.
+        if !matches!(init.ptr_ty.kind(), TyKind::RawPtr(..)) {
+            continue;
+        }
+        let ptr = init.ptr;
+        if gasn.flags[ptr].contains(FlagSet::FIXED) {
+            continue;
+        }
+        let desc = type_desc::perms_to_desc(init.ptr_ty, gasn.perms[ptr], gasn.flags[ptr]);
+        if desc.own != Ownership::Imm {
+            continue;
+        }
+        let rw = match init.value {
+            // `0 as *const T` -> `None`
+            InitValue::Null if desc.option => Rewrite::Text("None".into()),
+            InitValue::Null => continue,
+            // `&X as *const T` -> `&X`, or `&X[0]` if `X` is an array
+            InitValue::AddrOfStatic { place, is_array } => {
+                let mut place_rw = Rewrite::Extract(place);
+                if is_array && desc.qty == Quantity::Single {
+                    place_rw = Rewrite::Index(Box::new(place_rw), Box::new(Rewrite::LitZero));
+                }
+                let rw = Rewrite::Ref(Box::new(place_rw), Mutability::Not);
+                if desc.option {
+                    Rewrite::Call("Some".into(), vec![rw])
+                } else {
+                    rw
+                }
+            }
+        };
+        rewrites.push((init.span, rw));
+    }
+
+    rewrites
+}
@@ -0,0 +1,121 @@
+//! Rewrites for pointers to structs shared between threads, which become `Arc<Mutex<T>>`.  See
+//! `crate::thread_shared` for the analysis that finds them.
+//!
+//! Functions that use these pointers are excluded from the normal rewrites, so this module
+//! handles both the types and the expressions involving the pointers.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::rewrite::Rewrite;
+use crate::thread_shared::{self, SharedPtrUse};
+use rustc_hir::Mutability;
+use rustc_span::Span;
+
+/// `p.lock().unwrap()`
+fn lock(ptr: Span) -> Rewrite {
+    let locked = Rewrite::MethodCall("lock".into(), Box::new(Rewrite::Sub(0, ptr)), vec![]);
+    Rewrite::MethodCall("unwrap".into(), Box::new(locked), vec![])
+}
+
+pub fn gen_thread_shared_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let info = &gacx.thread_shared;
+    let mut rewrites = Vec::new();
+    if info.structs.is_empty() {
+        return rewrites;
+    }
+
+    for &did in &info.fns {
+        let ldid = match did.as_local() {
+            Some(x) => x,
+            None => continue,
+        };
+        let uses =
+            thread_shared::collect_shared_ptr_uses(tcx, ldid, &info.structs, &info.start_routines);
+        for (_, u) in uses {
+            let (span, rw) = match u {
+                // `*mut S` -> `Arc<Mutex<S>>`
+                SharedPtrUse::Ty { ty, pointee } => (
+                    ty,
+                    Rewrite::TyCtor(
+                        "std::sync::Arc".into(),
+                        vec![Rewrite::TyCtor(
+                            "std::sync::Mutex".into(),
+                            vec![Rewrite::Extract(pointee)],
+                        )],
+                    ),
+                ),
+                // `malloc(n) as *mut S` -> `Arc::new(Mutex::new(mem::zeroed()))`.  The struct
+                // usually contains a `pthread_mutex_t`, which can't be built field by field
+                // outside of `libc`, so we zero it all at once instead.
+                SharedPtrUse::Alloc { expr } => {
+                    let zero = Rewrite::Text("std::mem::zeroed()".into());
+                    let mutex = Rewrite::Call("std::sync::Mutex::new".into(), vec![zero]);
+                    (
+                        expr,
+                        Rewrite::Call("std::sync::Arc::new".into(), vec![mutex]),
+                    )
+                }
+                // `free(p as *mut c_void)` -> `drop(p)`
+                SharedPtrUse::Free { call, ptr } => (
+                    call,
+                    Rewrite::Call("std::mem::drop".into(), vec![Rewrite::Sub(0, ptr)]),
+                ),
+                // `*p` -> `*p_guard` or `*p.lock().unwrap()`
+                SharedPtrUse::Deref { expr, ptr, guard } => {
+                    let guard = match guard {
+                        Some(name) => Rewrite::Text(name),
+                        None => lock(ptr),
+                    };
+                    (expr, Rewrite::Deref(Box::new(guard)))
+                }
+                // `p` -> `p.clone()`
+                SharedPtrUse::Copy { expr } => (
+                    expr,
+                    Rewrite::MethodCall("clone".into(), Box::new(Rewrite::Identity), vec![]),
+                ),
+                // `p as *mut c_void` -> `Arc::into_raw(p.clone()) as *mut c_void`
+                SharedPtrUse::Spawn { ptr } => {
+                    let cloned =
+                        Rewrite::MethodCall("clone".into(), Box::new(Rewrite::Identity), vec![]);
+                    (
+                        ptr,
+                        Rewrite::Call("std::sync::Arc::into_raw".into(), vec![cloned]),
+                    )
+                }
+                // `arg as *mut S` -> `Arc::from_raw(arg as *const Mutex<S>)`
+                SharedPtrUse::ThreadArg { expr, arg, pointee } => {
+                    let ty = Rewrite::TyPtr(
+                        Box::new(Rewrite::TyCtor(
+                            "std::sync::Mutex".into(),
+                            vec![Rewrite::Extract(pointee)],
+                        )),
+                        Mutability::Not,
+                    );
+                    let cast = Rewrite::Cast(Box::new(Rewrite::Sub(0, arg)), Box::new(ty));
+                    (
+                        expr,
+                        Rewrite::Call("std::sync::Arc::from_raw".into(), vec![cast]),
+                    )
+                }
+                // `pthread_mutex_lock(&mut (*p).lock)` -> `let mut p_guard = p.lock().unwrap()`
+                SharedPtrUse::Lock { call, ptr, guard } => (
+                    call,
+                    Rewrite::Let1(format!("mut {guard}"), Box::new(lock(ptr))),
+                ),
+                // `pthread_mutex_unlock(&mut (*p).lock)` -> `drop(p_guard)`
+                SharedPtrUse::Unlock { call, guard } => (
+                    call,
+                    Rewrite::Call("std::mem::drop".into(), vec![Rewrite::Text(guard)]),
+                ),
+                // `pthread_mutex_init(&mut (*p).lock, attr)` -> `0`
+                SharedPtrUse::MutexNoop { call } => (call, Rewrite::LitZero),
+                SharedPtrUse::Other => {
+                    // `find_thread_shared_structs` rejects any struct with an `Other` use.
+                    unreachable!("unsupported use of thread-shared pointer")
+                }
+            };
+            rewrites.push((span, rw));
+        }
+    }
+    rewrites
+}
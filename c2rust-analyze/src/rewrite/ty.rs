@@ -7,6 +7,7 @@
 use std::collections::HashMap;
 use std::ops::Index;
 
+use crate::array_lens::{self, ArrayPtr};
 use crate::borrowck::{OriginArg, OriginParam};
 use crate::context::AdtMetadataTable;
 use crate::context::{
@@ -16,6 +17,7 @@ use crate::context::{
 use crate::labeled_ty::{LabeledTy, LabeledTyCtxt};
 use crate::pointee_type::PointeeTypes;
 use crate::pointer_id::{GlobalPointerTable, PointerId, PointerTable};
+use crate::rewrite::user_data::gen_user_data_sig_rewrites;
 use crate::rewrite::Rewrite;
 use crate::type_desc::{self, Ownership, PtrDesc, Quantity, TypeDesc};
 use hir::{
@@ -49,6 +51,9 @@ struct RewriteLabel<'tcx> {
     descendant_has_rewrite: bool,
     /// A lifetime rewrite for a pointer or reference.
     lifetime: &'tcx [OriginArg<'tcx>],
+    /// The pointer only ever points into statics, so a shared reference that has no `lifetime`
+    /// can be given the `'static` lifetime.
+    static_origin: bool,
 }
 
 type RwLTy<'tcx> = LabeledTy<'tcx, RewriteLabel<'tcx>>;
@@ -68,6 +73,13 @@ fn ty_has_adt_lifetime(ty: Ty, adt_metadata: &AdtMetadataTable) -> bool {
     }
 }
 
+fn ty_has_generic_void_fields(ty: Ty, adt_metadata: &AdtMetadataTable) -> bool {
+    match ty.kind() {
+        TyKind::Adt(adt_def, _) => adt_metadata.has_generic_void_fields(adt_def.did()),
+        _ => false,
+    }
+}
+
 fn has_lifetime_rws(rw_lty: &RwLTy, adt_metadata: &AdtMetadataTable) -> bool {
     let has_pointer_lifetime = rw_lty
         .label
@@ -84,6 +96,7 @@ fn descendant_has_rewrite(args: &[RwLTy], adt_metadata: &AdtMetadataTable) -> bo
             || child.label.pointee_ty.is_some()
             || child.label.descendant_has_rewrite
             || has_lifetime_rws(child, adt_metadata)
+            || ty_has_generic_void_fields(child.ty, adt_metadata)
     })
 }
 
@@ -96,6 +109,7 @@ fn create_rewrite_label<'tcx>(
     lifetime: &'tcx [OriginArg<'tcx>],
     adt_metadata: &AdtMetadataTable,
 ) -> RewriteLabel<'tcx> {
+    let mut static_origin = false;
     let ty_desc = if pointer_lty.label.is_none() {
         None
     } else {
@@ -108,6 +122,11 @@ fn create_rewrite_label<'tcx>(
             // can be `None` (no rewriting required).  This might let us avoid inlining a type
             // alias for some pointers where no actual improvement was possible.
             let desc = type_desc::perms_to_desc(pointer_lty.ty, perms, flags);
+            static_origin = flags & FlagSet::ORIGIN == FlagSet::STATIC
+                && matches!(
+                    desc.own,
+                    Ownership::Imm | Ownership::Cell | Ownership::RefCell | Ownership::Atomic
+                );
             Some(desc.into())
         }
     };
@@ -116,7 +135,18 @@ fn create_rewrite_label<'tcx>(
     // For now, we only rewrite in cases where the inferred pointee has no arguments.
     // TODO: expand this to handle pointer-to-pointer cases and other complex inferred pointees
     if !pointer_lty.label.is_none() && !flags[pointer_lty.label].contains(FlagSet::FIXED) {
-        if let Some(lty) = pointee_types[pointer_lty.label].get_sole_lty() {
+        let tys = &pointee_types[pointer_lty.label];
+        // When the pointer is used with several pointee types, we only apply the type chosen by
+        // `PointeeTypes::resolve_conflict` if the declared pointee type isn't one of them, as with
+        // `void*`.  Otherwise the declared type is kept, and casts to the other types are handled
+        // at the use sites.
+        let declared_is_used = tys.ltys.iter().any(|lty| lty.ty == args[0].ty);
+        let lty = if declared_is_used {
+            tys.get_sole_lty()
+        } else {
+            tys.get_resolved_lty()
+        };
+        if let Some(lty) = lty {
             let ty = lty.ty;
             if lty.args.is_empty() && !ty_has_adt_lifetime(ty, adt_metadata) {
                 // Don't rewrite if the old and new types are the same.
@@ -132,6 +162,7 @@ fn create_rewrite_label<'tcx>(
         pointee_ty,
         descendant_has_rewrite: descendant_has_rewrite(args, adt_metadata),
         lifetime,
+        static_origin,
     }
 }
 
@@ -162,6 +193,32 @@ where
     })
 }
 
+/// Change the outermost pointer of `rw_lty` from a slice to a reference to the array described by
+/// `array_ptr`.  See `crate::array_lens`.
+fn relabel_array_ptr<'tcx>(
+    lcx: LabeledTyCtxt<'tcx, RewriteLabel<'tcx>>,
+    rw_lty: RwLTy<'tcx>,
+    array_ptr: &ArrayPtr<'tcx>,
+) -> RwLTy<'tcx> {
+    let desc = match rw_lty.label.ty_desc {
+        Some(desc) if array_lens::desc_allows_array(desc) => desc,
+        _ => return rw_lty,
+    };
+    if rw_lty.label.pointee_ty.is_some() {
+        return rw_lty;
+    }
+    let tcx = *lcx;
+    let label = RewriteLabel {
+        ty_desc: Some(PtrDesc {
+            qty: Quantity::Single,
+            ..desc
+        }),
+        pointee_ty: Some(tcx.mk_array(array_ptr.elem_ty, array_ptr.len)),
+        ..rw_lty.label
+    };
+    lcx.mk(rw_lty.ty, rw_lty.args, label)
+}
+
 // Gets the generic type arguments of an HIR type.
 fn hir_generic_ty_args<'tcx>(ty: &hir::Ty<'tcx>) -> Option<Vec<&'tcx hir::Ty<'tcx>>> {
     let args = match ty.kind {
@@ -334,6 +391,10 @@ impl Convert<hir::Mutability> for mir::Mutability {
 }
 
 fn mk_adt_with_arg<'tcx>(tcx: TyCtxt<'tcx>, path: &str, arg_ty: ty::Ty<'tcx>) -> ty::Ty<'tcx> {
+    mk_adt_with_args(tcx, path, &[arg_ty])
+}
+
+fn mk_adt_with_args<'tcx>(tcx: TyCtxt<'tcx>, path: &str, arg_tys: &[ty::Ty<'tcx>]) -> ty::Ty<'tcx> {
     let mut path_parts_iter = path.split("::");
     let crate_name = path_parts_iter
         .next()
@@ -364,7 +425,7 @@ fn mk_adt_with_arg<'tcx>(tcx: TyCtxt<'tcx>, path: &str, arg_ty: ty::Ty<'tcx>) ->
     }
 
     let adt = tcx.adt_def(cur_did);
-    let substs = tcx.mk_substs([GenericArg::from(arg_ty)].into_iter());
+    let substs = tcx.mk_substs(arg_tys.iter().map(|&ty| GenericArg::from(ty)));
     tcx.mk_adt(adt, substs)
 }
 
@@ -372,6 +433,21 @@ fn mk_cell<'tcx>(tcx: TyCtxt<'tcx>, ty: ty::Ty<'tcx>) -> ty::Ty<'tcx> {
     mk_adt_with_arg(tcx, "core::cell::Cell", ty)
 }
 
+fn mk_ref_cell<'tcx>(tcx: TyCtxt<'tcx>, ty: ty::Ty<'tcx>) -> ty::Ty<'tcx> {
+    mk_adt_with_arg(tcx, "core::cell::RefCell", ty)
+}
+
+/// Build the atomic type that replaces `ty` behind an `Ownership::Atomic` pointer.  See
+/// `type_desc::atomic_ty_path`.
+fn mk_atomic<'tcx>(tcx: TyCtxt<'tcx>, ty: ty::Ty<'tcx>) -> ty::Ty<'tcx> {
+    let path = type_desc::atomic_ty_path(ty)
+        .unwrap_or_else(|| panic!("no atomic type corresponds to {ty:?}"));
+    match *ty.kind() {
+        TyKind::RawPtr(tm) => mk_adt_with_arg(tcx, &path, tm.ty),
+        _ => mk_adt_with_args(tcx, &path, &[]),
+    }
+}
+
 fn mk_option<'tcx>(tcx: TyCtxt<'tcx>, ty: ty::Ty<'tcx>) -> ty::Ty<'tcx> {
     mk_adt_with_arg(tcx, "core::option::Option", ty)
 }
@@ -426,6 +502,10 @@ pub fn desc_parts_to_ty<'tcx>(
 
     if own == Ownership::Cell {
         ty = mk_cell(tcx, ty);
+    } else if own == Ownership::RefCell {
+        ty = mk_ref_cell(tcx, ty);
+    } else if own == Ownership::Atomic {
+        ty = mk_atomic(tcx, ty);
     }
 
     ty = match qty {
@@ -433,6 +513,8 @@ pub fn desc_parts_to_ty<'tcx>(
         Quantity::Slice => tcx.mk_slice(ty),
         // TODO: This should generate `OffsetPtr<T>` rather than `&[T]`, but `OffsetPtr` is NYI
         Quantity::OffsetPtr => tcx.mk_slice(ty),
+        // `CStr` replaces `[c_char]` as a whole.
+        Quantity::CStr => mk_adt_with_args(tcx, "core::ffi::CStr", &[]),
         Quantity::Array => panic!("can't mk_rewritten_ty with Quantity::Array"),
     };
 
@@ -440,7 +522,9 @@ pub fn desc_parts_to_ty<'tcx>(
         Ownership::Raw => tcx.mk_imm_ptr(ty),
         Ownership::RawMut => tcx.mk_mut_ptr(ty),
         Ownership::Imm => tcx.mk_imm_ref(tcx.mk_region(ReErased), ty),
-        Ownership::Cell => tcx.mk_imm_ref(tcx.mk_region(ReErased), ty),
+        Ownership::Cell | Ownership::RefCell | Ownership::Atomic => {
+            tcx.mk_imm_ref(tcx.mk_region(ReErased), ty)
+        }
         Ownership::Mut => tcx.mk_mut_ref(tcx.mk_region(ReErased), ty),
         Ownership::Rc => todo!(),
         Ownership::Box => tcx.mk_box(ty),
@@ -465,12 +549,17 @@ struct HirTyVisitor<'a, 'tcx> {
     mir: &'a Body<'tcx>,
     hir_rewrites: Vec<(Span, Rewrite)>,
     hir_span_to_mir_local: HashMap<Span, rustc_middle::mir::Local>,
+    /// Locals that are rewritten to references to fixed-size arrays.  See `crate::array_lens`.
+    array_ptrs: HashMap<rustc_middle::mir::Local, ArrayPtr<'tcx>>,
+    /// Type argument to use for ADTs with generic `void*` fields.  See [`rewrite_ty`].
+    generic_arg: Option<&'static str>,
 }
 
 fn adt_ty_rw<S>(
     adt_def: &AdtDef,
     lifetime_params: &[OriginArg],
     substs: &List<GenericArg>,
+    generic_arg: Option<&str>,
 ) -> Rewrite<S> {
     let lifetime_names = lifetime_params
         .iter()
@@ -480,19 +569,31 @@ fn adt_ty_rw<S>(
         _ => Some(Rewrite::Print(format!("{p:?}"))),
     });
 
+    let generic_arg = generic_arg.map(|arg| Rewrite::Print(arg.to_owned()));
+
     Rewrite::TyCtor(
         format!("{adt_def:?}"),
-        lifetime_names.chain(other_param_names).collect(),
+        lifetime_names
+            .chain(other_param_names)
+            .chain(generic_arg)
+            .collect(),
     )
 }
 
 /// Generate rewrites on `hir_ty` according to its labeled representation `rw_lty`.
+///
+/// ADTs with generic `void*` fields (see [`AdtMetadata::generic_void_fields`]) are given
+/// `generic_arg` as their type argument.  If `generic_arg` is `None`, such ADTs are left as they
+/// are, which selects the parameter's default of `c_void`.
+///
+/// [`AdtMetadata::generic_void_fields`]: crate::borrowck::AdtMetadata::generic_void_fields
 fn rewrite_ty<'tcx>(
     rw_lcx: LabeledTyCtxt<'tcx, RewriteLabel<'tcx>>,
     hir_rewrites: &mut Vec<(Span, Rewrite)>,
     rw_lty: RwLTy<'tcx>,
     hir_ty: &hir::Ty<'tcx>,
     adt_metadata: &AdtMetadataTable,
+    generic_arg: Option<&str>,
 ) {
     if !rw_lty.ty.is_adt()
         && rw_lty.label.ty_desc.is_none()
@@ -525,6 +626,7 @@ fn rewrite_ty<'tcx>(
         ));
         let lifetime_type = match rw_lty.label.lifetime {
             [lifetime] => LifetimeName::Explicit(format!("{lifetime:?}")),
+            [] if rw_lty.label.static_origin => LifetimeName::Explicit("'static".into()),
             [] => LifetimeName::Elided,
             _ => panic!("Pointer or reference type cannot have multiple lifetime parameters"),
         };
@@ -543,6 +645,21 @@ fn rewrite_ty<'tcx>(
 
             if own == Ownership::Cell {
                 rw = Rewrite::TyCtor("core::cell::Cell".into(), vec![rw]);
+            } else if own == Ownership::RefCell {
+                rw = Rewrite::TyCtor("core::cell::RefCell".into(), vec![rw]);
+            } else if own == Ownership::Atomic {
+                let pointee_ty = rw_lty.label.pointee_ty.unwrap_or(rw_lty.args[0].ty);
+                let path = type_desc::atomic_ty_path(pointee_ty)
+                    .unwrap_or_else(|| panic!("no atomic type corresponds to {pointee_ty:?}"));
+                // The pointee of an `AtomicPtr` is `FIXED`, so its type can be printed as is.
+                rw = match *pointee_ty.kind() {
+                    TyKind::RawPtr(tm) => {
+                        let printer = FmtPrinter::new(*rw_lcx, Namespace::TypeNS);
+                        let s = tm.ty.print(printer).unwrap().into_buffer();
+                        Rewrite::TyCtor(path, vec![Rewrite::Print(s)])
+                    }
+                    _ => Rewrite::Print(path),
+                };
             }
 
             rw = match qty {
@@ -551,6 +668,7 @@ fn rewrite_ty<'tcx>(
                 // TODO: This should generate `OffsetPtr<T>` rather than `&[T]`, but `OffsetPtr` is
                 // NYI
                 Quantity::OffsetPtr => Rewrite::TySlice(Box::new(rw)),
+                Quantity::CStr => Rewrite::Print("std::ffi::CStr".into()),
                 Quantity::Array => panic!("can't rewrite to Quantity::Array"),
             };
 
@@ -558,10 +676,12 @@ fn rewrite_ty<'tcx>(
                 Ownership::Raw => Rewrite::TyPtr(Box::new(rw), Mutability::Not),
                 Ownership::RawMut => Rewrite::TyPtr(Box::new(rw), Mutability::Mut),
                 Ownership::Imm => Rewrite::TyRef(lifetime_type, Box::new(rw), Mutability::Not),
-                Ownership::Cell => Rewrite::TyRef(lifetime_type, Box::new(rw), Mutability::Not),
+                Ownership::Cell | Ownership::RefCell | Ownership::Atomic => {
+                    Rewrite::TyRef(lifetime_type, Box::new(rw), Mutability::Not)
+                }
                 Ownership::Mut => Rewrite::TyRef(lifetime_type, Box::new(rw), Mutability::Mut),
                 Ownership::Rc => todo!(),
-                Ownership::Box => todo!(),
+                Ownership::Box => Rewrite::TyCtor("std::boxed::Box".into(), vec![rw]),
             };
 
             if option {
@@ -581,10 +701,12 @@ fn rewrite_ty<'tcx>(
     }
 
     if let TyKind::Adt(adt_def, substs) = rw_lty.ty.kind() {
-        if !rw_lty.label.lifetime.is_empty() {
+        let generic_arg =
+            generic_arg.filter(|_| adt_metadata.has_generic_void_fields(adt_def.did()));
+        if !rw_lty.label.lifetime.is_empty() || generic_arg.is_some() {
             hir_rewrites.push((
                 hir_ty.span,
-                adt_ty_rw(adt_def, rw_lty.label.lifetime, substs),
+                adt_ty_rw(adt_def, rw_lty.label.lifetime, substs, generic_arg),
             ))
         };
     }
@@ -596,7 +718,14 @@ fn rewrite_ty<'tcx>(
             "descendant_has_rewrite is true for the remainder of rw_lty.args"
         );
         for (&arg_rw_lty, arg_hir_ty) in rw_lty_args.iter().zip(hir_args.into_iter()) {
-            rewrite_ty(rw_lcx, hir_rewrites, arg_rw_lty, arg_hir_ty, adt_metadata);
+            rewrite_ty(
+                rw_lcx,
+                hir_rewrites,
+                arg_rw_lty,
+                arg_hir_ty,
+                adt_metadata,
+                generic_arg,
+            );
         }
     }
 }
@@ -609,6 +738,7 @@ impl<'a, 'tcx> HirTyVisitor<'a, 'tcx> {
             rw_lty,
             hir_ty,
             &self.acx.gacx.adt_metadata,
+            self.generic_arg,
         );
     }
 }
@@ -628,7 +758,7 @@ impl<'tcx, 'a> intravisit::Visitor<'tcx> for HirTyVisitor<'a, 'tcx> {
                     let mir_local_decl = &self.mir.local_decls[*mir_local];
                     assert_eq!(mir_local_decl.source_info.span, hir_local.pat.span);
                     let lty = self.acx.local_tys[*mir_local];
-                    let rw_lty = relabel_rewrites(
+                    let mut rw_lty = relabel_rewrites(
                         &self.asn.perms(),
                         &self.asn.flags(),
                         &self.pointee_types,
@@ -636,6 +766,9 @@ impl<'tcx, 'a> intravisit::Visitor<'tcx> for HirTyVisitor<'a, 'tcx> {
                         lty,
                         self.acx.gacx,
                     );
+                    if let Some(array_ptr) = self.array_ptrs.get(mir_local) {
+                        rw_lty = relabel_array_ptr(self.rw_lcx, rw_lty, array_ptr);
+                    }
                     let hir_ty = hir_local.ty.unwrap();
                     self.handle_ty(rw_lty, hir_ty);
                 }
@@ -657,6 +790,13 @@ pub fn gen_ty_rewrites<'tcx>(
         span_to_mir_local.insert(local_decl.source_info.span, local);
     }
 
+    // If the signature mentions an ADT with generic `void*` fields, the function gets a type
+    // parameter `T`, which is passed through to those ADTs.
+    let needs_type_param = acx.gacx.fn_needs_type_param(ldid.to_def_id());
+
+    let array_ptrs =
+        array_lens::find_array_ptrs(acx, &asn.perms(), &asn.flags(), &pointee_types, mir);
+
     let rw_lcx = LabeledTyCtxt::new(acx.tcx());
     let mut v = HirTyVisitor {
         asn,
@@ -666,8 +806,19 @@ pub fn gen_ty_rewrites<'tcx>(
         mir,
         hir_rewrites: Vec::new(),
         hir_span_to_mir_local: span_to_mir_local,
+        array_ptrs,
+        generic_arg: needs_type_param.then_some("T"),
     };
 
+    // A closure's signature is always `FIXED`, since it's called through the `Fn*` traits, and
+    // its parameter types are usually inferred, so only its body needs rewriting.
+    if acx.tcx().is_closure(ldid.to_def_id()) {
+        let hir_body_id = acx.tcx().hir().body_owned_by(ldid);
+        let body = acx.tcx().hir().body(hir_body_id);
+        intravisit::Visitor::visit_body(&mut v, body);
+        return v.hir_rewrites;
+    }
+
     // Update function signature
     let hir_id = acx.tcx().hir().local_def_id_to_hir_id(ldid);
     let hir_sig = acx
@@ -684,7 +835,21 @@ pub fn gen_ty_rewrites<'tcx>(
     let hir_generics = acx.tcx().hir().get_generics(ldid);
 
     let generics = hir_generics.unwrap_or(Generics::empty());
-    gen_generics_rws(&mut v.hir_rewrites, generics, origin_params.iter());
+    let mut type_params: Vec<&str> = Vec::new();
+    if needs_type_param {
+        type_params.push("T");
+    }
+    type_params.extend(gen_user_data_sig_rewrites(
+        acx.gacx,
+        ldid,
+        &mut v.hir_rewrites,
+    ));
+    gen_generics_rws(
+        &mut v.hir_rewrites,
+        generics,
+        origin_params.iter(),
+        &type_params,
+    );
 
     let lty_sig = acx.gacx.fn_sigs.get(&ldid.to_def_id()).unwrap();
     assert_eq!(lty_sig.inputs.len(), hir_sig.decl.inputs.len());
@@ -739,10 +904,13 @@ pub fn gen_ty_rewrites<'tcx>(
     v.hir_rewrites
 }
 
+/// Insert the hypothetical lifetimes from `origin_params` into `generics`, followed by the new type
+/// parameters `type_params`, which are written out as given (so they can include defaults).
 pub fn gen_generics_rws<'p, 'tcx>(
     hir_rewrites: &mut Vec<(Span, Rewrite)>,
     generics: &Generics<'tcx>,
     origin_params: impl Iterator<Item = &'p OriginParam>,
+    type_params: &[&str],
 ) {
     let mut last_lifetime_span: Option<Span> = None;
     let mut first_generic_type_span: Option<Span> = None;
@@ -798,6 +966,7 @@ pub fn gen_generics_rws<'p, 'tcx>(
                 None
             }
         })
+        .chain(type_params.iter().map(|&p| p.to_owned()))
         .collect();
     let hypothetical_origin_string = hypothetical_origin_params.join(",");
 
@@ -817,6 +986,130 @@ pub fn gen_generics_rws<'p, 'tcx>(
     }
 }
 
+/// Add the lifetime arguments `lifetimes` to the last segment of `path`, after any existing
+/// lifetime arguments, as in `Foo` -> `Foo<'a>` or `Foo<T>` -> `Foo<'a,T>`.
+fn gen_path_lifetime_args_rw(path: &Path, lifetimes: &str) -> Option<(Span, Rewrite)> {
+    let seg = path.segments.last()?;
+    let args = match seg.args {
+        Some(args) if !args.args.is_empty() => args.args,
+        _ => {
+            let span = seg.ident.span.shrink_to_hi();
+            return Some((span, Rewrite::Print(format!("<{lifetimes}>"))));
+        }
+    };
+    let last_lifetime = args
+        .iter()
+        .filter(|arg| matches!(arg, hir::GenericArg::Lifetime(..)))
+        .last();
+    match last_lifetime {
+        Some(arg) => Some((
+            arg.span().shrink_to_hi(),
+            Rewrite::Print(format!(",{lifetimes}")),
+        )),
+        None => Some((
+            args[0].span().shrink_to_lo(),
+            Rewrite::Print(format!("{lifetimes},")),
+        )),
+    }
+}
+
+/// If `hir_ty` is a path naming the ADT `did`, return the path.
+fn adt_path<'hir>(hir_ty: &hir::Ty<'hir>, did: DefId) -> Option<&'hir Path<'hir>> {
+    match hir_ty.kind {
+        hir::TyKind::Path(hir::QPath::Resolved(None, path))
+            if path.res.opt_def_id() == Some(did) =>
+        {
+            Some(path)
+        }
+        _ => None,
+    }
+}
+
+/// Generate rewrites for impls and type aliases that name the ADT `did` directly, so they pass
+/// along the hypothetical lifetime parameters `origin_params` that are added to its definition:
+///
+/// * `impl Foo { .. }` -> `impl Foo<'_> { .. }`, and likewise for trait impls.  Anonymous
+///   lifetimes can't collide with the ones added to the signatures of the impl's methods.
+/// * `type Bar = Foo;` -> `type Bar<'h0> = Foo<'h0>;`.  Rewritten uses of `Bar` are replaced with
+///   the rewritten type `Foo<'h0>`, and elsewhere the lifetimes of `Bar` can be elided.
+///
+/// Derived impls are skipped, since `derive` already handles the struct's generics.
+fn gen_adt_mention_rewrites<'p>(
+    tcx: TyCtxt,
+    did: DefId,
+    origin_params: impl Iterator<Item = &'p OriginParam> + Clone,
+) -> Vec<(Span, Rewrite)> {
+    let mut hir_rewrites = Vec::new();
+    let hypothetical = origin_params
+        .clone()
+        .filter(|p| matches!(p, OriginParam::Hypothetical(_)))
+        .collect::<Vec<_>>();
+    if hypothetical.is_empty() {
+        return hir_rewrites;
+    }
+    let anon_lifetimes = vec!["'_"; hypothetical.len()].join(",");
+    let named_lifetimes = hypothetical
+        .iter()
+        .map(|p| format!("{p:?}"))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    for id in tcx.hir().items() {
+        let item = tcx.hir().item(id);
+        if item.span.from_expansion() {
+            continue;
+        }
+        match item.kind {
+            ItemKind::Impl(imp) => {
+                if let Some(path) = adt_path(imp.self_ty, did) {
+                    hir_rewrites.extend(gen_path_lifetime_args_rw(path, &anon_lifetimes));
+                }
+            }
+            ItemKind::TyAlias(hir_ty, ref generics) => {
+                if let Some(path) = adt_path(hir_ty, did) {
+                    gen_generics_rws(&mut hir_rewrites, generics, origin_params.clone(), &[]);
+                    hir_rewrites.extend(gen_path_lifetime_args_rw(path, &named_lifetimes));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    hir_rewrites
+}
+
+/// Generate a rewrite that removes `Copy` from the `derive` attribute of the struct `did`, as in
+/// `#[derive(Copy, Clone)]` -> `#[derive(Clone)]`.  This is needed when a field of the struct is
+/// rewritten to a type that isn't `Copy`, such as `Vec`.
+pub fn gen_remove_copy_derive_rewrite(tcx: TyCtxt, did: DefId) -> Option<(Span, Rewrite)> {
+    let copy_did = tcx.lang_items().copy_trait()?;
+    let source_map = tcx.sess.source_map();
+    for impl_did in tcx.all_impls(copy_did) {
+        let self_did = tcx
+            .type_of(impl_did)
+            .ty_adt_def()
+            .map(|adt_def| adt_def.did());
+        if self_did != Some(did) {
+            continue;
+        }
+        // The span of a derived impl points back to the trait name in the `derive` attribute.
+        let impl_span = tcx.def_span(impl_did);
+        if !impl_span.from_expansion() {
+            return None;
+        }
+        let span = impl_span.source_callsite();
+        match source_map.span_to_snippet(span) {
+            Ok(s) if s.ends_with("Copy") => {}
+            _ => return None,
+        }
+        let span = source_map
+            .span_extend_while(span, |c| c == ',' || c == ' ')
+            .ok()?;
+        return Some((span, Rewrite::Text(String::new())));
+    }
+    None
+}
+
 pub fn gen_adt_ty_rewrites<'tcx>(
     gacx: &GlobalAnalysisCtxt<'tcx>,
     gasn: &GlobalAssignment,
@@ -825,6 +1118,10 @@ pub fn gen_adt_ty_rewrites<'tcx>(
 ) -> Vec<(Span, Rewrite)> {
     let tcx = gacx.tcx;
     let mut hir_rewrites = Vec::new();
+    if gacx.unions.unions.contains(&did) {
+        // The whole definition is replaced by `rewrite::gen_union_rewrites`.
+        return hir_rewrites;
+    }
     let item = if let Some(Node::Item(item)) = tcx.hir().get_if_local(did) {
         item
     } else {
@@ -845,14 +1142,64 @@ pub fn gen_adt_ty_rewrites<'tcx>(
 
     let adt_metadata = &gacx.adt_metadata.table[&did];
 
+    // A struct with generic `void*` fields gets a new type parameter `T`.  The default keeps
+    // existing uses of the bare struct name valid.
+    let is_generic = !adt_metadata.generic_void_fields.is_empty();
+    let type_params: &[&str] = if is_generic {
+        &["T = core::ffi::c_void"]
+    } else {
+        &[]
+    };
     gen_generics_rws(
         &mut hir_rewrites,
         generics,
         gacx.adt_metadata.table[&did].lifetime_params.iter(),
+        type_params,
     );
+    hir_rewrites.extend(gen_adt_mention_rewrites(
+        tcx,
+        did,
+        adt_metadata.lifetime_params.iter(),
+    ));
+
+    let flex_tail = gacx.flex_arrays.structs.get(&did).copied();
+    if flex_tail.is_some() {
+        hir_rewrites.extend(gen_remove_copy_derive_rewrite(tcx, did));
+    }
 
     for field_def in field_defs.iter() {
         let fdid = tcx.hir().local_def_id(field_def.hir_id).to_def_id();
+        if flex_tail == Some(fdid) {
+            // `[T; 0]` -> `Vec<T>`.  Allocations size the `Vec` to the number of elements that
+            // the original `malloc` made room for; see `rewrite::gen_flex_array_rewrites`.
+            if let hir::TyKind::Array(elem_ty, _) = field_def.ty.kind {
+                hir_rewrites.push((
+                    field_def.ty.span,
+                    Rewrite::TyCtor("Vec".into(), vec![Rewrite::Extract(elem_ty.span)]),
+                ));
+                continue;
+            }
+        }
+        if adt_metadata.generic_void_fields.contains(&fdid) {
+            // `*mut c_void` -> `*mut T`
+            if let hir::TyKind::Ptr(ref mt) = field_def.ty.kind {
+                hir_rewrites.push((
+                    field_def.ty.span,
+                    Rewrite::TyPtr(Box::new(Rewrite::Print("T".into())), mt.mutbl),
+                ));
+                continue;
+            }
+        }
+        if let Some(&fn_ptr_ty) = gacx.fn_ptr_fields.get(&fdid) {
+            // `*mut c_void` -> `Option<unsafe extern "C" fn(..)>`
+            let printer = FmtPrinter::new(tcx, Namespace::TypeNS);
+            let fn_ptr_ty = fn_ptr_ty.print(printer).unwrap().into_buffer();
+            hir_rewrites.push((
+                field_def.ty.span,
+                Rewrite::TyCtor("Option".into(), vec![Rewrite::Print(fn_ptr_ty)]),
+            ));
+            continue;
+        }
         let field_metadata = &adt_metadata.field_info[&fdid];
         let f_lty = field_ltys[&fdid];
         let lcx = LabeledTyCtxt::<RewriteLabel>::new(tcx);
@@ -878,12 +1225,86 @@ pub fn gen_adt_ty_rewrites<'tcx>(
             rw_lty,
             field_def.ty,
             &gacx.adt_metadata,
+            is_generic.then_some("T"),
         );
     }
 
     hir_rewrites
 }
 
+/// Generate rewrites for the declared type of the static or `const` item `did`.  These items have
+/// no lifetime parameters, so references in the rewritten type use the default `'static` lifetime.
+pub fn gen_static_ty_rewrites<'tcx>(
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    gasn: &GlobalAssignment,
+    pointee_types: &GlobalPointerTable<PointeeTypes<'tcx>>,
+    did: DefId,
+) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let hir_ty = match tcx.hir().get_if_local(did) {
+        Some(Node::Item(item)) => match item.kind {
+            ItemKind::Static(ty, _, _) | ItemKind::Const(ty, _) => ty,
+            _ => panic!("expected item {:?} to be a `static` or `const`", item),
+        },
+        _ => panic!("def id {:?} not found", did),
+    };
+
+    let lcx = LabeledTyCtxt::<RewriteLabel>::new(tcx);
+    let rw_lty = relabel_rewrites(
+        &gasn.perms,
+        &gasn.flags,
+        pointee_types,
+        lcx,
+        gacx.item_lty(did),
+        gacx,
+    );
+    let mut hir_rewrites = Vec::new();
+    rewrite_ty(
+        lcx,
+        &mut hir_rewrites,
+        rw_lty,
+        hir_ty,
+        &gacx.adt_metadata,
+        None,
+    );
+    hir_rewrites
+}
+
+/// Compute the rewritten type for each pointer in `lty`.  The type given for each `PointerId` is
+/// the rewritten form of the part of `lty` whose outermost pointer has that ID.
+pub fn rewritten_ptr_tys<'tcx, P, F, PT>(
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    perms: &P,
+    flags: &F,
+    pointee_types: &PT,
+    lty: LTy<'tcx>,
+) -> Vec<(PointerId, Ty<'tcx>)>
+where
+    P: Index<PointerId, Output = PermissionSet>,
+    F: Index<PointerId, Output = FlagSet>,
+    PT: Index<PointerId, Output = PointeeTypes<'tcx>>,
+{
+    fn walk<'tcx>(
+        lcx: LabeledTyCtxt<'tcx, RewriteLabel<'tcx>>,
+        lty: LTy<'tcx>,
+        rw_lty: RwLTy<'tcx>,
+        out: &mut Vec<(PointerId, Ty<'tcx>)>,
+    ) {
+        if !lty.label.is_none() {
+            out.push((lty.label, mk_rewritten_ty(lcx, rw_lty)));
+        }
+        for (&arg, &rw_arg) in lty.args.iter().zip(rw_lty.args) {
+            walk(lcx, arg, rw_arg, out);
+        }
+    }
+
+    let lcx = LabeledTyCtxt::new(gacx.tcx);
+    let rw_lty = relabel_rewrites(perms, flags, pointee_types, lcx, lty, gacx);
+    let mut out = Vec::new();
+    walk(lcx, lty, rw_lty, &mut out);
+    out
+}
+
 /// Print the rewritten types for all locals in `mir`.  This is used for tests and debugging, as it
 /// reveals the inference results even for temporaries and other locals with no type annotation in
 /// the HIR.
@@ -895,9 +1316,11 @@ pub fn dump_rewritten_local_tys<'tcx>(
     mut describe_local: impl FnMut(TyCtxt<'tcx>, &LocalDecl) -> String,
 ) {
     let rw_lcx = LabeledTyCtxt::new(acx.tcx());
+    let array_ptrs =
+        array_lens::find_array_ptrs(acx, &asn.perms(), &asn.flags(), &pointee_types, mir);
     for (local, decl) in mir.local_decls.iter_enumerated() {
         // TODO: apply `Cell` if `addr_of_local` indicates it's needed
-        let rw_lty = relabel_rewrites(
+        let mut rw_lty = relabel_rewrites(
             &asn.perms(),
             &asn.flags(),
             &pointee_types,
@@ -905,6 +1328,9 @@ pub fn dump_rewritten_local_tys<'tcx>(
             acx.local_tys[local],
             acx.gacx,
         );
+        if let Some(array_ptr) = array_ptrs.get(&local) {
+            rw_lty = relabel_array_ptr(rw_lcx, rw_lty, array_ptr);
+        }
         let ty = mk_rewritten_ty(rw_lcx, rw_lty);
         eprintln!(
             "{:?} ({}): {:?}",
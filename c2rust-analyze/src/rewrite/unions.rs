@@ -0,0 +1,161 @@
+//! Rewrites for unions whose active field can be tracked, which become enums with one variant per
+//! field.  See `crate::unions` for the analysis that finds them.
+//!
+//! Functions that access the fields are excluded from the normal rewrites, so this module handles
+//! both the union definition and every use of the fields.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::rewrite::Rewrite;
+use crate::unions::{self, UnionUse};
+use rustc_hir as hir;
+use rustc_hir::def_id::DefId;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use std::fmt::Write as _;
+
+/// `(e)`.  A dereference followed by a field access or method call needs explicit parentheses.
+fn paren(rw: Rewrite) -> Rewrite {
+    Rewrite::Call(String::new(), vec![rw])
+}
+
+/// Rewrite the definition of `did` to an enum, with accessors that return the active field:
+///
+/// ```ignore
+/// pub enum value_data {
+///     i(libc::c_int),
+///     f(libc::c_float),
+/// }
+///
+/// impl value_data {
+///     pub fn as_i(&self) -> &libc::c_int {
+///         match self {
+///             value_data::i(x) => x,
+///             _ => panic!("union field `i` is not active"),
+///         }
+///     }
+///     // ...
+/// }
+/// ```
+fn gen_union_def_rewrite(tcx: TyCtxt, did: DefId) -> (Span, Rewrite) {
+    let item = tcx.hir().expect_item(did.expect_local());
+    let variant = match item.kind {
+        hir::ItemKind::Union(ref variant, _) => variant,
+        _ => unreachable!("union {did:?} should be a union"),
+    };
+    let source_map = tcx.sess.source_map();
+    let snippet = |span| {
+        source_map
+            .span_to_snippet(span)
+            .unwrap_or_else(|e| panic!("failed to get source for {span:?}: {e:?}"))
+    };
+    let vis = snippet(item.vis_span);
+    let vis = if vis.is_empty() { vis } else { vis + " " };
+    let name = item.ident;
+    let fields = variant
+        .fields()
+        .iter()
+        .map(|f| (f.ident, snippet(f.ty.span)))
+        .collect::<Vec<_>>();
+
+    let mut s = format!("{vis}enum {name} {{\n");
+    for (field, ty) in &fields {
+        writeln!(s, "    {field}({ty}),").unwrap();
+    }
+    writeln!(s, "}}\n\nimpl {name} {{").unwrap();
+    for (i, (field, ty)) in fields.iter().enumerate() {
+        for (j, (suffix, self_ref)) in [("", "&"), ("_mut", "&mut ")].into_iter().enumerate() {
+            if i > 0 || j > 0 {
+                s.push('\n');
+            }
+            writeln!(
+                s,
+                "    {vis}fn as_{field}{suffix}({self_ref}self) -> {self_ref}{ty} {{"
+            )
+            .unwrap();
+            writeln!(s, "        match self {{").unwrap();
+            writeln!(s, "            {name}::{field}(x) => x,").unwrap();
+            if fields.len() > 1 {
+                writeln!(
+                    s,
+                    "            _ => panic!(\"union field `{field}` is not active\"),"
+                )
+                .unwrap();
+            }
+            writeln!(s, "        }}").unwrap();
+            writeln!(s, "    }}").unwrap();
+        }
+    }
+    s.push('}');
+    (item.span, Rewrite::Text(s))
+}
+
+pub fn gen_union_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let info = &gacx.unions;
+    let mut rewrites = Vec::new();
+    if info.unions.is_empty() {
+        return rewrites;
+    }
+
+    for &did in &info.unions {
+        rewrites.push(gen_union_def_rewrite(tcx, did));
+    }
+
+    for &did in &info.fns {
+        let ldid = match did.as_local() {
+            Some(x) => x,
+            None => continue,
+        };
+        for (adt, u) in unions::collect_union_uses(tcx, ldid, info) {
+            let path = tcx.def_path_str(adt);
+            match u {
+                // `(*v).data.i` -> `(*(*v).data.as_i())`
+                UnionUse::Read {
+                    expr,
+                    union_expr,
+                    field,
+                    mutbl,
+                } => {
+                    let suffix = if mutbl { "_mut" } else { "" };
+                    let access = Rewrite::MethodCall(
+                        format!("as_{field}{suffix}"),
+                        Box::new(Rewrite::Sub(0, union_expr)),
+                        vec![],
+                    );
+                    rewrites.push((expr, paren(Rewrite::Deref(Box::new(access)))));
+                }
+                // `(*v).data.i = x` -> `(*v).data = value_data::i(x)`
+                UnionUse::Write {
+                    expr,
+                    union_expr,
+                    field,
+                    value,
+                } => {
+                    rewrites.push((
+                        expr,
+                        Rewrite::Binary(
+                            "=".into(),
+                            Box::new(Rewrite::Sub(0, union_expr)),
+                            Box::new(Rewrite::Call(
+                                format!("{path}::{field}"),
+                                vec![Rewrite::Sub(1, value)],
+                            )),
+                        ),
+                    ));
+                }
+                // `value_data { i: x }` -> `value_data::i(x)`
+                UnionUse::Literal { expr, field, value } => {
+                    rewrites.push((
+                        expr,
+                        Rewrite::Call(format!("{path}::{field}"), vec![Rewrite::Sub(0, value)]),
+                    ));
+                }
+                UnionUse::Other => {
+                    // `find_union_enums` rejects any union with an `Other` use.
+                    unreachable!("unsupported use of union")
+                }
+            }
+        }
+    }
+    rewrites
+}
@@ -0,0 +1,406 @@
+//! Removal of `unsafe` that is no longer needed after rewriting.
+//!
+//! For each rewritten function, we find the operations that will still require `unsafe` once the
+//! rewrites are applied: derefs of pointers that stay raw, calls to unsafe functions, accesses to
+//! `static mut`s and union fields, inline assembly, and unsafe calls introduced by the rewrites
+//! themselves, like `slice::from_raw_parts`.  Calls to local unsafe functions are
+//! tracked separately, since they become safe if the callee itself is made safe.  Then, when
+//! `C2RUST_ANALYZE_MINIMIZE_UNSAFE=1` is set:
+//!
+//! * An `unsafe` block that contains no remaining unsafe operations becomes a plain block.
+//! * An `unsafe fn` whose body contains no remaining unsafe operations loses its `unsafe`
+//!   qualifier.  Exported `extern "C"` functions are handled by `safe_wrappers` instead, and
+//!   trait methods are left alone, since their signatures have to match the trait.
+//!
+//! Unsafe blocks are only removed, never split, so a block with one remaining unsafe operation
+//! keeps covering its other statements.
+
+use crate::context::{self, AnalysisCtxt, Assignment, FlagSet, GlobalAnalysisCtxt, LTy};
+use crate::rewrite::safe_wrappers;
+use crate::rewrite::Rewrite;
+use crate::type_desc::{self, Ownership};
+use crate::util::{self, Callee};
+use rustc_hir::def::DefKind;
+use rustc_hir::def_id::DefId;
+use rustc_hir::{HirId, Unsafety};
+use rustc_middle::mir::visit::{PlaceContext, Visitor};
+use rustc_middle::mir::{
+    AggregateKind, Body, Constant, Location, Place, ProjectionElem, Rvalue, Safety, SourceInfo,
+    Terminator, TerminatorKind,
+};
+use rustc_middle::ty::{DefIdTree, TyCtxt, TyKind};
+use rustc_span::{BytePos, Span};
+use std::collections::{HashMap, HashSet};
+
+/// The operations in a function body that still require `unsafe` after rewriting.  Each is
+/// recorded along with the innermost `unsafe` block containing it, if any.
+#[derive(Clone, Debug, Default)]
+pub struct BodyUnsafety {
+    /// Operations that stay unsafe regardless of what happens to other functions.
+    pub ops: Vec<Option<HirId>>,
+    /// Calls to local unsafe functions, which are safe if the callee is made safe.  Closures
+    /// created in the body are recorded here too, since any unsafe operations in the closure
+    /// outside its own `unsafe` blocks rely on the `unsafe` context where it's created.
+    pub calls: Vec<(Option<HirId>, DefId)>,
+    /// All `unsafe` blocks in the body.
+    pub blocks: HashSet<HirId>,
+}
+
+impl BodyUnsafety {
+    /// Check whether the body is safe, given the set of local functions that are safe to call.
+    pub fn is_safe(&self, safe_fns: &HashSet<DefId>) -> bool {
+        self.ops.is_empty()
+            && self
+                .calls
+                .iter()
+                .all(|&(_, callee)| safe_fns.contains(&callee))
+    }
+}
+
+/// Check whether a pointer of type `lty` will be rewritten to a safe pointer type.
+fn is_safe_ptr(asn: &Assignment, lty: LTy) -> bool {
+    let ptr = lty.label;
+    if ptr.is_none() || asn.flags()[ptr].contains(FlagSet::FIXED) {
+        return false;
+    }
+    let desc = type_desc::perms_to_desc(lty.ty, asn.perms()[ptr], asn.flags()[ptr]);
+    !matches!(desc.own, Ownership::Raw | Ownership::RawMut)
+}
+
+struct UnsafetyVisitor<'a, 'tcx> {
+    acx: &'a AnalysisCtxt<'a, 'tcx>,
+    asn: &'a Assignment<'a>,
+    mir: &'a Body<'tcx>,
+    unsafety: BodyUnsafety,
+}
+
+impl<'a, 'tcx> UnsafetyVisitor<'a, 'tcx> {
+    fn block_at(&self, loc: Location) -> Option<HirId> {
+        let SourceInfo { scope, .. } = self.mir.source_info(loc);
+        match self.mir.source_scopes[*scope]
+            .local_data
+            .as_ref()
+            .assert_crate_local()
+            .safety
+        {
+            Safety::ExplicitUnsafe(hir_id) => Some(hir_id),
+            _ => None,
+        }
+    }
+
+    fn unsafe_op(&mut self, loc: Location) {
+        let block = self.block_at(loc);
+        self.unsafety.ops.push(block);
+    }
+
+    /// Check whether `lty` contains any raw pointer that stays raw after rewriting.
+    fn has_raw_ptr(&self, lty: LTy<'tcx>) -> bool {
+        lty.iter()
+            .any(|lty| lty.ty.is_unsafe_ptr() && !is_safe_ptr(self.asn, lty))
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for UnsafetyVisitor<'a, 'tcx> {
+    fn visit_rvalue(&mut self, rv: &Rvalue<'tcx>, location: Location) {
+        if let Rvalue::Aggregate(ref kind, _) = *rv {
+            if let AggregateKind::Closure(def_id, _) = **kind {
+                if def_id.is_local() {
+                    let block = self.block_at(location);
+                    self.unsafety.calls.push((block, def_id));
+                }
+            }
+        }
+        self.super_rvalue(rv, location);
+    }
+
+    fn visit_place(&mut self, pl: &Place<'tcx>, context: PlaceContext, location: Location) {
+        let tcx = self.acx.tcx();
+        for (base, proj) in pl.iter_projections() {
+            match proj {
+                ProjectionElem::Deref => {
+                    let base_lty = self.acx.type_of(base);
+                    if base_lty.ty.is_unsafe_ptr() && !is_safe_ptr(self.asn, base_lty) {
+                        self.unsafe_op(location);
+                    }
+                }
+                ProjectionElem::Field(..) => {
+                    let base_ty = base.ty(self.mir, tcx).ty;
+                    if matches!(*base_ty.kind(), TyKind::Adt(adt_def, _) if adt_def.is_union()) {
+                        self.unsafe_op(location);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.super_place(pl, context, location);
+    }
+
+    fn visit_constant(&mut self, c: &Constant<'tcx>, location: Location) {
+        let tcx = self.acx.tcx();
+        let static_did =
+            context::const_alloc_id(c).and_then(|id| context::find_static_for_alloc(&tcx, id));
+        if let Some(did) = static_did {
+            // Mutable statics are only safe to access if `static_kinds` replaces them with a safe
+            // alternative.
+            if tcx.is_mutable_static(did) && !self.acx.gacx.static_kinds.contains_key(&did) {
+                self.unsafe_op(location);
+            }
+        }
+        self.super_constant(c, location);
+    }
+
+    fn visit_terminator(&mut self, term: &Terminator<'tcx>, location: Location) {
+        let tcx = self.acx.tcx();
+        match term.kind {
+            TerminatorKind::Call {
+                ref func,
+                ref args,
+                destination,
+                ..
+            } => {
+                let func_ty = func.ty(self.mir, tcx);
+                if func_ty.fn_sig(tcx).unsafety() == Unsafety::Unsafe {
+                    match util::ty_callee(tcx, func_ty) {
+                        Callee::LocalDef { def_id, .. } => {
+                            let block = self.block_at(location);
+                            self.unsafety.calls.push((block, def_id));
+                        }
+                        // These are rewritten to safe operations when their pointer arguments
+                        // and results are rewritten to safe types.
+                        Callee::PtrOffset { .. }
+                        | Callee::Malloc
+                        | Callee::Calloc
+                        | Callee::Realloc
+                        | Callee::Free
+                        | Callee::Memcpy
+                        | Callee::Memmove
+                        | Callee::Memset => {
+                            let has_raw_ptr = args
+                                .iter()
+                                .map(|op| self.acx.type_of(op))
+                                .chain(std::iter::once(self.acx.type_of(destination)))
+                                .any(|lty| self.has_raw_ptr(lty));
+                            if has_raw_ptr {
+                                self.unsafe_op(location);
+                            }
+                        }
+                        _ => self.unsafe_op(location),
+                    }
+                }
+            }
+            TerminatorKind::InlineAsm { .. } => self.unsafe_op(location),
+            _ => {}
+        }
+        self.super_terminator(term, location);
+    }
+}
+
+/// Find the operations in `mir` that will still require `unsafe` after rewriting with `asn`.
+/// `unsafe_rewrite_locs` are the locations where the expression rewrites introduce an unsafe
+/// operation of their own.
+pub fn body_unsafety<'tcx>(
+    acx: &AnalysisCtxt<'_, 'tcx>,
+    asn: &Assignment,
+    mir: &Body<'tcx>,
+    unsafe_rewrite_locs: &[Location],
+) -> BodyUnsafety {
+    let blocks = mir
+        .source_scopes
+        .iter()
+        .filter_map(
+            |scope| match scope.local_data.as_ref().assert_crate_local().safety {
+                Safety::ExplicitUnsafe(hir_id) => Some(hir_id),
+                _ => None,
+            },
+        )
+        .collect();
+    let mut v = UnsafetyVisitor {
+        acx,
+        asn,
+        mir,
+        unsafety: BodyUnsafety {
+            blocks,
+            ..BodyUnsafety::default()
+        },
+    };
+    v.visit_body(mir);
+    for &loc in unsafe_rewrite_locs {
+        v.unsafe_op(loc);
+    }
+    v.unsafety
+}
+
+/// Check whether `def_id` can be made safe if its body is.  With `safe_wrappers`, exported
+/// `extern "C"` functions can be, by adding a wrapper.  With `minimize_unsafe`, other `unsafe fn`s
+/// can be, by removing the qualifier, unless they implement or define a trait method.  Closures
+/// have no qualifier, so they're safe whenever their bodies are.
+fn can_make_safe(tcx: TyCtxt, def_id: DefId, safe_wrappers: bool, minimize_unsafe: bool) -> bool {
+    if tcx.is_closure(def_id) {
+        return true;
+    }
+    if safe_wrappers::is_exported_extern_fn(tcx, def_id) {
+        return safe_wrappers;
+    }
+    if !minimize_unsafe || tcx.fn_sig(def_id).unsafety() != Unsafety::Unsafe {
+        return false;
+    }
+    match tcx.def_kind(def_id) {
+        DefKind::Fn => true,
+        DefKind::AssocFn => {
+            let parent_def_id = tcx.parent(def_id);
+            tcx.def_kind(parent_def_id) != DefKind::Trait
+                && tcx.impl_trait_ref(parent_def_id).is_none()
+        }
+        _ => false,
+    }
+}
+
+/// Find the unsafe functions among `bodies` that can be made safe.  A function can only be made
+/// safe if all the local unsafe functions it calls are made safe too.
+pub fn find_safe_fns(
+    tcx: TyCtxt,
+    bodies: &HashMap<DefId, BodyUnsafety>,
+    safe_wrappers: bool,
+    minimize_unsafe: bool,
+) -> HashSet<DefId> {
+    let mut fns = bodies
+        .iter()
+        .filter(|&(&did, unsafety)| {
+            unsafety.ops.is_empty() && can_make_safe(tcx, did, safe_wrappers, minimize_unsafe)
+        })
+        .map(|(&did, _)| did)
+        .collect::<HashSet<_>>();
+    loop {
+        let unsafe_fns = fns
+            .iter()
+            .copied()
+            .filter(|did| !bodies[did].is_safe(&fns))
+            .collect::<Vec<_>>();
+        if unsafe_fns.is_empty() {
+            break;
+        }
+        for did in unsafe_fns {
+            fns.remove(&did);
+        }
+    }
+    fns
+}
+
+/// Remove the `unsafe` keyword at the start of `span`, along with the whitespace after it.
+fn remove_unsafe_keyword(tcx: TyCtxt, span: Span) -> Option<(Span, Rewrite)> {
+    let source_map = tcx.sess.source_map();
+    let snippet = source_map.span_to_snippet(span).ok()?;
+    let offset = snippet.find("unsafe")?;
+    let lo = span.lo() + BytePos(offset as u32);
+    let keyword = span
+        .with_lo(lo)
+        .with_hi(lo + BytePos("unsafe".len() as u32));
+    let keyword = source_map
+        .span_extend_while(keyword, char::is_whitespace)
+        .ok()?;
+    Some((keyword, Rewrite::Text(String::new())))
+}
+
+/// Find the `unsafe` blocks in a body that no longer contain any unsafe operations, given the set
+/// of local functions that are made safe.
+fn unneeded_blocks(unsafety: &BodyUnsafety, safe_fns: &HashSet<DefId>) -> Vec<HirId> {
+    let needed = unsafety
+        .ops
+        .iter()
+        .copied()
+        .chain(
+            unsafety
+                .calls
+                .iter()
+                .filter(|&(_, callee)| !safe_fns.contains(callee))
+                .map(|&(block, _)| block),
+        )
+        .flatten()
+        .collect::<HashSet<_>>();
+    unsafety
+        .blocks
+        .iter()
+        .copied()
+        .filter(|block| !needed.contains(block))
+        .collect()
+}
+
+/// How much `unsafe` is left in the rewritten functions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UnsafetyCounts {
+    /// Functions that are rewritten.  Closures are counted as part of the function that contains
+    /// them.
+    pub fns: usize,
+    /// Rewritten functions with neither an `unsafe` qualifier nor any unsafe operations.
+    pub safe_fns: usize,
+    /// `unsafe` blocks in the rewritten functions.
+    pub blocks: usize,
+    /// `unsafe` blocks that are removed.  This is always zero without `minimize_unsafe`.
+    pub blocks_removed: usize,
+}
+
+/// Count the `unsafe` blocks and safe functions among the rewritten `bodies`, after the `unsafe`
+/// rewrites for `safe_fns` are applied.
+pub fn count_unsafety(
+    gacx: &GlobalAnalysisCtxt,
+    bodies: &HashMap<DefId, BodyUnsafety>,
+    safe_fns: &HashSet<DefId>,
+    minimize_unsafe: bool,
+) -> UnsafetyCounts {
+    let tcx = gacx.tcx;
+    let mut counts = UnsafetyCounts::default();
+    for (&did, unsafety) in bodies {
+        if gacx.dont_rewrite_fn(did) {
+            continue;
+        }
+        counts.blocks += unsafety.blocks.len();
+        if minimize_unsafe {
+            counts.blocks_removed += unneeded_blocks(unsafety, safe_fns).len();
+        }
+        if tcx.is_closure(did) {
+            continue;
+        }
+        counts.fns += 1;
+        let qualifier_removed =
+            tcx.fn_sig(did).unsafety() == Unsafety::Normal || safe_fns.contains(&did);
+        if unsafety.is_safe(safe_fns) && qualifier_removed {
+            counts.safe_fns += 1;
+        }
+    }
+    counts
+}
+
+/// Generate rewrites that remove unneeded `unsafe` blocks from the functions in `bodies`, and,
+/// for the `safe_fns` that don't get safe wrappers, the `unsafe` qualifier.
+pub fn gen_unsafety_rewrites(
+    gacx: &GlobalAnalysisCtxt,
+    bodies: &HashMap<DefId, BodyUnsafety>,
+    safe_fns: &HashSet<DefId>,
+) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let mut rewrites = Vec::new();
+
+    for (&did, unsafety) in bodies {
+        if gacx.dont_rewrite_fn(did) {
+            continue;
+        }
+
+        // `unsafe { .. }` -> `{ .. }`
+        for block in unneeded_blocks(unsafety, safe_fns) {
+            rewrites.extend(remove_unsafe_keyword(tcx, tcx.hir().span(block)));
+        }
+
+        // `unsafe fn` -> `fn`
+        if safe_fns.contains(&did) && !gacx.safe_wrapper_fns.contains(&did) {
+            let node = tcx.hir().get_by_def_id(did.expect_local());
+            let (sig, ident) = match (node.fn_sig(), node.ident()) {
+                (Some(sig), Some(ident)) => (sig, ident),
+                _ => continue,
+            };
+            let header = sig.span.with_hi(ident.span.lo());
+            rewrites.extend(remove_unsafe_keyword(tcx, header));
+        }
+    }
+
+    rewrites
+}
@@ -0,0 +1,148 @@
+//! Rewrites for `void*` user-data parameters that are passed through to callbacks.  See
+//! `crate::user_data` for the analysis that finds them.
+//!
+//! The signatures of registration fns and callbacks are rewritten whether or not the rest of the
+//! function is, since each callback's new signature must match the registration fn's new type
+//! parameter.  Calls to registration fns cast the user-data argument to `*mut _`; in rewritten
+//! functions, this is done by `mir_op`, and in the remaining functions, by
+//! [`gen_user_data_call_rewrites`].
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::rewrite::ty::gen_generics_rws;
+use crate::rewrite::Rewrite;
+use rustc_hir as hir;
+use rustc_hir::def::{DefKind, Namespace, Res};
+use rustc_hir::def_id::LocalDefId;
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{Expr, ExprKind, Generics};
+use rustc_middle::hir::nested_filter;
+use rustc_middle::ty::print::{FmtPrinter, Print};
+use rustc_middle::ty::TypeckResults;
+use rustc_span::Span;
+
+/// Generate rewrites for the signature of `ldid` if it's a user-data registration fn or callback.
+/// Returns the new type parameters that should be added to the function's generics.
+pub fn gen_user_data_sig_rewrites(
+    gacx: &GlobalAnalysisCtxt,
+    ldid: LocalDefId,
+    hir_rewrites: &mut Vec<(Span, Rewrite)>,
+) -> &'static [&'static str] {
+    let tcx = gacx.tcx;
+    let did = ldid.to_def_id();
+    let hir_sig = match tcx
+        .hir()
+        .fn_sig_by_hir_id(tcx.hir().local_def_id_to_hir_id(ldid))
+    {
+        Some(x) => x,
+        None => return &[],
+    };
+
+    if let Some(reg) = gacx.user_data.registration_fns.get(&did) {
+        // `data: *mut c_void` -> `data: *mut T`
+        let data_ty = &hir_sig.decl.inputs[reg.data_arg];
+        if let hir::TyKind::Ptr(ref mt) = data_ty.kind {
+            hir_rewrites.push((data_ty.span, ptr_to_rw("T".into(), mt.mutbl)));
+        }
+        // `cb: fn(*mut c_void)` -> `cb: fn(*mut T)`
+        if let hir::TyKind::BareFn(bare_fn) = hir_sig.decl.inputs[reg.cb_arg].kind {
+            let cb_data_ty = &bare_fn.decl.inputs[reg.cb_data_arg];
+            if let hir::TyKind::Ptr(ref mt) = cb_data_ty.kind {
+                hir_rewrites.push((cb_data_ty.span, ptr_to_rw("T".into(), mt.mutbl)));
+            }
+        }
+        return &["T"];
+    }
+
+    if let Some(cb) = gacx.user_data.callbacks.get(&did) {
+        // `data: *mut c_void` -> `data: *mut U`, where `U` is the type the callback actually uses
+        let data_ty = &hir_sig.decl.inputs[cb.data_arg];
+        if let hir::TyKind::Ptr(ref mt) = data_ty.kind {
+            let printer = FmtPrinter::new(tcx, Namespace::TypeNS);
+            let pointee = cb.pointee_ty.print(printer).unwrap().into_buffer();
+            hir_rewrites.push((data_ty.span, ptr_to_rw(pointee, mt.mutbl)));
+        }
+    }
+
+    &[]
+}
+
+fn ptr_to_rw(pointee: String, mutbl: hir::Mutability) -> Rewrite {
+    Rewrite::TyPtr(Box::new(Rewrite::Print(pointee)), mutbl)
+}
+
+/// Generate rewrites for user-data registration fns and callbacks in functions that aren't being
+/// rewritten.  This covers their signatures (see [`gen_user_data_sig_rewrites`]), and calls to
+/// registration fns within their bodies.
+pub fn gen_user_data_call_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let mut rewrites = Vec::new();
+    if gacx.user_data.registration_fns.is_empty() {
+        return rewrites;
+    }
+
+    for skip_def_id in gacx.iter_fns_skip_rewrite() {
+        let skip_ldid = match skip_def_id.as_local() {
+            Some(x) => x,
+            None => continue,
+        };
+        let hir_body_id = match tcx.hir().maybe_body_owned_by(skip_ldid) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let type_params = gen_user_data_sig_rewrites(gacx, skip_ldid, &mut rewrites);
+        if !type_params.is_empty() {
+            let generics = tcx
+                .hir()
+                .get_generics(skip_ldid)
+                .unwrap_or(Generics::empty());
+            gen_generics_rws(&mut rewrites, generics, [].iter(), type_params);
+        }
+
+        let mut v = UserDataCallVisitor {
+            gacx,
+            typeck_results: tcx.typeck_body(hir_body_id),
+            rewrites,
+        };
+        v.visit_body(tcx.hir().body(hir_body_id));
+        rewrites = v.rewrites;
+    }
+
+    rewrites
+}
+
+struct UserDataCallVisitor<'a, 'tcx> {
+    gacx: &'a GlobalAnalysisCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    rewrites: Vec<(Span, Rewrite)>,
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for UserDataCallVisitor<'a, 'tcx> {
+    type NestedFilter = nested_filter::OnlyBodies;
+
+    fn nested_visit_map(&mut self) -> Self::Map {
+        self.gacx.tcx.hir()
+    }
+
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        if let ExprKind::Call(func, args) = ex.kind {
+            if let ExprKind::Path(ref qp) = func.kind {
+                if let Res::Def(DefKind::Fn, did) = self.typeck_results.qpath_res(qp, func.hir_id) {
+                    if let Some(reg) = self.gacx.user_data.registration_fns.get(&did) {
+                        // `data` -> `(data) as *mut _`
+                        let data = &args[reg.data_arg];
+                        let ptr = if reg.mutbl { "*mut" } else { "*const" };
+                        self.rewrites
+                            .push((data.span.shrink_to_lo(), Rewrite::Text("(".into())));
+                        self.rewrites.push((
+                            data.span.shrink_to_hi(),
+                            Rewrite::Text(format!(") as {ptr} _")),
+                        ));
+                    }
+                }
+            }
+        }
+
+        intravisit::walk_expr(self, ex);
+    }
+}
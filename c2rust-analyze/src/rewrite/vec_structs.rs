@@ -0,0 +1,221 @@
+//! Rewrites for growable-buffer structs, whose `(ptr, len, cap)` fields are replaced by a single
+//! `Vec<T>`.  See `crate::vec_structs` for the analysis that finds them.
+//!
+//! Functions that use the fields are excluded from the normal rewrites, so this module handles
+//! both the struct definition and every use of the fields.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::rewrite::ty::gen_remove_copy_derive_rewrite;
+use crate::rewrite::Rewrite;
+use crate::vec_structs::{self, VecFieldUse, VecFields};
+use rustc_hir as hir;
+use rustc_hir::def::Namespace;
+use rustc_hir::def_id::DefId;
+use rustc_hir::Mutability;
+use rustc_middle::ty::print::{FmtPrinter, Print};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+
+/// `(e)`.  The emitter only parenthesizes casts based on the precedence of the enclosing
+/// rewrite, so a cast used as a method receiver, or rewritten in place of one, needs explicit
+/// parentheses.
+fn paren(rw: Rewrite) -> Rewrite {
+    Rewrite::Call(String::new(), vec![rw])
+}
+
+/// Rewrite the definition of `did`: the pointer field becomes a `Vec`, the length and capacity
+/// fields are removed, and `Copy` is removed from the derives.
+fn gen_struct_def_rewrites(
+    tcx: TyCtxt,
+    did: DefId,
+    fields: &VecFields,
+    rewrites: &mut Vec<(Span, Rewrite)>,
+) {
+    let item = tcx.hir().expect_item(did.expect_local());
+    let variant = match item.kind {
+        hir::ItemKind::Struct(ref variant, _) => variant,
+        _ => unreachable!("vec struct {did:?} should be a struct"),
+    };
+    for field in variant.fields() {
+        let field_did = tcx.hir().local_def_id(field.hir_id).to_def_id();
+        if field_did == fields.ptr {
+            // `*mut T` -> `Vec<T>`
+            if let hir::TyKind::Ptr(ref mt) = field.ty.kind {
+                rewrites.push((
+                    field.ty.span,
+                    Rewrite::TyCtor("Vec".into(), vec![Rewrite::Extract(mt.ty.span)]),
+                ));
+            }
+        } else if field_did == fields.len || field_did == fields.cap {
+            rewrites.push((
+                vec_structs::removal_span(tcx, field.span),
+                Rewrite::Text(String::new()),
+            ));
+        }
+    }
+
+    rewrites.extend(gen_remove_copy_derive_rewrite(tcx, did));
+}
+
+pub fn gen_vec_struct_rewrites(gacx: &GlobalAnalysisCtxt) -> Vec<(Span, Rewrite)> {
+    let tcx = gacx.tcx;
+    let info = &gacx.vec_structs;
+    let mut rewrites = Vec::new();
+    if info.structs.is_empty() {
+        return rewrites;
+    }
+
+    for (&did, fields) in &info.structs {
+        gen_struct_def_rewrites(tcx, did, fields, &mut rewrites);
+    }
+
+    for &did in &info.fns {
+        let ldid = match did.as_local() {
+            Some(x) => x,
+            None => continue,
+        };
+        for (adt, u) in vec_structs::collect_vec_field_uses(tcx, ldid, &info.structs) {
+            // Accesses to the length and capacity fields are renamed to the pointer field, which
+            // now holds the `Vec`.
+            let ptr_name = Rewrite::Text(tcx.item_name(info.structs[&adt].ptr).to_string());
+            match u {
+                // `(*b).len` -> `((*b).data.len() as size_t)`
+                VecFieldUse::Count {
+                    expr,
+                    ident,
+                    cap,
+                    ty,
+                } => {
+                    let method = if cap { "capacity" } else { "len" };
+                    let count =
+                        Rewrite::MethodCall(method.into(), Box::new(Rewrite::Identity), vec![]);
+                    let printer = FmtPrinter::new(tcx, Namespace::TypeNS);
+                    let ty = ty.print(printer).unwrap().into_buffer();
+                    let cast = Rewrite::Cast(Box::new(count), Box::new(Rewrite::Print(ty)));
+                    rewrites.push((expr, paren(cast)));
+                    rewrites.push((ident, ptr_name));
+                }
+                // `*(*b).data.offset(i)` -> `(*b).data[i as usize]`
+                VecFieldUse::Elem { expr, ptr, index } => {
+                    let index = match index {
+                        Some(index) => Rewrite::Cast(
+                            Box::new(Rewrite::Sub(1, index)),
+                            Box::new(Rewrite::Print("usize".into())),
+                        ),
+                        None => Rewrite::LitZero,
+                    };
+                    rewrites.push((
+                        expr,
+                        Rewrite::Index(Box::new(Rewrite::Sub(0, ptr)), Box::new(index)),
+                    ));
+                }
+                // `*(*b).data.offset((*b).len as isize) = x; (*b).len += 1;` ->
+                // `(*b).data.push(x);`
+                VecFieldUse::Push {
+                    write,
+                    ptr,
+                    value,
+                    incr,
+                } => {
+                    rewrites.push((
+                        write,
+                        Rewrite::MethodCall(
+                            "push".into(),
+                            Box::new(Rewrite::Sub(0, ptr)),
+                            vec![Rewrite::Sub(1, value)],
+                        ),
+                    ));
+                    rewrites.push((incr, Rewrite::Text(String::new())));
+                }
+                // `(*b).len = 0` -> `(*b).data.clear()`
+                VecFieldUse::Clear { expr, lhs, ident } => {
+                    rewrites.push((
+                        expr,
+                        Rewrite::MethodCall("clear".into(), Box::new(Rewrite::Sub(0, lhs)), vec![]),
+                    ));
+                    rewrites.push((ident, ptr_name));
+                }
+                // `(*b).cap = n` ->
+                // `(*b).data.reserve_exact((n as usize).saturating_sub((*b).data.len()))`
+                VecFieldUse::Reserve {
+                    expr,
+                    lhs,
+                    ident,
+                    rhs,
+                } => {
+                    let new_cap = Rewrite::Cast(
+                        Box::new(Rewrite::Sub(1, rhs)),
+                        Box::new(Rewrite::Print("usize".into())),
+                    );
+                    let len =
+                        Rewrite::MethodCall("len".into(), Box::new(Rewrite::Sub(0, lhs)), vec![]);
+                    let additional = Rewrite::MethodCall(
+                        "saturating_sub".into(),
+                        Box::new(paren(new_cap)),
+                        vec![len],
+                    );
+                    rewrites.push((
+                        expr,
+                        Rewrite::MethodCall(
+                            "reserve_exact".into(),
+                            Box::new(Rewrite::Sub(0, lhs)),
+                            vec![additional],
+                        ),
+                    ));
+                    rewrites.push((ident, ptr_name));
+                }
+                // `(*b).data = realloc(..) as *mut T` -> `()`.  The `Vec` already has room for
+                // the new capacity.
+                VecFieldUse::Realloc { expr } => {
+                    rewrites.push((expr, Rewrite::Text("()".into())));
+                }
+                // `let p = realloc(..) as *mut T;` -> nothing
+                VecFieldUse::GrowTemp { stmt } => {
+                    rewrites.push((stmt, Rewrite::Text(String::new())));
+                }
+                // `p.is_null()` -> `false`.  Growing a `Vec` never returns null.
+                VecFieldUse::GrowCheck { expr } => {
+                    rewrites.push((expr, Rewrite::Text("false".into())));
+                }
+                // `(*b).data = malloc(..) as *mut T` ->
+                // `ptr::write(addr_of_mut!((*b).data), Vec::new())`.  The old value of the field
+                // may be uninitialized memory, so it must not be dropped.
+                VecFieldUse::Init { expr, lhs } => {
+                    rewrites.push((
+                        expr,
+                        Rewrite::Call(
+                            "std::ptr::write".into(),
+                            vec![
+                                Rewrite::AddrOf(Box::new(Rewrite::Sub(0, lhs)), Mutability::Mut),
+                                Rewrite::Text("Vec::new()".into()),
+                            ],
+                        ),
+                    ));
+                }
+                // `free((*b).data as *mut c_void)` -> `(*b).data = Vec::new()`
+                VecFieldUse::Free { call, ptr } => {
+                    rewrites.push((
+                        call,
+                        Rewrite::Binary(
+                            "=".into(),
+                            Box::new(Rewrite::Sub(0, ptr)),
+                            Box::new(Rewrite::Text("Vec::new()".into())),
+                        ),
+                    ));
+                }
+                // `S { data: 0 as *mut T, len: 0, cap: 0 }` -> `S { data: Vec::new() }`
+                VecFieldUse::Literal { ptr_value, removed } => {
+                    rewrites.push((ptr_value, Rewrite::Text("Vec::new()".into())));
+                    for span in removed {
+                        rewrites.push((span, Rewrite::Text(String::new())));
+                    }
+                }
+                VecFieldUse::Other => {
+                    // `find_vec_structs` rejects any struct with an `Other` use.
+                    unreachable!("unsupported use of vec struct fields")
+                }
+            }
+        }
+    }
+    rewrites
+}
@@ -0,0 +1,131 @@
+//! Writing the rewrites as a structured manifest.
+//!
+//! When `C2RUST_ANALYZE_REWRITE_MANIFEST` names a file, every rewrite that's applied (or, with
+//! `--dry-run`, planned) is recorded there as JSON, so that external tools can re-render the
+//! changes or apply only some of them.  Each entry gives the span being replaced, both as lines
+//! and columns and as byte offsets into the file, along with the replacement code.  The
+//! replacement is also given as a template, with a `$N` placeholder for each subexpression that's
+//! kept; rewrites can nest, so a subexpression may itself be the span of another entry.  For
+//! expression rewrites, the entry also names the HIR expression, the MIR `RewriteKind`s the
+//! rewrite was built from, and the pointers involved.  Local `PointerId`s are only unique within
+//! a function, so they're identified together with `owner`.  When several crates are analyzed in
+//! one build, each appends its entries to the same file.
+
+use crate::json_file;
+use crate::output_facts::{self, SpanFact};
+use crate::rewrite::{self, ExprRewriteOrigin, Rewrite};
+use rustc_hir::def_id::LOCAL_CRATE;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Get the file to write the manifest to, if one was set.
+pub fn output_path() -> Option<PathBuf> {
+    env::var_os("C2RUST_ANALYZE_REWRITE_MANIFEST").map(PathBuf::from)
+}
+
+/// A source range, given both as a `SpanFact` and as byte offsets into the file.
+#[derive(Clone, Debug, Serialize)]
+pub struct ManifestSpan {
+    #[serde(flatten)]
+    pub span: Option<SpanFact>,
+    pub byte_lo: usize,
+    pub byte_hi: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SubFact {
+    pub index: usize,
+    pub span: ManifestSpan,
+}
+
+/// A single rewrite.
+#[derive(Clone, Debug, Serialize)]
+pub struct RewriteEntry {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub span: ManifestSpan,
+    /// The original code.
+    pub old: String,
+    /// The replacement code, with the original text of each subexpression.
+    pub new: String,
+    /// The replacement code, with `$N` in place of subexpression `N`.
+    pub template: String,
+    pub subs: Vec<SubFact>,
+    /// The def path of the HIR owner of the rewritten expression.  This and the following fields
+    /// are only set for expression rewrites.
+    pub owner: Option<String>,
+    /// The `ItemLocalId` of the rewritten expression within `owner`.
+    pub hir_local_id: Option<u32>,
+    pub kinds: Vec<String>,
+    pub ptrs: Vec<String>,
+}
+
+fn manifest_span(tcx: TyCtxt, span: Span) -> ManifestSpan {
+    let sm = tcx.sess.source_map();
+    ManifestSpan {
+        span: output_facts::span_fact(tcx, span),
+        byte_lo: sm.lookup_byte_offset(span.lo()).pos.0 as usize,
+        byte_hi: sm.lookup_byte_offset(span.hi()).pos.0 as usize,
+    }
+}
+
+/// Build the manifest entries for `rewrites`.  `origins` gives the origin of each expression
+/// rewrite, keyed by span.
+pub fn build_entries(
+    tcx: TyCtxt,
+    rewrites: &[(Span, Rewrite)],
+    origins: &HashMap<Span, ExprRewriteOrigin>,
+) -> Vec<RewriteEntry> {
+    let sm = tcx.sess.source_map();
+    let krate = tcx.crate_name(LOCAL_CRATE).to_string();
+    let mut entries = rewrites
+        .iter()
+        .map(|&(span, ref rw)| {
+            let (template, subs) = rewrite::render_rewrite_template(sm, rw);
+            let subs = subs
+                .into_iter()
+                .map(|(index, span)| SubFact {
+                    index,
+                    span: manifest_span(tcx, span),
+                })
+                .collect();
+            let origin = origins.get(&span.source_callsite());
+            RewriteEntry {
+                krate: krate.clone(),
+                span: manifest_span(tcx, span),
+                old: sm.span_to_snippet(span).unwrap_or_default(),
+                new: rewrite::render_rewrite(sm, rw),
+                template,
+                subs,
+                owner: origin.map(|o| tcx.def_path_str(o.hir_id.owner.to_def_id())),
+                hir_local_id: origin.map(|o| o.hir_id.local_id.as_u32()),
+                kinds: origin.map_or_else(Vec::new, |o| {
+                    o.kinds.iter().map(|kind| format!("{:?}", kind)).collect()
+                }),
+                ptrs: origin.map_or_else(Vec::new, |o| {
+                    o.ptrs.iter().map(|ptr| ptr.to_string()).collect()
+                }),
+            }
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by_cached_key(|e| {
+        let file = e.span.span.as_ref().map(|s| s.file.clone());
+        (file, e.span.byte_lo, usize::MAX - e.span.byte_hi)
+    });
+    entries
+}
+
+/// Write `entries` to `path` as a JSON array.  If `path` already holds the entries for another
+/// crate, the new entries are added after them.
+pub fn write(path: &Path, entries: &[RewriteEntry]) -> io::Result<()> {
+    let entries = entries
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()?;
+    json_file::append_to_array(path, entries)
+}
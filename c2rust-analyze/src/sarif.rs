@@ -0,0 +1,177 @@
+//! Writing analysis diagnostics in SARIF format.
+//!
+//! When `C2RUST_ANALYZE_SARIF` names a file, the analysis reports the problems that kept code
+//! from being rewritten there as a SARIF 2.1.0 log, which code review tools can display alongside
+//! the results of other static analyzers.  Each crate adds its own run to the log.
+
+use crate::json_file;
+use crate::output_facts::{self, SpanFact};
+use rustc_hir::def_id::LOCAL_CRATE;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use serde_json::{json, Value};
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Get the file to write the SARIF log to, if one was set.
+pub fn output_path() -> Option<PathBuf> {
+    env::var_os("C2RUST_ANALYZE_SARIF").map(PathBuf::from)
+}
+
+/// The kinds of diagnostics that can be reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rule {
+    /// The analysis panicked while processing a function.
+    AnalysisFailed,
+    /// A function isn't rewritten because it uses an unsupported construct or depends on a
+    /// function that isn't rewritten.
+    FnNotRewritten,
+    /// A static isn't rewritten.
+    StaticNotRewritten,
+    /// A field isn't rewritten.
+    FieldNotRewritten,
+    /// A pointer lost `UNIQUE` because a loan through it conflicts with a later use.
+    BorrowckConflict,
+}
+
+impl Rule {
+    const ALL: [Rule; 5] = [
+        Rule::AnalysisFailed,
+        Rule::FnNotRewritten,
+        Rule::StaticNotRewritten,
+        Rule::FieldNotRewritten,
+        Rule::BorrowckConflict,
+    ];
+
+    fn id(self) -> &'static str {
+        match self {
+            Rule::AnalysisFailed => "analysis-failed",
+            Rule::FnNotRewritten => "fn-not-rewritten",
+            Rule::StaticNotRewritten => "static-not-rewritten",
+            Rule::FieldNotRewritten => "field-not-rewritten",
+            Rule::BorrowckConflict => "borrowck-conflict",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Rule::AnalysisFailed => "The analysis failed on this function",
+            Rule::FnNotRewritten => "This function is left unrewritten",
+            Rule::StaticNotRewritten => "This static is left unrewritten",
+            Rule::FieldNotRewritten => "This field is left unrewritten",
+            Rule::BorrowckConflict => "A conflicting use keeps this pointer from being unique",
+        }
+    }
+
+    fn level(self) -> &'static str {
+        match self {
+            Rule::AnalysisFailed => "error",
+            Rule::FnNotRewritten | Rule::StaticNotRewritten | Rule::FieldNotRewritten => "warning",
+            Rule::BorrowckConflict => "note",
+        }
+    }
+}
+
+fn location(span: &SpanFact, message: Option<&str>) -> Value {
+    let mut loc = json!({
+        "physicalLocation": {
+            "artifactLocation": { "uri": span.file },
+            "region": {
+                "startLine": span.lo_line,
+                "startColumn": span.lo_col,
+                "endLine": span.hi_line,
+                "endColumn": span.hi_col,
+            },
+        },
+    });
+    if let Some(message) = message {
+        loc["message"] = json!({ "text": message });
+    }
+    loc
+}
+
+/// Accumulates SARIF results for the current crate.
+pub struct SarifLog<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    results: Vec<Value>,
+}
+
+impl<'tcx> SarifLog<'tcx> {
+    pub fn new(tcx: TyCtxt<'tcx>) -> SarifLog<'tcx> {
+        SarifLog {
+            tcx,
+            results: Vec::new(),
+        }
+    }
+
+    /// Add a result for `rule` at `span`.  `related` gives other locations involved in the
+    /// problem, each with a message describing its role.
+    pub fn add_result(&mut self, rule: Rule, span: Span, message: &str, related: &[(Span, &str)]) {
+        let mut result = json!({
+            "ruleId": rule.id(),
+            "level": rule.level(),
+            "message": { "text": message },
+        });
+        if let Some(span) = output_facts::span_fact(self.tcx, span) {
+            result["locations"] = json!([location(&span, None)]);
+        }
+        let related = related
+            .iter()
+            .filter_map(|&(span, message)| {
+                let span = output_facts::span_fact(self.tcx, span)?;
+                Some(location(&span, Some(message)))
+            })
+            .collect::<Vec<_>>();
+        if !related.is_empty() {
+            result["relatedLocations"] = Value::Array(related);
+        }
+        self.results.push(result);
+    }
+
+    /// Write the results to `path` as a SARIF log.  If `path` already holds a log from another
+    /// crate, the results are added to it as a new run.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let rules = Rule::ALL
+            .iter()
+            .map(|rule| {
+                json!({
+                    "id": rule.id(),
+                    "shortDescription": { "text": rule.description() },
+                    "defaultConfiguration": { "level": rule.level() },
+                })
+            })
+            .collect::<Vec<_>>();
+        let run = json!({
+            "tool": {
+                "driver": {
+                    "name": "c2rust-analyze",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules,
+                },
+            },
+            "automationDetails": {
+                "id": format!("c2rust-analyze/{}", self.tcx.crate_name(LOCAL_CRATE)),
+            },
+            "results": self.results,
+        });
+        let init = || {
+            json!({
+                "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+                "version": "2.1.0",
+                "runs": [],
+            })
+        };
+        json_file::update(path, init, |log| match log["runs"].as_array_mut() {
+            Some(runs) => {
+                runs.push(run);
+                Ok(())
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?} is not a SARIF log", path),
+            )),
+        })
+    }
+}
@@ -0,0 +1,657 @@
+//! Handling of self-referential structs, whose pointer fields point into other fields of the same
+//! struct:
+//!
+//! ```ignore
+//! struct Parser {
+//!     buf: [u8; 64],
+//!     cur: *mut u8,
+//! }
+//!
+//! unsafe fn reset(p: *mut Parser) {
+//!     (*p).cur = (*p).buf.as_mut_ptr();
+//! }
+//! ```
+//!
+//! The normal rewrites would turn `cur` into a reference that borrows from the struct holding it,
+//! which the borrow checker rejects, so every function that stores into `cur` fails to rewrite.
+//! Instead, each self-referential struct is handled by one of two strategies, selected per struct
+//! by listing it in `C2RUST_ANALYZE_SELF_REF_INDEX_STRUCTS_LIST` or
+//! `C2RUST_ANALYZE_SELF_REF_PIN_STRUCTS_LIST`, or by marking it
+//! `#[c2rust_analyze_test::self_ref_index]` or `#[c2rust_analyze_test::self_ref_pin]`:
+//!
+//! * With the index strategy, the struct serves as the arena for its own pointers: each pointer
+//!   into an array field becomes a `usize` index into that array.  Storing
+//!   `(*p).buf.as_mut_ptr()` stores `0`, `*(*p).cur` becomes `(*p).buf[(*p).cur]`,
+//!   `(*p).cur.offset(n)` adds `n` to the index, and `(*p).cur.offset_from((*p).buf.as_ptr())` is
+//!   the index itself.  A null pointer also becomes `0`, so checking a pointer for null isn't
+//!   supported.  The functions that use the pointers are excluded from the normal rewrites, and
+//!   any other use of the pointers, such as passing one to another function, prevents the struct
+//!   from being rewritten.
+//!
+//! * With the pin strategy, the pointers stay raw, and the struct gets a
+//!   `std::marker::PhantomPinned` field, which makes it `!Unpin`, so that once the struct is held
+//!   in a `Pin`, it can't be moved out from under its own pointers.  Every struct literal gets the
+//!   new field as well.  This works for pointers into any field, not just arrays, and places no
+//!   restrictions on how the pointers are used.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::util::{self, Callee};
+use log::debug;
+use rustc_ast::LitKind;
+use rustc_hir as hir;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{BinOpKind, Expr, ExprKind, HirId, UnOp};
+use rustc_middle::ty::{TyCtxt, TyKind, TypeckResults};
+use rustc_span::Span;
+use std::collections::{HashMap, HashSet};
+
+/// How the pointers of a self-referential struct are rewritten.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelfRefStrategy {
+    /// Pointers into an array field become `usize` indices into it.
+    Index,
+    /// Pointers stay raw, and the struct is made `!Unpin`.
+    Pin,
+}
+
+#[derive(Clone, Debug)]
+pub struct SelfRefStruct {
+    pub strategy: SelfRefStrategy,
+    /// Self-pointer fields, each mapped to the field of the same struct that it points into.
+    pub ptrs: HashMap<DefId, DefId>,
+}
+
+/// A single use of a self-pointer field of a struct using the index strategy.
+#[derive(Clone, Copy, Debug)]
+pub enum SelfPtrUse {
+    /// An address within the target field is stored, as in `(*p).buf.as_mut_ptr()`,
+    /// `(*p).buf.as_mut_ptr().offset(i)`, or `&mut (*p).buf[i] as *mut T`.  `index` is `i`, or
+    /// `None` for the start of the array.
+    Addr { expr: Span, index: Option<Span> },
+    /// A null pointer is stored, as in `0 as *mut T` or `ptr::null_mut()`.
+    Null { expr: Span },
+    /// An offset from another pointer into the same field is stored, as in
+    /// `(*p).cur.offset(n)`.  `ptr` is `(*p).cur`, and `delta` is `n`.
+    Advance { expr: Span, ptr: Span, delta: Span },
+    /// The pointer is dereferenced, as in `*(*p).cur` or `*(*p).cur.offset(n)`.  `field` is
+    /// `(*p).cur`, `ident` is the span of the field name, `target` is the field it points into,
+    /// and `delta` is `n`, if any.
+    Deref {
+        expr: Span,
+        field: Span,
+        ident: Span,
+        target: DefId,
+        delta: Option<Span>,
+    },
+    /// The distance from the start of the target field is computed, as in
+    /// `(*p).cur.offset_from((*p).buf.as_ptr())`.  `ptr` is `(*p).cur`.
+    Distance { expr: Span, ptr: Span },
+    /// Any other use of the pointer.  This prevents the struct from being rewritten.
+    Other,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SelfRefInfo {
+    /// Self-referential structs that will be rewritten, along with their self-pointer fields.
+    pub structs: HashMap<DefId, SelfRefStruct>,
+    /// Functions that use the self-pointer fields of any of the `structs` using the index
+    /// strategy.  These are excluded from the normal rewrites and handled entirely by
+    /// `rewrite::gen_self_ref_rewrites`.
+    pub fns: HashSet<DefId>,
+}
+
+impl SelfRefInfo {
+    /// Iterate over the structs using `strategy`.
+    pub fn structs_with(
+        &self,
+        strategy: SelfRefStrategy,
+    ) -> impl Iterator<Item = (DefId, &SelfRefStruct)> + '_ {
+        self.structs
+            .iter()
+            .filter(move |(_, s)| s.strategy == strategy)
+            .map(|(&did, s)| (did, s))
+    }
+
+    /// All self-pointer fields of structs using the index strategy, mapped to their targets.
+    pub fn index_ptrs(&self) -> HashMap<DefId, DefId> {
+        self.structs_with(SelfRefStrategy::Index)
+            .flat_map(|(_, s)| s.ptrs.iter().map(|(&ptr, &target)| (ptr, target)))
+            .collect()
+    }
+}
+
+/// Helpers for recognizing field accesses and addresses within the fields of the `structs`.
+struct FieldMatcher<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    structs: &'a HashSet<DefId>,
+}
+
+impl<'a, 'tcx> FieldMatcher<'a, 'tcx> {
+    /// If `ex` is an access to a field of one of the `structs`, as in `(*p).cur`, return the
+    /// field, the local that holds the struct or a pointer to it, and the span of the field name.
+    fn field(&self, ex: &'tcx Expr<'tcx>) -> Option<(DefId, Option<HirId>, Span)> {
+        let (base, ident) = match ex.kind {
+            ExprKind::Field(base, ident) => (base, ident),
+            _ => return None,
+        };
+        let adt_def = match *self
+            .typeck_results
+            .expr_ty_adjusted(base)
+            .peel_refs()
+            .kind()
+        {
+            TyKind::Adt(adt_def, _) => adt_def,
+            _ => return None,
+        };
+        if !self.structs.contains(&adt_def.did()) {
+            return None;
+        }
+        let idx = *self.typeck_results.field_indices().get(ex.hir_id)?;
+        let did = adt_def.non_enum_variant().fields.get(idx)?.did;
+        let owner = match base.kind {
+            ExprKind::Unary(UnOp::Deref, ptr) => local_var(ptr),
+            _ => local_var(base),
+        };
+        Some((did, owner, ident.span))
+    }
+
+    /// If `ex` is an address within a field of one of the `structs`, as in
+    /// `(*p).buf.as_mut_ptr().offset(i)`, return the field, the local that holds the struct, and
+    /// the index, if any.
+    fn addr(
+        &self,
+        ex: &'tcx Expr<'tcx>,
+    ) -> Option<(DefId, Option<HirId>, Option<&'tcx Expr<'tcx>>)> {
+        let ex = peel_casts(ex);
+        match ex.kind {
+            // `(*p).buf.as_mut_ptr().offset(i)`
+            ExprKind::MethodCall(seg, [base, index], _)
+                if matches!(seg.ident.as_str(), "offset" | "add") =>
+            {
+                match self.addr(base)? {
+                    (field, owner, None) => Some((field, owner, Some(index))),
+                    _ => None,
+                }
+            }
+            // `(*p).buf.as_mut_ptr()`
+            ExprKind::MethodCall(seg, [base], _)
+                if matches!(seg.ident.as_str(), "as_mut_ptr" | "as_ptr") =>
+            {
+                let (field, owner, _) = self.field(base)?;
+                Some((field, owner, None))
+            }
+            // `&mut (*p).buf[i]`, `&mut (*p).x`
+            ExprKind::AddrOf(_, _, pl) => match pl.kind {
+                ExprKind::Index(base, index) => {
+                    let (field, owner, _) = self.field(base)?;
+                    Some((field, owner, Some(index)))
+                }
+                _ => {
+                    let (field, owner, _) = self.field(pl)?;
+                    Some((field, owner, None))
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /// Check whether `ex` is a null pointer, as in `0 as *mut T` or `ptr::null_mut()`.
+    fn is_null(&self, ex: &Expr<'tcx>) -> bool {
+        let ex = peel_casts(ex);
+        match ex.kind {
+            ExprKind::Lit(ref lit) => matches!(lit.node, LitKind::Int(0, _)),
+            ExprKind::Call(func, _) => matches!(
+                util::ty_callee(self.tcx, self.typeck_results.expr_ty(func)),
+                Callee::Null { .. }
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// Visitor that finds stores of addresses within the `candidates` structs into pointer fields of
+/// the same struct instance, as in `(*p).cur = (*p).buf.as_mut_ptr()`.
+struct SelfPtrStoreVisitor<'a, 'tcx> {
+    m: FieldMatcher<'a, 'tcx>,
+    /// Each store, as a `(ptr, target)` pair of fields.
+    stores: Vec<(DefId, DefId)>,
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for SelfPtrStoreVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        if let ExprKind::Assign(lhs, rhs, _) = ex.kind {
+            if let (Some((ptr, Some(owner), _)), Some((target, Some(target_owner), _))) =
+                (self.m.field(lhs), self.m.addr(rhs))
+            {
+                if owner == target_owner && ptr != target {
+                    self.stores.push((ptr, target));
+                }
+            }
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Visitor that finds all uses of the self-pointer fields `ptrs` within a body.
+pub struct SelfPtrUseVisitor<'a, 'tcx> {
+    m: FieldMatcher<'a, 'tcx>,
+    /// Self-pointer fields, mapped to the fields they point into.
+    ptrs: &'a HashMap<DefId, DefId>,
+    pub uses: Vec<(DefId, SelfPtrUse)>,
+}
+
+impl<'a, 'tcx> SelfPtrUseVisitor<'a, 'tcx> {
+    /// If `ex` reads one of the `ptrs`, return the field and the local that holds the struct.
+    fn ptr(&self, ex: &'tcx Expr<'tcx>) -> Option<(DefId, Option<HirId>, Span)> {
+        self.m
+            .field(ex)
+            .filter(|&(field, ..)| self.ptrs.contains_key(&field))
+    }
+
+    /// If `ex` is an offset from one of the `ptrs`, as in `(*p).cur.offset(n)`, return the field
+    /// access `(*p).cur` and the offset `n`.
+    fn ptr_offset(&self, ex: &'tcx Expr<'tcx>) -> Option<(&'tcx Expr<'tcx>, &'tcx Expr<'tcx>)> {
+        match ex.kind {
+            ExprKind::MethodCall(seg, [base, delta], _)
+                if matches!(seg.ident.as_str(), "offset" | "add") && self.ptr(base).is_some() =>
+            {
+                Some((base, delta))
+            }
+            _ => None,
+        }
+    }
+
+    /// The struct that contains the field `did`.
+    fn adt(&self, did: DefId) -> DefId {
+        self.m.tcx.parent(did)
+    }
+
+    /// Record an unsupported use of the self-pointer `field`.
+    fn other(&mut self, field: DefId, span: Span) {
+        debug!("unsupported use of self-pointer {field:?} at {span:?}");
+        self.uses.push((self.adt(field), SelfPtrUse::Other));
+    }
+
+    /// Handle `lhs = rhs`, where `lhs` is the self-pointer `ptr` of the struct held in `owner`.
+    fn visit_store(&mut self, ptr: DefId, owner: Option<HirId>, rhs: &'tcx Expr<'tcx>) {
+        let adt = self.adt(ptr);
+        let ptrs = self.ptrs;
+        let target = ptrs[&ptr];
+        // Check that `field` of the struct held in `other_owner` points into `target` of the
+        // same struct instance, or is `target` itself.
+        let same_struct = |field: DefId, other_owner: Option<HirId>| {
+            owner.is_some()
+                && other_owner == owner
+                && ptrs.get(&field).copied().unwrap_or(field) == target
+        };
+
+        if self.m.is_null(rhs) {
+            // `(*p).cur = ptr::null_mut()`
+            self.uses.push((adt, SelfPtrUse::Null { expr: rhs.span }));
+        } else if let Some((field, other_owner, index)) = self.m.addr(rhs) {
+            // `(*p).cur = (*p).buf.as_mut_ptr()`
+            if !same_struct(field, other_owner) || ptrs.contains_key(&field) {
+                self.other(ptr, rhs.span);
+                return;
+            }
+            self.uses.push((
+                adt,
+                SelfPtrUse::Addr {
+                    expr: rhs.span,
+                    index: index.map(|i| i.span),
+                },
+            ));
+            if let Some(index) = index {
+                self.visit_expr(index);
+            }
+        } else if let Some((base, delta)) = self.ptr_offset(rhs) {
+            // `(*p).cur = (*p).cur.offset(n)`
+            let (field, other_owner, _) = self.ptr(base).unwrap();
+            if !same_struct(field, other_owner) {
+                self.other(ptr, rhs.span);
+                return;
+            }
+            self.uses.push((
+                adt,
+                SelfPtrUse::Advance {
+                    expr: rhs.span,
+                    ptr: base.span,
+                    delta: delta.span,
+                },
+            ));
+            self.visit_expr(delta);
+        } else if let Some((field, other_owner, _)) = self.ptr(rhs) {
+            // `(*p).start = (*p).cur`.  Both are indices into the same field, so nothing changes.
+            if !same_struct(field, other_owner) {
+                self.other(ptr, rhs.span);
+            }
+        } else {
+            self.other(ptr, rhs.span);
+        }
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for SelfPtrUseVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            ExprKind::Assign(lhs, rhs, _) => {
+                if let Some((ptr, owner, _)) = self.ptr(lhs) {
+                    self.visit_store(ptr, owner, rhs);
+                    return;
+                }
+            }
+
+            // `*(*p).cur`, `*(*p).cur.offset(n)`
+            ExprKind::Unary(UnOp::Deref, inner) => {
+                let (field, delta) = match self.ptr_offset(inner) {
+                    Some((base, delta)) => (base, Some(delta)),
+                    None => (inner, None),
+                };
+                if let Some((ptr, _, ident)) = self.ptr(field) {
+                    self.uses.push((
+                        self.adt(ptr),
+                        SelfPtrUse::Deref {
+                            expr: ex.span,
+                            field: field.span,
+                            ident,
+                            target: self.ptrs[&ptr],
+                            delta: delta.map(|d| d.span),
+                        },
+                    ));
+                    if let Some(delta) = delta {
+                        self.visit_expr(delta);
+                    }
+                    return;
+                }
+            }
+
+            // `(*p).cur.offset_from((*p).buf.as_ptr())`
+            ExprKind::MethodCall(seg, [base, arg], _) if seg.ident.as_str() == "offset_from" => {
+                if let Some((ptr, owner, _)) = self.ptr(base) {
+                    match self.m.addr(arg) {
+                        Some((field, other_owner, None))
+                            if field == self.ptrs[&ptr]
+                                && owner.is_some()
+                                && other_owner == owner =>
+                        {
+                            self.uses.push((
+                                self.adt(ptr),
+                                SelfPtrUse::Distance {
+                                    expr: ex.span,
+                                    ptr: base.span,
+                                },
+                            ));
+                        }
+                        _ => self.other(ptr, ex.span),
+                    }
+                    return;
+                }
+            }
+
+            // `(*p).cur < (*p).end`.  Comparing two indices into the same field gives the same
+            // result as comparing the pointers.
+            ExprKind::Binary(op, a, b)
+                if matches!(
+                    op.node,
+                    BinOpKind::Eq
+                        | BinOpKind::Ne
+                        | BinOpKind::Lt
+                        | BinOpKind::Le
+                        | BinOpKind::Gt
+                        | BinOpKind::Ge
+                ) =>
+            {
+                if let (Some((pa, oa, _)), Some((pb, ob, _))) = (self.ptr(a), self.ptr(b)) {
+                    if self.ptrs[&pa] != self.ptrs[&pb] || oa.is_none() || oa != ob {
+                        self.other(pa, ex.span);
+                    }
+                    return;
+                }
+            }
+
+            // `S { buf: [0; 64], cur: 0 as *mut u8 }`
+            ExprKind::Struct(_, fields, base) => {
+                let adt_def = match *self.m.typeck_results.expr_ty(ex).kind() {
+                    TyKind::Adt(adt_def, _) if self.m.structs.contains(&adt_def.did()) => adt_def,
+                    _ => {
+                        intravisit::walk_expr(self, ex);
+                        return;
+                    }
+                };
+                for field in fields {
+                    let did = self
+                        .m
+                        .typeck_results
+                        .field_indices()
+                        .get(field.hir_id)
+                        .and_then(|&idx| adt_def.non_enum_variant().fields.get(idx))
+                        .map(|f| f.did);
+                    match did {
+                        Some(did) if self.ptrs.contains_key(&did) => {
+                            if self.m.is_null(field.expr) {
+                                self.uses.push((
+                                    adt_def.did(),
+                                    SelfPtrUse::Null {
+                                        expr: field.expr.span,
+                                    },
+                                ));
+                            } else {
+                                self.other(did, field.expr.span);
+                            }
+                        }
+                        _ => self.visit_expr(field.expr),
+                    }
+                }
+                if let Some(base) = base {
+                    // The copied pointers would point into the other struct.
+                    if let Some(&ptr) = adt_def
+                        .all_fields()
+                        .map(|f| &f.did)
+                        .find(|did| self.ptrs.contains_key(did))
+                    {
+                        self.other(ptr, ex.span);
+                    }
+                    self.visit_expr(base);
+                }
+                return;
+            }
+
+            // Any other access, such as `&mut (*p).cur` or `f((*p).cur)`.
+            ExprKind::Field(..) => {
+                if let Some((ptr, ..)) = self.ptr(ex) {
+                    self.other(ptr, ex.span);
+                    return;
+                }
+            }
+
+            _ => {}
+        }
+
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+fn local_var(ex: &Expr) -> Option<HirId> {
+    match ex.kind {
+        ExprKind::Path(hir::QPath::Resolved(_, path)) => match path.res {
+            Res::Local(hir_id) => Some(hir_id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn peel_casts<'a, 'tcx>(mut ex: &'a Expr<'tcx>) -> &'a Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+/// Run `SelfPtrUseVisitor` over the body of `ldid`.
+pub fn collect_self_ptr_uses(
+    tcx: TyCtxt,
+    ldid: LocalDefId,
+    ptrs: &HashMap<DefId, DefId>,
+) -> Vec<(DefId, SelfPtrUse)> {
+    let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+    let structs = ptrs
+        .keys()
+        .map(|&did| tcx.parent(did))
+        .collect::<HashSet<_>>();
+    let mut v = SelfPtrUseVisitor {
+        m: FieldMatcher {
+            tcx,
+            typeck_results: tcx.typeck_body(hir_body_id),
+            structs: &structs,
+        },
+        ptrs,
+        uses: Vec::new(),
+    };
+    v.visit_body(tcx.hir().body(hir_body_id));
+    v.uses
+}
+
+/// Check whether the pointer field `ptr` can be rewritten to an index into the field `target`:
+/// `target` must be an array of the type that `ptr` points to.
+fn is_index_compatible(tcx: TyCtxt, ptr: DefId, target: DefId) -> bool {
+    let pointee = match *tcx.type_of(ptr).kind() {
+        TyKind::RawPtr(tm) => tm.ty,
+        _ => return false,
+    };
+    match *tcx.type_of(target).kind() {
+        TyKind::Array(elem, _) => elem == pointee,
+        _ => false,
+    }
+}
+
+/// Find the self-pointer fields of the structs in `index_candidates` and `pin_candidates`, which
+/// were selected for the index and pin strategies respectively.
+pub fn find_self_ref_structs(
+    gacx: &GlobalAnalysisCtxt,
+    index_candidates: &HashSet<DefId>,
+    pin_candidates: &HashSet<DefId>,
+) -> SelfRefInfo {
+    let tcx = gacx.tcx;
+
+    let mut candidates = HashMap::new();
+    for (&did, strategy) in index_candidates
+        .iter()
+        .map(|did| (did, SelfRefStrategy::Index))
+        .chain(pin_candidates.iter().map(|did| (did, SelfRefStrategy::Pin)))
+    {
+        if !did.is_local() || !matches!(tcx.def_kind(did), DefKind::Struct) {
+            debug!("self-referential struct candidate {did:?} is not a local struct");
+            continue;
+        }
+        if gacx.foreign_mentioned_tys.contains(&did) {
+            debug!("self-referential struct candidate {did:?} is used in FFI");
+            continue;
+        }
+        if candidates.insert(did, strategy).is_some() {
+            panic!("struct {did:?} was selected for both self-referential strategies");
+        }
+    }
+    if candidates.is_empty() {
+        return SelfRefInfo::default();
+    }
+
+    // Find the self-pointer fields by looking for stores of addresses within the same struct.
+    let candidate_set = candidates.keys().copied().collect::<HashSet<_>>();
+    let mut targets = HashMap::<DefId, HashSet<DefId>>::new();
+    for ldid in tcx.hir().body_owners() {
+        let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+            Some(x) => x,
+            None => continue,
+        };
+        let mut v = SelfPtrStoreVisitor {
+            m: FieldMatcher {
+                tcx,
+                typeck_results: tcx.typeck_body(hir_body_id),
+                structs: &candidate_set,
+            },
+            stores: Vec::new(),
+        };
+        v.visit_body(tcx.hir().body(hir_body_id));
+        for (ptr, target) in v.stores {
+            targets.entry(ptr).or_default().insert(target);
+        }
+    }
+
+    let mut info = SelfRefInfo::default();
+    for (did, strategy) in candidates {
+        let mut ptrs = HashMap::new();
+        let mut ok = true;
+        for field in tcx.adt_def(did).all_fields() {
+            let field_targets = match targets.get(&field.did) {
+                Some(x) => x,
+                None => continue,
+            };
+            if field_targets.len() != 1
+                || targets.contains_key(field_targets.iter().next().unwrap())
+            {
+                debug!(
+                    "self-pointer {:?} points into more than one field: {:?}",
+                    field.did, field_targets
+                );
+                ok = false;
+                break;
+            }
+            let target = *field_targets.iter().next().unwrap();
+            if strategy == SelfRefStrategy::Index && !is_index_compatible(tcx, field.did, target) {
+                debug!(
+                    "self-pointer {:?} can't become an index into {:?}",
+                    field.did, target
+                );
+                ok = false;
+                break;
+            }
+            ptrs.insert(field.did, target);
+        }
+        if !ok || ptrs.is_empty() {
+            debug!("no usable self-pointers in {did:?}");
+            continue;
+        }
+        info.structs.insert(did, SelfRefStruct { strategy, ptrs });
+    }
+
+    // Check that every use of the index strategy's pointers is supported.
+    let ptrs = info.index_ptrs();
+    let mut fn_adts = HashMap::new();
+    for ldid in tcx.hir().body_owners() {
+        let mut adts = HashSet::new();
+        for (adt, u) in collect_self_ptr_uses(tcx, ldid, &ptrs) {
+            if let SelfPtrUse::Other = u {
+                if info.structs.remove(&adt).is_some() {
+                    debug!("unsupported use of self-pointers in {adt:?}");
+                }
+            }
+            adts.insert(adt);
+        }
+        if !adts.is_empty() {
+            fn_adts.insert(ldid, adts);
+        }
+    }
+
+    for (did, s) in &info.structs {
+        debug!(
+            "found self-referential struct {did:?}, using {:?}, with pointers {:?}",
+            s.strategy, s.ptrs
+        );
+    }
+    for (ldid, adts) in fn_adts {
+        if !matches!(tcx.def_kind(ldid), DefKind::Fn | DefKind::AssocFn) {
+            continue;
+        }
+        if adts.iter().any(|did| info.structs.contains_key(did)) {
+            info.fns.insert(ldid.to_def_id());
+        }
+    }
+    info
+}
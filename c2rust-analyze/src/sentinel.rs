@@ -0,0 +1,146 @@
+//! Detection of function parameters that point to sentinel-terminated arrays.
+//!
+//! C code often passes arrays whose end is marked by a null pointer or a zero element instead of
+//! an explicit length, like `argv`:
+//!
+//! ```ignore
+//! unsafe fn count_args(argv: *mut *mut c_char) -> usize {
+//!     let mut n = 0;
+//!     while !(*argv.offset(n as isize)).is_null() {
+//!         n += 1;
+//!     }
+//!     n
+//! }
+//! ```
+//!
+//! When `argv` is rewritten to a slice, a caller that only has a raw pointer doesn't know how long
+//! the slice should be.  For parameters found here, such a caller scans for the sentinel to find
+//! the length, and passes a slice that includes the sentinel, so the callee's own loop stays in
+//! bounds (see `mir_op::CastBuilder`).
+//!
+//! A parameter is considered sentinel-terminated if the function compares some element of it
+//! against the sentinel: `(*p).is_null()`, `(*p.offset(i)).is_null()`, `*p.add(i) == 0`, and so
+//! on.
+
+use log::debug;
+use rustc_ast::LitKind;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{BinOpKind, Expr, ExprKind, HirId, PatKind, QPath, UnOp};
+use rustc_middle::ty::{TyCtxt, TyKind};
+use std::collections::HashSet;
+
+fn strip_casts<'tcx>(mut ex: &'tcx Expr<'tcx>) -> &'tcx Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+fn is_zero(ex: &Expr) -> bool {
+    match strip_casts(ex).kind {
+        ExprKind::Lit(ref lit) => matches!(lit.node, LitKind::Int(0, _)),
+        _ => false,
+    }
+}
+
+/// If `ex` reads an element of the array pointed to by a local, as in `*p` or `*p.offset(i)`,
+/// return the local.
+fn deref_elem_base(ex: &Expr) -> Option<HirId> {
+    let ptr = match strip_casts(ex).kind {
+        ExprKind::Unary(UnOp::Deref, ptr) => ptr,
+        _ => return None,
+    };
+    let base = match ptr.kind {
+        ExprKind::MethodCall(ps, [base, _], _)
+            if matches!(
+                ps.ident.as_str(),
+                "offset" | "add" | "wrapping_offset" | "wrapping_add"
+            ) =>
+        {
+            base
+        }
+        _ => ptr,
+    };
+    match base.kind {
+        ExprKind::Path(QPath::Resolved(None, path)) => match path.res {
+            Res::Local(id) => Some(id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Collects the locals whose elements are compared against a sentinel.
+struct SentinelVisitor {
+    checked: HashSet<HirId>,
+}
+
+impl<'tcx> Visitor<'tcx> for SentinelVisitor {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            // `(*p).is_null()`
+            ExprKind::MethodCall(ps, [recv], _) if ps.ident.as_str() == "is_null" => {
+                self.checked.extend(deref_elem_base(recv));
+            }
+            // `*p == 0`
+            ExprKind::Binary(op, a, b) if matches!(op.node, BinOpKind::Eq | BinOpKind::Ne) => {
+                if is_zero(b) {
+                    self.checked.extend(deref_elem_base(a));
+                } else if is_zero(a) {
+                    self.checked.extend(deref_elem_base(b));
+                }
+            }
+            _ => {}
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Find the parameters of `all_fn_ldids` that point to sentinel-terminated arrays.  Each entry is
+/// a function and the index of one of its parameters.
+pub fn find_sentinel_params(tcx: TyCtxt, all_fn_ldids: &[LocalDefId]) -> HashSet<(DefId, usize)> {
+    let mut params = HashSet::new();
+    for &ldid in all_fn_ldids {
+        let did = ldid.to_def_id();
+        if !matches!(tcx.def_kind(did), DefKind::Fn | DefKind::AssocFn) {
+            continue;
+        }
+        let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+            Some(x) => x,
+            None => continue,
+        };
+        let body = tcx.hir().body(hir_body_id);
+        let mut v = SentinelVisitor {
+            checked: HashSet::new(),
+        };
+        v.visit_body(body);
+        if v.checked.is_empty() {
+            continue;
+        }
+
+        let inputs = tcx.fn_sig(did).skip_binder().inputs();
+        for (i, param) in body.params.iter().enumerate() {
+            if !matches!(param.pat.kind, PatKind::Binding(..)) {
+                continue;
+            }
+            if !v.checked.contains(&param.pat.hir_id) {
+                continue;
+            }
+            // Only pointers and integers have a sentinel value.
+            let is_sentinel_array = match *inputs[i].kind() {
+                TyKind::RawPtr(tm) => matches!(
+                    *tm.ty.kind(),
+                    TyKind::RawPtr(_) | TyKind::Int(_) | TyKind::Uint(_)
+                ),
+                _ => false,
+            };
+            if is_sentinel_array {
+                debug!("{did:?}: parameter {i} is sentinel-terminated");
+                params.insert((did, i));
+            }
+        }
+    }
+    params
+}
@@ -0,0 +1,262 @@
+//! Saving and loading analysis results with stable pointer keys.
+//!
+//! `PointerId`s are assigned in the order the analysis visits types, so an unrelated change to the
+//! crate can give the same pointer a different ID.  Instead, this module identifies each global
+//! pointer by the `DefPathHash` of the item whose type contains it, the kind of type (a function
+//! signature, a field, or a static), and the pointer's position within that type.  These keys
+//! stay the same between runs as long as the item itself is unchanged.
+//!
+//! When `C2RUST_ANALYZE_SAVE_ANALYSIS_FACTS` names a directory, the final permissions and flags
+//! of the crate's global pointers are saved there.  When `C2RUST_ANALYZE_LOAD_ANALYSIS_FACTS`
+//! names a directory containing facts from an earlier run, the saved permissions are used as the
+//! starting point for the analysis, and the final results are compared against them.
+
+use crate::context::{FlagSet, GlobalAnalysisCtxt, GlobalAssignment, PermissionSet};
+use crate::crate_facts;
+use crate::pointer_id::{GlobalPointerTable, PointerId};
+use rustc_hir::def_id::{DefId, LOCAL_CRATE};
+use rustc_middle::ty::TyCtxt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// The kind of type that contains a global pointer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum PointerOwnerKind {
+    /// The signature of a function, in the order given by `crate_facts::sig_pointers`.
+    FnSig,
+    /// The type of a struct field.
+    Field,
+    /// The type of a static.
+    Static,
+    /// The pointer produced by taking the address of a static.
+    AddrOfStatic,
+}
+
+/// A key for a global pointer that doesn't depend on the order in which `PointerId`s are
+/// assigned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct StablePointerId {
+    /// The `DefPathHash` of the owning item.
+    pub owner: (u64, u64),
+    pub kind: PointerOwnerKind,
+    /// The position of the pointer among all positions of the owner's type, including
+    /// non-pointer positions.
+    pub index: u32,
+}
+
+/// The final permissions and flags of the global pointers of a crate.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AnalysisFacts {
+    /// The def path of each owner, for printing.
+    pub names: BTreeMap<(u64, u64), String>,
+    pub perms: BTreeMap<StablePointerId, PermissionSet>,
+    pub flags: BTreeMap<StablePointerId, FlagSet>,
+}
+
+/// Get the directory to save analysis facts in, if one was set.
+pub fn save_dir() -> Option<PathBuf> {
+    env::var_os("C2RUST_ANALYZE_SAVE_ANALYSIS_FACTS").map(PathBuf::from)
+}
+
+/// Get the directory to load analysis facts from, if one was set.
+pub fn load_dir() -> Option<PathBuf> {
+    env::var_os("C2RUST_ANALYZE_LOAD_ANALYSIS_FACTS").map(PathBuf::from)
+}
+
+/// Assign a `StablePointerId` to every global pointer that appears in the type of an item.
+pub fn stable_pointer_ids(
+    gacx: &GlobalAnalysisCtxt,
+) -> GlobalPointerTable<Option<StablePointerId>> {
+    let tcx = gacx.tcx;
+    let mut ids = GlobalPointerTable::new(gacx.num_pointers());
+    let mut add = |did: DefId, kind, ptrs: &mut dyn Iterator<Item = PointerId>| {
+        let owner = crate_facts::def_path_hash(tcx, did);
+        for (i, ptr) in ptrs.enumerate() {
+            if !ptr.is_global() {
+                continue;
+            }
+            ids[ptr] = Some(StablePointerId {
+                owner,
+                kind,
+                index: u32::try_from(i).unwrap(),
+            });
+        }
+    };
+
+    for (&did, lsig) in &gacx.fn_sigs {
+        let ptrs = crate_facts::sig_pointers(lsig);
+        add(did, PointerOwnerKind::FnSig, &mut ptrs.into_iter());
+    }
+    for (&did, &lty) in &gacx.field_ltys {
+        add(
+            did,
+            PointerOwnerKind::Field,
+            &mut lty.iter().map(|lty| lty.label),
+        );
+    }
+    for (&did, &lty) in &gacx.static_tys {
+        add(
+            did,
+            PointerOwnerKind::Static,
+            &mut lty.iter().map(|lty| lty.label),
+        );
+    }
+    for (&did, &ptr) in &gacx.addr_of_static {
+        add(did, PointerOwnerKind::AddrOfStatic, &mut [ptr].into_iter());
+    }
+    ids
+}
+
+/// Convert `table` into a map keyed by `StablePointerId`.  Pointers that have no stable ID are
+/// omitted.
+pub fn to_stable<T: Clone>(
+    table: &GlobalPointerTable<T>,
+    ids: &GlobalPointerTable<Option<StablePointerId>>,
+) -> BTreeMap<StablePointerId, T> {
+    table
+        .iter()
+        .filter_map(|(ptr, x)| Some((ids[ptr]?, x.clone())))
+        .collect()
+}
+
+/// Collect the final facts for the global pointers of the current crate.
+pub fn collect_analysis_facts(gacx: &GlobalAnalysisCtxt, gasn: &GlobalAssignment) -> AnalysisFacts {
+    let tcx = gacx.tcx;
+    let ids = stable_pointer_ids(gacx);
+
+    let mut names = BTreeMap::new();
+    let owners = gacx
+        .fn_sigs
+        .keys()
+        .chain(gacx.field_ltys.keys())
+        .chain(gacx.static_tys.keys());
+    for &did in owners {
+        names.insert(crate_facts::def_path_hash(tcx, did), tcx.def_path_str(did));
+    }
+
+    AnalysisFacts {
+        names,
+        perms: to_stable(&gasn.perms, &ids),
+        flags: to_stable(&gasn.flags, &ids),
+    }
+}
+
+/// Use the permissions in `facts` as the starting point for the matching pointers in `gasn`.
+/// `FIXED` pointers are left alone, since their permissions are set by other means.  Returns the
+/// number of pointers that were updated.
+pub fn apply_analysis_facts(
+    gacx: &GlobalAnalysisCtxt,
+    gasn: &mut GlobalAssignment,
+    facts: &AnalysisFacts,
+) -> usize {
+    let ids = stable_pointer_ids(gacx);
+    let mut count = 0;
+    for (ptr, id) in ids.iter() {
+        let perms = match id.and_then(|id| facts.perms.get(&id)) {
+            Some(&x) => x,
+            None => continue,
+        };
+        if gasn.flags[ptr].contains(FlagSet::FIXED) {
+            continue;
+        }
+        gasn.perms[ptr] = perms;
+        count += 1;
+    }
+    count
+}
+
+/// A difference between two sets of facts for the same pointer.  `None` means the pointer is
+/// missing from that side.
+pub struct FactChange<'a> {
+    pub name: &'a str,
+    pub id: StablePointerId,
+    pub old: Option<(PermissionSet, FlagSet)>,
+    pub new: Option<(PermissionSet, FlagSet)>,
+}
+
+impl fmt::Display for FactChange<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:?}#{}: ", self.name, self.id.kind, self.id.index)?;
+        match self.old {
+            Some((perms, flags)) => write!(f, "{perms:?} {flags:?}")?,
+            None => write!(f, "(none)")?,
+        }
+        write!(f, " -> ")?;
+        match self.new {
+            Some((perms, flags)) => write!(f, "{perms:?} {flags:?}"),
+            None => write!(f, "(none)"),
+        }
+    }
+}
+
+/// List the pointers whose permissions or flags differ between `old` and `new`.
+pub fn diff_analysis_facts<'a>(
+    old: &'a AnalysisFacts,
+    new: &'a AnalysisFacts,
+) -> Vec<FactChange<'a>> {
+    let get = |facts: &AnalysisFacts, id: &StablePointerId| {
+        let perms = *facts.perms.get(id)?;
+        let flags = facts.flags.get(id).copied().unwrap_or_default();
+        Some((perms, flags))
+    };
+    let mut ids = old.perms.keys().chain(new.perms.keys()).collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+
+    let mut changes = Vec::new();
+    for &id in ids {
+        let (old_val, new_val) = (get(old, &id), get(new, &id));
+        if old_val == new_val {
+            continue;
+        }
+        let name = new
+            .names
+            .get(&id.owner)
+            .or_else(|| old.names.get(&id.owner))
+            .map_or("?", |s| s.as_str());
+        changes.push(FactChange {
+            name,
+            id,
+            old: old_val,
+            new: new_val,
+        });
+    }
+    changes
+}
+
+fn facts_path(tcx: TyCtxt, dir: &Path) -> PathBuf {
+    dir.join(format!(
+        "{}-{:016x}.analysis",
+        tcx.crate_name(LOCAL_CRATE),
+        tcx.stable_crate_id(LOCAL_CRATE).to_u64()
+    ))
+}
+
+/// Load the facts for the current crate from `dir`, if a readable file for it exists.
+pub fn load_analysis_facts(tcx: TyCtxt, dir: &Path) -> Option<AnalysisFacts> {
+    let path = facts_path(tcx, dir);
+    let f = BufReader::new(File::open(&path).ok()?);
+    match bincode::deserialize_from(f) {
+        Ok(x) => Some(x),
+        Err(e) => {
+            log::warn!("failed to parse analysis facts file {path:?}: {e}");
+            None
+        }
+    }
+}
+
+/// Save the facts for the current crate in `dir`.
+pub fn save_analysis_facts(
+    tcx: TyCtxt,
+    dir: &Path,
+    facts: &AnalysisFacts,
+) -> Result<(), bincode::Error> {
+    fs::create_dir_all(dir)?;
+    let f = BufWriter::new(File::create(facts_path(tcx, dir))?);
+    bincode::serialize_into(f, facts)
+}
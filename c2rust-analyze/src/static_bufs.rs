@@ -0,0 +1,357 @@
+//! Detection of functions that return a pointer into a `static` buffer.
+//!
+//! C functions sometimes format their result into a static buffer and return a pointer to it,
+//! which is only valid until the next call:
+//!
+//! ```ignore
+//! static mut BUF: [c_char; 32] = [0; 32];
+//!
+//! unsafe fn describe(x: c_int) -> *mut c_char {
+//!     sprintf(BUF.as_mut_ptr(), b"value %d\0" as *const u8 as *const c_char, x);
+//!     return BUF.as_mut_ptr();
+//! }
+//! ```
+//!
+//! This is opt-in: a function is considered only if it's listed in the file named by
+//! `C2RUST_ANALYZE_STATIC_BUF_FNS_LIST` or marked `#[c2rust_analyze_test::static_buf]`.  If every
+//! value it returns points to the start of the same static array, it's rewritten to return an
+//! owned copy of the NUL-terminated string in the buffer, as a `Vec<u8>` that includes the
+//! terminator.  If the static is immutable, the function returns `&'static [T]` instead, with no
+//! copying.
+//!
+//! Callers are rewritten to match.  Only calls whose result is passed directly to a foreign
+//! function are supported; these become `describe(x).as_ptr() as *mut c_char`, which is valid
+//! because the temporary lives until the end of the enclosing statement.
+
+use log::debug;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{Body, BorrowKind, Expr, ExprKind, HirId, Mutability, QPath};
+use rustc_middle::ty::{IntTy, Ty, TyCtxt, TyKind, TypeckResults, UintTy};
+use rustc_span::Span;
+use std::collections::{HashMap, HashSet};
+
+/// A function that returns a pointer into a static buffer.
+#[derive(Clone, Copy, Debug)]
+pub struct StaticBufFn<'tcx> {
+    /// The static array that the function returns a pointer to.
+    pub static_did: DefId,
+    /// Whether the static is declared `static mut`.  If not, the function returns a `&'static`
+    /// slice; otherwise, it returns a `Vec<u8>`.
+    pub mutbl: Mutability,
+    /// The element type of the static array.
+    pub elem_ty: Ty<'tcx>,
+    /// The original return type of the function, which call sites cast back to.
+    pub ret_ty: Ty<'tcx>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct StaticBufInfo<'tcx> {
+    pub fns: HashMap<DefId, StaticBufFn<'tcx>>,
+    /// Every function that calls one of `fns`.
+    pub callers: HashSet<DefId>,
+}
+
+/// A use of a function that returns a pointer into a static buffer, or a value returned from one.
+#[derive(Clone, Copy, Debug)]
+pub enum StaticBufUse {
+    /// A returned pointer, as in `return BUF.as_mut_ptr()`.  `static_path` is the path that names
+    /// the static.
+    Return { expr: Span, static_path: Span },
+    /// A call whose result is passed to a foreign function, as in `puts(describe(x))`.
+    Call { call: Span, callee: DefId },
+}
+
+fn strip_casts<'tcx>(mut ex: &'tcx Expr<'tcx>) -> &'tcx Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+/// If `ex` is a path that names a local static, return the static.
+fn static_path(ex: &Expr) -> Option<DefId> {
+    match ex.kind {
+        ExprKind::Path(QPath::Resolved(None, path)) => match path.res {
+            Res::Def(DefKind::Static(_), did) if did.is_local() => Some(did),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// If `ex` produces a pointer to the start of a static, as in `BUF.as_mut_ptr()` or `&mut BUF as
+/// *mut _ as *mut c_char`, return the static and the path that names it.
+fn static_buf_ptr(ex: &Expr) -> Option<(DefId, Span)> {
+    let ex = strip_casts(ex);
+    let base = match ex.kind {
+        ExprKind::MethodCall(ps, [recv], _)
+            if matches!(ps.ident.as_str(), "as_ptr" | "as_mut_ptr") =>
+        {
+            recv
+        }
+        ExprKind::AddrOf(BorrowKind::Ref, _, inner) => inner,
+        _ => return None,
+    };
+    Some((static_path(base)?, base.span))
+}
+
+/// If `ex` is a direct call to a function in `fns`, return the function.
+fn static_buf_callee<K>(
+    typeck_results: &TypeckResults,
+    ex: &Expr,
+    fns: &HashMap<DefId, K>,
+) -> Option<DefId> {
+    let func = match ex.kind {
+        ExprKind::Call(func, _) => func,
+        _ => return None,
+    };
+    let qpath = match func.kind {
+        ExprKind::Path(ref qpath) => qpath,
+        _ => return None,
+    };
+    match typeck_results.qpath_res(qpath, func.hir_id) {
+        Res::Def(DefKind::Fn, did) if fns.contains_key(&did) => Some(did),
+        _ => None,
+    }
+}
+
+/// Collects the values returned from a function.
+struct ReturnVisitor<'tcx> {
+    returns: Vec<&'tcx Expr<'tcx>>,
+    ok: bool,
+}
+
+impl<'tcx> Visitor<'tcx> for ReturnVisitor<'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            ExprKind::Ret(Some(value)) => self.returns.push(value),
+            ExprKind::Ret(None) => self.ok = false,
+            _ => {}
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Check whether every value returned from `ldid` points to the same static buffer.  If so,
+/// return the function's description along with its `return` uses.
+fn find_static_buf<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ldid: LocalDefId,
+) -> Option<(StaticBufFn<'tcx>, Vec<StaticBufUse>)> {
+    let did = ldid.to_def_id();
+    if !matches!(tcx.def_kind(did), DefKind::Fn) {
+        return None;
+    }
+    let ret_ty = tcx.fn_sig(did).skip_binder().output();
+    if !ret_ty.is_unsafe_ptr() {
+        return None;
+    }
+    let hir_body_id = tcx.hir().maybe_body_owned_by(ldid)?;
+    let body: &Body = tcx.hir().body(hir_body_id);
+
+    let mut v = ReturnVisitor {
+        returns: Vec::new(),
+        ok: true,
+    };
+    v.visit_body(body);
+    if let ExprKind::Block(block, None) = body.value.kind {
+        if let Some(tail) = block.expr {
+            v.returns.push(tail);
+        }
+    }
+    if !v.ok || v.returns.is_empty() {
+        return None;
+    }
+
+    let mut static_did = None;
+    let mut uses = Vec::new();
+    for ex in v.returns {
+        let (sdid, path) = match static_buf_ptr(ex) {
+            Some(x) => x,
+            None => {
+                debug!(
+                    "{did:?}: return value at {:?} is not a static buffer",
+                    ex.span
+                );
+                return None;
+            }
+        };
+        if *static_did.get_or_insert(sdid) != sdid {
+            debug!("{did:?}: returns pointers to more than one static");
+            return None;
+        }
+        uses.push(StaticBufUse::Return {
+            expr: ex.span,
+            static_path: path,
+        });
+    }
+    let static_did = static_did?;
+
+    let mutbl = match tcx.def_kind(static_did) {
+        DefKind::Static(mutbl) => mutbl,
+        _ => return None,
+    };
+    let elem_ty = match *tcx.type_of(static_did).kind() {
+        TyKind::Array(elem_ty, _) => elem_ty,
+        _ => return None,
+    };
+    // An owned copy is made by reading a NUL-terminated string out of the buffer.
+    let is_char = matches!(
+        *elem_ty.kind(),
+        TyKind::Int(IntTy::I8) | TyKind::Uint(UintTy::U8)
+    );
+    if mutbl == Mutability::Mut && !is_char {
+        return None;
+    }
+
+    Some((
+        StaticBufFn {
+            static_did,
+            mutbl,
+            elem_ty,
+            ret_ty,
+        },
+        uses,
+    ))
+}
+
+/// Collects calls to functions returning static buffers, and other references to them.
+struct CallVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    fns: &'a HashMap<DefId, StaticBufFn<'tcx>>,
+    /// Calls that are passed directly to a foreign function, and so can be rewritten.
+    allowed_calls: HashSet<HirId>,
+    /// The callee expressions of all calls.
+    callees: HashSet<HirId>,
+    uses: Vec<StaticBufUse>,
+    rejected: HashSet<DefId>,
+}
+
+impl<'a, 'tcx> CallVisitor<'a, 'tcx> {
+    fn is_foreign_call(&self, ex: &Expr) -> bool {
+        let func = match ex.kind {
+            ExprKind::Call(func, _) => func,
+            _ => return false,
+        };
+        match func.kind {
+            ExprKind::Path(ref qpath) => match self.typeck_results.qpath_res(qpath, func.hir_id) {
+                Res::Def(DefKind::Fn, did) => self.tcx.is_foreign_item(did),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for CallVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            ExprKind::Call(func, args) => {
+                self.callees.insert(func.hir_id);
+                if self.is_foreign_call(ex) {
+                    for arg in args {
+                        self.allowed_calls.insert(strip_casts(arg).hir_id);
+                    }
+                }
+                if let Some(callee) = static_buf_callee(self.typeck_results, ex, self.fns) {
+                    if self.allowed_calls.contains(&ex.hir_id) && !ex.span.from_expansion() {
+                        self.uses.push(StaticBufUse::Call {
+                            call: ex.span,
+                            callee,
+                        });
+                    } else {
+                        debug!("{callee:?}: unsupported call at {:?}", ex.span);
+                        self.rejected.insert(callee);
+                    }
+                }
+            }
+            ExprKind::Path(ref qpath) if !self.callees.contains(&ex.hir_id) => {
+                if let Res::Def(DefKind::Fn, did) = self.typeck_results.qpath_res(qpath, ex.hir_id)
+                {
+                    if self.fns.contains_key(&did) {
+                        debug!("{did:?}: used other than by a direct call at {:?}", ex.span);
+                        self.rejected.insert(did);
+                    }
+                }
+            }
+            _ => {}
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+fn collect_calls_and_rejects<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ldid: LocalDefId,
+    fns: &HashMap<DefId, StaticBufFn<'tcx>>,
+) -> (Vec<StaticBufUse>, HashSet<DefId>) {
+    let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+        Some(x) => x,
+        None => return (Vec::new(), HashSet::new()),
+    };
+    let mut v = CallVisitor {
+        tcx,
+        typeck_results: tcx.typeck_body(hir_body_id),
+        fns,
+        allowed_calls: HashSet::new(),
+        callees: HashSet::new(),
+        uses: Vec::new(),
+        rejected: HashSet::new(),
+    };
+    v.visit_body(tcx.hir().body(hir_body_id));
+    (v.uses, v.rejected)
+}
+
+/// Collect the uses within the body of `ldid` that need rewriting: its own `return`s, if it's
+/// one of `info.fns`, and calls to any of `info.fns`.
+pub fn collect_static_buf_uses(
+    tcx: TyCtxt,
+    ldid: LocalDefId,
+    info: &StaticBufInfo,
+) -> Vec<StaticBufUse> {
+    let mut uses = Vec::new();
+    if info.fns.contains_key(&ldid.to_def_id()) {
+        let (_, returns) =
+            find_static_buf(tcx, ldid).expect("static buffer should have been found already");
+        uses.extend(returns);
+    }
+    uses.extend(collect_calls_and_rejects(tcx, ldid, &info.fns).0);
+    uses
+}
+
+/// Find the functions among `candidates` that return pointers into static buffers and can be
+/// rewritten to return owned or `&'static` values.
+pub fn find_static_buf_fns<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    all_fn_ldids: &[LocalDefId],
+    candidates: &HashSet<DefId>,
+) -> StaticBufInfo<'tcx> {
+    let mut fns = HashMap::new();
+    for &ldid in all_fn_ldids {
+        if !candidates.contains(&ldid.to_def_id()) {
+            continue;
+        }
+        if let Some((f, _)) = find_static_buf(tcx, ldid) {
+            fns.insert(ldid.to_def_id(), f);
+        }
+    }
+    if fns.is_empty() {
+        return StaticBufInfo::default();
+    }
+
+    let mut callers = HashSet::new();
+    let mut rejected = HashSet::new();
+    for &ldid in all_fn_ldids {
+        let (uses, r) = collect_calls_and_rejects(tcx, ldid, &fns);
+        if !uses.is_empty() {
+            callers.insert(ldid.to_def_id());
+        }
+        rejected.extend(r);
+    }
+    // Rejecting a function only removes some calls, so no other function is affected.
+    fns.retain(|did, _| !rejected.contains(did));
+    StaticBufInfo { fns, callers }
+}
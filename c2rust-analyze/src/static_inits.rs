@@ -0,0 +1,369 @@
+//! Analysis of the initializers of statics that hold pointers, such as global lookup tables.
+//!
+//! Uses of a static inside function bodies constrain the pointers in its type, but nothing else
+//! looks at the values those pointers start out with.  This module walks the initializer of each
+//! static alongside its labeled type and records how each pointer is initialized:
+//!
+//! * A null pointer, as in `0 as *const T` or `ptr::null()`, removes `NON_NULL` from the pointer.
+//! * The address of an immutable static, as in `&X as *const T`, is kept, and becomes `&X` once
+//!   the pointer is rewritten to a reference.
+//! * Anything else, such as the address of a `static mut` or an integer cast to a pointer, can't
+//!   be expressed as a safe reference, so the static is left unchanged.
+//!
+//! Pointers inside struct literals belong to the struct's fields rather than to the static, so an
+//! unsupported value there leaves the field unchanged instead.
+//!
+//! After the analysis, a static or field whose initialized pointers would need to become anything
+//! other than a shared reference is also left unchanged, since `&mut` and `&Cell` can't appear in
+//! a static's initializer.  The rest have their declared types and initializers rewritten by
+//! `rewrite::gen_static_init_rewrites`.
+//!
+//! `const` items that hold pointers are handled the same way, and count as statics throughout.
+//! Every use of a `const` shares its labeled type (`gacx.const_tys`), so a table declared as a
+//! `const` no longer keeps its element types from being rewritten.
+
+use crate::context::{FlagSet, GlobalAnalysisCtxt, GlobalAssignment, LTy};
+use crate::pointer_id::PointerId;
+use crate::type_desc::{self, Ownership, Quantity};
+use crate::util::{self, Callee};
+use log::debug;
+use rustc_ast::LitKind;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::DefId;
+use rustc_hir::{BorrowKind, Expr, ExprKind, ItemKind, Mutability, Node, QPath};
+use rustc_middle::ty::{GenericArgKind, Ty, TyKind, TypeckResults};
+use rustc_span::Span;
+use std::collections::HashSet;
+
+/// The item that a pointer in a static initializer belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InitOwner {
+    /// A pointer in the type of the static or `const` item itself.
+    Static(DefId),
+    /// A pointer in the type of a struct field, initialized by a struct literal.
+    Field(DefId),
+}
+
+/// The value of one pointer in a static initializer.
+#[derive(Clone, Copy, Debug)]
+pub enum InitValue {
+    /// A null pointer.
+    Null,
+    /// The address of an immutable static.  `place` is the span of the path naming the static,
+    /// and `is_array` is set if the static is an array of the pointee type rather than a single
+    /// value.
+    AddrOfStatic { place: Span, is_array: bool },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PtrInit<'tcx> {
+    pub owner: InitOwner,
+    pub ptr: PointerId,
+    /// The declared type of the pointer.
+    pub ptr_ty: Ty<'tcx>,
+    /// The whole expression that produces the pointer, including any casts.
+    pub span: Span,
+    pub value: InitValue,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct StaticInitInfo<'tcx> {
+    /// Statics and `const` items with pointers in their types whose initializers were analyzed.
+    pub statics: HashSet<DefId>,
+    /// Every supported pointer value found in those initializers.
+    pub inits: Vec<PtrInit<'tcx>>,
+    /// Statics and fields with at least one pointer whose value isn't supported.
+    pub unsupported: HashSet<InitOwner>,
+}
+
+struct InitWalker<'a, 'tcx> {
+    gacx: &'a GlobalAnalysisCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    info: &'a mut StaticInitInfo<'tcx>,
+}
+
+impl<'a, 'tcx> InitWalker<'a, 'tcx> {
+    /// Check whether `lty` contains any pointers, either directly or in the fields of the structs
+    /// it mentions.
+    fn has_pointers(&self, lty: LTy<'tcx>) -> bool {
+        !value_ptrs(lty).is_empty() || !self.struct_ptr_fields(lty.ty).is_empty()
+    }
+
+    /// List the fields with pointer types of all structs mentioned in `ty`, including structs
+    /// nested inside other structs.
+    fn struct_ptr_fields(&self, ty: Ty<'tcx>) -> Vec<DefId> {
+        let mut fields = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![ty];
+        while let Some(ty) = stack.pop() {
+            for arg in ty.walk() {
+                let adt_def = match arg.unpack() {
+                    GenericArgKind::Type(ty) => match *ty.kind() {
+                        TyKind::Adt(adt_def, _) => adt_def,
+                        _ => continue,
+                    },
+                    _ => continue,
+                };
+                if !seen.insert(adt_def.did()) {
+                    continue;
+                }
+                for field in adt_def.all_fields() {
+                    let has_ptr = self
+                        .gacx
+                        .field_ltys
+                        .get(&field.did)
+                        .map_or(false, |&lty| !value_ptrs(lty).is_empty());
+                    if has_ptr {
+                        fields.push(field.did);
+                    }
+                    stack.push(self.gacx.tcx.type_of(field.did));
+                }
+            }
+        }
+        fields
+    }
+
+    /// Record that the value `ex` given for `lty` isn't supported.
+    fn unsupported(&mut self, owner: InitOwner, ex: &Expr, lty: LTy<'tcx>) {
+        debug!("unsupported initializer for {owner:?} at {:?}", ex.span);
+        if !value_ptrs(lty).is_empty() {
+            self.info.unsupported.insert(owner);
+        }
+        for did in self.struct_ptr_fields(lty.ty) {
+            self.info.unsupported.insert(InitOwner::Field(did));
+        }
+    }
+
+    fn walk(&mut self, owner: InitOwner, ex: &'tcx Expr<'tcx>, lty: LTy<'tcx>) {
+        if !self.has_pointers(lty) {
+            return;
+        }
+        let ex = peel_blocks(ex);
+
+        match *lty.ty.kind() {
+            TyKind::RawPtr(..) | TyKind::Ref(..) => match self.classify(ex, lty.args[0].ty) {
+                Some(value) => self.info.inits.push(PtrInit {
+                    owner,
+                    ptr: lty.label,
+                    ptr_ty: lty.ty,
+                    span: ex.span.source_callsite(),
+                    value,
+                }),
+                None => self.unsupported(owner, ex, lty),
+            },
+            TyKind::Array(..) => match ex.kind {
+                ExprKind::Array(elems) => {
+                    for elem in elems {
+                        self.walk(owner, elem, lty.args[0]);
+                    }
+                }
+                ExprKind::Repeat(elem, _) => self.walk(owner, elem, lty.args[0]),
+                _ => self.unsupported(owner, ex, lty),
+            },
+            TyKind::Tuple(..) => match ex.kind {
+                ExprKind::Tup(elems) => {
+                    for (elem, &elem_lty) in elems.iter().zip(lty.args) {
+                        self.walk(owner, elem, elem_lty);
+                    }
+                }
+                _ => self.unsupported(owner, ex, lty),
+            },
+            TyKind::Adt(adt_def, _) if adt_def.is_struct() => match ex.kind {
+                ExprKind::Struct(_, fields, None) => {
+                    for field in fields {
+                        let idx = match self.typeck_results.field_indices().get(field.hir_id) {
+                            Some(&x) => x,
+                            None => {
+                                self.unsupported(owner, field.expr, lty);
+                                continue;
+                            }
+                        };
+                        let did = adt_def.non_enum_variant().fields[idx].did;
+                        let field_lty = match self.gacx.field_ltys.get(&did) {
+                            Some(&x) => x,
+                            None => continue,
+                        };
+                        self.walk(InitOwner::Field(did), field.expr, field_lty);
+                    }
+                }
+                _ => self.unsupported(owner, ex, lty),
+            },
+            _ => self.unsupported(owner, ex, lty),
+        }
+    }
+
+    /// Determine the value of the pointer expression `ex`, whose declared pointee type is
+    /// `pointee_ty`.  Returns `None` if the value isn't supported.
+    fn classify(&self, ex: &'tcx Expr<'tcx>, pointee_ty: Ty<'tcx>) -> Option<InitValue> {
+        let ex = peel_blocks(peel_casts(ex));
+        match ex.kind {
+            ExprKind::Lit(ref lit) if matches!(lit.node, LitKind::Int(0, _)) => {
+                Some(InitValue::Null)
+            }
+            ExprKind::Call(func, []) => {
+                let callee = util::ty_callee(self.gacx.tcx, self.typeck_results.expr_ty(func));
+                match callee {
+                    Callee::Null { .. } => Some(InitValue::Null),
+                    _ => None,
+                }
+            }
+            // `&X`, `&mut X`, or `addr_of!(X)`
+            ExprKind::AddrOf(BorrowKind::Ref | BorrowKind::Raw, _, place) => {
+                let did = match place.kind {
+                    ExprKind::Path(QPath::Resolved(_, path)) => match path.res {
+                        Res::Def(DefKind::Static(Mutability::Not), did) => did,
+                        _ => return None,
+                    },
+                    _ => return None,
+                };
+                if !did.is_local() {
+                    return None;
+                }
+                let static_ty = self.gacx.tcx.type_of(did);
+                let is_array = match *static_ty.kind() {
+                    _ if static_ty == pointee_ty => false,
+                    TyKind::Array(elem_ty, _) if elem_ty == pointee_ty => true,
+                    _ => return None,
+                };
+                Some(InitValue::AddrOfStatic {
+                    place: place.span,
+                    is_array,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// List the pointers in `lty` that hold values.  This excludes pointers in the signatures of
+/// function pointer types, which don't appear in the initializer; those are unified with the
+/// signatures of the functions stored there instead (see `crate::fn_tables`).
+fn value_ptrs(lty: LTy) -> Vec<LTy> {
+    let mut ptrs = Vec::new();
+    let mut stack = vec![lty];
+    while let Some(lty) = stack.pop() {
+        if matches!(*lty.ty.kind(), TyKind::FnPtr(_)) {
+            continue;
+        }
+        if !lty.label.is_none() {
+            ptrs.push(lty);
+        }
+        stack.extend(lty.args.iter().copied());
+    }
+    ptrs
+}
+
+fn peel_casts<'a, 'tcx>(mut ex: &'a Expr<'tcx>) -> &'a Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+/// Remove blocks that only produce a value, as in `unsafe { &X as *const T }`.
+fn peel_blocks<'a, 'tcx>(mut ex: &'a Expr<'tcx>) -> &'a Expr<'tcx> {
+    while let ExprKind::Block(block, None) = ex.kind {
+        match (block.stmts, block.expr) {
+            ([], Some(inner)) => ex = inner,
+            _ => break,
+        }
+    }
+    ex
+}
+
+/// Walk the initializer of every local static or `const` item whose type holds pointers.
+pub fn find_static_inits<'tcx>(gacx: &GlobalAnalysisCtxt<'tcx>) -> StaticInitInfo<'tcx> {
+    let tcx = gacx.tcx;
+    let mut info = StaticInitInfo::default();
+
+    let mut dids = gacx
+        .static_tys
+        .keys()
+        .chain(gacx.const_tys.keys())
+        .copied()
+        .collect::<Vec<_>>();
+    dids.sort();
+    for did in dids {
+        let body_id = match tcx.hir().get_if_local(did) {
+            Some(Node::Item(item)) => match item.kind {
+                ItemKind::Static(_, _, body_id) | ItemKind::Const(_, body_id) => body_id,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let lty = gacx.item_lty(did);
+        let mut walker = InitWalker {
+            gacx,
+            typeck_results: tcx.typeck_body(body_id),
+            info: &mut info,
+        };
+        if !walker.has_pointers(lty) {
+            continue;
+        }
+        walker.walk(InitOwner::Static(did), tcx.hir().body(body_id).value, lty);
+        info.statics.insert(did);
+    }
+    info
+}
+
+/// Find the statics and fields whose initialized pointers can't be rewritten, given the final
+/// assignment.  This includes pointers that would become anything but a shared reference, and
+/// addresses of single values that would become slices.
+pub fn find_unrewritable_owners(
+    gacx: &GlobalAnalysisCtxt,
+    gasn: &GlobalAssignment,
+) -> Vec<InitOwner> {
+    let info = &gacx.static_inits;
+    // Pointers that are `FIXED` aren't rewritten, so any value is fine for them.
+    let desc_of = |ptr: PointerId, ptr_ty| {
+        if ptr.is_none() || gasn.flags[ptr].contains(FlagSet::FIXED) {
+            return None;
+        }
+        Some(type_desc::perms_to_desc(
+            ptr_ty,
+            gasn.perms[ptr],
+            gasn.flags[ptr],
+        ))
+    };
+
+    let mut owners = Vec::new();
+    for init in &info.inits {
+        let desc = match desc_of(init.ptr, init.ptr_ty) {
+            Some(x) => x,
+            None => continue,
+        };
+        let ok = match desc.own {
+            Ownership::Imm => match init.value {
+                InitValue::AddrOfStatic {
+                    is_array: false, ..
+                } => desc.qty == Quantity::Single,
+                _ => true,
+            },
+            Ownership::Raw | Ownership::RawMut => true,
+            _ => false,
+        };
+        if !ok {
+            owners.push(init.owner);
+        }
+    }
+
+    let mut dids = info.statics.iter().copied().collect::<Vec<_>>();
+    dids.sort();
+    for did in dids {
+        let lty = gacx.item_lty(did);
+        let ok = value_ptrs(lty)
+            .into_iter()
+            .filter(|lty| matches!(lty.ty.kind(), TyKind::RawPtr(..) | TyKind::Ref(..)))
+            .filter_map(|lty| desc_of(lty.label, lty.ty))
+            .all(|desc| {
+                matches!(
+                    desc.own,
+                    Ownership::Imm | Ownership::Raw | Ownership::RawMut
+                )
+            });
+        if !ok {
+            owners.push(InitOwner::Static(did));
+        }
+    }
+    owners
+}
@@ -0,0 +1,542 @@
+//! Classification of `static mut` globals by how they are used, so they can be rewritten to safe
+//! alternatives.
+//!
+//! `c2rust-transpile` turns every mutable C global into a `static mut`, which can only be accessed
+//! in `unsafe` code.  For globals that don't hold pointers, we look at every use and pick a safe
+//! replacement:
+//!
+//! * A global that is never written is `ReadOnly`, and becomes a plain `static`.  This case is
+//!   handled by `rewrite::gen_static_rewrites`, based on the permissions of the static's address.
+//! * A global that is assigned in only one place, where the assignment provably runs at most once,
+//!   is `InitOnce`, and becomes a `OnceLock`.  Reads before the assignment produce the original
+//!   initializer.  An assignment runs at most once if it's outside of any loop in `main`, or in a
+//!   function whose only use is a call from such a place (see `find_once_fns`), since a second
+//!   assignment to the `OnceLock` would panic.
+//! * An integer global that is only assigned or updated with `+=`, `-=`, `&=`, `|=`, or `^=` is a
+//!   `Counter`, and becomes the corresponding atomic type.
+//! * Any other global is `Guarded`, and becomes a `Mutex`.  Each access locks the mutex for the
+//!   duration of a single read or write.
+//!
+//! Globals that are borrowed, indexed, accessed with method calls, used in constant contexts, or
+//! written in the middle of an expression can't be handled this way, and are left unchanged.
+
+use crate::context::{FlagSet, GlobalAnalysisCtxt, GlobalAssignment};
+use log::debug;
+use rustc_hir as hir;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{BinOpKind, Expr, ExprKind, HirId, ItemKind, Mutability, Node, Stmt, StmtKind};
+use rustc_middle::ty::{self, IntTy, Ty, TyCtxt, TyKind, TypeckResults, UintTy};
+use rustc_span::Span;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StaticKind {
+    /// The static is never written.  It becomes a plain `static`.
+    ReadOnly,
+    /// The static is assigned in only one place, which runs at most once.  It becomes a
+    /// `OnceLock`.
+    InitOnce,
+    /// The static is an integer that is only assigned or updated in place.  It becomes an atomic.
+    Counter,
+    /// The static is read and written arbitrarily.  It becomes a `Mutex`.
+    Guarded,
+}
+
+/// A single use of a `static mut`.  In each case, `root` is the span of the path that names the
+/// static, and `projected` is set if a field of the static is accessed rather than the whole
+/// value.
+#[derive(Clone, Copy, Debug)]
+pub enum StaticUse {
+    /// The static or one of its fields is read, as in `S` or `S.x`.  `expr` is the whole place
+    /// expression.
+    Read {
+        expr: Span,
+        root: Span,
+        projected: bool,
+    },
+    /// The static or one of its fields is assigned in a statement, as in `S = e;`.  `once` is set
+    /// if the statement provably runs at most once.
+    Assign {
+        expr: Span,
+        lhs: Span,
+        root: Span,
+        rhs: Span,
+        projected: bool,
+        once: bool,
+    },
+    /// The static or one of its fields is updated in a statement, as in `S += e;`.  `primitive` is
+    /// set if the operands are primitive types, which means `e` is evaluated before the place.
+    AssignOp {
+        expr: Span,
+        lhs: Span,
+        root: Span,
+        rhs: Span,
+        op: BinOpKind,
+        projected: bool,
+        primitive: bool,
+    },
+    /// Any other use of the static.  This prevents the static from being rewritten.
+    Other,
+}
+
+/// Visitor that finds all uses of the `candidates` statics within a body.
+pub struct StaticUseVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    candidates: &'a HashSet<DefId>,
+    /// Whether the body belongs to a function.  Uses in constant contexts, such as the
+    /// initializer of another static, can't be rewritten.
+    in_fn: bool,
+    /// Whether the body belongs to a function that runs at most once.  See `find_once_fns`.
+    once_fn: bool,
+    pub uses: Vec<(DefId, StaticUse)>,
+    /// Expressions that form a whole statement, as in `S = e;`.
+    stmt_exprs: HashSet<HirId>,
+    /// Parts of place expressions that were already handled as part of an enclosing expression.
+    handled: HashSet<HirId>,
+    /// Number of loops enclosing the current expression.
+    loop_depth: usize,
+}
+
+impl<'a, 'tcx> StaticUseVisitor<'a, 'tcx> {
+    pub fn new(
+        tcx: TyCtxt<'tcx>,
+        typeck_results: &'tcx TypeckResults<'tcx>,
+        candidates: &'a HashSet<DefId>,
+        in_fn: bool,
+        once_fn: bool,
+    ) -> StaticUseVisitor<'a, 'tcx> {
+        StaticUseVisitor {
+            tcx,
+            typeck_results,
+            candidates,
+            in_fn,
+            once_fn,
+            uses: Vec::new(),
+            stmt_exprs: HashSet::new(),
+            handled: HashSet::new(),
+            loop_depth: 0,
+        }
+    }
+
+    /// If `ex` is a path to one of the `candidates`, return the static's `DefId`.
+    fn static_path(&self, ex: &Expr<'tcx>) -> Option<DefId> {
+        match ex.kind {
+            ExprKind::Path(hir::QPath::Resolved(_, path)) => match path.res {
+                Res::Def(DefKind::Static(Mutability::Mut), did)
+                    if self.candidates.contains(&did) =>
+                {
+                    Some(did)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Find the static at the root of a place like `S.x[i]`.  Returns the path to the static,
+    /// the static's `DefId`, and whether the place contains any indexing.
+    fn place_root(&self, mut ex: &'tcx Expr<'tcx>) -> Option<(&'tcx Expr<'tcx>, DefId, bool)> {
+        let mut indexed = false;
+        loop {
+            match ex.kind {
+                ExprKind::Field(base, _) => ex = base,
+                ExprKind::Index(base, _) => {
+                    indexed = true;
+                    ex = base;
+                }
+                _ => return self.static_path(ex).map(|did| (ex, did, indexed)),
+            }
+        }
+    }
+
+    /// Mark every expression along the place `ex`, down to its root, as handled.
+    fn mark_place_handled(&mut self, mut ex: &'tcx Expr<'tcx>) {
+        loop {
+            self.handled.insert(ex.hir_id);
+            match ex.kind {
+                ExprKind::Field(base, _) | ExprKind::Index(base, _) => ex = base,
+                _ => return,
+            }
+        }
+    }
+
+    fn is_copy(&self, ty: Ty<'tcx>, span: Span) -> bool {
+        ty.is_copy_modulo_regions(self.tcx.at(span), ty::ParamEnv::reveal_all())
+    }
+
+    /// Handle an assignment or compound assignment to the place `lhs`.
+    fn visit_assign(
+        &mut self,
+        ex: &'tcx Expr<'tcx>,
+        lhs: &'tcx Expr<'tcx>,
+        rhs: &'tcx Expr<'tcx>,
+        op: Option<BinOpKind>,
+    ) {
+        let (root, did, indexed) = match self.place_root(lhs) {
+            Some(x) => x,
+            None => return,
+        };
+        self.mark_place_handled(lhs);
+        if indexed || !self.in_fn || !self.stmt_exprs.contains(&ex.hir_id) {
+            debug!("unsupported write to static {did:?} at {:?}", ex.span);
+            self.uses.push((did, StaticUse::Other));
+            return;
+        }
+        let projected = lhs.hir_id != root.hir_id;
+        let u = match op {
+            None => StaticUse::Assign {
+                expr: ex.span,
+                lhs: lhs.span,
+                root: root.span,
+                rhs: rhs.span,
+                projected,
+                once: self.once_fn && self.loop_depth == 0,
+            },
+            Some(op) => StaticUse::AssignOp {
+                expr: ex.span,
+                lhs: lhs.span,
+                root: root.span,
+                rhs: rhs.span,
+                op,
+                projected,
+                primitive: self.typeck_results.expr_ty(lhs).is_primitive(),
+            },
+        };
+        self.uses.push((did, u));
+    }
+
+    /// Handle a place expression rooted at one of the `candidates` in a value context.
+    fn visit_read(&mut self, ex: &'tcx Expr<'tcx>) {
+        let (root, did, indexed) = match self.place_root(ex) {
+            Some(x) => x,
+            None => return,
+        };
+        self.mark_place_handled(ex);
+        // An adjustment here means the place is borrowed, as in `S.f()` or `S.x.len()`.
+        let adjusted = !self.typeck_results.expr_adjustments(ex).is_empty();
+        let copy = self.is_copy(self.typeck_results.expr_ty(ex), ex.span);
+        if indexed || adjusted || !copy || !self.in_fn {
+            debug!("unsupported read of static {did:?} at {:?}", ex.span);
+            self.uses.push((did, StaticUse::Other));
+            return;
+        }
+        self.uses.push((
+            did,
+            StaticUse::Read {
+                expr: ex.span,
+                root: root.span,
+                projected: ex.hir_id != root.hir_id,
+            },
+        ));
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for StaticUseVisitor<'a, 'tcx> {
+    fn visit_stmt(&mut self, stmt: &'tcx Stmt<'tcx>) {
+        if let StmtKind::Semi(ex) | StmtKind::Expr(ex) = stmt.kind {
+            self.stmt_exprs.insert(ex.hir_id);
+        }
+        intravisit::walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        if self.handled.contains(&ex.hir_id) {
+            intravisit::walk_expr(self, ex);
+            return;
+        }
+
+        match ex.kind {
+            ExprKind::Assign(lhs, rhs, _) => self.visit_assign(ex, lhs, rhs, None),
+            ExprKind::AssignOp(op, lhs, rhs) => self.visit_assign(ex, lhs, rhs, Some(op.node)),
+            // `&S`, `&mut S`, `addr_of_mut!(S)`
+            ExprKind::AddrOf(_, _, pl) => {
+                if let Some((_, did, _)) = self.place_root(pl) {
+                    self.mark_place_handled(pl);
+                    self.uses.push((did, StaticUse::Other));
+                }
+            }
+            ExprKind::Path(..) | ExprKind::Field(..) | ExprKind::Index(..) => {
+                self.visit_read(ex);
+            }
+            ExprKind::Loop(..) => {
+                self.loop_depth += 1;
+                intravisit::walk_expr(self, ex);
+                self.loop_depth -= 1;
+                return;
+            }
+            _ => {}
+        }
+
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// If `ty` is an integer type with a corresponding atomic type, return the name of the atomic
+/// type.
+pub fn atomic_ty_name(ty: Ty) -> Option<&'static str> {
+    Some(match *ty.kind() {
+        TyKind::Int(IntTy::I8) => "AtomicI8",
+        TyKind::Int(IntTy::I16) => "AtomicI16",
+        TyKind::Int(IntTy::I32) => "AtomicI32",
+        TyKind::Int(IntTy::I64) => "AtomicI64",
+        TyKind::Int(IntTy::Isize) => "AtomicIsize",
+        TyKind::Uint(UintTy::U8) => "AtomicU8",
+        TyKind::Uint(UintTy::U16) => "AtomicU16",
+        TyKind::Uint(UintTy::U32) => "AtomicU32",
+        TyKind::Uint(UintTy::U64) => "AtomicU64",
+        TyKind::Uint(UintTy::Usize) => "AtomicUsize",
+        _ => return None,
+    })
+}
+
+/// Return the name of the atomic method that performs `op` in place, such as `fetch_add` for
+/// `+=`.
+pub fn atomic_op_method(op: BinOpKind) -> Option<&'static str> {
+    Some(match op {
+        BinOpKind::Add => "fetch_add",
+        BinOpKind::Sub => "fetch_sub",
+        BinOpKind::BitAnd => "fetch_and",
+        BinOpKind::BitOr => "fetch_or",
+        BinOpKind::BitXor => "fetch_xor",
+        _ => return None,
+    })
+}
+
+/// Visitor that finds all references to local functions within a body.
+struct FnRefVisitor<'a> {
+    caller: LocalDefId,
+    /// For each function, the bodies that refer to it, and whether each reference is a direct
+    /// call outside of any loop.
+    refs: &'a mut HashMap<LocalDefId, Vec<(LocalDefId, bool)>>,
+    /// Paths that were already handled as the callee of a call.
+    handled: HashSet<HirId>,
+    /// Number of loops enclosing the current expression.
+    loop_depth: usize,
+}
+
+impl<'a> FnRefVisitor<'a> {
+    fn fn_path(ex: &Expr) -> Option<LocalDefId> {
+        match ex.kind {
+            ExprKind::Path(hir::QPath::Resolved(_, path)) => match path.res {
+                Res::Def(DefKind::Fn, did) => did.as_local(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for FnRefVisitor<'a> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            ExprKind::Call(func, _) => {
+                if let Some(callee) = Self::fn_path(func) {
+                    self.handled.insert(func.hir_id);
+                    let direct = self.loop_depth == 0;
+                    self.refs
+                        .entry(callee)
+                        .or_default()
+                        .push((self.caller, direct));
+                }
+            }
+            ExprKind::Path(..) if !self.handled.contains(&ex.hir_id) => {
+                if let Some(did) = Self::fn_path(ex) {
+                    self.refs.entry(did).or_default().push((self.caller, false));
+                }
+            }
+            ExprKind::Loop(..) => {
+                self.loop_depth += 1;
+                intravisit::walk_expr(self, ex);
+                self.loop_depth -= 1;
+                return;
+            }
+            _ => {}
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Find the functions that run at most once.  This is the case for the crate's `main` function,
+/// if nothing else refers to it, and for any function whose only reference is a direct call,
+/// outside of any loop, from a function that runs at most once.  Functions that can be called from
+/// foreign code, and closures, which are bodies of their own, are never included.
+fn find_once_fns(tcx: TyCtxt) -> HashSet<LocalDefId> {
+    let mut once = HashSet::new();
+    let entry = match tcx.entry_fn(()) {
+        Some((did, _)) => match did.as_local() {
+            Some(ldid) => ldid,
+            None => return once,
+        },
+        None => return once,
+    };
+
+    let mut refs = HashMap::new();
+    for ldid in tcx.hir().body_owners() {
+        let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+            Some(x) => x,
+            None => continue,
+        };
+        let mut v = FnRefVisitor {
+            caller: ldid,
+            refs: &mut refs,
+            handled: HashSet::new(),
+            loop_depth: 0,
+        };
+        v.visit_body(tcx.hir().body(hir_body_id));
+    }
+
+    if refs.contains_key(&entry) {
+        return once;
+    }
+    once.insert(entry);
+    // Each step adds the functions called once from a function added by the previous step.
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (&callee, callee_refs) in &refs {
+            if once.contains(&callee)
+                || tcx
+                    .codegen_fn_attrs(callee.to_def_id())
+                    .contains_extern_indicator()
+            {
+                continue;
+            }
+            if let [(caller, true)] = callee_refs[..] {
+                if once.contains(&caller) {
+                    once.insert(callee);
+                    changed = true;
+                }
+            }
+        }
+    }
+    once
+}
+
+/// Run `StaticUseVisitor` over every body in the crate.
+pub fn collect_static_uses(tcx: TyCtxt, candidates: &HashSet<DefId>) -> Vec<(DefId, StaticUse)> {
+    let once_fns = find_once_fns(tcx);
+    let mut uses = Vec::new();
+    for ldid in tcx.hir().body_owners() {
+        let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+            Some(x) => x,
+            None => continue,
+        };
+        let in_fn = matches!(
+            tcx.def_kind(ldid),
+            DefKind::Fn | DefKind::AssocFn | DefKind::Closure
+        );
+        let once_fn = once_fns.contains(&ldid);
+        let mut v = StaticUseVisitor::new(
+            tcx,
+            tcx.typeck_body(hir_body_id),
+            candidates,
+            in_fn,
+            once_fn,
+        );
+        v.visit_body(tcx.hir().body(hir_body_id));
+        uses.extend(v.uses);
+    }
+    uses
+}
+
+fn classify(ty: Ty, uses: &[StaticUse]) -> Option<StaticKind> {
+    let mut writes = 0;
+    let mut any_projected = false;
+    let mut all_once = true;
+    let mut counter_ops = true;
+    let mut guard_ops = true;
+    for u in uses {
+        match *u {
+            StaticUse::Read { projected, .. } => any_projected |= projected,
+            StaticUse::Assign {
+                projected, once, ..
+            } => {
+                writes += 1;
+                any_projected |= projected;
+                all_once &= once;
+            }
+            StaticUse::AssignOp {
+                op,
+                projected,
+                primitive,
+                ..
+            } => {
+                writes += 1;
+                any_projected |= projected;
+                counter_ops &= atomic_op_method(op).is_some();
+                guard_ops &= primitive;
+            }
+            StaticUse::Other => return None,
+        }
+    }
+
+    let assigns_only = !uses.iter().any(|u| matches!(u, StaticUse::AssignOp { .. }));
+    if writes == 0 {
+        Some(StaticKind::ReadOnly)
+    } else if writes == 1 && assigns_only && !any_projected && all_once {
+        Some(StaticKind::InitOnce)
+    } else if atomic_ty_name(ty).is_some() && counter_ops && !any_projected {
+        Some(StaticKind::Counter)
+    } else if guard_ops {
+        Some(StaticKind::Guarded)
+    } else {
+        None
+    }
+}
+
+/// Classify each `static mut` that doesn't hold pointers according to how it's used.  Statics
+/// whose uses aren't supported are omitted from the result.
+pub fn find_static_kinds(
+    gacx: &GlobalAnalysisCtxt,
+    gasn: &GlobalAssignment,
+) -> HashMap<DefId, StaticKind> {
+    let tcx = gacx.tcx;
+
+    let mut candidates = HashSet::new();
+    for (&did, &ptr) in &gacx.addr_of_static {
+        let item = match tcx.hir().get_if_local(did) {
+            Some(Node::Item(item)) => item,
+            _ => continue,
+        };
+        if !matches!(item.kind, ItemKind::Static(_, Mutability::Mut, _)) {
+            continue;
+        }
+        if gasn.flags[ptr].contains(FlagSet::FIXED) {
+            continue;
+        }
+        // Exported statics must keep their type for the sake of foreign code.
+        if tcx.codegen_fn_attrs(did).contains_extern_indicator() {
+            continue;
+        }
+        // Statics holding pointers are handled by the normal rewrites.
+        let has_ptr = tcx
+            .type_of(did)
+            .walk()
+            .any(|arg| matches!(arg.unpack(), ty::GenericArgKind::Type(ty) if ty.is_any_ptr()));
+        if has_ptr {
+            continue;
+        }
+        candidates.insert(did);
+    }
+    if candidates.is_empty() {
+        return HashMap::new();
+    }
+
+    let mut uses = HashMap::<DefId, Vec<StaticUse>>::new();
+    for (did, u) in collect_static_uses(tcx, &candidates) {
+        uses.entry(did).or_default().push(u);
+    }
+
+    let mut kinds = HashMap::new();
+    for did in candidates {
+        let did_uses = uses.remove(&did).unwrap_or_default();
+        if let Some(kind) = classify(tcx.type_of(did), &did_uses) {
+            debug!("static {did:?} is {kind:?}");
+            kinds.insert(did, kind);
+        }
+    }
+    kinds
+}
@@ -0,0 +1,746 @@
+//! Detection of structs that are shared between threads through `pthread_create`.
+//!
+//! C code typically shares state with a new thread by passing a pointer to a heap-allocated
+//! struct as the thread's `void*` argument, and protects the struct with a `pthread_mutex_t`
+//! stored inside it:
+//!
+//! ```ignore
+//! unsafe extern "C" fn worker(arg: *mut c_void) -> *mut c_void {
+//!     let s = arg as *mut Shared;
+//!     pthread_mutex_lock(&mut (*s).lock);
+//!     (*s).count += 1;
+//!     pthread_mutex_unlock(&mut (*s).lock);
+//!     ptr::null_mut()
+//! }
+//!
+//! pthread_create(&mut tid, ptr::null(), Some(worker), s as *mut c_void);
+//! ```
+//!
+//! Such a pointer escapes to another thread, so it can never be `UNIQUE`, and the normal rewrites
+//! leave it as a raw pointer.  Instead, we rewrite all pointers to these structs as
+//! `Arc<Mutex<T>>`.  The `void*` argument of `pthread_create` carries one reference, created with
+//! `Arc::into_raw` on the spawning side and reclaimed with `Arc::from_raw` in the thread start
+//! routine.  Each `pthread_mutex_lock`/`pthread_mutex_unlock` pair in a block becomes a `lock()`
+//! guard that is dropped at the unlock, and dereferences between the two go through the guard.
+//! Other dereferences lock the mutex for the duration of the access.  Since `Mutex` isn't
+//! re-entrant, any other use of a shared pointer between a lock and its unlock, such as a copy
+//! passed to a call or a dereference through a different local, would deadlock, so it prevents
+//! the struct from being rewritten.  Calls to
+//! `pthread_mutex_init` and `pthread_mutex_destroy` on the struct's mutex are removed.  The
+//! original `pthread_mutex_t` field is kept, but is no longer used.
+//!
+//! As with `crate::refcount`, only simple uses of the pointers are supported, and any other use
+//! prevents the struct from being rewritten.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::util::{self, Callee};
+use log::debug;
+use rustc_hir as hir;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{Block, Expr, ExprKind, HirId, Local, Mutability, StmtKind, UnOp};
+use rustc_middle::ty::{GenericArgKind, Ty, TyCtxt, TyKind, TypeckResults, WithOptConstParam};
+use rustc_span::Span;
+use std::collections::{HashMap, HashSet};
+
+/// A single use of a pointer to a thread-shared struct.
+#[derive(Clone, Debug)]
+pub enum SharedPtrUse {
+    /// A new object is allocated, as in `malloc(n) as *mut S`.  `expr` is the whole cast
+    /// expression.
+    Alloc { expr: Span },
+    /// The object is freed, as in `free(p as *mut c_void)`.
+    Free { call: Span, ptr: Span },
+    /// The pointer is dereferenced, as in `(*p).x`.  `guard` is the name of the lock guard to
+    /// access it through, if the dereference occurs between a `pthread_mutex_lock` and
+    /// `pthread_mutex_unlock` pair.
+    Deref {
+        expr: Span,
+        ptr: Span,
+        guard: Option<String>,
+    },
+    /// A pointer held in a local is copied, as in `let q = p` or `f(p)`.
+    Copy { expr: Span },
+    /// A pointer type is written in the source, as in `*mut S`.  `pointee` is the span of `S`.
+    Ty { ty: Span, pointee: Span },
+    /// The pointer is passed to a new thread, as in `pthread_create(.., p as *mut c_void)`.
+    /// `ptr` is the span of `p`.
+    Spawn { ptr: Span },
+    /// The `void*` argument of a thread start routine is cast back to a pointer, as in
+    /// `arg as *mut S`.
+    ThreadArg {
+        expr: Span,
+        arg: Span,
+        pointee: Span,
+    },
+    /// The struct's mutex is locked, as in `pthread_mutex_lock(&mut (*p).lock)`.
+    Lock {
+        call: Span,
+        ptr: Span,
+        guard: String,
+    },
+    /// The struct's mutex is unlocked, as in `pthread_mutex_unlock(&mut (*p).lock)`.
+    Unlock { call: Span, guard: String },
+    /// A call to `pthread_mutex_init` or `pthread_mutex_destroy` on the struct's mutex.
+    MutexNoop { call: Span },
+    /// Any other use of the pointer.  This prevents the struct from being rewritten.
+    Other,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ThreadSharedInfo {
+    /// Structs shared between threads that will be rewritten to `Arc<Mutex<T>>`.
+    pub structs: HashSet<DefId>,
+    /// Functions that mention pointers to any of the `structs`.  These are excluded from the
+    /// normal rewrites and handled entirely by `rewrite::gen_thread_shared_rewrites`.
+    pub fns: HashSet<DefId>,
+    /// Thread start routines, mapped to the struct that is passed as their argument.
+    pub start_routines: HashMap<DefId, DefId>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PthreadFn {
+    Create,
+    MutexLock,
+    MutexUnlock,
+    MutexInit,
+    MutexDestroy,
+}
+
+/// If `ty` is the type of one of the `pthread` functions we recognize, return which one.
+fn pthread_fn<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Option<PthreadFn> {
+    let did = match *ty.kind() {
+        TyKind::FnDef(did, _) => did,
+        _ => return None,
+    };
+    if !matches!(tcx.def_kind(tcx.parent(did)), DefKind::ForeignMod) {
+        return None;
+    }
+    Some(match tcx.item_name(did).as_str() {
+        "pthread_create" => PthreadFn::Create,
+        "pthread_mutex_lock" => PthreadFn::MutexLock,
+        "pthread_mutex_unlock" => PthreadFn::MutexUnlock,
+        "pthread_mutex_init" => PthreadFn::MutexInit,
+        "pthread_mutex_destroy" => PthreadFn::MutexDestroy,
+        _ => return None,
+    })
+}
+
+/// Returns `true` if `did` is one of the `pthread` functions whose calls are rewritten by this
+/// module.
+pub fn is_rewritten_pthread_fn(tcx: TyCtxt, did: DefId) -> bool {
+    pthread_fn(tcx, tcx.type_of(did)).is_some()
+}
+
+fn peel_casts<'tcx>(mut ex: &'tcx Expr<'tcx>) -> &'tcx Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+fn local_struct_ptr(ty: Ty) -> Option<DefId> {
+    match *ty.kind() {
+        TyKind::RawPtr(tm) => match *tm.ty.kind() {
+            TyKind::Adt(adt_def, _) if adt_def.is_struct() && adt_def.did().is_local() => {
+                Some(adt_def.did())
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Visitor that finds calls to `pthread_create` and records which structs escape to the new
+/// thread and which start routines receive them.
+struct ThreadSpawnVisitor<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    /// `(struct, start routine)` pairs.  The start routine is `None` if it couldn't be
+    /// determined.
+    spawns: Vec<(DefId, Option<DefId>)>,
+}
+
+impl<'tcx> ThreadSpawnVisitor<'tcx> {
+    /// Find the struct whose pointer is passed as the `void*` argument, as in `p as *mut c_void`.
+    fn spawn_arg_adt(&self, mut ex: &'tcx Expr<'tcx>) -> Option<DefId> {
+        loop {
+            if let Some(did) = local_struct_ptr(self.typeck_results.expr_ty(ex)) {
+                return Some(did);
+            }
+            match ex.kind {
+                ExprKind::Cast(inner, _) => ex = inner,
+                _ => return None,
+            }
+        }
+    }
+
+    /// Find the local function passed as the start routine, as in `Some(f)`.
+    fn start_routine(&self, ex: &'tcx Expr<'tcx>) -> Option<DefId> {
+        let ex = match peel_casts(ex).kind {
+            ExprKind::Call(_, [inner]) => peel_casts(inner),
+            _ => return None,
+        };
+        match *self.typeck_results.expr_ty(ex).kind() {
+            TyKind::FnDef(did, _) if did.is_local() => Some(did),
+            _ => None,
+        }
+    }
+}
+
+impl<'tcx> Visitor<'tcx> for ThreadSpawnVisitor<'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        if let ExprKind::Call(func, args) = ex.kind {
+            let func_ty = self.typeck_results.expr_ty(func);
+            if pthread_fn(self.tcx, func_ty) == Some(PthreadFn::Create) {
+                if let [_, _, start, arg] = args {
+                    if let Some(adt) = self.spawn_arg_adt(arg) {
+                        self.spawns.push((adt, self.start_routine(start)));
+                    }
+                }
+            }
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Visitor that finds all uses of pointers to the `candidates` structs within a body.
+pub struct SharedPtrUseVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    candidates: &'a HashSet<DefId>,
+    pub uses: Vec<(DefId, SharedPtrUse)>,
+    /// Pointer expressions that were already handled as part of an enclosing expression.
+    handled: HashSet<HirId>,
+    /// If the current function is a thread start routine, its `void*` parameter and the struct
+    /// that is passed through it.
+    thread_arg: Option<(HirId, DefId)>,
+    /// Guard names for paired `pthread_mutex_lock` and `pthread_mutex_unlock` calls.
+    lock_guards: HashMap<HirId, String>,
+    /// Regions between paired lock and unlock calls, as `(local, region, guard)`.
+    lock_regions: Vec<(HirId, Span, String)>,
+}
+
+impl<'a, 'tcx> SharedPtrUseVisitor<'a, 'tcx> {
+    pub fn new(
+        tcx: TyCtxt<'tcx>,
+        typeck_results: &'tcx TypeckResults<'tcx>,
+        candidates: &'a HashSet<DefId>,
+        thread_arg: Option<(HirId, DefId)>,
+    ) -> SharedPtrUseVisitor<'a, 'tcx> {
+        SharedPtrUseVisitor {
+            tcx,
+            typeck_results,
+            candidates,
+            uses: Vec::new(),
+            handled: HashSet::new(),
+            thread_arg,
+            lock_guards: HashMap::new(),
+            lock_regions: Vec::new(),
+        }
+    }
+
+    /// If `ex` is a pointer to one of the `candidates`, return the struct's `DefId`.
+    fn shared_expr(&self, ex: &Expr<'tcx>) -> Option<DefId> {
+        shared_adt(self.candidates, self.typeck_results.expr_ty(ex))
+    }
+
+    /// If `ex` is a path to a local variable, return the variable's `HirId`.
+    fn local_var(&self, ex: &Expr<'tcx>) -> Option<HirId> {
+        match ex.kind {
+            ExprKind::Path(hir::QPath::Resolved(_, path)) => match path.res {
+                Res::Local(hir_id) => Some(hir_id),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Record `ex` as a copy of a pointer, if it's a pointer held in a local variable.
+    fn visit_copy(&mut self, ex: &Expr<'tcx>) {
+        if let Some(adt) = self.shared_expr(ex) {
+            if self.local_var(ex).is_some() {
+                self.handled.insert(ex.hir_id);
+                let u = self.unless_locked(ex.span, SharedPtrUse::Copy { expr: ex.span });
+                self.uses.push((adt, u));
+            }
+        }
+    }
+
+    /// Find the `*p` expression at the root of a place like `(*p).x[i]`, along with the struct
+    /// that `p` points to.
+    fn place_root_deref(&self, mut ex: &'tcx Expr<'tcx>) -> Option<(&'tcx Expr<'tcx>, DefId)> {
+        loop {
+            match ex.kind {
+                ExprKind::Field(base, _) | ExprKind::Index(base, _) => ex = base,
+                ExprKind::Unary(UnOp::Deref, ptr) => {
+                    return self.shared_expr(ptr).map(|adt| (ex, adt));
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// If `ex` is a call to one of the `pthread_mutex_*` functions on the mutex of a shared
+    /// struct, as in `pthread_mutex_lock(&mut (*p).lock)`, return the function, the pointer `p`,
+    /// and the struct.
+    fn mutex_call(&self, ex: &'tcx Expr<'tcx>) -> Option<(PthreadFn, &'tcx Expr<'tcx>, DefId)> {
+        let (func, args) = match ex.kind {
+            ExprKind::Call(func, args) => (func, args),
+            _ => return None,
+        };
+        let which = pthread_fn(self.tcx, self.typeck_results.expr_ty(func))?;
+        if which == PthreadFn::Create {
+            return None;
+        }
+        let place = match peel_casts(args.first()?).kind {
+            ExprKind::AddrOf(hir::BorrowKind::Ref, Mutability::Mut, place) => place,
+            _ => return None,
+        };
+        let (deref, adt) = self.place_root_deref(place)?;
+        let ptr = match deref.kind {
+            ExprKind::Unary(UnOp::Deref, ptr) => ptr,
+            _ => unreachable!(),
+        };
+        self.local_var(ptr)?;
+        Some((which, ptr, adt))
+    }
+
+    /// Pair up the lock and unlock calls among the statements of `block`.  Each lock must be
+    /// followed, in the same block, by an unlock of a mutex reached through the same local, with
+    /// no other lock or unlock in between.
+    fn pair_locks(&mut self, block: &'tcx Block<'tcx>) {
+        let mut calls = Vec::new();
+        for stmt in block.stmts {
+            let ex = match stmt.kind {
+                StmtKind::Semi(ex) | StmtKind::Expr(ex) => ex,
+                _ => continue,
+            };
+            if let Some((which, ptr, _)) = self.mutex_call(ex) {
+                if matches!(which, PthreadFn::MutexLock | PthreadFn::MutexUnlock) {
+                    let local = self.local_var(ptr).unwrap();
+                    calls.push((which, local, ex.hir_id, stmt.span));
+                }
+            }
+        }
+
+        for (i, &(which, local, lock_id, lock_span)) in calls.iter().enumerate() {
+            if which != PthreadFn::MutexLock {
+                continue;
+            }
+            if let Some(&(PthreadFn::MutexUnlock, l, unlock_id, unlock_span)) = calls.get(i + 1) {
+                if l != local {
+                    continue;
+                }
+                let guard = format!("{}_guard", self.tcx.hir().name(local));
+                self.lock_guards.insert(lock_id, guard.clone());
+                self.lock_guards.insert(unlock_id, guard.clone());
+                self.lock_regions
+                    .push((local, lock_span.to(unlock_span), guard));
+            }
+        }
+    }
+
+    /// Find the guard to use for a dereference of `ptr` at `span`.
+    fn guard_for(&self, ptr: &Expr<'tcx>, span: Span) -> Option<String> {
+        let local = self.local_var(ptr)?;
+        self.lock_regions
+            .iter()
+            .rev()
+            .find(|&&(l, region, _)| l == local && region.contains(span))
+            .map(|(_, _, guard)| guard.clone())
+    }
+
+    /// Return `u`, or `SharedPtrUse::Other` if `span` is between a paired lock and unlock.  The
+    /// `Mutex` isn't re-entrant, so any use other than a dereference through the guard might try
+    /// to lock it again while it's held.
+    fn unless_locked(&self, span: Span, u: SharedPtrUse) -> SharedPtrUse {
+        if self
+            .lock_regions
+            .iter()
+            .any(|&(_, region, _)| region.contains(span))
+        {
+            debug!("use of thread-shared pointer at {span:?} while its mutex is locked");
+            SharedPtrUse::Other
+        } else {
+            u
+        }
+    }
+
+    /// Any pointer that wasn't handled by an enclosing expression is used in an unsupported way.
+    fn check_handled(&mut self, ex: &Expr<'tcx>) {
+        if self.handled.contains(&ex.hir_id) {
+            return;
+        }
+        if let Some(adt) = self.shared_expr(ex) {
+            debug!("unsupported use of thread-shared pointer at {:?}", ex.span);
+            self.uses.push((adt, SharedPtrUse::Other));
+        }
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for SharedPtrUseVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            // `q = p`
+            ExprKind::Assign(lhs, rhs, _) => {
+                if self.shared_expr(lhs).is_some() && self.local_var(lhs).is_some() {
+                    self.handled.insert(lhs.hir_id);
+                    self.visit_copy(rhs);
+                }
+            }
+
+            // `&(*p).x` would borrow from a temporary lock guard, which doesn't live long enough.
+            ExprKind::AddrOf(_, _, pl) => {
+                if let Some((_, adt)) = self.place_root_deref(pl) {
+                    self.uses.push((adt, SharedPtrUse::Other));
+                }
+            }
+
+            // `*p`
+            ExprKind::Unary(UnOp::Deref, ptr) => {
+                if let Some(adt) = self.shared_expr(ptr) {
+                    self.handled.insert(ptr.hir_id);
+                    let u = match self.guard_for(ptr, ex.span) {
+                        Some(guard) => SharedPtrUse::Deref {
+                            expr: ex.span,
+                            ptr: ptr.span,
+                            guard: Some(guard),
+                        },
+                        None => self.unless_locked(
+                            ex.span,
+                            SharedPtrUse::Deref {
+                                expr: ex.span,
+                                ptr: ptr.span,
+                                guard: None,
+                            },
+                        ),
+                    };
+                    self.uses.push((adt, u));
+                }
+            }
+
+            // `malloc(n) as *mut S`, or `arg as *mut S` in a thread start routine
+            ExprKind::Cast(inner, ref ty) => {
+                if let Some(adt) = self.shared_expr(ex) {
+                    let callee = match inner.kind {
+                        ExprKind::Call(func, _) => {
+                            Some(util::ty_callee(self.tcx, self.typeck_results.expr_ty(func)))
+                        }
+                        _ => None,
+                    };
+                    if matches!(callee, Some(Callee::Malloc | Callee::Calloc)) {
+                        self.uses.push((adt, SharedPtrUse::Alloc { expr: ex.span }));
+                        // The whole expression is replaced, including the `*mut S` type.
+                        return;
+                    }
+
+                    let is_thread_arg = match (self.thread_arg, self.local_var(inner)) {
+                        (Some((param, param_adt)), Some(local)) => {
+                            param == local && param_adt == adt
+                        }
+                        _ => false,
+                    };
+                    if let (true, hir::TyKind::Ptr(ref mt)) = (is_thread_arg, &ty.kind) {
+                        self.uses.push((
+                            adt,
+                            SharedPtrUse::ThreadArg {
+                                expr: ex.span,
+                                arg: inner.span,
+                                pointee: mt.ty.span,
+                            },
+                        ));
+                        return;
+                    }
+                }
+            }
+
+            ExprKind::Call(func, args) => {
+                if let Some((which, ptr, adt)) = self.mutex_call(ex) {
+                    let u = match which {
+                        PthreadFn::MutexLock | PthreadFn::MutexUnlock => {
+                            // Each lock is inside its own region, so being inside any other
+                            // region means it's nested within another lock.
+                            let nested = self
+                                .lock_regions
+                                .iter()
+                                .filter(|&&(_, region, _)| region.contains(ex.span))
+                                .count()
+                                > 1;
+                            match self.lock_guards.get(&ex.hir_id) {
+                                Some(_) if nested => {
+                                    debug!("nested mutex lock at {:?}", ex.span);
+                                    SharedPtrUse::Other
+                                }
+                                Some(guard) if which == PthreadFn::MutexLock => {
+                                    SharedPtrUse::Lock {
+                                        call: ex.span,
+                                        ptr: ptr.span,
+                                        guard: guard.clone(),
+                                    }
+                                }
+                                Some(guard) => SharedPtrUse::Unlock {
+                                    call: ex.span,
+                                    guard: guard.clone(),
+                                },
+                                None => {
+                                    debug!("unpaired mutex lock or unlock at {:?}", ex.span);
+                                    SharedPtrUse::Other
+                                }
+                            }
+                        }
+                        _ => SharedPtrUse::MutexNoop { call: ex.span },
+                    };
+                    self.uses.push((adt, u));
+                    // The whole call is replaced, including the `&mut (*p).lock` argument.
+                    return;
+                }
+
+                let func_ty = self.typeck_results.expr_ty(func);
+                match util::ty_callee(self.tcx, func_ty) {
+                    // `free(p as *mut c_void)`
+                    Callee::Free => {
+                        if let [arg] = args {
+                            if let ExprKind::Cast(ptr, _) = arg.kind {
+                                if let Some(adt) = self.shared_expr(ptr) {
+                                    let u = SharedPtrUse::Free {
+                                        call: ex.span,
+                                        ptr: ptr.span,
+                                    };
+                                    self.uses.push((adt, self.unless_locked(ex.span, u)));
+                                    // The whole call is replaced, including the cast.
+                                    return;
+                                }
+                            }
+                        }
+                    }
+                    // `f(p)`, where `f` is a local function
+                    Callee::LocalDef { .. } => {
+                        self.handled.insert(ex.hir_id);
+                        for arg in args {
+                            self.visit_copy(arg);
+                        }
+                    }
+                    // `pthread_create(.., p as *mut c_void)`
+                    _ if pthread_fn(self.tcx, func_ty) == Some(PthreadFn::Create) => {
+                        if let [_, _, _, arg] = args {
+                            if let ExprKind::Cast(ptr, _) = arg.kind {
+                                if let Some(adt) = self.shared_expr(ptr) {
+                                    if self.local_var(ptr).is_some() {
+                                        self.handled.insert(ptr.hir_id);
+                                        let u = SharedPtrUse::Spawn { ptr: ptr.span };
+                                        self.uses.push((adt, self.unless_locked(ex.span, u)));
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            // `return p`
+            ExprKind::Ret(Some(val)) => self.visit_copy(val),
+
+            // `{ ...; p }`
+            ExprKind::Block(block, _) => {
+                self.handled.insert(ex.hir_id);
+                self.pair_locks(block);
+                if let Some(val) = block.expr {
+                    self.visit_copy(val);
+                }
+            }
+
+            _ => {}
+        }
+
+        self.check_handled(ex);
+        intravisit::walk_expr(self, ex);
+    }
+
+    fn visit_local(&mut self, local: &'tcx Local<'tcx>) {
+        // `let q = p`
+        if let Some(init) = local.init {
+            self.visit_copy(init);
+        }
+        intravisit::walk_local(self, local);
+    }
+
+    fn visit_ty(&mut self, ty: &'tcx hir::Ty<'tcx>) {
+        // `*mut S`
+        if let hir::TyKind::Ptr(ref mt) = ty.kind {
+            if let hir::TyKind::Path(hir::QPath::Resolved(None, path)) = mt.ty.kind {
+                if let Res::Def(DefKind::Struct, did) = path.res {
+                    if self.candidates.contains(&did) {
+                        self.uses.push((
+                            did,
+                            SharedPtrUse::Ty {
+                                ty: ty.span,
+                                pointee: mt.ty.span,
+                            },
+                        ));
+                        return;
+                    }
+                }
+            }
+        }
+        intravisit::walk_ty(self, ty);
+    }
+}
+
+fn shared_adt(candidates: &HashSet<DefId>, ty: Ty) -> Option<DefId> {
+    local_struct_ptr(ty).filter(|did| candidates.contains(did))
+}
+
+/// Check whether `ty` contains a raw pointer to one of the `candidates`, and if so, return the
+/// struct's `DefId`.
+fn mentions_shared_adt(candidates: &HashSet<DefId>, ty: Ty) -> Option<DefId> {
+    ty.walk().find_map(|arg| match arg.unpack() {
+        GenericArgKind::Type(ty) => shared_adt(candidates, ty),
+        _ => None,
+    })
+}
+
+/// Run `SharedPtrUseVisitor` over the signature and body of `ldid`.  `start_routines` maps each
+/// thread start routine to the struct passed as its argument.
+pub fn collect_shared_ptr_uses(
+    tcx: TyCtxt,
+    ldid: LocalDefId,
+    candidates: &HashSet<DefId>,
+    start_routines: &HashMap<DefId, DefId>,
+) -> Vec<(DefId, SharedPtrUse)> {
+    let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+    let body = tcx.hir().body(hir_body_id);
+    let thread_arg = match (start_routines.get(&ldid.to_def_id()), body.params) {
+        (Some(&adt), [param, ..]) => Some((param.pat.hir_id, adt)),
+        _ => None,
+    };
+    let mut v = SharedPtrUseVisitor::new(tcx, tcx.typeck_body(hir_body_id), candidates, thread_arg);
+    let hir_id = tcx.hir().local_def_id_to_hir_id(ldid);
+    if let Some(decl) = tcx.hir().fn_decl_by_hir_id(hir_id) {
+        for ty in decl.inputs {
+            v.visit_ty(ty);
+        }
+        if let hir::FnRetTy::Return(ty) = decl.output {
+            v.visit_ty(ty);
+        }
+    }
+    v.visit_body(body);
+    v.uses
+}
+
+/// Find structs that are passed to other threads through `pthread_create`, and whose pointers can
+/// be rewritten to `Arc<Mutex<T>>`.
+pub fn find_thread_shared_structs(
+    gacx: &GlobalAnalysisCtxt,
+    all_fn_ldids: &[LocalDefId],
+) -> ThreadSharedInfo {
+    let tcx = gacx.tcx;
+
+    // Find the structs that escape to other threads, and the start routines that receive them.
+    let mut escaping = HashSet::new();
+    let mut rejected = HashSet::new();
+    let mut start_routines = HashMap::new();
+    for &ldid in all_fn_ldids {
+        let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+            Some(x) => x,
+            None => continue,
+        };
+        let mut v = ThreadSpawnVisitor {
+            tcx,
+            typeck_results: tcx.typeck_body(hir_body_id),
+            spawns: Vec::new(),
+        };
+        v.visit_body(tcx.hir().body(hir_body_id));
+        for (adt, start) in v.spawns {
+            debug!("struct {adt:?} escapes to thread start routine {start:?}");
+            escaping.insert(adt);
+            match start {
+                Some(start) => {
+                    if let Some(old) = start_routines.insert(start, adt) {
+                        if old != adt {
+                            debug!("start routine {start:?} receives both {old:?} and {adt:?}");
+                            rejected.insert(old);
+                            rejected.insert(adt);
+                        }
+                    }
+                }
+                None => {
+                    rejected.insert(adt);
+                }
+            }
+        }
+    }
+
+    // Candidates must not cross an FFI boundary (other than as the `void*` thread argument), and
+    // must not be stored in struct fields or statics, since `Arc` isn't `Copy`.  Structs handled
+    // by the `Rc<RefCell<T>>` rewrite are left alone.
+    let mut candidates = escaping
+        .into_iter()
+        .filter(|did| {
+            !gacx.foreign_mentioned_tys.contains(did) && !gacx.refcount.structs.contains_key(did)
+        })
+        .collect::<HashSet<_>>();
+    for &adt_did in &gacx.adt_metadata.struct_dids {
+        for field in tcx.adt_def(adt_did).all_fields() {
+            if let Some(did) = mentions_shared_adt(&candidates, tcx.type_of(field.did)) {
+                rejected.insert(did);
+            }
+        }
+    }
+    for &did in gacx.static_tys.keys() {
+        if let Some(did) = mentions_shared_adt(&candidates, tcx.type_of(did)) {
+            rejected.insert(did);
+        }
+    }
+    candidates.retain(|did| !rejected.contains(did));
+    if candidates.is_empty() {
+        return ThreadSharedInfo::default();
+    }
+    start_routines.retain(|_, adt| candidates.contains(adt));
+
+    let mut fns = HashMap::new();
+    for &ldid in all_fn_ldids {
+        let mir = tcx.mir_built(WithOptConstParam::unknown(ldid));
+        let mir = mir.borrow();
+        let adts = mir
+            .local_decls
+            .iter()
+            .filter_map(|decl| mentions_shared_adt(&candidates, decl.ty))
+            .collect::<HashSet<_>>();
+        if !adts.is_empty() {
+            fns.insert(ldid, adts);
+        }
+    }
+
+    for &ldid in fns.keys() {
+        for (did, u) in collect_shared_ptr_uses(tcx, ldid, &candidates, &start_routines) {
+            if let SharedPtrUse::Other = u {
+                rejected.insert(did);
+            }
+        }
+    }
+    candidates.retain(|did| !rejected.contains(did));
+
+    let mut info = ThreadSharedInfo {
+        structs: candidates,
+        ..ThreadSharedInfo::default()
+    };
+    for did in &info.structs {
+        debug!("found thread-shared struct {did:?}");
+    }
+    for (ldid, adts) in fns {
+        if adts.iter().any(|did| info.structs.contains(did)) {
+            info.fns.insert(ldid.to_def_id());
+        }
+    }
+    info.start_routines = start_routines
+        .into_iter()
+        .filter(|(_, adt)| info.structs.contains(adt))
+        .collect();
+    info
+}
@@ -1,6 +1,7 @@
 use crate::context::{FlagSet, PermissionSet};
+use crate::static_kinds::atomic_ty_name;
 use rustc_middle::mir::Mutability;
-use rustc_middle::ty::{AdtDef, Ty, TyCtxt, TyKind};
+use rustc_middle::ty::{AdtDef, IntTy, Ty, TyCtxt, TyKind, UintTy};
 
 #[allow(dead_code)]
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
@@ -13,6 +14,12 @@ pub enum Ownership {
     Imm,
     /// E.g. `&Cell<T>`
     Cell,
+    /// E.g. `&RefCell<T>`.  Used instead of `Cell` for structs, whose fields are accessed in place
+    /// through `borrow()` and `borrow_mut()` rather than copied in and out as a whole.
+    RefCell,
+    /// E.g. `&AtomicI32` for a pointee of type `i32`, or `&AtomicPtr<T>` for `*mut T`.  See
+    /// [`atomic_ty_path`].
+    Atomic,
     /// E.g. `&mut T`
     Mut,
     /// E.g. `Rc<T>`
@@ -29,6 +36,9 @@ pub enum Quantity {
     Slice,
     /// E.g. `OffsetPtr<T>`
     OffsetPtr,
+    /// `&CStr`, which replaces `&[c_char]` for read-only pointers to NUL-terminated strings.  See
+    /// [`FlagSet::CSTR`].
+    CStr,
 
     /// E.g. `&[T; 10]`.  This is used only for existing `FIXED` pointers; `perms_to_desc` on a raw
     /// pointer never produces `Array`.
@@ -77,14 +87,27 @@ impl PtrDesc {
 impl Ownership {
     pub fn is_copy(&self) -> bool {
         match *self {
-            Ownership::Raw | Ownership::RawMut | Ownership::Imm | Ownership::Cell => true,
+            Ownership::Raw
+            | Ownership::RawMut
+            | Ownership::Imm
+            | Ownership::Cell
+            | Ownership::RefCell
+            | Ownership::Atomic => true,
             Ownership::Mut | Ownership::Rc | Ownership::Box => false,
         }
     }
 }
 
 fn perms_to_ptr_desc(perms: PermissionSet, flags: FlagSet) -> PtrDesc {
-    let own = if perms.contains(PermissionSet::UNIQUE | PermissionSet::WRITE) {
+    let own = if flags.contains(FlagSet::ATOMIC) {
+        Ownership::Atomic
+    } else if perms.contains(PermissionSet::UNIQUE | PermissionSet::FREE)
+        && !flags.intersects(FlagSet::STACK | FlagSet::STATIC)
+    {
+        // A unique pointer that eventually gets freed owns its allocation.  A pointer that may
+        // point to a local or a static doesn't own its pointee, even if it's passed to `free`.
+        Ownership::Box
+    } else if perms.contains(PermissionSet::UNIQUE | PermissionSet::WRITE) {
         Ownership::Mut
     } else if flags.contains(FlagSet::CELL) {
         Ownership::Cell
@@ -94,7 +117,9 @@ fn perms_to_ptr_desc(perms: PermissionSet, flags: FlagSet) -> PtrDesc {
         Ownership::Imm
     };
 
-    let qty = if perms.contains(PermissionSet::OFFSET_SUB) {
+    let qty = if flags.contains(FlagSet::CSTR) && own == Ownership::Imm {
+        Quantity::CStr
+    } else if perms.contains(PermissionSet::OFFSET_SUB) {
         Quantity::OffsetPtr
     } else if perms.contains(PermissionSet::OFFSET_ADD) {
         Quantity::Slice
@@ -107,6 +132,56 @@ fn perms_to_ptr_desc(perms: PermissionSet, flags: FlagSet) -> PtrDesc {
     PtrDesc { own, qty, option }
 }
 
+/// Return the path of the atomic type that replaces `ty` behind an `Ownership::Atomic` pointer.
+/// Integers use the atomic integer type of the same size, and `*mut T` uses `AtomicPtr`, which
+/// takes `T` as its type argument.  Other types have no atomic equivalent.
+pub fn atomic_ty_path(ty: Ty) -> Option<String> {
+    match *ty.kind() {
+        TyKind::RawPtr(tm) if tm.mutbl == Mutability::Mut => {
+            Some("core::sync::atomic::AtomicPtr".into())
+        }
+        _ => atomic_ty_name(ty).map(|name| format!("core::sync::atomic::{name}")),
+    }
+}
+
+/// Compute the `PtrDesc` of a pointer to `pointee_ty`.  `ATOMIC` is ignored if the pointee has no
+/// atomic equivalent, which can happen when it spreads to a pointer to the enclosing struct.  The
+/// casts between the two are rejected when rewriting, which leaves the pointers involved `FIXED`.
+/// Similarly, `CSTR` is ignored unless the pointee is `c_char`, which is `i8` or `u8` depending on
+/// the target.
+fn perms_to_ptr_desc_with_pointee(perms: PermissionSet, flags: FlagSet, pointee_ty: Ty) -> PtrDesc {
+    let mut flags = flags;
+    if atomic_ty_path(pointee_ty).is_none() {
+        flags.remove(FlagSet::ATOMIC);
+    }
+    if !matches!(
+        *pointee_ty.kind(),
+        TyKind::Int(IntTy::I8) | TyKind::Uint(UintTy::U8)
+    ) {
+        flags.remove(FlagSet::CSTR);
+    }
+    refine_cell(perms_to_ptr_desc(perms, flags), pointee_ty)
+}
+
+/// Refine the ownership of a pointer that's written through while aliased, based on its pointee.
+/// A `Cell` can only be read and written as a whole, so a single struct gets a `RefCell` instead,
+/// which allows its fields to be accessed in place.
+fn refine_cell(ptr_desc: PtrDesc, pointee_ty: Ty) -> PtrDesc {
+    match *pointee_ty.kind() {
+        TyKind::Adt(adt_def, _)
+            if ptr_desc.own == Ownership::Cell
+                && ptr_desc.qty == Quantity::Single
+                && adt_def.is_struct() =>
+        {
+            PtrDesc {
+                own: Ownership::RefCell,
+                ..ptr_desc
+            }
+        }
+        _ => ptr_desc,
+    }
+}
+
 /// Obtain the `TypeDesc` for a pointer.  `ptr_ty` should be the `Ty` of the pointer, and `perms`
 /// and `flags` should be taken from its outermost `PointerId`.
 pub fn perms_to_desc(ptr_ty: Ty, perms: PermissionSet, flags: FlagSet) -> TypeDesc {
@@ -116,8 +191,6 @@ pub fn perms_to_desc(ptr_ty: Ty, perms: PermissionSet, flags: FlagSet) -> TypeDe
         "building TypeDesc for FIXED pointer requires a related pointee type"
     );
 
-    let ptr_desc = perms_to_ptr_desc(perms, flags);
-
     let pointee_ty = match *ptr_ty.kind() {
         TyKind::Ref(_, ty, _) => ty,
         TyKind::RawPtr(mt) => mt.ty,
@@ -126,15 +199,14 @@ pub fn perms_to_desc(ptr_ty: Ty, perms: PermissionSet, flags: FlagSet) -> TypeDe
         _ => panic!("expected a pointer type, but got {:?}", ptr_ty),
     };
 
-    ptr_desc.to_type_desc(pointee_ty)
+    perms_to_ptr_desc_with_pointee(perms, flags, pointee_ty).to_type_desc(pointee_ty)
 }
 
 /// Obtain the `TypeDesc` for a pointer to a local.  `local_ty` should be the `Ty` of the local
 /// itself, and `perms` and `flags` should be taken from its `addr_of_local` `PointerId`.
 pub fn local_perms_to_desc(local_ty: Ty, perms: PermissionSet, flags: FlagSet) -> TypeDesc {
-    let ptr_desc = perms_to_ptr_desc(perms, flags);
     let pointee_ty = local_ty;
-    ptr_desc.to_type_desc(pointee_ty)
+    perms_to_ptr_desc_with_pointee(perms, flags, pointee_ty).to_type_desc(pointee_ty)
 }
 
 pub fn perms_to_desc_with_pointee<'tcx>(
@@ -147,7 +219,7 @@ pub fn perms_to_desc_with_pointee<'tcx>(
     let ptr_desc = if flags.contains(FlagSet::FIXED) {
         unpack_pointer_type(tcx, ptr_ty, pointee_ty)
     } else {
-        perms_to_ptr_desc(perms, flags)
+        perms_to_ptr_desc_with_pointee(perms, flags, pointee_ty)
     };
     ptr_desc.to_type_desc(pointee_ty)
 }
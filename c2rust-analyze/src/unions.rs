@@ -0,0 +1,786 @@
+//! Detection of C unions whose active field can be tracked, so they can be rewritten to enums.
+//!
+//! C code usually pairs a union with a tag that records which of its fields is in use:
+//!
+//! ```ignore
+//! pub union value_data {
+//!     pub i: libc::c_int,
+//!     pub f: libc::c_float,
+//! }
+//!
+//! pub struct value {
+//!     pub kind: libc::c_int,
+//!     pub data: value_data,
+//! }
+//!
+//! (*v).kind = 0;
+//! (*v).data.i = x;
+//!
+//! if (*v).kind == 0 {
+//!     return (*v).data.i;
+//! }
+//! ```
+//!
+//! Writes are the evidence for which tag value goes with which field: storing a tag value and
+//! writing a field of a union in the same struct, in the same block, associates the two.  With
+//! that association, we work out which field is active at each read.  A field is known to be
+//! active where its tag value was checked, in an `if` or in an arm of a `match` on the tag, and
+//! after the field was written, until something might write another field or change the tag.
+//!
+//! If every read of a union's fields happens where that field is known to be active, the union
+//! is rewritten to an `enum` with one variant per field, and accessor methods that `match` on the
+//! variant.  Reads call the accessors, writes replace the whole value with the new variant, and
+//! union literals become variant constructors.  The tag field is left in place.
+//!
+//! Functions that access the fields of these unions are excluded from the normal rewrites and
+//! handled entirely by `rewrite::gen_union_rewrites`.
+
+use crate::context::GlobalAnalysisCtxt;
+use log::debug;
+use rustc_ast::LitKind;
+use rustc_hir as hir;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{BinOpKind, Block, Expr, ExprKind, HirId, Pat, PatKind, StmtKind, UnOp};
+use rustc_middle::ty::adjustment::{Adjust, AutoBorrow, AutoBorrowMutability};
+use rustc_middle::ty::{Ty, TyCtxt, TyKind, TypeckResults};
+use rustc_span::symbol::Symbol;
+use rustc_span::Span;
+use std::collections::{HashMap, HashSet};
+
+/// A single use of a union that will be rewritten to an enum.
+#[derive(Clone, Copy, Debug)]
+pub enum UnionUse {
+    /// A field is read, as in `(*v).data.i`.  `union_expr` is the union itself, `(*v).data`.
+    /// `mutbl` is set if the field is used as a mutable place, as in `(*v).data.i += 1`.
+    Read {
+        expr: Span,
+        union_expr: Span,
+        field: Symbol,
+        mutbl: bool,
+    },
+    /// A field is written, as in `(*v).data.i = x`.
+    Write {
+        expr: Span,
+        union_expr: Span,
+        field: Symbol,
+        value: Span,
+    },
+    /// A union is built, as in `value_data { i: x }`.
+    Literal {
+        expr: Span,
+        field: Symbol,
+        value: Span,
+    },
+    /// A read of a field that isn't known to be active, or any other unsupported use.  This
+    /// prevents the union from being rewritten.
+    Other,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct UnionInfo {
+    /// Unions that will be rewritten to enums.
+    pub unions: HashSet<DefId>,
+    /// Functions that use any of the `unions`.  These are excluded from the normal rewrites and
+    /// handled entirely by `rewrite::gen_union_rewrites`.
+    pub fns: HashSet<DefId>,
+    /// The tag values associated with each union field, used to find the active field.
+    tags: TagMap,
+}
+
+/// A value stored in or compared against a tag field, as in the `0` or `KIND_INT` of
+/// `(*v).kind = KIND_INT`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum TagValue {
+    Int(u128),
+    Const(DefId),
+}
+
+/// Tag values associated with union fields, keyed by `(tag field, union field, value)`.  Each
+/// entry is the field of the union that's active when the tag has that value, or `None` if the
+/// writes disagree.
+type TagMap = HashMap<(DefId, DefId, TagValue), Option<DefId>>;
+
+/// A union stored in a particular place.  Two accesses with the same key refer to the same union.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct UnionKey {
+    /// The local variable that holds the union or the struct containing it, or a pointer to
+    /// either.
+    owner: HirId,
+    /// The field of the struct that holds the union, or `None` if `owner` holds the union
+    /// directly.
+    field: Option<DefId>,
+}
+
+/// An access to a field of one of the candidate unions, as in `(*v).data.i`.
+#[derive(Clone, Copy, Debug)]
+struct UnionPlace<'tcx> {
+    union_did: DefId,
+    key: UnionKey,
+    /// The union itself, as in `(*v).data`.
+    union_expr: &'tcx Expr<'tcx>,
+    field: DefId,
+    field_name: Symbol,
+}
+
+/// Helpers for recognizing union and tag accesses, shared by the visitors below.
+struct Matcher<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    unions: &'a HashSet<DefId>,
+}
+
+impl<'a, 'tcx> Matcher<'a, 'tcx> {
+    /// If `ex` is a field access, return the field's `DefId` and the base expression.
+    fn field(&self, ex: &'tcx Expr<'tcx>) -> Option<(DefId, &'tcx Expr<'tcx>)> {
+        let base = match ex.kind {
+            ExprKind::Field(base, _) => base,
+            _ => return None,
+        };
+        let adt_def = match *self
+            .typeck_results
+            .expr_ty_adjusted(base)
+            .peel_refs()
+            .kind()
+        {
+            TyKind::Adt(adt_def, _) if !adt_def.is_enum() => adt_def,
+            _ => return None,
+        };
+        let idx = *self.typeck_results.field_indices().get(ex.hir_id)?;
+        let did = adt_def.non_enum_variant().fields.get(idx)?.did;
+        Some((did, base))
+    }
+
+    /// If `ex` accesses a field of one of the `unions`, return the access.
+    fn union_place(&self, ex: &'tcx Expr<'tcx>) -> Option<UnionPlace<'tcx>> {
+        let (field, union_expr) = self.field(ex)?;
+        let union_did = self.tcx.parent(field);
+        if !self.unions.contains(&union_did) {
+            return None;
+        }
+        let key = match self.field(union_expr) {
+            Some((struct_field, struct_expr)) => UnionKey {
+                owner: owner_var(struct_expr)?,
+                field: Some(struct_field),
+            },
+            None => UnionKey {
+                owner: owner_var(union_expr)?,
+                field: None,
+            },
+        };
+        Some(UnionPlace {
+            union_did,
+            key,
+            union_expr,
+            field,
+            field_name: self.tcx.item_name(field),
+        })
+    }
+
+    /// If `ex` accesses an integer field of a struct that also holds one of the `unions`, return
+    /// the field and the local that holds the struct.
+    fn tag_field(&self, ex: &'tcx Expr<'tcx>) -> Option<(DefId, HirId)> {
+        let (field, base) = self.field(peel_casts(ex))?;
+        if !self.tcx.type_of(field).is_integral() || self.union_fields(field).next().is_none() {
+            return None;
+        }
+        Some((field, owner_var(base)?))
+    }
+
+    /// The fields of the struct containing `tag_field` that hold one of the `unions`.
+    fn union_fields(&self, tag_field: DefId) -> impl Iterator<Item = DefId> + '_ {
+        let adt_def = self.tcx.adt_def(self.tcx.parent(tag_field));
+        adt_def
+            .all_fields()
+            .filter(move |f| match *self.tcx.type_of(f.did).kind() {
+                TyKind::Adt(adt_def, _) => self.unions.contains(&adt_def.did()),
+                _ => false,
+            })
+            .map(|f| f.did)
+    }
+
+    fn tag_value(&self, ex: &Expr) -> Option<TagValue> {
+        match peel_casts(ex).kind {
+            ExprKind::Lit(ref lit) => match lit.node {
+                LitKind::Int(x, _) => Some(TagValue::Int(x)),
+                _ => None,
+            },
+            ExprKind::Path(hir::QPath::Resolved(_, path)) => match path.res {
+                Res::Def(DefKind::Const, did) => Some(TagValue::Const(did)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn pat_tag_values(&self, pat: &Pat) -> Option<Vec<TagValue>> {
+        match pat.kind {
+            PatKind::Lit(ex) => Some(vec![self.tag_value(ex)?]),
+            PatKind::Path(hir::QPath::Resolved(_, path)) => match path.res {
+                Res::Def(DefKind::Const, did) => Some(vec![TagValue::Const(did)]),
+                _ => None,
+            },
+            PatKind::Or(pats) => {
+                let mut values = Vec::new();
+                for p in pats {
+                    values.extend(self.pat_tag_values(p)?);
+                }
+                Some(values)
+            }
+            _ => None,
+        }
+    }
+
+    /// If `ex` is a store to a tag field, as in `(*v).kind = 0`, return the field, the local that
+    /// holds the struct, and the value, if it's a constant.
+    fn tag_store(&self, ex: &'tcx Expr<'tcx>) -> Option<(DefId, HirId, Option<TagValue>)> {
+        match ex.kind {
+            ExprKind::Assign(lhs, rhs, _) => {
+                let (field, owner) = self.tag_field(lhs)?;
+                Some((field, owner, self.tag_value(rhs)))
+            }
+            ExprKind::AssignOp(_, lhs, _) => {
+                let (field, owner) = self.tag_field(lhs)?;
+                Some((field, owner, None))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Visitor that associates tag values with union fields, using writes of both in the same block.
+struct TagWriteVisitor<'a, 'tcx> {
+    m: Matcher<'a, 'tcx>,
+    tags: &'a mut TagMap,
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for TagWriteVisitor<'a, 'tcx> {
+    fn visit_block(&mut self, block: &'tcx Block<'tcx>) {
+        let exprs = block
+            .stmts
+            .iter()
+            .filter_map(|stmt| match stmt.kind {
+                StmtKind::Semi(ex) | StmtKind::Expr(ex) => Some(ex),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for &tag_ex in &exprs {
+            let (tag_field, owner, value) = match self.m.tag_store(tag_ex) {
+                Some((f, o, Some(v))) => (f, o, v),
+                _ => continue,
+            };
+            for &ex in &exprs {
+                let up = match ex.kind {
+                    ExprKind::Assign(lhs, ..) => self.m.union_place(lhs),
+                    _ => None,
+                };
+                let up = match up {
+                    Some(x) if x.key.owner == owner => x,
+                    _ => continue,
+                };
+                let struct_field = match up.key.field {
+                    Some(x) if self.m.tcx.parent(x) == self.m.tcx.parent(tag_field) => x,
+                    _ => continue,
+                };
+                let entry = self
+                    .tags
+                    .entry((tag_field, struct_field, value))
+                    .or_insert(Some(up.field));
+                if *entry != Some(up.field) {
+                    *entry = None;
+                }
+            }
+        }
+        intravisit::walk_block(self, block);
+    }
+}
+
+/// Visitor that finds the keys of the unions whose active field might change within a loop.
+struct WrittenKeys<'a, 'b, 'tcx> {
+    m: &'b Matcher<'a, 'tcx>,
+    keys: HashSet<UnionKey>,
+}
+
+impl<'a, 'b, 'tcx> Visitor<'tcx> for WrittenKeys<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        if let ExprKind::Assign(lhs, ..) = ex.kind {
+            if let Some(up) = self.m.union_place(lhs) {
+                self.keys.insert(up.key);
+            }
+        }
+        if let Some((tag_field, owner, _)) = self.m.tag_store(ex) {
+            for field in self.m.union_fields(tag_field) {
+                self.keys.insert(UnionKey {
+                    owner,
+                    field: Some(field),
+                });
+            }
+        }
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Visitor that finds all uses of the fields of the candidate unions within a body, and checks
+/// that each read happens where the field is known to be active.
+struct UnionUseVisitor<'a, 'tcx> {
+    m: Matcher<'a, 'tcx>,
+    tags: &'a TagMap,
+    /// The field known to be active in each union, for each enclosing scope.  Lookups go from the
+    /// innermost scope outward.
+    scopes: Vec<HashMap<UnionKey, DefId>>,
+    uses: Vec<(DefId, UnionUse)>,
+}
+
+impl<'a, 'tcx> UnionUseVisitor<'a, 'tcx> {
+    fn active_field(&self, key: UnionKey) -> Option<DefId> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&key).copied())
+    }
+
+    /// Record that the active field of `key` changed.  Facts in enclosing scopes no longer hold
+    /// once control leaves the current scope, so they're removed.
+    fn set_active_field(&mut self, key: UnionKey, field: Option<DefId>) {
+        for scope in &mut self.scopes {
+            scope.remove(&key);
+        }
+        if let (Some(field), Some(scope)) = (field, self.scopes.last_mut()) {
+            scope.insert(key, field);
+        }
+    }
+
+    /// The active fields implied by the tag of `owner` having one of `values`.
+    fn tag_facts(
+        &self,
+        tag_field: DefId,
+        owner: HirId,
+        values: &[TagValue],
+    ) -> Vec<(UnionKey, DefId)> {
+        let mut facts = Vec::new();
+        for union_field in self.m.union_fields(tag_field) {
+            let mut variants = values.iter().map(|&v| {
+                self.tags
+                    .get(&(tag_field, union_field, v))
+                    .copied()
+                    .flatten()
+            });
+            let first = match variants.next() {
+                Some(Some(x)) => x,
+                _ => continue,
+            };
+            if variants.all(|v| v == Some(first)) {
+                let key = UnionKey {
+                    owner,
+                    field: Some(union_field),
+                };
+                facts.push((key, first));
+            }
+        }
+        facts
+    }
+
+    /// If `cond` checks the tag, as in `(*v).kind == 0`, return the active fields implied when
+    /// `cond` is true, and those implied when it's false.
+    fn cond_facts(
+        &self,
+        cond: &'tcx Expr<'tcx>,
+    ) -> (Vec<(UnionKey, DefId)>, Vec<(UnionKey, DefId)>) {
+        let (op, a, b) = match cond.kind {
+            ExprKind::Binary(op, a, b) => (op.node, a, b),
+            ExprKind::DropTemps(inner) => return self.cond_facts(inner),
+            _ => return (Vec::new(), Vec::new()),
+        };
+        let check = |tag: &'tcx Expr<'tcx>, value: &'tcx Expr<'tcx>| {
+            let (field, owner) = self.m.tag_field(tag)?;
+            Some(self.tag_facts(field, owner, &[self.m.tag_value(value)?]))
+        };
+        let facts = check(a, b).or_else(|| check(b, a)).unwrap_or_default();
+        match op {
+            BinOpKind::Eq => (facts, Vec::new()),
+            BinOpKind::Ne => (Vec::new(), facts),
+            _ => (Vec::new(), Vec::new()),
+        }
+    }
+
+    fn with_scope(&mut self, facts: Vec<(UnionKey, DefId)>, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(facts.into_iter().collect());
+        f(self);
+        self.scopes.pop();
+    }
+
+    /// Record a read of `up`, which must be known to be active.
+    fn read(&mut self, ex: &'tcx Expr<'tcx>, up: UnionPlace<'tcx>, mutbl: bool) {
+        if self.active_field(up.key) != Some(up.field) {
+            debug!(
+                "read of union field {:?} at {:?} isn't known to be active",
+                up.field, ex.span
+            );
+            self.uses.push((up.union_did, UnionUse::Other));
+            return;
+        }
+        self.uses.push((
+            up.union_did,
+            UnionUse::Read {
+                expr: ex.span,
+                union_expr: up.union_expr.span,
+                field: up.field_name,
+                mutbl,
+            },
+        ));
+    }
+
+    /// Visit an expression used as a mutable place.  A union field within the place, as in
+    /// `(*v).data.p.x`, is accessed mutably.
+    fn visit_place_mut(&mut self, ex: &'tcx Expr<'tcx>) {
+        if let Some(up) = self.m.union_place(ex) {
+            self.read(ex, up, true);
+            return;
+        }
+        match ex.kind {
+            ExprKind::Field(base, _) => self.visit_place_mut(base),
+            ExprKind::Index(base, idx) => {
+                self.visit_place_mut(base);
+                self.visit_expr(idx);
+            }
+            _ => self.visit_expr(ex),
+        }
+    }
+
+    fn is_mut_autoref(&self, ex: &Expr) -> bool {
+        self.m
+            .typeck_results
+            .expr_adjustments(ex)
+            .iter()
+            .any(|adj| {
+                matches!(
+                    adj.kind,
+                    Adjust::Borrow(AutoBorrow::Ref(_, AutoBorrowMutability::Mut { .. }))
+                )
+            })
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for UnionUseVisitor<'a, 'tcx> {
+    fn visit_block(&mut self, block: &'tcx Block<'tcx>) {
+        self.with_scope(Vec::new(), |v| intravisit::walk_block(v, block));
+    }
+
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            // `if (*v).kind == 0 { .. } else { .. }`
+            ExprKind::If(cond, then, els) => {
+                self.visit_expr(cond);
+                let (if_true, if_false) = self.cond_facts(cond);
+                self.with_scope(if_true, |v| v.visit_expr(then));
+                if let Some(els) = els {
+                    self.with_scope(if_false, |v| v.visit_expr(els));
+                }
+                return;
+            }
+
+            // `match (*v).kind { 0 => .., _ => .. }`
+            ExprKind::Match(scrut, arms, _) => {
+                self.visit_expr(scrut);
+                let tag = self.m.tag_field(scrut);
+                for arm in arms {
+                    let facts = match (tag, self.m.pat_tag_values(arm.pat)) {
+                        (Some((field, owner)), Some(values)) => {
+                            self.tag_facts(field, owner, &values)
+                        }
+                        _ => Vec::new(),
+                    };
+                    self.with_scope(facts, |v| v.visit_arm(arm));
+                }
+                return;
+            }
+
+            // A write later in the loop body may change the active field seen at the start of
+            // the next iteration.
+            ExprKind::Loop(body, ..) => {
+                let mut written = WrittenKeys {
+                    m: &self.m,
+                    keys: HashSet::new(),
+                };
+                written.visit_block(body);
+                for key in written.keys {
+                    self.set_active_field(key, None);
+                }
+            }
+
+            ExprKind::Assign(lhs, rhs, _) => {
+                // `(*v).data.i = x`
+                if let Some(up) = self.m.union_place(lhs) {
+                    self.visit_expr(rhs);
+                    self.uses.push((
+                        up.union_did,
+                        UnionUse::Write {
+                            expr: ex.span,
+                            union_expr: up.union_expr.span,
+                            field: up.field_name,
+                            value: rhs.span,
+                        },
+                    ));
+                    self.set_active_field(up.key, Some(up.field));
+                    return;
+                }
+                // `(*v).kind = 0`
+                if let Some((tag_field, owner, value)) = self.m.tag_store(ex) {
+                    self.visit_expr(rhs);
+                    let facts = value
+                        .map_or_else(Vec::new, |value| self.tag_facts(tag_field, owner, &[value]));
+                    for union_field in self.m.union_fields(tag_field).collect::<Vec<_>>() {
+                        let key = UnionKey {
+                            owner,
+                            field: Some(union_field),
+                        };
+                        let active = facts.iter().find(|&&(k, _)| k == key).map(|&(_, f)| f);
+                        self.set_active_field(key, active);
+                    }
+                    return;
+                }
+                self.visit_place_mut(lhs);
+                self.visit_expr(rhs);
+                return;
+            }
+
+            ExprKind::AssignOp(_, lhs, rhs) => {
+                if let Some((tag_field, owner, _)) = self.m.tag_store(ex) {
+                    for union_field in self.m.union_fields(tag_field).collect::<Vec<_>>() {
+                        let key = UnionKey {
+                            owner,
+                            field: Some(union_field),
+                        };
+                        self.set_active_field(key, None);
+                    }
+                }
+                self.visit_place_mut(lhs);
+                self.visit_expr(rhs);
+                return;
+            }
+
+            ExprKind::AddrOf(_, hir::Mutability::Mut, pl) => {
+                self.visit_place_mut(pl);
+                return;
+            }
+
+            // `(*v).data.buf.as_mut_ptr()`
+            ExprKind::MethodCall(_, [recv, args @ ..], _) if self.is_mut_autoref(recv) => {
+                self.visit_place_mut(recv);
+                for arg in args {
+                    self.visit_expr(arg);
+                }
+                return;
+            }
+
+            // The callee may change the active field through a pointer.
+            ExprKind::Call(..) => {
+                intravisit::walk_expr(self, ex);
+                for scope in &mut self.scopes {
+                    scope.clear();
+                }
+                return;
+            }
+
+            // `(*v).data.i`
+            ExprKind::Field(..) => {
+                if let Some(up) = self.m.union_place(ex) {
+                    self.read(ex, up, self.is_mut_autoref(ex));
+                    return;
+                }
+            }
+
+            // `value_data { i: x }`
+            ExprKind::Struct(_, fields, base) => {
+                if let TyKind::Adt(adt_def, _) = *self.m.typeck_results.expr_ty(ex).kind() {
+                    if self.m.unions.contains(&adt_def.did()) {
+                        match (fields, base) {
+                            ([field], None) => {
+                                self.visit_expr(field.expr);
+                                self.uses.push((
+                                    adt_def.did(),
+                                    UnionUse::Literal {
+                                        expr: ex.span,
+                                        field: field.ident.name,
+                                        value: field.expr.span,
+                                    },
+                                ));
+                                return;
+                            }
+                            _ => self.uses.push((adt_def.did(), UnionUse::Other)),
+                        }
+                    }
+                }
+            }
+
+            // `&mut (*v).data as *mut value_data as *mut i32` reinterprets the union's storage.
+            ExprKind::Cast(inner, _) => {
+                let pointee = |ty: Ty<'tcx>| match *ty.kind() {
+                    TyKind::RawPtr(tm) => tm.ty.ty_adt_def().map(|adt_def| adt_def.did()),
+                    _ => None,
+                };
+                let from = pointee(self.m.typeck_results.expr_ty(inner));
+                let to = pointee(self.m.typeck_results.expr_ty(ex));
+                if let Some(did) = from.filter(|did| self.m.unions.contains(did)) {
+                    if to != from {
+                        self.uses.push((did, UnionUse::Other));
+                    }
+                }
+            }
+
+            _ => {}
+        }
+
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// If `ex` is a local, or a dereference of a local pointer, return the local.
+fn owner_var(ex: &Expr) -> Option<HirId> {
+    let ex = match ex.kind {
+        ExprKind::Unary(UnOp::Deref, ptr) => ptr,
+        _ => ex,
+    };
+    match ex.kind {
+        ExprKind::Path(hir::QPath::Resolved(_, path)) => match path.res {
+            Res::Local(hir_id) => Some(hir_id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn peel_casts<'a, 'tcx>(mut ex: &'a Expr<'tcx>) -> &'a Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+/// Associate tag values with union fields, using the writes in every body.
+fn collect_tags(tcx: TyCtxt, all_fn_ldids: &[LocalDefId], unions: &HashSet<DefId>) -> TagMap {
+    let mut tags = TagMap::new();
+    for &ldid in all_fn_ldids {
+        let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+            Some(x) => x,
+            None => continue,
+        };
+        let mut v = TagWriteVisitor {
+            m: Matcher {
+                tcx,
+                typeck_results: tcx.typeck_body(hir_body_id),
+                unions,
+            },
+            tags: &mut tags,
+        };
+        v.visit_body(tcx.hir().body(hir_body_id));
+    }
+    tags
+}
+
+/// Run `UnionUseVisitor` over the body of `ldid`.
+pub fn collect_union_uses(
+    tcx: TyCtxt,
+    ldid: LocalDefId,
+    info: &UnionInfo,
+) -> Vec<(DefId, UnionUse)> {
+    collect_union_uses_with_tags(tcx, ldid, &info.unions, &info.tags)
+}
+
+fn collect_union_uses_with_tags(
+    tcx: TyCtxt,
+    ldid: LocalDefId,
+    unions: &HashSet<DefId>,
+    tags: &TagMap,
+) -> Vec<(DefId, UnionUse)> {
+    let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+    let mut v = UnionUseVisitor {
+        m: Matcher {
+            tcx,
+            typeck_results: tcx.typeck_body(hir_body_id),
+            unions,
+        },
+        tags,
+        scopes: Vec::new(),
+        uses: Vec::new(),
+    };
+    v.visit_body(tcx.hir().body(hir_body_id));
+    v.uses
+}
+
+/// Find unions whose accesses are all discriminated, so they can be rewritten to enums.
+pub fn find_union_enums(gacx: &GlobalAnalysisCtxt, all_fn_ldids: &[LocalDefId]) -> UnionInfo {
+    let tcx = gacx.tcx;
+
+    // Candidates are local unions that don't cross an FFI boundary, aren't stored in a `static`,
+    // and have no pointers in their fields, which would be rewritten by the normal type rewrites.
+    let mut static_adts = HashSet::new();
+    for &did in gacx.static_tys.keys() {
+        for ty in tcx.type_of(did).walk() {
+            if let Some(ty) = ty.as_type() {
+                if let Some(adt_def) = ty.ty_adt_def() {
+                    static_adts.insert(adt_def.did());
+                }
+            }
+        }
+    }
+    let mut candidates = HashSet::new();
+    for &adt_did in &gacx.adt_metadata.struct_dids {
+        if !adt_did.is_local()
+            || gacx.foreign_mentioned_tys.contains(&adt_did)
+            || static_adts.contains(&adt_did)
+        {
+            continue;
+        }
+        if !matches!(tcx.def_kind(adt_did), DefKind::Union) {
+            continue;
+        }
+        let has_ptrs = tcx.adt_def(adt_did).all_fields().any(|f| {
+            tcx.type_of(f.did)
+                .walk()
+                .any(|arg| arg.as_type().map_or(false, |ty| ty.is_any_ptr()))
+        });
+        if has_ptrs || !gacx.adt_metadata.table[&adt_did].lifetime_params.is_empty() {
+            continue;
+        }
+        candidates.insert(adt_did);
+    }
+    if candidates.is_empty() {
+        return UnionInfo::default();
+    }
+
+    let tags = collect_tags(tcx, all_fn_ldids, &candidates);
+    let mut ok = candidates.clone();
+    let mut fn_unions = HashMap::new();
+    for &ldid in all_fn_ldids {
+        let mut dids = HashSet::new();
+        for (did, u) in collect_union_uses_with_tags(tcx, ldid, &candidates, &tags) {
+            if let UnionUse::Other = u {
+                ok.remove(&did);
+            }
+            dids.insert(did);
+        }
+        if !dids.is_empty() {
+            fn_unions.insert(ldid, dids);
+        }
+    }
+
+    let mut info = UnionInfo {
+        tags,
+        ..UnionInfo::default()
+    };
+    for did in ok {
+        debug!("found discriminated union {did:?}");
+        info.unions.insert(did);
+    }
+    for (ldid, dids) in fn_unions {
+        if dids.iter().any(|did| info.unions.contains(did)) {
+            info.fns.insert(ldid.to_def_id());
+        }
+    }
+    info
+}
@@ -0,0 +1,473 @@
+//! Detection of `void*` user-data parameters that are passed through to callbacks.
+//!
+//! C APIs often take a callback together with a `void *user_data` argument, which they pass back
+//! to the callback without ever looking at it:
+//!
+//! ```ignore
+//! unsafe extern "C" fn for_each(cb: unsafe extern "C" fn(*mut c_void, i32), data: *mut c_void) {
+//!     for i in 0..10 {
+//!         cb(data, i);
+//!     }
+//! }
+//! ```
+//!
+//! Since `for_each` treats `data` as opaque, it can be made generic instead:
+//! `for_each<T>(cb: unsafe extern "C" fn(*mut T, i32), data: *mut T)`.  Each callback passed to
+//! `for_each` can then declare its user-data parameter as a pointer to the type it actually uses,
+//! which is determined by the pointee analysis, and callers cast their user data to `*mut _` to let
+//! the type parameter be inferred from the callback.
+
+use crate::context::{DontRewriteFnReason, GlobalAnalysisCtxt};
+use crate::pointee_type::PointeeTypes;
+use crate::pointer_id::GlobalPointerTable;
+use crate::util;
+use log::debug;
+use rustc_hir as hir;
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_middle::mir::visit::{PlaceContext, Visitor};
+use rustc_middle::mir::{
+    Body, CastKind, Constant, Local, Location, Operand, Rvalue, StatementKind, TerminatorKind,
+};
+use rustc_middle::ty::adjustment::PointerCast;
+use rustc_middle::ty::{GenericParamDefKind, Ty, TyCtxt, TyKind, WithOptConstParam};
+use std::collections::{HashMap, HashSet};
+
+/// A function that takes a callback and a `void*` user-data pointer, and does nothing with the
+/// user data except pass it to the callback.
+#[derive(Clone, Copy, Debug)]
+pub struct RegistrationFn {
+    /// Index of the user-data parameter.
+    pub data_arg: usize,
+    /// Index of the callback parameter.
+    pub cb_arg: usize,
+    /// Index of the user-data parameter in the callback's signature.
+    pub cb_data_arg: usize,
+    /// Whether the user-data pointer is `*mut c_void` rather than `*const c_void`.
+    pub mutbl: bool,
+}
+
+/// A function that is passed as the callback argument of a [`RegistrationFn`].
+#[derive(Clone, Copy, Debug)]
+pub struct Callback<'tcx> {
+    /// Index of the user-data parameter.
+    pub data_arg: usize,
+    /// The type the user data is actually used at within the callback.
+    pub pointee_ty: Ty<'tcx>,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct UserDataCallbacks<'tcx> {
+    pub registration_fns: HashMap<DefId, RegistrationFn>,
+    pub callbacks: HashMap<DefId, Callback<'tcx>>,
+}
+
+fn void_ptr_mutbl<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Option<bool> {
+    match *ty.kind() {
+        TyKind::RawPtr(tm) if util::is_c_void(tcx, tm.ty) => Some(tm.mutbl == hir::Mutability::Mut),
+        _ => None,
+    }
+}
+
+fn const_fn_def(op: &Operand) -> Option<DefId> {
+    match *op.constant()?.ty().kind() {
+        TyKind::FnDef(def_id, _) => Some(def_id),
+        _ => None,
+    }
+}
+
+/// Find user-data registration functions and their callbacks among `all_fn_ldids`.
+pub fn find_user_data_callbacks<'tcx>(
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    all_fn_ldids: &[LocalDefId],
+    pointee_types: &GlobalPointerTable<PointeeTypes<'tcx>>,
+) -> UserDataCallbacks<'tcx> {
+    let tcx = gacx.tcx;
+    let mut udc = UserDataCallbacks::default();
+
+    for &ldid in all_fn_ldids {
+        if let Some(reg) = registration_fn_info(gacx, ldid) {
+            debug!("found user-data registration fn {ldid:?}: {reg:?}");
+            udc.registration_fns.insert(ldid.to_def_id(), reg);
+        }
+    }
+    if udc.registration_fns.is_empty() {
+        return udc;
+    }
+
+    // Count every mention of each function, and the subset of mentions where the function is
+    // passed as the callback argument of a registration fn.  For each callback, we also record
+    // the index of its user-data parameter.
+    let mut mentions = HashMap::<DefId, usize>::new();
+    let mut passed_as_cb = HashMap::<DefId, Vec<usize>>::new();
+    for &ldid in all_fn_ldids {
+        let mir = tcx.mir_built(WithOptConstParam::unknown(ldid));
+        let mir = mir.borrow();
+
+        let mut v = FnMentionVisitor {
+            mentions: &mut mentions,
+        };
+        v.visit_body(&mir);
+
+        // Temporaries passed as the callback argument of a registration fn.
+        let mut cb_temps = HashMap::new();
+        for bb_data in mir.basic_blocks().iter() {
+            if let TerminatorKind::Call {
+                ref func, ref args, ..
+            } = bb_data.terminator().kind
+            {
+                let reg = match const_fn_def(func).and_then(|did| udc.registration_fns.get(&did)) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                if let Some(pl) = args[reg.cb_arg].place() {
+                    if pl.projection.is_empty() {
+                        cb_temps.insert(pl.local, reg.cb_data_arg);
+                    }
+                }
+            }
+        }
+
+        for bb_data in mir.basic_blocks().iter() {
+            for stmt in &bb_data.statements {
+                let (pl, rv) = match stmt.kind {
+                    StatementKind::Assign(ref x) => (x.0, &x.1),
+                    _ => continue,
+                };
+                let cb_data_arg = match cb_temps.get(&pl.local) {
+                    Some(&x) if pl.projection.is_empty() => x,
+                    _ => continue,
+                };
+                if let Rvalue::Cast(CastKind::Pointer(PointerCast::ReifyFnPointer), ref op, _) = *rv
+                {
+                    if let Some(did) = const_fn_def(op) {
+                        passed_as_cb.entry(did).or_default().push(cb_data_arg);
+                    }
+                }
+            }
+        }
+    }
+
+    for (did, data_args) in passed_as_cb {
+        // If the callback is called directly or used in some other way, those uses would still
+        // pass a `void*`, so we can't change its signature.
+        if mentions.get(&did).copied() != Some(data_args.len()) {
+            continue;
+        }
+        let data_arg = data_args[0];
+        if data_args.iter().any(|&i| i != data_arg) {
+            continue;
+        }
+        if let Some(cb) = callback_info(gacx, did, data_arg, pointee_types) {
+            debug!("found user-data callback {did:?}: {cb:?}");
+            udc.callbacks.insert(did, cb);
+        }
+    }
+
+    udc
+}
+
+/// Check whether `ldid` is a registration fn, and if so, return a description of its parameters.
+pub fn registration_fn_info(gacx: &GlobalAnalysisCtxt, ldid: LocalDefId) -> Option<RegistrationFn> {
+    let tcx = gacx.tcx;
+    let did = ldid.to_def_id();
+    if !can_change_sig(gacx, did) || gacx.fn_needs_type_param(did) {
+        return None;
+    }
+
+    let sig = tcx.fn_sig(did).skip_binder();
+    let mut data_params = sig
+        .inputs()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &ty)| Some((i, void_ptr_mutbl(tcx, ty)?)));
+    let (data_arg, mutbl) = data_params.next()?;
+    if data_params.next().is_some() {
+        return None;
+    }
+
+    let mut cb_params = sig.inputs().iter().enumerate().filter_map(|(i, &ty)| {
+        let cb_sig = match *ty.kind() {
+            TyKind::FnPtr(cb_sig) => cb_sig.skip_binder(),
+            _ => return None,
+        };
+        let mut cb_data_params = cb_sig
+            .inputs()
+            .iter()
+            .enumerate()
+            .filter(|&(_, &ty)| void_ptr_mutbl(tcx, ty) == Some(mutbl));
+        let (j, _) = cb_data_params.next()?;
+        if cb_data_params.next().is_some() {
+            return None;
+        }
+        Some((i, j))
+    });
+    let (cb_arg, cb_data_arg) = cb_params.next()?;
+    if cb_params.next().is_some() {
+        return None;
+    }
+
+    // Both types are rewritten in place, so they must be written out in the signature rather than
+    // hidden behind type aliases.
+    let hir_sig = tcx
+        .hir()
+        .fn_sig_by_hir_id(tcx.hir().local_def_id_to_hir_id(ldid))?;
+    if !matches!(hir_sig.decl.inputs[data_arg].kind, hir::TyKind::Ptr(_)) {
+        return None;
+    }
+    match hir_sig.decl.inputs[cb_arg].kind {
+        hir::TyKind::BareFn(bare_fn)
+            if matches!(bare_fn.decl.inputs[cb_data_arg].kind, hir::TyKind::Ptr(_)) => {}
+        _ => return None,
+    }
+
+    let mir = tcx.mir_built(WithOptConstParam::unknown(ldid));
+    let mir = mir.borrow();
+    let data = Local::from_usize(data_arg + 1);
+    let cb = Local::from_usize(cb_arg + 1);
+    if !only_passed_to_callback(&mir, data, cb, cb_data_arg) {
+        return None;
+    }
+
+    Some(RegistrationFn {
+        data_arg,
+        cb_arg,
+        cb_data_arg,
+        mutbl,
+    })
+}
+
+/// Check whether callback `did` can take its user data (parameter `data_arg`) as a pointer to a
+/// concrete type, and if so, return a description of the callback.
+fn callback_info<'tcx>(
+    gacx: &GlobalAnalysisCtxt<'tcx>,
+    did: DefId,
+    data_arg: usize,
+    pointee_types: &GlobalPointerTable<PointeeTypes<'tcx>>,
+) -> Option<Callback<'tcx>> {
+    let tcx = gacx.tcx;
+    let ldid = did.as_local()?;
+    if !can_change_sig(gacx, did) {
+        return None;
+    }
+
+    let hir_sig = tcx
+        .hir()
+        .fn_sig_by_hir_id(tcx.hir().local_def_id_to_hir_id(ldid))?;
+    if !matches!(hir_sig.decl.inputs.get(data_arg)?.kind, hir::TyKind::Ptr(_)) {
+        return None;
+    }
+
+    let lsig = gacx.fn_sigs.get(&did)?;
+    let ptr = lsig.inputs[data_arg].label;
+    if ptr.is_none() {
+        return None;
+    }
+    let pointee_lty = pointee_types[ptr].get_sole_lty()?;
+    if !pointee_lty.args.is_empty() || util::is_c_void(tcx, pointee_lty.ty) {
+        return None;
+    }
+
+    // Changing the parameter's pointee type is only safe if the callback immediately casts it to
+    // some other pointer type.
+    let mir = tcx.mir_built(WithOptConstParam::unknown(ldid));
+    let mir = mir.borrow();
+    if !only_used_in_casts(&mir, Local::from_usize(data_arg + 1)) {
+        return None;
+    }
+
+    Some(Callback {
+        data_arg,
+        pointee_ty: pointee_lty.ty,
+    })
+}
+
+/// Check whether the signature of `did` is allowed to change.  Functions the user asked us not to
+/// rewrite are left alone, as are generic functions.
+fn can_change_sig(gacx: &GlobalAnalysisCtxt, did: DefId) -> bool {
+    if gacx
+        .dont_rewrite_fns
+        .get(did)
+        .intersects(DontRewriteFnReason::USER_REQUEST | DontRewriteFnReason::CLOSURE_CALLBACK)
+    {
+        return false;
+    }
+    let generics = gacx.tcx.generics_of(did);
+    generics.parent.is_none()
+        && generics
+            .params
+            .iter()
+            .all(|p| matches!(p.kind, GenericParamDefKind::Lifetime))
+}
+
+/// Check that every use of the local `data` (or a temporary copy of it) is as argument
+/// `cb_data_arg` in a call to `cb` (or a temporary copy of it), and that `cb` is used only for
+/// such calls.
+fn only_passed_to_callback(mir: &Body, data: Local, cb: Local, cb_data_arg: usize) -> bool {
+    let data_copies = local_copies(mir, data);
+    let cb_copies = local_copies(mir, cb);
+    let tracked: HashSet<Local> = data_copies.union(&cb_copies).copied().collect();
+
+    for bb_data in mir.basic_blocks().iter() {
+        for (i, stmt) in bb_data.statements.iter().enumerate() {
+            if let StatementKind::Assign(ref x) = stmt.kind {
+                // Skip the statements that define the copies.
+                if let Rvalue::Use(ref op) = x.1 {
+                    if x.0.projection.is_empty() && tracked.contains(&x.0.local) {
+                        if let Some(src) = op.place() {
+                            if src.projection.is_empty() && tracked.contains(&src.local) {
+                                continue;
+                            }
+                        }
+                    }
+                }
+            }
+            let mut v = LocalUseVisitor::new(&tracked);
+            v.visit_statement(stmt, Location::START);
+            if v.found {
+                debug!("user data or callback is used in {stmt:?} (index {i})");
+                return false;
+            }
+        }
+
+        let term = bb_data.terminator();
+        if let TerminatorKind::Call {
+            ref func,
+            ref args,
+            destination,
+            ..
+        } = term.kind
+        {
+            if tracked.contains(&destination.local) {
+                return false;
+            }
+            let calls_cb = func.place().map_or(false, |pl| {
+                pl.projection.is_empty() && cb_copies.contains(&pl.local)
+            });
+            if !calls_cb && func.place().map_or(false, |pl| tracked.contains(&pl.local)) {
+                return false;
+            }
+            for (i, arg) in args.iter().enumerate() {
+                let pl = match arg.place() {
+                    Some(pl) => pl,
+                    None => continue,
+                };
+                if !tracked.contains(&pl.local) {
+                    continue;
+                }
+                let ok = calls_cb
+                    && i == cb_data_arg
+                    && pl.projection.is_empty()
+                    && data_copies.contains(&pl.local);
+                if !ok {
+                    return false;
+                }
+            }
+        } else {
+            let mut v = LocalUseVisitor::new(&tracked);
+            v.visit_terminator(term, Location::START);
+            if v.found {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Check that every use of `local` is as the operand of a cast.
+fn only_used_in_casts(mir: &Body, local: Local) -> bool {
+    let tracked = HashSet::from([local]);
+    for bb_data in mir.basic_blocks().iter() {
+        for stmt in &bb_data.statements {
+            if let StatementKind::Assign(ref x) = stmt.kind {
+                if let Rvalue::Cast(_, ref op, _) = x.1 {
+                    let is_direct_use = op
+                        .place()
+                        .map_or(false, |pl| pl.local == local && pl.projection.is_empty());
+                    if is_direct_use && x.0.local != local {
+                        continue;
+                    }
+                }
+            }
+            let mut v = LocalUseVisitor::new(&tracked);
+            v.visit_statement(stmt, Location::START);
+            if v.found {
+                return false;
+            }
+        }
+        let mut v = LocalUseVisitor::new(&tracked);
+        v.visit_terminator(bb_data.terminator(), Location::START);
+        if v.found {
+            return false;
+        }
+    }
+    true
+}
+
+/// Collect `local` and all temporaries that are assigned a copy of it (or of another such
+/// temporary).
+fn local_copies(mir: &Body, local: Local) -> HashSet<Local> {
+    let mut copies = HashSet::from([local]);
+    loop {
+        let mut changed = false;
+        for bb_data in mir.basic_blocks().iter() {
+            for stmt in &bb_data.statements {
+                let x = match stmt.kind {
+                    StatementKind::Assign(ref x) => x,
+                    _ => continue,
+                };
+                let src = match x.1 {
+                    Rvalue::Use(Operand::Copy(pl) | Operand::Move(pl)) => pl,
+                    _ => continue,
+                };
+                if !src.projection.is_empty() || !copies.contains(&src.local) {
+                    continue;
+                }
+                let dest = x.0;
+                if dest.projection.is_empty()
+                    && mir.local_kind(dest.local) == rustc_middle::mir::LocalKind::Temp
+                {
+                    changed |= copies.insert(dest.local);
+                }
+            }
+        }
+        if !changed {
+            return copies;
+        }
+    }
+}
+
+/// Checks whether a MIR statement or terminator uses any of the `locals`.
+struct LocalUseVisitor<'a> {
+    locals: &'a HashSet<Local>,
+    found: bool,
+}
+
+impl<'a> LocalUseVisitor<'a> {
+    fn new(locals: &'a HashSet<Local>) -> LocalUseVisitor<'a> {
+        LocalUseVisitor {
+            locals,
+            found: false,
+        }
+    }
+}
+
+impl<'tcx> Visitor<'tcx> for LocalUseVisitor<'_> {
+    fn visit_local(&mut self, local: Local, context: PlaceContext, _location: Location) {
+        if self.locals.contains(&local) && context.is_use() {
+            self.found = true;
+        }
+    }
+}
+
+/// Counts mentions of function items, such as `f` in `f(x)` or `f as fn(i32)`.
+struct FnMentionVisitor<'a> {
+    mentions: &'a mut HashMap<DefId, usize>,
+}
+
+impl<'tcx> Visitor<'tcx> for FnMentionVisitor<'_> {
+    fn visit_constant(&mut self, constant: &Constant<'tcx>, _location: Location) {
+        if let TyKind::FnDef(did, _) = *constant.ty().kind() {
+            *self.mentions.entry(did).or_default() += 1;
+        }
+    }
+}
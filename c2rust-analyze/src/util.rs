@@ -8,12 +8,14 @@ use rustc_middle::mir::{
     Body, Constant, Field, Local, Mutability, Operand, PlaceElem, PlaceRef, ProjectionElem, Rvalue,
 };
 use rustc_middle::ty::{
-    self, AdtDef, DefIdTree, EarlyBinder, FnSig, GenericArg, List, Subst, SubstsRef, Ty, TyCtxt,
-    TyKind, UintTy,
+    self, AdtDef, DefIdTree, EarlyBinder, FnSig, GenericArg, List, ParamEnv, Subst, SubstsRef, Ty,
+    TyCtxt, TyKind, UintTy, WithOptConstParam,
 };
 use rustc_span::symbol::{sym, Symbol};
 use rustc_type_ir::IntTy;
-use std::fmt::Debug;
+use sha2::{Digest, Sha256};
+use std::fmt::{Debug, Write as _};
+use std::hash::{Hash, Hasher};
 
 #[derive(Debug)]
 pub enum RvalueDesc<'tcx> {
@@ -158,6 +160,9 @@ pub enum Callee<'tcx> {
         mutbl: Mutability,
     },
 
+    /// `<*mut T>::offset_from` or `<*const T>::offset_from`.
+    PtrOffsetFrom,
+
     /// `<[T]>::as_ptr` and `<[T]>::as_mut_ptr` methods.  Also covers the array and str versions.
     SliceAsPtr {
         /// The pointee type.  This is either `TyKind::Slice`, `TyKind::Array`, or `TyKind::Str`.
@@ -182,6 +187,9 @@ pub enum Callee<'tcx> {
     /// libc::memcpy
     Memcpy,
 
+    /// libc::memmove
+    Memmove,
+
     /// libc::free
     Free,
 
@@ -194,8 +202,64 @@ pub enum Callee<'tcx> {
     /// core::ptr::null or core::ptr::null_mut
     Null { mutbl: Mutability },
 
+    /// `core::ptr::read_volatile`, or the `read_volatile` method of `*const T` or `*mut T`
+    ReadVolatile,
+
+    /// `core::ptr::write_volatile`, or the `write_volatile` method of `*mut T`
+    WriteVolatile,
+
     /// `core::mem::size_of<T>`
     SizeOf { ty: Ty<'tcx> },
+
+    /// The `arg` method of `core::ffi::VaListImpl` or `VaList`, which reads the next variadic
+    /// argument of a C-variadic function.
+    VaArg,
+
+    /// One of the `atomic_*` intrinsics in `core::intrinsics`, such as `atomic_xadd_seqcst`.
+    /// Fences are not included, since they don't take a pointer.
+    Atomic {
+        op: AtomicOp,
+        /// The memory ordering, as the name of a `core::sync::atomic::Ordering` variant.
+        ordering: &'static str,
+        /// The ordering used when a compare-exchange fails.  For other operations, this is
+        /// unused.
+        fail_ordering: &'static str,
+    },
+}
+
+/// The operation performed by a `Callee::Atomic` intrinsic.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AtomicOp {
+    /// `atomic_load(p)`
+    Load,
+    /// `atomic_store(p, val)`
+    Store,
+    /// `atomic_xchg(p, val)`, which returns the old value
+    Xchg,
+    /// `atomic_cxchg(p, old, new)` or `atomic_cxchgweak(p, old, new)`, which return the previous
+    /// value and whether it was replaced
+    Cxchg { weak: bool },
+    /// `atomic_xadd(p, val)`, `atomic_and(p, val)`, and the other read-modify-write operations
+    /// on integers, which return the old value.  `signed` is `Some` for the comparisons, which
+    /// are only valid on integers of that signedness.
+    Rmw {
+        method: &'static str,
+        signed: Option<bool>,
+    },
+}
+
+impl AtomicOp {
+    /// The name of the method of `AtomicI32` and similar types that performs this operation.
+    pub fn method(self) -> &'static str {
+        match self {
+            AtomicOp::Load => "load",
+            AtomicOp::Store => "store",
+            AtomicOp::Xchg => "swap",
+            AtomicOp::Cxchg { weak: false } => "compare_exchange",
+            AtomicOp::Cxchg { weak: true } => "compare_exchange_weak",
+            AtomicOp::Rmw { method, .. } => method,
+        }
+    }
 }
 
 pub fn ty_callee<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Callee<'tcx> {
@@ -258,6 +322,22 @@ fn builtin_callee<'tcx>(tcx: TyCtxt<'tcx>, did: DefId, substs: SubstsRef<'tcx>)
             Some(Callee::PtrOffset { pointee_ty, mutbl })
         }
 
+        "offset_from" => {
+            // The `offset_from` inherent method of `*const T` and `*mut T`.
+            let parent_did = tcx.parent(did);
+            if tcx.def_kind(parent_did) != DefKind::Impl {
+                return None;
+            }
+            if tcx.impl_trait_ref(parent_did).is_some() {
+                return None;
+            }
+            let parent_impl_ty = EarlyBinder(tcx.type_of(parent_did)).subst(tcx, substs);
+            if !parent_impl_ty.is_unsafe_ptr() {
+                return None;
+            }
+            Some(Callee::PtrOffsetFrom)
+        }
+
         name @ "as_ptr" | name @ "as_mut_ptr" => {
             // The `as_ptr` and `as_mut_ptr` inherent methods of `[T]`, `[T; n]`, and `str`.
             let parent_did = tcx.parent(did);
@@ -286,6 +366,18 @@ fn builtin_callee<'tcx>(tcx: TyCtxt<'tcx>, did: DefId, substs: SubstsRef<'tcx>)
             })
         }
 
+        name @ "read_volatile" | name @ "write_volatile" => {
+            // The free functions in `core::ptr`, and the inherent methods of raw pointers.
+            if !matches!(tcx.crate_name(did.krate).as_str(), "core" | "std") {
+                return None;
+            }
+            match name {
+                "read_volatile" => Some(Callee::ReadVolatile),
+                "write_volatile" => Some(Callee::WriteVolatile),
+                _ => unreachable!(),
+            }
+        }
+
         "malloc" => {
             if matches!(tcx.def_kind(tcx.parent(did)), DefKind::ForeignMod) {
                 return Some(Callee::Malloc);
@@ -328,6 +420,35 @@ fn builtin_callee<'tcx>(tcx: TyCtxt<'tcx>, did: DefId, substs: SubstsRef<'tcx>)
             None
         }
 
+        "memmove" => {
+            if matches!(tcx.def_kind(tcx.parent(did)), DefKind::ForeignMod) {
+                return Some(Callee::Memmove);
+            }
+            None
+        }
+
+        "arg" => {
+            // The `arg` inherent method of `core::ffi::VaListImpl` and `VaList`.
+            if !matches!(tcx.crate_name(did.krate).as_str(), "core" | "std") {
+                return None;
+            }
+            let parent_did = tcx.parent(did);
+            if tcx.def_kind(parent_did) != DefKind::Impl {
+                return None;
+            }
+            if tcx.impl_trait_ref(parent_did).is_some() {
+                return None;
+            }
+            let adt_def = tcx.type_of(parent_did).ty_adt_def()?;
+            if !matches!(
+                tcx.item_name(adt_def.did()).as_str(),
+                "VaListImpl" | "VaList"
+            ) {
+                return None;
+            }
+            Some(Callee::VaArg)
+        }
+
         "is_null" => {
             // The `offset` inherent method of `*const T` and `*mut T`.
             let parent_did = tcx.parent(did);
@@ -389,6 +510,17 @@ fn builtin_callee<'tcx>(tcx: TyCtxt<'tcx>, did: DefId, substs: SubstsRef<'tcx>)
             Some(Callee::SizeOf { ty })
         }
 
+        name if name.starts_with("atomic_") => {
+            // The atomic intrinsics, declared in an `extern "rust-intrinsic"` block.
+            if !matches!(tcx.crate_name(did.krate).as_str(), "core" | "std") {
+                return None;
+            }
+            if tcx.def_kind(tcx.parent(did)) != DefKind::ForeignMod {
+                return None;
+            }
+            atomic_callee(name)
+        }
+
         _ => {
             eprintln!("name: {name:?}");
             None
@@ -396,6 +528,84 @@ fn builtin_callee<'tcx>(tcx: TyCtxt<'tcx>, did: DefId, substs: SubstsRef<'tcx>)
     }
 }
 
+/// Parse the name of an atomic intrinsic, such as `atomic_load_acquire` or
+/// `atomic_cxchg_acq_failrelaxed`.  Both the older orderings (`acq`, `rel`, `failrelaxed`) and
+/// the newer ones (`acquire`, `release`, `acquire_relaxed`) are accepted.  Intrinsics without an
+/// ordering use `SeqCst`.
+fn atomic_callee<'tcx>(name: &str) -> Option<Callee<'tcx>> {
+    let rest = name.strip_prefix("atomic_")?;
+    let (op_name, orderings) = rest.split_once('_').unwrap_or((rest, ""));
+    let rmw = |method, signed| AtomicOp::Rmw { method, signed };
+    let op = match op_name {
+        "load" => AtomicOp::Load,
+        "store" => AtomicOp::Store,
+        "xchg" => AtomicOp::Xchg,
+        "cxchg" => AtomicOp::Cxchg { weak: false },
+        "cxchgweak" => AtomicOp::Cxchg { weak: true },
+        "xadd" => rmw("fetch_add", None),
+        "xsub" => rmw("fetch_sub", None),
+        "and" => rmw("fetch_and", None),
+        "nand" => rmw("fetch_nand", None),
+        "or" => rmw("fetch_or", None),
+        "xor" => rmw("fetch_xor", None),
+        "max" => rmw("fetch_max", Some(true)),
+        "min" => rmw("fetch_min", Some(true)),
+        "umax" => rmw("fetch_max", Some(false)),
+        "umin" => rmw("fetch_min", Some(false)),
+        // `fence` and `singlethreadfence`
+        _ => return None,
+    };
+
+    let ordering_name = |s: &str| {
+        Some(match s {
+            "seqcst" => "SeqCst",
+            "acq" | "acquire" => "Acquire",
+            "rel" | "release" => "Release",
+            "acqrel" => "AcqRel",
+            "relaxed" | "unordered" => "Relaxed",
+            _ => return None,
+        })
+    };
+    // The strongest failure ordering allowed for each success ordering, which is what the
+    // intrinsics without an explicit failure ordering use.
+    let default_fail = |ordering: &'static str| match ordering {
+        "Release" => "Relaxed",
+        "AcqRel" => "Acquire",
+        _ => ordering,
+    };
+    let (ordering, fail_ordering) = match orderings {
+        "" => ("SeqCst", "SeqCst"),
+        "failrelaxed" => ("SeqCst", "Relaxed"),
+        "failacq" => ("SeqCst", "Acquire"),
+        _ => match orderings.split_once('_') {
+            Some((success, fail)) => {
+                let fail = fail.strip_prefix("fail").unwrap_or(fail);
+                (ordering_name(success)?, ordering_name(fail)?)
+            }
+            None => {
+                let ordering = ordering_name(orderings)?;
+                (ordering, default_fail(ordering))
+            }
+        },
+    };
+
+    Some(Callee::Atomic {
+        op,
+        ordering,
+        fail_ordering,
+    })
+}
+
+/// Get the longest prefix of `proj` that consists only of `Field` projections, as in the `.a.b`
+/// of `x.a.b[i].c`.
+pub fn field_path_prefix<'a, 'tcx>(proj: &'a [PlaceElem<'tcx>]) -> &'a [PlaceElem<'tcx>] {
+    let len = proj
+        .iter()
+        .position(|p| !matches!(p, PlaceElem::Field(..)))
+        .unwrap_or(proj.len());
+    &proj[..len]
+}
+
 pub fn lty_project<'tcx, L: Debug>(
     lty: LabeledTy<'tcx, L>,
     proj: &PlaceElem<'tcx>,
@@ -408,7 +618,9 @@ pub fn lty_project<'tcx, L: Debug>(
             lty.args[0]
         }
         ProjectionElem::Field(f, _) => match lty.kind() {
-            TyKind::Tuple(_) => lty.args[f.index()],
+            // The fields of a closure are its captured variables, which are labeled like the
+            // elements of a tuple.
+            TyKind::Tuple(_) | TyKind::Closure(..) => lty.args[f.index()],
             TyKind::Adt(def, _) => field_lty(lty, *def, f),
             _ => panic!("Field projection is unsupported on type {:?}", lty),
         },
@@ -521,6 +733,61 @@ pub fn is_transmutable_ptr_cast<'tcx>(from: Ty<'tcx>, to: Ty<'tcx>) -> Option<bo
     Some(is_transmutable_to(from, to))
 }
 
+/// Determine if a value of type `from` can be read as a value of type `to`, as in
+/// `*(p as *const A as *const B)`.  This requires both types to be [plain data](is_plain_data),
+/// `to` to be no larger than `from`, and `to` to have no stricter alignment than `from`.
+///
+/// Unlike [`is_transmutable_to`], this only covers reads.  Writing through the cast pointer could
+/// still break invariants of `from` that aren't captured by its layout.
+pub fn is_layout_compatible_read<'tcx>(tcx: TyCtxt<'tcx>, from: Ty<'tcx>, to: Ty<'tcx>) -> bool {
+    if !is_plain_data(tcx, from) || !is_plain_data(tcx, to) {
+        return false;
+    }
+    let param_env = ParamEnv::reveal_all();
+    let (from_layout, to_layout) = match (
+        tcx.layout_of(param_env.and(from)),
+        tcx.layout_of(param_env.and(to)),
+    ) {
+        (Ok(from_layout), Ok(to_layout)) => (from_layout.layout, to_layout.layout),
+        _ => return false,
+    };
+    to_layout.size() <= from_layout.size() && to_layout.align().abi <= from_layout.align().abi
+}
+
+/// Determine if `ty` is plain data: every bit pattern is a valid value of `ty`, and `ty` contains
+/// no padding bytes.  This holds for integers, floats, arrays of plain data, and `#[repr(C)]` or
+/// `#[repr(transparent)]` structs whose fields are plain data and leave no gaps.
+pub fn is_plain_data<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> bool {
+    match *ty.kind() {
+        TyKind::Int(_) | TyKind::Uint(_) | TyKind::Float(_) => true,
+        TyKind::Array(elem_ty, _) => is_plain_data(tcx, elem_ty),
+        TyKind::Adt(adt_def, substs) => {
+            let repr = adt_def.repr();
+            if !adt_def.is_struct() || !(repr.c() || repr.transparent()) {
+                return false;
+            }
+            let param_env = ParamEnv::reveal_all();
+            let mut fields_size = 0;
+            for field in adt_def.all_fields() {
+                let field_ty = field.ty(tcx, substs);
+                if !is_plain_data(tcx, field_ty) {
+                    return false;
+                }
+                match tcx.layout_of(param_env.and(field_ty)) {
+                    Ok(layout) => fields_size += layout.layout.size().bytes(),
+                    Err(_) => return false,
+                }
+            }
+            // If the fields don't add up to the size of the struct, then the struct has padding.
+            match tcx.layout_of(param_env.and(ty)) {
+                Ok(layout) => layout.layout.size().bytes() == fields_size,
+                Err(_) => false,
+            }
+        }
+        _ => false,
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 pub enum TestAttr {
     /// `#[c2rust_analyze_test::fixed_signature]`: Mark all pointers in the function signature as
@@ -537,6 +804,27 @@ pub enum TestAttr {
     /// `#[c2rust_analyze_test::force_non_null_args]`: Mark arguments as `NON_NULL` and don't allow
     /// that flag to be changed during dataflow analysis.
     ForceNonNullArgs,
+    /// `#[c2rust_analyze_test::errno_result]`: Select the function for rewriting its errno-style
+    /// return codes to `Result`, as if it were listed in `C2RUST_ANALYZE_ERRNO_RESULT_FNS_LIST`.
+    ErrnoResult,
+    /// `#[c2rust_analyze_test::out_param]`: Select the function for rewriting its out-parameter to
+    /// a return value, as if it were listed in `C2RUST_ANALYZE_OUT_PARAM_FNS_LIST`.
+    OutParam,
+    /// `#[c2rust_analyze_test::static_buf]`: Select the function for rewriting its returned
+    /// pointer into a static buffer, as if it were listed in `C2RUST_ANALYZE_STATIC_BUF_FNS_LIST`.
+    StaticBuf,
+    /// `#[c2rust_analyze_test::closure_callback]`: Select the function for replacing its callback
+    /// and user-data parameters with a closure, as if it were listed in
+    /// `C2RUST_ANALYZE_CLOSURE_CALLBACK_FNS_LIST`.
+    ClosureCallback,
+    /// `#[c2rust_analyze_test::self_ref_index]`: Select the struct for rewriting its
+    /// self-pointers to indices, as if it were listed in
+    /// `C2RUST_ANALYZE_SELF_REF_INDEX_STRUCTS_LIST`.
+    SelfRefIndex,
+    /// `#[c2rust_analyze_test::self_ref_pin]`: Select the struct for making it `!Unpin` instead of
+    /// rewriting its self-pointers, as if it were listed in
+    /// `C2RUST_ANALYZE_SELF_REF_PIN_STRUCTS_LIST`.
+    SelfRefPin,
 }
 
 impl TestAttr {
@@ -547,13 +835,55 @@ impl TestAttr {
             TestAttr::FailBeforeRewriting => "fail_before_rewriting",
             TestAttr::SkipRewrite => "skip_rewrite",
             TestAttr::ForceNonNullArgs => "force_non_null_args",
+            TestAttr::ErrnoResult => "errno_result",
+            TestAttr::OutParam => "out_param",
+            TestAttr::StaticBuf => "static_buf",
+            TestAttr::ClosureCallback => "closure_callback",
+            TestAttr::SelfRefIndex => "self_ref_index",
+            TestAttr::SelfRefPin => "self_ref_pin",
         }
     }
 }
 
 pub fn has_test_attr(tcx: TyCtxt, ldid: LocalDefId, attr: TestAttr) -> bool {
-    let tool_sym = Symbol::intern("c2rust_analyze_test");
-    let name_sym = Symbol::intern(attr.name());
+    has_tool_attr(tcx, ldid, "c2rust_analyze_test", attr.name())
+}
+
+/// Attributes that users can put on items to control rewriting without changing the command line,
+/// so a crate can be migrated incrementally, one function at a time.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum UserAttr {
+    /// `#[c2rust::skip_rewrite]`: Leave the item unchanged, as if it were listed in
+    /// `C2RUST_ANALYZE_FIXED_DEFS_LIST`.
+    SkipRewrite,
+    /// `#[c2rust::force_rewrite]`: Rewrite the item even if it's listed in
+    /// `C2RUST_ANALYZE_FIXED_DEFS_LIST` or excluded by `C2RUST_ANALYZE_REWRITE_PATHS`.  This takes
+    /// precedence over `#[c2rust::skip_rewrite]`.
+    ForceRewrite,
+    /// `#[c2rust::foreign_memory]`: On an `extern` function declaration, mark the pointers it
+    /// returns as foreign memory, which is managed outside of Rust (see
+    /// `analyze::mark_foreign_memory`).
+    ForeignMemory,
+}
+
+impl UserAttr {
+    pub fn name(self) -> &'static str {
+        match self {
+            UserAttr::SkipRewrite => "skip_rewrite",
+            UserAttr::ForceRewrite => "force_rewrite",
+            UserAttr::ForeignMemory => "foreign_memory",
+        }
+    }
+}
+
+pub fn has_user_attr(tcx: TyCtxt, ldid: LocalDefId, attr: UserAttr) -> bool {
+    has_tool_attr(tcx, ldid, "c2rust", attr.name())
+}
+
+/// Check whether `ldid` has the attribute `#[tool::name]`.
+fn has_tool_attr(tcx: TyCtxt, ldid: LocalDefId, tool: &str, name: &str) -> bool {
+    let tool_sym = Symbol::intern(tool);
+    let name_sym = Symbol::intern(name);
 
     for attr in tcx.get_attrs_unchecked(ldid.to_def_id()) {
         let path = match attr.kind {
@@ -578,3 +908,65 @@ pub fn is_automatically_derived<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>) -> b
         .map(|parent_def_id| tcx.has_attr(parent_def_id, sym::automatically_derived))
         .unwrap_or(false)
 }
+
+/// Get the signature of the function or closure that owns the body `ldid`, with late-bound
+/// regions erased.  The inputs of a closure's signature are its environment followed by its
+/// arguments, matching the argument locals of its MIR body.
+pub fn body_fn_sig<'tcx>(tcx: TyCtxt<'tcx>, ldid: LocalDefId) -> FnSig<'tcx> {
+    let closure_sig = match *tcx.type_of(ldid).kind() {
+        TyKind::Closure(_, substs) => substs.as_closure().sig(),
+        _ => return tcx.erase_late_bound_regions(tcx.fn_sig(ldid.to_def_id())),
+    };
+    let sig = tcx.erase_late_bound_regions(closure_sig);
+    let mir = tcx.mir_built(WithOptConstParam::unknown(ldid));
+    let mir = mir.borrow();
+    let inputs = mir.args_iter().map(|local| mir.local_decls[local].ty);
+    tcx.mk_fn_sig(
+        inputs,
+        mir.return_ty(),
+        sig.c_variadic,
+        sig.unsafety,
+        sig.abi,
+    )
+}
+
+/// Get the name of the function or closure `did`, for use in diagnostics.  Closures have no name
+/// of their own, so they're named by their path, such as `f::{closure#0}`.
+pub fn fn_name(tcx: TyCtxt, did: DefId) -> Symbol {
+    tcx.opt_item_name(did)
+        .unwrap_or_else(|| Symbol::intern(&tcx.def_path_str(did)))
+}
+
+/// Check whether `ty` is `c_void`, as defined in `core::ffi`, `std::ffi`, or `libc`.
+pub fn is_c_void<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> bool {
+    match *ty.kind() {
+        TyKind::Adt(adt_def, _) => tcx.item_name(adt_def.did()).as_str() == "c_void",
+        _ => false,
+    }
+}
+
+pub fn bytes_to_hex_string(b: &[u8]) -> String {
+    let mut s = String::with_capacity(b.len() * 2);
+    for &x in b {
+        write!(s, "{:02x}", x).unwrap();
+    }
+    s
+}
+
+/// Compute the SHA-256 hash of `x`, using its `Hash` impl.
+pub fn sha256_hash<T: Hash>(x: &T) -> [u8; 32] {
+    struct Sha256Hasher(Sha256);
+    impl Hasher for Sha256Hasher {
+        fn write(&mut self, bytes: &[u8]) {
+            self.0.update(bytes);
+        }
+        fn finish(&self) -> u64 {
+            panic!("Sha256Hasher doesn't support finish()");
+        }
+    }
+
+    let mut hasher = Sha256Hasher(Sha256::new());
+    x.hash(&mut hasher);
+    let digest = hasher.0.finalize();
+    digest.as_slice().try_into().unwrap()
+}
@@ -0,0 +1,801 @@
+//! Detection of growable-buffer structs, whose `(ptr, len, cap)` fields can be replaced by a
+//! single `Vec<T>`.
+//!
+//! C code often implements a growable array as a struct holding a heap pointer, the number of
+//! elements in use, and the number of elements allocated:
+//!
+//! ```ignore
+//! pub struct buffer {
+//!     pub data: *mut i32,
+//!     pub len: size_t,
+//!     pub cap: size_t,
+//! }
+//! ```
+//!
+//! Such structs are recognized by the names of their fields: a raw pointer field and two integer
+//! fields whose names appear in the lists of pointer, length, and capacity names.  The default
+//! lists cover common spellings like `data`/`len`/`cap` and `items`/`count`/`capacity`.  They can
+//! be replaced by setting `C2RUST_ANALYZE_VEC_FIELDS` to three colon-separated lists of names, as
+//! in `data,buf:len,used:cap,alloc`.
+//!
+//! Every use of the three fields must also fit one of the patterns below, or the struct is left
+//! alone.  The pointer field's type becomes `Vec<T>`, the length and capacity fields are removed,
+//! and the uses are rewritten as follows:
+//!
+//! * `(*b).len` and `(*b).cap` become `(*b).data.len()` and `(*b).data.capacity()`.
+//! * `*(*b).data.offset(i)` becomes `(*b).data[i as usize]`.
+//! * `*(*b).data.offset((*b).len as isize) = x; (*b).len += 1;` becomes `(*b).data.push(x);`.
+//! * `(*b).len = 0` becomes `(*b).data.clear()`.
+//! * `(*b).cap = n` reserves room for `n` elements, and the `realloc` of the pointer field that
+//!   goes with it is removed.  This includes a `realloc` whose result is stored in a temporary
+//!   that's checked for null before being stored back into the field, as in
+//!   `let p = realloc(..) as *mut T; if p.is_null() { .. } (*b).data = p;`.  The `let` is
+//!   removed, the null check becomes `false`, and the store is removed.
+//! * Storing the result of `malloc` or a null pointer into the pointer field stores an empty
+//!   `Vec` instead, and `free((*b).data)` replaces the field with an empty `Vec`.
+//! * A struct literal that sets the pointer to null and both counts to zero keeps only the
+//!   pointer field, initialized to an empty `Vec`.
+//!
+//! Functions that use the fields are excluded from the normal rewrites and handled entirely by
+//! `rewrite::gen_vec_struct_rewrites`.  Since `Vec` isn't `Copy`, `Copy` is also removed from the
+//! struct's derives.
+
+use crate::context::GlobalAnalysisCtxt;
+use crate::util::{self, Callee};
+use log::debug;
+use rustc_ast::LitKind;
+use rustc_hir as hir;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{BinOpKind, Block, Expr, ExprKind, HirId, StmtKind, UnOp};
+use rustc_middle::ty::{Ty, TyCtxt, TyKind, TypeckResults};
+use rustc_span::{BytePos, Span};
+use std::collections::{HashMap, HashSet};
+use std::env;
+
+/// Default field names for `C2RUST_ANALYZE_VEC_FIELDS`.
+const DEFAULT_FIELD_NAMES: &str =
+    "data,buf,buffer,items,elems,ptr:len,length,size,count,used:cap,capacity,alloc,allocated";
+
+/// The three fields of a growable-buffer struct.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VecFields {
+    pub ptr: DefId,
+    pub len: DefId,
+    pub cap: DefId,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FieldRole {
+    Ptr,
+    Len,
+    Cap,
+}
+
+/// A single use of one of the fields of a growable-buffer struct.
+#[derive(Clone, Copy, Debug)]
+pub enum VecFieldUse<'tcx> {
+    /// The length or capacity is read, as in `(*b).len`.  `ident` is the span of the field name,
+    /// and `ty` is the field's integer type.
+    Count {
+        expr: Span,
+        ident: Span,
+        cap: bool,
+        ty: Ty<'tcx>,
+    },
+    /// An element is accessed, as in `*(*b).data.offset(i)`.  `ptr` is the pointer field
+    /// expression `(*b).data`, and `index` is `i`, or `None` for `*(*b).data`.
+    Elem {
+        expr: Span,
+        ptr: Span,
+        index: Option<Span>,
+    },
+    /// An element is appended, as in `*(*b).data.offset((*b).len as isize) = x;` followed by
+    /// `(*b).len += 1;`.  `write` is the first assignment, and `incr` is the whole second
+    /// statement.
+    Push {
+        write: Span,
+        ptr: Span,
+        value: Span,
+        incr: Span,
+    },
+    /// The length is reset, as in `(*b).len = 0`.  `lhs` is `(*b).len`.
+    Clear { expr: Span, lhs: Span, ident: Span },
+    /// The capacity is set, as in `(*b).cap = n`.  `lhs` is `(*b).cap`, and `rhs` is `n`.
+    Reserve {
+        expr: Span,
+        lhs: Span,
+        ident: Span,
+        rhs: Span,
+    },
+    /// The buffer is grown, as in `(*b).data = realloc(..) as *mut T`, or the result of a
+    /// `GrowTemp` is stored back into the field, as in `(*b).data = p`.
+    Realloc { expr: Span },
+    /// The buffer is grown into a temporary, as in `let p = realloc((*b).data as *mut c_void, n)
+    /// as *mut T;`.  `stmt` is the whole statement.
+    GrowTemp { stmt: Span },
+    /// A `GrowTemp` temporary is checked for null, as in `p.is_null()`.
+    GrowCheck { expr: Span },
+    /// The buffer is initialized, as in `(*b).data = malloc(..) as *mut T` or
+    /// `(*b).data = ptr::null_mut()`.  `lhs` is `(*b).data`.
+    Init { expr: Span, lhs: Span },
+    /// The buffer is freed, as in `free((*b).data as *mut c_void)`.
+    Free { call: Span, ptr: Span },
+    /// An empty buffer is built, as in `S { data: 0 as *mut T, len: 0, cap: 0 }`.  `ptr_value` is
+    /// the pointer field's initializer, and `removed` covers the other two field initializers.
+    Literal { ptr_value: Span, removed: [Span; 2] },
+    /// Any other use of the fields.  This prevents the struct from being rewritten.
+    Other,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct VecStructInfo {
+    /// Growable-buffer structs that will be rewritten, mapped to their `(ptr, len, cap)` fields.
+    pub structs: HashMap<DefId, VecFields>,
+    /// Functions that use the fields of any of the `structs`.  These are excluded from the normal
+    /// rewrites and handled entirely by `rewrite::gen_vec_struct_rewrites`.
+    pub fns: HashSet<DefId>,
+}
+
+/// Field names accepted for each of the three roles.
+struct FieldNames {
+    ptr: Vec<String>,
+    len: Vec<String>,
+    cap: Vec<String>,
+}
+
+impl FieldNames {
+    fn from_env() -> FieldNames {
+        let spec = env::var("C2RUST_ANALYZE_VEC_FIELDS")
+            .unwrap_or_else(|_| DEFAULT_FIELD_NAMES.to_owned());
+        let lists = spec
+            .split(':')
+            .map(|list| {
+                list.split(',')
+                    .map(|name| name.trim().to_owned())
+                    .filter(|name| !name.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        match <[_; 3]>::try_from(lists) {
+            Ok([ptr, len, cap]) => FieldNames { ptr, len, cap },
+            Err(_) => panic!(
+                "C2RUST_ANALYZE_VEC_FIELDS should have three colon-separated lists, but got {spec:?}"
+            ),
+        }
+    }
+
+    fn role(&self, name: &str) -> Option<FieldRole> {
+        let has = |names: &[String]| names.iter().any(|n| n == name);
+        if has(&self.ptr) {
+            Some(FieldRole::Ptr)
+        } else if has(&self.len) {
+            Some(FieldRole::Len)
+        } else if has(&self.cap) {
+            Some(FieldRole::Cap)
+        } else {
+            None
+        }
+    }
+}
+
+/// Visitor that finds all uses of the fields of the `structs` within a body.
+pub struct VecFieldUseVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    structs: &'a HashMap<DefId, VecFields>,
+    pub uses: Vec<(DefId, VecFieldUse<'tcx>)>,
+    /// Field expressions that were already handled as part of an enclosing expression.
+    handled: HashSet<HirId>,
+    /// Locals holding the result of a `GrowTemp`, mapped to the struct and the local that holds
+    /// it.
+    grow_temps: HashMap<HirId, (DefId, HirId)>,
+}
+
+impl<'a, 'tcx> VecFieldUseVisitor<'a, 'tcx> {
+    pub fn new(
+        tcx: TyCtxt<'tcx>,
+        typeck_results: &'tcx TypeckResults<'tcx>,
+        structs: &'a HashMap<DefId, VecFields>,
+    ) -> VecFieldUseVisitor<'a, 'tcx> {
+        VecFieldUseVisitor {
+            tcx,
+            typeck_results,
+            structs,
+            uses: Vec::new(),
+            handled: HashSet::new(),
+            grow_temps: HashMap::new(),
+        }
+    }
+
+    /// If `ex` is an access to one of the three fields of one of the `structs`, as in
+    /// `(*b).len`, return the struct, the field's role, the base expression `(*b)`, and the span
+    /// of the field name.
+    fn vec_field(
+        &self,
+        ex: &'tcx Expr<'tcx>,
+    ) -> Option<(DefId, FieldRole, &'tcx Expr<'tcx>, Span)> {
+        let (base, ident) = match ex.kind {
+            ExprKind::Field(base, ident) => (base, ident),
+            _ => return None,
+        };
+        let adt_def = match *self
+            .typeck_results
+            .expr_ty_adjusted(base)
+            .peel_refs()
+            .kind()
+        {
+            TyKind::Adt(adt_def, _) => adt_def,
+            _ => return None,
+        };
+        let fields = self.structs.get(&adt_def.did())?;
+        let idx = *self.typeck_results.field_indices().get(ex.hir_id)?;
+        let did = adt_def.non_enum_variant().fields.get(idx)?.did;
+        let role = if did == fields.ptr {
+            FieldRole::Ptr
+        } else if did == fields.len {
+            FieldRole::Len
+        } else if did == fields.cap {
+            FieldRole::Cap
+        } else {
+            return None;
+        };
+        Some((adt_def.did(), role, base, ident.span))
+    }
+
+    /// If `ex` accesses the field with role `role`, return the local variable that holds the
+    /// struct or a pointer to it.  This is used to check that two field accesses refer to the
+    /// same struct.
+    fn field_owner(&self, ex: &'tcx Expr<'tcx>, role: FieldRole) -> Option<HirId> {
+        let (_, r, base, _) = self.vec_field(ex)?;
+        if r != role {
+            return None;
+        }
+        let base = match base.kind {
+            ExprKind::Unary(UnOp::Deref, ptr) => ptr,
+            _ => base,
+        };
+        local_var(base)
+    }
+
+    /// If `ex` is an element pointer, as in `(*b).data.offset(i)` or `(*b).data`, return the
+    /// struct, the pointer field expression, and the index, if any.
+    fn elem_ptr(
+        &self,
+        ex: &'tcx Expr<'tcx>,
+    ) -> Option<(DefId, &'tcx Expr<'tcx>, Option<&'tcx Expr<'tcx>>)> {
+        let (ptr, index) = match ex.kind {
+            ExprKind::MethodCall(seg, [ptr, index], _)
+                if matches!(seg.ident.as_str(), "offset" | "add") =>
+            {
+                (ptr, Some(index))
+            }
+            _ => (ex, None),
+        };
+        match self.vec_field(ptr)? {
+            (adt, FieldRole::Ptr, _, _) => Some((adt, ptr, index)),
+            _ => None,
+        }
+    }
+
+    /// If `ex` is a call, return the callee according to `util::ty_callee`.
+    fn callee(&self, ex: &Expr<'tcx>) -> Option<Callee<'tcx>> {
+        match ex.kind {
+            ExprKind::Call(func, _) => {
+                Some(util::ty_callee(self.tcx, self.typeck_results.expr_ty(func)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Check whether `ex` is a null pointer, as in `0 as *mut T` or `ptr::null_mut()`.
+    fn is_null(&self, ex: &Expr<'tcx>) -> bool {
+        is_int_lit(ex, 0) || matches!(self.callee(peel_casts(ex)), Some(Callee::Null { .. }))
+    }
+
+    /// Check whether `ex` increments the length field of `owner` by one, as in
+    /// `(*b).len += 1` or `(*b).len = (*b).len.wrapping_add(1)`.
+    fn is_len_incr(&self, ex: &'tcx Expr<'tcx>, owner: HirId) -> bool {
+        let is_len = |e| self.field_owner(e, FieldRole::Len) == Some(owner);
+        match ex.kind {
+            ExprKind::AssignOp(op, lhs, rhs) => {
+                op.node == BinOpKind::Add && is_len(lhs) && is_int_lit(rhs, 1)
+            }
+            ExprKind::Assign(lhs, rhs, _) if is_len(lhs) => match peel_casts(rhs).kind {
+                ExprKind::MethodCall(seg, [recv, amount], _) => {
+                    seg.ident.as_str() == "wrapping_add" && is_len(recv) && is_int_lit(amount, 1)
+                }
+                ExprKind::Binary(op, a, b) => {
+                    op.node == BinOpKind::Add && is_len(a) && is_int_lit(b, 1)
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Check whether `ex` is the first half of a push, `*(*b).data.offset((*b).len as isize) = x`.
+    /// Returns the struct, the pointer field expression, the value being stored, and the local
+    /// that holds the struct.
+    fn push_write(
+        &self,
+        ex: &'tcx Expr<'tcx>,
+    ) -> Option<(DefId, &'tcx Expr<'tcx>, &'tcx Expr<'tcx>, HirId)> {
+        let (lhs, value) = match ex.kind {
+            ExprKind::Assign(lhs, value, _) => (lhs, value),
+            _ => return None,
+        };
+        let (adt, ptr, index) = match lhs.kind {
+            ExprKind::Unary(UnOp::Deref, inner) => self.elem_ptr(inner)?,
+            _ => return None,
+        };
+        let owner = self.field_owner(ptr, FieldRole::Ptr)?;
+        if self.field_owner(peel_casts(index?), FieldRole::Len) != Some(owner) {
+            return None;
+        }
+        Some((adt, ptr, value, owner))
+    }
+
+    /// Check whether `local` is `let p = realloc((*b).data as *mut c_void, n) as *mut T;`.
+    /// Returns the binding `p`, the struct, and the local that holds the struct.
+    fn grow_temp(&self, local: &'tcx hir::Local<'tcx>) -> Option<(HirId, DefId, HirId)> {
+        let binding = match local.pat.kind {
+            hir::PatKind::Binding(_, hir_id, _, None) => hir_id,
+            _ => return None,
+        };
+        let init = peel_casts(local.init?);
+        let old_ptr = match init.kind {
+            ExprKind::Call(_, [old_ptr, _])
+                if matches!(self.callee(init), Some(Callee::Realloc)) =>
+            {
+                peel_casts(old_ptr)
+            }
+            _ => return None,
+        };
+        let (adt, ..) = self.vec_field(old_ptr)?;
+        let owner = self.field_owner(old_ptr, FieldRole::Ptr)?;
+        Some((binding, adt, owner))
+    }
+
+    /// Check whether `ex` is a `GrowTemp` holding the grown buffer of the pointer field `ptr`.
+    fn is_grow_temp_of(&self, ex: &'tcx Expr<'tcx>, ptr: &'tcx Expr<'tcx>) -> bool {
+        let temp = local_var(peel_casts(ex)).and_then(|v| self.grow_temps.get(&v));
+        match (temp, self.field_owner(ptr, FieldRole::Ptr)) {
+            (Some(&(_, temp_owner)), Some(owner)) => temp_owner == owner,
+            _ => false,
+        }
+    }
+
+    /// Record an unsupported use of the fields of `adt`.
+    fn other(&mut self, adt: DefId, span: Span) {
+        debug!("unsupported use of vec struct {adt:?} at {span:?}");
+        self.uses.push((adt, VecFieldUse::Other));
+    }
+
+    /// Check whether the struct literal `ex` builds an empty buffer.
+    fn visit_literal(&mut self, adt: DefId, fields: &'tcx [hir::ExprField<'tcx>]) -> bool {
+        let vf = self.structs[&adt];
+        let mut ptr_value = None;
+        let mut removed = Vec::new();
+        for field in fields {
+            let idx = match self.typeck_results.field_indices().get(field.hir_id) {
+                Some(&x) => x,
+                None => return false,
+            };
+            let did = self.tcx.adt_def(adt).non_enum_variant().fields[idx].did;
+            if did == vf.ptr {
+                if !self.is_null(field.expr) {
+                    return false;
+                }
+                ptr_value = Some(field.expr.span);
+            } else if did == vf.len || did == vf.cap {
+                if !is_int_lit(field.expr, 0) {
+                    return false;
+                }
+                removed.push(removal_span(self.tcx, field.span));
+            } else {
+                self.visit_expr(field.expr);
+            }
+        }
+        match (ptr_value, <[Span; 2]>::try_from(removed)) {
+            (Some(ptr_value), Ok(removed)) => {
+                self.uses
+                    .push((adt, VecFieldUse::Literal { ptr_value, removed }));
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for VecFieldUseVisitor<'a, 'tcx> {
+    fn visit_block(&mut self, block: &'tcx Block<'tcx>) {
+        // `*(*b).data.offset((*b).len as isize) = x; (*b).len += 1;`
+        let mut i = 0;
+        while i < block.stmts.len() {
+            let push = match (
+                &block.stmts[i].kind,
+                block.stmts.get(i + 1).map(|s| &s.kind),
+            ) {
+                (
+                    &StmtKind::Semi(write) | &StmtKind::Expr(write),
+                    Some(&StmtKind::Semi(incr) | &StmtKind::Expr(incr)),
+                ) => self
+                    .push_write(write)
+                    .filter(|&(.., owner)| self.is_len_incr(incr, owner))
+                    .map(|(adt, ptr, value, _)| (adt, write, ptr, value)),
+                _ => None,
+            };
+            if let StmtKind::Local(local) = block.stmts[i].kind {
+                if let Some((binding, adt, owner)) = self.grow_temp(local) {
+                    self.grow_temps.insert(binding, (adt, owner));
+                    self.uses.push((
+                        adt,
+                        VecFieldUse::GrowTemp {
+                            stmt: removal_span(self.tcx, block.stmts[i].span),
+                        },
+                    ));
+                    i += 1;
+                    continue;
+                }
+            }
+            match push {
+                Some((adt, write, ptr, value)) => {
+                    self.uses.push((
+                        adt,
+                        VecFieldUse::Push {
+                            write: write.span,
+                            ptr: ptr.span,
+                            value: value.span,
+                            incr: removal_span(self.tcx, block.stmts[i + 1].span),
+                        },
+                    ));
+                    self.visit_expr(value);
+                    i += 2;
+                }
+                None => {
+                    self.visit_stmt(&block.stmts[i]);
+                    i += 1;
+                }
+            }
+        }
+        if let Some(ex) = block.expr {
+            self.visit_expr(ex);
+        }
+    }
+
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        match ex.kind {
+            // `(*b).len`
+            ExprKind::Field(..) => {
+                if let Some((adt, role, _, ident)) = self.vec_field(ex) {
+                    if !self.handled.contains(&ex.hir_id) {
+                        match role {
+                            FieldRole::Len | FieldRole::Cap => {
+                                self.uses.push((
+                                    adt,
+                                    VecFieldUse::Count {
+                                        expr: ex.span,
+                                        ident,
+                                        cap: role == FieldRole::Cap,
+                                        ty: self.typeck_results.expr_ty(ex),
+                                    },
+                                ));
+                            }
+                            FieldRole::Ptr => self.other(adt, ex.span),
+                        }
+                    }
+                }
+            }
+
+            // `*(*b).data.offset(i)`
+            ExprKind::Unary(UnOp::Deref, inner) => {
+                if let Some((adt, ptr, index)) = self.elem_ptr(inner) {
+                    self.handled.insert(ptr.hir_id);
+                    self.uses.push((
+                        adt,
+                        VecFieldUse::Elem {
+                            expr: ex.span,
+                            ptr: ptr.span,
+                            index: index.map(|i| i.span),
+                        },
+                    ));
+                }
+            }
+
+            ExprKind::Assign(lhs, rhs, _) => match self.vec_field(lhs) {
+                Some((adt, FieldRole::Ptr, _, _)) => {
+                    self.handled.insert(lhs.hir_id);
+                    match self.callee(peel_casts(rhs)) {
+                        // `(*b).data = realloc(..) as *mut T`
+                        Some(Callee::Realloc) => {
+                            self.uses
+                                .push((adt, VecFieldUse::Realloc { expr: ex.span }));
+                            // The whole assignment is removed, including the old size.
+                            return;
+                        }
+                        // `(*b).data = malloc(..) as *mut T`
+                        Some(Callee::Malloc | Callee::Calloc) => {
+                            self.uses.push((
+                                adt,
+                                VecFieldUse::Init {
+                                    expr: ex.span,
+                                    lhs: lhs.span,
+                                },
+                            ));
+                            return;
+                        }
+                        // `(*b).data = p`, where `p` is a `GrowTemp`
+                        _ if self.is_grow_temp_of(rhs, lhs) => {
+                            self.uses
+                                .push((adt, VecFieldUse::Realloc { expr: ex.span }));
+                            return;
+                        }
+                        // `(*b).data = ptr::null_mut()`
+                        _ if self.is_null(rhs) => {
+                            self.uses.push((
+                                adt,
+                                VecFieldUse::Init {
+                                    expr: ex.span,
+                                    lhs: lhs.span,
+                                },
+                            ));
+                            return;
+                        }
+                        _ => self.other(adt, ex.span),
+                    }
+                }
+                // `(*b).len = 0`
+                Some((adt, FieldRole::Len, _, ident)) => {
+                    self.handled.insert(lhs.hir_id);
+                    if is_int_lit(rhs, 0) {
+                        self.uses.push((
+                            adt,
+                            VecFieldUse::Clear {
+                                expr: ex.span,
+                                lhs: lhs.span,
+                                ident,
+                            },
+                        ));
+                    } else {
+                        self.other(adt, ex.span);
+                    }
+                }
+                // `(*b).cap = n`
+                Some((adt, FieldRole::Cap, _, ident)) => {
+                    self.handled.insert(lhs.hir_id);
+                    self.uses.push((
+                        adt,
+                        VecFieldUse::Reserve {
+                            expr: ex.span,
+                            lhs: lhs.span,
+                            ident,
+                            rhs: rhs.span,
+                        },
+                    ));
+                }
+                None => {}
+            },
+
+            // `p.is_null()`, where `p` is a `GrowTemp`
+            ExprKind::MethodCall(seg, [recv], _) if seg.ident.as_str() == "is_null" => {
+                if let Some(&(adt, _)) = local_var(recv).and_then(|v| self.grow_temps.get(&v)) {
+                    self.uses
+                        .push((adt, VecFieldUse::GrowCheck { expr: ex.span }));
+                    return;
+                }
+            }
+
+            // Any other use of a `GrowTemp`
+            ExprKind::Path(..) => {
+                if let Some(&(adt, _)) = local_var(ex).and_then(|v| self.grow_temps.get(&v)) {
+                    self.other(adt, ex.span);
+                }
+            }
+
+            // `(*b).len -= 1`, `&mut (*b).len`
+            ExprKind::AssignOp(_, pl, _) | ExprKind::AddrOf(_, _, pl) => {
+                if let Some((adt, ..)) = self.vec_field(pl) {
+                    self.handled.insert(pl.hir_id);
+                    self.other(adt, ex.span);
+                }
+            }
+
+            // `free((*b).data as *mut c_void)`
+            ExprKind::Call(_, [arg]) if matches!(self.callee(ex), Some(Callee::Free)) => {
+                if let ExprKind::Cast(ptr, _) = arg.kind {
+                    if let Some((adt, FieldRole::Ptr, _, _)) = self.vec_field(ptr) {
+                        self.uses.push((
+                            adt,
+                            VecFieldUse::Free {
+                                call: ex.span,
+                                ptr: ptr.span,
+                            },
+                        ));
+                        return;
+                    }
+                }
+            }
+
+            // `S { data: 0 as *mut T, len: 0, cap: 0 }`
+            ExprKind::Struct(_, fields, base) => {
+                if let TyKind::Adt(adt_def, _) = *self.typeck_results.expr_ty(ex).kind() {
+                    let adt = adt_def.did();
+                    if self.structs.contains_key(&adt) {
+                        if base.is_none() && self.visit_literal(adt, fields) {
+                            return;
+                        }
+                        self.other(adt, ex.span);
+                    }
+                }
+            }
+
+            _ => {}
+        }
+
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+fn local_var(ex: &Expr) -> Option<HirId> {
+    match ex.kind {
+        ExprKind::Path(hir::QPath::Resolved(_, path)) => match path.res {
+            Res::Local(hir_id) => Some(hir_id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn peel_casts<'a, 'tcx>(mut ex: &'a Expr<'tcx>) -> &'a Expr<'tcx> {
+    while let ExprKind::Cast(inner, _) = ex.kind {
+        ex = inner;
+    }
+    ex
+}
+
+/// Check whether `ex` is the integer literal `n`, possibly with casts, as in `0 as size_t`.
+fn is_int_lit(ex: &Expr, n: u128) -> bool {
+    match peel_casts(ex).kind {
+        ExprKind::Lit(ref lit) => matches!(lit.node, LitKind::Int(x, _) if x == n),
+        _ => false,
+    }
+}
+
+/// Extend the span of a field or statement to be removed so that it covers the whitespace before
+/// it and the comma after it, if any.  Removing the resulting span leaves the surrounding list
+/// well-formed and doesn't leave an empty line behind.
+pub fn removal_span(tcx: TyCtxt, span: Span) -> Span {
+    let source_map = tcx.sess.source_map();
+    let mut span = span;
+    if let Ok(prev) = source_map.span_to_prev_source(span) {
+        let ws = prev.len() - prev.trim_end().len();
+        span = span.with_lo(span.lo() - BytePos(ws as u32));
+    }
+    if let Ok(next) = source_map.span_to_next_source(span) {
+        if next.starts_with(',') {
+            span = span.with_hi(span.hi() + BytePos(1));
+        }
+    }
+    span
+}
+
+/// Run `VecFieldUseVisitor` over the body of `ldid`.
+pub fn collect_vec_field_uses<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ldid: LocalDefId,
+    structs: &HashMap<DefId, VecFields>,
+) -> Vec<(DefId, VecFieldUse<'tcx>)> {
+    let hir_body_id = match tcx.hir().maybe_body_owned_by(ldid) {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+    let mut v = VecFieldUseVisitor::new(tcx, tcx.typeck_body(hir_body_id), structs);
+    v.visit_body(tcx.hir().body(hir_body_id));
+    v.uses
+}
+
+/// Check whether `ty` is a raw pointer to a sized element type other than `c_void`.
+fn is_elem_ptr(tcx: TyCtxt, ty: Ty) -> bool {
+    let pointee = match *ty.kind() {
+        TyKind::RawPtr(tm) => tm.ty,
+        _ => return false,
+    };
+    match *pointee.kind() {
+        TyKind::Adt(adt_def, _) if tcx.item_name(adt_def.did()).as_str() == "c_void" => false,
+        TyKind::Slice(_) | TyKind::Str | TyKind::Dynamic(..) | TyKind::Foreign(_) => false,
+        _ => true,
+    }
+}
+
+/// Find the `(ptr, len, cap)` fields of `adt_did`, using the configured field names.
+fn find_vec_fields(tcx: TyCtxt, names: &FieldNames, adt_did: DefId) -> Option<VecFields> {
+    let mut ptr = None;
+    let mut len = None;
+    let mut cap = None;
+    for field in tcx.adt_def(adt_did).all_fields() {
+        let ty = tcx.type_of(field.did);
+        let slot = match names.role(field.name.as_str()) {
+            Some(FieldRole::Ptr) if is_elem_ptr(tcx, ty) => &mut ptr,
+            Some(FieldRole::Len) if ty.is_integral() => &mut len,
+            Some(FieldRole::Cap) if ty.is_integral() => &mut cap,
+            _ => continue,
+        };
+        if slot.is_some() {
+            // Ambiguous: more than one field could play this role.
+            return None;
+        }
+        *slot = Some(field.did);
+    }
+    Some(VecFields {
+        ptr: ptr?,
+        len: len?,
+        cap: cap?,
+    })
+}
+
+/// Find growable-buffer structs whose fields can be replaced by a `Vec<T>`.
+pub fn find_vec_structs(gacx: &GlobalAnalysisCtxt, all_fn_ldids: &[LocalDefId]) -> VecStructInfo {
+    let tcx = gacx.tcx;
+    let names = FieldNames::from_env();
+
+    // Candidates are local structs with the right field names that don't cross an FFI boundary.
+    let mut candidates = HashMap::new();
+    for &adt_did in &gacx.adt_metadata.struct_dids {
+        if !adt_did.is_local() || gacx.foreign_mentioned_tys.contains(&adt_did) {
+            continue;
+        }
+        if !matches!(tcx.def_kind(adt_did), DefKind::Struct) {
+            continue;
+        }
+        if let Some(fields) = find_vec_fields(tcx, &names, adt_did) {
+            candidates.insert(adt_did, fields);
+        }
+    }
+    if candidates.is_empty() {
+        return VecStructInfo::default();
+    }
+
+    let mut ok = candidates
+        .keys()
+        .map(|&did| (did, true))
+        .collect::<HashMap<_, _>>();
+    // A struct stored in a `static` would need its initializer rewritten as well.
+    for &did in gacx.static_tys.keys() {
+        if let TyKind::Adt(adt_def, _) = *tcx.type_of(did).kind() {
+            if let Some(flag) = ok.get_mut(&adt_def.did()) {
+                *flag = false;
+            }
+        }
+    }
+
+    let mut fn_adts = HashMap::new();
+    for &ldid in all_fn_ldids {
+        let uses = collect_vec_field_uses(tcx, ldid, &candidates);
+        let mut adts = HashSet::new();
+        for (did, u) in uses {
+            if let VecFieldUse::Other = u {
+                ok.insert(did, false);
+            }
+            adts.insert(did);
+        }
+        if !adts.is_empty() {
+            fn_adts.insert(ldid, adts);
+        }
+    }
+
+    let mut info = VecStructInfo::default();
+    for (did, fields) in candidates {
+        if ok[&did] {
+            debug!("found vec struct {did:?}, with fields {fields:?}");
+            info.structs.insert(did, fields);
+        }
+    }
+    for (ldid, adts) in fn_adts {
+        if adts.iter().any(|did| info.structs.contains_key(did)) {
+            info.fns.insert(ldid.to_def_id());
+        }
+    }
+    info
+}
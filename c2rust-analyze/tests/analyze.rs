@@ -6,7 +6,7 @@ use crate::common::Analyze;
 use crate::common::CrateOptions;
 use crate::common::CrateType;
 use fs_err::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 #[test]
@@ -93,3 +93,356 @@ fn with_pdg_file() {
         Some(crate_options),
     );
 }
+
+#[test]
+fn pdg_facts() {
+    let analyze = Analyze::resolve();
+    let mut cmd = Command::new(analyze.path());
+
+    // `cargo` runs `rustc` in the workspace root, so this only works if the relative path is
+    // resolved against the current directory first.
+    let pdg_path = Path::new("../analysis/tests/minimal/reference_pdg.bc");
+    cmd.arg("--pdg-facts").arg(pdg_path);
+
+    cmd.arg("--");
+    cmd.arg("check");
+    cmd.arg("--manifest-path")
+        .arg("../analysis/tests/minimal/Cargo.toml");
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("pdg_facts");
+    cmd.arg("--target-dir").arg(target_dir);
+
+    let output = cmd.output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    let abs_pdg_path = std::env::current_dir().unwrap().join(pdg_path);
+    let loaded = format!(" PDG graphs from {abs_pdg_path:?}");
+    assert!(
+        stderr
+            .lines()
+            .any(|line| line.starts_with("loaded ") && line.ends_with(&loaded)),
+        "{stderr}"
+    );
+}
+
+/// Copy the test input `file_name` into a fresh directory under the target directory, so that
+/// tests can edit it and keep other files alongside it.  Returns the path of the copy.
+fn copy_to_tmp_dir(file_name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(Path::new(file_name).with_extension(""));
+    let _ = fs_err::remove_dir_all(&dir);
+    fs_err::create_dir_all(&dir).unwrap();
+    let path = dir.join(file_name);
+    fs_err::copy(test_dir_for(file!(), true).join(file_name), &path).unwrap();
+    path
+}
+
+/// Get the `final labeling` sections of the analysis output.
+fn final_labeling(output: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut in_labeling = false;
+    for line in output.lines() {
+        if line.starts_with("final labeling for ") {
+            in_labeling = true;
+        } else if line.is_empty() {
+            in_labeling = false;
+        }
+        if in_labeling {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+#[test]
+fn fn_cache() {
+    let path = copy_to_tmp_dir("fn_cache.rs");
+    let cache_dir = path.with_file_name("cache");
+    let run = || {
+        let output_path = Analyze::resolve().run_with(
+            &path,
+            |cmd| {
+                cmd.env("C2RUST_ANALYZE_CACHE_DIR", &cache_dir);
+            },
+            None,
+        );
+        fs_err::read_to_string(output_path).unwrap()
+    };
+    let loaded = |output: &str, name: &str| {
+        let suffix = format!("::{name})");
+        output.lines().any(|line| {
+            line.starts_with("loaded cached dataflow constraints for ") && line.ends_with(&suffix)
+        })
+    };
+
+    // Nothing is cached on the first run.
+    let first = run();
+    for name in ["get", "set", "swap"] {
+        assert!(
+            !loaded(&first, name),
+            "{name} was loaded from an empty cache"
+        );
+    }
+
+    // The second run reuses every entry and gets the same results.
+    let second = run();
+    for name in ["get", "set", "swap"] {
+        assert!(loaded(&second, name), "{name} wasn't loaded from the cache");
+    }
+    assert!(second.contains("loaded cached permissions for "));
+    assert_eq!(final_labeling(&first), final_labeling(&second));
+
+    // Editing the body of `get` invalidates its entry, but not those of the other functions.
+    let src = fs_err::read_to_string(&path).unwrap();
+    fs_err::write(&path, src.replacen("    *p\n", "    *p + 1\n", 1)).unwrap();
+    let third = run();
+    assert!(!loaded(&third, "get"), "get was loaded from a stale entry");
+    assert!(loaded(&third, "set"));
+    assert!(loaded(&third, "swap"));
+}
+
+/// Run the analysis on `path`, with the environment variable `var` set to `out_path`, and parse
+/// the JSON written to `out_path`.
+fn run_with_json_output(path: &Path, var: &str, out_path: &Path) -> serde_json::Value {
+    Analyze::resolve().run_with(
+        path,
+        |cmd| {
+            cmd.env(var, out_path);
+        },
+        None,
+    );
+    serde_json::from_str(&fs_err::read_to_string(out_path).unwrap()).unwrap()
+}
+
+#[test]
+fn output_facts() {
+    let path = copy_to_tmp_dir("output_facts.rs");
+    let out_path = path.with_file_name("facts.json");
+    let facts = run_with_json_output(&path, "C2RUST_ANALYZE_OUTPUT_FACTS", &out_path);
+    let facts = facts.as_array().unwrap();
+
+    let sig = facts
+        .iter()
+        .find(|f| f["owner"] == "get" && f["kind"] == "sig")
+        .unwrap();
+    assert_eq!(sig["crate"], "output_facts");
+    assert!(sig["ptr"].as_str().unwrap().starts_with('g'));
+    assert!(sig["span"]["file"]
+        .as_str()
+        .unwrap()
+        .ends_with("output_facts.rs"));
+    assert_eq!(sig["span"]["lo_line"], 1);
+    assert!(sig["perms"].as_array().unwrap().iter().any(|p| p == "READ"));
+    assert_eq!(sig["orig_ty"], "*const i32");
+    assert!(sig["rewrite_ty"].as_str().unwrap().starts_with('&'));
+    assert!(sig["failure"].is_null());
+
+    // A second crate writing to the same file adds its facts after the existing ones.
+    let again = run_with_json_output(&path, "C2RUST_ANALYZE_OUTPUT_FACTS", &out_path);
+    let again = again.as_array().unwrap();
+    assert_eq!(again.len(), 2 * facts.len());
+    assert_eq!(&again[..facts.len()], &facts[..]);
+}
+
+#[test]
+fn sarif() {
+    let path = copy_to_tmp_dir("sarif.rs");
+    let out_path = path.with_file_name("sarif.json");
+    let log = run_with_json_output(&path, "C2RUST_ANALYZE_SARIF", &out_path);
+    assert_eq!(log["version"], "2.1.0");
+    let runs = log["runs"].as_array().unwrap();
+    assert_eq!(runs.len(), 1);
+
+    let run = &runs[0];
+    assert_eq!(run["tool"]["driver"]["name"], "c2rust-analyze");
+    assert_eq!(run["automationDetails"]["id"], "c2rust-analyze/sarif");
+    let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+    assert!(rules.iter().any(|r| r["id"] == "fn-not-rewritten"));
+
+    let results = run["results"].as_array().unwrap();
+    let result = results
+        .iter()
+        .find(|r| r["ruleId"] == "fn-not-rewritten")
+        .unwrap();
+    assert_eq!(result["level"], "warning");
+    assert!(result["message"]["text"]
+        .as_str()
+        .unwrap()
+        .starts_with("`fixed` is not rewritten: "));
+    let loc = &result["locations"][0]["physicalLocation"];
+    assert!(loc["artifactLocation"]["uri"]
+        .as_str()
+        .unwrap()
+        .ends_with("sarif.rs"));
+    assert_eq!(loc["region"]["startLine"], 9);
+    assert!(!results
+        .iter()
+        .any(|r| r["message"]["text"].as_str().unwrap().contains("`good`")));
+
+    // A second crate writing to the same log adds its own run.
+    let log = run_with_json_output(&path, "C2RUST_ANALYZE_SARIF", &out_path);
+    let runs = log["runs"].as_array().unwrap();
+    assert_eq!(runs.len(), 2);
+    assert_eq!(runs[0]["results"], runs[1]["results"]);
+}
+
+#[test]
+fn migration_stats() {
+    let path = copy_to_tmp_dir("migration_stats.rs");
+    let out_path = path.with_file_name("stats.json");
+    let all = run_with_json_output(&path, "C2RUST_ANALYZE_STATS_JSON", &out_path);
+    let all = all.as_array().unwrap();
+    assert_eq!(all.len(), 1);
+
+    let stats = &all[0];
+    assert_eq!(stats["crate"], "migration_stats");
+    assert_eq!(stats["raw_ptrs"], 2);
+    assert_eq!(stats["raw_ptrs_eliminated"], 1);
+    assert_eq!(stats["unsafe_blocks"], 2);
+    assert_eq!(stats["fns"], 2);
+    assert_eq!(
+        stats["raw_reasons"],
+        serde_json::json!([{ "reason": "FIXED", "count": 1 }])
+    );
+
+    // A second crate writing to the same file adds its statistics after the existing ones.
+    let all = run_with_json_output(&path, "C2RUST_ANALYZE_STATS_JSON", &out_path);
+    let all = all.as_array().unwrap();
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[0], all[1]);
+}
+
+#[test]
+fn rewrite_manifest() {
+    let path = copy_to_tmp_dir("rewrite_manifest.rs");
+    let out_path = path.with_file_name("manifest.json");
+    let src = fs_err::read_to_string(&path).unwrap();
+    let entries = run_with_json_output(&path, "C2RUST_ANALYZE_REWRITE_MANIFEST", &out_path);
+    let entries = entries.as_array().unwrap();
+
+    let entry = entries.iter().find(|e| e["old"] == "*const i32").unwrap();
+    assert_eq!(entry["crate"], "rewrite_manifest");
+    assert!(entry["new"].as_str().unwrap().starts_with('&'));
+    assert_eq!(entry["template"], entry["new"]);
+    assert_eq!(entry["subs"], serde_json::json!([]));
+    let span = &entry["span"];
+    assert!(span["file"]
+        .as_str()
+        .unwrap()
+        .ends_with("rewrite_manifest.rs"));
+    assert_eq!(span["lo_line"], 1);
+    let byte_lo = span["byte_lo"].as_u64().unwrap() as usize;
+    let byte_hi = span["byte_hi"].as_u64().unwrap() as usize;
+    assert_eq!(&src[byte_lo..byte_hi], "*const i32");
+
+    // A second crate writing to the same file adds its entries after the existing ones.
+    let again = run_with_json_output(&path, "C2RUST_ANALYZE_REWRITE_MANIFEST", &out_path);
+    let again = again.as_array().unwrap();
+    assert_eq!(again.len(), 2 * entries.len());
+    assert_eq!(&again[..entries.len()], &entries[..]);
+}
+
+#[test]
+fn patch_mode() {
+    let path = copy_to_tmp_dir("patch_mode.rs");
+    let patch_path = path.with_file_name("rewrites.patch");
+    let src = fs_err::read_to_string(&path).unwrap();
+    Analyze::resolve().run_with(
+        &path,
+        |cmd| {
+            cmd.env("C2RUST_ANALYZE_REWRITE_MODE", "patch")
+                .env("C2RUST_ANALYZE_PATCH_FILE", &patch_path);
+        },
+        None,
+    );
+
+    let patch = fs_err::read_to_string(&patch_path).unwrap();
+    let lines = patch.lines().collect::<Vec<_>>();
+    assert!(lines[0].starts_with("--- a/"), "{patch}");
+    assert!(lines[0].ends_with("patch_mode.rs"), "{patch}");
+    assert!(lines[1].starts_with("+++ b/"), "{patch}");
+    assert!(lines[1].ends_with("patch_mode.rs"), "{patch}");
+    assert!(lines[2].starts_with("@@ -1,"), "{patch}");
+    assert!(lines.contains(&"-pub unsafe fn get(p: *const i32) -> i32 {"));
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("+pub unsafe fn get(p: &")));
+
+    // The source files themselves are left alone.
+    assert_eq!(fs_err::read_to_string(&path).unwrap(), src);
+    assert!(!path.with_extension("new.rs").exists());
+}
+
+#[test]
+fn analysis_facts() {
+    let path = copy_to_tmp_dir("analysis_facts.rs");
+    let facts_dir = path.with_file_name("facts");
+    let run = |var: &str| {
+        let output_path = Analyze::resolve().run_with(
+            &path,
+            |cmd| {
+                cmd.env(var, &facts_dir);
+            },
+            None,
+        );
+        fs_err::read_to_string(output_path).unwrap()
+    };
+
+    let saved = run("C2RUST_ANALYZE_SAVE_ANALYSIS_FACTS");
+    let loaded = run("C2RUST_ANALYZE_LOAD_ANALYSIS_FACTS");
+
+    let count = loaded
+        .lines()
+        .find_map(|line| line.strip_prefix("loaded analysis facts for "))
+        .and_then(|rest| rest.split(' ').next())
+        .map(|n| n.parse::<usize>().unwrap());
+    assert!(matches!(count, Some(n) if n > 0), "{loaded}");
+
+    // Starting from the saved facts gives the same results, so no changes are reported.
+    let mut lines = loaded.lines();
+    assert!(lines.any(|line| line == "changes since loaded analysis facts:"));
+    assert!(!lines.next().unwrap_or("").starts_with("  "), "{loaded}");
+    assert_eq!(final_labeling(&saved), final_labeling(&loaded));
+}
+
+/// Run the analysis on the two-crate workspace in `analysis/tests/crate-facts`, and return the
+/// rewritten signature of `read_it` from the binary crate, or `None` if its file isn't rewritten.
+fn crate_facts_read_it_sig(name: &str, crate_facts_dir: Option<&Path>) -> Option<String> {
+    let analyze = Analyze::resolve();
+    let mut cmd = Command::new(analyze.path());
+    if let Some(dir) = crate_facts_dir {
+        cmd.arg("--crate-facts-dir").arg(dir);
+    }
+
+    cmd.arg("--");
+    cmd.arg("check");
+    cmd.arg("--manifest-path")
+        .arg("../analysis/tests/crate-facts/Cargo.toml");
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs_err::remove_dir_all(&target_dir);
+    cmd.arg("--target-dir").arg(target_dir);
+
+    let output = cmd.output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    // `cargo` passes on the output of `rustc`, but may not keep it on the same stream.
+    stdout
+        .lines()
+        .chain(stderr.lines())
+        .find(|line| line.starts_with("unsafe fn read_it") || line.starts_with("fn read_it"))
+        .map(|line| line.to_owned())
+}
+
+#[test]
+fn crate_facts() {
+    // Without the library's facts, `get` is an unknown function, so its argument stays raw.
+    let sig = crate_facts_read_it_sig("crate_facts_none", None);
+    assert!(sig.map_or(true, |sig| sig.contains("p: *const i32")));
+
+    // The library is analyzed first and saves the facts for `get`, whose argument is only read.
+    // The binary loads them, and `p` can become a shared reference.
+    let facts_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("crate_facts_dir");
+    let _ = fs_err::remove_dir_all(&facts_dir);
+    let sig = crate_facts_read_it_sig("crate_facts", Some(&facts_dir)).unwrap();
+    assert!(sig.contains("p: &"), "{sig}");
+    assert!(!sig.contains("mut"), "{sig}");
+}
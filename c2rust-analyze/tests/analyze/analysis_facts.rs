@@ -0,0 +1,16 @@
+pub struct Pair {
+    pub a: *mut i32,
+    pub b: *const i32,
+}
+
+pub unsafe fn get(p: *const i32) -> i32 {
+    *p
+}
+
+pub unsafe fn set(p: *mut i32, x: i32) {
+    *p = x;
+}
+
+pub unsafe fn copy(pair: *mut Pair) {
+    set((*pair).a, get((*pair).b));
+}
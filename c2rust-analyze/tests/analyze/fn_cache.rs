@@ -0,0 +1,13 @@
+pub unsafe fn get(p: *const i32) -> i32 {
+    *p
+}
+
+pub unsafe fn set(p: *mut i32, x: i32) {
+    *p = x;
+}
+
+pub unsafe fn swap(p: *mut i32, q: *mut i32) {
+    let x = get(p);
+    set(p, get(q));
+    set(q, x);
+}
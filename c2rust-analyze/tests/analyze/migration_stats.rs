@@ -0,0 +1,15 @@
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+
+fn good(x: *mut i32) {
+    unsafe {
+        *x = 1;
+    }
+}
+
+#[c2rust_analyze_test::fixed_signature]
+fn fixed(x: *mut i32) {
+    unsafe {
+        *x = 1;
+    }
+}
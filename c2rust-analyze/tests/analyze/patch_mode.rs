@@ -0,0 +1,3 @@
+pub unsafe fn get(p: *const i32) -> i32 {
+    *p
+}
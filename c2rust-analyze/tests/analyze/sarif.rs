@@ -0,0 +1,11 @@
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+
+pub unsafe fn good(p: *mut i32) {
+    *p = 1;
+}
+
+#[c2rust_analyze_test::fixed_signature]
+pub unsafe fn fixed(p: *mut i32) {
+    *p = 1;
+}
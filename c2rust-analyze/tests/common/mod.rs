@@ -9,7 +9,7 @@ use std::{
 };
 
 use c2rust_build_paths::find_llvm_config;
-use clap::Parser;
+use clap::{ArgAction, Parser};
 
 #[derive(Default)]
 pub struct Analyze {
@@ -63,6 +63,22 @@ struct AnalyzeArgs {
     #[clap(long)]
     rewrite_paths: Option<OsString>,
 
+    /// Only rewrite functions whose path matches this regex.  Can be given more than once.
+    #[clap(long, action(ArgAction::Append))]
+    include_fn: Vec<String>,
+
+    /// Don't rewrite functions whose path matches this regex.  Can be given more than once.
+    #[clap(long, action(ArgAction::Append))]
+    exclude_fn: Vec<String>,
+
+    /// Print the planned rewrites instead of applying them.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Add comments giving the inferred permissions instead of rewriting.
+    #[clap(long)]
+    annotate_only: bool,
+
     /// Use `todo!()` placeholders in shims for casts that must be implemented manually.
     ///
     /// When a function requires a shim, and the shim requires a cast that can't be generated
@@ -71,6 +87,62 @@ struct AnalyzeArgs {
     /// unsupported cast.
     #[clap(long)]
     use_manual_shims: bool,
+
+    /// Rewrite pointers to reference-counted structs as `Rc<RefCell<T>>`.
+    #[clap(long)]
+    use_rc: bool,
+
+    /// Rewrite offsets that can't be proven in bounds to slice operations that panic at run time.
+    #[clap(long)]
+    checked_offsets: bool,
+
+    /// Rewrite offsets of raw pointers with a known length to slices with run-time bounds checks.
+    #[clap(long)]
+    dynamic_checks: bool,
+
+    /// Copy callees for non-rewritten callers instead of leaving them unrewritten.
+    #[clap(long)]
+    clone_callees: bool,
+
+    /// Rewrite linked-list node structs to owned links or arena indices.
+    #[clap(long)]
+    linked_lists: bool,
+
+    /// Give exported `extern "C"` functions with safe bodies a safe Rust API.
+    #[clap(long)]
+    safe_wrappers: bool,
+
+    /// Remove `unsafe` blocks and `unsafe fn` qualifiers that are no longer needed.
+    #[clap(long)]
+    minimize_unsafe: bool,
+
+    /// Read permissions and pointee types for external functions from this TOML file, given
+    /// relative to the test file.
+    #[clap(long)]
+    known_fns_db: Option<PathBuf>,
+
+    /// Comma-separated list of variables, like `path::to::fn::var`, whose missing permissions
+    /// should be explained.
+    #[clap(long)]
+    explain: Option<OsString>,
+
+    /// Run a may-alias pre-pass before the borrow check: `borrowck`, `andersen`, or
+    /// `steensgaard`.
+    #[clap(long)]
+    alias_analysis: Option<OsString>,
+
+    /// Skip functions whose permission propagation takes longer than this many seconds.
+    #[clap(long)]
+    fn_time_budget: Option<OsString>,
+
+    /// Build the test as a binary crate, so that its `main` function is the entry point.
+    #[clap(long)]
+    bin: bool,
+
+    /// Also write the rewritten code alongside the test file, as `.new.rs`, and check that it
+    /// compiles.
+    #[clap(long)]
+    compile_rewritten: bool,
 }
 
 impl AnalyzeArgs {
@@ -191,18 +263,75 @@ impl Analyze {
         if !args.catch_panics {
             cmd.env("C2RUST_ANALYZE_TEST_DONT_CATCH_PANIC", "1");
         }
+        if args.dry_run {
+            cmd.env("C2RUST_ANALYZE_DRY_RUN", "1");
+        }
+        if args.annotate_only {
+            cmd.env("C2RUST_ANALYZE_ANNOTATE_ONLY", "1");
+        }
         if args.use_manual_shims {
             cmd.env("C2RUST_ANALYZE_USE_MANUAL_SHIMS", "1");
         }
+        if args.use_rc {
+            cmd.env("C2RUST_ANALYZE_USE_RC", "1");
+        }
+        if args.checked_offsets {
+            cmd.env("C2RUST_ANALYZE_CHECKED_OFFSETS", "1");
+        }
+        if args.dynamic_checks {
+            cmd.env("C2RUST_ANALYZE_DYNAMIC_CHECKS", "1");
+        }
+        if args.clone_callees {
+            cmd.env("C2RUST_ANALYZE_CLONE_CALLEES", "1");
+        }
+        if args.linked_lists {
+            cmd.env("C2RUST_ANALYZE_LINKED_LISTS", "1");
+        }
+        if args.safe_wrappers {
+            cmd.env("C2RUST_ANALYZE_SAFE_WRAPPERS", "1");
+        }
+        if args.minimize_unsafe {
+            cmd.env("C2RUST_ANALYZE_MINIMIZE_UNSAFE", "1");
+        }
+        if let Some(ref known_fns_db) = args.known_fns_db {
+            cmd.env(
+                "C2RUST_ANALYZE_KNOWN_FNS_DB",
+                rs_path.with_file_name(known_fns_db),
+            );
+        }
         if let Some(ref rewrite_paths) = args.rewrite_paths {
             cmd.env("C2RUST_ANALYZE_REWRITE_PATHS", rewrite_paths);
         }
+        if !args.include_fn.is_empty() {
+            cmd.env("C2RUST_ANALYZE_INCLUDE_FN", args.include_fn.join("\n"));
+        }
+        if !args.exclude_fn.is_empty() {
+            cmd.env("C2RUST_ANALYZE_EXCLUDE_FN", args.exclude_fn.join("\n"));
+        }
+        if let Some(ref explain) = args.explain {
+            cmd.env("C2RUST_ANALYZE_EXPLAIN", explain);
+        }
+        if let Some(ref alias_analysis) = args.alias_analysis {
+            cmd.env("C2RUST_ANALYZE_ALIAS_ANALYSIS", alias_analysis);
+        }
+        if let Some(ref fn_time_budget) = args.fn_time_budget {
+            cmd.env("C2RUST_ANALYZE_FN_TIME_BUDGET", fn_time_budget);
+        }
+        if args.compile_rewritten {
+            cmd.env("C2RUST_ANALYZE_REWRITE_MODE", "alongside");
+        }
+        let crate_type: &str = if args.bin {
+            CrateType::Bin
+        } else {
+            crate_options.crate_type
+        }
+        .into();
         cmd.arg(&rs_path)
             .arg("-L")
             .arg(lib_dir)
             .args([
                 "--crate-type",
-                crate_options.crate_type.into(),
+                crate_type,
                 "--edition",
                 &crate_options.edition.to_string(),
             ])
@@ -226,6 +355,9 @@ impl Analyze {
                 panic!("\n{message}\n{output}\n{message}");
             };
         }
+        if args.compile_rewritten {
+            compile_rewritten(&rs_path, lib_dir, crate_type, crate_options.edition);
+        }
         output_path
     }
 
@@ -243,6 +375,34 @@ impl Analyze {
     }
 }
 
+/// Check that the rewritten version of `rs_path`, written alongside it by
+/// `C2RUST_ANALYZE_REWRITE_MODE=alongside`, compiles.
+fn compile_rewritten(rs_path: &Path, lib_dir: &Path, crate_type: &str, edition: u16) {
+    let new_path = rs_path.with_extension("new.rs");
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("compile_rewritten");
+    fs::create_dir_all(&out_dir).unwrap();
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let mut cmd = Command::new(rustc);
+    cmd.arg(&new_path)
+        .arg("-L")
+        .arg(lib_dir)
+        .args([
+            "--crate-type",
+            crate_type,
+            "--edition",
+            &edition.to_string(),
+            "--emit",
+            "metadata",
+            "--out-dir",
+        ])
+        .arg(&out_dir);
+    let status = cmd.status().unwrap();
+    assert!(
+        status.success(),
+        "\nrewritten code failed to compile with status {status}:\n> {cmd:?}\n",
+    );
+}
+
 pub struct FileCheck {
     path: PathBuf,
 }
@@ -35,41 +35,115 @@ define_tests! {
     adjust_unsize,
     aggregate1,
     algo_md5,
+    align,
     alias1,
     alias2,
     alias3,
+    alias_analysis,
     alloc,
+    alloc_box,
+    annotate_only,
+    array_lens,
     as_ptr,
+    atomics,
+    box_fields,
     call1,
     call_cast,
     cast,
     catch_panic,
     cell,
+    checked_offsets,
     clone1,
+    clone_callees,
+    closure_callbacks,
+    closures,
+    const_tables,
+    cstr_literal,
+    cstring,
+    downgrade_ref_mut,
+    dry_run,
+    dynamic_checks,
+    errno_result,
+    explain,
     extern_fn1,
+    ffi_escape,
     fields,
     field_temp,
+    file_io,
     fixed,
+    flex_array,
+    fn_filters,
+    fn_ptr_fields,
+    fn_ptr_pointee,
+    fn_time_budget,
+    fn_tables,
     foreign,
+    foreign_memory,
+    free_null,
+    generic_void,
+    goto_cleanup,
+    inline_asm,
+    inout_params,
     insertion_sort,
     insertion_sort_driver,
     insertion_sort_rewrites,
     known_fn,
+    known_fn_db,
+    len_fields,
+    linked_lists,
+    macros,
+    memcpy,
+    memcpy_void,
+    memset,
+    migration_stats,
+    minimize_unsafe,
+    nested_fields,
     non_null,
     non_null_force,
     non_null_rewrites,
     offset1,
     offset2,
+    origins,
+    out_params,
     pointee,
+    pointee_conflict,
+    printf,
+    provenance,
+    ptr_diff,
+    ptr_loop,
     ptrptr1,
+    ptrptr_owned,
+    recursion,
+    ref_cell,
+    refcount,
     regions_fixed,
+    return_nullability,
     rewrite_paths,
     rewrite_paths_manual_shim,
+    safe_wrappers,
+    self_ref,
+    sentinel,
+    simple_buffer,
+    sprintf,
+    static_bufs,
+    static_kinds,
+    static_tables,
     statics,
+    struct_cast,
+    struct_lifetimes,
     test_attrs,
+    thread_shared,
     trivial,
     type_alias,
     type_annotation_rewrite,
+    union_variants,
+    unions,
+    unresolved_casts,
     unrewritten_calls,
     unrewritten_calls_shim_fail,
+    user_attrs,
+    user_data,
+    variadic,
+    vec_structs,
+    volatile,
 }
@@ -30,3 +30,11 @@ pub fn cast_array_to_ptr_explicit(s: &[u8; 1]) {
     // CHECK: &(*s) as *const u8;
     std::ptr::addr_of!(*s) as *const u8;
 }
+
+// CHECK-LABEL: unsafe fn mut_ref_unwritten() {
+unsafe fn mut_ref_unwritten() {
+    let mut z = 2;
+    // CHECK-DAG: let x = &(z);
+    let x = std::ptr::addr_of_mut!(z);
+    let y = *x;
+}
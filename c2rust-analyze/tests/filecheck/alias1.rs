@@ -14,15 +14,18 @@ pub unsafe fn alias1_good() {
 // CHECK-LABEL: final labeling for "alias1_bad"
 pub unsafe fn alias1_bad() {
     // CHECK-DAG: ([[@LINE+2]]: mut x): addr_of = READ | WRITE | NON_NULL,
-    // CHECK-DAG: ([[@LINE+1]]: mut x): addr_of flags = CELL,
+    // CHECK-DAG: ([[@LINE+1]]: mut x): addr_of flags = CELL | STACK,
     let mut x = 0;
     // CHECK-DAG: ([[@LINE+1]]: p): {{.*}}type = READ | WRITE | NON_NULL#
     let p = ptr::addr_of_mut!(x);
     // CHECK-DAG: ([[@LINE+2]]: q): {{.*}}type = NON_NULL#
-    // CHECK-DAG: ([[@LINE+1]]: q): {{.*}}type flags = CELL#
+    // CHECK-DAG: ([[@LINE+1]]: q): {{.*}}type flags = CELL | STACK#
     let q = ptr::addr_of_mut!(x);
     *p = 1;
 }
+// CHECK-LABEL: borrowck conflicts for "alias1_bad"
+// CHECK: lost UNIQUE: loan `{{.*}}` at [[@LINE-7]]: ptr::addr_of_mut!(x)
+// CHECK-NEXT: conflicts with `{{.*}}` at [[@LINE-5]]: ptr::addr_of_mut!(x)
 
 
 // The safe versions of these functions are useful for debugging Polonius fact generation, but
@@ -0,0 +1,17 @@
+//! --alias-analysis andersen --explain through_memory::q
+
+use std::ptr;
+
+// `q` is loaded from the slot that `p` was stored into, so the two may point to the same object.
+pub unsafe fn through_memory(p: *mut i32) {
+    let mut slot = ptr::null_mut();
+    let pp = ptr::addr_of_mut!(slot);
+    *pp = p;
+    let q = *pp;
+    *q = 1;
+    *p = 2;
+}
+
+// CHECK-LABEL: explain "through_memory::q":
+// CHECK: missing UNIQUE:
+// CHECK: lost UNIQUE in "through_memory": `{{.*}}` at [[@LINE-7]]: {{.*}}(NoPerms({{.*}}, UNIQUE))
@@ -0,0 +1,25 @@
+// CHECK-LABEL: unsafe fn is_aligned{{[<(]}}
+pub unsafe fn is_aligned(p: *const u8) -> bool {
+    // CHECK: .align_offset(8) == 0
+    (p as usize & 7) == 0
+}
+
+// CHECK-LABEL: unsafe fn is_not_aligned{{[<(]}}
+pub unsafe fn is_not_aligned(p: *const u8) -> bool {
+    // CHECK: !({{.*}}.align_offset(16) == 0)
+    p as usize % 16 != 0
+}
+
+// CHECK-LABEL: unsafe fn is_aligned_natural{{[<(]}}
+pub unsafe fn is_aligned_natural(p: *const u64) -> bool {
+    // CHECK: .is_aligned()
+    (p as usize & 7) == 0
+}
+
+// CHECK-LABEL: unsafe fn align_up_slice{{[<(]}}
+pub unsafe fn align_up_slice(p: *mut u8, n: usize) -> u8 {
+    let q = p.add(n);
+    // CHECK: &ptr[ptr.as_ptr().align_offset(8)..]
+    let r = ((q as usize).wrapping_add(7) & !7) as *mut u8;
+    *r
+}
@@ -85,7 +85,9 @@ pub unsafe extern "C" fn alloc_and_free1(mut cnt: libc::c_int) {
 
 // CHECK-LABEL: final labeling for "alloc_and_free2"
 pub unsafe extern "C" fn alloc_and_free2(mut cnt: libc::c_int) {
-    // CHECK-DAG: ([[@LINE+1]]: i): addr_of = UNIQUE | NON_NULL, type = READ | WRITE | UNIQUE | FREE | NON_NULL#
+    // `i` is checked for null, so it's nullable, but `b` and the argument to `free` are only
+    // assigned after the check succeeds, so they remain `NON_NULL`.
+    // CHECK-DAG: ([[@LINE+1]]: i): addr_of = UNIQUE | NON_NULL, type = READ | WRITE | UNIQUE | FREE#
     let i = malloc(::std::mem::size_of::<i32>() as libc::c_ulong) as *mut i32;
     if !i.is_null() {
         // CHECK-DAG: ([[@LINE+1]]: mut b): addr_of = UNIQUE | NON_NULL, type = READ | WRITE | UNIQUE | NON_NULL#
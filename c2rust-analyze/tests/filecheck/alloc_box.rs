@@ -0,0 +1,94 @@
+#![allow(dead_code)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn calloc(_: libc::c_ulong, _: libc::c_ulong) -> *mut libc::c_void;
+    fn realloc(_: *mut libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+// CHECK-LABEL: unsafe extern "C" fn alloc_and_free(
+pub unsafe extern "C" fn alloc_and_free() -> i32 {
+    // CHECK: let i: std::boxed::Box<(i32)> =
+    // CHECK: let (byte_len, ) =
+    // CHECK: Box::new(0)
+    let i: *mut i32 = malloc(::std::mem::size_of::<i32>() as libc::c_ulong) as *mut i32;
+    *i = 1;
+    let x = *i;
+    // CHECK: std::mem::drop((i))
+    free(i as *mut libc::c_void);
+    x
+}
+
+struct S {
+    a: i32,
+    b: bool,
+}
+
+// CHECK-LABEL: unsafe extern "C" fn alloc_and_free_struct(
+pub unsafe extern "C" fn alloc_and_free_struct() {
+    // CHECK: Box::new(S { a: 0, b: false, })
+    let s: *mut S = malloc(::std::mem::size_of::<S>() as libc::c_ulong) as *mut S;
+    (*s).a = 1;
+    // CHECK: std::mem::drop((s))
+    free(s as *mut libc::c_void);
+}
+
+struct G<T> {
+    x: T,
+}
+
+// CHECK-LABEL: unsafe extern "C" fn alloc_and_free_generic(
+pub unsafe extern "C" fn alloc_and_free_generic() {
+    // CHECK: Box::new(G::<u8> { x: 0, })
+    let g: *mut G<u8> = malloc(::std::mem::size_of::<G<u8>>() as libc::c_ulong) as *mut G<u8>;
+    (*g).x = 1;
+    free(g as *mut libc::c_void);
+}
+
+struct Empty {}
+
+// Zero-sized types are left alone, since the byte length can't be converted to an element count.
+// CHECK-LABEL: unsafe extern "C" fn alloc_zero_sized(
+pub unsafe extern "C" fn alloc_zero_sized() {
+    // CHECK-NOT: Box::new
+    // CHECK: malloc(
+    let e: *mut Empty = malloc(1) as *mut Empty;
+    free(e as *mut libc::c_void);
+}
+
+// CHECK-LABEL: unsafe extern "C" fn calloc_and_free(
+pub unsafe extern "C" fn calloc_and_free(n: libc::c_ulong) -> i32 {
+    // CHECK: let p: std::boxed::Box<[(i32)]> =
+    // CHECK: let (count, size, ) =
+    // CHECK: (0..n).map(|_| 0).collect::<Box<[_]>>()
+    let p: *mut i32 = calloc(n, ::std::mem::size_of::<i32>() as libc::c_ulong) as *mut i32;
+    *p.offset(1) = 1;
+    let x = *p;
+    // CHECK: std::mem::drop((p))
+    free(p as *mut libc::c_void);
+    x
+}
+
+// Structs are zeroed field by field, so they don't need to implement `Default` or `Clone`.
+// CHECK-LABEL: unsafe extern "C" fn calloc_struct(
+pub unsafe extern "C" fn calloc_struct(n: libc::c_ulong) {
+    // CHECK: (0..n).map(|_| S { a: 0, b: false, }).collect::<Box<[_]>>()
+    let p: *mut S = calloc(n, ::std::mem::size_of::<S>() as libc::c_ulong) as *mut S;
+    (*p.offset(1)).a = 1;
+    free(p as *mut libc::c_void);
+}
+
+// CHECK-LABEL: unsafe extern "C" fn realloc_grow(
+pub unsafe extern "C" fn realloc_grow(n: libc::c_ulong) {
+    let mut p: *mut i32 = malloc(::std::mem::size_of::<i32>() as libc::c_ulong) as *mut i32;
+    // CHECK: let (src_ptr, dest_byte_len, ) =
+    // CHECK: v.resize_with(dest_n, || 0)
+    // CHECK: v.into_boxed_slice()
+    p = realloc(p as *mut libc::c_void, n) as *mut i32;
+    *p.offset(1) = 1;
+    free(p as *mut libc::c_void);
+}
@@ -0,0 +1,35 @@
+//! --annotate-only
+
+// No code is rewritten; only comments giving the inferred permissions are added.
+// CHECK-LABEL: ===== BEGIN {{.*}}annotate_only.rs =====
+
+// CHECK-LABEL: static mut COUNTER: *mut i32
+// CHECK-NEXT: // c2rust: COUNTER: {{.*}}
+static mut COUNTER: *mut i32 = std::ptr::null_mut();
+
+// CHECK-LABEL: struct S {
+struct S {
+    // CHECK: data: *const u8,
+    // CHECK-NEXT: // c2rust: data: READ{{.*}}
+    data: *const u8,
+}
+
+// CHECK-LABEL: unsafe fn update(p: *mut i32, q: *const i32) -> *mut i32 {
+// CHECK-DAG: // c2rust: p: READ | WRITE | UNIQUE{{.*}}
+// CHECK-DAG: // c2rust: q: READ{{.*}}
+// CHECK-DAG: // c2rust: return: {{.*}}
+unsafe fn update(p: *mut i32, q: *const i32) -> *mut i32 {
+    // CHECK: let r: *mut i32 = p;
+    // CHECK-NEXT: // c2rust: r: READ | WRITE | UNIQUE{{.*}}
+    let r: *mut i32 = p;
+    *r = *q;
+    r
+}
+
+// CHECK-LABEL: unsafe fn first(pp: *mut *mut i32) -> i32 {
+// CHECK: // c2rust: pp: READ{{.*}}; READ{{.*}}
+unsafe fn first(pp: *mut *mut i32) -> i32 {
+    **pp
+}
+
+// CHECK-LABEL: ===== END {{.*}}annotate_only.rs =====
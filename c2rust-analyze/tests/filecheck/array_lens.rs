@@ -0,0 +1,28 @@
+// A pointer that comes straight from an array keeps the array's length in its type.
+
+// CHECK-LABEL: unsafe fn sum_three
+pub unsafe fn sum_three() -> i32 {
+    let arr: [i32; 4] = [1, 2, 3, 4];
+    // CHECK: let p: &[i32; 4] = &{{.*}}arr{{.*}};
+    let p: *const i32 = arr.as_ptr();
+    *p + *p.offset(1) + *p.offset(2)
+}
+
+// CHECK-LABEL: unsafe fn fill
+pub unsafe fn fill() {
+    let mut buf: [u8; 16] = [0; 16];
+    // CHECK: let p: &mut [u8; 16] = &mut {{.*}}buf{{.*}};
+    let p: *mut u8 = buf.as_mut_ptr();
+    *p.offset(3) = 1;
+}
+
+// A pointer that's reassigned may point anywhere in the array, so it stays a slice.
+
+// CHECK-LABEL: unsafe fn advance
+pub unsafe fn advance() -> i32 {
+    let arr: [i32; 4] = [1, 2, 3, 4];
+    // CHECK: let mut p: &[i32] =
+    let mut p: *const i32 = arr.as_ptr();
+    p = p.offset(1);
+    *p
+}
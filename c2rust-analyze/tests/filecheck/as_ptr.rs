@@ -31,7 +31,7 @@ pub unsafe fn array_as_ptr_load(x: &[i32; 10]) -> i32 {
 // CHECK-LABEL: type assignment for "array_as_ptr_offset_load"
 // CHECK-DAG: ([[@LINE+1]]: x): &{{\[i32; 10]}}
 pub unsafe fn array_as_ptr_offset_load(x: &[i32; 10]) -> i32 {
-    // CHECK-DAG: ([[@LINE+1]]: p): &[i32]
+    // CHECK-DAG: ([[@LINE+1]]: p): &{{\[i32; 10]}}
     let p = x.as_ptr();
     // CHECK-DAG: ([[@LINE+1]]: q): &i32
     let q = p.offset(2);
@@ -0,0 +1,58 @@
+#![feature(core_intrinsics)]
+
+use std::intrinsics;
+
+// Pointers passed to atomic intrinsics are rewritten to references to atomic types, and the
+// intrinsics to the equivalent methods, rather than being left raw like the arguments of other
+// foreign functions.
+
+// CHECK-LABEL: final labeling for "bump"
+pub unsafe fn bump(counter: *mut i32) -> i32 {
+    intrinsics::atomic_xadd_seqcst(counter, 1)
+}
+
+// CHECK-LABEL: final labeling for "publish"
+pub unsafe fn publish(flag: *mut u32, seen: *mut u32) {
+    intrinsics::atomic_store_release(flag, 1);
+    *seen = intrinsics::atomic_load_acquire(flag);
+}
+
+// CHECK-LABEL: final labeling for "try_lock"
+pub unsafe fn try_lock(lock: *mut i32) -> bool {
+    intrinsics::atomic_cxchg_acquire_relaxed(lock, 0, 1).1
+}
+
+// Plain accesses through a pointer that's also used atomically become loads and stores.
+// CHECK-LABEL: final labeling for "reset"
+pub unsafe fn reset(counter: *mut i32) -> i32 {
+    let old = *counter;
+    *counter = 0;
+    intrinsics::atomic_xsub_relaxed(counter, old);
+    old
+}
+
+// CHECK-LABEL: final labeling for "swap_head"
+pub unsafe fn swap_head(head: *mut *mut u8, node: *mut u8) -> *mut u8 {
+    intrinsics::atomic_xchg_acqrel(head, node)
+}
+
+// CHECK-LABEL: ===== BEGIN
+
+// CHECK-LABEL: unsafe fn bump(counter: &{{.*}}core::sync::atomic::AtomicI32{{.*}}) -> i32
+// CHECK: (counter).fetch_add((1), std::sync::atomic::Ordering::SeqCst)
+
+// CHECK-LABEL: unsafe fn publish(flag: &{{.*}}core::sync::atomic::AtomicU32{{.*}}, seen: &{{.*}}mut (u32))
+// CHECK: (flag).store((1), std::sync::atomic::Ordering::Release);
+// CHECK: (flag).load(std::sync::atomic::Ordering::Acquire)
+
+// CHECK-LABEL: unsafe fn try_lock(lock: &{{.*}}core::sync::atomic::AtomicI32{{.*}}) -> bool
+// CHECK: (lock).compare_exchange((0), (1), std::sync::atomic::Ordering::Acquire, std::sync::atomic::Ordering::Relaxed)
+// CHECK-SAME: .map_or_else(|x| (x, false), |x| (x, true))
+
+// CHECK-LABEL: unsafe fn reset(counter: &{{.*}}core::sync::atomic::AtomicI32{{.*}}) -> i32
+// CHECK: let old = (counter).load(std::sync::atomic::Ordering::SeqCst);
+// CHECK: (counter).store((0), std::sync::atomic::Ordering::SeqCst);
+// CHECK: (counter).fetch_sub((old), std::sync::atomic::Ordering::Relaxed);
+
+// CHECK-LABEL: unsafe fn swap_head(head: &{{.*}}core::sync::atomic::AtomicPtr<u8>{{.*}}, node: *mut u8) -> *mut u8
+// CHECK: (head).swap((node), std::sync::atomic::Ordering::AcqRel)
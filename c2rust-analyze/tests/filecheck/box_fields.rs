@@ -0,0 +1,43 @@
+#![allow(dead_code)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+struct Item {
+    val: i32,
+}
+
+// CHECK-LABEL: struct Holder
+struct Holder {
+    // CHECK: item: std::option::Option<std::boxed::Box<(Item)>>
+    item: *mut Item,
+}
+
+// CHECK-LABEL: unsafe extern "C" fn fill(
+pub unsafe extern "C" fn fill(h: *mut Holder) {
+    // CHECK: Some(Box::new(
+    (*h).item = malloc(::std::mem::size_of::<Item>() as libc::c_ulong) as *mut Item;
+    (*(*h).item).val = 1;
+}
+
+// CHECK-LABEL: unsafe extern "C" fn empty(
+pub unsafe extern "C" fn empty(h: *mut Holder) {
+    // CHECK: std::mem::drop(
+    // CHECK-SAME: .take()
+    free((*h).item as *mut libc::c_void);
+}
+
+// CHECK-LABEL: unsafe extern "C" fn steal(
+pub unsafe extern "C" fn steal(h: *mut Holder) -> i32 {
+    // CHECK: let it =
+    // CHECK-SAME: .take()
+    let it = (*h).item;
+    let v = (*it).val;
+    free(it as *mut libc::c_void);
+    v
+}
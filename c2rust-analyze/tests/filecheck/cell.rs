@@ -14,6 +14,20 @@ unsafe fn cell() {
     *y = *z;
 }
 
+// CHECK-LABEL: fn cell_compound_assign(
+unsafe fn cell_compound_assign() {
+    // CHECK-DAG: let mut x = std::cell::Cell::new((1));
+    let mut x = 1;
+    // CHECK-DAG: let mut y = &(x);
+    let mut y = std::ptr::addr_of_mut!(x);
+    // CHECK-DAG: let mut z = &(x);
+    let mut z = std::ptr::addr_of_mut!(x);
+    // CHECK-DAG: (y).set((y).get() + (2));
+    *y += 2;
+    // CHECK-DAG: (z).set((z).get() * ((y).get()));
+    *z *= *y;
+}
+
 struct R {
     i: i32,
 }
@@ -0,0 +1,20 @@
+//! --checked-offsets
+#![allow(dead_code)]
+
+#[repr(C)]
+pub struct Pair {
+    pub a: i32,
+    pub b: i32,
+}
+
+// A pointer to a single field can only be offset by zero, which the analysis can't prove here.
+// In checked mode, the field reference becomes a one-element slice, and an out-of-bounds offset
+// panics when the slice is indexed.
+
+// CHECK-LABEL: unsafe fn field_elem(
+unsafe fn field_elem(p: *mut Pair, i: isize) -> i32 {
+    // CHECK: let q = std::slice::from_{{(ref|mut)}}(
+    let q = &mut (*p).a as *mut i32;
+    // CHECK: [{{.*}} as usize ..]
+    *q.offset(i)
+}
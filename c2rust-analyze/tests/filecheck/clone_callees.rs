@@ -0,0 +1,78 @@
+//! --clone-callees
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+
+// `bad` isn't rewritten and passes a borrow of its local to `swap_in`.  Rather than leaving
+// `swap_in` unchanged for every caller, `bad` gets its own unchanged copy, `swap_in_raw`.
+// CHECK-LABEL: fn swap_in<'h0>(p: &'h0 mut (i32), v: i32) -> i32
+unsafe fn swap_in(p: *mut i32, v: i32) -> i32 {
+    let old = *p;
+    *p = v;
+    old
+}
+// CHECK-LABEL: unsafe fn swap_in_raw(p: *mut i32, v: i32) -> i32 {
+// CHECK-NEXT: let old = *p;
+// CHECK-NEXT: *p = v;
+
+#[c2rust_analyze_test::fail_before_rewriting]
+unsafe fn bad() -> i32 {
+    let mut x = 1;
+    // CHECK-NOT: swap_in_shim
+    // CHECK: swap_in_raw(&mut x, 2)
+    swap_in(&mut x, 2)
+}
+
+// CHECK-LABEL: fn good()
+unsafe fn good() -> i32 {
+    let mut y = 3;
+    // CHECK: swap_in(&mut y, 4)
+    swap_in(&mut y, 4)
+}
+
+// The copy keeps `#[inline]`, but not `#[no_mangle]`, which would clash with the original.
+// CHECK-LABEL: fn bump_in<'h0>(p: &'h0 mut (i32)) -> i32
+#[inline]
+#[no_mangle]
+unsafe fn bump_in(p: *mut i32) -> i32 {
+    *p += 1;
+    *p
+}
+// CHECK-NOT: no_mangle
+// CHECK: #[inline]
+// CHECK-NEXT: unsafe fn bump_in_raw(p: *mut i32) -> i32 {
+
+#[c2rust_analyze_test::fail_before_rewriting]
+unsafe fn bad_bump() -> i32 {
+    let mut x = 1;
+    // CHECK: bump_in_raw(&mut x)
+    bump_in(&mut x)
+}
+
+// CHECK-LABEL: fn good_bump()
+unsafe fn good_bump() -> i32 {
+    let mut y = 3;
+    // CHECK: bump_in(&mut y)
+    bump_in(&mut y)
+}
+
+// `put_in_raw` is already taken, so `put_in` is left unchanged for every caller.
+// CHECK-LABEL: unsafe fn put_in(p: *mut i32, v: i32)
+unsafe fn put_in(p: *mut i32, v: i32) {
+    *p = *p + v;
+}
+
+unsafe fn put_in_raw() {}
+
+#[c2rust_analyze_test::fail_before_rewriting]
+unsafe fn bad_put() {
+    let mut x = 1;
+    // CHECK: put_in(&mut x, 2)
+    put_in(&mut x, 2)
+}
+
+// CHECK-LABEL: fn good_put()
+unsafe fn good_put() {
+    let mut y = 3;
+    // CHECK: put_in(&mut y, 4)
+    put_in(&mut y, 4)
+}
@@ -0,0 +1,72 @@
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+
+use std::ffi::c_void;
+
+// CHECK-LABEL: unsafe fn for_each{{[<(]}}
+// CHECK-SAME: mut cb: impl FnMut(i32))
+#[c2rust_analyze_test::closure_callback]
+pub unsafe fn for_each(cb: unsafe extern "C" fn(*mut c_void, i32), data: *mut c_void) {
+    let mut i = 0;
+    while i < 10 {
+        // CHECK: cb(i);
+        cb(data, i);
+        i += 1;
+    }
+}
+
+pub unsafe extern "C" fn add(data: *mut c_void, x: i32) {
+    *(data as *mut i32) += x;
+}
+
+// CHECK-LABEL: unsafe fn sum()
+pub unsafe fn sum() -> i32 {
+    let mut sum = 0;
+    // CHECK: for_each(|arg0| add(&mut sum as *mut i32 as *mut c_void,arg0));
+    for_each(add, &mut sum as *mut i32 as *mut c_void);
+    sum
+}
+
+// The callback's result is passed back to the registration fn, so it's part of the closure type.
+// CHECK-LABEL: unsafe fn count_if{{[<(]}}
+// CHECK-NOT: data
+// CHECK: mut pred: impl FnMut(i32) -> bool
+#[c2rust_analyze_test::closure_callback]
+pub unsafe fn count_if(
+    data: *const c_void,
+    n: i32,
+    pred: unsafe extern "C" fn(i32, *const c_void) -> bool,
+) -> i32 {
+    let mut count = 0;
+    let mut i = 0;
+    while i < n {
+        // CHECK: if pred(i) {
+        if pred(i, data) {
+            count += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
+pub unsafe extern "C" fn is_multiple(x: i32, data: *const c_void) -> bool {
+    x % *(data as *const i32) == 0
+}
+
+// CHECK-LABEL: unsafe fn count_multiples{{[<(]}}
+pub unsafe fn count_multiples(n: i32, k: i32) -> i32 {
+    // CHECK: count_if(n, |arg0| is_multiple(arg0,&k as *const i32 as *const c_void))
+    count_if(&k as *const i32 as *const c_void, n, is_multiple)
+}
+
+// This registration fn is passed a callback that isn't a function item, so it's left unchanged.
+// CHECK-LABEL: unsafe fn apply{{[<(]}}
+// CHECK-SAME: cb: unsafe extern "C" fn(
+#[c2rust_analyze_test::closure_callback]
+pub unsafe fn apply(cb: unsafe extern "C" fn(*mut c_void), data: *mut c_void) {
+    cb(data);
+}
+
+pub unsafe fn apply_indirect(cb: unsafe extern "C" fn(*mut c_void), data: *mut c_void) {
+    apply(cb, data);
+}
@@ -0,0 +1,24 @@
+// A closure's body is lowered to a MIR body of its own, and the pointers in it are rewritten like
+// those in any other function.
+
+// CHECK-LABEL: pub unsafe fn local_in_closure{{.*}}()
+pub unsafe fn local_in_closure() -> i32 {
+    let f = |a: i32| {
+        let mut z = a;
+        // CHECK: let x: &mut (i32);
+        let x: *mut i32;
+        x = std::ptr::addr_of_mut!(z);
+        *x = *x + 1;
+        z
+    };
+    f(1)
+}
+
+// The closure's environment can't be rewritten, so a captured pointer stays raw, both in the
+// closure and in the function that creates it.
+
+// CHECK-LABEL: pub unsafe fn captured{{.*}}(p: *mut i32)
+pub unsafe fn captured(p: *mut i32) {
+    let f = || *p = 1;
+    f();
+}
@@ -0,0 +1,28 @@
+#![allow(dead_code)]
+
+// CHECK-LABEL: ===== BEGIN
+
+// Null entries make the pointers nullable, and the uses below are rewritten to match.
+// CHECK: const NO_ENTRIES: [Option<&i32>; 2] = [None, None];
+const NO_ENTRIES: [*const i32; 2] = [0 as *const i32, 0 as *const i32];
+
+// CHECK: const EMPTY: Option<&i32> = None;
+const EMPTY: *const i32 = 0 as *const i32;
+
+// CHECK-LABEL: unsafe fn lookup(
+unsafe fn lookup(i: usize) -> i32 {
+    let p = NO_ENTRIES[i];
+    if p.is_null() {
+        return 0;
+    }
+    *p
+}
+
+// CHECK-LABEL: unsafe fn empty_or_zero(
+unsafe fn empty_or_zero() -> i32 {
+    let p = EMPTY;
+    if p.is_null() {
+        return 0;
+    }
+    *p
+}
@@ -0,0 +1,36 @@
+#![allow(dead_code)]
+
+extern crate libc;
+
+use libc::{c_char, size_t};
+
+extern "C" {
+    fn strlen(_: *const c_char) -> size_t;
+}
+
+// CHECK-LABEL: unsafe fn greeting_len(
+unsafe fn greeting_len() -> size_t {
+    // CHECK: std::ffi::CStr::from_bytes_with_nul(b"hi\0").unwrap()
+    strlen(b"hi\0" as *const u8 as *const c_char)
+}
+
+// CHECK-LABEL: unsafe fn first_char(
+unsafe fn first_char() -> c_char {
+    // CHECK: &[104, 105, 0i8]
+    let p = b"hi\0" as *const u8 as *const c_char;
+    *p
+}
+
+// CHECK-LABEL: unsafe fn first_byte(
+unsafe fn first_byte() -> u8 {
+    // CHECK: std::ffi::CStr::from_bytes_with_nul(b"hi\0").unwrap().to_bytes_with_nul()
+    let p = b"hi\0" as *const u8;
+    *p
+}
+
+// The literal has an interior NUL, so it isn't a valid C string and is left unchanged.
+// CHECK-LABEL: unsafe fn two_strings(
+unsafe fn two_strings() -> size_t {
+    // CHECK-NOT: from_bytes_with_nul
+    strlen(b"a\0b\0" as *const u8 as *const c_char)
+}
@@ -0,0 +1,76 @@
+#![allow(dead_code)]
+
+extern crate libc;
+
+use libc::{c_char, c_int, size_t};
+
+extern "C" {
+    fn strlen(_: *const c_char) -> size_t;
+    fn strcmp(_: *const c_char, _: *const c_char) -> c_int;
+    fn strcpy(_: *mut c_char, _: *const c_char) -> *mut c_char;
+}
+
+// CHECK-LABEL: unsafe fn len(
+// CHECK-SAME: s: &{{('[^ ]* )?}}std::ffi::CStr
+unsafe fn len(s: *const c_char) -> size_t {
+    // CHECK: s.to_bytes().len() as u64
+    strlen(s)
+}
+
+// `s` is also dereferenced, so it stays a slice.
+// CHECK-LABEL: unsafe fn len_nonempty(
+// CHECK-SAME: s: &{{('[^ ]* )?}}[(i8)]
+unsafe fn len_nonempty(s: *const c_char) -> size_t {
+    if *s == 0 {
+        return 0;
+    }
+    // CHECK: s.iter().position(|&c| c == 0).unwrap() as u64
+    strlen(s)
+}
+
+// CHECK-LABEL: unsafe fn compare(
+unsafe fn compare(a: *const c_char, b: *const c_char) -> c_int {
+    // CHECK: s1.to_bytes().iter().copied().cmp(s2.to_bytes().iter().copied()) as i32
+    strcmp(a, b)
+}
+
+// CHECK-LABEL: unsafe fn compare_nonempty(
+unsafe fn compare_nonempty(a: *const c_char, b: *const c_char) -> c_int {
+    if *a == 0 {
+        return -1;
+    }
+    // CHECK: s1.iter().take_while(|&&c| c != 0).map(|&c| c as u8).cmp(s2.to_bytes().iter().copied()) as i32
+    strcmp(a, b)
+}
+
+// CHECK-LABEL: unsafe fn copy(
+// CHECK-SAME: dest: &{{('[^ ]* )?}}mut [(i8)]
+// CHECK-SAME: src: &{{('[^ ]* )?}}std::ffi::CStr
+unsafe fn copy(dest: *mut c_char, src: *const c_char) {
+    // CHECK: dest[off..off + n].iter_mut().zip(src.to_bytes_with_nul()).for_each(|(d, &c)| *d = c as _)
+    strcpy(dest, src);
+}
+
+// CHECK-LABEL: unsafe fn copy_nonempty(
+// CHECK-SAME: src: &{{('[^ ]* )?}}[(i8)]
+unsafe fn copy_nonempty(dest: *mut c_char, src: *const c_char) {
+    if *src == 0 {
+        return;
+    }
+    // CHECK: dest[off..off + n].copy_from_slice(&src[..n])
+    strcpy(dest, src);
+}
+
+// On targets where `c_char` is `u8`, such as aarch64 Linux, C strings are `*const u8`.
+mod unsigned_char {
+    extern "C" {
+        fn strlen(_: *const u8) -> libc::size_t;
+    }
+
+    // CHECK-LABEL: unsafe fn len_u8(
+    // CHECK-SAME: s: &{{('[^ ]* )?}}std::ffi::CStr
+    unsafe fn len_u8(s: *const u8) -> libc::size_t {
+        // CHECK: s.to_bytes().len() as u64
+        strlen(s)
+    }
+}
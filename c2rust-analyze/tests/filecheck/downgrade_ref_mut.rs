@@ -0,0 +1,21 @@
+// `&mut` borrows that are never written through should become shared borrows, and the
+// parameters they're passed to should become `&T`.
+
+// CHECK-LABEL: unsafe fn read(p: &'h0 (i32)) -> i32
+unsafe fn read(p: *mut i32) -> i32 {
+    *p
+}
+
+// CHECK-LABEL: unsafe fn write(p: &'h0 mut (i32))
+unsafe fn write(p: *mut i32) {
+    *p = 1;
+}
+
+// CHECK-LABEL: unsafe fn caller()
+unsafe fn caller() -> i32 {
+    let mut x = 0;
+    // CHECK: write(&mut x);
+    write(&mut x);
+    // CHECK: read(&(x))
+    read(&mut x)
+}
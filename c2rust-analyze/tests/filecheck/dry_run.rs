@@ -0,0 +1,14 @@
+//! --dry-run
+
+// Nothing is rewritten; the planned rewrites are only listed.
+// CHECK-NOT: ===== BEGIN
+
+// CHECK: dry_run.rs:[[@LINE+1]]:{{[0-9]+}} *const i32 => {{.*}}Option<&{{.*}}i32>
+pub unsafe fn get(p: *const i32) -> i32 {
+    // CHECK: dry_run.rs:[[@LINE+1]]:{{[0-9]+}} p.is_null() => {{.*}}is_none(){{.*}} [{{.*}}IsNullToIsNone{{.*}}]
+    if p.is_null() {
+        0
+    } else {
+        *p
+    }
+}
@@ -0,0 +1,49 @@
+//! --dynamic-checks
+#![allow(dead_code)]
+#![allow(non_camel_case_types)]
+
+extern crate libc;
+
+extern "C" {
+    // Mentioning `list` in an extern block keeps its fields as raw pointers.
+    fn list_free(l: *mut list);
+}
+
+#[repr(C)]
+pub struct list {
+    pub items: *mut i32,
+    pub n_items: libc::size_t,
+}
+
+// `(*l).items` stays raw, so the offset can't be rewritten to a slice operation without knowing
+// its length.  The loop bounds the index by `(*l).n_items`, which becomes the slice length.  The
+// loop condition already proves that the offset is in bounds, so no check is added.
+
+// CHECK-LABEL: unsafe extern "C" fn list_sum(
+pub unsafe extern "C" fn list_sum(l: *const list) -> i32 {
+    let mut total = 0;
+    let mut i: libc::size_t = 0;
+    while i < (*l).n_items {
+        // CHECK: std::slice::from_raw_parts{{(_mut)?}}({{.*}}(*l).items{{.*}},(*l).n_items as usize)
+        // CHECK-NOT: assert!
+        total += *(*l).items.offset(i as isize);
+        i = i.wrapping_add(1);
+    }
+    total
+}
+
+// Here the index changes between the loop condition and the offset, so the offset is checked
+// against the length at run time.
+
+// CHECK-LABEL: unsafe extern "C" fn list_sum_next(
+pub unsafe extern "C" fn list_sum_next(l: *const list) -> i32 {
+    let mut total = 0;
+    let mut i: libc::size_t = 0;
+    while i < (*l).n_items {
+        i = i.wrapping_add(1);
+        // CHECK: std::slice::from_raw_parts{{(_mut)?}}({{.*}}(*l).items{{.*}},(*l).n_items as usize)
+        // CHECK: assert!(idx <= arr.len(),
+        total += *(*l).items.offset(i as isize);
+    }
+    total
+}
@@ -0,0 +1,59 @@
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+
+extern crate libc;
+
+const EINVAL: libc::c_int = 22;
+
+// CHECK-LABEL: unsafe fn check_arg{{[<(]}}
+// CHECK-SAME: -> Result<(), std::io::Error>
+#[c2rust_analyze_test::errno_result]
+pub unsafe fn check_arg(p: *const i32) -> libc::c_int {
+    if p.is_null() {
+        // CHECK: return Err(std::io::Error::from_raw_os_error({{.*}}EINVAL{{.*}} as i32))
+        return -EINVAL;
+    }
+    if *p < 0 {
+        // CHECK: return Err(std::io::Error::from_raw_os_error({{.*}}1 as libc::c_int{{.*}} as i32))
+        return -(1 as libc::c_int);
+    }
+    // CHECK: return Ok(())
+    return 0 as libc::c_int;
+}
+
+// CHECK-LABEL: unsafe fn check_twice{{[<(]}}
+// CHECK-SAME: -> Result<(), std::io::Error>
+#[c2rust_analyze_test::errno_result]
+pub unsafe fn check_twice(p: *const i32, q: *const i32) -> libc::c_int {
+    // CHECK: let () = {{.*}}check_arg({{.*}})?;
+    let r = check_arg(p);
+    // CHECK-NEXT: {}
+    if r < 0 {
+        return r;
+    }
+    // CHECK: check_arg(
+    check_arg(q)
+}
+
+// CHECK-LABEL: unsafe fn caller{{[<(]}}
+pub unsafe fn caller(p: *const i32) -> libc::c_int {
+    // CHECK: let _ = {{.*}}check_arg(
+    check_arg(p);
+    // CHECK: check_arg({{.*}}.is_err()
+    if check_arg(p) < 0 {
+        return -1;
+    }
+    // CHECK: check_twice({{.*}}.map_or_else(|e| -e.raw_os_error().unwrap_or(1) as i32, |()| 0)
+    check_twice(p, p)
+}
+
+// Returns a value other than `0` or an error code, so it isn't rewritten.
+// CHECK-LABEL: unsafe fn count{{[<(]}}
+// CHECK-SAME: -> libc::c_int
+#[c2rust_analyze_test::errno_result]
+pub unsafe fn count(p: *const i32) -> libc::c_int {
+    if p.is_null() {
+        return -1;
+    }
+    *p
+}
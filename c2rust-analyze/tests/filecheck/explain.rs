@@ -0,0 +1,30 @@
+//! --explain nullable::q,alias_bad::q
+
+use std::ptr;
+
+// A null pointer is stored into `q`, so it can't be `NON_NULL`.
+pub unsafe fn nullable(cond: bool, p: *mut i32) -> *mut i32 {
+    let mut q = p;
+    if cond {
+        q = ptr::null_mut();
+    }
+    q
+}
+
+// Writing through `p` while the loan behind `q` is still live takes away `UNIQUE`.
+pub unsafe fn alias_bad() {
+    let mut x = 0;
+    let p = ptr::addr_of_mut!(x);
+    let q = ptr::addr_of_mut!(x);
+    *p = 1;
+    let _ = q;
+}
+
+// CHECK-LABEL: explain "nullable::q":
+// CHECK: missing NON_NULL:
+// CHECK: lost NON_NULL in "nullable": `{{.*}}` at [[@LINE-16]]: q = ptr::null_mut(){{.*}}, copied from
+// CHECK: lost NON_NULL in "nullable": `{{.*}}` at [[@LINE-17]]: ptr::null_mut() (NoPerms
+
+// CHECK-LABEL: explain "alias_bad::q":
+// CHECK: missing UNIQUE:
+// CHECK: lost UNIQUE in "alias_bad": borrowck: loan
@@ -0,0 +1,40 @@
+extern "C" {
+    fn fill(buf: *mut i32, n: usize);
+    fn total(buf: *const i32, n: usize) -> i32;
+    fn log_msg(msg: *const u8);
+    fn lookup(key: i32) -> *mut i32;
+}
+
+// The callee may write through `*mut` parameters, so `p` becomes a mutable reference, converted
+// back to a raw pointer only at the call.
+
+// CHECK-LABEL: unsafe fn fill_one{{.*}}(p: &{{.*}}mut i32)
+pub unsafe fn fill_one(p: *mut i32) {
+    // CHECK: fill(core::ptr::addr_of_mut!(*{{.*}}p{{.*}}), 1);
+    fill(p, 1);
+}
+
+// CHECK-LABEL: unsafe fn total_plus_second{{.*}}(p: &{{[^m]*}}[i32]{{.*}}, n: usize)
+pub unsafe fn total_plus_second(p: *const i32, n: usize) -> i32 {
+    let x = *p.offset(1);
+    // CHECK: total({{.*}}p{{.*}}.as_ptr(), n)
+    total(p, n) + x
+}
+
+// A nullable pointer is passed as null for `None`.
+
+// CHECK-LABEL: unsafe fn log_if_set{{.*}}(msg: Option<&{{[^m]*}}u8>, cond: bool)
+pub unsafe fn log_if_set(msg: *const u8, cond: bool) {
+    if cond && msg.is_null() {
+        return;
+    }
+    // CHECK: log_msg({{.*}}.unwrap_or(std::ptr::null()));
+    log_msg(msg);
+}
+
+// CHECK-LABEL: unsafe fn bump
+pub unsafe fn bump(key: i32) {
+    // CHECK: let p = &mut *{{.*}}lookup(key)
+    let p = lookup(key);
+    *p += 1;
+}
@@ -0,0 +1,72 @@
+#![allow(dead_code)]
+
+extern crate libc;
+
+use libc::{c_char, c_int, c_void, size_t, FILE};
+
+extern "C" {
+    fn fopen(path: *const c_char, mode: *const c_char) -> *mut FILE;
+    fn fread(ptr: *mut c_void, size: size_t, count: size_t, stream: *mut FILE) -> size_t;
+    fn fwrite(ptr: *const c_void, size: size_t, count: size_t, stream: *mut FILE) -> size_t;
+    fn fclose(stream: *mut FILE) -> c_int;
+    fn fflush(stream: *mut FILE) -> c_int;
+}
+
+// CHECK-LABEL: unsafe fn load(
+pub unsafe fn load(path: *const c_char, buf: *mut u8, len: size_t) -> c_int {
+    // CHECK: let mut f: Option<std::io::BufReader<std::fs::File>> = std::fs::File::open(<std::ffi::OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(std::ffi::CStr::from_ptr((path)).to_bytes())).ok().map(std::io::BufReader::new);
+    let mut f: *mut FILE = fopen(path, b"rb\0" as *const u8 as *const c_char);
+    // CHECK: if (f).is_none() {
+    if f.is_null() {
+        return -1;
+    }
+    // CHECK: let (ptr, size, count, f, ) = ((buf as *mut c_void) as *mut u8, (1) as usize, (len) as usize, (f).as_mut().unwrap(), );
+    // CHECK: std::io::Read::read(f, &mut buf[n..])
+    let n = fread(buf as *mut c_void, 1, len, f);
+    // CHECK: (f).take().map_or(-1, |_| 0);
+    fclose(f);
+    n as c_int
+}
+
+// CHECK-LABEL: unsafe fn save(
+pub unsafe fn save(buf: *const u8, len: size_t) -> c_int {
+    // CHECK: let mut f: Option<std::io::BufWriter<std::fs::File>> = std::fs::File::create("out.bin").ok().map(std::io::BufWriter::new);
+    let mut f: *mut FILE = fopen(
+        b"out.bin\0" as *const u8 as *const c_char,
+        b"wb\0" as *const u8 as *const c_char,
+    );
+    if f.is_null() {
+        return -1;
+    }
+    // CHECK: match std::io::Write::write_all(f, buf) { Ok(()) => count as {{.*}}, Err(_) => 0 }
+    if fwrite(buf as *const c_void, 1, len, f) != len {
+        fclose(f);
+        return -1;
+    }
+    // CHECK: return (f).take().map_or(-1, |mut f| std::io::Write::flush(&mut f).map_or(-1, |()| 0));
+    return fclose(f);
+}
+
+// CHECK-LABEL: unsafe fn append_log(
+pub unsafe fn append_log(msg: *const u8, len: size_t) {
+    // CHECK: std::fs::OpenOptions::new().append(true).create(true).open("log.txt").ok().map(std::io::BufWriter::new)
+    let mut f: *mut FILE = fopen(
+        b"log.txt\0" as *const u8 as *const c_char,
+        b"a\0" as *const u8 as *const c_char,
+    );
+    fwrite(msg as *const c_void, 1, len, f);
+    fclose(f);
+}
+
+// The stream is passed to `fflush`, which has no equivalent here, so it stays a `FILE*`.
+// CHECK-LABEL: unsafe fn flushed(
+pub unsafe fn flushed(buf: *const u8, len: size_t) {
+    // CHECK: let mut f: *mut FILE = fopen(
+    let mut f: *mut FILE = fopen(
+        b"out.bin\0" as *const u8 as *const c_char,
+        b"w\0" as *const u8 as *const c_char,
+    );
+    fwrite(buf as *const c_void, 1, len, f);
+    fflush(f);
+    fclose(f);
+}
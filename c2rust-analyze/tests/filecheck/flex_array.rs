@@ -0,0 +1,45 @@
+#![allow(dead_code)]
+#![allow(non_camel_case_types)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+// CHECK: #[derive(Clone)]
+// CHECK-LABEL: pub struct packet {
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct packet {
+    pub len: libc::size_t,
+    // CHECK: pub data: Vec<u8>,
+    pub data: [u8; 0],
+}
+
+// CHECK-LABEL: unsafe extern "C" fn packet_new(
+pub unsafe extern "C" fn packet_new(n: libc::size_t) -> *mut packet {
+    // CHECK: let (tail_len, ptr, ) = ((n) as usize, Box::into_raw(Box::new(packet { len: 0, data: Vec::new(), })), );
+    // CHECK: (*ptr).data.resize_with(tail_len, || 0);
+    let p = malloc(
+        (::std::mem::size_of::<packet>() as libc::c_ulong).wrapping_add(
+            (n as libc::c_ulong).wrapping_mul(::std::mem::size_of::<u8>() as libc::c_ulong),
+        ),
+    ) as *mut packet;
+    (*p).len = n;
+    p
+}
+
+// CHECK-LABEL: unsafe extern "C" fn packet_set(
+pub unsafe extern "C" fn packet_set(p: *mut packet, i: libc::size_t, x: u8) {
+    // CHECK: ((*p).data)[(i) as usize] = x;
+    *((*p).data).as_mut_ptr().offset(i as isize) = x;
+}
+
+// CHECK-LABEL: unsafe extern "C" fn packet_free(
+pub unsafe extern "C" fn packet_free(p: *mut packet) {
+    // CHECK: std::mem::drop(Box::from_raw((p)))
+    free(p as *mut libc::c_void);
+}
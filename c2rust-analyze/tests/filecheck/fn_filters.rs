@@ -0,0 +1,24 @@
+//! --include-fn ^good
+//! --exclude-fn _excluded$
+
+// CHECK-LABEL: fn good1{{.*}}(x: &{{.*}}mut (i32))
+unsafe fn good1(x: *mut i32) {
+    *x = 1;
+}
+
+// `bad` doesn't match `--include-fn`, so it's not rewritten.
+// CHECK-LABEL: fn bad(x: *mut i32)
+unsafe fn bad(x: *mut i32) {
+    *x = 1;
+}
+
+// `good_excluded` matches `--include-fn`, but `--exclude-fn` takes precedence.
+// CHECK-LABEL: fn good_excluded(x: *mut i32)
+unsafe fn good_excluded(x: *mut i32) {
+    *x = 1;
+}
+
+// CHECK-LABEL: fn good2{{.*}}(x: &{{.*}}mut (i32))
+unsafe fn good2(x: *mut i32) {
+    *x = 2;
+}
@@ -0,0 +1,38 @@
+use std::ffi::c_void;
+use std::mem::transmute;
+use std::ptr;
+
+// CHECK-LABEL: pub struct Handler {
+pub struct Handler {
+    // CHECK: cb: Option<unsafe extern "C" fn(i32) -> i32>,
+    cb: *mut c_void,
+    count: i32,
+}
+
+pub unsafe extern "C" fn double(x: i32) -> i32 {
+    x * 2
+}
+
+// CHECK-LABEL: unsafe fn init(
+pub unsafe fn init(h: *mut Handler) {
+    // CHECK: (*h).cb = None;
+    (*h).cb = ptr::null_mut();
+    (*h).count = 0;
+}
+
+// CHECK-LABEL: unsafe fn set(
+pub unsafe fn set(h: *mut Handler) {
+    // CHECK: (*h).cb = Some(double);
+    (*h).cb = double as *mut c_void;
+}
+
+// CHECK-LABEL: unsafe fn call(
+pub unsafe fn call(h: *mut Handler, x: i32) -> i32 {
+    // CHECK: if (*h).cb.is_none() {
+    if (*h).cb.is_null() {
+        return x;
+    }
+    (*h).count += 1;
+    // CHECK: (*h).cb{{.*}}.expect("non-null function pointer"){{.*}}(x)
+    transmute::<*mut c_void, unsafe extern "C" fn(i32) -> i32>((*h).cb)(x)
+}
@@ -0,0 +1,18 @@
+use std::ffi::c_void;
+
+unsafe fn store_int(p: *mut c_void) {
+    *(p as *mut i32) = 1;
+}
+
+// `q` is only passed through the function pointer `f`.  Its pointee type comes from `store_int`,
+// which is the only address-taken function with a matching signature.
+// CHECK-LABEL: pointee types for call_indirect
+// CHECK: (q): addr_of
+// CHECK-NEXT: pointer {{.*}}#i32[]{{$}}
+pub unsafe fn call_indirect(f: unsafe fn(*mut c_void), q: *mut c_void) {
+    f(q);
+}
+
+pub unsafe fn setup(q: *mut c_void) {
+    call_indirect(store_int, q);
+}
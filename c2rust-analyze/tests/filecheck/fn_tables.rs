@@ -0,0 +1,56 @@
+#![allow(dead_code)]
+
+// CHECK-LABEL: fn table fields:
+// CHECK-NEXT: Ops::get: [get_int, get_double]
+// CHECK-NEXT: Ops::set: [set_int]
+
+// CHECK-LABEL: ===== BEGIN
+
+// The field types match the rewritten signatures of the functions stored in them.
+// CHECK-LABEL: pub struct Ops
+pub struct Ops {
+    // CHECK: pub get: Option<unsafe extern "C" fn(&{{[^m]*}}i32) -> i32>,
+    pub get: Option<unsafe extern "C" fn(*mut i32) -> i32>,
+    // CHECK: pub set: Option<unsafe extern "C" fn(&{{.*}}mut i32, i32)>,
+    pub set: Option<unsafe extern "C" fn(*mut i32, i32)>,
+}
+
+// CHECK-LABEL: unsafe extern "C" fn get_int
+// CHECK-SAME: (p: &{{[^m]*}}i32) -> i32
+unsafe extern "C" fn get_int(p: *mut i32) -> i32 {
+    *p
+}
+
+// CHECK-LABEL: unsafe extern "C" fn get_double
+// CHECK-SAME: (p: &{{[^m]*}}i32) -> i32
+unsafe extern "C" fn get_double(p: *mut i32) -> i32 {
+    *p * 2
+}
+
+// CHECK-LABEL: unsafe extern "C" fn set_int
+// CHECK-SAME: (p: &{{.*}}mut i32, x: i32)
+unsafe extern "C" fn set_int(p: *mut i32, x: i32) {
+    *p = x;
+}
+
+// Casts to the old function pointer type are removed.
+// CHECK-LABEL: static INT_OPS
+// CHECK: get: Some(get_int),
+static INT_OPS: Ops = Ops {
+    get: Some(get_int as unsafe extern "C" fn(*mut i32) -> i32),
+    set: Some(set_int),
+};
+
+static DOUBLE_OPS: Ops = Ops {
+    get: Some(get_double),
+    set: Some(set_int),
+};
+
+// The indirect calls are resolved to the functions stored in `get` and `set`, so `p` must be
+// writable.
+// CHECK-LABEL: unsafe fn run
+// CHECK-SAME: p: &{{.*}}mut i32
+unsafe fn run(ops: *const Ops, p: *mut i32) -> i32 {
+    ((*ops).set.unwrap())(p, 1);
+    ((*ops).get.unwrap())(p)
+}
@@ -0,0 +1,22 @@
+//! --fn-time-budget 0
+
+// With a budget of zero, every function runs out of time after its first round of permission
+// propagation.  Each one should be reported and left unrewritten, and the analysis should still
+// run to completion.
+
+// CHECK-NOT: final labeling for "inc"
+unsafe fn inc(p: *mut i32) {
+    *p += 1;
+}
+
+// CHECK-NOT: final labeling for "call_inc"
+unsafe fn call_inc(p: *mut i32) {
+    inc(p);
+    inc(p);
+}
+
+// CHECK: analysis of DefId({{.*}}::inc) failed:
+// CHECK-SAME: exceeding the time budget of 0.000s
+
+// CHECK: analysis of DefId({{.*}}::call_inc) failed:
+// CHECK-SAME: exceeding the time budget of 0.000s
@@ -0,0 +1,45 @@
+#![feature(register_tool)]
+#![register_tool(c2rust)]
+
+extern crate libc;
+
+extern "C" {
+    fn mmap(
+        addr: *mut libc::c_void,
+        len: libc::size_t,
+        prot: libc::c_int,
+        flags: libc::c_int,
+        fd: libc::c_int,
+        offset: libc::off_t,
+    ) -> *mut libc::c_void;
+    fn munmap(addr: *mut libc::c_void, len: libc::size_t) -> libc::c_int;
+    fn free(_: *mut libc::c_void);
+
+    // The returned buffer is owned by the C library.
+    #[c2rust::foreign_memory]
+    fn get_shared_buffer(len: libc::size_t) -> *mut i32;
+}
+
+// CHECK-LABEL: unsafe extern "C" fn use_mapped(
+pub unsafe extern "C" fn use_mapped(len: libc::size_t) -> i32 {
+    // CHECK-NOT: Box
+    let p = mmap(0 as *mut libc::c_void, len, 3, 0x22, -1, 0) as *mut i32;
+    *p = 1;
+    let x = *p;
+    munmap(p as *mut libc::c_void, len);
+    x
+}
+
+// CHECK-LABEL: unsafe extern "C" fn use_shared(
+pub unsafe extern "C" fn use_shared() -> i32 {
+    // CHECK-NOT: Box
+    let p = get_shared_buffer(4);
+    *p.offset(1) = 2;
+    let x = *p.offset(1);
+    // `free` would make `p` a `Box`, but foreign memory is never boxed.
+    free(p as *mut libc::c_void);
+    x
+}
+
+// CHECK-LABEL: fn end(
+pub fn end() {}
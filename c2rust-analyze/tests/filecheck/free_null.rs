@@ -0,0 +1,62 @@
+#![allow(dead_code)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+struct Item {
+    val: i32,
+}
+
+struct Holder {
+    item: *mut Item,
+}
+
+// CHECK-LABEL: unsafe extern "C" fn fill(
+pub unsafe extern "C" fn fill(h: *mut Holder) {
+    (*h).item = malloc(::std::mem::size_of::<Item>() as libc::c_ulong) as *mut Item;
+    (*(*h).item).val = 1;
+}
+
+// `free(p); p = NULL;` on a field becomes `drop(p.take())`, which leaves `None` behind on its own.
+// CHECK-LABEL: unsafe extern "C" fn reset(
+pub unsafe extern "C" fn reset(h: *mut Holder) {
+    if (*h).item.is_null() {
+        return;
+    }
+    // CHECK: {{^ *}}std::mem::drop((*h).item.take());
+    // CHECK-NOT: {{^ *}}(*h).item = None
+    free((*h).item as *mut libc::c_void);
+    (*h).item = std::ptr::null_mut();
+}
+
+// The same for a local.  Freeing it again afterward is fine, since it's `None` by then.
+// CHECK-LABEL: unsafe extern "C" fn local_cycle(
+pub unsafe extern "C" fn local_cycle() -> i32 {
+    let mut p = malloc(::std::mem::size_of::<Item>() as libc::c_ulong) as *mut Item;
+    if p.is_null() {
+        return -1;
+    }
+    (*p).val = 1;
+    let v = (*p).val;
+    // CHECK: {{^ *}}std::mem::drop(p.take());
+    // CHECK-NOT: {{^ *}}p = None
+    free(p as *mut libc::c_void);
+    p = 0 as *mut Item;
+    // CHECK: {{^ *}}std::mem::drop(p);
+    free(p as *mut libc::c_void);
+    v
+}
+
+// `free(NULL)` does nothing, so it's removed.
+// CHECK-LABEL: unsafe extern "C" fn free_null(
+pub unsafe extern "C" fn free_null() -> i32 {
+    // CHECK-NOT: {{^ *}}free(
+    free(std::ptr::null_mut());
+    // CHECK: {{^ *}}return 0;
+    return 0;
+}
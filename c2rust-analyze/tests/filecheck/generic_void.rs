@@ -0,0 +1,29 @@
+use std::ffi::c_void;
+
+// `data` is read as both `i32` and `f64`, so it gets a type parameter instead of a single pointee.
+// CHECK-LABEL: pub struct Node<{{.*}}T = core::ffi::c_void> {
+pub struct Node {
+    // CHECK: data: *mut T,
+    data: *mut c_void,
+    // CHECK: next: {{.*}}Node<{{.*}}T>
+    next: *mut Node,
+}
+
+pub unsafe fn get_int(n: *mut Node) -> i32 {
+    *((*n).data as *mut i32)
+}
+
+pub unsafe fn get_float(n: *mut Node) -> f64 {
+    *((*n).data as *mut f64)
+}
+
+// Values stored into `data` are cast to the type parameter, and loaded values are cast back to
+// `c_void`.
+// CHECK-LABEL: unsafe fn swap_data<{{.*}}T>(
+pub unsafe fn swap_data(n: *mut Node, p: *mut c_void) -> *mut c_void {
+    // CHECK: let old = {{.*}}(*n).data{{.*}} as *mut core::ffi::c_void;
+    let old = (*n).data;
+    // CHECK: (*n).data = {{.*}}p{{.*}} as *mut T;
+    (*n).data = p;
+    old
+}
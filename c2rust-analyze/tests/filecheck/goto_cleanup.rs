@@ -0,0 +1,55 @@
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+unsafe fn fill(p: *mut i32, n: i32) -> i32 {
+    if n < 0 {
+        return -1;
+    }
+    *p = n;
+    0
+}
+
+// CHECK-LABEL: unsafe fn alloc_and_fill{{[<(]}}
+pub unsafe fn alloc_and_fill(n: i32) -> i32 {
+    let x = malloc(4) as *mut i32;
+    // CHECK: let result = (|| {
+    'c_10: loop {
+        if x.is_null() {
+            // CHECK: return Err(())
+            break 'c_10;
+        }
+        if fill(x, n) < 0 {
+            // CHECK: return Err(())
+            break 'c_10;
+        }
+        // CHECK: return Ok(
+        return *x;
+    }
+    // CHECK: if let Ok(ret) = result {
+    // CHECK-NEXT: return ret;
+    // CHECK: free(
+    free(x as *mut libc::c_void);
+    return -1;
+}
+
+// The loop repeats, so it isn't a `goto` emulation.
+// CHECK-LABEL: unsafe fn retry{{[<(]}}
+pub unsafe fn retry(n: i32) -> i32 {
+    let mut i = 0;
+    // CHECK-NOT: let result
+    'c_20: loop {
+        if i >= n {
+            break 'c_20;
+        }
+        i += 1;
+        if i == 3 {
+            return i;
+        }
+    }
+    // CHECK: return -1
+    return -1;
+}
@@ -0,0 +1,28 @@
+use std::arch::asm;
+
+// CHECK-LABEL: ===== BEGIN
+
+// The pointer passed to the `asm!` stays raw, but the rest of the function is still rewritten.
+// CHECK-LABEL: unsafe fn touch
+// CHECK-SAME: (p: *mut i32, q: &{{[^m]*}}i32) -> i32
+unsafe fn touch(p: *mut i32, q: *mut i32) -> i32 {
+    // CHECK: asm!("/* {0} */", in(reg) p);
+    asm!("/* {0} */", in(reg) p);
+    *q
+}
+
+// The assembly may write through `p`, so the caller's pointer must stay mutable.
+// CHECK-LABEL: unsafe fn caller
+// CHECK-SAME: (p: {{.*}}mut i32) -> i32
+unsafe fn caller(p: *mut i32) -> i32 {
+    touch(p, p)
+}
+
+// Pointers written by an `asm!` are also left alone.
+// CHECK-LABEL: unsafe fn load
+// CHECK: let mut p: *const i32 = std::ptr::null();
+unsafe fn load(x: &i32) -> i32 {
+    let mut p: *const i32 = std::ptr::null();
+    asm!("mov {0}, {1}", out(reg) p, in(reg) x as *const i32);
+    *p
+}
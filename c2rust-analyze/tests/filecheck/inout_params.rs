@@ -0,0 +1,42 @@
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+
+// A parameter that is both read and written through becomes `&mut`, and callers pass `&mut`
+// borrows of their locals.
+
+// CHECK-LABEL: fn bump<'h0>(p: &'h0 mut (i32))
+unsafe fn bump(p: *mut i32) {
+    // CHECK: *p = *p + 1;
+    *p = *p + 1;
+}
+
+// CHECK-LABEL: fn caller()
+unsafe fn caller() -> i32 {
+    let mut x = 0;
+    // CHECK: bump(&mut x);
+    bump(&mut x);
+    x
+}
+
+// `bad` isn't rewritten and passes a borrow of its local to `swap_in`, so `swap_in` is left
+// unchanged as well, rather than being rewritten for some callers but not others.
+// CHECK-LABEL: fn swap_in(p: *mut i32, v: i32) -> i32
+unsafe fn swap_in(p: *mut i32, v: i32) -> i32 {
+    let old = *p;
+    *p = v;
+    old
+}
+
+#[c2rust_analyze_test::fail_before_rewriting]
+unsafe fn bad() -> i32 {
+    let mut x = 1;
+    // CHECK-NOT: swap_in_shim
+    swap_in(&mut x, 2)
+}
+
+// CHECK-LABEL: fn good()
+unsafe fn good() -> i32 {
+    let mut y = 3;
+    // CHECK: swap_in(&mut y, 4)
+    swap_in(&mut y, 4)
+}
@@ -50,7 +50,7 @@ unsafe fn main_0() -> libc::c_int {
     // CHECK: &mut (arr1) as &mut [i32]
     insertion_sort(3 as libc::c_int, arr1.as_mut_ptr());
     let mut expect1: [libc::c_int; 3] = [1, 2, 3];
-    // CHECK: &*((&mut arr1)) as &[i32]
+    // CHECK: (&(arr1)) as &[i32]
     // CHECK-SAME: &(expect1) as &[i32]
     check_eq(3 as libc::c_int, (&mut arr1).as_mut_ptr(), expect1.as_mut_ptr());
 
@@ -5,6 +5,11 @@ extern "C" {
         path: *const c_char,
         amode: c_int,
     ) -> c_int;
+
+    fn strnlen(
+        cs: *const c_char,
+        maxlen: usize,
+    ) -> usize;
 }
 
 // CHECK-LABEL: final labeling for "known_fn"
@@ -16,4 +21,10 @@ pub fn known_fn() {
     unsafe {
         access(path, 0);
     }
+    // CHECK-DAG: ([[@LINE+1]]: s): &[i8]
+    let s = b"abc\0" as *const u8 as *const c_char;
+    unsafe {
+        strnlen(s, 8);
+    }
 }
+
@@ -0,0 +1,19 @@
+//! --known-fns-db known_fn_db.toml
+#![allow(dead_code)]
+
+extern crate libc;
+
+// Not a `libc` function.  Its permissions and the pointee type of `buf` come from
+// `known_fn_db.toml`.
+extern "C" {
+    fn zchecksum(buf: *const libc::c_void, len: libc::c_ulong) -> libc::c_uint;
+}
+
+// CHECK-LABEL: pointee types for checksum_bytes
+// CHECK: (p): addr_of
+// CHECK-NEXT: pointer {{.*}}#u8[]{{$}}
+// CHECK-LABEL: final labeling for "checksum_bytes"
+// CHECK: ([[@LINE+1]]: p): addr_of = {{.*}}, type = READ | {{.*}}NON_NULL#
+unsafe fn checksum_bytes(p: *const u8, len: usize) -> u32 {
+    zchecksum(p as *const libc::c_void, len as libc::c_ulong)
+}
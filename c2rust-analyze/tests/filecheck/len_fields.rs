@@ -0,0 +1,48 @@
+#![allow(dead_code)]
+#![allow(non_camel_case_types)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+extern "C" {
+    fn get_items(n: libc::size_t) -> *mut i32;
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct list {
+    pub items: *mut i32,
+    pub n_items: libc::size_t,
+    pub id: libc::size_t,
+}
+
+// CHECK-LABEL: unsafe extern "C" fn list_init(
+pub unsafe extern "C" fn list_init(l: *mut list, n: libc::size_t) {
+    // CHECK: std::slice::from_raw_parts{{(_mut)?}}({{.*}}get_items(n){{.*}},n as usize)
+    (*l).items = get_items(n);
+    (*l).n_items = n;
+    (*l).id = 0;
+}
+
+// CHECK-LABEL: unsafe extern "C" fn list_reset(
+pub unsafe extern "C" fn list_reset(l: *mut list, mut n: libc::size_t) {
+    n = n.wrapping_add(1);
+    (*l).n_items = n;
+    // CHECK: std::slice::from_raw_parts{{(_mut)?}}({{.*}}get_items(n){{.*}},(*l).n_items as usize)
+    (*l).items = get_items(n);
+}
+
+// CHECK-LABEL: unsafe extern "C" fn list_sum(
+pub unsafe extern "C" fn list_sum(l: *const list) -> i32 {
+    let mut total = 0;
+    let mut i: libc::size_t = 0;
+    while i < (*l).n_items {
+        total += *(*l).items.offset(i as isize);
+        i = i.wrapping_add(1);
+    }
+    // `id` is compared against an index, but never bounds an offset of `items`.
+    if i != (*l).id {
+        total += 1;
+    }
+    total
+}
@@ -0,0 +1,120 @@
+//! --linked-lists
+#![allow(dead_code)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+// A singly-linked list owns each node through the `next` link of the node before it.
+
+// CHECK-LABEL: struct SNode
+struct SNode {
+    val: i32,
+    // CHECK: next: std::option::Option<std::boxed::Box<(SNode)>>
+    next: *mut SNode,
+}
+
+pub unsafe fn snode_push(head: *mut SNode, val: i32) -> *mut SNode {
+    let n = malloc(::std::mem::size_of::<SNode>() as libc::c_ulong) as *mut SNode;
+    (*n).val = val;
+    (*n).next = head;
+    n
+}
+
+// A doubly-linked list stores its nodes in an arena, and links them by index.
+
+// CHECK-LABEL: struct DNode
+struct DNode {
+    val: i32,
+    // CHECK: prev: std::option::Option<usize>
+    prev: *mut DNode,
+    // CHECK: next: std::option::Option<usize>
+    next: *mut DNode,
+}
+// CHECK: thread_local! {
+// CHECK: static DNODE_ARENA: std::cell::RefCell<Vec<Option<DNode>>> =
+// CHECK: impl DNode {
+// CHECK: fn arena_alloc(node: DNode) -> usize {
+// CHECK: fn arena_get_mut<R>(i: usize, f: impl FnOnce(&mut DNode) -> R) -> R {
+
+// CHECK-LABEL: struct DList
+struct DList {
+    // CHECK: head: std::option::Option<usize>
+    head: *mut DNode,
+    // CHECK: tail: std::option::Option<usize>
+    tail: *mut DNode,
+}
+
+// CHECK-LABEL: unsafe fn dlist_push(
+pub unsafe fn dlist_push(l: *mut DList, val: i32) {
+    // CHECK: let n = Some(DNode::arena_alloc(DNode { val: 0, prev: None, next: None, }));
+    let n = malloc(::std::mem::size_of::<DNode>() as libc::c_ulong) as *mut DNode;
+    // CHECK: let (arena_idx, arena_val, ) = ((n).unwrap(), (val), );
+    // CHECK-NEXT: DNode::arena_get_mut(arena_idx, |node| node.val = arena_val)
+    (*n).val = val;
+    // CHECK: let (arena_idx, arena_val, ) = ((n).unwrap(), (None), );
+    // CHECK-NEXT: DNode::arena_get_mut(arena_idx, |node| node.next = arena_val)
+    (*n).next = 0 as *mut DNode;
+    // CHECK: DNode::arena_get_mut(arena_idx, |node| node.prev = arena_val)
+    (*n).prev = (*l).tail;
+    // CHECK: if ((*l).tail).is_none() {
+    if (*l).tail.is_null() {
+        (*l).head = n;
+    } else {
+        // CHECK: let (arena_idx, arena_val, ) = (((*l).tail).unwrap(), (n), );
+        (*(*l).tail).next = n;
+    }
+    (*l).tail = n;
+}
+
+// CHECK-LABEL: unsafe fn dlist_sum(
+pub unsafe fn dlist_sum(l: *mut DList) -> i32 {
+    // CHECK: let mut p: std::option::Option<usize> = (*l).head;
+    let mut p: *mut DNode = (*l).head;
+    let mut sum = 0;
+    // CHECK: while !(p).is_none() {
+    while !p.is_null() {
+        // CHECK: sum += DNode::arena_get((p).unwrap(), |node| node.val);
+        sum += (*p).val;
+        // CHECK: p = DNode::arena_get((p).unwrap(), |node| node.next);
+        p = (*p).next;
+    }
+    sum
+}
+
+// CHECK-LABEL: unsafe fn dlist_pop(
+pub unsafe fn dlist_pop(l: *mut DList) -> i32 {
+    let n = (*l).tail;
+    // CHECK: let val = DNode::arena_get((n).unwrap(), |node| node.val);
+    let val = (*n).val;
+    // CHECK: (*l).tail = DNode::arena_get((n).unwrap(), |node| node.prev);
+    (*l).tail = (*n).prev;
+    if (*l).tail.is_null() {
+        (*l).head = 0 as *mut DNode;
+    } else {
+        // CHECK: let (arena_idx, arena_val, ) = (((*l).tail).unwrap(), (None), );
+        (*(*l).tail).next = 0 as *mut DNode;
+    }
+    // CHECK: DNode::arena_free((n).unwrap());
+    free(n as *mut libc::c_void);
+    val
+}
+
+// Taking the address of a field of a node isn't supported, so `Pinned` keeps its raw pointers.
+
+// CHECK-LABEL: struct Pinned
+struct Pinned {
+    // CHECK: prev: *mut Pinned,
+    prev: *mut Pinned,
+    next: *mut Pinned,
+    val: i32,
+}
+
+// CHECK-LABEL: unsafe fn pinned_val(
+pub unsafe fn pinned_val(p: *mut Pinned) -> *mut i32 {
+    &mut (*p).val
+}
@@ -0,0 +1,29 @@
+macro_rules! second {
+    ($p:expr) => {
+        *$p.offset(1)
+    };
+}
+
+macro_rules! store {
+    ($p:expr, $v:expr) => {
+        *$p = $v
+    };
+}
+
+// The macro's arguments are written at the call site, so they're rewritten like any other
+// expression, and `p` becomes a reference.
+
+// CHECK-LABEL: unsafe fn set{{.*}}(p: &{{.*}}mut i32)
+pub unsafe fn set(p: *mut i32) {
+    // CHECK: store!(p, 1);
+    store!(p, 1);
+}
+
+// The offset inside the macro can't be rewritten to a slice operation, so the pointer passed to
+// it stays raw, but the rest of the function is still rewritten.
+
+// CHECK-LABEL: unsafe fn get_second{{.*}}(p: {{.*}}, q: &{{.*}}i32)
+pub unsafe fn get_second(p: *const i32, q: *const i32) -> i32 {
+    // CHECK: // {{[0-9]+}}: second!(p): c2rust::unresolved(reason = "rewrite inside macro expansion")
+    second!(p) + *q
+}
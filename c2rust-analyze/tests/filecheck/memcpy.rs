@@ -0,0 +1,86 @@
+#![allow(dead_code)]
+
+extern crate libc;
+
+extern "C" {
+    fn memcpy(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong)
+        -> *mut libc::c_void;
+    fn memmove(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong)
+        -> *mut libc::c_void;
+}
+
+// CHECK-LABEL: unsafe fn copy_slice(
+// CHECK-SAME: dest: &{{('[^ ]* )?}}mut [(i32)]
+// CHECK-SAME: src: &{{('[^ ]* )?}}[(i32)]
+unsafe fn copy_slice(dest: *mut i32, src: *const i32, n: usize) {
+    // CHECK: let (dest, src, byte_len, ) =
+    // CHECK: ((&mut *dest))[..n].copy_from_slice(&((&*src))[..n])
+    memcpy(
+        dest as *mut libc::c_void,
+        src as *const libc::c_void,
+        (n * ::std::mem::size_of::<i32>()) as libc::c_ulong,
+    );
+    *dest.offset(1) = *src.offset(1);
+}
+
+// CHECK-LABEL: unsafe fn copy_single(
+// CHECK-SAME: dest: &{{('[^ ]* )?}}mut (i32)
+unsafe fn copy_single(dest: *mut i32, src: *const i32) {
+    // CHECK: std::slice::from_mut(&mut *dest)[..n].copy_from_slice(&std::slice::from_ref(&*src)[..n])
+    memcpy(
+        dest as *mut libc::c_void,
+        src as *const libc::c_void,
+        ::std::mem::size_of::<i32>() as libc::c_ulong,
+    );
+}
+
+// CHECK-LABEL: unsafe fn shift_left(
+// CHECK-SAME: p: &{{('[^ ]* )?}}mut [(i32)]
+unsafe fn shift_left(p: *mut i32, n: usize) {
+    // CHECK: let (base, dest_off, src_off, byte_len, ) = (&mut *(p),
+    // CHECK: base.copy_within(src_off..src_off + n, dest_off)
+    memmove(
+        p.offset(0) as *mut libc::c_void,
+        p.offset(1) as *const libc::c_void,
+        ((n - 1) * ::std::mem::size_of::<i32>()) as libc::c_ulong,
+    );
+}
+
+// CHECK-LABEL: unsafe fn shift_by(
+unsafe fn shift_by(p: *mut i32, i: isize, n: usize) {
+    // The offset might be negative, so it can't be passed to `copy_within`.
+    // CHECK-NOT: {{^[^/]*}}copy_within
+    memmove(
+        p.offset(i) as *mut libc::c_void,
+        p.offset(1) as *const libc::c_void,
+        (n * ::std::mem::size_of::<i32>()) as libc::c_ulong,
+    );
+}
+
+// CHECK-LABEL: unsafe fn move_between(
+unsafe fn move_between(dest: *mut i32, src: *const i32, n: usize) {
+    // Distinct slices fall back on `copy_from_slice`.
+    // CHECK: ((&mut *dest))[..n].copy_from_slice(&((&*src))[..n])
+    memmove(
+        dest as *mut libc::c_void,
+        src as *const libc::c_void,
+        (n * ::std::mem::size_of::<i32>()) as libc::c_ulong,
+    );
+    *dest.offset(1) = *src.offset(1);
+}
+
+struct NotCopy {
+    x: i32,
+}
+
+// CHECK-LABEL: unsafe fn copy_not_copy(
+unsafe fn copy_not_copy(dest: *mut NotCopy, src: *const NotCopy, n: usize) {
+    // `copy_from_slice` requires `T: Copy`.
+    // CHECK-NOT: {{^[^/]*}}copy_from_slice
+    memcpy(
+        dest as *mut libc::c_void,
+        src as *const libc::c_void,
+        (n * ::std::mem::size_of::<NotCopy>()) as libc::c_ulong,
+    );
+    (*dest.offset(1)).x = (*src.offset(1)).x;
+}
@@ -0,0 +1,34 @@
+#![allow(dead_code)]
+
+extern crate libc;
+
+extern "C" {
+    fn memcpy(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+    fn memset(_: *mut libc::c_void, _: libc::c_int, _: libc::c_ulong) -> *mut libc::c_void;
+}
+
+// `src` is only used by `memcpy`, after a cast to `void*`.  The type before the cast gives the
+// pointee type of the copy, and from there the pointee type of `dest`.
+
+// CHECK-LABEL: pointee types for copy_into_void
+// CHECK: (dest): addr_of
+// CHECK-NEXT: pointer {{.*}}#i32[]{{$}}
+// CHECK: (src): addr_of
+unsafe fn copy_into_void(dest: *mut libc::c_void, src: *const i32) {
+    memcpy(
+        dest,
+        src as *const libc::c_void,
+        ::std::mem::size_of::<i32>() as libc::c_ulong,
+    );
+}
+
+// CHECK-LABEL: pointee types for clear_through_void
+// CHECK: (p): addr_of
+// CHECK-NEXT: pointer {{.*}}#i32[]{{$}}
+unsafe fn clear_through_void(p: *mut i32) {
+    memset(
+        p as *mut libc::c_void,
+        0,
+        ::std::mem::size_of::<i32>() as libc::c_ulong,
+    );
+}
@@ -0,0 +1,37 @@
+#![allow(dead_code)]
+
+extern crate libc;
+
+extern "C" {
+    fn memset(_: *mut libc::c_void, _: libc::c_int, _: libc::c_ulong) -> *mut libc::c_void;
+}
+
+// CHECK-LABEL: unsafe fn zero_slice(
+// CHECK-SAME: p: &{{('[^ ]* )?}}mut [(i32)]
+unsafe fn zero_slice(p: *mut i32, n: usize) {
+    // CHECK: let (dest, val, byte_len, ) =
+    // CHECK: ((&mut *dest))[..n].fill(0)
+    memset(
+        p as *mut libc::c_void,
+        0,
+        (n * ::std::mem::size_of::<i32>()) as libc::c_ulong,
+    );
+    *p.offset(1) = 1;
+}
+
+struct S {
+    a: i32,
+    b: bool,
+}
+
+// CHECK-LABEL: unsafe fn zero_struct(
+unsafe fn zero_struct(p: *mut S) {
+    // Non-primitive pointees still use field-by-field zeroing.
+    // CHECK: (*dest).a = 0
+    // CHECK: (*dest).b = false
+    memset(
+        p as *mut libc::c_void,
+        0,
+        ::std::mem::size_of::<S>() as libc::c_ulong,
+    );
+}
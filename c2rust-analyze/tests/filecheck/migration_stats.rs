@@ -0,0 +1,25 @@
+//! --minimize-unsafe
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+
+// `x` is rewritten to `&mut i32`, so the `unsafe` block is no longer needed.
+fn good(x: *mut i32) {
+    unsafe {
+        *x = 1;
+    }
+}
+
+// `x` stays raw, so the `unsafe` block is kept.
+#[c2rust_analyze_test::fixed_signature]
+fn fixed(x: *mut i32) {
+    unsafe {
+        *x = 1;
+    }
+}
+
+// CHECK-LABEL: migration statistics for migration_stats:
+// CHECK-NEXT: raw pointers eliminated: 1 / 2 (50.0%)
+// CHECK-NEXT: unsafe blocks removed: 1 / 2 (50.0%)
+// CHECK-NEXT: functions fully safe: 1 / 2 (50.0%)
+// CHECK-NEXT: top reasons pointers stayed raw:
+// CHECK-NEXT: 1 FIXED
@@ -0,0 +1,98 @@
+//! --minimize-unsafe
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+#![allow(dead_code)]
+#![allow(unused_unsafe)]
+
+extern "C" {
+    fn abs(x: i32) -> i32;
+}
+
+#[repr(C)]
+pub struct Pair {
+    a: i32,
+    b: i32,
+}
+
+#[repr(C)]
+pub struct First {
+    a: i32,
+}
+
+// `get` only derefs a pointer that becomes a reference, so it no longer needs to be unsafe.
+
+// CHECK-LABEL: pub fn get{{.*}}(p: &{{.*}}i32{{.*}}) -> i32
+pub unsafe fn get(p: *const i32) -> i32 {
+    *p
+}
+
+// `get_twice` is safe too, since the only unsafe function it calls is made safe.
+
+// CHECK-LABEL: pub fn get_twice{{.*}}(
+pub unsafe fn get_twice(p: *const i32) -> i32 {
+    get(p) + get(p)
+}
+
+// `get_abs` calls a foreign function, so it stays unsafe.
+
+// CHECK-LABEL: pub unsafe fn get_abs{{.*}}(
+pub unsafe fn get_abs(p: *const i32) -> i32 {
+    abs(get(p))
+}
+
+// The `transmute` that replaces the struct pointer cast in `read_first` has its own `unsafe`
+// block, so the function can still be made safe.
+
+// CHECK-LABEL: pub fn read_first{{.*}}(
+pub unsafe fn read_first(p: *const Pair) -> i32 {
+    let b = (*p).b;
+    // CHECK: let q = unsafe {
+    // CHECK-NEXT: // SAFETY:
+    // CHECK-NEXT: std::mem::transmute::<&Pair, &First>
+    let q = p as *const First;
+    (*q).a + b
+}
+
+// In a function that isn't unsafe, blocks with no remaining unsafe operations are removed, and
+// blocks with a remaining unsafe operation are kept.
+
+// CHECK-LABEL: pub fn blocks(
+pub fn blocks(p: *const i32, x: i32) -> i32 {
+    // CHECK: let a = {
+    let a = unsafe { get(p) };
+    // CHECK: let b = unsafe { abs(x) };
+    let b = unsafe { abs(x) };
+    a + b
+}
+
+// `sum_until_zero` only reads through a pointer that becomes a slice, so it's made safe.
+
+// CHECK-LABEL: pub fn sum_until_zero{{.*}}(xs: &{{.*}}[{{.*}}]) -> i32
+pub unsafe fn sum_until_zero(xs: *const i32) -> i32 {
+    let mut sum = 0;
+    let mut i = 0;
+    while *xs.offset(i) != 0 {
+        sum += *xs.offset(i);
+        i += 1;
+    }
+    sum
+}
+
+// The caller's pointer stays raw, so the call converts it with `slice::from_raw_parts`, which is
+// unsafe.  `sum_raw` has to stay unsafe, even though the function it calls is made safe.
+
+// CHECK-LABEL: {{^}}pub unsafe fn sum_raw(xs: *const i32) -> i32
+#[c2rust_analyze_test::fixed_signature]
+pub unsafe fn sum_raw(xs: *const i32) -> i32 {
+    // CHECK: std::slice::from_raw_parts(ptr,
+    sum_until_zero(xs)
+}
+
+// Likewise, the `unsafe` block around the call is kept.
+
+// CHECK-LABEL: {{^}}pub fn sum_raw_block(xs: *const i32) -> i32
+#[c2rust_analyze_test::fixed_signature]
+pub fn sum_raw_block(xs: *const i32) -> i32 {
+    // CHECK: {{^ *}}unsafe { sum_until_zero(
+    unsafe { sum_until_zero(xs) }
+}
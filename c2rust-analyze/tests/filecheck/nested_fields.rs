@@ -0,0 +1,29 @@
+use std::ptr;
+
+pub struct Inner {
+    x: i32,
+    y: i32,
+}
+
+pub struct Outer {
+    a: Inner,
+    b: Inner,
+}
+
+// `p` and `q` alias `s.a.x`, so the address of that field needs `Cell`.  The sibling field `s.b.x`
+// is tracked separately and keeps `UNIQUE`.
+// CHECK-LABEL: final labeling for "nested_fields"
+pub unsafe fn nested_fields() {
+    // CHECK-DAG: ([[@LINE+3]]: mut s).0.0: addr_of flags = CELL | STACK,
+    // CHECK-DAG: ([[@LINE+2]]: mut s).1.0: addr_of = {{[A-Z_| ]*}}UNIQUE{{[A-Z_| ]*}}, type
+    // CHECK-DAG: ([[@LINE+1]]: mut s).1.0: addr_of flags = STACK,
+    let mut s = Outer {
+        a: Inner { x: 0, y: 0 },
+        b: Inner { x: 0, y: 0 },
+    };
+    let p = ptr::addr_of_mut!(s.a.x);
+    let q = ptr::addr_of_mut!(s.a.x);
+    *p = 1;
+    let r = ptr::addr_of_mut!(s.b.x);
+    *r = 2;
+}
@@ -44,3 +44,16 @@ fn f_zero(cond: bool) {
         y = 0 as *const _;
     }
 }
+
+// CHECK-LABEL: final labeling for "null_check"
+// `y` can be passed in by other crates, so comparing it against null makes it nullable, even
+// though it's never assigned null here.
+// CHECK: ([[@LINE+1]]: y): {{.*}}, type = UNIQUE#
+pub fn null_check(y: *const i32) {
+    if y == ptr::null() {
+        return;
+    }
+    // Inside the non-null branch, copies of `y` keep `NON_NULL`.
+    // CHECK: ([[@LINE+1]]: z): {{.*}}, type = UNIQUE | NON_NULL#
+    let z = y;
+}
@@ -75,8 +75,8 @@ unsafe fn call_use_slice(cond: bool, q: *const i32) -> i32 {
 // CHECK-LABEL: unsafe fn use_slice{{[<(]}}
 // CHECK-SAME: p: core::option::Option<&{{('[^ ]* )?}}[(i32)]>
 unsafe fn use_slice(p: *const i32) -> i32 {
-    // `p`'s new type is `Option<&[i32]>`, so `is_null()` should become `is_none()`.
-    // CHECK: .is_none()
+    // `p`'s new type is `Option<&[i32]>`, so `!p.is_null()` should become `p.is_some()`.
+    // CHECK: .is_some() {
     if !p.is_null() {
         let x = *p.offset(1);
     }
@@ -189,3 +189,43 @@ unsafe fn null_ptr_special_cases(cond: bool, p: *const i32, i: isize) -> *const
 
     s
 }
+
+// CHECK-LABEL: unsafe fn compare_null{{[<(]}}
+// CHECK-SAME: p: core::option::Option<&{{('[^ ]* )?}}(i32)>
+unsafe fn compare_null(cond: bool, mut p: *const i32) -> i32 {
+    if cond {
+        // Ensure `p` is wrapped in `Option`.
+        p = ptr::null();
+    }
+    // CHECK: .is_none() {
+    if p == ptr::null() {
+        return 0;
+    }
+    // CHECK: .is_some() {
+    if p != 0 as *const i32 {
+        return *p;
+    }
+    -1
+}
+
+// CHECK-LABEL: unsafe fn check_then_use{{[<(]}}
+// CHECK-SAME: q: core::option::Option<&{{('[^ ]* )?}}(i32)>
+pub unsafe fn check_then_use(q: *const i32) -> i32 {
+    // `q` can be passed in by other crates, and it's checked for null, so it becomes nullable.
+    // CHECK: .is_none() {
+    if q.is_null() {
+        return 0;
+    }
+    0
+}
+
+// CHECK-LABEL: unsafe fn guarded_copy{{[<(]}}
+pub unsafe fn guarded_copy(q: *const i32) -> i32 {
+    if !q.is_null() {
+        // Inside the guard, `q` is known to be non-null, so `r` doesn't need to be an `Option`.
+        // CHECK: (q).unwrap()
+        let r: *const i32 = q;
+        return *r;
+    }
+    0
+}
@@ -0,0 +1,51 @@
+#![allow(dead_code)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+static LIMIT: i32 = 10;
+
+// Each pointer records where its value may come from: a local, an allocation, or a static.
+
+// CHECK-LABEL: final labeling for "origins"
+pub unsafe fn origins(c: bool) -> i32 {
+    let mut x = 1;
+    // CHECK-DAG: ([[@LINE+1]]: p): {{.*}}type flags = STACK#
+    let p: *mut i32 = &mut x;
+    // CHECK-DAG: ([[@LINE+1]]: q): {{.*}}type flags = HEAP#
+    let q: *mut i32 = malloc(::std::mem::size_of::<i32>() as libc::c_ulong) as *mut i32;
+    // CHECK-DAG: ([[@LINE+1]]: r): {{.*}}type flags = STATIC#
+    let r: *const i32 = &LIMIT;
+    // CHECK-DAG: ([[@LINE+1]]: s): {{.*}}type flags = STACK | STATIC#
+    let s: *const i32 = if c { p } else { r };
+    *q = *s;
+    let v = *q;
+    free(q as *mut libc::c_void);
+    v
+}
+
+// CHECK-LABEL: ===== BEGIN
+
+// A reference that only ever points into statics gets the `'static` lifetime.
+
+// CHECK-LABEL: unsafe fn limit_ref(
+pub unsafe fn limit_ref() -> i32 {
+    // CHECK: let r: &'static (i32) =
+    let r: *const i32 = &LIMIT;
+    *r
+}
+
+// An allocation that's never freed lives for the rest of the program.  It's leaked instead of
+// being freed, so a read-only pointer to it can also be `'static`.
+
+// CHECK-LABEL: unsafe fn make_limit() -> &'static (i32)
+pub unsafe fn make_limit() -> *const i32 {
+    // CHECK: Box::leak(
+    let p = malloc(::std::mem::size_of::<i32>() as libc::c_ulong) as *mut i32;
+    *p = 10;
+    p
+}
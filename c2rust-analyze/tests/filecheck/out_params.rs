@@ -0,0 +1,66 @@
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+
+extern crate libc;
+
+// CHECK-LABEL: unsafe fn parse_digit{{[<(]}}
+// CHECK-SAME: c: u8) -> Option<i32>
+// CHECK-NEXT: let mut out: i32 = 0;
+#[c2rust_analyze_test::out_param]
+pub unsafe fn parse_digit(c: u8, out: *mut i32) -> libc::c_int {
+    if !(c >= b'0' && c <= b'9') {
+        // CHECK: return None
+        return -(1 as libc::c_int);
+    }
+    // CHECK: out = (c - b'0') as i32;
+    *out = (c - b'0') as i32;
+    // CHECK: return Some(out)
+    return 0 as libc::c_int;
+}
+
+// Different failures return different statuses, so the status is kept in the `Err` case.
+// CHECK-LABEL: unsafe fn parse_pair{{[<(]}}
+// CHECK-SAME: b: u8) -> Result<i32, libc::c_int>
+// CHECK-NEXT: let mut result: i32 = 0;
+#[c2rust_analyze_test::out_param]
+pub unsafe fn parse_pair(result: *mut i32, a: u8, b: u8) -> libc::c_int {
+    let mut hi: i32 = 0;
+    let mut lo: i32 = 0;
+    // CHECK: parse_digit(a).map_or(-1, |out_value| {
+    // CHECK-NEXT: hi = out_value;
+    // CHECK-NEXT: 0
+    // CHECK-NEXT: }) != 0
+    if parse_digit(a, &mut hi) != 0 {
+        // CHECK: return Err(1)
+        return 1;
+    }
+    if parse_digit(b, &mut lo) != 0 {
+        // CHECK: return Err(2)
+        return 2;
+    }
+    // CHECK: result = hi * 10 + lo;
+    *result = hi * 10 + lo;
+    // CHECK: Ok(result)
+    0
+}
+
+// CHECK-LABEL: unsafe fn caller{{[<(]}}
+pub unsafe fn caller(a: u8, b: u8, p: *mut i32) -> libc::c_int {
+    // CHECK: parse_pair(a, b).map_or_else(|status| status, |out_value| {
+    // CHECK-NEXT: *p = out_value;
+    // CHECK-NEXT: 0
+    // CHECK-NEXT: })
+    parse_pair(p, a, b)
+}
+
+// The out-parameter isn't written before every successful return, so it isn't rewritten.
+// CHECK-LABEL: unsafe fn maybe_write{{[<(]}}
+// CHECK-SAME: -> libc::c_int
+#[c2rust_analyze_test::out_param]
+pub unsafe fn maybe_write(c: u8, out: *mut i32) -> libc::c_int {
+    if c == 0 {
+        return 0;
+    }
+    *out = c as i32;
+    return -1;
+}
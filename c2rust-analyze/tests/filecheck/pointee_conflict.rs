@@ -0,0 +1,42 @@
+use std::ffi::c_void;
+
+#[repr(C)]
+pub struct Base {
+    tag: i32,
+}
+
+#[repr(C)]
+pub struct Derived {
+    tag: i32,
+    value: i32,
+}
+
+// `Derived` starts with the fields of `Base`, so `p` is treated as a pointer to `Derived`.
+// CHECK: pointee type conflict for {{.*}} (read_both): using common prefix struct Derived
+// CHECK-NEXT: Base used at {{.*}}pointee_conflict.rs
+// CHECK-NEXT: Derived used at {{.*}}pointee_conflict.rs
+pub unsafe fn read_both(p: *const c_void) -> i32 {
+    let base = p as *const Base;
+    let derived = p as *const Derived;
+    (*base).tag + (*derived).value
+}
+
+// `p` is read both as bytes and as a wider integer, so it's treated as a pointer to bytes.
+// CHECK: pointee type conflict for {{.*}} (read_bytes): using byte type u8
+// CHECK-NEXT: i32 used at
+// CHECK-NEXT: u8 used at
+pub unsafe fn read_bytes(p: *const c_void) -> i32 {
+    let bytes = p as *const u8;
+    let word = p as *const i32;
+    *bytes as i32 + *word
+}
+
+// There's no type that covers both uses, so `p` is kept as it is.
+// CHECK: pointee type conflict for {{.*}} (read_mixed): keeping raw pointer
+// CHECK-NEXT: Base used at
+// CHECK-NEXT: f64 used at
+pub unsafe fn read_mixed(p: *const c_void) -> f64 {
+    let x = p as *const f64;
+    let s = p as *const Base;
+    *x + (*s).tag as f64
+}
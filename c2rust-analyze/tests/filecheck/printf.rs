@@ -0,0 +1,42 @@
+extern crate libc;
+
+extern "C" {
+    static mut stdout: *mut libc::FILE;
+    static mut stderr: *mut libc::FILE;
+    fn printf(format: *const libc::c_char, _: ...) -> libc::c_int;
+    fn fprintf(stream: *mut libc::FILE, format: *const libc::c_char, _: ...) -> libc::c_int;
+}
+
+// CHECK-LABEL: unsafe fn report{{[<(]}}
+pub unsafe fn report(name: *const libc::c_char, count: libc::c_int, ratio: f64) {
+    // CHECK: print!("{}: {:5} items\n",std::ffi::CStr::from_ptr((name)).to_string_lossy(),(count));
+    printf(
+        b"%s: %5d items\n\0" as *const u8 as *const libc::c_char,
+        name,
+        count,
+    );
+    // CHECK: print!("{:.2}% {{[{][{]}}done{{[}][}]}}\n",(ratio));
+    fprintf(
+        stdout,
+        b"%.2f%% {done}\n\0" as *const u8 as *const libc::c_char,
+        ratio,
+    );
+    // CHECK: eprint!("flag '{}' = {:#x}\n",(count + 'a' as i32) as u8 as char,(count));
+    fprintf(
+        stderr,
+        b"flag '%c' = %#x\n\0" as *const u8 as *const libc::c_char,
+        count + 'a' as i32,
+        count,
+    );
+}
+
+// CHECK-LABEL: unsafe fn unsupported{{[<(]}}
+pub unsafe fn unsupported(count: libc::c_int, ratio: f64) -> libc::c_int {
+    // `%e` has no equivalent in Rust.
+    // CHECK: printf(
+    printf(b"%e\n\0" as *const u8 as *const libc::c_char, ratio);
+    // The number of bytes written is used.
+    // CHECK: let n = printf(
+    let n = printf(b"%d\n\0" as *const u8 as *const libc::c_char, count);
+    n
+}
@@ -0,0 +1,37 @@
+// A pointer built from an integer has no provenance, so the function is left unchanged.
+
+// CHECK-LABEL: unsafe fn from_addr{{.*}}(addr: usize) -> i32
+pub unsafe fn from_addr(addr: usize) -> i32 {
+    // CHECK: let p = addr as *const i32;
+    let p = addr as *const i32;
+    *p
+}
+
+// Rebuilding a pointer from its own address is still an int-to-pointer cast.
+
+// CHECK-LABEL: unsafe fn round_trip{{.*}}(p: *mut i32)
+pub unsafe fn round_trip(p: *mut i32) {
+    let q = (p as usize + 4) as *mut i32;
+    *q = 1;
+}
+
+// `p` is offset to reach `q`, but the result keeps the provenance of `p`.
+
+// CHECK-LABEL: unsafe fn jump
+pub unsafe fn jump(p: *const u8, q: *const u8) -> u8 {
+    let r = p.offset((q as usize - p as usize) as isize);
+    *r
+}
+
+// Rounding a pointer up to an aligned address is not a violation.
+
+// CHECK-LABEL: unsafe fn align_up
+pub unsafe fn align_up(p: *mut u8) -> *mut u8 {
+    ((p as usize).wrapping_add(7) & !7) as *mut u8
+}
+
+// CHECK-LABEL: provenance violations (must fix):
+// CHECK-NEXT: provenance[{{.*}}]::from_addr{{.*}}: int-to-pointer cast at {{.*}}provenance.rs:6:{{.*}}: pointer is created from an integer that has no provenance
+// CHECK-NEXT: provenance[{{.*}}]::round_trip{{.*}}: int-to-pointer cast at {{.*}}provenance.rs:14:{{.*}}: pointer is rebuilt from the address of another pointer
+// CHECK-NEXT: provenance[{{.*}}]::jump{{.*}}: cross-allocation pointer arithmetic at {{.*}}provenance.rs:22:{{.*}}: pointer is offset by the distance to a pointer that may be in another allocation
+// CHECK-NOT: align_up
@@ -0,0 +1,27 @@
+// CHECK-LABEL: unsafe fn offset_from{{[<(]}}
+pub unsafe fn offset_from(p: *const i32, n: isize) -> isize {
+    let start = p;
+    let end = p.offset(n);
+    // CHECK: let (end, start, ) = ((end), (start), );
+    // CHECK-NEXT: start.len() as isize - end.len() as isize
+    end.offset_from(start)
+}
+
+// CHECK-LABEL: unsafe fn addr_diff{{[<(]}}
+pub unsafe fn addr_diff(p: *const i32, n: isize) -> usize {
+    let start = p;
+    let end = p.offset(n);
+    // CHECK: let (end, start, ) = ((end), (start), );
+    // CHECK-NEXT: start.len() - end.len()
+    // CHECK-NOT: {{^[^/]*}}size_of
+    (end as usize - start as usize) / std::mem::size_of::<i32>()
+}
+
+// CHECK-LABEL: unsafe fn byte_diff{{[<(]}}
+pub unsafe fn byte_diff(p: *const i32, n: isize) -> usize {
+    let start = p;
+    let end = p.offset(n);
+    // CHECK: let (end, start, ) = ((end), (start), );
+    // CHECK-NEXT: (start.len() - end.len()) * std::mem::size_of::<i32>()
+    end as usize - start as usize
+}
@@ -0,0 +1,27 @@
+// CHECK-LABEL: unsafe fn sum{{[<(]}}
+pub unsafe fn sum(p: *const i32, n: isize) -> i32 {
+    let mut p = p;
+    // `end` is compared against `p`, so it becomes a slice too.
+    let end = p.offset(n);
+    let mut total = 0;
+    // CHECK: let __len = p.len() - end.len();
+    // CHECK-NEXT: for p in p[..__len].iter() {
+    while p != end {
+        total += *p;
+        p = p.offset(1);
+    }
+    total
+}
+
+// The loop can't be converted when `p` is used after the loop, but the comparison is still
+// rewritten to compare the slices' start addresses.
+// CHECK-LABEL: unsafe fn skip_to_end{{[<(]}}
+pub unsafe fn skip_to_end(p: *const i32, n: isize) -> usize {
+    let mut p = p;
+    let end = p.offset(n);
+    // CHECK: while (p).as_ptr() != (end).as_ptr() {
+    while p != end {
+        p = p.offset(1);
+    }
+    p as usize
+}
@@ -0,0 +1,52 @@
+#![allow(dead_code)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+struct Item {
+    val: i32,
+}
+
+// An out-parameter that receives ownership of a new allocation becomes `&mut Option<Box<T>>`.
+
+// CHECK-LABEL: final labeling for "make_item"
+// CHECK-DAG: ([[@LINE+1]]: out): &mut std::option::Option<std::boxed::Box<Item>>
+pub unsafe extern "C" fn make_item(out: *mut *mut Item) {
+    *out = malloc(::std::mem::size_of::<Item>() as libc::c_ulong) as *mut Item;
+    (**out).val = 1;
+}
+
+// CHECK-LABEL: final labeling for "use_item"
+pub unsafe extern "C" fn use_item() -> i32 {
+    // CHECK-DAG: ([[@LINE+1]]: it): std::option::Option<std::boxed::Box<Item>>
+    let mut it: *mut Item = 0 as *mut Item;
+    make_item(&mut it);
+    let v = (*it).val;
+    free(it as *mut libc::c_void);
+    v
+}
+
+// An array of owned pointers becomes `&mut [Option<Box<T>>]`.  Moving an element out of the array
+// leaves `None` behind.
+
+// CHECK-LABEL: final labeling for "free_all"
+// CHECK-DAG: ([[@LINE+1]]: items): &mut [std::option::Option<std::boxed::Box<Item>>]
+pub unsafe extern "C" fn free_all(items: *mut *mut Item, n: usize) {
+    let mut i = 0;
+    while i < n {
+        free(*items.offset(i as isize) as *mut libc::c_void);
+        i += 1;
+    }
+}
+
+// CHECK-LABEL: unsafe extern "C" fn make_item(
+// CHECK: Some(Box::new(
+
+// CHECK-LABEL: unsafe extern "C" fn free_all(
+// CHECK: std::mem::drop(
+// CHECK-SAME: .take()
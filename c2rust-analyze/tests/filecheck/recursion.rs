@@ -0,0 +1,33 @@
+// Each cycle of mutually recursive functions is iterated to a fixpoint before any of its callers
+// are visited, so the callers see the final permissions of the cycle right away.  The first
+// iteration computes all the right permissions, and the second checks that we've actually
+// reached a fixpoint.
+//
+// CHECK: reached fixpoint in 2 iterations
+
+// CHECK-LABEL: final labeling for "even"
+// CHECK-DAG: ([[@LINE+1]]: p): &mut i32
+pub unsafe fn even(p: *mut i32, n: i32) -> bool {
+    if n == 0 {
+        return true;
+    }
+    odd(p, n - 1)
+}
+
+// CHECK-LABEL: final labeling for "odd"
+// CHECK-DAG: ([[@LINE+1]]: p): &mut i32
+pub unsafe fn odd(p: *mut i32, n: i32) -> bool {
+    *p += 1;
+    if n == 0 {
+        return false;
+    }
+    even(p, n - 1)
+}
+
+// CHECK-LABEL: final labeling for "count"
+pub unsafe fn count(x: *mut i32, n: i32) -> i32 {
+    // CHECK-DAG: ([[@LINE+1]]: p): &mut i32
+    let p = x;
+    even(p, n);
+    *x
+}
@@ -0,0 +1,52 @@
+struct S {
+    a: i32,
+    b: i32,
+}
+
+// CHECK-LABEL: fn ref_cell(
+unsafe fn ref_cell() -> i32 {
+    // CHECK: let mut x = std::cell::RefCell::new(
+    let mut x = S { a: 1, b: 2 };
+    // CHECK: let mut p = &(x);
+    let mut p = std::ptr::addr_of_mut!(x);
+    // CHECK: let mut q = &(x);
+    let mut q = std::ptr::addr_of_mut!(x);
+    // CHECK: (*(p).borrow_mut()).a = 3;
+    (*p).a = 3;
+    // CHECK: (*(q).borrow_mut()).b = 4;
+    (*q).b = 4;
+    // Reading and writing the same field borrows only once.
+    // CHECK: (*(q).borrow_mut()).b += 5;
+    (*q).b += 5;
+    // CHECK: (x).borrow_mut().a = 6;
+    x.a = 6;
+    // CHECK: (*(p).borrow()).a + (x).borrow().b
+    (*p).a + x.b
+}
+
+// CHECK-LABEL: fn ref_cell_param(
+// CHECK-SAME: p: &core::cell::RefCell<(S)>
+// CHECK-SAME: q: &core::cell::RefCell<(S)>
+unsafe fn ref_cell_param(p: *mut S, q: *mut S) {
+    // CHECK: (*(p).borrow_mut()).a = (*(q).borrow()).b;
+    (*p).a = (*q).b;
+}
+
+// CHECK-LABEL: fn ref_cell_caller(
+unsafe fn ref_cell_caller() {
+    let mut x = S { a: 1, b: 2 };
+    // CHECK: ref_cell_param(&(x), &(x));
+    ref_cell_param(std::ptr::addr_of_mut!(x), std::ptr::addr_of_mut!(x));
+}
+
+// Borrowing a field would outlive the `RefMut` guard, so this function is left unchanged.
+// CHECK-LABEL: fn ref_cell_field_ref(
+unsafe fn ref_cell_field_ref() {
+    let mut x = S { a: 1, b: 2 };
+    let p = std::ptr::addr_of_mut!(x);
+    let q = std::ptr::addr_of_mut!(x);
+    // CHECK: let r = &mut (*p).a;
+    let r = &mut (*p).a;
+    (*q).b = 1;
+    *r = 2;
+}
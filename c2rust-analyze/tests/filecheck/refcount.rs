@@ -0,0 +1,134 @@
+//! --use-rc
+#![allow(dead_code)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+// `obj_retain` and `obj_release` are the only users of `refcount`, so the field and the functions
+// are removed.
+// CHECK-LABEL: {{^}}struct Obj {
+// CHECK-NEXT: {{^ *}}value: i32,
+struct Obj {
+    refcount: i32,
+    value: i32,
+}
+
+// CHECK-LABEL: unsafe fn obj_new(value: i32) -> std::rc::Rc<std::cell::RefCell<Obj>>
+unsafe fn obj_new(value: i32) -> *mut Obj {
+    // CHECK: {{^ *}}let p = std::rc::Rc::new(std::cell::RefCell::new(Obj { value: 0, }));
+    let p = malloc(::std::mem::size_of::<Obj>() as libc::c_ulong) as *mut Obj;
+    // CHECK-NOT: {{^ *}}(*(p).borrow_mut()).refcount = 1;
+    (*p).refcount = 1;
+    (*p).value = value;
+    // CHECK: (p).clone()
+    p
+}
+
+// CHECK-NOT: {{^}}unsafe fn obj_retain(
+unsafe fn obj_retain(p: *mut Obj) -> *mut Obj {
+    (*p).refcount += 1;
+    return p;
+}
+
+// CHECK-NOT: {{^}}unsafe fn obj_release(
+unsafe fn obj_release(p: *mut Obj) {
+    (*p).refcount -= 1;
+    if (*p).refcount == 0 {
+        free(p as *mut libc::c_void);
+    }
+}
+
+// CHECK-LABEL: unsafe fn obj_get(p: std::rc::Rc<std::cell::RefCell<Obj>>) -> i32
+unsafe fn obj_get(p: *mut Obj) -> i32 {
+    // CHECK: (*(p).borrow()).value
+    (*p).value
+}
+
+// CHECK-LABEL: unsafe fn use_objs()
+unsafe fn use_objs() -> i32 {
+    let a = obj_new(1);
+    // CHECK: {{^ *}}let b = (a).clone();
+    let b = obj_retain(a);
+    // CHECK: (*(b).borrow_mut()).value = 2;
+    (*b).value = 2;
+    // CHECK: let v = obj_get((a).clone());
+    let v = obj_get(a);
+    // CHECK: {{^ *}}std::mem::drop((a));
+    obj_release(a);
+    // CHECK: {{^ *}}std::mem::drop((b));
+    obj_release(b);
+    v
+}
+
+// `node_count` reads the count, so `refcount` is kept.  Removing `node_ref` and `node_unref` would
+// leave the count stale, so they're kept too, and still update it.
+// CHECK-LABEL: {{^}}struct Node {
+// CHECK-NEXT: {{^ *}}refcount: i32,
+struct Node {
+    refcount: i32,
+    next: i32,
+}
+
+// CHECK-LABEL: unsafe fn node_new()
+unsafe fn node_new() -> *mut Node {
+    // CHECK: {{^ *}}let p = std::rc::Rc::new(std::cell::RefCell::new(Node { refcount: 0, next: 0, }));
+    let p = malloc(::std::mem::size_of::<Node>() as libc::c_ulong) as *mut Node;
+    // CHECK: {{^ *}}(*(p).borrow_mut()).refcount = 1;
+    (*p).refcount = 1;
+    p
+}
+
+// CHECK-LABEL: unsafe fn node_ref(p: std::rc::Rc<std::cell::RefCell<Node>>)
+unsafe fn node_ref(p: *mut Node) {
+    // CHECK: (*(p).borrow_mut()).refcount += 1;
+    (*p).refcount += 1;
+}
+
+// CHECK-LABEL: unsafe fn node_unref(p: std::rc::Rc<std::cell::RefCell<Node>>)
+unsafe fn node_unref(p: *mut Node) {
+    // CHECK: (*(p).borrow_mut()).refcount -= 1;
+    (*p).refcount -= 1;
+    // CHECK: if (*(p).borrow()).refcount == 0 as libc::c_int {
+    if (*p).refcount == 0 as libc::c_int {
+        // CHECK: std::mem::drop((p));
+        free(p as *mut libc::c_void);
+    }
+}
+
+// CHECK-LABEL: unsafe fn node_count(p: std::rc::Rc<std::cell::RefCell<Node>>) -> i32
+unsafe fn node_count(p: *mut Node) -> i32 {
+    // CHECK: (*(p).borrow()).refcount
+    (*p).refcount
+}
+
+// CHECK-LABEL: unsafe fn use_nodes()
+unsafe fn use_nodes() -> i32 {
+    let n = node_new();
+    // CHECK: {{^ *}}node_ref((n).clone());
+    node_ref(n);
+    // CHECK: {{^ *}}let m = (n).clone();
+    let m = n;
+    // CHECK: {{^ *}}let c = node_count((m).clone());
+    let c = node_count(m);
+    // CHECK: {{^ *}}node_unref((n).clone());
+    node_unref(n);
+    // CHECK: {{^ *}}node_unref((m).clone());
+    node_unref(m);
+    c
+}
+
+// Freeing without checking the reference count means `Unique` isn't reference counted.
+struct Unique {
+    refcount: i32,
+}
+
+// CHECK-LABEL: unsafe fn unique_free(
+// CHECK-NOT: RefCell
+unsafe fn unique_free(p: *mut Unique) {
+    free(p as *mut libc::c_void);
+}
@@ -0,0 +1,49 @@
+use std::ptr;
+
+pub struct Node {
+    val: i32,
+}
+
+// Every return site of `get` yields a non-null pointer, so its return type is a plain reference,
+// and the caller's null check on the result is dead.
+
+// CHECK-LABEL: final labeling for "use_get"
+pub unsafe fn use_get(n: &mut Node) -> i32 {
+    // CHECK: ([[@LINE+1]]: p): {{.*}}type = READ | UNIQUE | NON_NULL#
+    let p = get(n);
+    if p.is_null() {
+        return -1;
+    }
+    (*p).val
+}
+
+// `find` may return null, so the null check in the caller is kept.
+
+// CHECK-LABEL: final labeling for "use_find"
+pub unsafe fn use_find(n: &mut Node, want: bool) -> i32 {
+    // CHECK: ([[@LINE+1]]: p): {{.*}}type = READ | UNIQUE#
+    let p = find(n, want);
+    if p.is_null() {
+        return -1;
+    }
+    (*p).val
+}
+
+// CHECK-LABEL: unsafe fn use_get{{[<(]}}
+// CHECK: if false {
+
+// CHECK-LABEL: unsafe fn get{{[<(]}}
+// CHECK-SAME: -> &{{.*}}mut (Node)
+unsafe fn get(n: &mut Node) -> *mut Node {
+    n as *mut Node
+}
+
+// CHECK-LABEL: unsafe fn find{{[<(]}}
+// CHECK-SAME: -> core::option::Option<&{{.*}}mut (Node)>
+unsafe fn find(n: &mut Node, want: bool) -> *mut Node {
+    if want {
+        n as *mut Node
+    } else {
+        ptr::null_mut()
+    }
+}
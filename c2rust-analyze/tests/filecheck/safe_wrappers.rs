@@ -0,0 +1,42 @@
+//! --safe-wrappers
+#![allow(dead_code)]
+
+// `sum` is safe after rewriting, so it becomes `sum_safe`, and an extern wrapper with the
+// original signature is added under the original name.
+
+// CHECK-LABEL: pub fn sum_safe{{.*}}(p: &{{.*}}i32{{.*}}, n: usize) -> i32
+#[no_mangle]
+pub unsafe extern "C" fn sum(p: *const i32, n: usize) -> i32 {
+    let mut total = 0;
+    let mut i = 0;
+    while i < n {
+        total += *p.offset(i as isize);
+        i += 1;
+    }
+    total
+}
+// CHECK: #[no_mangle]
+// CHECK-NEXT: pub unsafe extern "C" fn sum(arg0: *const i32, arg1: usize) -> i32 {
+// CHECK: sum_safe(safe_arg0, safe_arg1)
+
+// Rewritten Rust callers call the safe function directly.
+
+// CHECK-LABEL: pub unsafe fn sum4(
+pub unsafe fn sum4(p: *const i32) -> i32 {
+    // CHECK: sum_safe(
+    sum(p, 4)
+}
+
+// `first_abs` still calls an unsafe foreign function, so it keeps its original name and
+// qualifiers.
+
+extern "C" {
+    fn abs(x: i32) -> i32;
+}
+
+// CHECK-LABEL: pub unsafe extern "C" fn first_abs(
+#[no_mangle]
+pub unsafe extern "C" fn first_abs(p: *const i32) -> i32 {
+    abs(*p)
+}
+// CHECK-NOT: first_abs_safe
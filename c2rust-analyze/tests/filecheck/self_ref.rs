@@ -0,0 +1,99 @@
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+
+// With the index strategy, pointers into the struct's own array become indices into it.
+
+// CHECK-LABEL: pub struct Parser
+#[c2rust_analyze_test::self_ref_index]
+pub struct Parser {
+    pub buf: [u8; 16],
+    // CHECK: pub cur: usize,
+    pub cur: *mut u8,
+    // CHECK: pub end: usize,
+    pub end: *mut u8,
+}
+
+// CHECK-LABEL: pub fn parser_new()
+pub fn parser_new() -> Parser {
+    Parser {
+        buf: [0; 16],
+        // CHECK: cur: 0,
+        cur: 0 as *mut u8,
+        // CHECK: end: 0,
+        end: 0 as *mut u8,
+    }
+}
+
+// CHECK-LABEL: pub unsafe fn parser_init(p: *mut Parser)
+pub unsafe fn parser_init(p: *mut Parser) {
+    // CHECK: (*p).cur = 0;
+    (*p).cur = (*p).buf.as_mut_ptr();
+    // CHECK: (*p).end = {{.*}}16{{.*}} as usize;
+    (*p).end = (*p).buf.as_mut_ptr().offset(16);
+}
+
+// CHECK-LABEL: pub unsafe fn parser_push(
+pub unsafe fn parser_push(p: *mut Parser, c: u8) -> i32 {
+    // CHECK: if (*p).cur == (*p).end
+    if (*p).cur == (*p).end {
+        return -1;
+    }
+    // CHECK: {{.*}}buf{{.*}}[(*p).cur] = c;
+    *(*p).cur = c;
+    // CHECK: (*p).cur = ({{.*}}(*p).cur{{.*}} as isize + {{.*}}1{{.*}} as isize) as usize;
+    (*p).cur = (*p).cur.offset(1);
+    0
+}
+
+// CHECK-LABEL: pub unsafe fn parser_peek(
+pub unsafe fn parser_peek(p: *mut Parser) -> u8 {
+    // CHECK: {{.*}}buf{{.*}}[({{.*}}(*p).cur as isize + {{.*}}-1{{.*}} as isize) as usize]
+    *(*p).cur.offset(-1)
+}
+
+// CHECK-LABEL: pub unsafe fn parser_len(
+pub unsafe fn parser_len(p: *mut Parser) -> isize {
+    // CHECK: {{.*}}(*p).cur{{.*}} as isize
+    // CHECK-NOT: offset_from
+    (*p).cur.offset_from((*p).buf.as_ptr())
+}
+
+// With the pin strategy, the pointer stays raw, and the struct gets a `PhantomPinned` field.
+
+// CHECK-LABEL: pub struct Counter
+#[c2rust_analyze_test::self_ref_pin]
+pub struct Counter {
+    pub count: i32,
+    // CHECK: pub target: *mut i32,
+    pub target: *mut i32,
+    // CHECK-NEXT: pub _pin: std::marker::PhantomPinned,
+}
+
+// CHECK-LABEL: pub fn counter_new()
+pub fn counter_new() -> Counter {
+    Counter {
+        count: 0,
+        target: 0 as *mut i32,
+        // CHECK: _pin: std::marker::PhantomPinned,
+    }
+}
+
+// CHECK-LABEL: pub unsafe fn counter_init(
+pub unsafe fn counter_init(c: *mut Counter) {
+    (*c).target = &mut (*c).count;
+}
+
+// A pointer that escapes the struct can't become an index, so `Escaping` is left alone.
+
+// CHECK-LABEL: pub struct Escaping
+#[c2rust_analyze_test::self_ref_index]
+pub struct Escaping {
+    pub buf: [u8; 4],
+    // CHECK-NOT: cur: usize
+    pub cur: *mut u8,
+}
+
+pub unsafe fn escaping_init(e: *mut Escaping) -> *mut u8 {
+    (*e).cur = (*e).buf.as_mut_ptr();
+    (*e).cur
+}
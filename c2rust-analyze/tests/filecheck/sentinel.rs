@@ -0,0 +1,41 @@
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+
+extern crate libc;
+
+extern "C" {
+    fn puts(s: *const libc::c_char) -> libc::c_int;
+}
+
+// CHECK-LABEL: unsafe fn count_args{{.*}}(argv: &{{.*}}[{{.*}}]) -> usize
+pub unsafe fn count_args(argv: *mut *mut libc::c_char) -> usize {
+    let mut n = 0;
+    while !(*argv.offset(n as isize)).is_null() {
+        puts(*argv.offset(n as isize));
+        n += 1;
+    }
+    n
+}
+
+// CHECK-LABEL: unsafe fn sum_until_zero{{.*}}(xs: &{{.*}}[{{.*}}]) -> i32
+pub unsafe fn sum_until_zero(xs: *const i32) -> i32 {
+    let mut sum = 0;
+    let mut i = 0;
+    while *xs.offset(i) != 0 {
+        sum += *xs.offset(i);
+        i += 1;
+    }
+    sum
+}
+
+// The caller only has raw pointers, so it finds each array's length by scanning for the sentinel.
+// CHECK-LABEL: unsafe fn main_0(argv: *mut *mut libc::c_char, xs: *const i32)
+#[c2rust_analyze_test::fixed_signature]
+pub unsafe fn main_0(argv: *mut *mut libc::c_char, xs: *const i32) -> i32 {
+    // CHECK: count_args({{.*}}let ptr = {{.*}}argv{{.*}};
+    // CHECK: std::slice::from_raw_parts(ptr,(0..).position(|i| (*ptr.add(i)).is_null()).unwrap() + 1)
+    count_args(argv);
+    // CHECK: sum_until_zero({{.*}}let ptr = {{.*}}xs{{.*}};
+    // CHECK: std::slice::from_raw_parts(ptr,(0..).position(|i| *ptr.add(i) == 0).unwrap() + 1)
+    sum_until_zero(xs)
+}
@@ -0,0 +1,56 @@
+#![allow(dead_code)]
+#![allow(non_camel_case_types)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn realloc(_: *mut libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+// A byte buffer grown with `realloc`, where the result is checked for null before it's stored.
+
+// CHECK-LABEL: pub struct simple_buffer {
+// CHECK-NEXT: pub data: Vec<u8>,
+// CHECK-NEXT: }
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct simple_buffer {
+    pub data: *mut u8,
+    pub len: libc::size_t,
+    pub cap: libc::size_t,
+}
+
+// CHECK-LABEL: unsafe extern "C" fn buffer_push(
+pub unsafe extern "C" fn buffer_push(b: *mut simple_buffer, x: u8) -> libc::c_int {
+    if (*b).len == (*b).cap {
+        let new_cap = if (*b).cap == 0 {
+            16
+        } else {
+            (*b).cap.wrapping_mul(2)
+        };
+        // CHECK-NOT: realloc
+        let p = realloc((*b).data as *mut libc::c_void, new_cap as libc::c_ulong) as *mut u8;
+        // CHECK: if false {
+        if p.is_null() {
+            return -1;
+        }
+        // CHECK: ((*b).data).reserve_exact(
+        (*b).cap = new_cap;
+        (*b).data = p;
+    }
+    // CHECK: ((*b).data).push((x));
+    *(*b).data.offset((*b).len as isize) = x;
+    (*b).len = (*b).len.wrapping_add(1);
+    0
+}
+
+// CHECK-LABEL: unsafe extern "C" fn buffer_free(
+pub unsafe extern "C" fn buffer_free(b: *mut simple_buffer) {
+    // CHECK: ((*b).data) = Vec::new()
+    free((*b).data as *mut libc::c_void);
+    // CHECK: ((*b).data).clear()
+    (*b).len = 0;
+}
@@ -0,0 +1,36 @@
+#![allow(dead_code)]
+
+extern crate libc;
+
+use libc::{c_char, c_int, size_t};
+
+extern "C" {
+    fn sprintf(_: *mut c_char, _: *const c_char, _: ...) -> c_int;
+    fn snprintf(_: *mut c_char, _: size_t, _: *const c_char, _: ...) -> c_int;
+}
+
+// CHECK-LABEL: unsafe fn format_int(
+// CHECK-SAME: buf: &{{('[^ ]* )?}}mut [(i8)]
+unsafe fn format_int(buf: *mut c_char, x: c_int) -> c_int {
+    // CHECK: let (dest, s, ) = ((buf), format!("x = {:5}\n", (x)), );
+    // CHECK: let n = s.len();
+    // CHECK: dest[..n].iter_mut().zip(s.bytes()).for_each(|(d, c)| *d = c as _);
+    // CHECK: dest[n] = 0;
+    // CHECK: s.len() as i32
+    sprintf(buf, b"x = %5d\n\0" as *const u8 as *const c_char, x)
+}
+
+// CHECK-LABEL: unsafe fn format_bounded(
+// CHECK-SAME: buf: &{{('[^ ]* )?}}mut [(i8)]
+unsafe fn format_bounded(buf: *mut c_char, n: size_t, i: c_int, c: c_int) {
+    // CHECK: let (dest, size, s, ) = ((buf), (n) as usize, format!("{}: {}", (i), (c) as u8 as char), );
+    // CHECK: if size > 0 { let n = s.len().min(size - 1);
+    snprintf(buf, n, b"%d: %c\0" as *const u8 as *const c_char, i, c);
+}
+
+// CHECK-LABEL: unsafe fn format_unsupported(
+unsafe fn format_unsupported(buf: *mut c_char, x: f64) {
+    // `%e` has no equivalent in Rust's formatting syntax, so the call is left alone.
+    // CHECK: sprintf(
+    sprintf(buf, b"%e\0" as *const u8 as *const c_char, x);
+}
@@ -0,0 +1,59 @@
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+
+extern crate libc;
+
+extern "C" {
+    fn puts(s: *const libc::c_char) -> libc::c_int;
+    fn sprintf(s: *mut libc::c_char, format: *const libc::c_char, _: ...) -> libc::c_int;
+}
+
+static mut NAME_BUF: [libc::c_char; 32] = [0; 32];
+
+// CHECK-LABEL: unsafe fn describe{{[<(]}}
+// CHECK-SAME: x: libc::c_int) -> Vec<u8>
+#[c2rust_analyze_test::static_buf]
+pub unsafe fn describe(x: libc::c_int) -> *mut libc::c_char {
+    sprintf(
+        NAME_BUF.as_mut_ptr(),
+        b"value %d\0" as *const u8 as *const libc::c_char,
+        x,
+    );
+    // CHECK: return std::ffi::CStr::from_ptr(NAME_BUF.as_ptr() as *const std::os::raw::c_char).to_bytes_with_nul().to_vec();
+    return NAME_BUF.as_mut_ptr();
+}
+
+static GREETING: [u8; 6] = *b"hello\0";
+
+// The static is immutable, so the function returns a reference to it instead of a copy.
+// CHECK-LABEL: unsafe fn greeting{{[<(]}}
+// CHECK-SAME: ) -> &'static [u8]
+#[c2rust_analyze_test::static_buf]
+pub unsafe fn greeting() -> *const u8 {
+    // CHECK: &GREETING
+    GREETING.as_ptr()
+}
+
+// CHECK-LABEL: unsafe fn print_both{{[<(]}}
+pub unsafe fn print_both(x: libc::c_int) {
+    // CHECK: puts((describe(x)).as_ptr() as *mut i8);
+    puts(describe(x));
+    // CHECK: puts((greeting()).as_ptr() as *const u8 as *const libc::c_char);
+    puts(greeting() as *const libc::c_char);
+}
+
+static mut OTHER_BUF: [libc::c_char; 32] = [0; 32];
+
+// The result is stored in a local, which might outlive the returned value, so the function isn't
+// rewritten.
+// CHECK-LABEL: unsafe fn stored{{[<(]}}
+// CHECK-SAME: ) -> *mut libc::c_char
+#[c2rust_analyze_test::static_buf]
+pub unsafe fn stored() -> *mut libc::c_char {
+    OTHER_BUF.as_mut_ptr()
+}
+
+pub unsafe fn print_stored() {
+    let s = stored();
+    puts(s);
+}
@@ -0,0 +1,112 @@
+//! --bin
+//! --compile-rewritten
+#![allow(dead_code)]
+
+#[derive(Clone, Copy)]
+struct Config {
+    verbose: i32,
+    level: i32,
+}
+
+// CHECK-LABEL: ===== BEGIN
+// CHECK-NEXT: #![feature(once_cell)]
+
+// CHECK: static LIMIT: i32 = 10;
+static mut LIMIT: i32 = 10;
+
+// `SEED` is only assigned in `init`, which `main` calls once.
+// CHECK: static SEED: std::sync::OnceLock<u32> = std::sync::OnceLock::new();
+static mut SEED: u32 = 0;
+
+// `STATE` is also assigned in only one place, but `set_state` may be called more than once, so
+// it's guarded by a mutex.
+// CHECK: static STATE: std::sync::Mutex<u32> = std::sync::Mutex::new((0));
+static mut STATE: u32 = 0;
+
+// CHECK: static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new((0));
+static mut COUNTER: usize = 0;
+
+// CHECK: static CONFIG: std::sync::Mutex<Config> = std::sync::Mutex::new((Config {
+static mut CONFIG: Config = Config {
+    verbose: 0,
+    level: 1,
+};
+
+// Borrowing a static prevents it from being rewritten.
+// CHECK: static mut BUF: [u8; 4] = [0; 4];
+static mut BUF: [u8; 4] = [0; 4];
+
+// CHECK-LABEL: unsafe fn init(
+unsafe fn init(seed: u32) {
+    // CHECK: (SEED).set((seed)).ok().expect("static initialized more than once");
+    SEED = seed;
+}
+
+// CHECK-LABEL: unsafe fn seed(
+unsafe fn seed() -> u32 {
+    // CHECK: (SEED).get().copied().unwrap_or(0)
+    SEED
+}
+
+// CHECK-LABEL: unsafe fn set_state(
+unsafe fn set_state(x: u32) {
+    // CHECK: *(STATE).lock().unwrap() = x;
+    STATE = x;
+}
+
+// CHECK-LABEL: unsafe fn state(
+unsafe fn state() -> u32 {
+    // CHECK: let v = *(STATE).lock().unwrap();
+    STATE
+}
+
+// CHECK-LABEL: unsafe fn bump(
+unsafe fn bump() -> usize {
+    // CHECK: (COUNTER).fetch_add((1), std::sync::atomic::Ordering::SeqCst);
+    COUNTER += 1;
+    // CHECK: (COUNTER).load(std::sync::atomic::Ordering::SeqCst)
+    COUNTER
+}
+
+// CHECK-LABEL: unsafe fn reset(
+unsafe fn reset() {
+    // CHECK: (COUNTER).store((0), std::sync::atomic::Ordering::SeqCst);
+    COUNTER = 0;
+}
+
+// CHECK-LABEL: unsafe fn configure(
+unsafe fn configure(level: i32) {
+    // CHECK: (CONFIG).lock().unwrap().level = level;
+    CONFIG.level = level;
+    // CHECK: (CONFIG).lock().unwrap().verbose += 1;
+    CONFIG.verbose += 1;
+}
+
+// CHECK-LABEL: unsafe fn get_level(
+unsafe fn get_level() -> i32 {
+    // CHECK: let v = (CONFIG).lock().unwrap().level;
+    if CONFIG.level > LIMIT {
+        return LIMIT;
+    }
+    // CHECK: *(CONFIG).lock().unwrap() = Config {
+    CONFIG = Config {
+        verbose: 0,
+        level: 0,
+    };
+    0
+}
+
+// CHECK-LABEL: unsafe fn buf_ptr(
+unsafe fn buf_ptr() -> *mut u8 {
+    BUF[0] = 1;
+    BUF.as_mut_ptr()
+}
+
+fn main() {
+    unsafe {
+        init(1);
+        for i in 0..2 {
+            set_state(i);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+#![allow(dead_code)]
+
+static A: i32 = 1;
+static B: i32 = 2;
+static DIGITS: [u8; 4] = [1, 2, 3, 4];
+
+// CHECK-LABEL: final labeling for static items:
+// An integer cast to a pointer can't become a reference.
+// CHECK: "FIXED_ADDR": addr_of flags = {{.*}}, type flags = FIXED#
+static mut FIXED_ADDR: *const i32 = 0x1000 as *const i32;
+
+// CHECK-LABEL: ===== BEGIN
+
+// Null entries make the pointers nullable.
+// CHECK: static TABLE: [Option<&i32>; 3] = [Some({{.*}}&{{.*}}A{{.*}}), Some({{.*}}&{{.*}}B{{.*}}), None];
+static mut TABLE: [*const i32; 3] = [&A as *const i32, &B as *const i32, 0 as *const i32];
+
+// CHECK: static FIRST_DIGIT: &u8 = {{.*}}&{{.*}}DIGITS[0]{{.*}};
+static mut FIRST_DIGIT: *const u8 = &DIGITS as *const [u8; 4] as *const u8;
+
+// CHECK-LABEL: unsafe fn lookup(
+unsafe fn lookup(i: usize) -> i32 {
+    let p = TABLE[i];
+    if p.is_null() {
+        return 0;
+    }
+    *p
+}
+
+unsafe fn first_digit() -> u8 {
+    *FIRST_DIGIT
+}
+
+unsafe fn fixed_addr() -> i32 {
+    *FIXED_ADDR
+}
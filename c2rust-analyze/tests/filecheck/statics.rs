@@ -1,3 +1,5 @@
+//! --bin
+//! --compile-rewritten
 #![allow(dead_code)]
 
 // CHECK: final labeling for static items:
@@ -55,6 +57,7 @@ fn main() {
 }
 
 // CHECK:  ===== BEGIN
+// CHECK-NEXT: #![feature(once_cell)]
 // CHECK: static UNUSED_MUT: usize = 6;
 // CHECK: static READ_MUT: usize = 21;
-// CHECK: static mut WRITTEN_MUT: usize = 3;
+// CHECK: static WRITTEN_MUT: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
@@ -0,0 +1,32 @@
+#[repr(C)]
+pub struct Pair {
+    a: i32,
+    b: i32,
+}
+
+#[repr(C)]
+pub struct First {
+    a: i32,
+}
+
+// `Pair` can be read as `First`, so a read-only cast is rewritten to a `transmute` of references.
+// The `transmute` is unsafe, so it gets its own `unsafe` block.
+// CHECK-LABEL: unsafe fn read_first{{[<(]}}
+pub unsafe fn read_first(p: *const Pair) -> i32 {
+    let b = (*p).b;
+    // CHECK: let q = unsafe {
+    // CHECK-NEXT: // SAFETY: `Pair` can be read as `First`, and the result is only used for reading
+    // CHECK-NEXT: std::mem::transmute::<&Pair, &First>((p))
+    // CHECK-NEXT: };
+    let q = p as *const First;
+    (*q).a + b
+}
+
+// Writing through the cast pointer has no safe equivalent, so the function is left unchanged.
+// CHECK-LABEL: unsafe fn write_first(p: *mut Pair)
+pub unsafe fn write_first(p: *mut Pair) {
+    // CHECK: let q = p as *mut First;
+    let q = p as *mut First;
+    (*q).a = 1;
+    (*p).b = 2;
+}
@@ -0,0 +1,33 @@
+// A struct whose pointer field becomes a reference gets a lifetime parameter, and the impls and
+// type aliases that name it are updated to match.
+
+// CHECK-LABEL: pub struct Holder<'h0>
+pub struct Holder {
+    // CHECK: pub p: &'h0 (i32),
+    pub p: *const i32,
+}
+
+// CHECK: pub type HolderAlias<'h0> = Holder<'h0>;
+pub type HolderAlias = Holder;
+
+// CHECK: impl Holder<'_> {
+impl Holder {
+    pub unsafe fn get(&self) -> i32 {
+        *self.p
+    }
+}
+
+// CHECK: impl Clone for Holder<'_> {
+impl Clone for Holder {
+    fn clone(&self) -> Self {
+        Holder { p: self.p }
+    }
+}
+
+pub unsafe fn make(x: &i32) -> Holder {
+    Holder { p: x }
+}
+
+pub unsafe fn get_alias(h: *const HolderAlias) -> i32 {
+    (*h).get()
+}
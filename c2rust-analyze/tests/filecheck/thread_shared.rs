@@ -0,0 +1,119 @@
+#![allow(dead_code)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+    fn pthread_create(
+        _: *mut libc::pthread_t,
+        _: *const libc::pthread_attr_t,
+        _: Option<unsafe extern "C" fn(*mut libc::c_void) -> *mut libc::c_void>,
+        _: *mut libc::c_void,
+    ) -> libc::c_int;
+    fn pthread_join(_: libc::pthread_t, _: *mut *mut libc::c_void) -> libc::c_int;
+    fn pthread_mutex_init(
+        _: *mut libc::pthread_mutex_t,
+        _: *const libc::pthread_mutexattr_t,
+    ) -> libc::c_int;
+    fn pthread_mutex_destroy(_: *mut libc::pthread_mutex_t) -> libc::c_int;
+    fn pthread_mutex_lock(_: *mut libc::pthread_mutex_t) -> libc::c_int;
+    fn pthread_mutex_unlock(_: *mut libc::pthread_mutex_t) -> libc::c_int;
+}
+
+struct Shared {
+    lock: libc::pthread_mutex_t,
+    count: i32,
+}
+
+// CHECK-LABEL: unsafe extern "C" fn worker(
+unsafe extern "C" fn worker(arg: *mut libc::c_void) -> *mut libc::c_void {
+    // CHECK: let s = std::sync::Arc::from_raw((arg) as *const std::sync::Mutex<Shared>);
+    let s = arg as *mut Shared;
+    // CHECK: let mut s_guard = (s).lock().unwrap();
+    pthread_mutex_lock(&mut (*s).lock);
+    // CHECK: (*s_guard).count += 1;
+    (*s).count += 1;
+    // CHECK: std::mem::drop(s_guard);
+    pthread_mutex_unlock(&mut (*s).lock);
+    0 as *mut libc::c_void
+}
+
+// CHECK-LABEL: unsafe fn read_count(s: std::sync::Arc<std::sync::Mutex<Shared>>) -> i32
+unsafe fn read_count(s: *mut Shared) -> i32 {
+    // CHECK: (*(s).lock().unwrap()).count
+    (*s).count
+}
+
+// CHECK-LABEL: unsafe fn run_threads()
+unsafe fn run_threads() -> i32 {
+    // CHECK: let s = std::sync::Arc::new(std::sync::Mutex::new(std::mem::zeroed()));
+    let s = malloc(::std::mem::size_of::<Shared>() as libc::c_ulong) as *mut Shared;
+    // CHECK: 0;
+    pthread_mutex_init(&mut (*s).lock, 0 as *const libc::pthread_mutexattr_t);
+    // CHECK: (*(s).lock().unwrap()).count = 0;
+    (*s).count = 0;
+    let mut tid: libc::pthread_t = 0;
+    // CHECK: std::sync::Arc::into_raw((s).clone()) as *mut libc::c_void
+    pthread_create(
+        &mut tid,
+        0 as *const libc::pthread_attr_t,
+        Some(worker as unsafe extern "C" fn(*mut libc::c_void) -> *mut libc::c_void),
+        s as *mut libc::c_void,
+    );
+    pthread_join(tid, 0 as *mut *mut libc::c_void);
+    // CHECK: let count = read_count((s).clone());
+    let count = read_count(s);
+    pthread_mutex_destroy(&mut (*s).lock);
+    // CHECK: std::mem::drop((s));
+    free(s as *mut libc::c_void);
+    count
+}
+
+struct Stats {
+    lock: libc::pthread_mutex_t,
+    total: i32,
+}
+
+// `Mutex` isn't re-entrant, so `stats_get` would deadlock if it locked the mutex while
+// `stats_worker` holds it.  `Stats` is left alone.
+// CHECK-LABEL: unsafe extern "C" fn stats_worker(
+// CHECK-NOT: Arc
+unsafe extern "C" fn stats_worker(arg: *mut libc::c_void) -> *mut libc::c_void {
+    let st = arg as *mut Stats;
+    pthread_mutex_lock(&mut (*st).lock);
+    (*st).total = stats_get(st) + 1;
+    pthread_mutex_unlock(&mut (*st).lock);
+    0 as *mut libc::c_void
+}
+
+unsafe fn stats_get(st: *mut Stats) -> i32 {
+    (*st).total
+}
+
+// CHECK-LABEL: unsafe fn run_stats()
+// CHECK-NOT: Arc
+unsafe fn run_stats() {
+    let st = malloc(::std::mem::size_of::<Stats>() as libc::c_ulong) as *mut Stats;
+    (*st).total = 0;
+    let mut tid: libc::pthread_t = 0;
+    pthread_create(
+        &mut tid,
+        0 as *const libc::pthread_attr_t,
+        Some(stats_worker as unsafe extern "C" fn(*mut libc::c_void) -> *mut libc::c_void),
+        st as *mut libc::c_void,
+    );
+    pthread_join(tid, 0 as *mut *mut libc::c_void);
+    free(st as *mut libc::c_void);
+}
+
+// `Local` is only used on one thread, so it's left alone.
+struct Local {
+    count: i32,
+}
+
+// CHECK-LABEL: unsafe fn local_count(
+// CHECK-NOT: Arc
+unsafe fn local_count(p: *mut Local) -> i32 {
+    (*p).count
+}
@@ -0,0 +1,26 @@
+// Each field of a union has its own permissions.  A union literal initializes only the field it
+// names, so a pointer stored in one variant doesn't pick up the permissions of the others.
+
+#[derive(Copy, Clone)]
+pub union Slot {
+    r: *const i32,
+    w: *mut i32,
+}
+
+// CHECK-LABEL: unsafe fn make_reader(p: &'h{{[0-9]+}} (i32))
+pub unsafe fn make_reader(p: *const i32) -> Slot {
+    Slot { r: p }
+}
+
+// CHECK-LABEL: unsafe fn make_writer(p: &'h{{[0-9]+}} mut (i32))
+pub unsafe fn make_writer(p: *mut i32) -> Slot {
+    Slot { w: p }
+}
+
+pub unsafe fn get(s: Slot) -> i32 {
+    *s.r
+}
+
+pub unsafe fn put(s: Slot) {
+    *s.w = 1;
+}
@@ -0,0 +1,79 @@
+#![allow(dead_code)]
+#![allow(non_camel_case_types)]
+
+extern crate libc;
+
+// CHECK-LABEL: pub enum value_data {
+// CHECK: i(libc::c_int),
+// CHECK: f(libc::c_float),
+// CHECK: pub fn as_i(&self) -> &libc::c_int {
+// CHECK: value_data::i(x) => x,
+// CHECK: _ => panic!("union field `i` is not active"),
+// CHECK: pub fn as_f_mut(&mut self) -> &mut libc::c_float {
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub union value_data {
+    pub i: libc::c_int,
+    pub f: libc::c_float,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct value {
+    pub kind: libc::c_int,
+    pub data: value_data,
+}
+
+// CHECK-LABEL: unsafe extern "C" fn value_set_int(
+pub unsafe extern "C" fn value_set_int(v: *mut value, x: libc::c_int) {
+    (*v).kind = 0;
+    // CHECK: ((*v).data) = value_data::i((x));
+    (*v).data.i = x;
+}
+
+// CHECK-LABEL: unsafe extern "C" fn value_set_float(
+pub unsafe extern "C" fn value_set_float(v: *mut value, x: libc::c_float) {
+    (*v).kind = 1;
+    // CHECK: ((*v).data) = value_data::f((x));
+    (*v).data.f = x;
+}
+
+// CHECK-LABEL: unsafe extern "C" fn value_get_float(
+pub unsafe extern "C" fn value_get_float(v: *const value) -> libc::c_float {
+    if (*v).kind == 0 {
+        // CHECK: return (*((*v).data).as_i()) as libc::c_float;
+        return (*v).data.i as libc::c_float;
+    }
+    if (*v).kind == 1 {
+        // CHECK: return (*((*v).data).as_f());
+        return (*v).data.f;
+    }
+    0.0
+}
+
+// CHECK-LABEL: unsafe extern "C" fn value_incr(
+pub unsafe extern "C" fn value_incr(v: *mut value) {
+    match (*v).kind {
+        // CHECK: 0 => (*((*v).data).as_i_mut()) += 1,
+        0 => (*v).data.i += 1,
+        // CHECK: 1 => (*((*v).data).as_f_mut()) += 1.0,
+        1 => (*v).data.f += 1.0,
+        _ => {}
+    }
+}
+
+// A field that's read without checking the tag keeps the union as is.
+// CHECK-LABEL: pub union bits {
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub union bits {
+    pub i: u32,
+    pub f: f32,
+}
+
+// CHECK-LABEL: unsafe extern "C" fn float_bits(
+pub unsafe extern "C" fn float_bits(x: f32) -> u32 {
+    let mut b = bits { f: x };
+    // CHECK: return b.i;
+    return b.i;
+}
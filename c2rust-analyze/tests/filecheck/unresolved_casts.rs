@@ -0,0 +1,23 @@
+#![feature(register_tool)]
+#![register_tool(c2rust_analyze_test)]
+// Tests that a cast that can't be built keeps the pointers involved as raw pointers, with an
+// annotation explaining why, instead of failing the rewrite of the whole function.
+
+// CHECK-LABEL: fn pass_slice(x: *mut i32) -> *mut i32
+#[c2rust_analyze_test::fixed_signature]
+#[c2rust_analyze_test::skip_rewrite]
+unsafe fn pass_slice(x: *mut i32) -> *mut i32 {
+    *x.offset(1) = 1;
+    x
+}
+
+// CHECK-LABEL: fn h<'h0>(x: &'h0 mut (i32))
+unsafe fn h(x: *mut i32) {
+    *x = 1;
+
+    // There's no way to obtain the slice length for `z`, so it stays a raw pointer.
+    // CHECK: let z: {{[*]}}mut i32 = pass_slice(core::ptr::addr_of_mut!(*(x)));
+    // CHECK: // {{[0-9]+}}: pass_slice(x): c2rust::unresolved(reason = "{{.*}}")
+    let z: *mut i32 = pass_slice(x);
+    *z.offset(1) = 2;
+}
@@ -0,0 +1,22 @@
+//! --rewrite-paths good,skipped
+#![feature(register_tool)]
+#![register_tool(c2rust)]
+
+// CHECK-LABEL: fn good<'h0>(x: &'h0 mut (i32))
+unsafe fn good(x: *mut i32) {
+    *x = 1;
+}
+
+// CHECK-LABEL: fn skipped(x: *mut i32)
+// `skipped` is listed in --rewrite-paths, but the attribute excludes it.
+#[c2rust::skip_rewrite]
+unsafe fn skipped(x: *mut i32) {
+    *x = 2;
+}
+
+// CHECK-LABEL: fn forced<'h0>(x: &'h0 mut (i32))
+// `forced` is not listed in --rewrite-paths, but the attribute includes it.
+#[c2rust::force_rewrite]
+unsafe fn forced(x: *mut i32) {
+    *x = 3;
+}
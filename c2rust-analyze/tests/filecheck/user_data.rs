@@ -0,0 +1,25 @@
+use std::ffi::c_void;
+
+// `data` is only passed through to `cb`, so both get a type parameter.
+// CHECK-LABEL: unsafe fn for_each<{{.*}}T>(cb: unsafe extern "C" fn(*mut T, i32), data: *mut T)
+pub unsafe fn for_each(cb: unsafe extern "C" fn(*mut c_void, i32), data: *mut c_void) {
+    let mut i = 0;
+    while i < 10 {
+        cb(data, i);
+        i += 1;
+    }
+}
+
+// The callback's `data` parameter takes the one type it's actually used as.
+// CHECK-LABEL: unsafe extern "C" fn add(data: *mut i32, x: i32)
+pub unsafe extern "C" fn add(data: *mut c_void, x: i32) {
+    *(data as *mut i32) += x;
+}
+
+// CHECK-LABEL: unsafe fn sum()
+pub unsafe fn sum() -> i32 {
+    let mut sum = 0;
+    // CHECK: for_each(add, {{.*}} as *mut _);
+    for_each(add, &mut sum as *mut i32 as *mut c_void);
+    sum
+}
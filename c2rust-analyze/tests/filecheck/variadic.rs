@@ -0,0 +1,28 @@
+#![feature(c_variadic)]
+
+// The body and the declared parameters of a C-variadic function are analyzed and rewritten like
+// those of any other function.  Only the pointers read from the variadic arguments keep their raw
+// types.
+
+// CHECK-LABEL: final labeling for "log_msg"
+pub unsafe extern "C" fn log_msg(count: *mut i32, level: i32, mut args: ...) -> i32 {
+    *count += 1;
+    let mut ap = args.clone();
+    // CHECK-DAG: ([[@LINE+1]]: name): {{.*}}type flags = FIXED#
+    let name: *const u8 = ap.arg::<*const u8>();
+    let x = ap.arg::<i32>();
+    *name as i32 + x + level
+}
+
+// CHECK-LABEL: final labeling for "caller"
+pub unsafe fn caller(count: *mut i32, name: *const u8) -> i32 {
+    *count = 0;
+    log_msg(count, 2, name, 3)
+}
+
+// CHECK-LABEL: ===== BEGIN
+
+// CHECK-LABEL: unsafe extern "C" fn log_msg(count: &{{.*}}mut (i32), level: i32, mut args: ...) -> i32
+// CHECK: let name: *const u8 = ap.arg::<*const u8>();
+
+// CHECK-LABEL: unsafe fn caller(count: &{{.*}}mut (i32), name: {{.*}}) -> i32
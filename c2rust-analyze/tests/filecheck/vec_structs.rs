@@ -0,0 +1,68 @@
+#![allow(dead_code)]
+#![allow(non_camel_case_types)]
+#![allow(unused_mut)]
+
+extern crate libc;
+
+extern "C" {
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    fn realloc(_: *mut libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+    fn free(_: *mut libc::c_void);
+}
+
+// CHECK: #[derive(Clone)]
+// CHECK-LABEL: pub struct buffer {
+// CHECK-NEXT: pub data: Vec<i32>,
+// CHECK-NEXT: }
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct buffer {
+    pub data: *mut i32,
+    pub len: libc::size_t,
+    pub cap: libc::size_t,
+}
+
+// CHECK-LABEL: unsafe extern "C" fn buffer_init(
+pub unsafe extern "C" fn buffer_init(b: *mut buffer) {
+    // CHECK: std::ptr::write(core::ptr::addr_of_mut!(((*b).data)),Vec::new())
+    (*b).data = malloc((4 as libc::c_ulong).wrapping_mul(4)) as *mut i32;
+    // CHECK: ((*b).data).reserve_exact(
+    (*b).cap = 4;
+    // CHECK: ((*b).data).clear()
+    (*b).len = 0;
+}
+
+// CHECK-LABEL: unsafe extern "C" fn buffer_push(
+pub unsafe extern "C" fn buffer_push(b: *mut buffer, x: i32) {
+    // CHECK: if (((*b).data).len() as {{.*}}) == (((*b).data).capacity() as {{.*}})
+    if (*b).len == (*b).cap {
+        (*b).cap = (*b).cap.wrapping_mul(2);
+        // CHECK-NOT: realloc
+        (*b).data = realloc(
+            (*b).data as *mut libc::c_void,
+            (*b).cap.wrapping_mul(4) as libc::c_ulong,
+        ) as *mut i32;
+    }
+    // CHECK: ((*b).data).push((x));
+    // CHECK-NOT: wrapping_add
+    *(*b).data.offset((*b).len as isize) = x;
+    (*b).len = (*b).len.wrapping_add(1);
+}
+
+// CHECK-LABEL: unsafe extern "C" fn buffer_get(
+pub unsafe extern "C" fn buffer_get(b: *mut buffer, i: libc::size_t) -> i32 {
+    // CHECK: ((*b).data)[(i) as usize]
+    *(*b).data.offset(i as isize)
+}
+
+// CHECK-LABEL: unsafe extern "C" fn buffer_free(
+pub unsafe extern "C" fn buffer_free(b: *mut buffer) {
+    // CHECK: ((*b).data) = Vec::new()
+    free((*b).data as *mut libc::c_void);
+}
+
+// CHECK-LABEL: unsafe extern "C" fn buffer_empty(
+pub unsafe extern "C" fn buffer_empty() -> buffer {
+    // CHECK: buffer { data: Vec::new(), }
+    buffer { data: 0 as *mut i32, len: 0, cap: 0 }
+}
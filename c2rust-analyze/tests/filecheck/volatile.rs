@@ -0,0 +1,20 @@
+use std::ptr;
+
+// Pointers used for volatile accesses keep their raw types, along with the pointers they're
+// derived from.  The other pointers in the same function are still rewritten.
+
+// CHECK-LABEL: final labeling for "poll"
+pub unsafe fn poll(regs: *mut u32, out: *mut u32) {
+    // CHECK-DAG: ([[@LINE+1]]: status): {{.*}}type flags = FIXED#
+    let status = regs.offset(1);
+    while ptr::read_volatile(status) == 0 {}
+    ptr::write_volatile(regs, 1);
+    *out = status.read_volatile();
+}
+
+// CHECK-LABEL: ===== BEGIN
+
+// CHECK-LABEL: unsafe fn poll(regs: *mut u32, out: &'h0 mut (u32))
+// CHECK: let status = regs.offset(1);
+// CHECK: ptr::write_volatile(regs, 1);
+// CHECK: *out = status.read_volatile();
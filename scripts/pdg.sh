@@ -89,7 +89,7 @@ main() {
         export RUST_LOG=error
         if [[ "${NO_USE_PDG:-}" == "" ]]; then
             # cargo runs this from a different pwd, so make path absolute
-            export PDG_FILE="$(realpath ${pdg})"
+            export C2RUST_ANALYZE_PDG_FACTS="$(realpath ${pdg})"
         fi
         cargo run \
             --bin c2rust-analyze \